@@ -42,6 +42,12 @@ pub struct SharedAttr<'s> {
     pub lang_items: Vec<(Tr<&'s str>, parser::Type<'s>)>,
     #[new(default)]
     pub deprecated: Option<&'s str>,
+    /// Stub of an item which is only available with the standard library
+    #[new(default)]
+    pub std_only: bool,
+    /// Features which all need to be enabled for the item to be included
+    #[new(default)]
+    pub features: Vec<&'s str>,
     #[new(default)]
     pub public: bool,
 }
@@ -49,15 +55,76 @@ pub struct SharedAttr<'s> {
 #[derive(Debug, Default, Clone)]
 pub struct FuncAttr<'s> {
     pub no_mangle: bool,
+    /// Symbol the function is exported as from a shared library
+    pub export: Option<String>,
+    pub linkage: Linkage,
+    /// `@[visibility "hidden"]` was given explicitly rather than being the default
+    pub explicit_hidden: bool,
     pub precedence: Option<u32>,
+    pub associativity: Associativity,
     pub extern_: Option<String>,
+    pub extern_data: bool,
+    /// Native library the extern function is linked from
+    pub link: Option<String>,
+    /// Evaluate the val initialiser at compile time
+    pub const_: bool,
+    /// Fail compilation with the message if the val evaluates to false at compile time
+    pub static_assert: Option<String>,
+    /// Give every thread its own copy of the val
+    pub thread_local: bool,
+    pub test: Option<TestKind>,
+    pub expect_output: Option<ExpectOutput>,
     pub shared: SharedAttr<'s>,
 }
 
+/// Functions ran by `lumina test`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TestKind {
+    Unit,
+    /// Called repeatedly with generated parameters, which are shrunk on failure
+    Property,
+}
+
+/// How a chain of operators sharing the same precedence is grouped
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Associativity {
+    /// `a - b - c` is `(a - b) - c`
+    #[default]
+    Left,
+    /// `x : y : xs` is `x : (y : xs)`
+    Right,
+}
+
+/// What a test is expected to print
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ExpectOutput {
+    /// `@[test expect_output "..."]`, still containing its escape sequences
+    Inline(String),
+    /// `@[test expect_output]`, compared against a `.expected` file next to the module
+    File,
+}
+
+/// How the symbol of a function or val is exposed in the emitted object
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Linkage {
+    pub weak: bool,
+    pub visibility: SymbolVisibility,
+    pub section: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolVisibility {
+    #[default]
+    Hidden,
+    Default,
+}
+
 #[derive(Debug, Default)]
 pub struct TypeAttr<'s> {
     pub shared: SharedAttr<'s>,
     pub repr: Repr,
+    /// Traits which may only be implemented by the library declaring them
+    pub sealed: bool,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
@@ -76,7 +143,11 @@ impl<'s> TypeAttr<'s> {
         sources: &Sources,
         exprs: &[Tr<parser::Expr<'s>>],
     ) -> TypeAttr<'s> {
-        let mut this = TypeAttr { shared: SharedAttr::new(), repr: Repr::default() };
+        let mut this = TypeAttr {
+            shared: SharedAttr::new(),
+            repr: Repr::default(),
+            sealed: false,
+        };
 
         for expr in exprs {
             if let Err(err) = this.parse_attr(expr.as_ref()) {
@@ -91,6 +162,10 @@ impl<'s> TypeAttr<'s> {
         let (entry, params) = path(expr, "attribute name")?;
         match entry.path.as_slice() {
             ["repr"] => self.parse_repr(expr.span, params),
+            ["sealed"] => {
+                self.sealed = true;
+                Ok(())
+            }
             _ => self.shared.parse_attr(expr.span, entry, params),
         }
     }
@@ -106,7 +181,16 @@ impl<'s> TypeAttr<'s> {
                     params[0].span.move_indice(5),
                     "integer argument for `repr align`",
                 )),
-                "align" => num(params[1].as_ref()).map(|n| self.repr = Repr::Align(n as u8)),
+                "align" => match num(params[1].as_ref())? {
+                    n @ 1..=128 if n.is_power_of_two() => {
+                        self.repr = Repr::Align(n as u8);
+                        Ok(())
+                    }
+                    _ => Err(Error::Expected(
+                        params[1].span,
+                        "power of two no larger than 128 for `repr align`",
+                    )),
+                },
                 "packed" => {
                     self.repr = Repr::Packed;
                     Ok(())
@@ -146,9 +230,46 @@ impl<'s> TypeAttr<'s> {
 
 #[derive(Debug)]
 pub struct ImplAttr<'s> {
+    /// Named implementations are never selected implicitly, only with `Trait(name):method`
+    pub name: Option<Tr<&'s str>>,
     pub shared: SharedAttr<'s>,
 }
 
+impl<'s> ImplAttr<'s> {
+    pub fn parse(
+        module: key::Module,
+        sources: &Sources,
+        exprs: &[Tr<parser::Expr<'s>>],
+    ) -> ImplAttr<'s> {
+        let mut this = ImplAttr { name: None, shared: SharedAttr::new() };
+
+        for expr in exprs {
+            if let Err(err) = this.parse_attr(expr.as_ref()) {
+                emit_err(module, sources, err);
+            }
+        }
+
+        this
+    }
+
+    fn parse_attr(&mut self, expr: Tr<&parser::Expr<'s>>) -> Result<(), Error> {
+        let (entry, params) = path(expr, "attribute name")?;
+        match entry.path.as_slice() {
+            ["name"] => match params {
+                [n] => {
+                    self.name = Some(name(n.as_ref())?.tr(n.span));
+                    Ok(())
+                }
+                _ => Err(Error::Expected(
+                    expr.span,
+                    "`name` followed by an identifier",
+                )),
+            },
+            _ => self.shared.parse_attr(expr.span, entry, params),
+        }
+    }
+}
+
 fn emit_err(module: key::Module, sources: &Sources, err: Error) {
     let base = sources.error("invalid attribute").m(module);
     match err {
@@ -167,9 +288,20 @@ impl<'s> FuncAttr<'s> {
     ) -> FuncAttr<'s> {
         let mut this = FuncAttr {
             no_mangle: false,
+            export: None,
+            linkage: Linkage::default(),
+            explicit_hidden: false,
             precedence: None,
+            associativity: Associativity::Left,
             shared: SharedAttr::new(),
             extern_: None,
+            extern_data: false,
+            link: None,
+            const_: false,
+            static_assert: None,
+            thread_local: false,
+            test: None,
+            expect_output: None,
         };
 
         for expr in exprs {
@@ -188,15 +320,105 @@ impl<'s> FuncAttr<'s> {
                 self.no_mangle = true;
                 Ok(())
             }
+            ["export"] => {
+                let name = string(params[0].as_ref(), "exported symbol name")?;
+                self.export = Some(name.to_string());
+                Ok(())
+            }
+            ["weak"] => {
+                self.linkage.weak = true;
+                Ok(())
+            }
+            ["visibility"] => {
+                match string(params[0].as_ref(), "symbol visibility")? {
+                    "hidden" => {
+                        self.linkage.visibility = SymbolVisibility::Hidden;
+                        self.explicit_hidden = true;
+                    }
+                    "default" => {
+                        self.linkage.visibility = SymbolVisibility::Default;
+                        self.explicit_hidden = false;
+                    }
+                    _ => return Err(Error::Expected(params[0].span, "`hidden` or `default`")),
+                }
+                Ok(())
+            }
+            ["section"] => {
+                let name = string(params[0].as_ref(), "name of linker section")?;
+                self.linkage.section = Some(name.to_string());
+                Ok(())
+            }
+            ["const"] => {
+                self.const_ = true;
+                Ok(())
+            }
+            ["static_assert"] => match params {
+                [message] => {
+                    let message = string(message.as_ref(), "assertion message")?;
+                    self.static_assert = Some(message.to_string());
+                    Ok(())
+                }
+                _ => Err(Error::Expected(
+                    expr.span,
+                    "`static_assert` followed by a message",
+                )),
+            },
+            ["thread_local"] => {
+                self.thread_local = true;
+                Ok(())
+            }
+            ["test"] => {
+                self.test = Some(TestKind::Unit);
+                match params {
+                    [] => {}
+                    [flag, rest @ ..] if name(flag.as_ref())? == "expect_output" => {
+                        self.expect_output = Some(match rest {
+                            [] => ExpectOutput::File,
+                            [output] => {
+                                let output = string(output.as_ref(), "expected output")?;
+                                ExpectOutput::Inline(output.to_string())
+                            }
+                            [_, extra, ..] => {
+                                return Err(Error::Expected(extra.span, "end of attribute"))
+                            }
+                        });
+                    }
+                    [flag, ..] => return Err(Error::Expected(flag.span, "`expect_output`")),
+                }
+                Ok(())
+            }
+            ["property"] => {
+                self.test = Some(TestKind::Property);
+                Ok(())
+            }
             ["precedence"] => {
                 self.precedence = Some(num(params[0].as_ref())?);
                 Ok(())
             }
+            ["associativity"] => {
+                match string(params[0].as_ref(), "operator associativity")? {
+                    "left" => self.associativity = Associativity::Left,
+                    "right" => self.associativity = Associativity::Right,
+                    _ => return Err(Error::Expected(params[0].span, "`left` or `right`")),
+                }
+                Ok(())
+            }
             ["extern"] => {
                 let name = string(params[0].as_ref(), "function symbol to link to")?;
                 self.extern_ = Some(name.to_string());
                 Ok(())
             }
+            ["link"] => {
+                let name = string(params[0].as_ref(), "name of native library")?;
+                self.link = Some(name.to_string());
+                Ok(())
+            }
+            ["extern_data"] => {
+                let name = string(params[0].as_ref(), "data symbol to link to")?;
+                self.extern_ = Some(name.to_string());
+                self.extern_data = true;
+                Ok(())
+            }
             _ => self.shared.parse_attr(expr.span, entry, params),
         }
     }
@@ -220,6 +442,26 @@ impl<'s> SharedAttr<'s> {
                 self.public = true;
                 Ok(())
             }
+            ["cfg"] => match params {
+                [kind, feature] if name(kind.as_ref())? == "feature" => {
+                    let feature = string(feature.as_ref(), "name of feature")?;
+                    self.features.push(feature);
+                    Ok(())
+                }
+                _ => Err(Error::Expected(span, "`cfg feature \"name\"`")),
+            },
+            ["std_only"] => {
+                self.std_only = true;
+                Ok(())
+            }
+            ["deprecated"] => {
+                self.deprecated = Some(match params {
+                    [] => "",
+                    [message] => string(message.as_ref(), "deprecation message")?,
+                    [_, extra, ..] => return Err(Error::Expected(extra.span, "end of attribute")),
+                });
+                Ok(())
+            }
             _ => Err(Error::Unknown(span)),
         }
     }
@@ -0,0 +1,83 @@
+//! Declarations parsed by previous compilations, so that editors only re-parse the files which
+//! changed between compilations of the same project
+
+use lumina_parser::{Declaration, Error as ParseError, Parser};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+#[derive(Default)]
+pub struct ParseCache {
+    files: HashMap<PathBuf, Parsed>,
+    overlays: HashMap<PathBuf, String>,
+}
+
+struct Parsed {
+    hash: u64,
+    // Borrows from `_source`
+    declarations: Vec<Declaration<'static>>,
+    errors: Vec<ParseError>,
+    _source: Box<str>,
+}
+
+impl ParseCache {
+    /// Use `source` instead of the contents on disk for the file at `path`, such as for buffers
+    /// with unsaved changes. Passing `None` reads the file from disk again.
+    pub fn overlay(&mut self, path: &Path, source: Option<String>) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        match source {
+            Some(source) => self.overlays.insert(path, source),
+            None => self.overlays.remove(&path),
+        };
+    }
+
+    pub(super) fn read(&self, path: &Path) -> std::io::Result<String> {
+        if !self.overlays.is_empty() {
+            if let Some(source) = path
+                .canonicalize()
+                .ok()
+                .and_then(|path| self.overlays.get(&path))
+            {
+                return Ok(source.clone());
+            }
+        }
+
+        std::fs::read_to_string(path)
+    }
+
+    /// Parse the declarations of a file, or reuse them if its contents haven't changed
+    ///
+    /// SAFETY: The declarations may not outlive the cache, and must be dropped before the same
+    /// path is parsed again
+    pub(super) unsafe fn parse<'s>(
+        &mut self,
+        path: &Path,
+        src: &str,
+    ) -> (Vec<Declaration<'s>>, Vec<ParseError>) {
+        let mut hasher = DefaultHasher::new();
+        src.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        if self
+            .files
+            .get(path)
+            .map_or(true, |parsed| parsed.hash != hash)
+        {
+            let source: Box<str> = src.into();
+            let src: &'static str = &*(source.as_ref() as *const str);
+
+            let mut parser = Parser::new(src);
+            let declarations = std::iter::from_fn(|| parser.declaration())
+                .map(|(_, decl)| decl)
+                .collect();
+            let errors = parser.into_errors();
+
+            let parsed = Parsed { hash, declarations, errors, _source: source };
+            self.files.insert(path.to_path_buf(), parsed);
+        }
+
+        let parsed = &self.files[path];
+        (parsed.declarations.clone(), parsed.errors.clone())
+    }
+}
@@ -3,7 +3,7 @@ use super::{
     attr,
     entities::{FuncBody, ImplDef, TyHeader},
     resolve::{Entity, Mod, Visibility},
-    Entities, Lookups, ModuleAttr, NFunc, Sources,
+    Entities, Lookups, ModuleAttr, NFunc, ParseCache, References, Referent, Sources,
 };
 use crate::prelude::*;
 use crate::Target;
@@ -11,6 +11,7 @@ use lumina_parser as parser;
 use lumina_parser::{func, r#use, ty, val, when, Error as ParseError, Parser};
 use lumina_parser::{AnnotatedPath, Expr};
 use lumina_util::{Identifier, Spanned, Tr};
+use std::collections::HashSet;
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 use tracing::info;
@@ -33,12 +34,22 @@ pub(crate) struct Collector<'s> {
     pub entities: Entities<'s>,
     pub lookups: Lookups<'s>,
     pub sources: Sources,
+    pub references: References,
     pub debug: BinDebugInfo,
+    /// Declarations of previous compilations, if compiling incrementally
+    pub cache: Option<ParseCache>,
 
     pub dir: PathBuf,
 
     pub super_debug: bool,
 
+    /// Whether modules currently being included should have their doc comments extracted as tests
+    pub doctests: bool,
+    /// Expression to append to the matching module while it's being included
+    pub eval: Option<super::Eval>,
+    /// Features enabled for the project or library currently being included
+    pub features: HashSet<String>,
+
     std_lib_directory: PathBuf,
     pub uses: Map<key::Module, Vec<r#use::Declaration<'s>>>,
 
@@ -51,10 +62,15 @@ impl<'s> Collector<'s> {
             entities: Entities::default(),
             lookups: Lookups::new(),
             sources: Sources::new(),
+            references: References::default(),
 
             debug: BinDebugInfo::new(target),
+            cache: None,
 
             super_debug,
+            doctests: false,
+            eval: None,
+            features: HashSet::new(),
 
             dir: PathBuf::new(),
 
@@ -65,6 +81,21 @@ impl<'s> Collector<'s> {
         }
     }
 
+    fn with_generated(&self, path: &Path, source: String) -> String {
+        let source = if self.doctests {
+            super::doctest::append_doctests(source)
+        } else {
+            source
+        };
+
+        match &self.eval {
+            Some(eval) if path.canonicalize().is_ok_and(|path| path == eval.file) => {
+                eval.append_to(source)
+            }
+            _ => source,
+        }
+    }
+
     // HACK: this modules is already registered to exist so we
     // need to make sure the buffer lenghts are up to sync even
     // though this one won't be used.
@@ -90,11 +121,11 @@ impl<'s> Collector<'s> {
         {
             let root = path.join(root_name);
             info!("opening {} as {module}", trim_display(&root));
-            let source = match std::fs::read_to_string(&root) {
+            let source = match self.read_source(&root) {
                 Err(err) => {
                     return Err(self.reserve_module_and_err(module, &path, Error::File(err, root)))
                 }
-                Ok(str) => str,
+                Ok(str) => self.with_generated(&root, str),
             };
 
             let src = self.sources.push(module, source, root);
@@ -125,17 +156,32 @@ impl<'s> Collector<'s> {
                 continue;
             }
 
+            // `name.platform.lm` and `name.platform/` are only included as `name` when building
+            // for that platform
+            let stem = if file_type.is_file() {
+                path.file_stem().unwrap()
+            } else {
+                fname
+            };
+            let stem = stem.to_string_lossy().to_string();
+            let name = match stem.split_once('.') {
+                Some((name, platform)) if self.target.include_for(platform) => name.to_string(),
+                Some(_) => continue,
+                None => stem,
+            };
+
             let child = self.lookups.new_member_module(module);
             self.entities.add_module(child);
 
-            let name = path.file_stem().unwrap().to_string_lossy().to_string();
             self.lookups
                 .declare_module_link(module, Visibility::Public, name, child);
 
             if file_type.is_file() {
                 info!("opening {} as {child}", trim_display(&path));
-                let source =
-                    std::fs::read_to_string(&path).map_err(|err| Error::File(err, path.clone()))?;
+                let source = self
+                    .read_source(&path)
+                    .map_err(|err| Error::File(err, path.clone()))?;
+                let source = self.with_generated(&path, source);
                 self.debug.add_file(child, &self.dir.join(fname), module);
                 let src = self.sources.push(child, source, path);
                 self.parse_declarations(child, src);
@@ -149,8 +195,30 @@ impl<'s> Collector<'s> {
         Ok(())
     }
 
+    fn read_source(&self, path: &Path) -> std::io::Result<String> {
+        match &self.cache {
+            Some(cache) => cache.read(path),
+            None => std::fs::read_to_string(path),
+        }
+    }
+
     fn parse_declarations(&mut self, module: key::Module, src: &'s str) {
         self.uses.push_as(module, Vec::new());
+
+        if let Some(cache) = self.cache.as_mut() {
+            let path = self.sources.get_path(module);
+            let (declarations, errors) = unsafe { cache.parse(path, src) };
+
+            for decl in declarations {
+                self.include_declaration(module, decl);
+            }
+            for err in errors {
+                self.emit_err(module, err);
+            }
+
+            return;
+        }
+
         let mut parser = Parser::new(src);
 
         while let Some((_, decl)) = parser.declaration() {
@@ -214,6 +282,43 @@ impl<'s> Collector<'s> {
 
         let vis = Visibility::from_public_flag(module, attributes.shared.public);
 
+        self.check_linkage(module, name.span, &attributes);
+
+        if attributes.const_ {
+            self.sources
+                .error("invalid attribute")
+                .m(module)
+                .eline(name.span, "only vals can be evaluated at compile time")
+                .emit();
+        }
+
+        if attributes.static_assert.is_some() {
+            self.sources
+                .error("invalid attribute")
+                .m(module)
+                .eline(name.span, "only vals can be asserted at compile time")
+                .emit();
+        }
+
+        if attributes.link.is_some() && attributes.extern_.is_none() {
+            self.sources
+                .error("invalid attribute")
+                .m(module)
+                .eline(
+                    name.span,
+                    "only extern functions can be linked from a library",
+                )
+                .emit();
+        }
+
+        if attributes.thread_local {
+            self.sources
+                .error("invalid attribute")
+                .m(module)
+                .eline(name.span, "only vals can be thread-local")
+                .emit();
+        }
+
         let poison_body = || {
             FuncBody::Func(func::Body {
                 where_binds: vec![],
@@ -221,7 +326,7 @@ impl<'s> Collector<'s> {
             })
         };
 
-        if is_targetted(&attributes.shared, &self.target) {
+        if self.is_enabled(&attributes.shared) {
             if self.super_debug && !BANNED_SUPER_DEBUG_FNS.contains(&*func.header.name) {
                 let mut text = if func.header.when.generics.is_empty() {
                     format!("super-trace: {}", &func.header)
@@ -258,7 +363,11 @@ impl<'s> Collector<'s> {
 
             let body = match to_body(func.body) {
                 None => match attributes.extern_.clone() {
-                    Some(link_name) => FuncBody::Extern { link_name },
+                    Some(link_name) => FuncBody::Extern {
+                        link_name,
+                        data: attributes.extern_data,
+                        library: attributes.link.clone(),
+                    },
                     None => {
                         self.sources
                             .error("syntax error")
@@ -353,7 +462,7 @@ impl<'s> Collector<'s> {
 
         let visiblity = Visibility::from_public_flag(module, attributes.shared.public);
 
-        if is_targetted(&attributes.shared, &self.target) {
+        if self.is_enabled(&attributes.shared) {
             let kind = match ty.body {
                 ty::DeclarationBody::Record(body) => {
                     let record = self
@@ -462,15 +571,16 @@ impl<'s> Collector<'s> {
         module: key::Module,
         imp: parser::r#impl::Declaration<'s>,
     ) -> M<key::Impl> {
-        if !imp.attributes.is_empty() {
-            unimplemented!("impl block attributes");
-        }
-
+        let attributes = attr::ImplAttr::parse(module, &self.sources, &imp.attributes);
         let ikey = self.entities.impls[module].next_key().inside(module);
 
         let to_body = |body: Option<_>| body.map(|body| FuncBody::ImplMethod(body, ikey));
 
-        let impdef = ImplDef { header: imp.header, associations: imp.associations };
+        let impdef = ImplDef {
+            attributes,
+            header: imp.header,
+            associations: imp.associations,
+        };
         let methods = self.include_methods_as_functions(module, imp.methods, to_body);
 
         let k = self.entities.impls.push(module, impdef);
@@ -490,7 +600,10 @@ impl<'s> Collector<'s> {
     fn include_val(&mut self, module: key::Module, val: val::Declaration<'s>) {
         let (name, span) = (val.name, val.span);
         let key = self.entities.vals[module].next_key();
-        let (header, body, attributes) = val_to_func(key, val);
+        let mut attributes = attr::FuncAttr::parse(module, &self.sources, &val.attributes);
+        attributes.shared.public |= val.public;
+        self.check_linkage(module, span, &attributes);
+        let (header, body) = val_to_func(key, val);
         let fkey = self.entities.fheaders.push(module, header);
         let visibility = Visibility::from_public_flag(module, attributes.shared.public);
         self.entities.fattributes.push_as(fkey, attributes);
@@ -504,6 +617,17 @@ impl<'s> Collector<'s> {
         }
     }
 
+    // Weak symbols are always exported, see `to_cranelift_linkage`
+    fn check_linkage(&self, module: key::Module, span: Span, attributes: &attr::FuncAttr) {
+        if attributes.linkage.weak && attributes.explicit_hidden {
+            self.sources
+                .error("invalid attribute")
+                .m(module)
+                .eline(span, "weak symbols cannot have hidden visibility")
+                .emit();
+        }
+    }
+
     fn include_tydef_members(&mut self, module: key::Module, vis: Visibility, kind: key::TypeKind) {
         let clashes = match kind {
             key::TypeKind::Record(rkey) => {
@@ -598,6 +722,9 @@ impl<'s> Collector<'s> {
             ParseError::NestedWhere { previous, kw } => error
                 .eline(kw, "unexpected `where`")
                 .iline(previous, "function may only have one set of where-bindings"),
+            ParseError::OpaqueParameter(span) => error
+                .eline(span, "unexpected `some`")
+                .text("only the return type of a function can be opaque"),
             ParseError::BadHeaderForWhere(span, token) => error.eline(
                 span,
                 format!("where bindings can not be prefixed by {}", token.describe()),
@@ -696,6 +823,8 @@ impl<'s> Collector<'s> {
                     Entity::Func(nfunc) => {
                         self.forbid_members(module, "function", &exposed);
                         expose_func(self, exposed.name, entity.module, nfunc);
+                        let referent = Referent::Func(M(entity.module, nfunc));
+                        self.record_export(module, span, referent);
                     }
                     Entity::Alias(ty) => match self.lookups.alias_as_func(dst, &ty) {
                         Some((path, nfunc)) => {
@@ -722,6 +851,9 @@ impl<'s> Collector<'s> {
                             );
                         }
 
+                        let referent = Referent::Type(M(entity.module, ty));
+                        self.record_export(module, span, referent);
+
                         self.expose_type_members(module, v, entity.map(|_| ty), &exposed.members);
                     }
                     Entity::Module(_) => {
@@ -768,6 +900,12 @@ impl<'s> Collector<'s> {
         }
     }
 
+    /// Record the name of an exposed item as a reference to it
+    fn record_export(&self, module: key::Module, span: Span, referent: Referent) {
+        let src = self.sources.get(module);
+        self.references.used(src, module, span, 0, referent);
+    }
+
     fn forbid_members(
         &mut self,
         module: key::Module,
@@ -839,6 +977,14 @@ impl<'s> Collector<'s> {
                             }
                             Some(variant) => {
                                 let nfunc = NFunc::SumVar(sum, variant);
+                                let referent = Referent::Func(M(ty.module, nfunc));
+                                self.references.used(
+                                    sources.get(module),
+                                    module,
+                                    name.span,
+                                    0,
+                                    referent,
+                                );
                                 self.lookups
                                     .declare(module, vis, name, ty.module, nfunc)
                                     .map(|existing| (name.span, existing))
@@ -877,6 +1023,14 @@ impl<'s> Collector<'s> {
                                 }
                                 Some(m) => {
                                     let nfunc = NFunc::Method(trait_, m);
+                                    let referent = Referent::Func(M(ty.module, nfunc));
+                                    self.references.used(
+                                        sources.get(module),
+                                        module,
+                                        name.span,
+                                        0,
+                                        referent,
+                                    );
                                     self.lookups
                                         .declare(module, vis, **name, ty.module, nfunc)
                                         .map(|existing| (name.span, existing))
@@ -917,10 +1071,7 @@ fn is_op(str: &str) -> bool {
     b"[:\\!+/*&%@$?^~<>=|-]+".contains(&str.as_bytes()[0])
 }
 
-fn val_to_func<'s>(
-    key: key::Val,
-    val: val::Declaration<'s>,
-) -> (func::Header<'s>, FuncBody<'s>, ast::FuncAttr<'s>) {
+fn val_to_func<'s>(key: key::Val, val: val::Declaration<'s>) -> (func::Header<'s>, FuncBody<'s>) {
     (
         func::Header {
             name: val.name.tr(val.span),
@@ -930,15 +1081,11 @@ fn val_to_func<'s>(
                 span: val.span,
                 ptypes: vec![],
                 returns,
+                opaque: None,
+                variadic: None,
             }),
         },
         FuncBody::Val(func::Body { expr: val.value, where_binds: vec![] }, key),
-        ast::FuncAttr {
-            precedence: None,
-            no_mangle: false,
-            shared: ast::SharedAttr { public: val.public, ..ast::SharedAttr::new() },
-            extern_: None,
-        },
     )
 }
 
@@ -952,3 +1099,14 @@ fn trim_display(path: &std::path::Path) -> impl std::fmt::Display + '_ {
 fn is_targetted(attrs: &attr::SharedAttr<'_>, target: &Target) -> bool {
     attrs.platforms.is_empty() || attrs.platforms.iter().any(|name| target.include_for(name))
 }
+
+impl<'s> Collector<'s> {
+    /// Whether an item should be included given its `@[platform]` and `@[cfg]` attributes
+    fn is_enabled(&self, attrs: &attr::SharedAttr<'_>) -> bool {
+        is_targetted(attrs, &self.target)
+            && attrs
+                .features
+                .iter()
+                .all(|feature| self.features.contains(*feature))
+    }
+}
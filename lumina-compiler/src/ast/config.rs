@@ -1,7 +1,8 @@
 use lumina_parser as parser;
 use lumina_parser::{Declaration, Parser, Type};
 use lumina_util::{Span, Tr};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 
 #[derive(Default, Debug)]
 pub struct ProjectConfig {
@@ -11,16 +12,36 @@ pub struct ProjectConfig {
     pub parameters: Vec<String>,
     pub epanic: bool,
     pub super_debug: bool,
+    /// Compile the code examples in doc comments as tests, only set by `lumina test`
+    pub doctests: bool,
+    /// Expression to evaluate in one of the project's modules, only set by `lumina eval`
+    pub eval: Option<super::Eval>,
     pub prelude: String,
+    pub std_version: Option<String>,
+    pub vendored: bool,
+    /// Use the core prelude, which stubs out the parts of the standard library needing an allocator
+    pub no_std: bool,
     pub dependencies: Vec<Dependency>,
+    /// Optional features this project can be built with
+    pub features: Vec<String>,
+    pub default_features: Vec<String>,
+    /// Features enabled for this build, from `default_features` and `--features`
+    pub enabled_features: HashSet<String>,
     pub linker_args: Vec<String>,
     pub linker_libs: Vec<String>,
+    /// Native libraries linked by name, such as `m` for `-lm`
+    pub libraries: Vec<String>,
+    /// Directories searched for `libraries`
+    pub library_paths: Vec<PathBuf>,
+    pub linker: Option<PathBuf>,
+    pub linker_script: Option<PathBuf>,
 }
 
 #[derive(Debug)]
 pub struct Dependency {
     pub name: String,
     pub version: String,
+    pub features: Vec<String>,
     pub parameters: HashMap<String, Type<'static>>,
 }
 
@@ -29,7 +50,6 @@ impl ProjectConfig {
         let mut parser = Parser::new(src);
 
         let mut project = ProjectConfig::default();
-        project.prelude = String::from("std:prelude");
 
         while let Some((span, decl)) = parser.declaration() {
             match decl {
@@ -46,6 +66,14 @@ impl ProjectConfig {
             }
         }
 
+        if project.prelude.is_empty() {
+            project.prelude = String::from(if project.no_std {
+                "std:core"
+            } else {
+                "std:prelude"
+            });
+        }
+
         Ok(project)
     }
 
@@ -71,12 +99,45 @@ impl ProjectConfig {
                 self.prelude = name(val.value)?;
                 Ok(())
             }
+            "vendored" => {
+                self.vendored = bool(val.value)?;
+                Ok(())
+            }
+            "no_std" => {
+                self.no_std = bool(val.value)?;
+                Ok(())
+            }
+            "std_version" => {
+                self.std_version = Some(name(val.value)?);
+                Ok(())
+            }
+            "features" => self
+                .parse_str_list(val.value)
+                .map(|features| self.features.extend(features)),
+            "default_features" => self
+                .parse_str_list(val.value)
+                .map(|features| self.default_features.extend(features)),
             "linker_args" => self
                 .parse_str_list(val.value)
                 .map(|args| self.linker_args.extend(args)),
             "linker_libs" => self
                 .parse_str_list(val.value)
                 .map(|args| self.linker_libs.extend(args)),
+            "libraries" => self
+                .parse_str_list(val.value)
+                .map(|libs| self.libraries.extend(libs)),
+            "library_paths" => self.parse_str_list(val.value).map(|paths| {
+                self.library_paths
+                    .extend(paths.into_iter().map(PathBuf::from))
+            }),
+            "linker" => {
+                self.linker = Some(name(val.value)?.into());
+                Ok(())
+            }
+            "linker_script" => {
+                self.linker_script = Some(name(val.value)?.into());
+                Ok(())
+            }
             _ => Err(Error::InvalidVal(val.span)),
         }
     }
@@ -95,6 +156,7 @@ impl ProjectConfig {
                     let mut dep = Dependency {
                         name: String::new(),
                         version: String::new(),
+                        features: vec![],
                         parameters: HashMap::new(),
                     };
 
@@ -110,6 +172,11 @@ impl ProjectConfig {
                                         dep.version = name(v)?;
                                         Ok(())
                                     }
+                                    "features" => {
+                                        let features = self.parse_str_list(v)?;
+                                        dep.features.extend(features);
+                                        Ok(())
+                                    }
                                     other => {
                                         let ty = ty_in_str_literal(v)?;
                                         dep.parameters.insert(other.to_string(), ty);
@@ -0,0 +1,60 @@
+//! Extraction of code examples from documentation comments
+//!
+//! Every fenced code block inside of a `///` comment is turned into a `@[test]` function which is
+//! appended to the end of the module it's written in. Appending keeps the spans of the original
+//! source intact, and lets the example refer to the private items of the module.
+
+/// Append a test function for each code block found in the doc comments of `source`
+pub fn append_doctests(mut source: String) -> String {
+    for (line, block) in code_blocks(&source) {
+        source.push_str(&format!("\n\n@[test]\nfn doctest_line_{line} =\n"));
+        for code in block {
+            if !code.is_empty() {
+                source.push_str("  ");
+                source.push_str(&code);
+            }
+            source.push('\n');
+        }
+    }
+
+    source
+}
+
+enum State {
+    Outside,
+    Lumina { line: usize, lines: Vec<String> },
+    Other,
+}
+
+/// Code blocks tagged with `lm` or without a language, together with the line they start on
+fn code_blocks(source: &str) -> Vec<(usize, Vec<String>)> {
+    let mut blocks = vec![];
+    let mut state = State::Outside;
+
+    for (i, line) in source.lines().enumerate() {
+        let Some(doc) = line.trim_start().strip_prefix("///") else {
+            // unterminated code blocks end with the doc comment
+            state = State::Outside;
+            continue;
+        };
+        let doc = doc.strip_prefix(' ').unwrap_or(doc);
+        let fence = doc.trim_start().strip_prefix("```").map(str::trim);
+
+        state = match (state, fence) {
+            (State::Outside, Some("" | "lm")) => State::Lumina { line: i + 1, lines: vec![] },
+            (State::Outside, Some(_)) => State::Other,
+            (State::Lumina { line, lines }, Some(_)) => {
+                blocks.push((line, lines));
+                State::Outside
+            }
+            (State::Lumina { line, mut lines }, None) => {
+                lines.push(doc.to_string());
+                State::Lumina { line, lines }
+            }
+            (State::Other, Some(_)) => State::Outside,
+            (state, None) => state,
+        };
+    }
+
+    blocks
+}
@@ -1,4 +1,4 @@
-use super::{FuncAttr, TypeAttr};
+use super::{FuncAttr, ImplAttr, TypeAttr};
 use crate::prelude::*;
 use lumina_parser::r#impl::Association;
 use lumina_parser::{func, ty, Type};
@@ -29,7 +29,11 @@ pub struct Entities<'s> {
 }
 
 pub enum FuncBody<'s> {
-    Extern { link_name: String },
+    Extern {
+        link_name: String,
+        data: bool,
+        library: Option<String>,
+    },
     Val(func::Body<'s>, key::Val),
     Func(func::Body<'s>),
     TraitMethod(Option<func::Body<'s>>, M<key::Trait>),
@@ -56,6 +60,7 @@ pub struct TyHeader<'s> {
 }
 
 pub struct ImplDef<'s> {
+    pub attributes: ImplAttr<'s>,
     pub header: lumina_parser::r#impl::Header<'s>,
     pub associations: Map<key::AssociatedType, Association<'s>>,
 }
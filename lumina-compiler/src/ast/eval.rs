@@ -0,0 +1,29 @@
+//! Evaluation of a single expression in the context of a module
+//!
+//! Like doctests, the expression is wrapped in a `@[test]` function appended to the end of the
+//! module it's evaluated in. The expression may then refer to the private functions and vals of
+//! the module without shifting the spans of the original source.
+
+use std::path::PathBuf;
+
+/// Name of the function the evaluated expression is placed in
+pub const EVAL_FUNCTION: &str = "lumina_eval";
+
+#[derive(Debug, Clone)]
+pub struct Eval {
+    /// Source file of the module to evaluate the expression in
+    pub file: PathBuf,
+    pub expr: String,
+}
+
+impl Eval {
+    pub fn append_to(&self, mut source: String) -> String {
+        source.push_str(&format!("\n\n@[test]\nfn {EVAL_FUNCTION} =\n"));
+        for line in self.expr.lines() {
+            source.push_str("  ");
+            source.push_str(line);
+            source.push('\n');
+        }
+        source
+    }
+}
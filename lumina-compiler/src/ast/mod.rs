@@ -3,11 +3,12 @@
 use crate::{debuginfo::BinDebugInfo, Target};
 use derive_more::From;
 use lumina_key as key;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use tracing::{info_span, warn};
 
 mod sources;
-pub use sources::{ErrorBuilder, Sources};
+pub use sources::{ErrorBuilder, Fix, Sources};
 
 mod resolve;
 pub use resolve::{Entity, ImportError, Lookups, Mod, NFunc, Visibility};
@@ -19,16 +20,28 @@ mod collect;
 use collect::Collector;
 pub use collect::Error as CollectError;
 
+mod cache;
+pub use cache::ParseCache;
 mod config;
+mod doctest;
+mod eval;
+pub mod outline;
+mod references;
 pub use config::{Error as ConfigError, ProjectConfig};
+pub use eval::{Eval, EVAL_FUNCTION};
+pub use references::{Reference, References, Referent};
 
 pub mod attr;
-pub use attr::{FuncAttr, ImplAttr, ModuleAttr, SharedAttr, TypeAttr};
+pub use attr::{
+    ExpectOutput, FuncAttr, ImplAttr, Linkage, ModuleAttr, SharedAttr, SymbolVisibility, TestKind,
+    TypeAttr,
+};
 
 pub struct AST<'s> {
     pub entities: Entities<'s>,
     pub lookups: Lookups<'s>,
     pub sources: Sources,
+    pub references: References,
 
     pub main_module: key::Module,
 
@@ -39,28 +52,48 @@ pub struct AST<'s> {
 pub enum Error {
     ProjectNotDir(PathBuf),
     LuminaNotDir(PathBuf),
+    /// LUMINAPATH isn't set and the project isn't vendored
+    NoLuminaPath,
     SrcDir(std::io::Error),
     Config(std::io::Error),
     ConfigError(String, PathBuf, config::Error),
+    StdVersion {
+        expected: String,
+        found: Option<String>,
+    },
+    UnknownFeature {
+        project: String,
+        feature: String,
+    },
     #[from]
     Collect(collect::Error),
 }
 
+/// Code to generate into the project's modules in addition to their sources
+#[derive(Debug, Clone, Default)]
+pub enum Generate {
+    #[default]
+    Nothing,
+    /// Compile the code examples in doc comments as tests
+    Doctests,
+    /// Append a function evaluating the expression to a module
+    Eval(Eval),
+}
+
+/// `lumina` is only required for projects which aren't vendored
 pub fn parse<'s>(
     project: PathBuf,
-    lumina: PathBuf,
+    lumina: Option<PathBuf>,
     epanic: bool,
     super_debug: bool,
+    generate: Generate,
+    features: &[String],
     target: Target,
 ) -> Result<(AST<'s>, BinDebugInfo), Error> {
     if !project.is_dir() {
         return Err(Error::ProjectNotDir(project));
     }
 
-    if !lumina.is_dir() {
-        return Err(Error::LuminaNotDir(lumina));
-    }
-
     let mut config = {
         info_span!("project config");
         let configpath = project.join("config.lm");
@@ -68,72 +101,183 @@ pub fn parse<'s>(
         ProjectConfig::parse(&str).map_err(|cerr| Error::ConfigError(str, configpath, cerr))?
     };
 
+    // vendored projects carry their own copy of the std and dependencies
+    let lumina = if config.vendored {
+        project.join("vendor")
+    } else {
+        lumina.ok_or(Error::NoLuminaPath)?
+    };
+
+    if !lumina.is_dir() {
+        return Err(Error::LuminaNotDir(lumina));
+    }
+
     config.epanic |= epanic;
     config.super_debug |= super_debug;
+    match generate {
+        Generate::Nothing => {}
+        Generate::Doctests => config.doctests = true,
+        Generate::Eval(eval) => config.eval = Some(eval),
+    }
+    config.enabled_features = enabled_features(&config, features)?;
+
+    if let Some(expected) = &config.std_version {
+        check_std_version(&lumina, expected)?;
+    }
 
     parse_with_config(project, lumina, config, target)
 }
 
+/// Default features of the project together with the explicitly requested ones
+fn enabled_features(
+    config: &ProjectConfig,
+    requested: &[String],
+) -> Result<HashSet<String>, Error> {
+    config
+        .default_features
+        .iter()
+        .chain(requested)
+        .map(|feature| {
+            if config.features.contains(feature) {
+                Ok(feature.clone())
+            } else {
+                Err(Error::UnknownFeature {
+                    project: config.name.clone(),
+                    feature: feature.clone(),
+                })
+            }
+        })
+        .collect()
+}
+
+/// Verify that the standard library in LUMINAPATH matches the version pinned by the project
+fn check_std_version(lumina: &Path, expected: &str) -> Result<(), Error> {
+    let found = std::fs::read_to_string(lumina.join("std").join("version"))
+        .ok()
+        .map(|str| str.trim().to_string());
+
+    if found.as_deref() == Some(expected) {
+        Ok(())
+    } else {
+        Err(Error::StdVersion { expected: expected.to_string(), found })
+    }
+}
+
 pub fn parse_with_config<'s>(
     project: PathBuf,
     lumina: PathBuf,
     config: ProjectConfig,
     target: Target,
 ) -> Result<(AST<'s>, BinDebugInfo), Error> {
-    unsafe {
-        let ispan = info_span!("collector");
-        let _ispan = ispan.enter();
+    unsafe { collect(project, lumina, config, target, &mut None) }
+}
+
+/// Parse a project while reusing the declarations of files whose contents are unchanged since
+/// they were last parsed with `cache`
+///
+/// SAFETY: The returned AST may not outlive `cache`, and must be dropped before `cache` is used
+/// again
+pub unsafe fn parse_with_cache<'s>(
+    project: PathBuf,
+    lumina: PathBuf,
+    config: ProjectConfig,
+    target: Target,
+    cache: &mut ParseCache,
+) -> Result<(AST<'s>, BinDebugInfo), Error> {
+    let mut incremental = Some(std::mem::take(cache));
+    let result = collect(project, lumina, config, target, &mut incremental);
+    *cache = incremental.unwrap();
+    result
+}
 
-        let std_lib_directory = lumina.join("std");
-        let mut collector = Collector::new(std_lib_directory.clone(), config.super_debug, target);
-        collector.sources.set_panicy(config.epanic);
+unsafe fn collect<'s>(
+    project: PathBuf,
+    lumina: PathBuf,
+    config: ProjectConfig,
+    target: Target,
+    cache: &mut Option<ParseCache>,
+) -> Result<(AST<'s>, BinDebugInfo), Error> {
+    let ispan = info_span!("collector");
+    let _ispan = ispan.enter();
 
-        // include the prelude directory
-        assert_eq!(
-            key::PRELUDE,
-            collector.lookups.new_lib("std", "prelude".into())
-        );
-        collector.entities.add_module(key::PRELUDE);
+    let std_lib_directory = lumina.join("std");
+    let mut collector = Collector::new(std_lib_directory.clone(), config.super_debug, target);
+    collector.sources.set_panicy(config.epanic);
+    collector.cache = cache.take();
 
-        let prelude_path = lumina_util::Identifier::parse(&config.prelude)
-            .expect("invalid prelude path")
-            .to_directory(&lumina, &project, &lumina);
+    let included = include_project(&mut collector, &project, &lumina, &config);
+    *cache = collector.cache.take();
+    let main_module = included?;
 
+    for module in collector.sources.modules() {
         collector
             .debug
-            .add_dir(key::PRELUDE, "lib.lm", &PathBuf::from("std/prelude"), None);
-        collector.include_dir("lib.lm", key::PRELUDE, prelude_path)?;
+            .add_source(module, collector.sources.get(module));
+    }
 
-        // include the project source directory recursively
-        let main_module = collector.lookups.new_root_module(None);
-        collector.dir = PathBuf::from(&config.name);
-        collector.dir.push("src");
-        collector
-            .debug
-            .add_dir(main_module, "main.lm", &collector.dir, None);
-        collector.entities.add_module(main_module);
-        collector.include_dir("main.lm", main_module, project.join("src"))?;
+    Ok((
+        AST {
+            entities: collector.entities,
+            lookups: collector.lookups,
+            sources: collector.sources,
+            references: collector.references,
 
-        // include all external dependencies listed in config
-        for dep in config.dependencies.iter() {
-            include_ext_library(&mut collector, &lumina, dep)?;
-        }
+            main_module,
 
-        collector.link_up_imports_and_exposed();
+            config,
+        },
+        collector.debug,
+    ))
+}
 
-        Ok((
-            AST {
-                entities: collector.entities,
-                lookups: collector.lookups,
-                sources: collector.sources,
+fn include_project(
+    collector: &mut Collector<'_>,
+    project: &Path,
+    lumina: &Path,
+    config: &ProjectConfig,
+) -> Result<key::Module, Error> {
+    // include the prelude directory
+    assert_eq!(
+        key::PRELUDE,
+        collector.lookups.new_lib("std", "prelude".into())
+    );
+    collector.entities.add_module(key::PRELUDE);
 
-                main_module,
+    let prelude_path = lumina_util::Identifier::parse(&config.prelude)
+        .expect("invalid prelude path")
+        .to_directory(lumina, project, lumina);
 
-                config,
-            },
-            collector.debug,
-        ))
+    collector
+        .debug
+        .add_dir(key::PRELUDE, "lib.lm", &PathBuf::from("std/prelude"), None);
+    collector.include_dir("lib.lm", key::PRELUDE, prelude_path)?;
+
+    // include the project source directory recursively
+    let main_module = collector.lookups.new_root_module(None);
+    collector.dir = PathBuf::from(&config.name);
+    collector.dir.push("src");
+    collector
+        .debug
+        .add_dir(main_module, "main.lm", &collector.dir, None);
+    collector.entities.add_module(main_module);
+    collector.doctests = config.doctests;
+    collector.eval = config.eval.clone();
+    collector.features = config.enabled_features.clone();
+    collector.include_dir("main.lm", main_module, project.join("src"))?;
+    collector.doctests = false;
+    collector.eval = None;
+
+    // include all external dependencies listed in config
+    for dep in config.dependencies.iter() {
+        include_ext_library(collector, lumina, dep)?;
     }
+
+    // standard libraries included lazily while linking up imports have no features
+    collector.features.clear();
+
+    collector.link_up_imports_and_exposed();
+
+    Ok(main_module)
 }
 
 fn include_ext_library<'s>(
@@ -169,7 +313,10 @@ fn include_ext_library<'s>(
         .add_dir(module, "lib.lm", &collector.dir, None);
 
     path.push("src");
+    let features = enabled_features(&config, &dep.features)?;
+
     collector.entities.add_module(module);
+    collector.features = features;
     collector.include_dir("lib.lm", module, path)?;
 
     // include all external dependencies listed in config
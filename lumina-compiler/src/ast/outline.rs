@@ -0,0 +1,230 @@
+//! Document outlines and semantic highlighting for editor integrations
+
+use super::{Entity, AST};
+use lumina_key as key;
+use lumina_parser::ty::DeclarationBody;
+use lumina_parser::{func, Declaration, Parser, Token};
+use lumina_util::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Record,
+    Sum,
+    Trait,
+    Type,
+    Alias,
+    Val,
+    Impl,
+    Field,
+    Variant,
+    Method,
+}
+
+#[derive(Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Span of the name, or of the header for implementations
+    pub span: Span,
+    pub children: Vec<Symbol>,
+}
+
+impl Symbol {
+    fn leaf(name: &str, kind: SymbolKind, span: Span) -> Self {
+        Symbol { name: name.to_string(), kind, span, children: vec![] }
+    }
+
+    fn method(func: &func::Declaration) -> Self {
+        Symbol::leaf(
+            func.header.name.value,
+            SymbolKind::Method,
+            func.header.name.span,
+        )
+    }
+}
+
+/// Top-level declarations of a source file together with their members
+///
+/// Only parses the file, so an outline is available even if the project fails to compile.
+pub fn outline(src: &str) -> Vec<Symbol> {
+    declarations(src)
+        .iter()
+        .filter_map(|(_, decl)| symbol(decl))
+        .collect()
+}
+
+fn declarations(src: &str) -> Vec<(Span, Declaration<'_>)> {
+    let mut parser = Parser::new(src);
+    std::iter::from_fn(|| parser.declaration()).collect()
+}
+
+fn symbol(decl: &Declaration) -> Option<Symbol> {
+    Some(match decl {
+        Declaration::Function(func) => Symbol::leaf(
+            func.header.name.value,
+            SymbolKind::Function,
+            func.header.name.span,
+        ),
+        Declaration::Type(ty) => {
+            let (kind, children) = match &ty.body {
+                DeclarationBody::Record(record) => {
+                    let fields = record.fields.values();
+                    let fields = fields
+                        .map(|(span, name, _)| Symbol::leaf(name, SymbolKind::Field, *span))
+                        .collect();
+                    (SymbolKind::Record, fields)
+                }
+                DeclarationBody::Sum(sum) => {
+                    let variants = sum.variants.values();
+                    let variants = variants
+                        .map(|(span, name, _)| Symbol::leaf(name, SymbolKind::Variant, *span))
+                        .collect();
+                    (SymbolKind::Sum, variants)
+                }
+                DeclarationBody::Trait(trait_) => {
+                    let methods = trait_.methods.values().map(Symbol::method).collect();
+                    (SymbolKind::Trait, methods)
+                }
+                DeclarationBody::None => (SymbolKind::Type, vec![]),
+            };
+            let name = ty.header.name.to_string();
+            Symbol { name, kind, span: ty.header.span, children }
+        }
+        Declaration::Alias(alias) => {
+            Symbol::leaf(alias.name.value, SymbolKind::Alias, alias.name.span)
+        }
+        Declaration::Val(val) => Symbol::leaf(val.name, SymbolKind::Val, val.span),
+        Declaration::Impl(impl_) => Symbol {
+            name: format!(
+                "impl {} for {}",
+                impl_.header.trait_.value, impl_.header.impltor.value
+            ),
+            kind: SymbolKind::Impl,
+            span: impl_.header.span,
+            children: impl_.methods.values().map(Symbol::method).collect(),
+        },
+        Declaration::Use(_) | Declaration::ModuleAttribute(..) | Declaration::Failure => {
+            return None
+        }
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    Comment,
+    String,
+    Number,
+    Operator,
+    Function,
+    Method,
+    Type,
+    TypeParameter,
+    Namespace,
+}
+
+/// Classify the tokens of a module for highlighting
+///
+/// Identifiers are classified by resolving them from the module, and by the generics declared by
+/// the declaration they're in. Local bindings don't resolve and are left out.
+pub fn semantic_tokens(ast: &AST, module: key::Module) -> Vec<(Span, TokenKind)> {
+    let src = ast.sources.get(module);
+
+    // Generics in scope starting at each declaration, in source order
+    let generics: Vec<(u32, Vec<&str>)> = declarations(src)
+        .iter()
+        .map(|(span, decl)| {
+            let names = match decl {
+                Declaration::Function(func) => func
+                    .header
+                    .when
+                    .generics
+                    .iter()
+                    .map(|(_, name, _)| *name)
+                    .collect(),
+                Declaration::Impl(impl_) => impl_
+                    .header
+                    .when
+                    .generics
+                    .iter()
+                    .map(|(_, name, _)| *name)
+                    .collect(),
+                Declaration::Type(ty) => ty.header.type_params.values().copied().collect(),
+                _ => vec![],
+            };
+            (span.indice, names)
+        })
+        .collect();
+
+    let mut in_scope: &[&str] = &[];
+    let mut next = generics.iter().peekable();
+
+    let mut tokens = vec![];
+
+    for (token, span) in lumina_parser::tokenize(src) {
+        while let Some((_, names)) = next.next_if(|(start, _)| *start <= span.indice) {
+            in_scope = names.as_slice();
+        }
+
+        let kind = match token {
+            Token::Pub
+            | Token::If
+            | Token::Can
+            | Token::As
+            | Token::Where
+            | Token::When
+            | Token::Match
+            | Token::For
+            | Token::Then
+            | Token::Else
+            | Token::Let
+            | Token::In
+            | Token::Do
+            | Token::Fn
+            | Token::Alias
+            | Token::Type
+            | Token::Trait
+            | Token::Use
+            | Token::Impl
+            | Token::Val
+            | Token::Default => TokenKind::Keyword,
+            Token::LineComment | Token::LineDocComment => TokenKind::Comment,
+            Token::StringLiteral | Token::CharLiteral => TokenKind::String,
+            Token::Int | Token::Float => TokenKind::Number,
+            Token::Operator => TokenKind::Operator,
+            Token::Path => match classify(ast, module, in_scope, span.get_str(src)) {
+                Some(kind) => kind,
+                None => continue,
+            },
+            Token::AnnotatedPath => {
+                let span = span.shortened(1);
+                if let Some(kind) = classify(ast, module, in_scope, span.get_str(src)) {
+                    tokens.push((span, kind));
+                }
+                continue;
+            }
+            _ => continue,
+        };
+
+        tokens.push((span, kind));
+    }
+
+    tokens
+}
+
+fn classify(ast: &AST, module: key::Module, generics: &[&str], path: &str) -> Option<TokenKind> {
+    if generics.contains(&path) {
+        return Some(TokenKind::TypeParameter);
+    }
+
+    let path: Vec<&str> = path.split(':').collect();
+    let entity = ast.lookups.resolve_func(module, &path).ok()?;
+
+    Some(match entity.key {
+        Entity::Func(_) => TokenKind::Function,
+        Entity::Member(..) => TokenKind::Method,
+        Entity::Type(_) | Entity::Alias(_) => TokenKind::Type,
+        Entity::Module(_) => TokenKind::Namespace,
+    })
+}
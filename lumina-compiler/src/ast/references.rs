@@ -0,0 +1,191 @@
+//! Uses of functions and types recorded while resolving names, for refactoring and navigation
+
+use super::{Entity, NFunc, AST};
+use crate::prelude::*;
+use std::cell::RefCell;
+
+/// An item which can be referred to by name
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Referent {
+    Func(M<NFunc>),
+    Type(M<key::TypeKind>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Reference {
+    pub module: key::Module,
+    /// Span of the name itself, excluding the modules or type it's accessed through
+    pub span: Span,
+    pub referent: Referent,
+    pub declaration: bool,
+}
+
+/// References recorded so far
+///
+/// Names are resolved from shared references to the AST, so the table is kept behind a `RefCell`.
+#[derive(Default)]
+pub struct References(RefCell<Vec<Reference>>);
+
+impl References {
+    /// Record a use of `referent` through the path at `span`
+    ///
+    /// `segment` counts from the end of the path, so that the type in `Maybe:Just` can be recorded
+    /// as well as the variant.
+    pub fn used(
+        &self,
+        src: &str,
+        module: key::Module,
+        span: Span,
+        segment: usize,
+        referent: Referent,
+    ) {
+        let span = path_segment(src, span, segment);
+        self.push(Reference { module, span, referent, declaration: false });
+    }
+
+    /// Record the declaration of `referent`, named at the start of `span`
+    pub fn declared(&self, src: &str, module: key::Module, span: Span, referent: Referent) {
+        let span = path_segment(src, span, 0);
+        self.push(Reference { module, span, referent, declaration: true });
+    }
+
+    fn push(&self, reference: Reference) {
+        self.0.borrow_mut().push(reference);
+    }
+
+    pub fn take(&self) -> Vec<Reference> {
+        self.0.take()
+    }
+}
+
+/// Narrow the span of a path down to one of its segments, counting from the end
+///
+/// The span may extend past the path itself, such as for type declarations followed by their
+/// type parameters.
+fn path_segment(src: &str, span: Span, segment: usize) -> Span {
+    let text = span.get_str(src);
+
+    let is_name = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    if !text.starts_with(|c: char| is_name(c)) {
+        let len = text.find(char::is_whitespace).unwrap_or(text.len());
+        return Span::new(span.indice, len as u16);
+    }
+
+    let path = &text[..text.find(|c| !is_name(c) && c != ':').unwrap_or(text.len())];
+
+    let mut end = path.len();
+    for _ in 0..segment {
+        end = path[..end].rfind(':').unwrap_or(end);
+    }
+    let start = path[..end].rfind(':').map_or(0, |i| i + 1);
+
+    Span::new(span.indice + start as u32, (end - start) as u16)
+}
+
+impl<'s> AST<'s> {
+    /// Resolve a path such as `list:map` or `Maybe:Just` from the main module
+    ///
+    /// Fields and aliases aren't referents and resolve to `None`.
+    pub fn referent(&self, path: &[&str]) -> Option<Referent> {
+        let module = self.main_module;
+
+        let entity = match self.lookups.resolve_func(module, path) {
+            Ok(entity) => entity,
+            Err(_) => self.lookups.resolve_type(module, path).ok()?,
+        };
+
+        match entity.key {
+            Entity::Func(nfunc) => Some(Referent::Func(M(entity.module, nfunc))),
+            Entity::Type(kind) => Some(Referent::Type(M(entity.module, kind))),
+            Entity::Member(key::TypeKind::Trait(trait_), name) => {
+                let methods = &self.entities.methods[trait_.inside(entity.module)];
+                let method = methods.find(|func| {
+                    *self.entities.fheaders[func.inside(entity.module)].name == name
+                })?;
+                let nfunc = NFunc::Method(trait_, method);
+                Some(Referent::Func(M(entity.module, nfunc)))
+            }
+            Entity::Member(key::TypeKind::Sum(sum), name) => {
+                let variants = &self.entities.variant_names[sum.inside(entity.module)];
+                let var = variants.find(|n| **n == name)?;
+                Some(Referent::Func(M(entity.module, NFunc::SumVar(sum, var))))
+            }
+            Entity::Member(key::TypeKind::Record(_), _) | Entity::Module(_) | Entity::Alias(_) => {
+                None
+            }
+        }
+    }
+
+    pub(crate) fn record_use(
+        &self,
+        module: key::Module,
+        span: Span,
+        segment: usize,
+        referent: Referent,
+    ) {
+        let src = self.sources.get(module);
+        self.references.used(src, module, span, segment, referent)
+    }
+
+    /// Record the names of all declarations
+    ///
+    /// Methods of implementations are recorded as references to the trait method they implement,
+    /// which requires the traits of implementations to already be resolved.
+    pub(crate) fn record_declarations(&self, trait_of: impl Fn(M<key::Impl>) -> M<key::Trait>) {
+        let entities = &self.entities;
+        let declared = |module, span, referent| {
+            let src = self.sources.get(module);
+            self.references.declared(src, module, span, referent);
+        };
+
+        let mut members = HashMap::new();
+        for trait_ in entities.methods.iter() {
+            for (method, func) in entities.methods[trait_].iter() {
+                let nfunc = NFunc::Method(trait_.1, method);
+                members.insert(func.inside(trait_.0), Referent::Func(M(trait_.0, nfunc)));
+            }
+        }
+        for val in entities.vals.iter() {
+            let nfunc = NFunc::Val(val.1);
+            members.insert(entities.vals[val], Referent::Func(M(val.0, nfunc)));
+        }
+        for impl_ in entities.imethods.iter() {
+            let trait_ = trait_of(impl_);
+            let methods = &entities.methods[trait_];
+            for func in entities.imethods[impl_].values() {
+                let name = entities.fheaders[func.inside(impl_.0)].name;
+                let method = methods.find(|f| *entities.fheaders[f.inside(trait_.0)].name == *name);
+                if let Some(method) = method {
+                    let nfunc = NFunc::Method(trait_.1, method);
+                    members.insert(func.inside(impl_.0), Referent::Func(M(trait_.0, nfunc)));
+                }
+            }
+        }
+
+        for func in entities.fheaders.iter() {
+            let referent = members
+                .get(&func)
+                .copied()
+                .unwrap_or(Referent::Func(M(func.0, NFunc::Key(func.1))));
+            declared(func.0, entities.fheaders[func].name.span, referent);
+        }
+
+        let types = entities
+            .records
+            .iter()
+            .map(|k| k.map(key::TypeKind::Record))
+            .chain(entities.sums.iter().map(|k| k.map(key::TypeKind::Sum)))
+            .chain(entities.traits.iter().map(|k| k.map(key::TypeKind::Trait)));
+        for kind in types {
+            let span = entities.header_of_ty(kind).header.span;
+            declared(kind.0, span, Referent::Type(kind));
+        }
+
+        for sum in entities.variant_names.iter() {
+            for (var, name) in entities.variant_names[sum].iter() {
+                let nfunc = NFunc::SumVar(sum.1, var);
+                declared(sum.0, name.span, Referent::Func(M(sum.0, nfunc)));
+            }
+        }
+    }
+}
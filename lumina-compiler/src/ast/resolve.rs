@@ -1,4 +1,4 @@
-use super::Sources;
+use super::{Fix, Sources};
 use crate::impl_map_arrow_fmt;
 use itertools::Itertools;
 use lumina_key as key;
@@ -6,7 +6,7 @@ use lumina_key::{Map, M};
 use lumina_parser::Type;
 use lumina_util::Tr;
 use lumina_util::{Highlighting, Span};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
 use tracing::trace;
 
@@ -268,7 +268,7 @@ impl<'s> Lookups<'s> {
         let start_at = match self.libs.get(path[0]) {
             Some(libs) => {
                 let lname = path.get(1).copied().unwrap_or("");
-                path = &path[2..];
+                path = path.get(2..).unwrap_or(&[]);
 
                 match libs.get(lname) {
                     None => return Err(ImportError::LibNotInstalled(lname)),
@@ -395,7 +395,7 @@ impl<'s> Lookups<'s> {
         }
     }
 
-    fn are_members_of_same_project(&self, modules: &[key::Module]) -> bool {
+    pub fn are_members_of_same_project(&self, modules: &[key::Module]) -> bool {
         let mut ms = modules.iter().copied().map(|of| self.get_root_module(of));
         let m = ms.next().unwrap();
         ms.all(|m_| m_ == m)
@@ -407,7 +407,7 @@ impl<'s> Lookups<'s> {
                 Some(p) => self.get_root_module(p),
                 None => of,
             },
-            ModuleKind::Member { root } => root,
+            ModuleKind::Member { root } => self.get_root_module(root),
         }
     }
 
@@ -421,6 +421,71 @@ impl<'s> Lookups<'s> {
         }
     }
 
+    /// Modules which `module` imports, or imports entities from
+    pub fn imported_modules(&self, module: key::Module) -> impl Iterator<Item = key::Module> + '_ {
+        let namespaces = &self.modules[module];
+        namespaces
+            .child_modules
+            .values()
+            .map(|link| link.key)
+            .chain(namespaces.funcs.values().map(|m| m.module))
+            .chain(namespaces.types.values().map(|m| m.module))
+            .chain(namespaces.aliases.values().map(|m| m.module))
+            .chain(namespaces.accessors.values().flatten().map(|m| m.module))
+    }
+
+    /// Paths of the modules which publicly declare an item named `name`, for suggesting imports
+    ///
+    /// The modules of the project are searched before the included libraries.
+    pub fn import_candidates(&self, from: key::Module, name: &str, types: bool) -> Vec<String> {
+        let mut queue: VecDeque<(String, key::Module)> = self
+            .members(self.get_root_module(from))
+            .map(|(cname, child)| (cname.clone(), child))
+            .collect();
+
+        for lib in ["std", "ext"] {
+            for (lname, module) in self.libs[lib].iter().sorted_by_key(|(lname, _)| *lname) {
+                queue.push_back((format!("{lib}:{lname}"), *module));
+            }
+        }
+
+        let mut candidates = vec![];
+
+        while let Some((path, module)) = queue.pop_front() {
+            let namespaces = &self.modules[module];
+            let declared = if types {
+                namespaces.types.get(name).map(|m| (m.module, m.visibility))
+            } else {
+                namespaces.funcs.get(name).map(|m| (m.module, m.visibility))
+            };
+
+            // Items exposed through an import are suggested from the module declaring them instead
+            if let Some((m, vis)) = declared {
+                if m == module && module != from && self.is_valid_reachability(from, vis) {
+                    candidates.push(path.clone());
+                }
+            }
+
+            for (cname, child) in self.members(module) {
+                queue.push_back((format!("{path}:{cname}"), child));
+            }
+        }
+
+        candidates
+    }
+
+    /// Modules which are members of a module, such as the files of its directory, by name
+    fn members(&self, module: key::Module) -> impl Iterator<Item = (&String, key::Module)> + '_ {
+        self.modules[module]
+            .child_modules
+            .iter()
+            .filter(move |(_, link)| {
+                matches!(self.modules[link.key].kind, ModuleKind::Member { root } if root == module)
+            })
+            .map(|(name, link)| (name, link.key))
+            .sorted_by_key(|(name, _)| *name)
+    }
+
     /// Standard libraries do not have to be declared as dependencies but are still lazily included
     /// when imported. This checks whether the given path is an un-included standard library
     pub fn lib_should_be_included<'a, 'b>(
@@ -592,7 +657,7 @@ impl<'s> Namespaces<'s> {
 }
 
 /// Pointer to something in the function namespace
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum NFunc {
     Key(key::Func),
     Method(key::Trait, key::Method),
@@ -640,6 +705,44 @@ impl Sources {
     }
 }
 
+impl<'s> super::AST<'s> {
+    /// Report a failed lookup, suggesting imports if the name is declared in another module
+    pub fn emit_lookup_err(&self, span: Span, module: key::Module, kind: &str, err: ImportError) {
+        match err {
+            ImportError::NotFound(m, name) if m == module => {
+                let candidates = self.lookups.import_candidates(module, name, kind == "type");
+
+                let at = self.sources.import_position(module);
+                let before = &self.sources.get(module)[..at.indice as usize];
+                let separator = if before.is_empty() || before.ends_with('\n') {
+                    ""
+                } else {
+                    "\n"
+                };
+
+                let mut error = self
+                    .sources
+                    .error("identifier not found")
+                    .m(module)
+                    .eline(span, format!("no {kind} named {name}"));
+
+                for path in candidates {
+                    let import = format!("use {path} [{name}]");
+                    error = error.fix(Fix {
+                        module,
+                        span: at,
+                        replacement: format!("{separator}{import}\n"),
+                        title: format!("add `{import}`"),
+                    });
+                }
+
+                error.emit()
+            }
+            err => self.sources.emit_lookup_err(span, module, kind, err),
+        }
+    }
+}
+
 impl_map_arrow_fmt!(<'s> std::fmt::Debug; for Lookups<'s>;  ("modules", modules, |(k, v)| format!("{k} → {v:#?}")));
 
 impl fmt::Display for NFunc {
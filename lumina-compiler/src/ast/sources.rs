@@ -1,6 +1,6 @@
 use crate::prelude::*;
 use lumina_util::LineMode;
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::path::{Path, PathBuf};
 
 pub struct Sources {
@@ -8,6 +8,17 @@ pub struct Sources {
     paths: Map<key::Module, PathBuf>,
     panicy: bool,
     has_failed: Cell<bool>,
+    fixes: RefCell<Vec<Vec<Fix>>>,
+}
+
+/// An edit which resolves a diagnostic, for editors and `lumina fix` to apply
+#[derive(Clone, Debug)]
+pub struct Fix {
+    pub module: key::Module,
+    /// Span to replace, which is empty for insertions
+    pub span: Span,
+    pub replacement: String,
+    pub title: String,
 }
 
 impl Sources {
@@ -18,6 +29,7 @@ impl Sources {
             paths: Map::new(),
             panicy: true,
             has_failed: Cell::new(false),
+            fixes: RefCell::new(vec![]),
         }
     }
 
@@ -33,6 +45,25 @@ impl Sources {
         self.has_failed.get()
     }
 
+    /// The alternative fixes of each emitted diagnostic which had any
+    pub fn take_fixes(&self) -> Vec<Vec<Fix>> {
+        self.fixes.take()
+    }
+
+    /// Position following the last `use` declaration of a module, where new imports are inserted
+    pub fn import_position(&self, module: key::Module) -> Span {
+        let src = self.get(module);
+        let mut at = 0;
+        let mut offset = 0;
+        for line in src.split_inclusive('\n') {
+            offset += line.len();
+            if line.starts_with("use ") {
+                at = offset;
+            }
+        }
+        Span::new(at as u32, 0)
+    }
+
     pub fn name_of_module(&self, module: key::Module) -> String {
         let name = self.paths[module].file_stem().unwrap();
         if name == std::ffi::OsStr::new("lib") {
@@ -91,6 +122,7 @@ impl Sources {
             sources: self,
             module: None,
             error: lumina_util::Error::warning(name),
+            fixes: vec![],
         }
     }
 
@@ -99,6 +131,7 @@ impl Sources {
             sources: self,
             module: None,
             error: lumina_util::Error::error(name),
+            fixes: vec![],
         }
     }
 }
@@ -108,6 +141,7 @@ pub struct ErrorBuilder<'a> {
     sources: &'a Sources,
     module: Option<key::Module>,
     error: lumina_util::Error,
+    fixes: Vec<Fix>,
 }
 
 impl<'a> ErrorBuilder<'a> {
@@ -147,8 +181,27 @@ impl<'a> ErrorBuilder<'a> {
         self
     }
 
+    /// Suggest an edit which resolves the diagnostic
+    ///
+    /// Adding several fixes presents them as alternatives.
+    #[must_use]
+    pub fn fix(mut self, fix: Fix) -> Self {
+        self.error = self.error.with_text(format!("help: {}", fix.title));
+        self.fixes.push(fix);
+        self
+    }
+
     #[track_caller]
     pub fn emit(self) {
+        if !self.fixes.is_empty() {
+            self.sources.fixes.borrow_mut().push(self.fixes);
+        }
+
+        if self.error.is_warning() {
+            eprintln!("{}", self.error);
+            return;
+        }
+
         self.sources.has_failed.set(true);
         if self.sources.panicy {
             panic!("{}", self.error);
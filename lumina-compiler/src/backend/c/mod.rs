@@ -0,0 +1,1223 @@
+//! Backend lowering the LIR into portable C99
+//!
+//! Meant for platforms Cranelift doesn't support yet. The generated source is compiled into an
+//! object file with the C compiler from `$CC`, falling back to `cc`, using the flags in `$CFLAGS`.
+//!
+//! Records and sums become structs, with sum payloads stored in a union. Self-referential fields
+//! are boxed on the heap since C can't express recursive types by value. Every function body is a
+//! loop switching on the current block, with block parameters assigned before each jump.
+
+use super::{Backend, Error};
+use crate::debuginfo::BinDebugInfo;
+use crate::lir::{
+    self, AtomicOrdering, AtomicRmwOp, BinOp, Block, BlockJump, Entry, MonoFunc, MonoType,
+    MonoTypeData, MonoTypeKey, Value, V,
+};
+use crate::prelude::*;
+use crate::target::{LinuxPlatform, Platform};
+use crate::Target;
+use ast::attr::Repr;
+use lumina_typesystem::{FloatSize, IntSize};
+use lumina_util::Symbol;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::process::Command;
+
+const DEFAULT_CFLAGS: &[&str] = &[
+    "-O2",
+    "-fno-strict-aliasing",
+    "-ffunction-sections",
+    "-fdata-sections",
+];
+
+const PRELUDE: &str = "\
+#include <stddef.h>
+#include <stdint.h>
+
+#if defined(__GNUC__)
+#define LUMINA_TRAP() __builtin_trap()
+#define LUMINA_WEAK __attribute__((weak))
+#define LUMINA_SECTION(name) __attribute__((section(name)))
+#define LUMINA_PACKED __attribute__((packed))
+#define LUMINA_ALIGN(n) __attribute__((aligned(n)))
+#else
+#define LUMINA_TRAP() (*(volatile int *)0 = 0)
+#define LUMINA_WEAK
+#define LUMINA_SECTION(name)
+#define LUMINA_PACKED
+#define LUMINA_ALIGN(n)
+#endif
+
+#define LUMINA_ALIGNOF(T) offsetof(struct { char c; T t; }, t)
+
+static void lumina_memcpy(void *dst, const void *src, size_t n) {
+    unsigned char *d = dst;
+    const unsigned char *s = src;
+    while (n--) *d++ = *s++;
+}
+
+static void lumina_memset(void *dst, unsigned char byte, size_t n) {
+    unsigned char *d = dst;
+    while (n--) *d++ = byte;
+}
+";
+
+#[derive(Default)]
+pub struct C {
+    /// If set, the generated C source is written to it
+    pub source: Option<String>,
+}
+
+impl Backend for C {
+    fn compile(
+        &mut self,
+        target: Target,
+        _dwarf: BinDebugInfo,
+        mut lir: lir::Output,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        if let Platform::Linux { sub: LinuxPlatform::Syscall }
+        | Platform::Windows
+        | Platform::Wasi = target.platform
+        {
+            return Err(Error::Target(target.to_string()));
+        }
+
+        let bodies = lir.take_bodies();
+        let source = Codegen::new(&lir).run(bodies)?;
+
+        if let Some(out) = self.source.as_mut() {
+            out.push_str(&source);
+        }
+
+        compile_source(&source).map(|object| vec![object])
+    }
+}
+
+/// Invoke the system C compiler to turn the generated source into an object file
+fn compile_source(source: &str) -> Result<Vec<u8>, Error> {
+    let workdir = std::env::temp_dir().join(format!("lumina-c-{}", std::process::id()));
+    let io = |err: std::io::Error| Error::Codegen(err.to_string());
+    std::fs::create_dir_all(&workdir).map_err(io)?;
+
+    let input = workdir.join("lumina.c");
+    let output = workdir.join("lumina.o");
+    std::fs::write(&input, source).map_err(io)?;
+
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let mut command = Command::new(&cc);
+    match std::env::var("CFLAGS") {
+        Ok(flags) => command.args(flags.split_whitespace()),
+        Err(_) => command.args(DEFAULT_CFLAGS),
+    };
+    command.arg("-c").arg("-o").arg(&output).arg(&input);
+
+    info!("invoking C compiler as: {:#?}", command);
+
+    let status = command
+        .status()
+        .map_err(|err| Error::Codegen(format!("failed to invoke C compiler {cc}: {err}")))?;
+
+    if !status.success() {
+        return Err(Error::Codegen(format!("C compiler exited with {status}")));
+    }
+
+    let object = std::fs::read(&output).map_err(io)?;
+    let _ = std::fs::remove_dir_all(&workdir);
+    Ok(object)
+}
+
+pub(super) fn int(intsize: IntSize) -> Result<&'static str, Error> {
+    Ok(match (intsize.bits(), intsize.signed) {
+        (8, true) => "int8_t",
+        (8, false) => "uint8_t",
+        (16, true) => "int16_t",
+        (16, false) => "uint16_t",
+        (32, true) => "int32_t",
+        (32, false) => "uint32_t",
+        (64, true) => "int64_t",
+        (64, false) => "uint64_t",
+        (bits, _) => return Err(Error::Unsupported(format!("{bits}-bit integers"))),
+    })
+}
+
+/// The unsigned type arithmetic on an integer is performed in, to get wrapping semantics without
+/// signed overflow or integer promotion to `int`
+fn wrapping(intsize: IntSize) -> &'static str {
+    if intsize.bits() <= 32 {
+        "uint32_t"
+    } else {
+        "uint64_t"
+    }
+}
+
+fn memory_order(ordering: AtomicOrdering) -> &'static str {
+    match ordering {
+        AtomicOrdering::Relaxed => "__ATOMIC_RELAXED",
+        AtomicOrdering::Acquire => "__ATOMIC_ACQUIRE",
+        AtomicOrdering::Release => "__ATOMIC_RELEASE",
+        AtomicOrdering::AcqRel => "__ATOMIC_ACQ_REL",
+        AtomicOrdering::SeqCst => "__ATOMIC_SEQ_CST",
+    }
+}
+
+fn is_c_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn int_literal(n: i128, intsize: IntSize) -> Result<String, Error> {
+    let ty = int(intsize)?;
+    Ok(if (0..=i32::MAX as i128).contains(&n) {
+        format!("(({ty}){n})")
+    } else {
+        format!("(({ty})UINT64_C({}))", n as u64)
+    })
+}
+
+fn float_literal(n: f64) -> String {
+    if n.is_nan() {
+        "(0.0 / 0.0)".into()
+    } else if n.is_infinite() && n > 0.0 {
+        "(1.0 / 0.0)".into()
+    } else if n.is_infinite() {
+        "(-1.0 / 0.0)".into()
+    } else {
+        format!("{n:e}")
+    }
+}
+
+struct Codegen<'a> {
+    lir: &'a lir::Output,
+
+    /// Struct definitions and typedefs, in the order they need to be declared
+    types: String,
+    /// Names of the typedefs declared for arrays and function pointers
+    typedefs: HashMap<MonoType, String>,
+
+    defined: HashSet<MonoTypeKey>,
+    in_progress: HashSet<MonoTypeKey>,
+    zst: HashSet<MonoTypeKey>,
+    /// Record fields and sum variants stored behind a pointer because they refer back to a type
+    /// which contains them
+    boxed: HashSet<(MonoTypeKey, u32)>,
+
+    functions: Map<MonoFunc, Symbol>,
+    externs: HashMap<M<key::Func>, Symbol>,
+    vals: HashMap<M<key::Val>, Symbol>,
+    rodata: HashMap<M<key::ReadOnly>, Symbol>,
+}
+
+/// State of the function currently being lowered
+struct Func<'f> {
+    func: &'f lir::Function,
+    params: HashMap<Block, Vec<V>>,
+    out: String,
+}
+
+impl<'a> Codegen<'a> {
+    fn new(lir: &'a lir::Output) -> Self {
+        Codegen {
+            lir,
+            types: String::new(),
+            typedefs: HashMap::new(),
+            defined: HashSet::new(),
+            in_progress: HashSet::new(),
+            zst: HashSet::new(),
+            boxed: HashSet::new(),
+            functions: Map::new(),
+            externs: HashMap::new(),
+            vals: HashMap::new(),
+            rodata: HashMap::new(),
+        }
+    }
+
+    fn run(mut self, bodies: Map<MonoFunc, lir::Function>) -> Result<String, Error> {
+        let mut src = String::from(PRELUDE);
+
+        writeln!(src).unwrap();
+        let lir = self.lir;
+        for key in lir.types.keys() {
+            if !matches!(lir.types[key], MonoTypeData::Placeholder) {
+                writeln!(src, "typedef struct mr{0} mr{0};", key.0).unwrap();
+            }
+        }
+
+        for key in lir.types.keys() {
+            if !matches!(lir.types[key], MonoTypeData::Placeholder) {
+                self.define_key(key)?;
+            }
+        }
+
+        let decls = self.declarations()?;
+        let mut definitions = String::new();
+        for (mfunc, func) in bodies {
+            info!("lowering function {} to C", func.symbol);
+            self.function(mfunc, &func, &mut definitions)?;
+        }
+        self.entrypoint(&mut definitions)?;
+
+        writeln!(src, "\n{}\n{decls}\n{definitions}", self.types).unwrap();
+        Ok(src)
+    }
+
+    /// Zero-sized types are erased, which is why this returns `None` for the unit type
+    fn ty(&mut self, ty: &MonoType) -> Result<Option<String>, Error> {
+        match ty {
+            MonoType::Int(intsize) => int(*intsize).map(|ty| Some(ty.to_string())),
+            MonoType::Float(FloatSize::F32) => Ok(Some("float".into())),
+            MonoType::Float(FloatSize::F64) => Ok(Some("double".into())),
+            MonoType::Pointer(_) => Ok(Some("void *".into())),
+            MonoType::Unreachable | MonoType::Const(_) => Ok(None),
+            MonoType::FnPointer(..) | MonoType::Array(..) => {
+                self.define(ty)?;
+                Ok(self.typedefs.get(ty).cloned())
+            }
+            MonoType::Monomorphised(key) => {
+                if !self.in_progress.contains(key) {
+                    self.define_key(*key)?;
+                }
+                Ok((!self.zst.contains(key)).then(|| format!("mr{}", key.0)))
+            }
+        }
+    }
+
+    fn expect_ty(&mut self, ty: &MonoType) -> Result<String, Error> {
+        self.ty(ty)?
+            .ok_or_else(|| Error::Codegen("expected type to not be zero-sized".into()))
+    }
+
+    fn is_zst(&mut self, ty: &MonoType) -> Result<bool, Error> {
+        self.ty(ty).map(|ty| ty.is_none())
+    }
+
+    fn define(&mut self, ty: &MonoType) -> Result<(), Error> {
+        if self.typedefs.contains_key(ty) {
+            return Ok(());
+        }
+
+        match ty {
+            MonoType::Array(len, inner) => {
+                if let MonoType::Monomorphised(key) = &**inner {
+                    if self.in_progress.contains(key) {
+                        return Err(Error::Unsupported("recursive arrays".into()));
+                    }
+                }
+                let Some(inner) = self.ty(inner)? else {
+                    return Ok(());
+                };
+                if *len == 0 {
+                    return Ok(());
+                }
+                let name = format!("lm_arr{}", self.typedefs.len());
+                writeln!(
+                    self.types,
+                    "typedef struct {{ {inner} items[{len}]; }} {name};"
+                )
+                .unwrap();
+                self.typedefs.insert(ty.clone(), name);
+            }
+            MonoType::FnPointer(params, ret) => {
+                let signature = self.signature("(*{})", params, ret)?;
+                let name = format!("lm_fn{}", self.typedefs.len());
+                writeln!(self.types, "typedef {};", signature.replace("{}", &name)).unwrap();
+                self.typedefs.insert(ty.clone(), name);
+            }
+            MonoType::Monomorphised(key) => self.define_key(*key)?,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn define_key(&mut self, key: MonoTypeKey) -> Result<(), Error> {
+        if self.defined.contains(&key) || self.in_progress.contains(&key) {
+            return Ok(());
+        }
+        self.in_progress.insert(key);
+
+        let lir = self.lir;
+        let mut body = String::new();
+        let mut attrs = String::new();
+
+        match &lir.types[key] {
+            MonoTypeData::Record { repr, fields, .. } => {
+                match repr {
+                    Repr::Lumina | Repr::C => {}
+                    Repr::Packed => attrs.push_str(" LUMINA_PACKED"),
+                    Repr::Align(n) => write!(attrs, " LUMINA_ALIGN({n})").unwrap(),
+                    Repr::Enum(_) => unreachable!(),
+                }
+                for (field, ty) in fields.iter() {
+                    match ty {
+                        MonoType::Monomorphised(inner) if self.in_progress.contains(inner) => {
+                            self.boxed.insert((key, field.0));
+                            writeln!(body, "    mr{} *f{};", inner.0, field.0).unwrap();
+                        }
+                        _ => {
+                            if let Some(ty) = self.ty(ty)? {
+                                writeln!(body, "    {ty} f{};", field.0).unwrap();
+                            }
+                        }
+                    }
+                }
+            }
+            MonoTypeData::Sum { tag, variants, .. } => {
+                writeln!(body, "    {} tag;", int(*tag)?).unwrap();
+                let mut payload = String::new();
+                for (var, tuple) in variants.iter() {
+                    if self.in_progress.contains(tuple) {
+                        self.boxed.insert((key, var.0));
+                        writeln!(payload, "        mr{} *v{};", tuple.0, var.0).unwrap();
+                    } else if let Some(ty) = self.ty(&MonoType::Monomorphised(*tuple))? {
+                        writeln!(payload, "        {ty} v{};", var.0).unwrap();
+                    }
+                }
+                if !payload.is_empty() {
+                    writeln!(body, "    union {{\n{payload}    }} payload;").unwrap();
+                }
+            }
+            MonoTypeData::DynTraitObject { vtable, .. } => {
+                if let MonoType::Monomorphised(inner) = vtable {
+                    if self.in_progress.contains(inner) {
+                        return Err(Error::Unsupported("recursive vtables".into()));
+                    }
+                }
+                writeln!(body, "    void *data;").unwrap();
+                if let Some(ty) = self.ty(vtable)? {
+                    writeln!(body, "    {ty} vtable;").unwrap();
+                }
+            }
+            MonoTypeData::Placeholder => panic!("placeholder type in LIR output"),
+        }
+
+        if body.is_empty() {
+            self.zst.insert(key);
+        } else {
+            writeln!(self.types, "struct mr{} {{\n{body}}}{attrs};", key.0).unwrap();
+        }
+
+        self.in_progress.remove(&key);
+        self.defined.insert(key);
+
+        Ok(())
+    }
+
+    /// Format a function signature, where `{}` in `name` is the position of the declarator
+    fn signature<'t>(
+        &mut self,
+        name: &str,
+        params: impl IntoIterator<Item = &'t MonoType>,
+        returns: &MonoType,
+    ) -> Result<String, Error> {
+        let mut ptypes = vec![];
+        for ty in params {
+            if let Some(ty) = self.ty(ty)? {
+                ptypes.push(ty);
+            }
+        }
+        let params = if ptypes.is_empty() {
+            "void".to_string()
+        } else {
+            ptypes.join(", ")
+        };
+        let ret = self.ty(returns)?.unwrap_or_else(|| "void".into());
+        Ok(format!("{ret} {name}({params})"))
+    }
+
+    /// Declare extern functions, prototypes of all functions, vals and read-only data
+    fn declarations(&mut self) -> Result<String, Error> {
+        let lir = self.lir;
+        let mut out = String::new();
+
+        let mut declared = HashSet::new();
+        for (key, func) in lir.extern_funcs.iter() {
+            if !is_c_identifier(&func.symbol) {
+                return Err(Error::Unsupported(format!(
+                    "the symbol name {}",
+                    func.symbol
+                )));
+            }
+            self.externs.insert(*key, func.symbol);
+
+            if !declared.insert(func.symbol.as_str()) {
+                continue;
+            }
+
+            if func.data {
+                let ty = self.ty(&func.returns)?.unwrap_or_else(|| "char".into());
+                writeln!(out, "extern {ty} {};", func.symbol).unwrap();
+            } else if func.variadic {
+                let signature = self.signature(&func.symbol, &func.params, &func.returns)?;
+                // Without any named parameters, only an unprototyped declaration accepts `...`
+                let signature = match signature.strip_suffix("(void)") {
+                    Some(name) => format!("{name}()"),
+                    None => format!("{}, ...)", signature.strip_suffix(')').unwrap()),
+                };
+                writeln!(out, "extern {signature};").unwrap();
+            } else {
+                let signature = self.signature(&func.symbol, &func.params, &func.returns)?;
+                writeln!(out, "extern {signature};").unwrap();
+            }
+        }
+
+        for (mfunc, func) in lir.functions.iter() {
+            // Generated functions already use the C calling convention, so exported functions
+            // are simply given their exported name
+            let name = match (func.export, func.linkage.visibility) {
+                (Some(export), _) if !is_c_identifier(&export) => {
+                    return Err(Error::Unsupported(format!("exporting {export}")));
+                }
+                (Some(export), _) => export.to_string(),
+                (None, ast::SymbolVisibility::Default) if !is_c_identifier(&func.symbol) => {
+                    return Err(Error::Unsupported(format!("exporting {}", func.symbol)));
+                }
+                (None, ast::SymbolVisibility::Default) => func.symbol.to_string(),
+                (None, ast::SymbolVisibility::Hidden) => format!("lm_{mfunc}"),
+            };
+
+            let mut attrs = String::new();
+            if func.linkage.visibility == ast::SymbolVisibility::Hidden
+                && !func.linkage.weak
+                && func.export.is_none()
+            {
+                attrs.push_str("static ");
+            }
+            if func.linkage.weak {
+                attrs.push_str("LUMINA_WEAK ");
+            }
+            if let Some(section) = &func.linkage.section {
+                write!(attrs, "LUMINA_SECTION({section:?}) ").unwrap();
+            }
+
+            let signature = self.signature(&name, func.ssa.func_param_types(), &func.returns)?;
+            writeln!(out, "{attrs}{signature};").unwrap();
+
+            self.functions.push(Symbol::from(name));
+        }
+
+        for (i, val) in lir.val_types.iter().enumerate() {
+            let name = format!("lm_val{i}");
+            if let Some(ty) = self.ty(&lir.val_types[val])? {
+                let storage = if lir.thread_local_vals.contains(&val) {
+                    "static _Thread_local"
+                } else {
+                    "static"
+                };
+                writeln!(out, "{storage} {ty} {name};").unwrap();
+            }
+            self.vals.insert(val, Symbol::from(name));
+        }
+
+        for (i, ro) in lir.read_only_table.iter().enumerate() {
+            let name = format!("lm_ro{i}");
+            let (bytes, _) = &lir.read_only_table[ro];
+            let bytes = if bytes.0.is_empty() {
+                "0".to_string()
+            } else {
+                bytes.0.iter().format(", ").to_string()
+            };
+            writeln!(
+                out,
+                "static const unsigned char {name}[] LUMINA_ALIGN(16) = {{ {bytes} }};"
+            )
+            .unwrap();
+            self.rodata.insert(ro, Symbol::from(name));
+        }
+
+        writeln!(out, "static unsigned char lumina_zst;").unwrap();
+
+        Ok(out)
+    }
+
+    fn function(
+        &mut self,
+        mfunc: MonoFunc,
+        func: &lir::Function,
+        out: &mut String,
+    ) -> Result<(), Error> {
+        let ssa = &func.ssa;
+
+        let mut params = vec![];
+        for v in ssa.block_params(Block::entry()) {
+            if let Some(ty) = self.ty(ssa.type_of(v))? {
+                params.push(format!("{ty} {v}"));
+            }
+        }
+        let params = if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.join(", ")
+        };
+        let ret = self.ty(&func.returns)?.unwrap_or_else(|| "void".into());
+        let storage = match &func.linkage {
+            _ if func.export.is_some() => "",
+            ast::Linkage { weak: false, visibility: ast::SymbolVisibility::Hidden, .. } => {
+                "static "
+            }
+            _ => "",
+        };
+
+        writeln!(out, "/* {} */", func.symbol.replace("*/", "* /")).unwrap();
+        writeln!(out, "{storage}{ret} {}({params}) {{", self.functions[mfunc]).unwrap();
+
+        let mut f = Func { func, params: HashMap::new(), out: String::new() };
+
+        // Every value is declared upfront since the blocks are cases of the same switch
+        for block in ssa.blocks() {
+            f.params.insert(block, ssa.block_params(block).collect());
+        }
+        for v in ssa.iterv() {
+            if let Entry::BlockParam(Block(0), _) = ssa.entry_of(v) {
+                continue;
+            }
+            if let Some(ty) = self.ty(ssa.type_of(v))? {
+                writeln!(out, "    {ty} {v};").unwrap();
+            }
+            if let (Entry::Alloca, MonoType::Pointer(inner)) = (ssa.entry_of(v), ssa.type_of(v)) {
+                if let Some(ty) = self.ty(inner)? {
+                    writeln!(out, "    {ty} s{};", v.0).unwrap();
+                }
+            }
+        }
+
+        writeln!(
+            out,
+            "    uint32_t block = 0;\n    for (;;) {{\n    switch (block) {{"
+        )
+        .unwrap();
+
+        for v in ssa.iterv() {
+            if let Some((block, _)) = ssa.as_block_start(v) {
+                writeln!(f.out, "    case {}:", block.0).unwrap();
+            }
+            self.entry(&mut f, v)?;
+        }
+
+        out.push_str(&f.out);
+        writeln!(out, "    }}\n    }}\n}}\n").unwrap();
+
+        Ok(())
+    }
+
+    fn value(&mut self, f: &Func, value: &Value) -> Result<Option<String>, Error> {
+        Ok(Some(match value {
+            Value::V(v) if self.is_zst(f.func.ssa.type_of(*v))? => return Ok(None),
+            Value::V(v) => v.to_string(),
+            Value::Int(n, intsize) => int_literal(*n, *intsize)?,
+            Value::Float(n) => float_literal(*n),
+            Value::FuncPtr(mfunc) => self.functions[*mfunc].to_string(),
+            Value::ExternFuncPtr(key) => self.externs[key].to_string(),
+            Value::ReadOnly(ro) => format!("((void *){})", self.rodata[ro]),
+        }))
+    }
+
+    fn expect_value(&mut self, f: &Func, value: &Value) -> Result<String, Error> {
+        self.value(f, value)?
+            .ok_or_else(|| Error::Codegen(format!("expected {value} to not be zero-sized")))
+    }
+
+    fn args(&mut self, f: &Func, params: &[Value]) -> Result<String, Error> {
+        let mut args = Vec::with_capacity(params.len());
+        for param in params {
+            if let Some(value) = self.value(f, param)? {
+                args.push(value);
+            }
+        }
+        Ok(args.join(", "))
+    }
+
+    fn type_of_value(&self, f: &Func, value: &Value) -> MonoType {
+        match value {
+            Value::V(v) => f.func.ssa.type_of(*v).clone(),
+            Value::Int(_, intsize) => MonoType::Int(*intsize),
+            Value::Float(_) => MonoType::Float(FloatSize::F64),
+            Value::FuncPtr(mfunc) => self.lir.functions[*mfunc].as_fnpointer(),
+            Value::ExternFuncPtr(key) => {
+                let func = &self.lir.extern_funcs[key];
+                MonoType::FnPointer(func.params.clone(), Box::new(func.returns.clone()))
+            }
+            Value::ReadOnly(ro) => MonoType::pointer(self.lir.read_only_table[*ro].1.clone()),
+        }
+    }
+
+    /// Assign the parameters of the target block and jump to it
+    fn jump(&mut self, f: &mut Func, jump: &BlockJump, indent: &str) -> Result<(), Error> {
+        let mut assignments = vec![];
+        for (param, value) in f.params[&jump.id].clone().into_iter().zip(&jump.params) {
+            if let Some(value) = self.value(f, value)? {
+                let ty = self.expect_ty(f.func.ssa.type_of(param))?;
+                assignments.push((param, ty, value));
+            }
+        }
+
+        // Temporaries are needed since a parameter may be assigned from another parameter
+        match assignments.as_slice() {
+            [] => {}
+            [(param, _, value)] => writeln!(f.out, "{indent}{param} = {value};").unwrap(),
+            _ => {
+                writeln!(f.out, "{indent}{{").unwrap();
+                for (i, (_, ty, value)) in assignments.iter().enumerate() {
+                    writeln!(f.out, "{indent}    {ty} t{i} = {value};").unwrap();
+                }
+                for (i, (param, _, _)) in assignments.iter().enumerate() {
+                    writeln!(f.out, "{indent}    {param} = t{i};").unwrap();
+                }
+                writeln!(f.out, "{indent}}}").unwrap();
+            }
+        }
+
+        writeln!(f.out, "{indent}block = {};\n{indent}continue;", jump.id.0).unwrap();
+        Ok(())
+    }
+
+    fn size_of(&mut self, ty: &MonoType) -> Result<String, Error> {
+        Ok(match self.ty(ty)? {
+            Some(ty) => format!("sizeof({ty})"),
+            None => "0".into(),
+        })
+    }
+
+    /// Heap allocate a value for a boxed field
+    fn alloc(&mut self, ty: &MonoType) -> Result<String, Error> {
+        let size_t = self.size_t()?;
+        let size = self.size_of(ty)?;
+        Ok(format!(
+            "{}(({size_t}){size})",
+            self.functions[self.lir.alloc]
+        ))
+    }
+
+    /// The integer type taken by the allocator of the standard library
+    fn size_t(&self) -> Result<&'static str, Error> {
+        let alloc = &self.lir.functions[self.lir.alloc];
+        match alloc.ssa.func_param_types().next() {
+            Some(MonoType::Int(intsize)) => int(*intsize),
+            _ => Err(Error::Codegen("alloc doesn't take an integer".into())),
+        }
+    }
+
+    /// Initialise the fields of a record stored at `lvalue`
+    fn construct(
+        &mut self,
+        f: &mut Func,
+        lvalue: &str,
+        key: MonoTypeKey,
+        values: &[Value],
+    ) -> Result<(), Error> {
+        let lir = self.lir;
+        let fields = lir.types[key].as_record();
+        for (field, value) in fields.keys().zip(values) {
+            let Some(value) = self.value(f, value)? else {
+                continue;
+            };
+            if self.boxed.contains(&(key, field.0)) {
+                let alloc = self.alloc(&fields[field])?;
+                writeln!(f.out, "        {lvalue}.f{} = {alloc};", field.0).unwrap();
+                writeln!(f.out, "        *{lvalue}.f{} = {value};", field.0).unwrap();
+            } else {
+                writeln!(f.out, "        {lvalue}.f{} = {value};", field.0).unwrap();
+            }
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, f: &mut Func, v: V, callee: String, params: &[Value]) -> Result<(), Error> {
+        let args = self.args(f, params)?;
+        if self.is_zst(f.func.ssa.type_of(v))? {
+            writeln!(f.out, "        {callee}({args});").unwrap();
+        } else {
+            writeln!(f.out, "        {v} = {callee}({args});").unwrap();
+        }
+        Ok(())
+    }
+
+    fn entry(&mut self, f: &mut Func, v: V) -> Result<(), Error> {
+        let lir = self.lir;
+        let func = f.func;
+        let ssa = &func.ssa;
+        let ty = ssa.type_of(v).clone();
+
+        // Entries producing zero-sized values are only lowered for their side effects
+        let Some(cty) = self.ty(&ty)? else {
+            return self.effect(f, v);
+        };
+
+        let value = match ssa.entry_of(v) {
+            Entry::BlockParam(..) => return Ok(()),
+
+            Entry::CallStatic(mfunc, params) => {
+                let callee = self.functions[*mfunc].to_string();
+                return self.call(f, v, callee, params);
+            }
+            Entry::CallExtern(key, params) => {
+                let callee = self.externs[key].to_string();
+                return self.call(f, v, callee, params);
+            }
+            Entry::CallValue(fptr, params) => {
+                let callee = self.expect_value(f, fptr)?;
+                return self.call(f, v, callee, params);
+            }
+
+            Entry::Construct(values) => {
+                match &ty {
+                    MonoType::Monomorphised(key) => {
+                        self.construct(f, &v.to_string(), *key, values)?
+                    }
+                    MonoType::Array(_, _) => {
+                        for (i, value) in values.iter().enumerate() {
+                            if let Some(value) = self.value(f, value)? {
+                                writeln!(f.out, "        {v}.items[{i}] = {value};").unwrap();
+                            }
+                        }
+                    }
+                    _ => return Err(Error::Codegen(format!("cannot construct {cty}"))),
+                }
+                return Ok(());
+            }
+            Entry::Replicate(value, times) => {
+                let MonoType::Array(..) = &ty else {
+                    return Err(Error::Unsupported("replicating into records".into()));
+                };
+                let value = self.expect_value(f, value)?;
+                writeln!(
+                    f.out,
+                    "        for (uint64_t i = 0; i < {times}; i++) {v}.items[i] = {value};"
+                )
+                .unwrap();
+                return Ok(());
+            }
+            Entry::Variant(var, values) => {
+                let (_, _, variants) = lir.types[ty.as_key()].as_sum();
+                let tuple = variants[*var];
+                if self.boxed.contains(&(ty.as_key(), var.0)) {
+                    let alloc = self.alloc(&MonoType::Monomorphised(tuple))?;
+                    writeln!(f.out, "        {v}.payload.v{} = {alloc};", var.0).unwrap();
+                    let lvalue = format!("(*{v}.payload.v{})", var.0);
+                    self.construct(f, &lvalue, tuple, values)?;
+                } else if !self.is_zst(&MonoType::Monomorphised(tuple))? {
+                    let lvalue = format!("{v}.payload.v{}", var.0);
+                    self.construct(f, &lvalue, tuple, values)?;
+                }
+                // The tag is written last, so that it's never observed with a stale payload
+                writeln!(f.out, "        {v}.tag = {};", var.0).unwrap();
+                return Ok(());
+            }
+            Entry::RefStaticVal(val) => {
+                if self.is_zst(&lir.val_types[*val])? {
+                    "((void *)&lumina_zst)".to_string()
+                } else {
+                    format!("((void *)&{})", self.vals[val])
+                }
+            }
+            Entry::RefExternData(key) => format!("((void *)&{})", self.externs[key]),
+
+            Entry::Field { of, key, field } => {
+                let of = self.expect_value(f, of)?;
+                if self.boxed.contains(&(*key, field.0)) {
+                    format!("*{of}.f{}", field.0)
+                } else {
+                    format!("{of}.f{}", field.0)
+                }
+            }
+            Entry::CastFromSum { of } => {
+                let sum = self.type_of_value(f, of).as_key();
+                let of = self.expect_value(f, of)?;
+                let (_, _, variants) = lir.types[sum].as_sum();
+                // Variants with equal parameters share the same tuple, making any of them valid
+                let var = variants
+                    .iter()
+                    .find(|(_, tuple)| **tuple == ty.as_key())
+                    .map(|(var, _)| var)
+                    .ok_or_else(|| Error::Codegen(format!("{cty} is not a variant of {of}")))?;
+                if self.boxed.contains(&(sum, var.0)) {
+                    format!("*{of}.payload.v{}", var.0)
+                } else {
+                    format!("{of}.payload.v{}", var.0)
+                }
+            }
+            Entry::TagFromSum { of } => format!("{}.tag", self.expect_value(f, of)?),
+            Entry::Indice { of, indice } => {
+                let of = self.expect_value(f, of)?;
+                let indice = self.expect_value(f, indice)?;
+                format!("{of}.items[{indice}]")
+            }
+
+            Entry::BinOp(op, [lhs, rhs]) => {
+                let lhs = self.expect_value(f, lhs)?;
+                let rhs = self.expect_value(f, rhs)?;
+                let op = match op {
+                    BinOp::Add => "+",
+                    BinOp::Sub => "-",
+                    BinOp::Mul => "*",
+                    BinOp::Div => "/",
+                    BinOp::Rem => "%",
+                    BinOp::And => "&",
+                };
+                match &ty {
+                    MonoType::Int(intsize) if intsize.signed && matches!(op, "/" | "%") => {
+                        format!("{lhs} {op} {rhs}")
+                    }
+                    MonoType::Int(intsize) => {
+                        let w = wrapping(*intsize);
+                        format!("({cty})(({w}){lhs} {op} ({w}){rhs})")
+                    }
+                    _ => format!("(void *)((uintptr_t){lhs} {op} (uintptr_t){rhs})"),
+                }
+            }
+            Entry::IntCmpInclusive([lhs, rhs], ord, intsize) => {
+                let lhs = self.expect_value(f, lhs)?;
+                let rhs = self.expect_value(f, rhs)?;
+                let op = match ord {
+                    Ordering::Equal => "==",
+                    Ordering::Less => "<",
+                    Ordering::Greater => ">",
+                };
+                let t = int(*intsize)?;
+                format!("({t}){lhs} {op} ({t}){rhs}")
+            }
+            Entry::IntAbs(value) => {
+                let value = self.expect_value(f, value)?;
+                let MonoType::Int(intsize) = &ty else {
+                    return Err(Error::Codegen(format!("abs of {cty}")));
+                };
+                let w = wrapping(*intsize);
+                format!("({cty})({value} < 0 ? ({w})0 - ({w}){value} : ({w}){value})")
+            }
+            Entry::IntMin([lhs, rhs], intsize) | Entry::IntMax([lhs, rhs], intsize) => {
+                let lhs = self.expect_value(f, lhs)?;
+                let rhs = self.expect_value(f, rhs)?;
+                let op = match ssa.entry_of(v) {
+                    Entry::IntMin(..) => "<",
+                    _ => ">",
+                };
+                let t = int(*intsize)?;
+                format!("(({t}){lhs} {op} ({t}){rhs} ? {lhs} : {rhs})")
+            }
+            Entry::FloatAdd([lhs, rhs])
+            | Entry::FloatSub([lhs, rhs])
+            | Entry::FloatMul([lhs, rhs])
+            | Entry::FloatDiv([lhs, rhs]) => {
+                let lhs = self.expect_value(f, lhs)?;
+                let rhs = self.expect_value(f, rhs)?;
+                let op = match ssa.entry_of(v) {
+                    Entry::FloatAdd(_) => "+",
+                    Entry::FloatSub(_) => "-",
+                    Entry::FloatMul(_) => "*",
+                    _ => "/",
+                };
+                format!("({cty})({lhs} {op} {rhs})")
+            }
+            Entry::FloatNeg(value) => format!("-{}", self.expect_value(f, value)?),
+            Entry::FloatCmp([lhs, rhs], ord) => {
+                let lhs = self.expect_value(f, lhs)?;
+                let rhs = self.expect_value(f, rhs)?;
+                let op = match ord {
+                    Ordering::Equal => "==",
+                    Ordering::Less => "<",
+                    Ordering::Greater => ">",
+                };
+                format!("{lhs} {op} {rhs}")
+            }
+            Entry::SelectValue([cond, on_true, on_false]) => {
+                let cond = self.expect_value(f, cond)?;
+                let on_true = self.expect_value(f, on_true)?;
+                let on_false = self.expect_value(f, on_false)?;
+                format!("({cond} ? {on_true} : {on_false})")
+            }
+            Entry::BitNot(value) => format!("({cty})~{}", self.expect_value(f, value)?),
+            Entry::IntAddCarry([lhs, rhs, carry]) | Entry::IntSubBorrow([lhs, rhs, carry]) => {
+                let op = match ssa.entry_of(v) {
+                    Entry::IntAddCarry(_) => "add",
+                    _ => "sub",
+                };
+                let n = lir.types[ty.as_key()].as_record()[key::Field(0)].clone();
+                let t = self.expect_ty(&n)?;
+                let lhs = self.expect_value(f, lhs)?;
+                let rhs = self.expect_value(f, rhs)?;
+                let carry = self.expect_value(f, carry)?;
+                writeln!(
+                    f.out,
+                    "        {v}.f1 = __builtin_{op}_overflow({lhs}, {rhs}, &{v}.f0);"
+                )
+                .unwrap();
+                writeln!(
+                    f.out,
+                    "        {v}.f1 |= __builtin_{op}_overflow({v}.f0, ({t}){carry}, &{v}.f0);"
+                )
+                .unwrap();
+                return Ok(());
+            }
+
+            Entry::Reduce(value) => {
+                let value = self.expect_value(f, value)?;
+                match &ty {
+                    MonoType::Int(intsize) => {
+                        let unsigned = int(IntSize::new(false, intsize.bits()))?;
+                        format!("({cty})({unsigned}){value}")
+                    }
+                    _ => format!("({cty}){value}"),
+                }
+            }
+            Entry::ExtendSigned(value) | Entry::ExtendUnsigned(value) => {
+                let signed = matches!(ssa.entry_of(v), Entry::ExtendSigned(_));
+                let from = self.type_of_value(f, value);
+                let value = self.expect_value(f, value)?;
+                match from {
+                    MonoType::Int(intsize) => {
+                        let from = int(IntSize::new(signed, intsize.bits()))?;
+                        format!("({cty})({from}){value}")
+                    }
+                    _ => format!("({cty}){value}"),
+                }
+            }
+            Entry::IntToFloat(value, intsize) => {
+                let value = self.expect_value(f, value)?;
+                format!("({cty})({}){value}", int(*intsize)?)
+            }
+            Entry::FloatToInt(value, _) => format!("({cty}){}", self.expect_value(f, value)?),
+            Entry::FloatCast(value) => format!("({cty}){}", self.expect_value(f, value)?),
+            Entry::Transmute(value) => {
+                let from = self.type_of_value(f, value);
+                let from = self.expect_ty(&from)?;
+                let value = self.expect_value(f, value)?;
+                writeln!(
+                    f.out,
+                    "        {{ {from} t = {value}; lumina_memcpy(&{v}, &t, sizeof({v})); }}"
+                )
+                .unwrap();
+                return Ok(());
+            }
+
+            Entry::SizeOf(of) => format!("({cty}){}", self.size_of(of)?),
+            Entry::AlignOf(of) => match self.ty(of)? {
+                Some(of) => format!("({cty})LUMINA_ALIGNOF({of})"),
+                None => format!("({cty})1"),
+            },
+            Entry::Alloca => {
+                let MonoType::Pointer(inner) = &ty else {
+                    unreachable!()
+                };
+                if self.is_zst(inner)? {
+                    "(void *)&lumina_zst".into()
+                } else {
+                    format!("(void *)&s{}", v.0)
+                }
+            }
+            Entry::Alloc => {
+                let MonoType::Pointer(inner) = &ty else {
+                    unreachable!()
+                };
+                self.alloc(inner)?
+            }
+            Entry::Deref(ptr) => format!("*({cty} *){}", self.expect_value(f, ptr)?),
+            Entry::AtomicLoad(ptr, ordering) => {
+                let ptr = self.expect_value(f, ptr)?;
+                let order = memory_order(*ordering);
+                format!("__atomic_load_n(({cty} *){ptr}, {order})")
+            }
+            Entry::AtomicCas([ptr, expected, replacement], ordering) => {
+                let ptr = self.expect_value(f, ptr)?;
+                let expected = self.expect_value(f, expected)?;
+                let replacement = self.expect_value(f, replacement)?;
+                let order = memory_order(*ordering);
+                // The failure ordering may not contain a release
+                let failure = memory_order(match ordering {
+                    AtomicOrdering::Release => AtomicOrdering::Relaxed,
+                    AtomicOrdering::AcqRel => AtomicOrdering::Acquire,
+                    other => *other,
+                });
+                // On failure the current value is written back to `t`, so either way it ends up
+                // holding the previous value
+                writeln!(
+                    f.out,
+                    "        {{ {cty} t = {expected}; __atomic_compare_exchange_n(({cty} *){ptr}, &t, {replacement}, 0, {order}, {failure}); {v} = t; }}"
+                )
+                .unwrap();
+                return Ok(());
+            }
+            Entry::AtomicRmw(op, [ptr, value], ordering) => {
+                let ptr = self.expect_value(f, ptr)?;
+                let value = self.expect_value(f, value)?;
+                let order = memory_order(*ordering);
+                let builtin = match op {
+                    AtomicRmwOp::Add => "__atomic_fetch_add",
+                    AtomicRmwOp::Sub => "__atomic_fetch_sub",
+                    AtomicRmwOp::And => "__atomic_fetch_and",
+                    AtomicRmwOp::Or => "__atomic_fetch_or",
+                    AtomicRmwOp::Xor => "__atomic_fetch_xor",
+                    AtomicRmwOp::Xchg => "__atomic_exchange_n",
+                };
+                format!("{builtin}(({cty} *){ptr}, {value}, {order})")
+            }
+
+            Entry::JmpFunc(..)
+            | Entry::JmpBlock(_)
+            | Entry::Return(_)
+            | Entry::Select { .. }
+            | Entry::JmpTable(..)
+            | Entry::Trap(_)
+            | Entry::Breakpoint
+            | Entry::Dealloc { .. }
+            | Entry::WritePtr { .. }
+            | Entry::AtomicStore { .. }
+            | Entry::MemCpy { .. }
+            | Entry::MemSet { .. } => return self.effect(f, v),
+        };
+
+        writeln!(f.out, "        {v} = {value};").unwrap();
+        Ok(())
+    }
+
+    /// Lower entries which don't produce a value
+    fn effect(&mut self, f: &mut Func, v: V) -> Result<(), Error> {
+        let func = f.func;
+        let ssa = &func.ssa;
+
+        match ssa.entry_of(v) {
+            Entry::CallStatic(mfunc, params) => {
+                let callee = self.functions[*mfunc].to_string();
+                self.call(f, v, callee, params)?;
+            }
+            Entry::CallExtern(key, params) => {
+                let callee = self.externs[key].to_string();
+                self.call(f, v, callee, params)?;
+            }
+            Entry::CallValue(fptr, params) => {
+                let callee = self.expect_value(f, fptr)?;
+                self.call(f, v, callee, params)?;
+            }
+
+            Entry::JmpFunc(mfunc, params) => {
+                let callee = self.functions[*mfunc];
+                let args = self.args(f, params)?;
+                if self.is_zst(&func.returns)? {
+                    writeln!(f.out, "        {callee}({args});\n        return;").unwrap();
+                } else {
+                    writeln!(f.out, "        return {callee}({args});").unwrap();
+                }
+            }
+            Entry::JmpBlock(jump) => self.jump(f, jump, "        ")?,
+            Entry::Return(value) => match self.value(f, value)? {
+                Some(value) if !self.is_zst(&func.returns)? => {
+                    writeln!(f.out, "        return {value};").unwrap()
+                }
+                _ => writeln!(f.out, "        return;").unwrap(),
+            },
+            Entry::Select { value, on_true, on_false } => {
+                let value = self.expect_value(f, value)?;
+                writeln!(f.out, "        if ({value}) {{").unwrap();
+                self.jump(f, on_true, "            ")?;
+                writeln!(f.out, "        }} else {{").unwrap();
+                self.jump(f, on_false, "            ")?;
+                writeln!(f.out, "        }}").unwrap();
+            }
+            Entry::JmpTable(value, blocks) => {
+                let value = self.expect_value(f, value)?;
+                writeln!(f.out, "        switch ({value}) {{").unwrap();
+                for (i, block) in blocks.iter().enumerate() {
+                    writeln!(f.out, "        case {i}: block = {}; continue;", block.0).unwrap();
+                }
+                writeln!(f.out, "        default: LUMINA_TRAP();\n        }}").unwrap();
+            }
+            Entry::Trap(_) => writeln!(f.out, "        LUMINA_TRAP();").unwrap(),
+
+            Entry::Dealloc { ptr } => {
+                let MonoType::Pointer(inner) = self.type_of_value(f, ptr) else {
+                    unreachable!()
+                };
+                let size = self.size_of(&inner)?;
+                let ptr = self.expect_value(f, ptr)?;
+                let dealloc = self.functions[self.lir.dealloc];
+                let size_t = self.size_t()?;
+                writeln!(f.out, "        {dealloc}({ptr}, ({size_t}){size});").unwrap();
+            }
+            Entry::WritePtr { ptr, value } => {
+                let ty = self.type_of_value(f, value);
+                if let Some(value) = self.value(f, value)? {
+                    let ty = self.expect_ty(&ty)?;
+                    let ptr = self.expect_value(f, ptr)?;
+                    writeln!(f.out, "        *({ty} *){ptr} = {value};").unwrap();
+                }
+            }
+            Entry::AtomicStore { ptr, value, ordering } => {
+                let ty = self.type_of_value(f, value);
+                let ty = self.expect_ty(&ty)?;
+                let ptr = self.expect_value(f, ptr)?;
+                let value = self.expect_value(f, value)?;
+                let order = memory_order(*ordering);
+                writeln!(
+                    f.out,
+                    "        __atomic_store_n(({ty} *){ptr}, {value}, {order});"
+                )
+                .unwrap();
+            }
+            Entry::MemCpy { dst, src, count } => {
+                let dst = self.expect_value(f, dst)?;
+                let src = self.expect_value(f, src)?;
+                let count = self.expect_value(f, count)?;
+                writeln!(
+                    f.out,
+                    "        lumina_memcpy({dst}, {src}, (size_t){count});"
+                )
+                .unwrap();
+            }
+            Entry::MemSet { dst, byte, count } => {
+                let dst = self.expect_value(f, dst)?;
+                let byte = self.expect_value(f, byte)?;
+                let count = self.expect_value(f, count)?;
+                writeln!(
+                    f.out,
+                    "        lumina_memset({dst}, {byte}, (size_t){count});"
+                )
+                .unwrap();
+            }
+
+            // `--trap-on-panic` is only supported by the Cranelift backend
+            Entry::Breakpoint => {}
+
+            // Remaining entries don't have side effects
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Declare the `main` function called by libc, running the val initialisers before calling
+    /// the system-specific initialiser and the Lumina main function
+    fn entrypoint(&mut self, out: &mut String) -> Result<(), Error> {
+        let lir = self.lir;
+        writeln!(out, "int main(int argc, char **argv) {{").unwrap();
+
+        for val in lir.val_types.iter() {
+            let init = self.functions[lir.val_initializers[&val]];
+            if self.is_zst(&lir.val_types[val])? {
+                writeln!(out, "    {init}();").unwrap();
+            } else {
+                writeln!(out, "    {} = {init}();", self.vals[&val]).unwrap();
+            }
+        }
+
+        let sys_init = &lir.functions[lir.sys_init];
+        let mut args = vec![];
+        for (ty, arg) in sys_init.ssa.func_param_types().zip(["argc", "argv"]) {
+            let ty = self.expect_ty(ty)?;
+            args.push(format!("({ty}){arg}"));
+        }
+        writeln!(
+            out,
+            "    {}({});",
+            self.functions[self.lir.sys_init],
+            args.join(", ")
+        )
+        .unwrap();
+
+        writeln!(out, "    {}();", self.functions[self.lir.main]).unwrap();
+        writeln!(out, "    return 0;\n}}").unwrap();
+
+        Ok(())
+    }
+}
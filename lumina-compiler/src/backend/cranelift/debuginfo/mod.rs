@@ -1,7 +1,7 @@
 use super::Context;
 use crate::debuginfo::emit::address_for_func;
 use crate::debuginfo::{BinDebugInfo, Item};
-use crate::lir::MonoFunc;
+use crate::lir::{self, MonoFunc};
 use cranelift_codegen as codegen;
 use cranelift_codegen::binemit::CodeOffset;
 use cranelift_codegen::MachSrcLoc;
@@ -23,7 +23,7 @@ impl<'a> Context<'a> {
         self.debuginfo.add_item(item.clone(), name)
     }
 
-    pub fn def_function(&mut self, mfunc: MonoFunc) -> FunctionDebugContext {
+    pub fn def_function(&mut self, mfunc: MonoFunc, ssa: &lir::SSA) -> FunctionDebugContext {
         let item = &self.lir.functions[mfunc].kind;
         let module = item.module();
         let root = self.debuginfo.find_root(module);
@@ -32,7 +32,7 @@ impl<'a> Context<'a> {
         // names instead of the module annotation. If we do we'll need to use a more sensible way
         // to get the function name.
         let name = {
-            let symbol = &self.lir.functions[mfunc].symbol;
+            let symbol = self.lir.functions[mfunc].symbol.as_str();
             let end = symbol.len()
                 - symbol
                     .bytes()
@@ -52,8 +52,14 @@ impl<'a> Context<'a> {
 
         let scope = self.get_or_make_namespace(name, item);
 
-        let dwarf = &mut self.debuginfo.units[root];
         let file_id = self.debuginfo.modules[module].file;
+        let function_source_loc = ssa
+            .first_location()
+            .and_then(|location| self.debuginfo.source_loc(root, location))
+            .unwrap_or((file_id, 1, 1));
+        let (file_id, line, _) = function_source_loc;
+
+        let dwarf = &mut self.debuginfo.units[root];
 
         let entry_id = dwarf.unit.add(scope, gimli::DW_TAG_subprogram);
         let entry = dwarf.unit.get_mut(entry_id);
@@ -78,14 +84,9 @@ impl<'a> Context<'a> {
             gimli::DW_AT_decl_file,
             AttributeValue::FileIndex(Some(file_id)),
         );
-        const TODO_LINE_ROW: u64 = 1;
-        entry.set(gimli::DW_AT_decl_line, AttributeValue::Udata(TODO_LINE_ROW));
+        entry.set(gimli::DW_AT_decl_line, AttributeValue::Udata(line));
 
-        FunctionDebugContext {
-            entry_id,
-            module,
-            function_source_loc: (file_id, TODO_LINE_ROW, 1),
-        }
+        FunctionDebugContext { entry_id, module, function_source_loc }
     }
 }
 
@@ -101,11 +102,31 @@ impl FunctionDebugContext {
         debug_context: &mut BinDebugInfo,
         func_id: FuncId,
         ctx: &codegen::Context,
+        ssa: &lir::SSA,
     ) {
         let root = debug_context.find_root(self.module);
+
+        // Values inlined from other project roots fall back to the location of the function, as
+        // their files aren't part of this line program
+        let rows = ctx
+            .compiled_code()
+            .unwrap()
+            .buffer
+            .get_srclocs_sorted()
+            .iter()
+            .filter(|srcloc| !srcloc.loc.is_default())
+            .map(|&MachSrcLoc { start, end, loc }| {
+                let location = ssa.location_of(lir::V(loc.bits()));
+                let source_loc = location
+                    .and_then(|location| debug_context.source_loc(root, location))
+                    .unwrap_or(self.function_source_loc);
+                (start, end, source_loc)
+            })
+            .collect::<Vec<_>>();
+
         let dwarf = &mut debug_context.units[root];
 
-        let end = self.create_debug_lines(dwarf, func_id, ctx);
+        let end = self.create_debug_lines(dwarf, func_id, ctx, &rows);
 
         debug_context
             .unit_range_list
@@ -127,6 +148,7 @@ impl FunctionDebugContext {
         unit: &mut DwarfUnit,
         func_id: FuncId,
         context: &codegen::Context,
+        rows: &[(CodeOffset, CodeOffset, (FileId, u64, u64))],
     ) -> CodeOffset {
         let create_row_for_span = |unit: &mut DwarfUnit, source_loc: (FileId, u64, u64)| {
             let (file_id, line, col) = source_loc;
@@ -144,9 +166,9 @@ impl FunctionDebugContext {
         let mut func_end = 0;
 
         let mcr = context.compiled_code().unwrap();
-        for &MachSrcLoc { start, end, .. } in mcr.buffer.get_srclocs_sorted() {
+        for &(start, end, source_loc) in rows {
             unit.unit.line_program.row().address_offset = u64::from(start);
-            create_row_for_span(unit, self.function_source_loc);
+            create_row_for_span(unit, source_loc);
             func_end = end;
         }
 
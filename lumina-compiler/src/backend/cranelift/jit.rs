@@ -0,0 +1,57 @@
+//! Compilation into the running process for `lumina run --jit`
+//!
+//! The functions and data are defined in a `JITModule` instead of an object file, which resolves
+//! the symbols of extern functions by looking them up in the process itself. The compiler is linked
+//! against the host's libc, so a program for the native target finds everything the standard
+//! library needs without being linked. Symbols of other libraries can't be resolved.
+//!
+//! Neither the unwind tables nor the debug info are registered, so a debugger attached to the
+//! compiler sees the program's functions as anonymous code.
+
+use cranelift::codegen::isa;
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::FuncId;
+use std::ffi::{c_char, CString};
+use std::sync::Arc;
+
+pub(super) fn jit_module(isa: &Arc<dyn isa::TargetIsa>) -> JITModule {
+    let builder = JITBuilder::with_isa(isa.clone(), cranelift_module::default_libcall_names());
+    JITModule::new(builder)
+}
+
+/// A program whose functions and data have been loaded into this process
+pub struct Program {
+    module: JITModule,
+    main: FuncId,
+}
+
+impl Program {
+    pub(super) fn new(mut module: JITModule, main: FuncId) -> Self {
+        if let Err(err) = module.finalize_definitions() {
+            panic!("could not relocate the JIT compiled program:\n {err}");
+        }
+        Program { module, main }
+    }
+
+    /// Call the entrypoint the same way libc would, returning the exit code
+    ///
+    /// The program shares the process with the caller, so it exiting or crashing also ends the
+    /// caller.
+    pub fn run(&self, name: &str, args: &[String]) -> i32 {
+        let argv = std::iter::once(name)
+            .chain(args.iter().map(String::as_str))
+            .map(|arg| CString::new(arg).expect("nul byte in program argument"))
+            .collect::<Vec<_>>();
+
+        let mut pointers = argv.iter().map(|arg| arg.as_ptr()).collect::<Vec<_>>();
+        pointers.push(std::ptr::null());
+
+        let main = self.module.get_finalized_function(self.main);
+
+        // The entrypoint has the signature of C's `main`, see `Context::declare_entrypoint`
+        let main: extern "C" fn(i32, *const *const c_char) -> i32 =
+            unsafe { std::mem::transmute(main) };
+
+        main(argv.len() as i32, pointers.as_ptr())
+    }
+}
@@ -2,18 +2,17 @@ use crate::lir;
 use crate::lir::{MonoType, MonoTypeKey};
 use crate::prelude::*;
 use ast::attr::Repr;
-use cranelift::codegen::ir::ArgumentPurpose;
+use cranelift::codegen::ir::{ArgumentExtension, ArgumentPurpose};
 use cranelift::prelude::*;
 use cranelift_codegen::isa::CallConv;
 use lumina_collections::{map_key_impl, KeysIter};
-use lumina_typesystem::ConstValue;
-use std::cell::RefCell;
+use lumina_typesystem::{ConstValue, FloatSize};
+use std::collections::HashSet;
 
 pub struct Structs<'a> {
     structs: Map<MonoTypeKey, Struct>,
+    unaligned: HashSet<MonoTypeKey>,
     pub records: &'a lir::Types,
-
-    autobox_stack: RefCell<Vec<M<key::TypeKind>>>,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
@@ -38,7 +37,10 @@ pub enum StructField {
     Flat(MonoType),
     AutoBoxed(MonoType),
 
+    // Payloads of recursive sums are put behind a pointer
     SumPayloadPointer { sum: MonoTypeKey },
+    // Large enough and aligned for the payload of any variant
+    SumPayloadEmbedded { sum: MonoTypeKey },
     SumPayloadInline(Type),
 }
 
@@ -101,6 +103,8 @@ pub enum Scalar {
 pub enum SpecialPointer {
     StackSumPayload { sum: MonoTypeKey },
     HeapSumPayload { sum: MonoTypeKey },
+    // Only found in S_Stable layouts, where the payload is stored in the memory of the sum itself
+    EmbeddedSumPayload { sum: MonoTypeKey },
 
     // Struct/Arrays implicitly passed as a pointer
     StackStruct(MonoTypeKey),
@@ -139,9 +143,9 @@ impl Layout<Type> {
             ),
             Layout::SpecialPointer(kind, ptr) => {
                 let kind = match kind {
-                    SpecialPointer::HeapSumPayload { .. } | SpecialPointer::HeapStruct(_) => {
-                        kind.clone()
-                    }
+                    SpecialPointer::HeapSumPayload { .. }
+                    | SpecialPointer::EmbeddedSumPayload { .. }
+                    | SpecialPointer::HeapStruct(_) => kind.clone(),
 
                     &SpecialPointer::StackSumPayload { sum } => {
                         SpecialPointer::HeapSumPayload { sum }
@@ -197,7 +201,9 @@ impl<T: Copy> Layout<T> {
             Layout::ArrayFlat(_, elems) => elems.iter().any(Layout::has_stack_pointers),
             Layout::StructFlat(_, fields) => fields.values().any(Layout::has_stack_pointers),
             Layout::SpecialPointer(kind, _) => match kind {
-                SpecialPointer::HeapSumPayload { .. } | SpecialPointer::HeapStruct(_) => false,
+                SpecialPointer::HeapSumPayload { .. }
+                | SpecialPointer::EmbeddedSumPayload { .. }
+                | SpecialPointer::HeapStruct(_) => false,
                 _ => true,
             },
             Layout::OutPointer(_, _) => false,
@@ -265,14 +271,16 @@ impl<'a> Structs<'a> {
                 .keys()
                 .map(|_| Struct { align: u32::MAX, field_map: Map::new(), fields: Map::new() })
                 .collect(),
+            unaligned: HashSet::new(),
             records,
-            autobox_stack: RefCell::new(vec![]),
         };
 
         for mk in records.keys() {
             let _ = this.get_or_make(mk);
         }
 
+        this.unaligned = this.find_unaligned();
+
         this
     }
 
@@ -283,26 +291,18 @@ impl<'a> Structs<'a> {
                     return false;
                 };
 
-                let kind = original.map(key::TypeKind::Record);
-                self.autobox_stack.borrow_mut().push(kind);
-
-                let autobox = self.autobox_check(ty);
-
-                let kind = original.map(key::Record::into);
-                assert_eq!(self.autobox_stack.borrow_mut().pop(), Some(kind));
-
-                autobox
+                let mut stack = vec![original.map(key::TypeKind::Record)];
+                self.autobox_check(&mut stack, ty)
             }
             _ => false,
         }
     }
 
-    fn autobox_check(&self, ty: &MonoType) -> bool {
+    fn autobox_check(&self, stack: &mut Vec<M<key::TypeKind>>, ty: &MonoType) -> bool {
         match ty {
             MonoType::Monomorphised(key) => match &self.records[*key] {
                 lir::MonoTypeData::Record { key: rkey, fields, .. } => {
                     if let Some(original) = rkey {
-                        let mut stack = self.autobox_stack.borrow_mut();
                         let kind = original.map(key::TypeKind::Record);
 
                         // early-return if one of the inner structs are recursive so we don't halt
@@ -316,11 +316,11 @@ impl<'a> Structs<'a> {
                         stack.push(kind);
                     }
 
-                    let autobox = fields.values().any(|fty| self.autobox_check(fty));
+                    let autobox = fields.values().any(|fty| self.autobox_check(stack, fty));
 
                     if let Some(original) = rkey {
                         let kind = original.map(key::Record::into);
-                        assert_eq!(self.autobox_stack.borrow_mut().pop(), Some(kind));
+                        assert_eq!(stack.pop(), Some(kind));
                     }
 
                     autobox
@@ -354,7 +354,7 @@ impl<'a> Structs<'a> {
         }
 
         match self.c_class_of(inner) {
-            SystemVClass::Integer => PassBy::Value,
+            SystemVClass::Integer | SystemVClass::Sse => PassBy::Value,
             SystemVClass::Memory => PassBy::Pointer,
         }
     }
@@ -375,7 +375,7 @@ impl<'a> Structs<'a> {
                     *repr,
                     fields.as_slice(),
                 ) {
-                    SystemVClass::Integer => PassBy::Value,
+                    SystemVClass::Integer | SystemVClass::Sse => PassBy::Value,
                     SystemVClass::Memory => PassBy::Pointer,
                 }
             }
@@ -430,7 +430,7 @@ impl<'a> Structs<'a> {
         match ty {
             MonoType::Int(intsize) => (1, intsize.bytes() as u32),
             MonoType::Pointer(_) | MonoType::FnPointer(_, _) => (1, ptr()),
-            MonoType::Float => (1, 8),
+            MonoType::Float(size) => (1, size.bytes() as u32),
             MonoType::Const(const_) => match const_ {
                 lumina_typesystem::ConstValue::Usize(_) => (1, self.records.pointer_bits / 8),
                 lumina_typesystem::ConstValue::Bool(_) => (1, 1),
@@ -448,12 +448,57 @@ impl<'a> Structs<'a> {
     // TODO: we're calling this operation *a lot* while it's fairly expensive we should probably
     // memoizise it.
     pub fn sum_payload_alloca_size(&self, sum: MonoTypeKey) -> u32 {
+        self.sum_payload_size_and_align(sum).0
+    }
+
+    pub fn sum_payload_size_and_align(&self, sum: MonoTypeKey) -> (u32, u32) {
         let (_, _, variants) = self.records[sum].as_sum();
         variants
             .values()
-            .map(|&param_tuple| self.size_of(&param_tuple.into()))
-            .max()
-            .unwrap()
+            .map(|&param_tuple| self.size_and_align_of(&param_tuple.into()))
+            .fold((0, 1), |(size, align), (vsize, valign)| {
+                (size.max(vsize), align.max(valign))
+            })
+    }
+
+    /// Whether the payload of a sum is stored in its own memory rather than behind a pointer
+    pub fn has_embedded_payload(&self, sum: MonoTypeKey) -> bool {
+        self.get(sum)
+            .fields
+            .values()
+            .any(|field| matches!(field, StructField::SumPayloadEmbedded { .. }))
+    }
+
+    // Whether a value of `ty` may contain `target` without going through a pointer
+    fn contains_directly(
+        &self,
+        target: MonoTypeKey,
+        ty: &MonoType,
+        visited: &mut Vec<MonoTypeKey>,
+    ) -> bool {
+        match ty {
+            MonoType::Array(_, inner) => self.contains_directly(target, inner, visited),
+            &MonoType::Monomorphised(mk) if mk == target => true,
+            &MonoType::Monomorphised(mk) if visited.contains(&mk) => false,
+            &MonoType::Monomorphised(mk) => {
+                visited.push(mk);
+
+                match &self.records[mk] {
+                    lir::MonoTypeData::Record { fields, .. } => fields.values().any(|fty| {
+                        !self.should_autobox_field(mk, fty)
+                            && self.contains_directly(target, fty, visited)
+                    }),
+                    lir::MonoTypeData::Sum { variants, .. } => {
+                        variants.values().any(|&param_tuple| {
+                            self.contains_directly(target, &param_tuple.into(), visited)
+                        })
+                    }
+                    lir::MonoTypeData::DynTraitObject { .. } => false,
+                    lir::MonoTypeData::Placeholder => unreachable!(),
+                }
+            }
+            _ => false,
+        }
     }
 
     fn make(&mut self, key: MonoTypeKey) {
@@ -497,7 +542,18 @@ impl<'a> Structs<'a> {
                     let fields = [StructField::SumPayloadInline(size), tagfield].into();
                     Struct::new(ptr.bytes(), [1, 0].map(Field).into(), fields)
                 } else {
-                    let fields = [StructField::SumPayloadPointer { sum: key }, tagfield].into();
+                    // Recursive sums would be infinitely large if their payload was embedded
+                    let recursive = variants.values().any(|&param_tuple| {
+                        self.contains_directly(key, &param_tuple.into(), &mut vec![])
+                    });
+
+                    let payload = if recursive {
+                        StructField::SumPayloadPointer { sum: key }
+                    } else {
+                        StructField::SumPayloadEmbedded { sum: key }
+                    };
+
+                    let fields = [payload, tagfield].into();
                     Struct::new(ptr.bytes(), [1, 0].map(Field).into(), fields)
                 };
             }
@@ -532,15 +588,13 @@ impl<'a> Structs<'a> {
 
                         assert!(!self.structs[key].fields.is_empty(), "{key}");
                     }
-                    ast::attr::Repr::C => {
+                    ast::attr::Repr::C | ast::attr::Repr::Packed | ast::attr::Repr::Align(_) => {
                         let _align = self.calculate_align_of_struct(key);
                         self.structs[key].field_map = fields.keys().map(|k| Field(k.0)).collect();
 
                         let fieldorder = fields.keys();
                         self.lower_struct_fields(key, fieldorder);
                     }
-                    ast::attr::Repr::Packed => todo!(),
-                    ast::attr::Repr::Align(_) => todo!(),
                     ast::attr::Repr::Enum(_) => unreachable!(),
                 }
             }
@@ -589,12 +643,12 @@ impl<'a> Structs<'a> {
         let mut offset = 0;
         for i in KeysIter::up_to(field) {
             let field = &struct_.fields[i];
-            let (fsize, pad) = self.field_size_and_pad(offset, field);
+            let (fsize, pad) = self.field_size_and_pad_in(key, offset, field);
             offset += fsize + pad;
         }
 
         let (_, align) = self.size_and_align_of_field(&struct_.fields[field]);
-        if align != 0 {
+        if align != 0 && self.repr_of(key) != Repr::Packed {
             let end_padding = (align - offset % align) % align;
             offset += end_padding;
         }
@@ -634,14 +688,40 @@ impl<'a> Structs<'a> {
             }
         }
 
+        let align = match self.repr_of(for_) {
+            Repr::Packed => 1,
+            Repr::Align(bytes) => align.max(bytes as u32),
+            _ => align,
+        };
+
         trace!("{for_}: alignment calculated to {align}");
 
-        assert!(align <= self.records.pointer_bits / 8);
         self.structs[for_].align = align;
 
         align
     }
 
+    fn repr_of(&self, key: MonoTypeKey) -> Repr {
+        match &self.records[key] {
+            lir::MonoTypeData::Record { repr, .. } => *repr,
+            _ => Repr::Lumina,
+        }
+    }
+
+    // Fields of packed records are placed directly after each other without any padding
+    fn field_size_and_pad_in(
+        &self,
+        key: MonoTypeKey,
+        offset: u32,
+        field: &StructField,
+    ) -> (u32, u32) {
+        if self.repr_of(key) == Repr::Packed {
+            (0, self.size_and_align_of_field(field).0)
+        } else {
+            self.field_size_and_pad(offset, field)
+        }
+    }
+
     pub fn field_size_and_pad(&self, offset: u32, field: &StructField) -> (u32, u32) {
         let (size, align) = self.size_and_align_of_field(field);
         if align == 0 {
@@ -662,7 +742,7 @@ impl<'a> Structs<'a> {
         let mut offset = 0;
 
         for field in self.structs[mk].fields.values() {
-            let (fsize, pad) = self.field_size_and_pad(offset, field);
+            let (fsize, pad) = self.field_size_and_pad_in(mk, offset, field);
             offset += fsize + pad;
         }
 
@@ -675,12 +755,8 @@ impl<'a> Structs<'a> {
     pub fn size_and_align_of_ptr_dst(&self, special: &SpecialPointer) -> (u32, u32) {
         match special {
             &SpecialPointer::HeapSumPayload { sum, .. }
-            | &SpecialPointer::StackSumPayload { sum, .. } => {
-                let size = self.sum_payload_alloca_size(sum);
-                // TODO: this align seems weird. Not sure whether it's valid
-                let align = (self.records.pointer_bits / 8).min(size);
-                (size, align)
-            }
+            | &SpecialPointer::StackSumPayload { sum, .. }
+            | &SpecialPointer::EmbeddedSumPayload { sum } => self.sum_payload_size_and_align(sum),
             &SpecialPointer::HeapStruct(mk) | &SpecialPointer::StackStruct(mk) => {
                 self.size_and_align_of_mk(mk)
             }
@@ -703,7 +779,8 @@ impl<'a> Structs<'a> {
                 (size, align)
             }
             MonoType::Int(intsize) => (intsize.bytes() as u32, intsize.bytes() as u32),
-            MonoType::Float | MonoType::FnPointer(_, _) | MonoType::Pointer(_) => {
+            MonoType::Float(size) => (size.bytes() as u32, size.bytes() as u32),
+            MonoType::FnPointer(_, _) | MonoType::Pointer(_) => {
                 let size = self.records.pointer_bits / 8;
                 (size, size)
             }
@@ -740,11 +817,62 @@ impl<'a> Structs<'a> {
 
     fn struct_has_unaligned_fields(&self, key: Option<MonoTypeKey>) -> bool {
         match key {
-            Some(key) => {
+            Some(key) if self.repr_of(key) == Repr::Packed => {
                 let struct_ = self.get(key);
-                struct_.align == 0 && !struct_.fields.is_empty()
+                struct_.fields.iter().any(|(field, f)| {
+                    let (_, align) = self.size_and_align_of_field(f);
+                    align != 0 && self.offset_of(key, field).0 % align != 0
+                })
             }
-            None => false,
+            _ => false,
+        }
+    }
+
+    /// Flags for loads and stores into the memory of `key`
+    ///
+    /// Packed records, and anything stored inside of them, may not be aligned.
+    pub fn mem_flags(&self, key: MonoTypeKey) -> MemFlags {
+        if self.unaligned.contains(&key) {
+            MemFlags::new().with_notrap()
+        } else {
+            MemFlags::trusted()
+        }
+    }
+
+    // Types stored by value inside of packed records
+    fn find_unaligned(&self) -> HashSet<MonoTypeKey> {
+        let mut unaligned = HashSet::new();
+        let mut queue = self
+            .records
+            .keys()
+            .filter(|&key| self.repr_of(key) == Repr::Packed)
+            .collect::<Vec<_>>();
+
+        while let Some(key) = queue.pop() {
+            if !unaligned.insert(key) {
+                continue;
+            }
+
+            for field in self.structs[key].fields.values() {
+                match field {
+                    StructField::Flat(ty) => queue.extend(self.key_stored_in(ty)),
+                    &StructField::SumPayloadEmbedded { sum } => {
+                        let (_, _, variants) = self.records[sum].as_sum();
+                        queue.extend(variants.values().copied());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        unaligned
+    }
+
+    fn key_stored_in(&self, ty: &MonoType) -> Option<MonoTypeKey> {
+        match ty {
+            MonoType::Monomorphised(key) => Some(*key),
+            MonoType::Array(_, inner) => self.key_stored_in(inner),
+            _ => None,
         }
     }
 
@@ -752,10 +880,56 @@ impl<'a> Structs<'a> {
         false
     }
 
+    /// Convert a value evaluated at compile time from the interpreter's layout to ours
+    pub fn constant_from_interpreter(
+        &self,
+        layouts: &lir::interpret::Layouts,
+        ty: &MonoType,
+        bytes: &[u8],
+    ) -> Vec<u8> {
+        let mut out = vec![0; self.size_of(ty) as usize];
+        self.write_constant(layouts, ty, bytes, &mut out);
+        out
+    }
+
+    fn write_constant(
+        &self,
+        layouts: &lir::interpret::Layouts,
+        ty: &MonoType,
+        src: &[u8],
+        dst: &mut [u8],
+    ) {
+        match ty {
+            MonoType::Array(0, _) => {}
+            MonoType::Array(len, inner) => {
+                let (size, _, _) = self.size_and_align_of_array(inner, *len);
+                let stride = (size as u64 / len) as usize;
+                let src_stride = layouts.stride_of(inner) as usize;
+                for i in 0..*len as usize {
+                    let (src, dst) = (&src[i * src_stride..], &mut dst[i * stride..]);
+                    self.write_constant(layouts, inner, src, dst);
+                }
+            }
+            MonoType::Monomorphised(key) => {
+                let src_offsets = &layouts.record(*key).offsets;
+                for (field, fty) in self.records[*key].as_record() {
+                    let offset = self.offset_of(*key, self.get_real_field(*key, field)).0;
+                    let src = &src[src_offsets[field.0 as usize] as usize..];
+                    self.write_constant(layouts, fty, src, &mut dst[offset as usize..]);
+                }
+            }
+            _ => {
+                let size = self.size_of(ty) as usize;
+                dst[..size].copy_from_slice(&src[..size]);
+            }
+        }
+    }
+
     pub fn size_and_align_of_field(&self, f: &StructField) -> (u32, u32) {
         match f {
             StructField::Flat(ty) => self.size_and_align_of(ty),
             StructField::SumPayloadInline(clty) => (clty.bytes(), clty.bytes()),
+            &StructField::SumPayloadEmbedded { sum } => self.sum_payload_size_and_align(sum),
             StructField::AutoBoxed(_) | StructField::SumPayloadPointer { .. } => {
                 let ptr = self.records.pointer_bits / 8;
                 (ptr, ptr)
@@ -788,24 +962,26 @@ impl<'a> Structs<'a> {
                     // let field_class = self.c_class_of_aggregate_of_layout(layout);
                     let field_class = match field {
                         StructField::Flat(ty) => self.c_class_of(&ty),
+                        // Embedded payloads are read through pointers to the memory of the sum
+                        StructField::SumPayloadEmbedded { .. } => SystemVClass::Memory,
                         _ => SystemVClass::Integer,
                     };
 
-                    match class {
-                        None => Some(field_class),
-                        Some(SystemVClass::Memory) => Some(SystemVClass::Memory),
-                        Some(SystemVClass::Integer) => match field_class {
-                            SystemVClass::Memory => Some(SystemVClass::Memory),
-                            SystemVClass::Integer => Some(SystemVClass::Integer),
-                        },
+                    match (class, field_class) {
+                        (None, field_class) => Some(field_class),
+                        (Some(SystemVClass::Memory), _) | (_, SystemVClass::Memory) => {
+                            Some(SystemVClass::Memory)
+                        }
+                        (Some(SystemVClass::Sse), SystemVClass::Sse) => Some(SystemVClass::Sse),
+                        _ => Some(SystemVClass::Integer),
                     }
                 })
                 .unwrap_or(SystemVClass::Integer);
 
             let max_struct_regs = if repr == Repr::Lumina { 4 } else { 2 };
 
-            // 5c. if the size exceeds two eightbytes and there are no floats or
-            // vectors (which we don't support C repr of) then also use the stack.
+            // 5c. if the size exceeds two eightbytes and there are no vectors (which we don't
+            // support C repr of) then also use the stack.
             if struct_size > (eightbyte * max_struct_regs) {
                 SystemVClass::Memory
             } else {
@@ -846,6 +1022,7 @@ impl<'a> Structs<'a> {
                 self.c_class_of_aggregate_layout_struct(Some(*mk), size, repr, fields.as_slice())
             }
             MonoType::Array(len, inner) => self.c_class_of_array(*len, inner),
+            MonoType::Float(_) => SystemVClass::Sse,
             _ => panic!("unsupported type in repr C struct: {ty:?}"),
         }
     }
@@ -854,7 +1031,9 @@ impl<'a> Structs<'a> {
 enum SystemVClass {
     Integer,
     Memory,
-    // Sse,
+    // Floats are only ever given an entire eightbyte of their own, so they're passed by value
+    // in the same way as integers but in the floating point registers.
+    Sse,
     // Sseup,
     // NoClass,
 }
@@ -869,19 +1048,72 @@ impl<'a> Structs<'a> {
     where
         P: IntoIterator<Item = &'t MonoType>,
     {
-        let params = params
-            .into_iter()
-            .map(|ty| self.type_to_layout(ty, Stability::F))
-            .collect::<Map<_, _>>();
+        let params = params.into_iter().collect::<Vec<_>>();
 
-        let ret = self.type_to_layout(&ret, Stability::FRet);
+        let flayout = FuncLayout {
+            conv,
+            params: params
+                .iter()
+                .map(|ty| self.type_to_layout(ty, Stability::F))
+                .collect(),
+            ret: self.type_to_layout(&ret, Stability::FRet),
+        };
+        let mut sig = self.signature(&flayout);
 
-        let flayout = FuncLayout { conv, params, ret };
-        let sig = self.signature(&flayout);
+        if conv == CallConv::AppleAarch64 {
+            Self::extend_narrow_ints(&mut sig, &flayout, &params, ret);
+        }
 
         (flayout, sig)
     }
 
+    // Apple's variant of AAPCS64 requires integers narrower than 32 bits to be sign or zero
+    // extended by the caller for parameters and by the callee for return values, while the
+    // standard AAPCS64 leaves the upper bits unspecified.
+    //
+    // ref: Writing ARM64 code for Apple platforms
+    fn extend_narrow_ints(
+        sig: &mut Signature,
+        flayout: &FuncLayout,
+        params: &[&MonoType],
+        ret: &MonoType,
+    ) {
+        let extension = |ty: &MonoType| match ty {
+            MonoType::Int(size) if size.bits() < 32 && size.signed => Some(ArgumentExtension::Sext),
+            MonoType::Int(size) if size.bits() < 32 => Some(ArgumentExtension::Uext),
+            MonoType::Const(ConstValue::Bool(_)) => Some(ArgumentExtension::Uext),
+            _ => None,
+        };
+
+        // Out pointers for the return value are put before the parameters
+        let mut i = sig.params.len()
+            - flayout
+                .params
+                .values()
+                .map(Self::abi_param_count)
+                .sum::<usize>();
+
+        for (ty, layout) in params.iter().zip(flayout.params.values()) {
+            if let Some(extension) = extension(ty) {
+                sig.params[i].extension = extension;
+            }
+            i += Self::abi_param_count(layout);
+        }
+
+        if let (Some(extension), [ret]) = (extension(ret), sig.returns.as_mut_slice()) {
+            ret.extension = extension;
+        }
+    }
+
+    fn abi_param_count(layout: &Layout<Type>) -> usize {
+        match layout {
+            Layout::OutPointer(..) | Layout::ZST => 0,
+            Layout::AutoBoxed(..) | Layout::Scalar(..) | Layout::SpecialPointer(..) => 1,
+            Layout::ArrayFlat(_, elems) => elems.iter().map(Self::abi_param_count).sum(),
+            Layout::StructFlat(_, fields) => fields.values().map(Self::abi_param_count).sum(),
+        }
+    }
+
     pub fn signature(&self, layout: &FuncLayout) -> Signature {
         let mut sig = Signature::new(layout.conv);
 
@@ -903,7 +1135,8 @@ impl<'a> Structs<'a> {
                     // Since that's exactly what we want to do for tagged unions,
                     // I hope there isn't a good reason for that...
                     SpecialPointer::HeapSumPayload { .. }
-                    | SpecialPointer::StackSumPayload { .. } => ArgumentPurpose::Normal,
+                    | SpecialPointer::StackSumPayload { .. }
+                    | SpecialPointer::EmbeddedSumPayload { .. } => ArgumentPurpose::Normal,
                     _ => ArgumentPurpose::StructReturn,
                 };
                 sig.params.push(AbiParam::special(*size_t, purpose));
@@ -966,12 +1199,14 @@ impl<'a> Structs<'a> {
                         self.field_to_layout(&self.structs[mk].fields[Field(1)], Stability::S);
                     let payload = match &self.structs[mk].fields[Field(0)] {
                         StructField::SumPayloadPointer { .. }
+                        | StructField::SumPayloadEmbedded { .. }
                             if matches!(stab, Stability::FRet) =>
                         {
                             let kind = SpecialPointer::StackSumPayload { sum: mk };
                             Layout::OutPointer(kind, size_t)
                         }
-                        StructField::SumPayloadPointer { .. } => {
+                        StructField::SumPayloadPointer { .. }
+                        | StructField::SumPayloadEmbedded { .. } => {
                             let kind = SpecialPointer::StackSumPayload { sum: mk };
                             Layout::SpecialPointer(kind, size_t)
                         }
@@ -1002,7 +1237,7 @@ impl<'a> Structs<'a> {
                 let (flayout, _sig) = self.flayout(CallConv::Tail, params, &ret);
                 Layout::Scalar(Scalar::FuncPointer(Box::new(flayout)), size_t)
             }
-            MonoType::Float => Layout::direct(types::F64),
+            MonoType::Float(size) => Layout::direct(float_type(*size)),
             MonoType::Array(n, inner) => {
                 if *n == 0 || self.size_of(inner) == 0 {
                     return Layout::ZST;
@@ -1077,6 +1312,20 @@ impl<'a> Structs<'a> {
                     Layout::SpecialPointer(kind, size_t)
                 }
             },
+            &StructField::SumPayloadEmbedded { sum } => match stab {
+                Stability::FRet => {
+                    let kind = SpecialPointer::StackSumPayload { sum };
+                    Layout::OutPointer(kind, size_t)
+                }
+                Stability::S => {
+                    let kind = SpecialPointer::EmbeddedSumPayload { sum };
+                    Layout::SpecialPointer(kind, size_t)
+                }
+                Stability::F => {
+                    let kind = SpecialPointer::StackSumPayload { sum };
+                    Layout::SpecialPointer(kind, size_t)
+                }
+            },
             &StructField::SumPayloadInline(clty) => Layout::Scalar(Scalar::SumPayloadInline, clty),
         }
     }
@@ -1086,6 +1335,13 @@ impl<'a> Structs<'a> {
     }
 }
 
+pub fn float_type(size: FloatSize) -> Type {
+    match size {
+        FloatSize::F32 => types::F32,
+        FloatSize::F64 => types::F64,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1122,6 +1378,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn repr_packed_offsets() {
+        let mut records = lir::MonomorphisedTypes::new(
+            M(key::Module(0), key::Trait::from(0)),
+            64,
+            ast::attr::Repr::Packed,
+        );
+
+        let int = |bits| MonoType::Int(IntSize::new(false, bits));
+
+        let record = records.get_or_make_tuple(vec![int(8), int(32), int(16)]);
+
+        let structs = Structs::new(&records.types);
+
+        assert_eq!(structs.size_and_align_of(&record.into()), (1 + 4 + 2, 1));
+
+        let u32_ = structs.get_real_field(record, key::Field(1));
+        assert_eq!(structs.offset_of(record, u32_), ByteOffset(1));
+        assert!(!structs.mem_flags(record).aligned());
+    }
+
+    #[test]
+    fn repr_align() {
+        let mut records = lir::MonomorphisedTypes::new(
+            M(key::Module(0), key::Trait::from(0)),
+            64,
+            ast::attr::Repr::Align(16),
+        );
+
+        let int = |bits| MonoType::Int(IntSize::new(false, bits));
+
+        let record = records.get_or_make_tuple(vec![int(8), int(32)]);
+
+        let structs = Structs::new(&records.types);
+
+        assert_eq!(structs.size_and_align_of(&record.into()), (16, 16));
+
+        let u32_ = structs.get_real_field(record, key::Field(1));
+        assert_eq!(structs.offset_of(record, u32_), ByteOffset(4));
+        assert!(structs.mem_flags(record).aligned());
+    }
+
     #[test]
     fn padding_at_end() {
         lumina_util::test_logger();
@@ -1180,4 +1478,48 @@ mod tests {
         assert_eq!(tuple_struct.align, 8);
         assert_eq!(structs.size_of(&tuple.into()), 8 * 4);
     }
+
+    #[test]
+    fn sum_payloads() {
+        lumina_util::test_logger();
+
+        let m = key::Module(0);
+
+        let mut records =
+            lir::MonomorphisedTypes::new(M(m, key::Trait::from(0)), 64, ast::attr::Repr::Lumina);
+
+        let int = |bits| MonoType::Int(IntSize::new(false, bits));
+        let tag = IntSize::new(false, 16);
+
+        // type Maybe = Nothing | Just u64 u32
+        let just = records.get_or_make_tuple(vec![int(64), int(32)]);
+        let maybe = records.types.push(lir::MonoTypeData::Sum {
+            tag,
+            key: M(m, key::Sum(0)),
+            variants: [lir::UNIT, just].into(),
+        });
+
+        // type List = Nil | Cons u64 List
+        let list = MonoTypeKey(maybe.0 + 2);
+        let cons = records.get_or_make_tuple(vec![int(64), list.into()]);
+        assert_eq!(
+            list,
+            records.types.push(lir::MonoTypeData::Sum {
+                tag,
+                key: M(m, key::Sum(1)),
+                variants: [lir::UNIT, cons].into(),
+            })
+        );
+
+        let structs = Structs::new(&records.types);
+
+        assert!(structs.has_embedded_payload(maybe));
+        assert_eq!(structs.sum_payload_size_and_align(maybe), (16, 8));
+        let maybe_tag = structs.get_real_field(maybe, key::Field(0));
+        assert_eq!(structs.offset_of(maybe, maybe_tag), ByteOffset(16));
+        assert_eq!(structs.size_of(&maybe.into()), 24);
+
+        assert!(!structs.has_embedded_payload(list));
+        assert_eq!(structs.size_of(&list.into()), 16);
+    }
 }
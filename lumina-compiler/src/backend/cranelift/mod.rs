@@ -2,66 +2,391 @@ use crate::debuginfo::BinDebugInfo;
 use crate::lir;
 use crate::prelude::*;
 use crate::target::{Arch, LinuxPlatform, Platform};
-use crate::Target;
+use crate::{CrateType, OptLevel, Target};
 use cranelift::codegen::ir;
 use cranelift::codegen::isa::CallConv;
 use cranelift::prelude::*;
+use cranelift_codegen::control::ControlPlane;
 use cranelift_entity::PrimaryMap;
+use cranelift_jit::JITModule;
 use cranelift_module::FuncOrDataId;
 use cranelift_module::{DataId, FuncId, Linkage, Module};
 use cranelift_object::{ObjectBuilder, ObjectModule};
-use std::sync::Arc;
+use lumina_util::{log_buffer, Symbol};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tracing::info_span;
 
 mod debuginfo;
 use debuginfo::unwind;
-mod layout;
+pub mod jit;
+pub(super) mod layout;
 mod ssa;
 
 use layout::FuncLayout;
 
 impl Target {
-    fn isa(&self) -> isa::Builder {
-        match self {
+    fn isa(&self) -> Option<isa::Builder> {
+        let triple = match self {
             Target { arch: Arch::X86_64, platform: Platform::Linux { sub } } => match sub {
                 LinuxPlatform::Gnu | LinuxPlatform::Musl | LinuxPlatform::Syscall => {
-                    isa::lookup_by_name("x86_64-unknown-linux").unwrap()
+                    "x86_64-unknown-linux"
                 }
             },
+            Target { arch: Arch::Aarch64, platform: Platform::Linux { .. } } => {
+                "aarch64-unknown-linux"
+            }
+            // The binary format of the triple makes `ObjectModule` emit COFF and Mach-O objects
+            Target { arch: Arch::X86_64, platform: Platform::Windows } => "x86_64-pc-windows-msvc",
+            Target { arch: Arch::X86_64, platform: Platform::Darwin } => "x86_64-apple-darwin",
+            // The default calling convention of this triple is `AppleAarch64`
+            Target { arch: Arch::Aarch64, platform: Platform::Darwin } => "aarch64-apple-darwin",
+            Target { arch: Arch::Aarch64, .. } => return None,
+            // Cranelift only generates native code
+            Target { arch: Arch::Wasm32, .. } | Target { platform: Platform::Wasi, .. } => {
+                return None
+            }
+        };
+
+        Some(isa::lookup_by_name(triple).unwrap())
+    }
+}
+
+/// The default backend, generating code with Cranelift
+#[derive(Default)]
+pub struct Cranelift {
+    /// If set, the Cranelift IR of every function is written to it
+    pub clif: Option<String>,
+    /// Whether to emit DWARF sections with line and function information
+    pub debug_info: bool,
+    /// Whether to instrument the program to write a profile when exiting, see [`lir::profile`]
+    pub profile_generate: bool,
+    /// If set, functions whose stack frame is larger than this many bytes are recorded in
+    /// `large_frames`
+    pub frame_size_warning: Option<u32>,
+    /// The symbols and frame sizes of the functions exceeding `frame_size_warning`, largest first
+    pub large_frames: Vec<(Symbol, u32)>,
+    /// If set, the symbols and compile times of this many of the slowest functions to compile are
+    /// recorded in `slowest`
+    pub timings: Option<usize>,
+    /// The functions which took the longest to translate and compile, slowest first
+    pub slowest: Vec<(Symbol, Duration)>,
+    /// The number of object files to spread the functions over, see [`run`]
+    pub codegen_units: u32,
+    /// Whether to instrument the program for AddressSanitizer, whose runtime must then be linked
+    pub sanitize_address: bool,
+    /// Whether to zero the stack memory of records and sum payloads before they're written, so
+    /// that Valgrind doesn't report their padding as uninitialised
+    pub debug_memory: bool,
+    /// Whether to lower breakpoints on the panic path to a debug trap instruction, so that an
+    /// attached debugger stops at the failure
+    pub trap_on_panic: bool,
+    /// Optimisation level given to Cranelift, which is the same level the LIR was optimised at
+    pub opt_level: OptLevel,
+    /// Libraries are compiled as position-independent code and don't get an entrypoint
+    pub crate_type: CrateType,
+}
+
+/// Statistics of the code generated for a function
+pub struct FunctionStats {
+    pub frame_size: u32,
+    /// Time spent translating the function to Cranelift IR and compiling it
+    pub compile_time: Duration,
+}
+
+impl super::Backend for Cranelift {
+    fn compile(
+        &mut self,
+        target: Target,
+        dwarf: BinDebugInfo,
+        lir: lir::Output,
+    ) -> Result<Vec<Vec<u8>>, super::Error> {
+        self.check(target, &lir)?;
+
+        match self.generate(target, dwarf, lir, false) {
+            Product::Objects(objects) => Ok(objects),
+            Product::Jit(_) => unreachable!(),
+        }
+    }
+}
+
+impl Cranelift {
+    /// Compile the program into this process instead of an object file, see [`jit`]
+    ///
+    /// Only the native target can be ran in-process, and only binaries have an entrypoint to run.
+    pub fn jit(
+        &mut self,
+        target: Target,
+        dwarf: BinDebugInfo,
+        lir: lir::Output,
+    ) -> Result<jit::Program, super::Error> {
+        self.check(target, &lir)?;
+
+        if target.name() != Target::native().name() {
+            return Err(super::Error::Unsupported(format!(
+                "running {target} in-process, only the native target can be JIT compiled"
+            )));
+        }
+
+        if self.crate_type != CrateType::Bin {
+            return Err(super::Error::Unsupported("JIT compiling libraries".into()));
+        }
+
+        // The runtime is only linked into binaries built by the system's C toolchain
+        if self.sanitize_address {
+            return Err(super::Error::Unsupported(
+                "the address sanitizer when JIT compiling".into(),
+            ));
+        }
+
+        // `JITModule` has no support for thread-local data
+        if !lir.thread_local_vals.is_empty() {
+            return Err(super::Error::Unsupported(
+                "thread-local vals when JIT compiling".into(),
+            ));
+        }
+
+        // There's no linker to resolve symbols between units, so everything goes in one
+        self.codegen_units = 1;
+
+        match self.generate(target, dwarf, lir, true) {
+            Product::Jit(program) => Ok(program),
+            Product::Objects(_) => unreachable!(),
+        }
+    }
+
+    fn check(&self, target: Target, lir: &lir::Output) -> Result<(), super::Error> {
+        if target.isa().is_none() {
+            return Err(super::Error::Target(target.to_string()));
+        }
+
+        // The profile is written with libc
+        if self.profile_generate
+            && matches!(
+                target.platform,
+                Platform::Linux { sub: LinuxPlatform::Syscall }
+            )
+        {
+            return Err(super::Error::Unsupported(
+                "recording profiles on the syscall target".into(),
+            ));
+        }
+
+        // The ASan runtime is only available through the system's C toolchain
+        if self.sanitize_address
+            && !matches!(target.platform, Platform::Linux { sub: LinuxPlatform::Gnu })
+        {
+            return Err(super::Error::Unsupported(format!(
+                "the address sanitizer on {target}"
+            )));
+        }
+
+        // Shared libraries are loaded by the dynamic linker, which only libc provides
+        if self.crate_type == CrateType::Lib
+            && !matches!(target.platform, Platform::Linux { sub: LinuxPlatform::Gnu })
+        {
+            return Err(super::Error::Unsupported(format!(
+                "shared libraries on {target}"
+            )));
+        }
+
+        // The vals of libraries are initialised through `.init_array`, which only ELF has
+        if self.crate_type == CrateType::Staticlib
+            && !matches!(
+                target.platform,
+                Platform::Linux { sub: LinuxPlatform::Gnu | LinuxPlatform::Musl }
+            )
+        {
+            return Err(super::Error::Unsupported(format!(
+                "static libraries on {target}"
+            )));
         }
+
+        // The profile is written by the entrypoint, which libraries don't have
+        if self.profile_generate && self.crate_type.is_library() {
+            return Err(super::Error::Unsupported(
+                "recording profiles of libraries".into(),
+            ));
+        }
+
+        // Every function is emitted into a section named after its symbol, which can't be renamed
+        if lir
+            .functions
+            .values()
+            .any(|func| func.linkage.section.is_some())
+        {
+            return Err(super::Error::Unsupported(
+                "custom sections for functions, use `--backend c` or `--backend llvm`".into(),
+            ));
+        }
+
+        // Variadic functions on x86_64 read the amount of vector registers used from `al`, which
+        // Cranelift has no way of setting
+        if matches!(target.arch, Arch::X86_64) && passes_variadic_floats(lir) {
+            return Err(super::Error::Unsupported(
+                "floats as variadic parameters on x86_64, use `--backend c` or `--backend llvm`"
+                    .into(),
+            ));
+        }
+
+        // Apple's arm64 convention passes every variadic parameter on the stack, while Cranelift
+        // would pass them in registers like fixed parameters
+        if matches!(
+            (target.arch, target.platform),
+            (Arch::Aarch64, Platform::Darwin)
+        ) && calls_variadic(lir)
+        {
+            return Err(super::Error::Unsupported(
+                "variadic extern functions on macOS, use `--backend c` or `--backend llvm`".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn generate(
+        &mut self,
+        target: Target,
+        dwarf: BinDebugInfo,
+        lir: lir::Output,
+        jit: bool,
+    ) -> Product {
+        let product = run(
+            target,
+            dwarf,
+            lir,
+            jit,
+            self.clif.as_mut(),
+            self.debug_info,
+            self.profile_generate,
+            self.codegen_units.max(1) as usize,
+            self.sanitize_address,
+            self.debug_memory,
+            self.trap_on_panic,
+            self.opt_level,
+            self.crate_type,
+            |symbol, stats| {
+                if self
+                    .frame_size_warning
+                    .is_some_and(|limit| stats.frame_size > limit)
+                {
+                    self.large_frames.push((symbol, stats.frame_size));
+                }
+                if self.timings.is_some() {
+                    self.slowest.push((symbol, stats.compile_time));
+                }
+            },
+        );
+
+        self.large_frames
+            .sort_by_key(|&(_, size)| std::cmp::Reverse(size));
+
+        self.slowest
+            .sort_by_key(|&(_, time)| std::cmp::Reverse(time));
+        self.slowest.truncate(self.timings.unwrap_or(0));
+
+        product
     }
 }
 
-pub fn run(target: Target, dwarf: BinDebugInfo, lir: lir::Output) -> Vec<u8> {
+/// The compiled program
+pub enum Product {
+    /// One object file for every codegen unit, to be handed to the linker
+    Objects(Vec<Vec<u8>>),
+    /// The program loaded into this process
+    Jit(jit::Program),
+}
+
+/// Compile the LIR into `codegen_units` object files, or into this process if `jit` is set
+///
+/// Functions are assigned to an object by the hash of their symbol, so that a function stays in
+/// the same object between builds. The first object also holds the vals, the read-only table and
+/// the entrypoint, or the initialiser of the vals for libraries.
+///
+/// If `clif` is given, the Cranelift IR of every function is also written to it. The statistics of
+/// every compiled function are given to `stats`.
+pub fn run(
+    target: Target,
+    dwarf: BinDebugInfo,
+    mut lir: lir::Output,
+    jit: bool,
+    mut clif: Option<&mut String>,
+    debug_info: bool,
+    profile_generate: bool,
+    codegen_units: usize,
+    sanitize_address: bool,
+    debug_memory: bool,
+    trap_on_panic: bool,
+    opt_level: OptLevel,
+    crate_type: CrateType,
+    mut stats: impl FnMut(Symbol, FunctionStats),
+) -> Product {
     let mut shared_builder = settings::builder();
-    shared_builder.set("opt_level", "speed").unwrap();
+    let opt_level = match opt_level {
+        OptLevel::None => "none",
+        OptLevel::Less | OptLevel::Speed => "speed",
+        OptLevel::Size => "speed_and_size",
+    };
+    shared_builder.set("opt_level", opt_level).unwrap();
     shared_builder.enable("preserve_frame_pointers").unwrap();
     shared_builder.enable("unwind_info").unwrap();
+    // Static libraries are also position-independent, since they may be linked into a PIE
+    if crate_type.is_library() {
+        shared_builder.enable("is_pic").unwrap();
+    }
+    let tls_model = match target.platform {
+        Platform::Linux { .. } => "elf_gd",
+        Platform::Darwin => "macho",
+        Platform::Windows => "coff",
+        Platform::Wasi => "none",
+    };
+    shared_builder.set("tls_model", tls_model).unwrap();
     let shared_flags = settings::Flags::new(shared_builder);
     shared_flags.enable_alias_analysis();
     assert!(shared_flags.unwind_info());
 
-    let isa = target.isa().finish(shared_flags).unwrap();
+    let isa = target
+        .isa()
+        .expect("target not supported by cranelift")
+        .finish(shared_flags)
+        .unwrap();
 
-    let objbuilder = ObjectBuilder::new(
-        isa.clone(),
-        b"lumina".to_vec(),
-        cranelift_module::default_libcall_names(),
-    )
-    .unwrap();
-    let mut objmodule = ObjectModule::new(objbuilder);
+    let mut objmodule = if jit {
+        CodegenModule::Jit(jit::jit_module(&isa))
+    } else {
+        CodegenModule::Object(object_module(&isa, b"lumina".to_vec()))
+    };
 
     let structs = layout::Structs::new(&lir.types);
+    let interpreted = lir::interpret::Layouts::new(&lir.types);
 
     let vals = lir.val_types.map(|val, ty| {
         let size = structs.size_of(ty) as usize;
-        let name = format!("{}___VAL", lir.functions[lir.val_initializers[&val]].symbol);
-        let thread_local = false; // TODO: this is something we're gonna want
+        let initializer = &lir.functions[lir.val_initializers[&val]];
+        let name = format!("{}___VAL", initializer.symbol);
+        let linkage = match &initializer.linkage {
+            ast::Linkage { weak: false, visibility: ast::SymbolVisibility::Hidden, .. } => {
+                Linkage::Export
+            }
+            linkage => to_cranelift_linkage(linkage),
+        };
+        let thread_local = lir.thread_local_vals.contains(&val);
         let id = objmodule
-            .declare_data(&name, Linkage::Export, true, thread_local)
+            .declare_data(&name, linkage, true, thread_local)
             .unwrap();
         let mut data = cranelift_module::DataDescription::new();
-        data.init = cranelift_module::Init::Zeros { size };
+        data.init = match lir.val_constants.get(&val) {
+            Some(bytes) => {
+                let contents = structs.constant_from_interpreter(&interpreted, ty, bytes);
+                cranelift_module::Init::Bytes { contents: contents.into() }
+            }
+            None => cranelift_module::Init::Zeros { size },
+        };
+        if let Some(section) = &initializer.linkage.section {
+            data.set_segment_section("", section);
+        }
         objmodule.define_data(id, &data).unwrap();
         id
     });
@@ -78,13 +403,51 @@ pub fn run(target: Target, dwarf: BinDebugInfo, lir: lir::Output) -> Vec<u8> {
         id
     });
 
+    let profile = profile_generate.then(|| {
+        let counters = lir::profile::Counters::new(&lir.functions);
+        // Functions in other objects bump the counters as well
+        let data = objmodule
+            .declare_data("__lumina_profile_counters", Linkage::Hidden, true, false)
+            .unwrap();
+        let mut desc = cranelift_module::DataDescription::new();
+        desc.init = cranelift_module::Init::Zeros { size: counters.count() * 8 };
+        objmodule.define_data(data, &desc).unwrap();
+        Instrumentation { counters, data }
+    });
+
+    let mut bodies = lir.take_bodies().into_iter().collect::<Vec<_>>();
+
+    // Functions which were never called while profiling are placed after all others, keeping the
+    // code which does run closer together
+    bodies.sort_by_key(|(_, func)| func.calls == Some(0));
+
     let mut flayouts = PrimaryMap::with_capacity(lir.functions.len() + lir.extern_funcs.len());
 
     info!("lowering function signatures");
 
+    let externdatamap = lir
+        .extern_funcs
+        .iter()
+        .filter(|(_, func)| func.data)
+        .map(|(key, data)| {
+            let id = match objmodule.get_name(&data.symbol) {
+                Some(FuncOrDataId::Data(id)) => id,
+                Some(FuncOrDataId::Func(_)) => {
+                    panic!("name collision for FFI element {}", &data.symbol)
+                }
+                None => objmodule
+                    .declare_data(&data.symbol, Linkage::Import, true, false)
+                    .unwrap(),
+            };
+
+            (*key, id)
+        })
+        .collect();
+
     let externmap = lir
         .extern_funcs
         .iter()
+        .filter(|(_, func)| !func.data)
         .map(|(key, func)| {
             let conv = isa.default_call_conv();
 
@@ -113,6 +476,24 @@ pub fn run(target: Target, dwarf: BinDebugInfo, lir: lir::Output) -> Vec<u8> {
         })
         .collect();
 
+    let asan = sanitize_address.then(|| {
+        let params = [lir::MonoType::u8_pointer(), lir::MonoType::u(64)];
+        let (flayout, sig) =
+            structs.flayout(isa.default_call_conv(), &params, &lir::MonoType::unit());
+        let [poison, unpoison] = [
+            "__asan_poison_memory_region",
+            "__asan_unpoison_memory_region",
+        ]
+        .map(|name| {
+            let id = objmodule
+                .declare_function(name, Linkage::Import, &sig)
+                .unwrap();
+            assert_eq!(id, flayouts.push(flayout.clone()));
+            id
+        });
+        Asan { poison, unpoison }
+    });
+
     let funcmap: Map<lir::MonoFunc, FuncId> = lir
         .functions
         .values()
@@ -121,8 +502,9 @@ pub fn run(target: Target, dwarf: BinDebugInfo, lir: lir::Output) -> Vec<u8> {
 
             let params = func.ssa.func_param_types();
             let (flayout, sig) = structs.flayout(conv, params, &func.returns);
+            let linkage = to_cranelift_linkage(&func.linkage);
             let id = objmodule
-                .declare_function(&func.symbol, Linkage::Hidden, &sig)
+                .declare_function(&func.symbol, linkage, &sig)
                 .unwrap();
             assert_eq!(id, flayouts.push(flayout));
 
@@ -130,44 +512,332 @@ pub fn run(target: Target, dwarf: BinDebugInfo, lir: lir::Output) -> Vec<u8> {
         })
         .collect();
 
+    let callbacks = super::callbacks(&lir, bodies.iter().map(|(_, func)| func))
+        .into_iter()
+        .map(|mfunc| {
+            let func = &lir.functions[mfunc];
+            let params = func.ssa.func_param_types();
+            let (flayout, sig) = structs.flayout(isa.default_call_conv(), params, &func.returns);
+            let id = objmodule
+                .declare_function(&super::callback_symbol(func), Linkage::Hidden, &sig)
+                .unwrap();
+            assert_eq!(id, flayouts.push(flayout));
+            (mfunc, id)
+        })
+        .collect::<HashMap<_, _>>();
+
+    // The other objects declare everything the first one has so far in the same order, so that
+    // the ids the bodies are compiled with refer to the same symbols regardless of which object
+    // they're defined in. The linker resolves the symbols an object declares but doesn't define.
+    let mut units = (1..codegen_units)
+        .map(|i| {
+            let mut unit = object_module(&isa, format!("lumina.{i}").into_bytes());
+            mirror_declarations(&*objmodule, &mut unit);
+            (unit, unwind::UnwindContext::new(&*isa, true))
+        })
+        .collect::<Vec<_>>();
+
     let unwindinfo = unwind::UnwindContext::new(&*isa, true);
 
     let mut ctx = Context::new(
-        isa, &vals, &lir, structs, objmodule, funcmap, externmap, flayouts, rotable, unwindinfo,
+        isa,
+        &vals,
+        &lir,
+        structs,
+        Mutex::new(objmodule),
+        funcmap,
+        externmap,
+        externdatamap,
+        callbacks,
+        flayouts,
+        rotable,
+        unwindinfo,
         dwarf,
+        profile,
+        asan,
+        debug_memory,
+        trap_on_panic,
     );
 
-    let mut cctx = codegen::Context::new();
-    let mut fctx = FunctionBuilderContext::new();
-    for (mfunc, func) in lir.functions.iter() {
-        let _span = info_span!(
-            "lowering function expression",
-            entity = func.symbol,
-            key = mfunc.to_string()
-        );
-        let _handle = _span.enter();
+    let compiled = compile_functions(&ctx, &bodies, clif.is_some());
+
+    // Functions are defined in the order of the bodies regardless of which thread compiled them,
+    // so that the object file is the same between builds
+    for ((mfunc, func), (cctx, text, compile_time)) in bodies.iter().zip(compiled) {
+        let id = ctx.funcmap[*mfunc];
+
+        if let (Some(clif), Some(text)) = (clif.as_deref_mut(), text) {
+            clif.push_str(&text);
+        }
 
-        let f_dbg_ctx = ssa::Translator::func(&mut ctx, &mut cctx, &mut fctx, func, mfunc);
-        let id = ctx.funcmap[mfunc];
+        let code = cctx.compiled_code().unwrap();
+        stats(
+            func.symbol,
+            FunctionStats { frame_size: code.frame_size, compile_time },
+        );
 
-        if let Err(err) = ctx.objmodule.define_function(id, &mut cctx) {
+        let (module, unwindinfo): (&mut dyn Module, _) = match unit_of(func.symbol, codegen_units) {
+            0 => (&mut **ctx.objmodule.get_mut().unwrap(), &mut ctx.unwindinfo),
+            i => {
+                let (module, unwindinfo) = &mut units[i - 1];
+                (module, unwindinfo)
+            }
+        };
+
+        let alignment = code.buffer.alignment as u64;
+        if let Err(err) = module.define_function_bytes(
+            id,
+            &cctx.func,
+            alignment,
+            code.code_buffer(),
+            code.buffer.relocs(),
+        ) {
             panic!("definition error when defining {}:\n {err}", func.symbol);
         }
 
-        ctx.unwindinfo.add_function(id, &cctx, &*ctx.isa);
+        unwindinfo.add_function(id, &cctx, &*ctx.isa);
 
-        f_dbg_ctx.finalize(&mut ctx.debuginfo, id, &cctx);
+        let f_dbg_ctx = ctx.def_function(*mfunc, &func.ssa);
+        f_dbg_ctx.finalize(&mut ctx.debuginfo, id, &cctx, &func.ssa);
+    }
 
-        cctx.clear();
+    for mfunc in lir.functions.keys() {
+        if let Some(symbol) = lir.functions[mfunc].export {
+            ctx.declare_export(mfunc, symbol);
+        }
+        if let Some(&id) = ctx.callbacks.get(&mfunc) {
+            ctx.define_forwarder(mfunc, id);
+        }
     }
 
-    ctx.declare_entrypoint(target);
+    let entrypoint = match crate_type {
+        CrateType::Bin => Some(ctx.declare_entrypoint(target)),
+        CrateType::Lib | CrateType::Staticlib => {
+            ctx.declare_library_initialiser();
+            None
+        }
+    };
+
+    // Neither the unwind tables nor the debug info are registered with the process
+    let module = match ctx.objmodule.into_inner().unwrap() {
+        CodegenModule::Object(module) => module,
+        CodegenModule::Jit(module) => {
+            let main = entrypoint.expect("JIT compiled library");
+            return Product::Jit(jit::Program::new(module, main));
+        }
+    };
 
-    let mut product = ctx.objmodule.finish();
+    let mut product = module.finish();
     ctx.unwindinfo.emit(&mut product);
-    ctx.debuginfo.emit(&mut product);
+    if debug_info {
+        ctx.debuginfo.emit(&mut product);
+    }
+
+    let mut objects = vec![product.emit().unwrap()];
+    objects.extend(units.into_iter().map(|(unit, unwindinfo)| {
+        let mut product = unit.finish();
+        unwindinfo.emit(&mut product);
+        product.emit().unwrap()
+    }));
+
+    Product::Objects(objects)
+}
 
-    product.emit().unwrap()
+/// The module the first unit is defined in
+enum CodegenModule {
+    Object(ObjectModule),
+    Jit(JITModule),
+}
+
+impl std::ops::Deref for CodegenModule {
+    type Target = dyn Module + Send;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            CodegenModule::Object(module) => module,
+            CodegenModule::Jit(module) => module,
+        }
+    }
+}
+
+impl std::ops::DerefMut for CodegenModule {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            CodegenModule::Object(module) => module,
+            CodegenModule::Jit(module) => module,
+        }
+    }
+}
+
+// Every function and data object gets a section of its own, so that the linker can leave out
+// the ones which are never referenced
+fn object_module(isa: &Arc<dyn isa::TargetIsa>, name: Vec<u8>) -> ObjectModule {
+    let mut objbuilder =
+        ObjectBuilder::new(isa.clone(), name, cranelift_module::default_libcall_names()).unwrap();
+    objbuilder.per_function_section(true);
+    objbuilder.per_data_object_section(true);
+    ObjectModule::new(objbuilder)
+}
+
+/// Declare the functions and data of `from` in `to` with the same ids and linkage
+fn mirror_declarations(from: &dyn Module, to: &mut ObjectModule) {
+    let declarations = from.declarations();
+
+    for (id, decl) in declarations.get_functions() {
+        let name = decl
+            .name
+            .as_deref()
+            .expect("anonymous function in mirrored object");
+        let mirrored = to
+            .declare_function(name, decl.linkage, &decl.signature)
+            .unwrap();
+        assert_eq!(id, mirrored);
+    }
+
+    for (id, decl) in declarations.get_data_objects() {
+        let name = decl
+            .name
+            .as_deref()
+            .expect("anonymous data in mirrored object");
+        let mirrored = to
+            .declare_data(name, decl.linkage, decl.writable, decl.tls)
+            .unwrap();
+        assert_eq!(id, mirrored);
+    }
+}
+
+/// The object a function is defined in, from the FNV-1a hash of its symbol
+fn unit_of(symbol: Symbol, units: usize) -> usize {
+    let hash = symbol
+        .as_str()
+        .bytes()
+        .fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    (hash % units as u64) as usize
+}
+
+/// Translate the bodies to Cranelift IR and compile them, spread over all available cores
+///
+/// The results are in the same order as the bodies, along with their Cranelift IR from before
+/// compilation if `dump_clif` is set and the time it took to translate and compile them. The
+/// tracing output of each body is buffered by its worker and written in the same order.
+fn compile_functions(
+    ctx: &Context,
+    bodies: &[(lir::MonoFunc, lir::Function)],
+    dump_clif: bool,
+) -> Vec<(codegen::Context, Option<String>, Duration)> {
+    let next = &AtomicUsize::new(0);
+    let threads = std::thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(bodies.len().max(1));
+
+    let mut compiled = std::thread::scope(|scope| {
+        let workers = (0..threads)
+            .map(move |_| {
+                scope.spawn(move || {
+                    let mut fctx = FunctionBuilderContext::new();
+                    let mut compiled = vec![];
+
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((mfunc, func)) = bodies.get(i) else {
+                            break compiled;
+                        };
+
+                        let ((cctx, clif, time), log) = log_buffer::capture(|| {
+                            let _span = info_span!(
+                                "lowering function expression",
+                                entity = func.symbol.as_str(),
+                                key = mfunc.to_string()
+                            );
+                            let _handle = _span.enter();
+                            let start = Instant::now();
+
+                            let mut cctx = codegen::Context::new();
+                            ssa::Translator::func(ctx, &mut cctx, &mut fctx, func, *mfunc);
+
+                            let clif = dump_clif.then(|| {
+                                let id = ctx.funcmap[*mfunc];
+                                format!(
+                                    "; symbol {id} = {}\n{}\n",
+                                    func.symbol,
+                                    cctx.func.display()
+                                )
+                            });
+
+                            if let Err(err) = cctx.compile(&*ctx.isa, &mut ControlPlane::default())
+                            {
+                                panic!(
+                                    "definition error when defining {}:\n {}",
+                                    func.symbol, err.inner
+                                );
+                            }
+
+                            (cctx, clif, start.elapsed())
+                        });
+
+                        compiled.push((i, cctx, clif, time, log));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Panics are resumed with their original payload, which `lumina bisect` matches on
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|err| std::panic::resume_unwind(err))
+            })
+            .collect::<Vec<_>>()
+    });
+
+    compiled.sort_by_key(|(i, ..)| *i);
+
+    compiled
+        .into_iter()
+        .map(|(_, cctx, clif, time, log)| {
+            log_buffer::flush(&log);
+            (cctx, clif, time)
+        })
+        .collect()
+}
+
+fn to_cranelift_linkage(linkage: &ast::Linkage) -> Linkage {
+    match linkage {
+        // Cranelift has no weak hidden linkage, so that combination is rejected in `ast::collect`
+        ast::Linkage { weak: true, .. } => Linkage::Preemptible,
+        ast::Linkage { visibility: ast::SymbolVisibility::Default, .. } => Linkage::Export,
+        ast::Linkage { visibility: ast::SymbolVisibility::Hidden, .. } => Linkage::Hidden,
+    }
+}
+
+fn passes_variadic_floats(lir: &lir::Output) -> bool {
+    lir.functions.values().any(|func| {
+        func.ssa.iterv().any(|v| match func.ssa.entry_of(v) {
+            lir::Entry::CallExtern(key, params) => {
+                let extern_ = &lir.extern_funcs[key];
+                extern_.variadic
+                    && params[extern_.params.len()..].iter().any(|p| match p {
+                        lir::Value::Float(_) => true,
+                        lir::Value::V(v) => matches!(func.ssa.type_of(*v), lir::MonoType::Float(_)),
+                        _ => false,
+                    })
+            }
+            _ => false,
+        })
+    })
+}
+
+fn calls_variadic(lir: &lir::Output) -> bool {
+    lir.functions.values().any(|func| {
+        func.ssa.iterv().any(|v| match func.ssa.entry_of(v) {
+            lir::Entry::CallExtern(key, _) => lir.extern_funcs[key].variadic,
+            _ => false,
+        })
+    })
 }
 
 #[derive(new)]
@@ -176,19 +846,44 @@ pub struct Context<'a> {
     val_to_globals: &'a MMap<key::Val, DataId>,
     lir: &'a lir::Output,
     structs: layout::Structs<'a>,
-    objmodule: ObjectModule,
+    // Only locked by the translation of functions in parallel to declare the functions and data
+    // they reference
+    objmodule: Mutex<CodegenModule>,
 
     funcmap: Map<lir::MonoFunc, FuncId>,
     externmap: HashMap<M<key::Func>, FuncId>,
+    externdatamap: HashMap<M<key::Func>, DataId>,
+    callbacks: HashMap<lir::MonoFunc, FuncId>,
 
     flayouts: PrimaryMap<FuncId, FuncLayout>,
     rotable: MMap<key::ReadOnly, DataId>,
 
     unwindinfo: unwind::UnwindContext,
     debuginfo: BinDebugInfo,
+
+    profile: Option<Instrumentation>,
+    asan: Option<Asan>,
+    debug_memory: bool,
+    trap_on_panic: bool,
+}
+
+/// The functions of the ASan runtime used to poison the redzones of stack allocations
+struct Asan {
+    poison: FuncId,
+    unpoison: FuncId,
+}
+
+/// Counters of a binary instrumented with `--profile-generate`
+struct Instrumentation {
+    counters: lir::profile::Counters,
+    data: DataId,
 }
 
 impl<'a> Context<'a> {
+    fn module(&mut self) -> &mut dyn Module {
+        &mut **self.objmodule.get_mut().unwrap()
+    }
+
     pub fn size_t(&self) -> Type {
         let triple = self.isa.triple();
         Type::triple_pointer_type(triple)
@@ -206,16 +901,22 @@ impl<'a> Context<'a> {
         builder.seal_block(entry);
         builder.switch_to_block(entry);
 
+        // Hidden so that the `.init_array` of every Lumina library loaded into the same process
+        // refers to its own initialiser
         let id = self
-            .objmodule
+            .module()
             .declare_function(
                 "__lumina_val_initialiser__",
-                Linkage::Export,
+                Linkage::Hidden,
                 &builder.func.signature,
             )
             .unwrap();
 
         for val in self.val_to_globals.iter() {
+            if self.lir.val_constants.contains_key(&val) {
+                continue;
+            }
+
             let mfunc = self.lir.val_initializers[&val];
             info!(
                 "lowering value initialiser {}",
@@ -227,20 +928,22 @@ impl<'a> Context<'a> {
             let flayout = &self.flayouts[funcid];
 
             let mut func_imports = HashMap::new();
+            let mut slots = ssa::StackSlots::default();
             let mut ins = ssa::InstHelper::new(
                 &mut builder,
                 &self.structs,
                 self.size_t(),
                 self.funcmap[self.lir.alloc],
                 self.isa.clone(),
-                &mut self.objmodule,
+                &self.objmodule,
                 &mut func_imports,
+                &mut slots,
             );
             let ptr = ins.dataid_as_pointer(dataid);
 
             let call = ins.new_call(0, &flayout.ret);
             let vlayout = ins.call_direct(funcid, call);
-            ins.write_vlayout_to_ptr(ptr, &vlayout);
+            ins.write_vlayout_to_ptr(ptr, &vlayout, MemFlags::trusted());
         }
 
         builder.ins().return_(&[]);
@@ -252,20 +955,213 @@ impl<'a> Context<'a> {
         }
 
         let mut fctx = codegen::Context::for_function(clfunc);
-        self.objmodule.define_function(id, &mut fctx).unwrap();
+        self.module().define_function(id, &mut fctx).unwrap();
 
         self.unwindinfo.add_function(id, &mut fctx, &*self.isa);
 
         id
     }
 
+    fn import_libc_function(&mut self, name: &str, params: &[Type], returns: &[Type]) -> FuncId {
+        match self.module().get_name(name) {
+            Some(FuncOrDataId::Func(id)) => id,
+            Some(FuncOrDataId::Data(_)) => panic!("name collision for libc function {name}"),
+            None => {
+                let mut sig = Signature::new(self.isa.default_call_conv());
+                sig.params
+                    .extend(params.iter().map(|&ty| AbiParam::new(ty)));
+                sig.returns
+                    .extend(returns.iter().map(|&ty| AbiParam::new(ty)));
+                self.module()
+                    .declare_function(name, Linkage::Import, &sig)
+                    .unwrap()
+            }
+        }
+    }
+
+    // Declares a function writing the header and counters of an instrumented binary to the
+    // profile, which the entrypoint registers with `atexit`
+    fn declare_profile_dump(&mut self) -> FuncId {
+        let profile = self.profile.as_ref().unwrap();
+        let (counters, len) = (profile.data, profile.counters.count());
+        let header = profile.counters.header();
+        let header_len = header.len();
+
+        let path = format!("{}\0", lir::profile::DEFAULT_PATH).into_bytes();
+        let [header, path, mode] = [header, path, b"wb\0".to_vec()].map(|bytes| {
+            let id = self.module().declare_anonymous_data(false, false).unwrap();
+            let mut desc = cranelift_module::DataDescription::new();
+            desc.init = cranelift_module::Init::Bytes { contents: bytes.into() };
+            self.module().define_data(id, &desc).unwrap();
+            id
+        });
+
+        let size_t = self.size_t();
+        let fopen = self.import_libc_function("fopen", &[size_t, size_t], &[size_t]);
+        let fwrite = self.import_libc_function("fwrite", &[size_t; 4], &[size_t]);
+        let fclose = self.import_libc_function("fclose", &[size_t], &[types::I32]);
+
+        let mut func_builder_ctx = FunctionBuilderContext::new();
+        let mut clfunc = ir::Function::new();
+        let mut builder = FunctionBuilder::new(&mut clfunc, &mut func_builder_ctx);
+        builder.func.signature = Signature::new(self.isa.default_call_conv());
+
+        let id = self
+            .module()
+            .declare_function(
+                "__lumina_profile_dump",
+                Linkage::Local,
+                &builder.func.signature,
+            )
+            .unwrap();
+
+        let [entry, write, done] = [(); 3].map(|_| builder.create_block());
+        builder.seal_block(entry);
+        builder.switch_to_block(entry);
+
+        let [fopen, fwrite, fclose] = [fopen, fwrite, fclose].map(|func_id| {
+            self.module()
+                .declare_func_in_func(func_id, &mut builder.func)
+        });
+        let [header, counters, path, mode] = [header, counters, path, mode].map(|data_id| {
+            let data = self
+                .module()
+                .declare_data_in_func(data_id, &mut builder.func);
+            builder.ins().symbol_value(size_t, data)
+        });
+
+        let call = builder.ins().call(fopen, &[path, mode]);
+        let file = builder.inst_results(call)[0];
+        builder.ins().brif(file, write, &[], done, &[]);
+
+        builder.seal_block(write);
+        builder.switch_to_block(write);
+        let one = builder.ins().iconst(size_t, 1);
+        let header_len = builder.ins().iconst(size_t, header_len as i64);
+        builder.ins().call(fwrite, &[header, one, header_len, file]);
+        let eight = builder.ins().iconst(size_t, 8);
+        let len = builder.ins().iconst(size_t, len as i64);
+        builder.ins().call(fwrite, &[counters, eight, len, file]);
+        builder.ins().call(fclose, &[file]);
+        builder.ins().jump(done, &[]);
+
+        builder.seal_block(done);
+        builder.switch_to_block(done);
+        builder.ins().return_(&[]);
+
+        info!("profile dump function:\n{}", builder.func);
+
+        if let Err(err) = cranelift_codegen::verify_function(&clfunc, self.isa.as_ref()) {
+            error!("cranelift_codegen verifier error:\n{err:#?}");
+        }
+
+        let mut fctx = codegen::Context::for_function(clfunc);
+        self.module().define_function(id, &mut fctx).unwrap();
+
+        self.unwindinfo.add_function(id, &mut fctx, &*self.isa);
+
+        id
+    }
+
+    // Lumina functions use the tail calling convention, so foreign code calls exported functions
+    // through a function using the platform's calling convention which forwards the call.
+    fn declare_export(&mut self, mfunc: lir::MonoFunc, symbol: Symbol) -> FuncId {
+        let func = &self.lir.functions[mfunc];
+        let (_, signature) = self.structs.flayout(
+            self.isa.default_call_conv(),
+            func.ssa.func_param_types(),
+            &func.returns,
+        );
+
+        let id = self
+            .module()
+            .declare_function(symbol.as_str(), Linkage::Export, &signature)
+            .unwrap();
+
+        self.define_forwarder(mfunc, id);
+
+        id
+    }
+
+    // Defines `id` as a function calling `mfunc` with the parameters it was called with, for
+    // exports and the callbacks given to extern functions. Both signatures come from the same
+    // `flayout`, so the parameters map one-to-one and only the calling convention differs. That
+    // only matches what C passes for scalars and pointers, which is why callbacks can't take
+    // structs by value (see `non_c_type` in the MIR).
+    fn define_forwarder(&mut self, mfunc: lir::MonoFunc, id: FuncId) {
+        let decl = self.module().declarations().get_function_decl(id);
+        let name = decl.name.clone().expect("anonymous forwarder");
+        let signature = decl.signature.clone();
+
+        let mut func_builder_ctx = FunctionBuilderContext::new();
+        let mut clfunc = ir::Function::new();
+        let mut builder = FunctionBuilder::new(&mut clfunc, &mut func_builder_ctx);
+        builder.func.signature = signature;
+
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.seal_block(entry);
+        builder.switch_to_block(entry);
+
+        let internal_id = self.funcmap[mfunc];
+        let internal = self
+            .module()
+            .declare_func_in_func(internal_id, &mut builder.func);
+
+        let params = builder.block_params(entry).to_vec();
+        let call = builder.ins().call(internal, &params);
+        let results = builder.inst_results(call).to_vec();
+        builder.ins().return_(&results);
+
+        info!("forwarder {name}:\n{}", builder.func);
+
+        if let Err(err) = cranelift_codegen::verify_function(&clfunc, self.isa.as_ref()) {
+            error!("cranelift_codegen verifier error:\n{err:#?}");
+        }
+
+        let mut fctx = codegen::Context::for_function(clfunc);
+        if let Err(err) = self.module().define_function(id, &mut fctx) {
+            panic!("definition error when defining {name}:\n {err}");
+        }
+
+        self.unwindinfo.add_function(id, &mut fctx, &*self.isa);
+    }
+
+    // Libraries have no entrypoint, so the val initialisers are ran through `.init_array` when the
+    // library is loaded, or when the program it's linked into starts.
+    fn declare_library_initialiser(&mut self) -> DataId {
+        let val_inits_id = self.declare_val_run_and_store();
+
+        let id = self
+            .module()
+            .declare_data("__lumina_init_array__", Linkage::Local, true, false)
+            .unwrap();
+
+        let mut data = cranelift_module::DataDescription::new();
+        data.define_zeroinit(self.isa.pointer_bytes() as usize);
+        data.set_segment_section("", ".init_array");
+        let val_inits = self.module().declare_func_in_data(val_inits_id, &mut data);
+        data.write_function_addr(0, val_inits);
+
+        self.module().define_data(id, &data).unwrap();
+
+        id
+    }
+
     fn declare_entrypoint(&mut self, target: Target) -> FuncId {
         let val_inits_id = self.declare_val_run_and_store();
 
+        let profile_dump_id = self.profile.is_some().then(|| {
+            let dump = self.declare_profile_dump();
+            let size_t = self.size_t();
+            let atexit = self.import_libc_function("atexit", &[size_t], &[types::I32]);
+            (dump, atexit)
+        });
+
         let mut func_builder_ctx = FunctionBuilderContext::new();
         let mut clfunc = ir::Function::new();
         let mut builder = FunctionBuilder::new(&mut clfunc, &mut func_builder_ctx);
-        builder.func.signature = Signature::new(isa::CallConv::SystemV);
+        builder.func.signature = Signature::new(self.isa.default_call_conv());
 
         let entryblock = builder.create_block();
         builder.seal_block(entryblock);
@@ -276,12 +1172,18 @@ impl<'a> Context<'a> {
 
         let [lumina_main, val_inits, sys_init] =
             [lumina_main_id, val_inits_id, sys_init_id].map(|func_id| {
-                self.objmodule
+                self.module()
                     .declare_func_in_func(func_id, &mut builder.func)
             });
 
         match target.platform {
-            Platform::Linux { sub: LinuxPlatform::Gnu | LinuxPlatform::Musl } => {
+            // The C runtime's `mainCRTStartup` calls `main` on Windows the same way libc does.
+            //
+            // Mach-O symbols are prefixed with an underscore by the object writer, so this
+            // becomes the `_main` which dyld expects on macOS.
+            Platform::Linux { sub: LinuxPlatform::Gnu | LinuxPlatform::Musl }
+            | Platform::Windows
+            | Platform::Darwin => {
                 builder.func.signature.params = vec![
                     AbiParam::new(types::I32),              // argc
                     AbiParam::new(self.isa.pointer_type()), // **argv
@@ -289,10 +1191,21 @@ impl<'a> Context<'a> {
                 builder.func.signature.returns = vec![AbiParam::new(types::I32)]; // exit code
                 builder.append_block_params_for_function_params(entryblock);
                 let id = self
-                    .objmodule
+                    .module()
                     .declare_function("main", Linkage::Export, &builder.func.signature)
                     .unwrap();
 
+                // Write the profile when exiting, including through `exit`
+                if let Some((dump, atexit)) = profile_dump_id {
+                    let [dump, atexit] = [dump, atexit].map(|func_id| {
+                        self.module()
+                            .declare_func_in_func(func_id, &mut builder.func)
+                    });
+                    let size_t = self.size_t();
+                    let dump = builder.ins().func_addr(size_t, dump);
+                    builder.ins().call(atexit, &[dump]);
+                }
+
                 // Call the val initialiser function
                 builder.ins().call(val_inits, &[]);
 
@@ -313,13 +1226,13 @@ impl<'a> Context<'a> {
                 }
 
                 let mut fctx = codegen::Context::for_function(clfunc);
-                self.objmodule.define_function(id, &mut fctx).unwrap();
+                self.module().define_function(id, &mut fctx).unwrap();
 
                 id
             }
             Platform::Linux { sub: LinuxPlatform::Syscall } => {
                 let id = self
-                    .objmodule
+                    .module()
                     .declare_function("_start", Linkage::Export, &builder.func.signature)
                     .unwrap();
 
@@ -329,20 +1242,28 @@ impl<'a> Context<'a> {
                 // Call the lumina main function
                 builder.ins().call(lumina_main, &[]);
 
+                // The syscall helpers take the syscall number as their last parameter, and
+                // either use `syscall` or `svc #0` to make the call
+                let (helper, exit) = match target.arch {
+                    Arch::X86_64 => ("x86_64_syscall", 60),
+                    Arch::Aarch64 => ("aarch64_syscall", 93),
+                    Arch::Wasm32 => unreachable!("wasm32 is only paired with wasi"),
+                };
+
                 let syscall = {
-                    let syscall_id = match self.objmodule.get_name("x86_64_syscall") {
+                    let syscall_id = match self.module().get_name(helper) {
                         Some(cranelift_module::FuncOrDataId::Func(fid)) => fid,
-                        _ => panic!("x86_64_syscall symbol not defined"),
+                        _ => panic!("{helper} symbol not defined"),
                     };
 
-                    self.objmodule
+                    self.module()
                         .declare_func_in_func(syscall_id, &mut builder.func)
                 };
 
                 // Add `syscall 0 EXIT` at the end of the start function so we don't segfault
                 let zero = builder.ins().iconst(types::I64, 0);
                 let exit_code = zero;
-                let sys_exit = builder.ins().iconst(types::I64, 60);
+                let sys_exit = builder.ins().iconst(types::I64, exit);
                 builder
                     .ins()
                     .call(syscall, &[exit_code, zero, zero, zero, zero, sys_exit]);
@@ -355,10 +1276,11 @@ impl<'a> Context<'a> {
                 }
 
                 let mut fctx = codegen::Context::for_function(clfunc);
-                self.objmodule.define_function(id, &mut fctx).unwrap();
+                self.module().define_function(id, &mut fctx).unwrap();
 
                 id
             }
+            Platform::Wasi => unreachable!("rejected by `Target::isa`"),
         }
     }
 }
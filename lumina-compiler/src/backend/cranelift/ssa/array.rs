@@ -9,7 +9,7 @@ impl<'a, 's, 'f> InstHelper<'a, 's, 'f> {
                     let (_, elem_size, _) = self.structs.size_and_align_of_array(&inner, len);
                     let indice_offset = self.ins().imul_imm(indice, elem_size as i64);
                     let nptr = self.ins().iadd(ptr, indice_offset);
-                    self.deref_type(nptr, ByteOffset(0), &inner)
+                    self.deref_type(nptr, ByteOffset(0), &inner, MemFlags::trusted())
                 }
                 _ => panic!("attempted indice of non-array"),
             },
@@ -57,7 +57,8 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
             let entry = self.value_to_vlayout(*value);
             let slot_addr = self.cins().stack_addr(size_t, slot, offset);
 
-            self.ins().write_vlayout_to_ptr(slot_addr, &entry);
+            self.ins()
+                .write_vlayout_to_ptr(slot_addr, &entry, MemFlags::trusted());
             offset += elem_size as i32;
         }
 
@@ -74,7 +75,37 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
         inner: &MonoType,
         times: u64,
     ) -> VLayout {
-        let values = vec![value; times as usize];
-        self.construct_array(&values, inner)
+        if times == 0 || self.ctx.structs.arr_pass_mode(times, inner) != PassBy::Pointer {
+            let values = vec![value; times as usize];
+            return self.construct_array(&values, inner);
+        }
+
+        let (slot, elem_size) = self.ins().alloc_array(inner, times as usize);
+        let (size, _, align) = self.ctx.structs.size_and_align_of_array(inner, times);
+        let size_t = self.ctx.size_t();
+        let ptr = self.cins().stack_addr(size_t, slot, 0);
+
+        if let lir::Value::Int(0, _) = value {
+            self.ins().memset_zero(ptr, size as u64, align as u8);
+        } else {
+            let entry = self.value_to_vlayout(value);
+            self.ins()
+                .write_vlayout_to_ptr(ptr, &entry, MemFlags::trusted());
+
+            // Double the initialised prefix with each copy instead of writing every element
+            let mut filled = 1;
+            while filled < times {
+                let n = filled.min(times - filled);
+                let dst = self
+                    .cins()
+                    .stack_addr(size_t, slot, (filled * elem_size as u64) as i32);
+                self.ins()
+                    .memcpy_struct(dst, ptr, n * elem_size as u64, align as u8);
+                filled += n;
+            }
+        }
+
+        let kind = SpecialPointer::StackArray(inner.clone(), times);
+        Layout::SpecialPointer(kind, ptr)
     }
 }
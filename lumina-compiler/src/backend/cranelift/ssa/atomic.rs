@@ -0,0 +1,67 @@
+use super::*;
+use ir::AtomicRmwOp;
+
+// Cranelift's atomic instructions are all sequentially consistent, so the ordering requested by
+// the LIR is always satisfied and therefore ignored.
+impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
+    pub(super) fn atomic_load(&mut self, ptr: lir::Value, ty: &MonoType) -> VLayout {
+        let ptr = self.value_to_vlayout(ptr).as_pointer().1;
+        let (kind, clty) = self.atomic_scalar(ty);
+        let v = self.cins().atomic_load(clty, MemFlags::trusted(), ptr);
+        Layout::Scalar(kind, v)
+    }
+
+    pub(super) fn atomic_store(&mut self, ptr: lir::Value, value: lir::Value) -> VLayout {
+        let ptr = self.value_to_vlayout(ptr).as_pointer().1;
+        let value = self.value_to_vlayout(value).as_scalar();
+        self.cins().atomic_store(MemFlags::trusted(), value, ptr);
+        Layout::ZST
+    }
+
+    pub(super) fn atomic_cas(
+        &mut self,
+        [ptr, expected, replacement]: [lir::Value; 3],
+        ty: &MonoType,
+    ) -> VLayout {
+        let ptr = self.value_to_vlayout(ptr).as_pointer().1;
+        let [expected, replacement] =
+            [expected, replacement].map(|v| self.value_to_vlayout(v).as_scalar());
+        let (kind, _) = self.atomic_scalar(ty);
+        let v = self
+            .cins()
+            .atomic_cas(MemFlags::trusted(), ptr, expected, replacement);
+        Layout::Scalar(kind, v)
+    }
+
+    pub(super) fn atomic_rmw(
+        &mut self,
+        op: lir::AtomicRmwOp,
+        [ptr, value]: [lir::Value; 2],
+        ty: &MonoType,
+    ) -> VLayout {
+        let ptr = self.value_to_vlayout(ptr).as_pointer().1;
+        let value = self.value_to_vlayout(value).as_scalar();
+        let (kind, clty) = self.atomic_scalar(ty);
+
+        let op = match op {
+            lir::AtomicRmwOp::Add => AtomicRmwOp::Add,
+            lir::AtomicRmwOp::Sub => AtomicRmwOp::Sub,
+            lir::AtomicRmwOp::And => AtomicRmwOp::And,
+            lir::AtomicRmwOp::Or => AtomicRmwOp::Or,
+            lir::AtomicRmwOp::Xor => AtomicRmwOp::Xor,
+            lir::AtomicRmwOp::Xchg => AtomicRmwOp::Xchg,
+        };
+
+        let v = self
+            .cins()
+            .atomic_rmw(clty, MemFlags::trusted(), op, ptr, value);
+        Layout::Scalar(kind, v)
+    }
+
+    fn atomic_scalar(&self, ty: &MonoType) -> (Scalar, Type) {
+        match self.ctx.structs.type_to_layout(ty, Stability::S) {
+            Layout::Scalar(kind, clty) => (kind, clty),
+            _ => panic!("non-scalar atomic: {ty:?}"),
+        }
+    }
+}
@@ -55,7 +55,51 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
 
     pub fn call_extern(&mut self, key: M<key::Func>, params: &[lir::Value]) -> VLayout {
         let id = self.ctx.externmap[&key];
-        self.call_func_id(id, params)
+        self.poison_redzones(true);
+        let vlayout = if self.ctx.lir.extern_funcs[&key].variadic {
+            self.call_variadic(key, id, params)
+        } else {
+            let layout = &self.ctx.flayouts[id];
+            let rlayout = layout.ret.clone();
+            let mut call = self.ins().new_call(layout.params.len(), &rlayout);
+            self.extern_fparams(id, params, &mut call.params);
+            self.ins().call_direct(id, call)
+        };
+        self.poison_redzones(false);
+        vlayout
+    }
+
+    // The function is declared with only its fixed parameters, so each call goes through a
+    // signature extended with the scalars given in place of `...`. The LIR has already applied the
+    // default argument promotions, after which both System V and AAPCS64 on Linux pass them the
+    // same way as fixed parameters. Apple's arm64 convention doesn't, so those calls are rejected
+    // in `check`.
+    fn call_variadic(&mut self, key: M<key::Func>, id: FuncId, params: &[lir::Value]) -> VLayout {
+        let fixed = self.ctx.lir.extern_funcs[&key].params.len();
+        let layout = self.ctx.flayouts[id].clone();
+        let mut sig = self.ctx.structs.signature(&layout);
+
+        let mut call = self.ins().new_call(layout.params.len(), &layout.ret);
+        self.extern_fparams(id, &params[..fixed], &mut call.params);
+
+        for p in &params[fixed..] {
+            let v = self.value_to_vlayout(*p).as_scalar();
+            sig.params.push(AbiParam::new(self.f.type_of_value(v)));
+            call.params.push(v);
+        }
+
+        let sigref = self.f.builder.import_signature(sig);
+        let fref = self.ins().declare_func_in_func(id);
+        let size_t = self.ctx.size_t();
+        let ptr = self.cins().func_addr(size_t, fref);
+        let c = self.cins().call_indirect(sigref, ptr, &call.params);
+
+        let inst_values = self.f.builder.inst_results(c).to_vec();
+        self.ins().layout_from_raw_values(
+            call.ret_pointer,
+            call.rlayout,
+            &mut inst_values.as_slice(),
+        )
     }
 
     pub fn call_func_id(&mut self, id: FuncId, lparams: &[lir::Value]) -> VLayout {
@@ -131,6 +175,27 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
         });
     }
 
+    // Lumina functions given directly as function pointers are replaced with their callback, which
+    // C can call with its own calling convention
+    fn extern_fparams(&mut self, id: FuncId, src: &[lir::Value], buf: &mut Vec<Value>) {
+        src.iter().enumerate().for_each(|(i, p)| {
+            let exp = self.ctx.flayouts[id].params[key::Param(i as u32)].clone();
+            let vlayout = match (p, &exp) {
+                (lir::Value::FuncPtr(mfunc), Layout::Scalar(kind @ Scalar::FuncPointer(_), _)) => {
+                    let callback = self.ctx.callbacks[mfunc];
+                    let fref = self.ins().declare_func_in_func(callback);
+                    let size_t = self.ctx.size_t();
+                    let fptr = self.cins().func_addr(size_t, fref);
+                    Layout::Scalar(kind.clone(), fptr)
+                }
+                _ => self.value_to_vlayout(*p),
+            };
+            trace!("param{i} as {vlayout:?} for {exp:?}");
+            let unified = self.ins().make_compatible(&exp, vlayout);
+            self.ins().layout_into_raw_values(&unified, buf);
+        });
+    }
+
     fn copy_tail_rptr(&self, params: &mut Vec<Value>) -> Option<Value> {
         let mut out_pointer = None;
         self.ctx.flayouts[self.f.id]
@@ -163,7 +228,13 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
             let uses_current_stack =
                 self.has_references_to_current_stack(&self.ctx.flayouts[id].params.as_slice());
 
-            if uses_current_stack {
+            if mfunc == self.f.fkey && !uses_current_stack {
+                info!("performing a self tail call in {cname} as a loop");
+
+                self.fparams_from_funcid(false, id, cparams, &mut params);
+                let (header, _) = self.f.blockmap[lir::Block::entry()];
+                self.cins().jump(header, &params);
+            } else if uses_current_stack {
                 self.fparams_from_funcid(true, id, cparams, &mut params);
                 let fref = self.ins().declare_func_in_func(id);
                 self.cins().return_call(fref, &params);
@@ -100,6 +100,9 @@ impl<'f, 's, 'a> InstHelper<'f, 's, 'a> {
                     Layout::SpecialPointer(SpecialPointer::HeapStruct(*mk), ptr)
                 }
                 SpecialPointer::StackArray(_, _) => todo!("array repr"),
+                SpecialPointer::EmbeddedSumPayload { .. } => {
+                    panic!("embedded sum payload outside of memory")
+                }
             },
             Layout::AutoBoxed(ty, _) => {
                 let ptr = self.layout_to_autoboxed(&ty, &have);
@@ -178,12 +181,17 @@ impl<'f, 's, 'a> InstHelper<'f, 's, 'a> {
                 match kind {
                     // we have a sum payload represented as a pointer.
                     // memcpy the data stored behind that payload pointer into dst.
-                    SpecialPointer::StackSumPayload { .. }
-                    | SpecialPointer::HeapSumPayload { .. } => {
-                        // copy the sum payload pointer
-                        let flags = MemFlags::trusted();
-                        let size_t = self.size_t;
-                        let innerp = self.ins().load(size_t, flags, src, offset.0 as i32);
+                    &SpecialPointer::StackSumPayload { sum }
+                    | &SpecialPointer::HeapSumPayload { sum }
+                    | &SpecialPointer::EmbeddedSumPayload { sum } => {
+                        let innerp = if self.structs.has_embedded_payload(sum) {
+                            self.ptr_offset(src, offset)
+                        } else {
+                            // copy the sum payload pointer
+                            let flags = self.structs.mem_flags(sum);
+                            let size_t = self.size_t;
+                            self.ins().load(size_t, flags, src, offset.0 as i32)
+                        };
                         self.memcpy_struct(dst, innerp, size as u64, align as u8);
                         Layout::OutPointer(kind.clone(), dst)
                     }
@@ -207,12 +215,13 @@ impl<'f, 's, 'a> InstHelper<'f, 's, 'a> {
         mk: MonoTypeKey,
         fields: &Map<layout::Field, Layout<Type>>,
     ) -> Map<layout::Field, VLayout> {
+        let flags = self.structs.mem_flags(mk);
         fields
             .iter()
             .map(|(field, flayout)| {
                 let mut foffset = self.structs.offset_of(mk, field);
                 foffset.0 += offset.0;
-                self.get_field_from_structptr(out, flayout, ptr, foffset)
+                self.get_field_from_structptr(out, flayout, ptr, foffset, flags)
             })
             .collect()
     }
@@ -225,18 +234,15 @@ impl<'f, 's, 'a> InstHelper<'f, 's, 'a> {
         flayout: &Layout<Type>,
         ptr: Value,
         offset: ByteOffset,
+        flags: MemFlags,
     ) -> VLayout {
         match flayout {
             Layout::Scalar(kind, clty) => {
-                let v = self
-                    .ins()
-                    .load(*clty, MemFlags::trusted(), ptr, offset.0 as i32);
+                let v = self.ins().load(*clty, flags, ptr, offset.0 as i32);
                 VLayout::Scalar(kind.clone(), v)
             }
             Layout::AutoBoxed(ty, clty) => {
-                let v = self
-                    .ins()
-                    .load(*clty, MemFlags::trusted(), ptr, offset.0 as i32);
+                let v = self.ins().load(*clty, flags, ptr, offset.0 as i32);
                 VLayout::AutoBoxed(ty.clone(), v)
             }
             Layout::ZST => VLayout::ZST,
@@ -245,10 +251,15 @@ impl<'f, 's, 'a> InstHelper<'f, 's, 'a> {
                 let fields = self.get_fields_from_structptr(out, ptr, offset, *mk, fields);
                 Layout::StructFlat(*mk, fields)
             }
+            &Layout::SpecialPointer(
+                SpecialPointer::StackSumPayload { sum }
+                | SpecialPointer::EmbeddedSumPayload { sum },
+                _,
+            ) if self.structs.has_embedded_payload(sum) => {
+                self.copy_embedded_sum_payload(sum, ptr, offset)
+            }
             Layout::SpecialPointer(kind, clty) => {
-                let v = self
-                    .ins()
-                    .load(*clty, MemFlags::trusted(), ptr, offset.0 as i32);
+                let v = self.ins().load(*clty, flags, ptr, offset.0 as i32);
                 VLayout::SpecialPointer(kind.clone(), v)
             }
             Layout::OutPointer(kind, _) => {
@@ -257,6 +268,21 @@ impl<'f, 's, 'a> InstHelper<'f, 's, 'a> {
         }
     }
 
+    // The payload is copied out of the memory of the sum, so that it doesn't change if that
+    // memory is written to while the payload is still in use
+    fn copy_embedded_sum_payload(
+        &mut self,
+        sum: MonoTypeKey,
+        ptr: Value,
+        offset: ByteOffset,
+    ) -> VLayout {
+        let (size, align) = self.structs.sum_payload_size_and_align(sum);
+        let src = self.ptr_offset(ptr, offset);
+        let dst = self.stack_alloc(size, align as u8);
+        self.memcpy_struct(dst, src, size as u64, align as u8);
+        VLayout::SpecialPointer(SpecialPointer::StackSumPayload { sum }, dst)
+    }
+
     fn layout_to_struct_stack_pointer(&mut self, mk: MonoTypeKey, got: &VLayout) -> Value {
         match got {
             Layout::AutoBoxed(ty, ptr) => {
@@ -1,5 +1,5 @@
 use super::{
-    debuginfo, layout,
+    layout,
     layout::{ByteOffset, Layout, PassBy, Scalar, SpecialPointer, Stability, Structs},
     Context, FuncLayout,
 };
@@ -10,22 +10,28 @@ use cranelift::prelude::*;
 use cranelift_codegen::ir;
 use cranelift_module::{DataId, FuncId, Module};
 use lir::{MonoFunc, MonoType, MonoTypeKey};
-use lumina_typesystem::IntSize;
-use std::sync::Arc;
+use lumina_typesystem::{FloatSize, IntSize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
 
 mod array;
+mod atomic;
 mod call;
 mod layout_mapping;
 mod num;
 mod pointer;
 mod record;
+mod sanitize;
+mod slots;
 mod sum;
 
+pub(super) use slots::StackSlots;
+
 use num::binops_from_kind;
 
 #[derive(new)]
 pub struct Translator<'c, 'a, 'f> {
-    ctx: &'c mut Context<'a>,
+    ctx: &'c Context<'a>,
     f: Current<'a, 'f>,
 }
 
@@ -33,11 +39,17 @@ pub(super) type Predecessors = u16;
 
 #[derive(new)]
 struct Current<'a, 'f> {
-    func: &'a lir::Function,
+    func: &'f lir::Function,
     fkey: MonoFunc,
     id: FuncId,
     builder: FunctionBuilder<'f>,
     blockmap: Map<lir::Block, (Block, Predecessors)>,
+    // Whether the function tail calls itself, which is lowered as a jump back to the LIR entry block
+    loops_to_entry: bool,
+    slots: StackSlots,
+    // Parameters of the LIR entry block already appended by the prologue
+    #[new(default)]
+    entry_params: VecDeque<Value>,
     #[new(default)]
     imports: HashMap<FuncId, ir::FuncRef>,
     #[new(default)]
@@ -46,6 +58,9 @@ struct Current<'a, 'f> {
     block: lir::Block,
     #[new(default)]
     ret_pointer: Option<Value>,
+    // Stack slots of allocations and the offset of the redzone after their storage
+    #[new(default)]
+    redzones: Vec<(ir::StackSlot, u32)>,
 }
 
 type VLayout = Layout<Value>;
@@ -72,7 +87,10 @@ impl<'a, 'f> Current<'a, 'f> {
         layout.out_pointers(&mut |_, size_t| {
             assert_eq!(self.block, lir::Block::entry());
             let clblock = self.blockmap[self.block].0;
-            let v = self.builder.append_block_param(clblock, size_t);
+            let v = self
+                .entry_params
+                .pop_front()
+                .unwrap_or_else(|| self.builder.append_block_param(clblock, size_t));
             self.ret_pointer = Some(v);
         });
     }
@@ -89,8 +107,9 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
             structs: &self.ctx.structs,
             alloc: self.ctx.funcmap[self.ctx.lir.alloc],
             isa: self.ctx.isa.clone(),
-            objmodule: &mut self.ctx.objmodule,
+            objmodule: &self.ctx.objmodule,
             func_imports: &mut self.f.imports,
+            slots: &mut self.f.slots,
             builder: &mut self.f.builder,
         }
     }
@@ -100,16 +119,18 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
     }
 
     pub fn func(
-        ctx: &'c mut Context<'a>,
+        ctx: &'c Context<'a>,
         cctx: &mut codegen::Context,
         fctx: &mut FunctionBuilderContext,
-        func: &'a lir::Function,
+        func: &'f lir::Function,
         key: MonoFunc,
-    ) -> debuginfo::FunctionDebugContext {
+    ) {
         let id = ctx.funcmap[key];
 
         cctx.func.signature = ctx
             .objmodule
+            .lock()
+            .unwrap()
             .declarations()
             .get_function_decl(id)
             .signature
@@ -119,26 +140,42 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
         let blockmap = func
             .ssa
             .blocks()
-            .map(|_| (builder.create_block(), 0))
+            .map(|block| {
+                let clblock = builder.create_block();
+                if func.ssa.is_cold(block) {
+                    builder.set_cold_block(clblock);
+                }
+                (clblock, 0)
+            })
             .collect();
 
-        let f_dbg_ctx = ctx.def_function(key);
+        let loops_to_entry = func.ssa.iterv().any(
+            |v| matches!(func.ssa.entry_of(v), lir::Entry::JmpFunc(mfunc, _) if *mfunc == key),
+        );
+
+        let slots = StackSlots::new(&func.ssa, key);
 
-        Translator { ctx, f: Current::new(func, key, id, builder, blockmap) }
-            .lower_and_finalize_current();
+        Translator {
+            ctx,
+            f: Current::new(func, key, id, builder, blockmap, loops_to_entry, slots),
+        }
+        .lower_and_finalize_current();
 
         info!("lowered {}:\n {}", func.symbol, &cctx.func);
 
         if let Err(err) = cranelift_codegen::verify_function(&cctx.func, ctx.isa.as_ref()) {
             error!("cranelift_codegen verifier error:\n{err}");
         }
-
-        f_dbg_ctx
     }
 
     fn lower_and_finalize_current(mut self) {
-        self.seal_block_if_last_predecessor(self.f.block);
-        self.f.switch_to_block(self.f.block);
+        if self.f.loops_to_entry {
+            self.lower_prologue();
+        } else {
+            self.seal_block_if_last_predecessor(self.f.block);
+            self.f.switch_to_block(self.f.block);
+            self.bump_function_counter();
+        }
 
         self.f
             .append_rptr_param_if_needed(&self.ctx.flayouts[self.f.id].ret);
@@ -151,13 +188,57 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
                 self.f.switch_to_block(block);
             }
 
+            // Source locations refer back to the LIR value, whose location is looked up when
+            // emitting the line table
+            self.f.builder.set_srcloc(ir::SourceLoc::new(v.0));
+            self.f.slots.set_current(v);
+
             let vlayout = self.entry(v, entry, ty);
             self.f.vmap.push_as(v, vlayout);
         }
 
+        if self.f.loops_to_entry {
+            let (header, _) = self.f.blockmap[lir::Block::entry()];
+            self.f.builder.seal_block(header);
+        }
+
         self.f.builder.finalize();
     }
 
+    // Cranelift doesn't allow jumping to the entry block of a function, so functions which loop
+    // back to their LIR entry block instead start in a prologue which forwards its parameters.
+    fn lower_prologue(&mut self) {
+        let prologue = self.f.builder.create_block();
+        self.f
+            .builder
+            .append_block_params_for_function_params(prologue);
+        self.f.builder.switch_to_block(prologue);
+        self.f.builder.seal_block(prologue);
+
+        self.bump_function_counter();
+
+        let (header, _) = self.f.blockmap[lir::Block::entry()];
+        let params = self.f.builder.block_params(prologue).to_vec();
+        for &p in &params {
+            let ty = self.f.type_of_value(p);
+            let v = self.f.builder.append_block_param(header, ty);
+            self.f.entry_params.push_back(v);
+        }
+        self.cins().jump(header, &params);
+
+        self.seal_block_if_last_predecessor(lir::Block::entry());
+        self.f.switch_to_block(lir::Block::entry());
+    }
+
+    fn bump_function_counter(&mut self) {
+        if let Some(profile) = &self.ctx.profile {
+            let counter = profile.counters.functions[self.f.fkey];
+            let size_t = self.ctx.size_t();
+            let offset = self.cins().iconst(size_t, counter as i64 * 8);
+            self.bump_profile_counter(offset);
+        }
+    }
+
     fn declare_block_param(&mut self, block: lir::Block, _: u32, ty: &MonoType) -> VLayout {
         assert_eq!(self.f.block, block);
 
@@ -165,7 +246,10 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
 
         let layout = self.ctx.structs.type_to_layout(ty, Stability::F);
         layout.map_layout(
-            &mut |clty| self.f.builder.append_block_param(clblock, clty),
+            &mut |clty| match self.f.entry_params.pop_front() {
+                Some(v) => v,
+                None => self.f.builder.append_block_param(clblock, clty),
+            },
             &mut |_, _| panic!("out pointer without FRet stability"),
         )
     }
@@ -193,6 +277,7 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
             }
 
             lir::Entry::RefStaticVal(val) => self.ref_static_val(*val, ty),
+            lir::Entry::RefExternData(key) => self.ref_extern_data(*key, ty),
 
             lir::Entry::Construct(values) => match ty {
                 MonoType::Monomorphised(mk) => self.construct_record(*mk, values),
@@ -235,11 +320,17 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
 
             lir::Entry::BinOp(lir::BinOp::And, ints) => self.bit_and(*ints),
             lir::Entry::BinOp(lir::BinOp::Div, ints) => self.int_div(*ints, as_int(ty)),
+            lir::Entry::BinOp(lir::BinOp::Rem, ints) => self.int_rem(*ints, as_int(ty)),
             lir::Entry::BinOp(kind, values) => self.ibinary(ty, *values, binops_from_kind(*kind)),
             lir::Entry::IntAbs(v) => self.iunary(*v, as_int(ty), |ins, _, v| ins.iabs(v)),
-
-            lir::Entry::IntCmpInclusive(values, cmp, bitsize) => {
-                self.int_cmpi(*values, *cmp, *bitsize)
+            lir::Entry::IntAddCarry(values) => self.int_carry(ty, *values, lir::BinOp::Add),
+            lir::Entry::IntSubBorrow(values) => self.int_carry(ty, *values, lir::BinOp::Sub),
+            lir::Entry::IntMin(values, intsize) => self.int_min_max(*values, *intsize, false),
+            lir::Entry::IntMax(values, intsize) => self.int_min_max(*values, *intsize, true),
+            lir::Entry::SelectValue(values) => self.select_value(*values),
+
+            lir::Entry::IntCmpInclusive(values, cmp, intsize) => {
+                self.int_cmpi(*values, *cmp, *intsize)
             }
 
             lir::Entry::Reduce(v) => self.iunary(*v, as_int(ty), InstBuilder::ireduce),
@@ -247,7 +338,18 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
             lir::Entry::ExtendUnsigned(v) => self.iunary(*v, as_int(ty), InstBuilder::uextend),
 
             lir::Entry::FloatToInt(v, intsize) => self.float_to_int(*v, *intsize),
-            lir::Entry::IntToFloat(v, intsize) => self.int_to_float(*v, *intsize),
+            lir::Entry::IntToFloat(v, intsize) => self.int_to_float(*v, *intsize, as_float(ty)),
+            lir::Entry::FloatCast(v) => self.float_cast(*v, as_float(ty)),
+
+            lir::Entry::FloatAdd(values) => self.fbinary(*values, InstBuilder::fadd),
+            lir::Entry::FloatSub(values) => self.fbinary(*values, InstBuilder::fsub),
+            lir::Entry::FloatMul(values) => self.fbinary(*values, InstBuilder::fmul),
+            lir::Entry::FloatDiv(values) => self.fbinary(*values, InstBuilder::fdiv),
+            lir::Entry::FloatNeg(v) => {
+                let v = self.value_to_vlayout(*v).as_direct();
+                Layout::direct(self.cins().fneg(v))
+            }
+            lir::Entry::FloatCmp(values, cmp) => self.float_cmp(*values, *cmp),
 
             lir::Entry::BitNot(v) => self.bit_not(*v),
 
@@ -259,8 +361,15 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
                 VLayout::pointer((**innert).clone(), ptr)
             }
             lir::Entry::Alloca => {
-                let ptr = self.ins().stack_alloc_type(ty);
-                VLayout::pointer(ty.clone(), ptr)
+                let MonoType::Pointer(innert) = ty else {
+                    panic!("Alloca to non-pointer");
+                };
+                let ptr = if self.ctx.asan.is_some() {
+                    self.stack_alloc_with_redzone(innert)
+                } else {
+                    self.ins().stack_alloc_type(innert)
+                };
+                VLayout::pointer((**innert).clone(), ptr)
             }
             lir::Entry::Dealloc { .. } => {
                 panic!("dealloc should be removed");
@@ -268,21 +377,22 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
             lir::Entry::WritePtr { ptr, value } => {
                 let [ptr, value] = [*ptr, *value].map(|v| self.value_to_vlayout(v));
                 let ptr = ptr.as_pointer().1;
-                self.ins().write_vlayout_to_ptr(ptr, &value);
-                Layout::ZST
-            }
-            lir::Entry::MemCpy { dst, src, count } => {
-                let [dst, src] = [*dst, *src].map(|v| self.value_to_vlayout(v).as_pointer().1);
-                let count = self.value_to_vlayout(*count).as_direct();
-                let config = self.ctx.isa.frontend_config();
-                self.ins().builder.call_memcpy(config, dst, src, count);
+                self.ins()
+                    .write_vlayout_to_ptr(ptr, &value, MemFlags::trusted());
                 Layout::ZST
             }
+            lir::Entry::MemCpy { dst, src, count } => self.memcpy(*dst, *src, *count),
+            lir::Entry::MemSet { dst, byte, count } => self.memset(*dst, *byte, *count),
             lir::Entry::Deref(ptr) => {
                 let ptr = self.value_to_vlayout(*ptr);
                 let (ty, ptr) = ptr.as_pointer();
-                self.ins().deref_type(ptr, ByteOffset(0), ty)
+                self.ins()
+                    .deref_type(ptr, ByteOffset(0), ty, MemFlags::trusted())
             }
+            lir::Entry::AtomicLoad(ptr, _) => self.atomic_load(*ptr, ty),
+            lir::Entry::AtomicStore { ptr, value, .. } => self.atomic_store(*ptr, *value),
+            lir::Entry::AtomicCas(values, _) => self.atomic_cas(*values, ty),
+            lir::Entry::AtomicRmw(op, values, _) => self.atomic_rmw(*op, *values, ty),
 
             lir::Entry::JmpFunc(mfunc, params) => {
                 self.tail_call(*mfunc, params);
@@ -301,6 +411,12 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
                 self.cins().trap(*code);
                 VLayout::ZST
             }
+            lir::Entry::Breakpoint => {
+                if self.ctx.trap_on_panic {
+                    self.cins().debugtrap();
+                }
+                VLayout::ZST
+            }
             &lir::Entry::Return(v) => {
                 let entry = self.value_to_vlayout(v);
                 self.return_(false, entry);
@@ -309,6 +425,19 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
             lir::Entry::Select { value, on_true, on_false } => {
                 let int = self.value_to_vlayout(*value).as_direct();
 
+                let branch = self
+                    .ctx
+                    .profile
+                    .as_ref()
+                    .map(|profile| profile.counters.branches[&(self.f.fkey, v)]);
+                if let Some(counter) = branch {
+                    let size_t = self.ctx.size_t();
+                    let [on_true, on_false] =
+                        [counter, counter + 1].map(|c| self.cins().iconst(size_t, c as i64 * 8));
+                    let offset = self.cins().select(int, on_true, on_false);
+                    self.bump_profile_counter(offset);
+                }
+
                 let then_params = self.bparams(on_true);
                 let else_params = self.bparams(on_false);
 
@@ -383,7 +512,10 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
                 let n = self.cins().iconst(ty, n as i64);
                 Layout::direct(n)
             }
-            lir::Value::Float(_) => todo!(),
+            lir::Value::Float(n) => {
+                let n = self.cins().f64const(n);
+                Layout::direct(n)
+            }
         }
     }
 
@@ -426,13 +558,28 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
         }
     }
 
+    // Increment the counter at a byte offset into the counters of a binary instrumented with
+    // `--profile-generate`
+    fn bump_profile_counter(&mut self, offset: Value) {
+        let data = self.ctx.profile.as_ref().unwrap().data;
+        let base = self.ins().dataid_as_pointer(data);
+        let ptr = self.cins().iadd(base, offset);
+        let flags = MemFlags::trusted();
+        let count = self.cins().load(types::I64, flags, ptr, 0);
+        let count = self.cins().iadd_imm(count, 1);
+        self.cins().store(flags, count, ptr, 0);
+    }
+
     fn seal_block_if_last_predecessor(&mut self, block: lir::Block) {
         let (clblock, predecessors) = &mut self.f.blockmap[block];
         *predecessors += 1;
 
         debug_assert!(*predecessors <= self.f.func.ssa.predecessors(block));
 
-        if *predecessors == self.f.func.ssa.predecessors(block) {
+        // Self tail calls aren't counted as predecessors, so the entry block is sealed last
+        let loops_here = self.f.loops_to_entry && block == lir::Block::entry();
+
+        if *predecessors == self.f.func.ssa.predecessors(block) && !loops_here {
             self.f.builder.seal_block(*clblock);
         }
     }
@@ -447,6 +594,17 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
 
         Layout::pointer((**ty).clone(), ptr)
     }
+
+    fn ref_extern_data(&mut self, key: M<key::Func>, ty: &MonoType) -> VLayout {
+        let MonoType::Pointer(ty) = ty else {
+            panic!("ref_extern_data into non-pointer type");
+        };
+
+        let dataid = self.ctx.externdatamap[&key];
+        let ptr = self.ins().dataid_as_pointer(dataid);
+
+        Layout::pointer((**ty).clone(), ptr)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -463,14 +621,14 @@ pub(super) struct InstHelper<'f, 's, 'a> {
     size_t: Type,
     alloc: FuncId,
     isa: Arc<dyn isa::TargetIsa>,
-    objmodule: &'f mut cranelift_object::ObjectModule,
+    objmodule: &'f Mutex<super::CodegenModule>,
     func_imports: &'f mut HashMap<FuncId, ir::FuncRef>,
+    slots: &'f mut StackSlots,
 }
 
 impl<'f, 's, 'a> InstHelper<'f, 's, 'a> {
     pub fn create_struct_stack_slot(&mut self, size: u32, align: u8) -> ir::StackSlot {
-        let slotdata = StackSlotData::new(StackSlotKind::ExplicitSlot, size, align as u8);
-        self.builder.create_sized_stack_slot(slotdata)
+        self.slots.get_or_create(self.builder, size, align)
     }
 
     fn transmute(&mut self, v: Layout<Value>, ty: &MonoType) -> VLayout {
@@ -513,9 +671,8 @@ impl<'f, 's, 'a> InstHelper<'f, 's, 'a> {
                     unreachable!();
                 };
                 let clty = Type::int(isize.bits() as u16).unwrap();
-                let tag = self
-                    .ins()
-                    .load(clty, MemFlags::trusted(), ptr, offset.0 as i32);
+                let flags = self.structs.mem_flags(key);
+                let tag = self.ins().load(clty, flags, ptr, offset.0 as i32);
                 VLayout::direct(tag)
             }
             other => unreachable!("{other:?}"),
@@ -539,10 +696,16 @@ impl<'f, 's, 'a> InstHelper<'f, 's, 'a> {
     pub fn dataid_as_pointer(&mut self, dataid: DataId) -> Value {
         let data = self
             .objmodule
+            .lock()
+            .unwrap()
             .declare_data_in_func(dataid, &mut self.builder.func);
 
         let size_t = self.size_t;
-        self.ins().symbol_value(size_t, data)
+        match self.builder.func.global_values[data] {
+            // Thread-local data has to be found through the platform's TLS access sequence
+            ir::GlobalValueData::Symbol { tls: true, .. } => self.ins().tls_value(size_t, data),
+            _ => self.ins().symbol_value(size_t, data),
+        }
     }
 
     pub fn ins(&mut self) -> FuncInstBuilder<'_, 'a> {
@@ -556,6 +719,13 @@ impl<'f, 's, 'a> InstHelper<'f, 's, 'a> {
             .emit_small_memory_copy(config, dst, src, size, align, align, true, flags);
     }
 
+    fn memset_zero(&mut self, dst: Value, size: u64, align: u8) {
+        let config = self.isa.frontend_config();
+        let flags = MemFlags::trusted();
+        self.builder
+            .emit_small_memset(config, dst, 0, size, align, flags);
+    }
+
     fn memcpy_to_heap(&mut self, src: Value, size: u64, align: u8, check_null: bool) -> Value {
         if !check_null {
             return self.memcpy_to_heap_unchecked(src, size, align);
@@ -597,6 +767,8 @@ impl<'f, 's, 'a> InstHelper<'f, 's, 'a> {
     fn declare_func_in_func(&mut self, id: FuncId) -> ir::FuncRef {
         *self.func_imports.entry(id).or_insert_with(|| {
             self.objmodule
+                .lock()
+                .unwrap()
                 .declare_func_in_func(id, &mut self.builder.func)
         })
     }
@@ -629,3 +801,10 @@ fn as_int(ty: &MonoType) -> IntSize {
         _ => unreachable!(),
     }
 }
+
+fn as_float(ty: &MonoType) -> FloatSize {
+    match ty {
+        MonoType::Float(size) => *size,
+        _ => unreachable!(),
+    }
+}
@@ -13,13 +13,57 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
         Layout::direct(v)
     }
 
-    pub(super) fn int_to_float(&mut self, n: lir::Value, intsize: IntSize) -> VLayout {
+    pub(super) fn int_to_float(
+        &mut self,
+        n: lir::Value,
+        intsize: IntSize,
+        to: FloatSize,
+    ) -> VLayout {
         let v = self.value_to_vlayout(n).as_direct();
+        let fty = layout::float_type(to);
         let v = if intsize.signed {
-            self.cins().fcvt_from_sint(types::F64, v)
+            self.cins().fcvt_from_sint(fty, v)
         } else {
-            self.cins().fcvt_from_uint(types::F64, v)
+            self.cins().fcvt_from_uint(fty, v)
+        };
+        Layout::direct(v)
+    }
+
+    pub(super) fn float_cast(&mut self, n: lir::Value, to: FloatSize) -> VLayout {
+        let v = self.value_to_vlayout(n).as_direct();
+        let fty = layout::float_type(to);
+        let has = self.f.type_of_value(v);
+        let v = if has == fty {
+            v
+        } else if has.bits() < fty.bits() {
+            self.cins().fpromote(fty, v)
+        } else {
+            self.cins().fdemote(fty, v)
+        };
+        Layout::direct(v)
+    }
+
+    pub(super) fn fbinary<'b, F>(&'b mut self, [left, right]: [lir::Value; 2], f: F) -> VLayout
+    where
+        F: FnOnce(FuncInstBuilder<'b, 'f>, Value, Value) -> Value + 'b,
+    {
+        let [left, right] = [left, right].map(|v| self.value_to_vlayout(v).as_direct());
+        let v = f(self.cins(), left, right);
+        Layout::direct(v)
+    }
+
+    pub(super) fn float_cmp(&mut self, [left, right]: [lir::Value; 2], cmp: Ordering) -> VLayout {
+        let [left, right] = [left, right].map(|v| self.value_to_vlayout(v).as_direct());
+
+        // Ordered comparisons, so that any comparison involving NaN is false
+        let floatcc = match cmp {
+            Ordering::Equal => FloatCC::Equal,
+            Ordering::Less => FloatCC::LessThan,
+            Ordering::Greater => FloatCC::GreaterThan,
         };
+
+        let v = self.cins().fcmp(floatcc, left, right);
+
         Layout::direct(v)
     }
 
@@ -59,17 +103,19 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
         &mut self,
         [left, right]: [lir::Value; 2],
         cmp: Ordering,
-        bitsize: IntSize,
+        intsize: IntSize,
     ) -> VLayout {
         let [left, right] = [left, right].map(|v| self.value_to_vlayout(v).as_scalar());
-        let intty = Type::int(bitsize.bits() as u16).unwrap();
+        let intty = Type::int(intsize.bits() as u16).unwrap();
         assert_eq!(self.f.type_of_value(left), intty);
         assert_eq!(self.f.type_of_value(right), intty);
 
-        let intcc = match cmp {
-            std::cmp::Ordering::Less => IntCC::SignedLessThan,
-            std::cmp::Ordering::Equal => IntCC::Equal,
-            std::cmp::Ordering::Greater => IntCC::SignedGreaterThan,
+        let intcc = match (cmp, intsize.signed) {
+            (Ordering::Equal, _) => IntCC::Equal,
+            (Ordering::Less, true) => IntCC::SignedLessThan,
+            (Ordering::Less, false) => IntCC::UnsignedLessThan,
+            (Ordering::Greater, true) => IntCC::SignedGreaterThan,
+            (Ordering::Greater, false) => IntCC::UnsignedGreaterThan,
         };
 
         let v = self.cins().icmp(intcc, left, right);
@@ -77,6 +123,36 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
         Layout::direct(v)
     }
 
+    pub(super) fn int_min_max(
+        &mut self,
+        [left, right]: [lir::Value; 2],
+        intsize: IntSize,
+        max: bool,
+    ) -> VLayout {
+        let [left, right] = [left, right].map(|v| self.value_to_vlayout(v).as_scalar());
+        let v = match (max, intsize.signed) {
+            (false, true) => self.cins().smin(left, right),
+            (false, false) => self.cins().umin(left, right),
+            (true, true) => self.cins().smax(left, right),
+            (true, false) => self.cins().umax(left, right),
+        };
+        Layout::direct(v)
+    }
+
+    pub(super) fn select_value(&mut self, [cond, on_true, on_false]: [lir::Value; 3]) -> VLayout {
+        let cond = self.value_to_vlayout(cond).as_scalar();
+        let [on_true, on_false] = [on_true, on_false].map(|v| self.value_to_vlayout(v));
+        let v = self
+            .cins()
+            .select(cond, on_true.as_scalar(), on_false.as_scalar());
+
+        // Aggregates are selected between with branches when lowering to LIR
+        let Layout::Scalar(kind, _) = on_true else {
+            panic!("select between non-scalar values");
+        };
+        Layout::Scalar(kind, v)
+    }
+
     pub(super) fn int_div(&mut self, [left, right]: [lir::Value; 2], intsize: IntSize) -> VLayout {
         let [left, right] = [left, right].map(|v| self.value_to_vlayout(v).as_scalar());
         let v = if intsize.signed {
@@ -87,6 +163,17 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
         Layout::direct(v)
     }
 
+    // The remainder has the sign of the dividend, same as C and Rust
+    pub(super) fn int_rem(&mut self, [left, right]: [lir::Value; 2], intsize: IntSize) -> VLayout {
+        let [left, right] = [left, right].map(|v| self.value_to_vlayout(v).as_scalar());
+        let v = if intsize.signed {
+            self.cins().srem(left, right)
+        } else {
+            self.cins().urem(left, right)
+        };
+        Layout::direct(v)
+    }
+
     pub(super) fn ibinary<'b>(
         &'b mut self,
         ty: &MonoType,
@@ -118,6 +205,48 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
             _ => panic!("invalid return signature for num binop: {ty:?}"),
         }
     }
+
+    // Cranelift's carry-in instructions aren't supported by all of its backends, so the carry is
+    // added in a second step and either of the two overflowing sets the carry out
+    pub(super) fn int_carry(
+        &mut self,
+        ty: &MonoType,
+        [left, right, carry]: [lir::Value; 3],
+        op: lir::BinOp,
+    ) -> VLayout {
+        let MonoType::Monomorphised(mk) = ty else {
+            panic!("invalid return signature for carry arithmetic: {ty:?}");
+        };
+        let signed = as_int(&self.types()[*mk].as_record()[key::Field(0)]).signed;
+
+        let [left, right, carry] =
+            [left, right, carry].map(|v| self.value_to_vlayout(v).as_scalar());
+        let intty = self.f.type_of_value(left);
+        let carry = self.resize_uint(carry, intty);
+
+        let (n, c0) = overflowing(self.cins(), op, signed, left, right);
+        let (n, c1) = overflowing(self.cins(), op, signed, n, carry);
+        let c = self.cins().bor(c0, c1);
+
+        let fields = [n, c].into_iter().map(Layout::direct).collect();
+        Layout::StructFlat(*mk, fields)
+    }
+}
+
+fn overflowing(
+    ins: FuncInstBuilder,
+    op: lir::BinOp,
+    signed: bool,
+    x: Value,
+    y: Value,
+) -> (Value, Value) {
+    match (op, signed) {
+        (lir::BinOp::Add, true) => ins.sadd_overflow(x, y),
+        (lir::BinOp::Add, false) => ins.uadd_overflow(x, y),
+        (lir::BinOp::Sub, true) => ins.ssub_overflow(x, y),
+        (lir::BinOp::Sub, false) => ins.usub_overflow(x, y),
+        _ => unreachable!(),
+    }
 }
 
 pub fn binops_from_kind<'b, 'f>(op: lir::BinOp) -> BinOpFuncs<'b, 'f> {
@@ -127,7 +256,7 @@ pub fn binops_from_kind<'b, 'f>(op: lir::BinOp) -> BinOpFuncs<'b, 'f> {
         lir::BinOp::Add => BinOpFuncs::new(F::sadd_overflow, F::uadd_overflow, F::iadd),
         lir::BinOp::Sub => BinOpFuncs::new(F::ssub_overflow, F::usub_overflow, F::isub),
         lir::BinOp::Mul => BinOpFuncs::new(F::smul_overflow, F::umul_overflow, F::imul),
-        lir::BinOp::Div => unreachable!(),
+        lir::BinOp::Div | lir::BinOp::Rem => unreachable!(),
         lir::BinOp::And => unreachable!(),
     }
 }
@@ -40,9 +40,15 @@ impl<'a, 's, 'f> InstHelper<'a, 's, 'f> {
         self.ins().stack_addr(size_t, slot, 0)
     }
 
-    pub(super) fn deref_type(&mut self, ptr: Value, offset: ByteOffset, ty: &MonoType) -> VLayout {
+    pub(super) fn deref_type(
+        &mut self,
+        ptr: Value,
+        offset: ByteOffset,
+        ty: &MonoType,
+        flags: MemFlags,
+    ) -> VLayout {
         let layout = self.structs.type_to_layout(ty, Stability::S);
-        self.deref(ptr, offset, layout)
+        self.deref(ptr, offset, layout, flags)
     }
 
     /// Reads `layout` at `offset` from `ptr`
@@ -51,6 +57,7 @@ impl<'a, 's, 'f> InstHelper<'a, 's, 'f> {
         ptr: Value,
         offset: ByteOffset,
         layout: Layout<Type>,
+        flags: MemFlags,
     ) -> VLayout {
         match layout {
             Layout::AutoBoxed(_, _) => panic!("???"),
@@ -79,9 +86,7 @@ impl<'a, 's, 'f> InstHelper<'a, 's, 'f> {
             Layout::ZST => Layout::ZST,
             Layout::OutPointer(..) => panic!("cant read from OutPointer"),
             Layout::Scalar(kind, clty) => {
-                let v = self
-                    .ins()
-                    .load(clty, MemFlags::trusted(), ptr, offset.0 as i32);
+                let v = self.ins().load(clty, flags, ptr, offset.0 as i32);
                 Layout::Scalar(kind.clone(), v)
             }
         }
@@ -96,34 +101,53 @@ impl<'a, 's, 'f> InstHelper<'a, 's, 'f> {
     }
 
     // S_Stable write of layout to pointer
-    pub fn write_vlayout_to_ptr(&mut self, dst: Value, vlayout: &VLayout) {
+    pub fn write_vlayout_to_ptr(&mut self, dst: Value, vlayout: &VLayout, flags: MemFlags) {
+        // Copies into packed records can't rely on the alignment of the copied type
+        let copy_align = |align: u32| if flags.aligned() { align as u8 } else { 1 };
+
         match vlayout {
             Layout::SpecialPointer(kind, ptr) => match kind {
+                &SpecialPointer::StackSumPayload { sum }
+                | &SpecialPointer::HeapSumPayload { sum }
+                    if self.structs.has_embedded_payload(sum) =>
+                {
+                    let (size, align) = self.structs.sum_payload_size_and_align(sum);
+                    self.memcpy_struct(dst, *ptr, size as u64, copy_align(align));
+                }
                 &SpecialPointer::StackSumPayload { sum } => {
                     let largest = self.structs.sum_payload_alloca_size(sum);
                     let (tag_size, _, _) = self.structs.records[sum].as_sum();
                     let nptr = self.heaplift_sum_payload(*ptr, tag_size.bytes(), largest);
-                    self.ins().store(MemFlags::trusted(), nptr, dst, 0);
+                    self.ins().store(flags, nptr, dst, 0);
                 }
                 SpecialPointer::HeapSumPayload { .. } => {
-                    self.ins().store(MemFlags::trusted(), *ptr, dst, 0);
+                    self.ins().store(flags, *ptr, dst, 0);
                 }
                 SpecialPointer::HeapStruct(key) | SpecialPointer::StackStruct(key) => {
                     let (size, align) = self.structs.size_and_align_of_mk(*key);
-                    self.memcpy_struct(dst, *ptr, size as u64, align as u8);
+                    // The copied record may itself be stored inside of a packed record
+                    let align = if self.structs.mem_flags(*key).aligned() {
+                        copy_align(align)
+                    } else {
+                        1
+                    };
+                    self.memcpy_struct(dst, *ptr, size as u64, align);
                 }
                 SpecialPointer::StackArray(inner, len) => {
                     let (size, _, align) = self.structs.size_and_align_of_array(inner, *len);
-                    self.memcpy_struct(dst, *ptr, size as u64, align as u8)
+                    self.memcpy_struct(dst, *ptr, size as u64, copy_align(align))
+                }
+                SpecialPointer::EmbeddedSumPayload { .. } => {
+                    panic!("embedded sum payload outside of memory")
                 }
             },
 
             Layout::StructFlat(key, flat) => self.write_fields_to_structptr(*key, &flat, dst),
-            Layout::ArrayFlat(inner, flat) => self.write_elems_to_arrayptr(inner, flat, dst),
+            Layout::ArrayFlat(inner, flat) => self.write_elems_to_arrayptr(inner, flat, dst, flags),
 
             Layout::ZST => {}
             Layout::Scalar(_, v) => {
-                self.ins().store(MemFlags::trusted(), *v, dst, 0);
+                self.ins().store(flags, *v, dst, 0);
             }
 
             Layout::AutoBoxed(_, _) => todo!("memcpy underlying type of autoboxed value"),
@@ -136,6 +160,7 @@ impl<'a, 's, 'f> InstHelper<'a, 's, 'f> {
         inner: &MonoType,
         flat: &[VLayout],
         ptr: Value,
+        flags: MemFlags,
     ) {
         let (_, elem_size, align) = self
             .structs
@@ -145,7 +170,7 @@ impl<'a, 's, 'f> InstHelper<'a, 's, 'f> {
 
         for flayout in flat {
             let ptr = self.ptr_offset(ptr, ByteOffset(offset));
-            self.write_vlayout_to_ptr(ptr, flayout);
+            self.write_vlayout_to_ptr(ptr, flayout, flags);
             let padding = (align - offset % align) % align;
             offset += elem_size + padding;
         }
@@ -157,10 +182,105 @@ impl<'a, 's, 'f> InstHelper<'a, 's, 'f> {
         fields: &Map<layout::Field, VLayout>,
         ptr: Value,
     ) {
-        for (field, flayout) in fields.iter() {
+        // The tag of a sum type is always written last, so that a tag is never observed
+        // together with the payload of a previous value
+        let tag = match &self.structs.records[key] {
+            lir::MonoTypeData::Sum { .. } => {
+                let tag = self.structs.get_real_field(key, key::Field(0));
+                self.verify_sum_tag_placement(key, tag);
+                Some(tag)
+            }
+            _ => None,
+        };
+
+        let payload = fields.iter().filter(|(field, _)| Some(*field) != tag);
+        let tag = tag.map(|tag| (tag, &fields[tag]));
+
+        let flags = self.structs.mem_flags(key);
+        for (field, flayout) in payload.chain(tag) {
             let offset = self.structs.offset_of(key, field);
             let ptr = self.ptr_offset(ptr, offset);
-            self.write_vlayout_to_ptr(ptr, flayout);
+            self.write_vlayout_to_ptr(ptr, flayout, flags);
         }
     }
+
+    /// Writing the payload may not clobber the tag, as it's already been written by the time a
+    /// payload would be observed
+    fn verify_sum_tag_placement(&self, key: MonoTypeKey, tag: layout::Field) {
+        let struct_ = self.structs.get(key);
+        let span = |field| {
+            let start = self.structs.offset_of(key, field).0;
+            let (size, _) = self.structs.size_and_align_of_field(&struct_.fields[field]);
+            start..start + size
+        };
+
+        let tag_span = span(tag);
+        for payload in struct_.fields.keys().filter(|field| *field != tag) {
+            let payload_span = span(payload);
+            assert!(
+                payload_span.end <= tag_span.start || payload_span.start >= tag_span.end,
+                "payload of {key} overlaps with its tag"
+            );
+        }
+    }
+}
+
+impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
+    // Copies of a constant size are inlined as loads and stores if they're small enough, falling
+    // back to calling `memcpy` otherwise
+    pub(super) fn memcpy(
+        &mut self,
+        dst: lir::Value,
+        src: lir::Value,
+        count: lir::Value,
+    ) -> VLayout {
+        let dst = self.value_to_vlayout(dst);
+        let (ty, dst) = dst.as_pointer();
+        let align = self.ctx.structs.size_and_align_of(ty).1.max(1) as u8;
+        let src = self.value_to_vlayout(src).as_pointer().1;
+        let config = self.ctx.isa.frontend_config();
+
+        match count {
+            lir::Value::Int(n, _) => {
+                let flags = MemFlags::trusted();
+                self.f
+                    .builder
+                    .emit_small_memory_copy(config, dst, src, n as u64, align, align, true, flags);
+            }
+            _ => {
+                let count = self.value_to_vlayout(count).as_direct();
+                self.f.builder.call_memcpy(config, dst, src, count);
+            }
+        }
+
+        Layout::ZST
+    }
+
+    pub(super) fn memset(
+        &mut self,
+        dst: lir::Value,
+        byte: lir::Value,
+        count: lir::Value,
+    ) -> VLayout {
+        let dst = self.value_to_vlayout(dst);
+        let (ty, dst) = dst.as_pointer();
+        let align = self.ctx.structs.size_and_align_of(ty).1.max(1) as u8;
+        let config = self.ctx.isa.frontend_config();
+
+        match (byte, count) {
+            (lir::Value::Int(byte, _), lir::Value::Int(n, _)) => {
+                let flags = MemFlags::trusted();
+                self.f
+                    .builder
+                    .emit_small_memset(config, dst, byte as u8, n as u64, align, flags);
+            }
+            _ => {
+                let byte = self.value_to_vlayout(byte).as_direct();
+                let count = self.value_to_vlayout(count).as_direct();
+                self.f.builder.call_memset(config, dst, byte, count);
+            }
+        }
+
+        Layout::ZST
+    }
 }
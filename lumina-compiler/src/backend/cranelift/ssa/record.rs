@@ -28,19 +28,27 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
 
     pub(super) fn construct_record_on_stack(
         &mut self,
-        size: Option<u32>,
+        size_and_align: Option<(u32, u32)>,
         key: MonoTypeKey,
         values: &[lir::Value],
     ) -> Value {
-        let (ssize, align) = self.ctx.structs.size_and_align_of(&key.into());
-        let size = match size {
-            Some(size) if ssize > size => panic!("alloca to small for {key}"),
-            Some(size) => size,
-            None => ssize,
+        let (ssize, salign) = self.ctx.structs.size_and_align_of(&key.into());
+        let (size, align) = match size_and_align {
+            Some((size, _)) if ssize > size => panic!("alloca to small for {key}"),
+            Some((size, align)) => (size, align.max(salign)),
+            None => (ssize, salign),
         };
 
         let slot = self.ins().create_struct_stack_slot(size, align as u8);
         let size_t = self.ctx.size_t();
+        let flags = self.ctx.structs.mem_flags(key);
+
+        // Padding and the bytes of a sum payload past the smaller variants are otherwise left
+        // uninitialised, which memcheck reports once they're copied into a syscall or branched on
+        if self.ctx.debug_memory {
+            let ptr = self.cins().stack_addr(size_t, slot, 0);
+            self.ins().memset_zero(ptr, size as u64, align as u8);
+        }
 
         for (field, value) in values.iter().enumerate() {
             let field = key::Field(field as u32);
@@ -51,7 +59,7 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
 
             let slot_addr = self.cins().stack_addr(size_t, slot, offset.0 as i32);
 
-            self.ins().write_vlayout_to_ptr(slot_addr, &vlayout);
+            self.ins().write_vlayout_to_ptr(slot_addr, &vlayout, flags);
         }
 
         self.cins().stack_addr(size_t, slot, 0)
@@ -103,6 +111,7 @@ impl<'f, 's, 'a> InstHelper<'f, 's, 'a> {
         let offset = self.structs.offset_of(mk, rfield);
 
         let fty = &self.structs.records[mk].as_record()[field].clone();
-        self.deref_type(ptr, offset, fty)
+        let flags = self.structs.mem_flags(mk);
+        self.deref_type(ptr, offset, fty, flags)
     }
 }
@@ -0,0 +1,43 @@
+//! AddressSanitizer instrumentation
+//!
+//! Heap memory is already checked by the ASan runtime, which intercepts `malloc` and `free`. Stack
+//! allocations are instead given a redzone after their storage, which is poisoned while extern
+//! functions run so that foreign code writing past the end of a buffer it was given is reported.
+//!
+//! Only the redzones of stack allocations lowered before an extern call are poisoned around it.
+
+use super::*;
+
+const REDZONE: u32 = 32;
+
+// Shadow memory tracks whether memory is addressable in granules of this many bytes
+const GRANULE: u32 = 8;
+
+impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
+    pub(super) fn stack_alloc_with_redzone(&mut self, ty: &MonoType) -> Value {
+        let (size, align) = self.ctx.structs.size_and_align_of(ty);
+        let end = size.next_multiple_of(GRANULE);
+        let align = (align as u8).max(GRANULE as u8);
+
+        let slot = self.ins().create_struct_stack_slot(end + REDZONE, align);
+        self.f.redzones.push((slot, end));
+
+        let size_t = self.ctx.size_t();
+        self.cins().stack_addr(size_t, slot, 0)
+    }
+
+    pub(super) fn poison_redzones(&mut self, poison: bool) {
+        let Some(asan) = &self.ctx.asan else {
+            return;
+        };
+        let id = if poison { asan.poison } else { asan.unpoison };
+        let size_t = self.ctx.size_t();
+
+        for (slot, offset) in self.f.redzones.clone() {
+            let fref = self.ins().declare_func_in_func(id);
+            let addr = self.cins().stack_addr(size_t, slot, offset as i32);
+            let len = self.cins().iconst(size_t, REDZONE as i64);
+            self.cins().call(fref, &[addr, len]);
+        }
+    }
+}
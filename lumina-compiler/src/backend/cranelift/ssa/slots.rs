@@ -0,0 +1,286 @@
+//! Sharing of stack slots between temporaries
+//!
+//! Records passed by pointer, arrays and the out pointers of calls are each given a stack slot.
+//! Functions constructing many such temporaries would end up with frames large enough to hold all
+//! of them at once, so slots are instead reused by temporaries which are never live at the same
+//! time.
+//!
+//! Values are grouped together with the values which may point into their storage, such as the
+//! fields of a record or the block parameters it's passed to. A slot is given to a group if none
+//! of the groups it was previously given to are live at the same time as it.
+
+use super::*;
+use std::collections::HashSet;
+
+// Closed ranges of values in which a group is live
+//
+// The values of a block are contiguous, so ranges from different blocks never overlap.
+type Lifetime = Vec<(u32, u32)>;
+
+#[derive(Default)]
+pub struct StackSlots {
+    // The value representing the group of each value
+    groups: Map<lir::V, lir::V>,
+    lifetimes: HashMap<lir::V, Lifetime>,
+    slots: Vec<Slot>,
+    // The value being lowered, whose group owns the slots created while lowering it
+    current: Option<lir::V>,
+}
+
+struct Slot {
+    slot: ir::StackSlot,
+    size: u32,
+    align: u8,
+    owners: Vec<lir::V>,
+}
+
+struct BlockLiveness {
+    start: u32,
+    end: u32,
+    successors: Vec<lir::Block>,
+    // Aggregates defined in other blocks which are used in this block
+    exposed: HashSet<lir::V>,
+    last_use: HashMap<lir::V, u32>,
+    live_in: HashSet<lir::V>,
+    live_out: HashSet<lir::V>,
+}
+
+impl StackSlots {
+    pub fn new(ssa: &lir::SSA, fkey: MonoFunc) -> Self {
+        let groups = group_aliasing_values(ssa, fkey);
+
+        let mut lifetimes = HashMap::<lir::V, Lifetime>::new();
+        for (v, range) in live_ranges(ssa, fkey) {
+            lifetimes.entry(groups[v]).or_default().push(range);
+        }
+
+        StackSlots { groups, lifetimes, slots: vec![], current: None }
+    }
+
+    pub fn set_current(&mut self, v: lir::V) {
+        self.current = Some(v);
+    }
+
+    pub fn get_or_create(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        size: u32,
+        align: u8,
+    ) -> ir::StackSlot {
+        let Some(v) = self.current else {
+            let slotdata =
+                StackSlotData::new(StackSlotKind::ExplicitSlot, size, align_shift(align));
+            return builder.create_sized_stack_slot(slotdata);
+        };
+
+        let group = self.groups[v];
+        let lifetime = &self.lifetimes[&group];
+
+        let free = self
+            .slots
+            .iter_mut()
+            .filter(|slot| {
+                slot.owners
+                    .iter()
+                    .all(|owner| !overlaps(&self.lifetimes[owner], lifetime))
+            })
+            // Prefer the slot closest in size which doesn't need to grow
+            .min_by_key(|slot| (slot.size < size, slot.size.abs_diff(size)));
+
+        match free {
+            Some(slot) => {
+                trace!("reusing {} for {v}", slot.slot);
+
+                if slot.size < size || slot.align < align {
+                    slot.size = slot.size.max(size);
+                    slot.align = slot.align.max(align);
+                    let data = &mut builder.func.sized_stack_slots[slot.slot];
+                    data.size = slot.size;
+                    data.align_shift = align_shift(slot.align);
+                }
+
+                slot.owners.push(group);
+                slot.slot
+            }
+            None => {
+                let slotdata =
+                    StackSlotData::new(StackSlotKind::ExplicitSlot, size, align_shift(align));
+                let slot = builder.create_sized_stack_slot(slotdata);
+                self.slots
+                    .push(Slot { slot, size, align, owners: vec![group] });
+                slot
+            }
+        }
+    }
+}
+
+// Stack slots are aligned to a power of two given by its exponent
+fn align_shift(align: u8) -> u8 {
+    align.max(1).trailing_zeros() as u8
+}
+
+fn overlaps(a: &Lifetime, b: &Lifetime) -> bool {
+    a.iter().any(|&(astart, aend)| {
+        b.iter()
+            .any(|&(bstart, bend)| astart <= bend && bstart <= aend)
+    })
+}
+
+// Records and arrays are the only values which may be stored behind a pointer to a stack slot
+fn is_aggregate(ty: &MonoType) -> bool {
+    matches!(ty, MonoType::Monomorphised(_) | MonoType::Array(..))
+}
+
+fn successors(tail: &lir::Entry, fkey: MonoFunc) -> Vec<lir::Block> {
+    match tail {
+        lir::Entry::JmpBlock(jump) => vec![jump.id],
+        lir::Entry::Select { on_true, on_false, .. } => vec![on_true.id, on_false.id],
+        lir::Entry::JmpTable(_, blocks) => blocks.clone(),
+        // Tail calls to the function itself are lowered as jumps back to its entry block
+        lir::Entry::JmpFunc(mfunc, _) if *mfunc == fkey => vec![lir::Block::entry()],
+        _ => vec![],
+    }
+}
+
+fn find(groups: &mut Map<lir::V, lir::V>, v: lir::V) -> lir::V {
+    let mut root = v;
+    while groups[root] != root {
+        root = groups[root];
+    }
+    groups[v] = root;
+    root
+}
+
+fn union(groups: &mut Map<lir::V, lir::V>, a: lir::V, b: lir::V) {
+    let (a, b) = (find(groups, a), find(groups, b));
+    groups[b] = a;
+}
+
+// Put values which may point into the storage of an aggregate in the same group as it
+fn group_aliasing_values(ssa: &lir::SSA, fkey: MonoFunc) -> Map<lir::V, lir::V> {
+    let mut groups: Map<lir::V, lir::V> = ssa.iterv().collect();
+
+    for v in ssa.iterv() {
+        let entry = ssa.entry_of(v);
+
+        match entry {
+            // Aggregates are copied when passed to calls, returned or written to pointers
+            lir::Entry::CallStatic(..)
+            | lir::Entry::CallExtern(..)
+            | lir::Entry::CallValue(..)
+            | lir::Entry::Return(_)
+            | lir::Entry::WritePtr { .. } => continue,
+            _ => {}
+        }
+
+        ssa.for_each_use(v, &mut |used| {
+            if is_aggregate(ssa.type_of(used)) {
+                union(&mut groups, v, used);
+            }
+        });
+
+        for block in successors(entry, fkey) {
+            for param in ssa.block_params(block) {
+                if is_aggregate(ssa.type_of(param)) {
+                    union(&mut groups, v, param);
+                }
+            }
+        }
+    }
+
+    ssa.iterv().map(|v| find(&mut groups, v)).collect()
+}
+
+// The ranges in which each value is live
+//
+// Only aggregates can be referred to after the entry defining them has been lowered, every other
+// value is only considered live at its own definition.
+fn live_ranges(ssa: &lir::SSA, fkey: MonoFunc) -> Vec<(lir::V, (u32, u32))> {
+    let mut blocks = vec![];
+    let mut indices = HashMap::new();
+
+    for v in ssa.iterv() {
+        let Some((block, info)) = ssa.as_block_start(v) else {
+            continue;
+        };
+
+        let mut liveness = BlockLiveness {
+            start: info.start.0,
+            end: info.end.0,
+            successors: successors(info.tail, fkey),
+            exposed: HashSet::new(),
+            last_use: HashMap::new(),
+            live_in: HashSet::new(),
+            live_out: HashSet::new(),
+        };
+
+        for user in (liveness.start..=liveness.end).map(lir::V) {
+            ssa.for_each_use(user, &mut |used| {
+                if is_aggregate(ssa.type_of(used)) {
+                    liveness.last_use.insert(used, user.0);
+                    if !(liveness.start..=liveness.end).contains(&used.0) {
+                        liveness.exposed.insert(used);
+                    }
+                }
+            });
+        }
+
+        indices.insert(block, blocks.len());
+        blocks.push(liveness);
+    }
+
+    // Blocks mostly jump forwards, so visiting them backwards settles most functions in one pass
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for i in (0..blocks.len()).rev() {
+            let live_out = blocks[i]
+                .successors
+                .iter()
+                .flat_map(|block| blocks[indices[block]].live_in.iter().copied())
+                .collect::<HashSet<_>>();
+
+            let b = &mut blocks[i];
+            let defined = b.start..=b.end;
+            let live_in = live_out
+                .iter()
+                .copied()
+                .filter(|v| !defined.contains(&v.0))
+                .chain(b.exposed.iter().copied())
+                .collect::<HashSet<_>>();
+
+            changed |= live_in.len() != b.live_in.len();
+            b.live_in = live_in;
+            b.live_out = live_out;
+        }
+    }
+
+    let mut ranges = ssa.iterv().map(|v| (v, (v.0, v.0))).collect::<Vec<_>>();
+
+    for b in &blocks {
+        let defined = (b.start..=b.end)
+            .map(lir::V)
+            .filter(|&v| is_aggregate(ssa.type_of(v)));
+
+        for v in b.live_in.iter().copied().chain(defined) {
+            let start = if b.live_in.contains(&v) { b.start } else { v.0 };
+            let end = if b.live_out.contains(&v) {
+                b.end
+            } else {
+                b.last_use.get(&v).map_or(start, |&last| last.max(start))
+            };
+            ranges.push((v, (start, end)));
+        }
+    }
+
+    // Pointers to stack allocations may be stored anywhere, so their slots are never shared
+    let last = ssa.iterv().last().map_or(0, |v| v.0);
+    for v in ssa.iterv() {
+        if matches!(ssa.entry_of(v), lir::Entry::Alloca) {
+            ranges.push((v, (0, last)));
+        }
+    }
+
+    ranges
+}
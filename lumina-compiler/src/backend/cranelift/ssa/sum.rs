@@ -24,10 +24,11 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
             [_tagi] => Layout::StructFlat(key, [tagfield].into()),
             [_tagi, payloadi] => {
                 let payload = match &sum_struct.fields[*payloadi] {
-                    &layout::StructField::SumPayloadPointer { sum } => {
-                        let largest = self.ctx.structs.sum_payload_alloca_size(sum);
+                    &layout::StructField::SumPayloadPointer { sum }
+                    | &layout::StructField::SumPayloadEmbedded { sum } => {
+                        let payload = self.ctx.structs.sum_payload_size_and_align(sum);
                         let ptr =
-                            self.construct_record_on_stack(Some(largest), param_tuple, values);
+                            self.construct_record_on_stack(Some(payload), param_tuple, values);
                         Layout::SpecialPointer(layout::SpecialPointer::StackSumPayload { sum }, ptr)
                     }
 
@@ -129,7 +130,7 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
 
                 let payi = self.ctx.structs.get_real_field(key, key::Field(1));
                 let poffset = self.ctx.structs.offset_of(key, payi);
-                let flags = MemFlags::trusted();
+                let flags = self.ctx.structs.mem_flags(key);
 
                 match &struct_.fields[payi] {
                     layout::StructField::SumPayloadPointer { .. } => {
@@ -145,6 +146,12 @@ impl<'c, 'a, 'f> Translator<'c, 'a, 'f> {
 
                         Layout::SpecialPointer(kind, payload_pointer)
                     }
+                    // Forwarded as a stack struct for the same reason as fields of structs
+                    layout::StructField::SumPayloadEmbedded { .. } => {
+                        let payload_pointer = self.ins().ptr_offset(ptr, poffset);
+                        let kind = SpecialPointer::StackStruct(requested.as_key());
+                        Layout::SpecialPointer(kind, payload_pointer)
+                    }
                     &layout::StructField::SumPayloadInline(clty) => {
                         let inline = self.cins().load(clty, flags, ptr, poffset.0 as i32);
                         let fields =
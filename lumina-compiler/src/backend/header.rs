@@ -0,0 +1,222 @@
+//! Generation of a C header declaring the `@[export]` functions of a library
+//!
+//! Exported functions are called with the platform's calling convention, so any parameter which
+//! is passed the same way as its C counterpart can be declared. Records and sums are laid out and
+//! passed as Lumina sees fit, and function pointers use the tail calling convention, so functions
+//! taking or returning them by value can't be declared.
+//!
+//! Pointers to records marked `@[repr "C"]` are declared as pointers to a matching `struct`, with
+//! its members taken from the same layout the Cranelift backend uses. Pointers to any other record
+//! are left as `void *` for C to treat as an opaque handle.
+
+use super::cranelift::layout::{StructField, Structs};
+use super::Error;
+use crate::lir::{self, MonoFormatter, MonoType, MonoTypeData, MonoTypeKey};
+use crate::prelude::*;
+use ast::attr::Repr;
+use lumina_typesystem::FloatSize;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+
+/// Declare every exported function of the program, with `name` used for the include guard
+pub fn c_header(lir: &lir::Output, name: &str) -> Result<String, Error> {
+    let guard = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect::<String>();
+
+    let mut header = Header {
+        lir,
+        structs: Structs::new(&lir.types),
+        typedefs: String::new(),
+        declared: HashMap::new(),
+        taken: HashSet::new(),
+    };
+
+    let mut declarations = String::new();
+
+    for func in lir.functions.values() {
+        let Some(symbol) = func.export else {
+            continue;
+        };
+
+        let unrepresentable = |what: String, ty: &MonoType, reason: String| {
+            let ty = MonoFormatter { types: &lir.types, funcs: None, v: ty };
+            Error::Codegen(format!(
+                "{what} of `{symbol}` is of type {ty}, which has no stable C representation: {reason}"
+            ))
+        };
+
+        let mut params = vec![];
+        for (i, ty) in func.ssa.func_param_types().enumerate() {
+            match header.c_type(ty) {
+                Ok(Some(cty)) => params.push(cty),
+                Ok(None) => {}
+                Err(reason) => return Err(unrepresentable(format!("parameter {i}"), ty, reason)),
+            }
+        }
+        let params = if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.join(", ")
+        };
+
+        let ret = match header.c_type(&func.returns) {
+            Ok(Some(cty)) => cty,
+            Ok(None) => "void".to_string(),
+            Err(reason) => {
+                return Err(unrepresentable(
+                    "the return value".into(),
+                    &func.returns,
+                    reason,
+                ))
+            }
+        };
+
+        writeln!(declarations, "{ret} {symbol}({params});").unwrap();
+    }
+
+    let mut out = String::new();
+    writeln!(out, "/* Generated by lumina, do not edit */").unwrap();
+    writeln!(out, "#ifndef {guard}_H").unwrap();
+    writeln!(out, "#define {guard}_H\n").unwrap();
+    writeln!(out, "#include <stdint.h>\n").unwrap();
+    writeln!(out, "#ifdef __cplusplus\nextern \"C\" {{\n#endif\n").unwrap();
+    out.push_str(&header.typedefs);
+    out.push_str(&declarations);
+    writeln!(out, "\n#ifdef __cplusplus\n}}\n#endif\n").unwrap();
+    writeln!(out, "#endif").unwrap();
+
+    Ok(out)
+}
+
+struct Header<'a> {
+    lir: &'a lir::Output,
+    structs: Structs<'a>,
+    typedefs: String,
+
+    // The C name given to each record, which is suffixed if multiple instances share a name
+    declared: HashMap<MonoTypeKey, String>,
+    taken: HashSet<String>,
+}
+
+impl<'a> Header<'a> {
+    // `Ok(None)` if it's zero-sized and therefore isn't passed at all
+    fn c_type(&mut self, ty: &MonoType) -> Result<Option<String>, String> {
+        match ty {
+            MonoType::Pointer(inner) => match &**inner {
+                MonoType::Int(_) | MonoType::Float(_) => {
+                    Ok(Some(format!("{} *", self.scalar(inner)?)))
+                }
+                MonoType::Monomorphised(key) if self.is_c_record(*key) => {
+                    Ok(Some(format!("{} *", self.record(*key)?)))
+                }
+                _ => Ok(Some("void *".into())),
+            },
+            MonoType::Const(_) => Ok(None),
+            MonoType::Monomorphised(key) if *key == lir::UNIT => Ok(None),
+            MonoType::Monomorphised(_) => Err(
+                "records and sums are passed as Lumina sees fit, pass a pointer to a record marked `@[repr \"C\"]` instead".into(),
+            ),
+            MonoType::FnPointer(..) => {
+                Err("function pointers use Lumina's own calling convention".into())
+            }
+            MonoType::Array(..) => Err("arrays can't be passed by value in C".into()),
+            MonoType::Unreachable => Err("the type is uninhabited".into()),
+            MonoType::Int(_) | MonoType::Float(_) => self.scalar(ty).map(Some),
+        }
+    }
+
+    fn scalar(&self, ty: &MonoType) -> Result<String, String> {
+        match ty {
+            MonoType::Int(intsize) => super::c::int(*intsize)
+                .map(str::to_string)
+                .map_err(|_| format!("C has no {intsize} integer")),
+            MonoType::Float(FloatSize::F32) => Ok("float".into()),
+            MonoType::Float(FloatSize::F64) => Ok("double".into()),
+            _ => unreachable!(),
+        }
+    }
+
+    fn is_c_record(&self, key: MonoTypeKey) -> bool {
+        matches!(
+            &self.lir.types[key],
+            MonoTypeData::Record { repr: Repr::C, key: Some(_), .. }
+        )
+    }
+
+    // Declares the struct the first time it's seen, any records embedded by value are declared
+    // before it
+    fn record(&mut self, key: MonoTypeKey) -> Result<String, String> {
+        if let Some(name) = self.declared.get(&key) {
+            return Ok(name.clone());
+        }
+
+        let lir = self.lir;
+        let MonoTypeData::Record { key: Some(rkey), .. } = &lir.types[key] else {
+            unreachable!();
+        };
+
+        let mut name = lir.record_names[*rkey].clone();
+        if !self.taken.insert(name.clone()) {
+            name = format!("{name}_{}", self.declared.len());
+            self.taken.insert(name.clone());
+        }
+
+        // Declared up-front so that self-referential pointers resolve to the same struct
+        writeln!(self.typedefs, "typedef struct {name} {name};").unwrap();
+        self.declared.insert(key, name.clone());
+
+        let struct_ = self.structs.get(key).clone();
+        let mut members = String::new();
+
+        for (field, sfield) in struct_.fields.iter() {
+            let (original, _) = struct_.field_map.iter().find(|(_, f)| *f == field).unwrap();
+            let fname = &lir.field_names[*rkey][original];
+            let offset = self.structs.offset_of(key, field).0;
+
+            let StructField::Flat(fty) = sfield else {
+                return Err(format!(
+                    "the field `{fname}` of `{name}` is recursive and therefore boxed"
+                ));
+            };
+
+            let Some(member) = self.member(fty, fname).map_err(|reason| {
+                format!("the field `{fname}` of `{name}` can't be declared: {reason}")
+            })?
+            else {
+                continue;
+            };
+
+            writeln!(members, "  {member}; /* offset {offset} */").unwrap();
+        }
+
+        let (size, align) = self.structs.size_and_align_of_mk(key);
+        writeln!(
+            self.typedefs,
+            "struct {name} {{ /* size {size}, align {align} */\n{members}}};\n"
+        )
+        .unwrap();
+
+        Ok(name)
+    }
+
+    fn member(&mut self, ty: &MonoType, fname: &str) -> Result<Option<String>, String> {
+        match ty {
+            MonoType::Monomorphised(key) if *key == lir::UNIT => Ok(None),
+            MonoType::Monomorphised(key) if self.is_c_record(*key) => {
+                Ok(Some(format!("{} {fname}", self.record(*key)?)))
+            }
+            MonoType::Array(len, inner) => Ok(self
+                .member(inner, fname)?
+                .map(|member| format!("{member}[{len}]"))),
+            _ => Ok(self.c_type(ty)?.map(|cty| match cty.strip_suffix('*') {
+                Some(pointee) => format!("{pointee}*{fname}"),
+                None => format!("{cty} {fname}"),
+            })),
+        }
+    }
+}
@@ -0,0 +1,957 @@
+//! Experimental LLVM backend, enabled with the `llvm` feature
+//!
+//! Only a subset of the LIR can be lowered so far. Functions operating on integers, floats and
+//! pointers along with calls and control flow are supported, while records, sums and vals are
+//! reported as unsupported rather than being miscompiled.
+
+use super::{Backend, Error};
+use crate::debuginfo::BinDebugInfo;
+use crate::lir::{
+    self, AtomicOrdering, AtomicRmwOp, BinOp, Block, BlockJump, Entry, MonoFunc, MonoType,
+    MonoTypeData, Value, V,
+};
+use crate::prelude::*;
+use crate::target::{Arch, LinuxPlatform, Platform};
+use crate::Target;
+use inkwell::attributes::AttributeLoc;
+use inkwell::basic_block::BasicBlock;
+use inkwell::builder::{Builder, BuilderError};
+use inkwell::context::Context;
+use inkwell::intrinsics::Intrinsic;
+use inkwell::module::{Linkage, Module};
+use inkwell::passes::PassBuilderOptions;
+use inkwell::targets::{
+    CodeModel, FileType, InitializationConfig, RelocMode, Target as LlvmTarget, TargetMachine,
+    TargetTriple,
+};
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType};
+use inkwell::values::{
+    BasicMetadataValueEnum, BasicValueEnum, FunctionValue, IntValue, PhiValue, PointerValue,
+};
+use inkwell::{
+    AddressSpace, AtomicRMWBinOp, FloatPredicate, GlobalVisibility, IntPredicate, OptimizationLevel,
+};
+use lumina_typesystem::FloatSize;
+use std::cmp::Ordering;
+
+/// `tailcc`, guarantees that calls marked as tail calls don't grow the stack
+const TAIL_CALL_CONV: u32 = 18;
+
+/// `ccc`, the only convention WebAssembly has
+const C_CALL_CONV: u32 = 0;
+
+#[derive(Default)]
+pub struct Llvm {
+    /// If set, the textual LLVM IR of the optimised module is written to it
+    pub ir: Option<String>,
+}
+
+impl From<BuilderError> for Error {
+    fn from(err: BuilderError) -> Self {
+        Error::Codegen(err.to_string())
+    }
+}
+
+impl Backend for Llvm {
+    fn compile(
+        &mut self,
+        target: Target,
+        _dwarf: BinDebugInfo,
+        mut lir: lir::Output,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        if !lir.val_initializers.is_empty() {
+            return Err(Error::Unsupported("declaring vals".into()));
+        }
+
+        let machine = target_machine(target)?;
+        let bodies = lir.take_bodies();
+
+        let context = Context::create();
+        let module = context.create_module("lumina");
+        module.set_triple(&machine.get_triple());
+        module.set_data_layout(&machine.get_target_data().get_data_layout());
+
+        let mut codegen = Codegen {
+            context: &context,
+            builder: context.create_builder(),
+            module,
+            lir: &lir,
+            target,
+            functions: Map::new(),
+            externs: HashMap::new(),
+            callbacks: HashMap::new(),
+        };
+
+        codegen.declare_functions()?;
+        codegen.declare_callbacks(&bodies)?;
+
+        for (mfunc, func) in bodies {
+            info!("lowering function {} to LLVM", func.symbol);
+            codegen.function(mfunc, &func)?;
+        }
+
+        codegen.declare_entrypoint()?;
+
+        codegen
+            .module
+            .verify()
+            .map_err(|err| Error::Codegen(err.to_string()))?;
+
+        codegen
+            .module
+            .run_passes("default<O3>", &machine, PassBuilderOptions::create())
+            .map_err(|err| Error::Codegen(err.to_string()))?;
+
+        if let Some(ir) = self.ir.as_mut() {
+            ir.push_str(&codegen.module.print_to_string().to_string());
+        }
+
+        machine
+            .write_to_memory_buffer(&codegen.module, FileType::Object)
+            .map(|buffer| vec![buffer.as_slice().to_vec()])
+            .map_err(|err| Error::Codegen(err.to_string()))
+    }
+}
+
+fn target_machine(target: Target) -> Result<TargetMachine, Error> {
+    let (triple, cpu) = match target {
+        Target {
+            arch: Arch::X86_64,
+            platform: Platform::Linux { sub: LinuxPlatform::Gnu },
+        } => ("x86_64-unknown-linux-gnu", "x86-64"),
+        Target {
+            arch: Arch::X86_64,
+            platform: Platform::Linux { sub: LinuxPlatform::Musl },
+        } => ("x86_64-unknown-linux-musl", "x86-64"),
+        Target { arch: Arch::Wasm32, platform: Platform::Wasi } => {
+            ("wasm32-unknown-wasi", "generic")
+        }
+        other => return Err(Error::Target(other.to_string())),
+    };
+
+    match target.arch {
+        Arch::Wasm32 => LlvmTarget::initialize_webassembly(&InitializationConfig::default()),
+        _ => LlvmTarget::initialize_x86(&InitializationConfig::default()),
+    }
+
+    let triple = TargetTriple::create(triple);
+    LlvmTarget::from_triple(&triple)
+        .map_err(|err| Error::Codegen(err.to_string()))?
+        .create_target_machine(
+            &triple,
+            cpu,
+            "",
+            OptimizationLevel::Aggressive,
+            RelocMode::Default,
+            CodeModel::Default,
+        )
+        .ok_or_else(|| Error::Target(target.to_string()))
+}
+
+struct Codegen<'c, 'a> {
+    context: &'c Context,
+    builder: Builder<'c>,
+    module: Module<'c>,
+    lir: &'a lir::Output,
+    target: Target,
+
+    functions: Map<MonoFunc, FunctionValue<'c>>,
+    externs: HashMap<M<key::Func>, FunctionValue<'c>>,
+    callbacks: HashMap<MonoFunc, FunctionValue<'c>>,
+}
+
+/// State of the function currently being lowered
+struct Func<'c> {
+    function: FunctionValue<'c>,
+    values: HashMap<V, BasicValueEnum<'c>>,
+    blocks: HashMap<Block, BasicBlock<'c>>,
+    phis: HashMap<(Block, u32), PhiValue<'c>>,
+}
+
+impl<'c, 'a> Codegen<'c, 'a> {
+    fn call_conv(&self) -> u32 {
+        match self.target.platform {
+            Platform::Wasi => C_CALL_CONV,
+            _ => TAIL_CALL_CONV,
+        }
+    }
+
+    /// Zero-sized types are erased, which is why this returns `None` for the unit type
+    fn ty(&self, ty: &MonoType) -> Result<Option<BasicTypeEnum<'c>>, Error> {
+        match ty {
+            MonoType::Int(intsize) => Ok(Some(
+                self.context
+                    .custom_width_int_type(intsize.bits() as u32)
+                    .into(),
+            )),
+            MonoType::Float(FloatSize::F32) => Ok(Some(self.context.f32_type().into())),
+            MonoType::Float(FloatSize::F64) => Ok(Some(self.context.f64_type().into())),
+            MonoType::Pointer(_) | MonoType::FnPointer(..) => {
+                Ok(Some(self.context.ptr_type(AddressSpace::default()).into()))
+            }
+            MonoType::Unreachable => Ok(None),
+            MonoType::Monomorphised(key) => match &self.lir.types[*key] {
+                MonoTypeData::Record { fields, .. } if fields.is_empty() => Ok(None),
+                MonoTypeData::Record { .. } => Err(Error::Unsupported("records".into())),
+                MonoTypeData::Sum { .. } => Err(Error::Unsupported("sum types".into())),
+                MonoTypeData::DynTraitObject { .. } => {
+                    Err(Error::Unsupported("trait objects".into()))
+                }
+                MonoTypeData::Placeholder => panic!("placeholder type in LIR output"),
+            },
+            MonoType::Const(_) | MonoType::Array(..) => Err(Error::Unsupported("arrays".into())),
+        }
+    }
+
+    fn fn_type<'t>(
+        &self,
+        params: impl IntoIterator<Item = &'t MonoType>,
+        returns: &MonoType,
+        variadic: bool,
+    ) -> Result<FunctionType<'c>, Error> {
+        let mut ptypes: Vec<BasicMetadataTypeEnum> = vec![];
+        for ty in params {
+            if let Some(ty) = self.ty(ty)? {
+                ptypes.push(ty.into());
+            }
+        }
+
+        Ok(match self.ty(returns)? {
+            Some(ret) => ret.fn_type(&ptypes, variadic),
+            None => self.context.void_type().fn_type(&ptypes, variadic),
+        })
+    }
+
+    fn declare_functions(&mut self) -> Result<(), Error> {
+        for (key, func) in self.lir.extern_funcs.iter() {
+            if func.data {
+                return Err(Error::Unsupported("extern data".into()));
+            }
+
+            let fn_type = self.fn_type(&func.params, &func.returns, func.variadic)?;
+            let function = match self.module.get_function(&func.symbol) {
+                Some(existing) => existing,
+                None => self
+                    .module
+                    .add_function(&func.symbol, fn_type, Some(Linkage::External)),
+            };
+
+            // The library of an extern function is the module it's imported from on WASI
+            if let (Platform::Wasi, Some(module)) = (self.target.platform, &func.library) {
+                for (kind, value) in [
+                    ("wasm-import-module", module.as_str()),
+                    ("wasm-import-name", &*func.symbol),
+                ] {
+                    let attribute = self.context.create_string_attribute(kind, value);
+                    function.add_attribute(AttributeLoc::Function, attribute);
+                }
+            }
+
+            self.externs.insert(*key, function);
+        }
+
+        for func in self.lir.functions.values() {
+            if func.export.is_some() {
+                return Err(Error::Unsupported("exported functions".into()));
+            }
+
+            let fn_type = self.fn_type(func.ssa.func_param_types(), &func.returns, false)?;
+            let function = self.module.add_function(&func.symbol, fn_type, None);
+            function.set_call_conventions(self.call_conv());
+
+            let global = function.as_global_value();
+            match &func.linkage {
+                ast::Linkage { weak: true, .. } => global.set_linkage(Linkage::WeakAny),
+                ast::Linkage { visibility: ast::SymbolVisibility::Default, .. } => {}
+                ast::Linkage { visibility: ast::SymbolVisibility::Hidden, .. } => {
+                    global.set_visibility(GlobalVisibility::Hidden)
+                }
+            }
+            if let Some(section) = &func.linkage.section {
+                global.set_section(Some(section));
+            }
+
+            self.functions.push(function);
+        }
+
+        Ok(())
+    }
+
+    // Lumina functions use `tailcc`, so the functions given to extern functions are called through
+    // a callback using the C calling convention which forwards the call
+    fn declare_callbacks(&mut self, bodies: &Map<MonoFunc, lir::Function>) -> Result<(), Error> {
+        for mfunc in super::callbacks(self.lir, bodies.values()) {
+            let internal = self.functions[mfunc];
+            let callback = self.module.add_function(
+                &super::callback_symbol(&bodies[mfunc]),
+                internal.get_type(),
+                Some(Linkage::Internal),
+            );
+
+            let entry = self.context.append_basic_block(callback, "entry");
+            self.builder.position_at_end(entry);
+
+            let args = callback
+                .get_param_iter()
+                .map(BasicMetadataValueEnum::from)
+                .collect::<Vec<_>>();
+            let call = self.builder.build_call(internal, &args, "")?;
+            call.set_call_convention(self.call_conv());
+            match call.try_as_basic_value().left() {
+                Some(value) => self.builder.build_return(Some(&value))?,
+                None => self.builder.build_return(None)?,
+            };
+
+            self.callbacks.insert(mfunc, callback);
+        }
+
+        Ok(())
+    }
+
+    fn function(&mut self, mfunc: MonoFunc, func: &lir::Function) -> Result<(), Error> {
+        let ssa = &func.ssa;
+        let function = self.functions[mfunc];
+        let mut f = Func {
+            function,
+            values: HashMap::new(),
+            blocks: HashMap::new(),
+            phis: HashMap::new(),
+        };
+
+        for block in ssa.blocks() {
+            let bb = self
+                .context
+                .append_basic_block(function, &block.to_string());
+            f.blocks.insert(block, bb);
+        }
+
+        // Parameters of the entry block are the function parameters, while the parameters of
+        // other blocks become phi nodes assigned by the jumps to them.
+        let mut fparams = function.get_param_iter();
+        for block in ssa.blocks() {
+            self.builder.position_at_end(f.blocks[&block]);
+            for (i, v) in ssa.block_params(block).enumerate() {
+                let Some(ty) = self.ty(ssa.type_of(v))? else {
+                    continue;
+                };
+                if block == Block::entry() {
+                    f.values.insert(v, fparams.next().unwrap());
+                } else {
+                    let phi = self.builder.build_phi(ty, "")?;
+                    f.values.insert(v, phi.as_basic_value());
+                    f.phis.insert((block, i as u32), phi);
+                }
+            }
+        }
+
+        for v in ssa.iterv() {
+            if let Some((block, _)) = ssa.as_block_start(v) {
+                let bb = f.blocks[&block];
+                self.builder.position_at_end(bb);
+            }
+
+            if let Some(value) = self.entry(&f, func, v)? {
+                f.values.insert(v, value);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn value(
+        &self,
+        f: &Func<'c>,
+        ssa: &lir::SSA,
+        value: &Value,
+    ) -> Result<Option<BasicValueEnum<'c>>, Error> {
+        Ok(Some(match value {
+            Value::V(v) => match f.values.get(v) {
+                Some(value) => *value,
+                None if self.ty(ssa.type_of(*v))?.is_none() => return Ok(None),
+                None => return Err(Error::Codegen(format!("{v} used before its definition"))),
+            },
+            Value::Int(n, intsize) => self
+                .context
+                .custom_width_int_type(intsize.bits() as u32)
+                .const_int(*n as u64, intsize.signed)
+                .into(),
+            Value::Float(n) => self.context.f64_type().const_float(*n).into(),
+            Value::FuncPtr(mfunc) => self.functions[*mfunc]
+                .as_global_value()
+                .as_pointer_value()
+                .into(),
+            Value::ExternFuncPtr(key) => self.externs[key]
+                .as_global_value()
+                .as_pointer_value()
+                .into(),
+            Value::ReadOnly(_) => return Err(Error::Unsupported("read-only data".into())),
+        }))
+    }
+
+    fn expect_value(
+        &self,
+        f: &Func<'c>,
+        ssa: &lir::SSA,
+        value: &Value,
+    ) -> Result<BasicValueEnum<'c>, Error> {
+        self.value(f, ssa, value)?
+            .ok_or_else(|| Error::Codegen(format!("expected {value} to not be zero-sized")))
+    }
+
+    fn int(&self, f: &Func<'c>, ssa: &lir::SSA, value: &Value) -> Result<IntValue<'c>, Error> {
+        self.expect_value(f, ssa, value)
+            .map(BasicValueEnum::into_int_value)
+    }
+
+    fn ptr(&self, f: &Func<'c>, ssa: &lir::SSA, value: &Value) -> Result<PointerValue<'c>, Error> {
+        self.expect_value(f, ssa, value)
+            .map(BasicValueEnum::into_pointer_value)
+    }
+
+    fn args(
+        &self,
+        f: &Func<'c>,
+        ssa: &lir::SSA,
+        params: &[Value],
+    ) -> Result<Vec<BasicMetadataValueEnum<'c>>, Error> {
+        let mut args = Vec::with_capacity(params.len());
+        for param in params {
+            if let Some(value) = self.value(f, ssa, param)? {
+                args.push(value.into());
+            }
+        }
+        Ok(args)
+    }
+
+    // Lumina functions given directly as function pointers are replaced with their callback
+    fn extern_args(
+        &self,
+        f: &Func<'c>,
+        ssa: &lir::SSA,
+        key: M<key::Func>,
+        params: &[Value],
+    ) -> Result<Vec<BasicMetadataValueEnum<'c>>, Error> {
+        let ptypes = &self.lir.extern_funcs[&key].params;
+        let mut args = Vec::with_capacity(params.len());
+        for (i, param) in params.iter().enumerate() {
+            match (param, ptypes.get(i)) {
+                (Value::FuncPtr(mfunc), Some(MonoType::FnPointer(..))) => {
+                    let callback = self.callbacks[mfunc].as_global_value();
+                    args.push(callback.as_pointer_value().into());
+                }
+                _ => {
+                    if let Some(value) = self.value(f, ssa, param)? {
+                        args.push(value.into());
+                    }
+                }
+            }
+        }
+        Ok(args)
+    }
+
+    fn jump(
+        &self,
+        f: &Func<'c>,
+        ssa: &lir::SSA,
+        jump: &BlockJump,
+    ) -> Result<BasicBlock<'c>, Error> {
+        let from = self.builder.get_insert_block().unwrap();
+        for (i, param) in jump.params.iter().enumerate() {
+            if let Some(phi) = f.phis.get(&(jump.id, i as u32)) {
+                let value = self.expect_value(f, ssa, param)?;
+                phi.add_incoming(&[(&value, from)]);
+            }
+        }
+        Ok(f.blocks[&jump.id])
+    }
+
+    fn size_of(&self, ty: &MonoType) -> Result<IntValue<'c>, Error> {
+        let size_t = self
+            .context
+            .custom_width_int_type(self.lir.types.pointer_bits);
+        Ok(match self.ty(ty)? {
+            Some(ty) => {
+                let size = ty.size_of().unwrap();
+                self.builder.build_int_cast(size, size_t, "")?
+            }
+            None => size_t.const_zero(),
+        })
+    }
+
+    fn entry(
+        &self,
+        f: &Func<'c>,
+        func: &lir::Function,
+        v: V,
+    ) -> Result<Option<BasicValueEnum<'c>>, Error> {
+        let ssa = &func.ssa;
+        let ty = ssa.type_of(v);
+        let b = &self.builder;
+
+        let value: BasicValueEnum = match ssa.entry_of(v) {
+            Entry::BlockParam(..) => return Ok(None),
+
+            Entry::CallStatic(mfunc, params) => {
+                let args = self.args(f, ssa, params)?;
+                let call = b.build_call(self.functions[*mfunc], &args, "")?;
+                call.set_call_convention(self.call_conv());
+                return Ok(call.try_as_basic_value().left());
+            }
+            Entry::CallExtern(key, params) => {
+                let args = self.extern_args(f, ssa, *key, params)?;
+                let call = b.build_call(self.externs[key], &args, "")?;
+                return Ok(call.try_as_basic_value().left());
+            }
+            Entry::CallValue(fptr, params) => {
+                let MonoType::FnPointer(ptypes, ret) = self.type_of_value(ssa, fptr) else {
+                    return Err(Error::Unsupported("calling closures".into()));
+                };
+                let fn_type = self.fn_type(&ptypes, &ret, false)?;
+                let fptr = self.ptr(f, ssa, fptr)?;
+                let args = self.args(f, ssa, params)?;
+                let call = b.build_indirect_call(fn_type, fptr, &args, "")?;
+                call.set_call_convention(self.call_conv());
+                return Ok(call.try_as_basic_value().left());
+            }
+
+            Entry::JmpFunc(mfunc, params) => {
+                let args = self.args(f, ssa, params)?;
+                let call = b.build_call(self.functions[*mfunc], &args, "")?;
+                call.set_call_convention(self.call_conv());
+                call.set_tail_call(true);
+                match call.try_as_basic_value().left() {
+                    Some(value) => b.build_return(Some(&value))?,
+                    None => b.build_return(None)?,
+                };
+                return Ok(None);
+            }
+            Entry::JmpBlock(jump) => {
+                let bb = self.jump(f, ssa, jump)?;
+                b.build_unconditional_branch(bb)?;
+                return Ok(None);
+            }
+            Entry::Return(value) => {
+                match self.value(f, ssa, value)? {
+                    Some(value) if self.ty(&func.returns)?.is_some() => {
+                        b.build_return(Some(&value))?
+                    }
+                    _ => b.build_return(None)?,
+                };
+                return Ok(None);
+            }
+            Entry::Select { value, on_true, on_false } => {
+                let value = self.int(f, ssa, value)?;
+                let cond = b.build_int_compare(
+                    IntPredicate::NE,
+                    value,
+                    value.get_type().const_zero(),
+                    "",
+                )?;
+                let then = self.jump(f, ssa, on_true)?;
+                let else_ = self.jump(f, ssa, on_false)?;
+                b.build_conditional_branch(cond, then, else_)?;
+                return Ok(None);
+            }
+            Entry::JmpTable(value, blocks) => {
+                let value = self.int(f, ssa, value)?;
+                let cases = blocks
+                    .iter()
+                    .enumerate()
+                    .map(|(i, block)| {
+                        (value.get_type().const_int(i as u64, false), f.blocks[block])
+                    })
+                    .collect::<Vec<_>>();
+
+                let current = b.get_insert_block().unwrap();
+                let otherwise = self.context.append_basic_block(f.function, "");
+                b.position_at_end(otherwise);
+                b.build_unreachable()?;
+                b.position_at_end(current);
+
+                b.build_switch(value, otherwise, &cases)?;
+                return Ok(None);
+            }
+            Entry::Trap(_) => {
+                let trap = Intrinsic::find("llvm.trap")
+                    .and_then(|trap| trap.get_declaration(&self.module, &[]))
+                    .unwrap();
+                b.build_call(trap, &[], "")?;
+                b.build_unreachable()?;
+                return Ok(None);
+            }
+            // `--trap-on-panic` is only supported by the Cranelift backend
+            Entry::Breakpoint => return Ok(None),
+
+            Entry::BinOp(op, [lhs, rhs]) => {
+                let lhs = self.expect_value(f, ssa, lhs)?;
+                let rhs = self.expect_value(f, ssa, rhs)?;
+                let (lhs, rhs) = (lhs.into_int_value(), rhs.into_int_value());
+                let signed = matches!(ty, MonoType::Int(intsize) if intsize.signed);
+                match op {
+                    BinOp::Add => b.build_int_add(lhs, rhs, "")?,
+                    BinOp::Sub => b.build_int_sub(lhs, rhs, "")?,
+                    BinOp::Mul => b.build_int_mul(lhs, rhs, "")?,
+                    BinOp::Div if signed => b.build_int_signed_div(lhs, rhs, "")?,
+                    BinOp::Div => b.build_int_unsigned_div(lhs, rhs, "")?,
+                    BinOp::Rem if signed => b.build_int_signed_rem(lhs, rhs, "")?,
+                    BinOp::Rem => b.build_int_unsigned_rem(lhs, rhs, "")?,
+                    BinOp::And => b.build_and(lhs, rhs, "")?,
+                }
+                .into()
+            }
+            Entry::FloatAdd([lhs, rhs])
+            | Entry::FloatSub([lhs, rhs])
+            | Entry::FloatMul([lhs, rhs])
+            | Entry::FloatDiv([lhs, rhs]) => {
+                let lhs = self.expect_value(f, ssa, lhs)?.into_float_value();
+                let rhs = self.expect_value(f, ssa, rhs)?.into_float_value();
+                match ssa.entry_of(v) {
+                    Entry::FloatAdd(_) => b.build_float_add(lhs, rhs, "")?,
+                    Entry::FloatSub(_) => b.build_float_sub(lhs, rhs, "")?,
+                    Entry::FloatMul(_) => b.build_float_mul(lhs, rhs, "")?,
+                    _ => b.build_float_div(lhs, rhs, "")?,
+                }
+                .into()
+            }
+            Entry::FloatNeg(value) => {
+                let value = self.expect_value(f, ssa, value)?.into_float_value();
+                b.build_float_neg(value, "")?.into()
+            }
+            Entry::FloatCmp([lhs, rhs], ord) => {
+                let lhs = self.expect_value(f, ssa, lhs)?.into_float_value();
+                let rhs = self.expect_value(f, ssa, rhs)?.into_float_value();
+                let predicate = match ord {
+                    Ordering::Equal => FloatPredicate::OEQ,
+                    Ordering::Less => FloatPredicate::OLT,
+                    Ordering::Greater => FloatPredicate::OGT,
+                };
+                let cmp = b.build_float_compare(predicate, lhs, rhs, "")?;
+                b.build_int_z_extend(cmp, self.context.i8_type(), "")?
+                    .into()
+            }
+            Entry::IntCmpInclusive([lhs, rhs], ord, intsize) => {
+                let lhs = self.int(f, ssa, lhs)?;
+                let rhs = self.int(f, ssa, rhs)?;
+                let predicate = match (ord, intsize.signed) {
+                    (Ordering::Equal, _) => IntPredicate::EQ,
+                    (Ordering::Less, true) => IntPredicate::SLT,
+                    (Ordering::Less, false) => IntPredicate::ULT,
+                    (Ordering::Greater, true) => IntPredicate::SGT,
+                    (Ordering::Greater, false) => IntPredicate::UGT,
+                };
+                let cmp = b.build_int_compare(predicate, lhs, rhs, "")?;
+                b.build_int_z_extend(cmp, self.context.i8_type(), "")?
+                    .into()
+            }
+            Entry::IntAbs(value) => {
+                let value = self.int(f, ssa, value)?;
+                let zero = value.get_type().const_zero();
+                let negative = b.build_int_compare(IntPredicate::SLT, value, zero, "")?;
+                let negated = b.build_int_neg(value, "")?;
+                b.build_select(negative, negated, value, "")?
+            }
+            Entry::BitNot(value) => b.build_not(self.int(f, ssa, value)?, "")?.into(),
+            Entry::IntMin([lhs, rhs], intsize) | Entry::IntMax([lhs, rhs], intsize) => {
+                let lhs = self.int(f, ssa, lhs)?;
+                let rhs = self.int(f, ssa, rhs)?;
+                let min = matches!(ssa.entry_of(v), Entry::IntMin(..));
+                let predicate = match (min, intsize.signed) {
+                    (true, true) => IntPredicate::SLT,
+                    (true, false) => IntPredicate::ULT,
+                    (false, true) => IntPredicate::SGT,
+                    (false, false) => IntPredicate::UGT,
+                };
+                let cmp = b.build_int_compare(predicate, lhs, rhs, "")?;
+                b.build_select(cmp, lhs, rhs, "")?
+            }
+            Entry::SelectValue([cond, on_true, on_false]) => {
+                let cond = self.int(f, ssa, cond)?;
+                let cond =
+                    b.build_int_compare(IntPredicate::NE, cond, cond.get_type().const_zero(), "")?;
+                let on_true = self.expect_value(f, ssa, on_true)?;
+                let on_false = self.expect_value(f, ssa, on_false)?;
+                b.build_select(cond, on_true, on_false, "")?
+            }
+
+            Entry::Reduce(value) => {
+                let to = self.ty(ty)?.unwrap().into_int_type();
+                b.build_int_truncate(self.int(f, ssa, value)?, to, "")?
+                    .into()
+            }
+            Entry::ExtendSigned(value) => {
+                let to = self.ty(ty)?.unwrap().into_int_type();
+                b.build_int_s_extend(self.int(f, ssa, value)?, to, "")?
+                    .into()
+            }
+            Entry::ExtendUnsigned(value) => {
+                let to = self.ty(ty)?.unwrap().into_int_type();
+                b.build_int_z_extend(self.int(f, ssa, value)?, to, "")?
+                    .into()
+            }
+            Entry::IntToFloat(value, intsize) => {
+                let value = self.int(f, ssa, value)?;
+                let float = self.ty(ty)?.unwrap().into_float_type();
+                if intsize.signed {
+                    b.build_signed_int_to_float(value, float, "")?.into()
+                } else {
+                    b.build_unsigned_int_to_float(value, float, "")?.into()
+                }
+            }
+            Entry::FloatToInt(value, intsize) => {
+                let value = self.expect_value(f, ssa, value)?.into_float_value();
+                let to = self.ty(ty)?.unwrap().into_int_type();
+                if intsize.signed {
+                    b.build_float_to_signed_int(value, to, "")?.into()
+                } else {
+                    b.build_float_to_unsigned_int(value, to, "")?.into()
+                }
+            }
+            Entry::FloatCast(value) => {
+                let value = self.expect_value(f, ssa, value)?.into_float_value();
+                let to = self.ty(ty)?.unwrap().into_float_type();
+                b.build_float_cast(value, to, "")?.into()
+            }
+            Entry::Transmute(value) => {
+                let value = self.expect_value(f, ssa, value)?;
+                let to = self.ty(ty)?.unwrap();
+                match (value, to) {
+                    (BasicValueEnum::PointerValue(ptr), BasicTypeEnum::IntType(int)) => {
+                        b.build_ptr_to_int(ptr, int, "")?.into()
+                    }
+                    (BasicValueEnum::IntValue(int), BasicTypeEnum::PointerType(ptr)) => {
+                        b.build_int_to_ptr(int, ptr, "")?.into()
+                    }
+                    (value, to) => b.build_bit_cast(value, to, "")?,
+                }
+            }
+
+            Entry::SizeOf(of) => {
+                let to = self.ty(ty)?.unwrap().into_int_type();
+                b.build_int_cast(self.size_of(of)?, to, "")?.into()
+            }
+            Entry::Alloca => {
+                let MonoType::Pointer(inner) = ty else {
+                    unreachable!()
+                };
+                match self.ty(inner)? {
+                    Some(inner) => b.build_alloca(inner, "")?.into(),
+                    None => self
+                        .context
+                        .ptr_type(AddressSpace::default())
+                        .const_null()
+                        .into(),
+                }
+            }
+            Entry::Alloc => {
+                let MonoType::Pointer(inner) = ty else {
+                    unreachable!()
+                };
+                let size = self.size_of(inner)?;
+                let call = b.build_call(self.functions[self.lir.alloc], &[size.into()], "")?;
+                call.set_call_convention(self.call_conv());
+                call.try_as_basic_value().left().unwrap()
+            }
+            Entry::Dealloc { ptr } => {
+                let MonoType::Pointer(inner) = self.type_of_value(ssa, ptr) else {
+                    unreachable!()
+                };
+                let size = self.size_of(&inner)?;
+                let ptr = self.ptr(f, ssa, ptr)?;
+                let call = b.build_call(
+                    self.functions[self.lir.dealloc],
+                    &[ptr.into(), size.into()],
+                    "",
+                )?;
+                call.set_call_convention(self.call_conv());
+                return Ok(None);
+            }
+            Entry::Deref(ptr) => {
+                let Some(to) = self.ty(ty)? else {
+                    return Ok(None);
+                };
+                b.build_load(to, self.ptr(f, ssa, ptr)?, "")?
+            }
+            Entry::WritePtr { ptr, value } => {
+                if let Some(value) = self.value(f, ssa, value)? {
+                    b.build_store(self.ptr(f, ssa, ptr)?, value)?;
+                }
+                return Ok(None);
+            }
+            Entry::AtomicLoad(ptr, ordering) => {
+                let to = self.ty(ty)?.expect("atomic on zero-sized type");
+                let v = b.build_load(to, self.ptr(f, ssa, ptr)?, "")?;
+                v.as_instruction_value()
+                    .unwrap()
+                    .set_atomic_ordering(atomic_ordering(*ordering))
+                    .map_err(|err| Error::Codegen(err.to_string()))?;
+                v
+            }
+            Entry::AtomicStore { ptr, value, ordering } => {
+                let value = self.expect_value(f, ssa, value)?;
+                b.build_store(self.ptr(f, ssa, ptr)?, value)?
+                    .set_atomic_ordering(atomic_ordering(*ordering))
+                    .map_err(|err| Error::Codegen(err.to_string()))?;
+                return Ok(None);
+            }
+            Entry::AtomicCas([ptr, expected, replacement], ordering) => {
+                let ptr = self.ptr(f, ssa, ptr)?;
+                let expected = self.expect_value(f, ssa, expected)?;
+                let replacement = self.expect_value(f, ssa, replacement)?;
+                // The failure ordering may not contain a release
+                let failure = match ordering {
+                    AtomicOrdering::Release => AtomicOrdering::Relaxed,
+                    AtomicOrdering::AcqRel => AtomicOrdering::Acquire,
+                    other => *other,
+                };
+                let pair = b.build_cmpxchg(
+                    ptr,
+                    expected,
+                    replacement,
+                    atomic_ordering(*ordering),
+                    atomic_ordering(failure),
+                )?;
+                b.build_extract_value(pair, 0, "")?
+            }
+            Entry::AtomicRmw(op, [ptr, value], ordering) => {
+                if !matches!(ty, MonoType::Int(_)) {
+                    return Err(Error::Unsupported("atomic operations on pointers".into()));
+                }
+                let ptr = self.ptr(f, ssa, ptr)?;
+                let value = self.int(f, ssa, value)?;
+                let op = match op {
+                    AtomicRmwOp::Add => AtomicRMWBinOp::Add,
+                    AtomicRmwOp::Sub => AtomicRMWBinOp::Sub,
+                    AtomicRmwOp::And => AtomicRMWBinOp::And,
+                    AtomicRmwOp::Or => AtomicRMWBinOp::Or,
+                    AtomicRmwOp::Xor => AtomicRMWBinOp::Xor,
+                    AtomicRmwOp::Xchg => AtomicRMWBinOp::Xchg,
+                };
+                b.build_atomicrmw(op, ptr, value, atomic_ordering(*ordering))?
+                    .into()
+            }
+            Entry::Construct(params) if params.is_empty() => return Ok(None),
+
+            Entry::Construct(_)
+            | Entry::Replicate(..)
+            | Entry::Variant(..)
+            | Entry::Field { .. }
+            | Entry::CastFromSum { .. }
+            | Entry::TagFromSum { .. }
+            | Entry::Indice { .. } => return Err(Error::Unsupported("aggregate values".into())),
+            Entry::AlignOf(_) => return Err(Error::Unsupported("builtin:align_of".into())),
+            Entry::MemCpy { .. } => return Err(Error::Unsupported("builtin:memcpy".into())),
+            Entry::MemSet { .. } => return Err(Error::Unsupported("builtin:memset".into())),
+            Entry::IntAddCarry(_) | Entry::IntSubBorrow(_) => {
+                return Err(Error::Unsupported("carry arithmetic".into()))
+            }
+            Entry::RefStaticVal(_) => return Err(Error::Unsupported("declaring vals".into())),
+            Entry::RefExternData(_) => return Err(Error::Unsupported("extern data".into())),
+        };
+
+        Ok(Some(value))
+    }
+
+    fn type_of_value(&self, ssa: &lir::SSA, value: &Value) -> MonoType {
+        match value {
+            Value::V(v) => ssa.type_of(*v).clone(),
+            Value::Int(_, intsize) => MonoType::Int(*intsize),
+            Value::Float(_) => MonoType::Float(FloatSize::F64),
+            Value::FuncPtr(mfunc) => self.lir.functions[*mfunc].as_fnpointer(),
+            Value::ExternFuncPtr(key) => {
+                let func = &self.lir.extern_funcs[key];
+                MonoType::FnPointer(func.params.clone(), Box::new(func.returns.clone()))
+            }
+            Value::ReadOnly(ro) => MonoType::pointer(self.lir.read_only_table[*ro].1.clone()),
+        }
+    }
+
+    /// Declare the `main` function called by libc, or the `_start` function called by WASI
+    /// runtimes
+    fn declare_entrypoint(&mut self) -> Result<(), Error> {
+        match self.target.platform {
+            Platform::Linux { sub: LinuxPlatform::Gnu | LinuxPlatform::Musl } => {
+                self.declare_main()
+            }
+            Platform::Wasi => self.declare_start(),
+            _ => Err(Error::Target(self.target.to_string())),
+        }
+    }
+
+    fn declare_main(&mut self) -> Result<(), Error> {
+        let i32 = self.context.i32_type();
+        let argv = self.context.ptr_type(AddressSpace::default());
+        let fn_type = i32.fn_type(&[i32.into(), argv.into()], false);
+        let main = self
+            .module
+            .add_function("main", fn_type, Some(Linkage::External));
+
+        let entry = self.context.append_basic_block(main, "entry");
+        self.builder.position_at_end(entry);
+
+        let sys_init = self.functions[self.lir.sys_init];
+        let args = main
+            .get_param_iter()
+            .take(sys_init.count_params() as usize)
+            .map(Into::into)
+            .collect::<Vec<BasicMetadataValueEnum>>();
+        self.builder
+            .build_call(sys_init, &args, "")?
+            .set_call_convention(self.call_conv());
+
+        self.builder
+            .build_call(self.functions[self.lir.main], &[], "")?
+            .set_call_convention(self.call_conv());
+
+        self.builder.build_return(Some(&i32.const_zero()))?;
+
+        Ok(())
+    }
+
+    // WASI commands read their arguments with `args_get` instead of receiving them, and returning
+    // from `_start` exits the process successfully
+    fn declare_start(&mut self) -> Result<(), Error> {
+        let sys_init = self.functions[self.lir.sys_init];
+        if sys_init.count_params() != 0 {
+            return Err(Error::Unsupported(
+                "a system initialiser taking the process arguments on WASI".into(),
+            ));
+        }
+
+        let fn_type = self.context.void_type().fn_type(&[], false);
+        let start = self
+            .module
+            .add_function("_start", fn_type, Some(Linkage::External));
+
+        // Defined by the linker to run the constructors of any C code linked into the module
+        let ctors = self
+            .module
+            .add_function("__wasm_call_ctors", fn_type, Some(Linkage::External));
+
+        let entry = self.context.append_basic_block(start, "entry");
+        self.builder.position_at_end(entry);
+
+        self.builder.build_call(ctors, &[], "")?;
+
+        self.builder
+            .build_call(sys_init, &[], "")?
+            .set_call_convention(self.call_conv());
+
+        self.builder
+            .build_call(self.functions[self.lir.main], &[], "")?
+            .set_call_convention(self.call_conv());
+
+        self.builder.build_return(None)?;
+
+        Ok(())
+    }
+}
+
+fn atomic_ordering(ordering: AtomicOrdering) -> inkwell::AtomicOrdering {
+    match ordering {
+        AtomicOrdering::Relaxed => inkwell::AtomicOrdering::Monotonic,
+        AtomicOrdering::Acquire => inkwell::AtomicOrdering::Acquire,
+        AtomicOrdering::Release => inkwell::AtomicOrdering::Release,
+        AtomicOrdering::AcqRel => inkwell::AtomicOrdering::AcquireRelease,
+        AtomicOrdering::SeqCst => inkwell::AtomicOrdering::SequentiallyConsistent,
+    }
+}
@@ -1,7 +1,16 @@
+pub mod c;
 pub mod cranelift;
+mod header;
+pub use header::c_header;
+#[cfg(feature = "llvm")]
+pub mod llvm;
 
-use super::{ast, target::LinuxPlatform, target::Platform, Target};
+use super::{ast, target::Arch, target::LinuxPlatform, target::Platform, CrateType, Target};
+use crate::debuginfo::BinDebugInfo;
+use crate::lir;
+use std::collections::HashSet;
 use std::ffi::OsStr;
+use std::fmt;
 use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -9,50 +18,186 @@ use std::process::Command;
 use std::process::ExitCode;
 use tracing::info;
 
+/// A code generator turning the monomorphised LIR into object files for the target
+///
+/// The objects are linked together, so a backend may split the program across several of them.
+/// Implementations take the function bodies out of the LIR with [`lir::Output::take_bodies`] and
+/// drop each body once its code has been generated.
+pub trait Backend {
+    fn compile(
+        &mut self,
+        target: Target,
+        dwarf: BinDebugInfo,
+        lir: lir::Output,
+    ) -> Result<Vec<Vec<u8>>, Error>;
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The backend can not yet generate code for a construct used by the program
+    Unsupported(String),
+    /// The target is not supported by the backend
+    Target(String),
+    /// The code generator itself reported an error
+    Codegen(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Unsupported(what) => write!(f, "{what} is not supported by this backend"),
+            Error::Target(target) => write!(f, "target {target} is not supported by this backend"),
+            Error::Codegen(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+/// The functions given directly to extern functions as function pointers
+///
+/// Lumina functions use a calling convention of their own, so C calls these through a callback
+/// which forwards the call. They're listed in the order of the LIR so that builds are reproducible.
+fn callbacks<'f>(
+    lir: &lir::Output,
+    bodies: impl IntoIterator<Item = &'f lir::Function>,
+) -> Vec<lir::MonoFunc> {
+    let mut given = HashSet::new();
+
+    for func in bodies {
+        for v in func.ssa.iterv() {
+            let lir::Entry::CallExtern(key, params) = func.ssa.entry_of(v) else {
+                continue;
+            };
+
+            for (param, ty) in params.iter().zip(&lir.extern_funcs[key].params) {
+                if let (lir::Value::FuncPtr(mfunc), lir::MonoType::FnPointer(..)) = (param, ty) {
+                    given.insert(*mfunc);
+                }
+            }
+        }
+    }
+
+    lir.functions
+        .keys()
+        .filter(|mfunc| given.contains(mfunc))
+        .collect()
+}
+
+/// The symbol of the callback forwarding calls from C to `func`
+fn callback_symbol(func: &lir::Function) -> String {
+    format!("__lumina_callback_{}", func.symbol)
+}
+
 pub fn link_native_binary(
     config: ast::ProjectConfig,
     target: Target,
+    crate_type: CrateType,
     output: &Path,
     projectpath: PathBuf,
     luminapath: PathBuf,
-    object: Vec<u8>,
+    objects: Vec<Vec<u8>>,
 ) -> Result<(), ExitCode> {
     let project_name = config.name.clone();
-    let workdir = create_workdir(&luminapath, &project_name);
+    let workdir = create_workdir(&luminapath, target, &project_name);
 
-    let objectfile = {
-        let mut path = workdir.join(&project_name);
-        path.set_extension(target.object_extension());
+    let objectfiles = objects
+        .iter()
+        .enumerate()
+        .map(|(i, object)| {
+            let mut path = workdir.join(format!("{project_name}.{i}"));
+            path.set_extension(target.object_extension());
 
-        let mut f = File::create(&path).unwrap();
-        f.write_all(&object).unwrap();
+            let mut f = File::create(&path).unwrap();
+            f.write_all(object).unwrap();
 
-        path
-    };
+            path
+        })
+        .collect::<Vec<_>>();
 
     let targetdir = luminapath.join("targets");
 
+    // Static libraries are archived instead of linked, leaving the C runtime and the libraries in
+    // the target directory to the program they're linked into
+    if crate_type == CrateType::Staticlib {
+        let archdir = match target.arch {
+            Arch::X86_64 => targetdir.join("linux"),
+            Arch::Aarch64 => targetdir.join("linux").join("aarch64"),
+            Arch::Wasm32 => unreachable!("static libraries are rejected by the wasm32 backends"),
+        };
+
+        // `ar` adds to the archive if it already exists
+        if output.exists() {
+            std::fs::remove_file(output).unwrap();
+        }
+
+        let mut archiver = Command::new("ar");
+        archiver
+            .arg("rcs")
+            .arg(output)
+            .args(&objectfiles)
+            .arg(archdir.join("syscall.o"));
+
+        return invoke(archiver, workdir);
+    }
+
     let bindir = targetdir.join("bin");
 
     let mut linker = match target.platform {
         Platform::Linux { sub } => {
             let linuxdir = targetdir.join("linux");
-            let sublinuxdir = linuxdir.join(sub.to_string());
-
-            let mut linker = if matches!(sub, LinuxPlatform::Gnu) {
-                let mut linker = Command::new("gcc");
-                linker.arg("-no-pie").arg("-flto");
-                linker
-            } else {
-                Command::new(bindir.join("ld.lld"))
+
+            // Libraries and the syscall helper of other architectures are kept in their own
+            // directory
+            let archdir = match target.arch {
+                Arch::X86_64 => linuxdir,
+                Arch::Aarch64 => linuxdir.join("aarch64"),
+                Arch::Wasm32 => unreachable!("wasm32 is only paired with wasi"),
+            };
+            let sublinuxdir = archdir.join(sub.to_string());
+
+            let mut linker = match config.linker {
+                Some(name) if name.components().count() == 1 => Command::new(name),
+                Some(path) => Command::new(projectpath.join(path)),
+                None if matches!(sub, LinuxPlatform::Gnu) => {
+                    let mut linker = Command::new("gcc");
+                    if crate_type == CrateType::Bin {
+                        linker.arg("-no-pie");
+                    }
+                    linker.arg("-flto").arg("-Wl,--gc-sections");
+                    linker
+                }
+                None => {
+                    // musl's C runtime is bundled and the syscall target has no runtime at all,
+                    // so the system's libraries and start files must never be picked up
+                    let mut linker = Command::new(bindir.join("ld.lld"));
+                    linker.arg("-static").arg("--gc-sections");
+                    if matches!(sub, LinuxPlatform::Syscall) {
+                        linker.arg("-nostdlib");
+                    }
+                    linker
+                }
             };
 
-            linker.arg("-o").arg(output).arg(&objectfile);
+            if crate_type == CrateType::Lib {
+                linker.arg("-shared");
+            }
+
+            linker.arg("-o").arg(output).args(&objectfiles);
+
+            if let Some(script) = config.linker_script {
+                linker.arg("-T").arg(projectpath.join(script));
+            }
 
             for arg in config.linker_args {
                 linker.arg(arg);
             }
 
+            for path in &config.library_paths {
+                linker.arg("-L").arg(projectpath.join(path));
+            }
+            for lib in &config.libraries {
+                linker.arg(format!("-l{lib}"));
+            }
+
             iter_objects(&sublinuxdir, &["o", "a"], |path| {
                 linker.arg(path);
             });
@@ -61,19 +206,151 @@ pub fn link_native_binary(
                 linker.arg(projectpath.join(lib));
             }
 
-            linker.arg(linuxdir.join("syscall.o"));
+            linker.arg(archdir.join("syscall.o"));
+
+            linker
+        }
+        Platform::Windows => {
+            let windowsdir = targetdir.join("windows");
+
+            let mut linker = match config.linker {
+                Some(name) if name.components().count() == 1 => Command::new(name),
+                Some(path) => Command::new(projectpath.join(path)),
+                None => {
+                    let mut linker = Command::new(bindir.join("lld-link"));
+                    linker.arg("/opt:ref");
+                    linker
+                }
+            };
+
+            linker
+                .arg(format!("/out:{}", output.display()))
+                .arg("/entry:mainCRTStartup")
+                .arg("/subsystem:console")
+                .args(&objectfiles);
+
+            for arg in config.linker_args {
+                linker.arg(arg);
+            }
+
+            for path in &config.library_paths {
+                linker.arg(format!("/libpath:{}", projectpath.join(path).display()));
+            }
+            for lib in &config.libraries {
+                linker.arg(format!("{lib}.lib"));
+            }
+
+            iter_objects(&windowsdir, &["obj", "lib"], |path| {
+                linker.arg(path);
+            });
+
+            for lib in config.linker_libs {
+                linker.arg(projectpath.join(lib));
+            }
+
+            linker
+        }
+        Platform::Darwin => {
+            let darwindir = targetdir.join("darwin");
+
+            // The system libraries are only available through the SDK, which the C compiler
+            // driver knows how to find
+            let mut linker = match config.linker {
+                Some(name) if name.components().count() == 1 => Command::new(name),
+                Some(path) => Command::new(projectpath.join(path)),
+                None => {
+                    let mut linker = Command::new("cc");
+                    linker.arg("-Wl,-dead_strip");
+                    linker
+                }
+            };
+
+            let arch = match target.arch {
+                Arch::X86_64 => "x86_64",
+                Arch::Aarch64 => "arm64",
+                Arch::Wasm32 => unreachable!("wasm32 is only paired with wasi"),
+            };
+
+            linker
+                .arg("-arch")
+                .arg(arch)
+                .arg("-o")
+                .arg(output)
+                .args(&objectfiles);
+
+            for arg in config.linker_args {
+                linker.arg(arg);
+            }
+
+            for path in &config.library_paths {
+                linker.arg("-L").arg(projectpath.join(path));
+            }
+            for lib in &config.libraries {
+                linker.arg(format!("-l{lib}"));
+            }
+
+            iter_objects(&darwindir, &["o", "a"], |path| {
+                linker.arg(path);
+            });
+
+            for lib in config.linker_libs {
+                linker.arg(projectpath.join(lib));
+            }
+
+            linker
+        }
+        Platform::Wasi => {
+            let wasidir = targetdir.join("wasi");
+
+            // The entrypoint is the exported `_start` which WASI runtimes call, and the WASI
+            // functions are imported under the module named by the objects themselves
+            let mut linker = match config.linker {
+                Some(name) if name.components().count() == 1 => Command::new(name),
+                Some(path) => Command::new(projectpath.join(path)),
+                None => Command::new(bindir.join("wasm-ld")),
+            };
+
+            linker.arg("-o").arg(output).args(&objectfiles);
+
+            for arg in config.linker_args {
+                linker.arg(arg);
+            }
+
+            for path in &config.library_paths {
+                linker.arg("-L").arg(projectpath.join(path));
+            }
+            for lib in &config.libraries {
+                linker.arg(format!("-l{lib}"));
+            }
+
+            iter_objects(&wasidir, &["o", "a"], |path| {
+                linker.arg(path);
+            });
+
+            for lib in config.linker_libs {
+                linker.arg(projectpath.join(lib));
+            }
 
             linker
         }
     };
 
+    invoke(linker, workdir)
+}
+
+fn invoke(mut linker: Command, workdir: PathBuf) -> Result<(), ExitCode> {
     info!("invoking system linker as: {:#?}", linker);
 
-    let status = linker
-        .spawn()
-        .expect("failed to invoke linker")
-        .wait()
-        .unwrap();
+    let status = match linker.status() {
+        Ok(status) => status,
+        Err(err) => {
+            eprintln!(
+                "could not invoke linker {}: {err}",
+                linker.get_program().to_string_lossy()
+            );
+            return Err(ExitCode::FAILURE);
+        }
+    };
 
     if status.success() {
         std::fs::remove_dir_all(workdir).unwrap();
@@ -94,8 +371,8 @@ fn iter_objects(path: &Path, objs: &[&str], mut f: impl FnMut(PathBuf)) {
     }
 }
 
-fn create_workdir(luminapath: &Path, project_name: &str) -> PathBuf {
-    let mut workdir = luminapath.to_path_buf();
+fn create_workdir(luminapath: &Path, target: Target, project_name: &str) -> PathBuf {
+    let mut workdir = target.artifact_dir(luminapath);
     workdir.push("workdirs");
     workdir.push(project_name);
 
@@ -1,4 +1,4 @@
-use crate::lir::MonoFunc;
+use crate::lir::{Location, MonoFunc};
 use crate::target::Arch;
 use crate::Target;
 use gimli::write::{
@@ -6,10 +6,11 @@ use gimli::write::{
     UnitEntryId,
 };
 use gimli::LineEncoding;
-use gimli::{Register, X86_64};
+use gimli::{AArch64, Register, X86_64};
 use key::M;
 use lumina_collections::Map;
 use lumina_key as key;
+use lumina_util::Span;
 use std::collections::HashMap;
 use std::path::Path;
 
@@ -60,6 +61,9 @@ impl BinDebugInfo {
             unit_range_list: RangeList(Vec::new()),
             stack_pointer_register: match target.arch {
                 Arch::X86_64 => X86_64::RSP,
+                Arch::Aarch64 => AArch64::SP,
+                // WebAssembly has no registers, and the backends targetting it don't use DWARF
+                Arch::Wasm32 => Register(0),
             },
         }
     }
@@ -87,7 +91,8 @@ impl BinDebugInfo {
                 let file = line_program.add_file(lib_name, dir, None);
 
                 let kind = ModuleKind::DirRoot { parent, dir };
-                let info = ModuleDebugInfo { file, kind, functions: HashMap::new() };
+                let info =
+                    ModuleDebugInfo { file, kind, functions: HashMap::new(), lines: Vec::new() };
 
                 self.modules.push_as(module, info);
             }
@@ -142,7 +147,8 @@ impl BinDebugInfo {
                 let root = self.units.push(unit);
 
                 let kind = ModuleKind::ProjectRoot { root, dir };
-                let info = ModuleDebugInfo { file, kind, functions: HashMap::new() };
+                let info =
+                    ModuleDebugInfo { file, kind, functions: HashMap::new(), lines: Vec::new() };
 
                 self.modules.push_as(module, info);
             }
@@ -160,7 +166,7 @@ impl BinDebugInfo {
         let lib_name = path_to_debug_section(unit, &path);
 
         let file = unit.unit.line_program.add_file(lib_name, dir, None);
-        let info = ModuleDebugInfo { file, kind, functions: HashMap::new() };
+        let info = ModuleDebugInfo { file, kind, functions: HashMap::new(), lines: Vec::new() };
 
         self.modules.push_as(module, info);
     }
@@ -192,6 +198,42 @@ impl BinDebugInfo {
         scope
     }
 
+    /// Remember where the lines of a module's source code start, so that spans can be mapped to
+    /// line and column numbers
+    pub fn add_source(&mut self, module: key::Module, src: &str) {
+        if !self.modules.has(module) {
+            return;
+        }
+
+        let lines = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i as u32 + 1))
+            .collect();
+        self.modules[module].lines = lines;
+    }
+
+    /// The 1-indexed line and column of the start of a span
+    pub(crate) fn line_and_column(&self, module: key::Module, span: Span) -> (u64, u64) {
+        let lines = &self.modules[module].lines;
+        let line = lines.partition_point(|&start| start <= span.indice).max(1);
+        let start = lines.get(line - 1).copied().unwrap_or(0);
+        (line as u64, (span.indice - start) as u64 + 1)
+    }
+
+    /// The file, line and column of a location, or `None` if its module belongs to another
+    /// project root, as its file then isn't part of the same line program
+    pub(crate) fn source_loc(
+        &mut self,
+        root: Root,
+        location: Location,
+    ) -> Option<(FileId, u64, u64)> {
+        if self.find_root(location.module) != root {
+            return None;
+        }
+
+        let (line, column) = self.line_and_column(location.module, location.span);
+        Some((self.modules[location.module].file, line, column))
+    }
+
     pub(crate) fn find_root(&mut self, m: key::Module) -> Root {
         match self.modules[m].kind {
             ModuleKind::Member { parent } | ModuleKind::DirRoot { parent, .. } => {
@@ -211,6 +253,9 @@ pub struct ModuleDebugInfo<ItemKey> {
     // However; each declared function may result in multiple monomorphised functions in the final
     // binary. These are declared as DW_TAG_subprogram units under the item declaration UnitEntry
     pub functions: HashMap<ItemKey, UnitEntryId>,
+
+    // Byte offsets of the start of each line in the module's source code
+    pub lines: Vec<u32>,
 }
 
 pub enum ModuleKind {
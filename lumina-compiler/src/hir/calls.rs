@@ -0,0 +1,70 @@
+//! Queries over resolved names and calls, for code navigation and impact analysis
+
+use super::{Callable, Expr, FuncDefKind, HIR};
+use crate::prelude::*;
+use ast::{NFunc, Reference, Referent};
+
+impl<'s> HIR<'s> {
+    /// The declarations and uses of an item, in the order they were resolved
+    pub fn references_to(&self, referent: Referent) -> impl Iterator<Item = &Reference> + '_ {
+        self.references
+            .iter()
+            .filter(move |reference| reference.referent == referent)
+    }
+
+    /// Functions called or passed as function pointers by a function, including from its lambdas
+    ///
+    /// Methods called through type-dependent lookups such as `.map` aren't resolved until
+    /// monomorphisation and are left out.
+    pub fn callees(&self, func: M<key::Func>) -> Vec<M<NFunc>> {
+        let fdef = match &self.funcs[func] {
+            FuncDefKind::Defined(fdef)
+            | FuncDefKind::ImplMethod(_, fdef)
+            | FuncDefKind::TraitDefaultMethod(.., fdef) => fdef,
+            _ => return vec![],
+        };
+        let mut callees = vec![];
+
+        calls(&fdef.expr, &mut callees);
+        for body in fdef.lambdas.bodies.values() {
+            calls(body, &mut callees);
+        }
+
+        callees.into_iter().unique().collect()
+    }
+
+    /// Functions which call or pass `callee` as a function pointer
+    pub fn callers(&self, callee: M<NFunc>) -> Vec<M<key::Func>> {
+        self.funcs
+            .iter()
+            .filter(|func| self.callees(*func).contains(&callee))
+            .collect()
+    }
+}
+
+fn calls(expr: &Expr, out: &mut Vec<M<NFunc>>) {
+    match expr {
+        Expr::Call(callable, _, params) | Expr::Pass(callable, _, params) => {
+            if let Callable::Func(nfunc) = callable {
+                out.push(M(nfunc.module, nfunc.key));
+            }
+            params.iter().for_each(|p| calls(p, out));
+        }
+        Expr::PassFnptr(func, _) => out.push(func.map(NFunc::Key)),
+        Expr::PassExpr(inner)
+        | Expr::Access(_, inner, _)
+        | Expr::TupleAccess(inner, _)
+        | Expr::Cast(inner, _) => calls(inner, out),
+        Expr::Record(_, _, _, fields) => fields.iter().for_each(|(_, e)| calls(e, out)),
+        Expr::BuiltinOp(_, operands) => operands.iter().for_each(|e| calls(e, out)),
+        Expr::Tuple(elems)
+        | Expr::List(elems, _)
+        | Expr::Array(elems, _)
+        | Expr::GenericArray(elems, _) => elems.iter().for_each(|e| calls(e, out)),
+        Expr::Match(on, branches) => {
+            calls(on, out);
+            branches.iter().for_each(|(_, e)| calls(e, out));
+        }
+        Expr::Lit(_) | Expr::Poison => {}
+    }
+}
@@ -2,6 +2,7 @@ use super::pat::Pattern;
 use super::ty::{TypeAnnotation, TypeLower};
 use super::{FuncLower, ToAnnotate};
 use crate::prelude::*;
+use ast::attr::Associativity;
 use ast::{Entity, Mod, NFunc};
 use derive_more::Display;
 use derive_more::From;
@@ -96,7 +97,11 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
                 Expr::Lit(Literal::Int(*neg, *n, var))
             }
             parser::Expr::Lit(parser::Literal::Float(n)) => Expr::Lit(Literal::Float(*n)),
-            parser::Expr::Lit(parser::Literal::String(str)) => Expr::Lit(Literal::String(*str)),
+            parser::Expr::Lit(parser::Literal::String(str)) => {
+                let string = self.type_info.string;
+                super::check_std_feature(self.ast, self.module, expr.span, "strings", string);
+                Expr::Lit(Literal::String(*str))
+            }
             parser::Expr::Lit(parser::Literal::Char(c)) => Expr::Lit(Literal::Char(*c)),
             parser::Expr::Call(apath, params) => self.callable(apath.as_ref(), params, Expr::Call),
             parser::Expr::Lambda(patterns, params, body) => {
@@ -149,6 +154,8 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
                 Expr::Match(Box::new(on), branches)
             }
             parser::Expr::List(elems, ListLength::None) => {
+                let list = self.type_info.list;
+                super::check_std_feature(self.ast, self.module, expr.span, "lists", list);
                 let elems = self.exprs(elems);
                 let ivar = self
                     .vars()
@@ -166,6 +173,17 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
                     .map(|generic| Expr::GenericArray(elems, generic.tr(name.span)))
                     .unwrap_or(Expr::Poison)
             }
+            parser::Expr::List(_, ListLength::Unknown) => {
+                self.ast
+                    .sources
+                    .error("invalid slice")
+                    .m(self.module)
+                    .eline(expr.span, "slices can not be constructed from list syntax")
+                    .text("note: take a slice of an array with `builtin:array_slice` instead")
+                    .emit();
+
+                Expr::Poison
+            }
             parser::Expr::Tuple(elems) => {
                 let elems = self.exprs(elems);
                 Expr::Tuple(elems)
@@ -224,6 +242,9 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
     }
 
     fn pass(&mut self, to_pass: Tr<&parser::Expr<'s>>) -> Expr<'s> {
+        let closure = self.type_info.closure;
+        super::check_std_feature(self.ast, self.module, to_pass.span, "closures", closure);
+
         match &to_pass.value {
             // #(...)
             parser::Expr::Group(inner) => match &**inner {
@@ -289,6 +310,9 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
         let segments = apath.path.as_slice();
         match self.ast.lookups.resolve_func(self.module, segments) {
             Ok(Mod { key: ast::Entity::Func(ast::NFunc::Key(key)), module, .. }) => {
+                let nfunc = M(module, ast::NFunc::Key(key));
+                // skip the `#` so that the span starts at the path
+                super::check_func_use(self.ast, self.module, span.move_indice(1), nfunc);
                 let tanot = self.type_annotation(apath, None);
                 Expr::PassFnptr(key.inside(module), tanot)
             }
@@ -306,68 +330,88 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
                 Expr::Poison
             }
             Err(err) => {
-                self.ast
-                    .sources
-                    .emit_lookup_err(span, self.module, "function", err);
+                self.ast.emit_lookup_err(span, self.module, "function", err);
 
                 Expr::Poison
             }
         }
     }
 
+    // The left-hand side of a pipe is evaluated before the right-hand side, even though it's
+    // passed as the last parameter.
     fn dotpipe(&mut self, left: Tr<&parser::Expr<'s>>, right: Tr<&parser::Expr<'s>>) -> Expr<'s> {
+        let left = self.expr(left);
+        self.pipe(left, right)
+    }
+
+    fn pipe(&mut self, left: Tr<Expr<'s>>, right: Tr<&parser::Expr<'s>>) -> Expr<'s> {
         match right.value {
-            parser::Expr::Call(apath, params) => {
-                let mut params = self.exprs(params);
-                let left = self.expr(left);
+            parser::Expr::Call(apath, params) => self.evaluate_first(left, |this, left| {
+                let mut params = this.exprs(params);
                 params.push(left);
 
                 let path = apath.path.as_slice();
 
                 match path {
-                    ["true"] | ["false"] | ["builtin", ..] => self.emit_invalid_pipe(right.span),
-                    [name] => match self.try_resolve_local(&apath.path) {
+                    ["true"] | ["false"] | ["builtin", ..] => this.emit_invalid_pipe(right.span),
+                    [name] => match this.try_resolve_local(&apath.path) {
                         Some((c, to_anot)) => {
-                            let tanot = self.type_annotation_if_allowed(&apath, to_anot);
+                            let tanot = this.type_annotation_if_allowed(&apath, to_anot);
                             Expr::Call(c, tanot, params)
                         }
                         None => {
-                            let tanot = self.type_annotation(apath, None);
+                            let tanot = this.type_annotation(apath, None);
                             Expr::Call(Callable::TypeDependentLookup(*name), tanot, params)
                         }
                     },
 
-                    _ => self
+                    _ => this
                         .try_resolve_foreign(right.span, &apath.path)
                         .map(|(c, to_anot)| {
-                            let tanot = self.type_annotation_if_allowed(&apath, to_anot);
+                            let tanot = this.type_annotation_if_allowed(&apath, to_anot);
                             Expr::Call(c, tanot, params)
                         })
                         .unwrap_or(Expr::Poison),
                 }
-            }
+                .tr(right.span)
+            }),
             // 1 . handlers.f
             // --------------
             // let _ = #handlers.f in _ 1
-            parser::Expr::FieldAccess(..) => {
-                let yielded_function = self.pass(right).tr(right.span);
-                let intermediate = self.bindings.declare_nameless();
-                let param = self.expr(left);
-                self.bind_and_call(yielded_function, intermediate, vec![param])
-            }
+            parser::Expr::FieldAccess(..) => self.evaluate_first(left, |this, param| {
+                let yielded_function = this.pass(right).tr(right.span);
+                let intermediate = this.bindings.declare_nameless();
+                this.bind_and_call(yielded_function, intermediate, vec![param])
+                    .tr(right.span)
+            }),
             // 1 . (returns_function 0)
             // ------------------------
             // let _ = #(returns_function 0) in _ 1
-            parser::Expr::Group(inner) => {
-                let yielded_function = self.expr((&**inner).tr(right.span));
-                let intermediate = self.bindings.declare_nameless();
-                let param = self.expr(left); // TODO: Isn't this supposed to go *before* the rest?
-                self.bind_and_call(yielded_function, intermediate, vec![param])
-            }
+            parser::Expr::Group(inner) => self.evaluate_first(left, |this, param| {
+                let yielded_function = this.expr((&**inner).tr(right.span));
+                let intermediate = this.bindings.declare_nameless();
+                this.bind_and_call(yielded_function, intermediate, vec![param])
+                    .tr(right.span)
+            }),
             _ => self.emit_invalid_pipe(right.span),
         }
     }
 
+    // Bind a value so that it's evaluated before the expression using it, unless it's a literal
+    // or binding which has no effects to order
+    fn evaluate_first<F>(&mut self, value: Tr<Expr<'s>>, and_then: F) -> Expr<'s>
+    where
+        F: FnOnce(&mut Self, Tr<Expr<'s>>) -> Tr<Expr<'s>>,
+    {
+        match &value.value {
+            Expr::Lit(_) => and_then(self, value).value,
+            Expr::Call(Callable::Binding(_), _, params) if params.is_empty() => {
+                and_then(self, value).value
+            }
+            _ => self.bind_and_then(value, |this, _, value| and_then(this, value)),
+        }
+    }
+
     fn bind_and_call(
         &mut self,
         f: Tr<Expr<'s>>,
@@ -384,7 +428,7 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
             .sources
             .error("invalid pipe operator")
             .m(self.module)
-            .eline(span, "dot pipes may only be used to applicate functions")
+            .eline(span, "pipes may only be used to applicate functions")
             .emit();
 
         Expr::Poison
@@ -443,42 +487,58 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
         ops: &[(Tr<&'s str>, Tr<parser::Expr<'s>>)],
     ) -> Expr<'s> {
         let init = Side::Tail(init);
+        let mut mixed = vec![];
 
         let op = ops.iter().fold(init, |lhs, (op, rhs)| {
             const BOOL_AND_PREC: u32 = 2000;
             const BOOL_OR_PREC: u32 = 1900;
+            const PIPE_PREC: u32 = 0;
 
-            match **op {
+            let builtin = match **op {
                 // Since we want these to be lazy, they can't be user-defined operators
-                "&&" => {
-                    return lhs.handle_right(BOOL_AND_PREC, Either::Right("&&"), rhs.as_ref(), *op);
-                }
-                "||" => {
-                    return lhs.handle_right(BOOL_OR_PREC, Either::Right("||"), rhs.as_ref(), *op);
-                }
-                _ => {}
+                "&&" => Some(("&&", BOOL_AND_PREC)),
+                "||" => Some(("||", BOOL_OR_PREC)),
+                // `x |> f` is `x . f` applied after every other operator
+                "|>" => Some(("|>", PIPE_PREC)),
+                _ => None,
+            };
+
+            if let Some((name, precedence)) = builtin {
+                let fixity = Fixity { precedence, associativity: Associativity::Left };
+                let func = Either::Right(name);
+                return lhs.handle_right(fixity, func, rhs.as_ref(), *op, &mut mixed);
             }
 
             match self.ast.lookups.resolve_func(self.module, &[**op]) {
                 Ok(entity) => match entity.key {
                     Entity::Func(func) => {
-                        let precedence = match func {
-                            NFunc::Key(fkey) => {
-                                self.ast.entities.fattributes[fkey.inside(entity.module)].precedence
-                            }
+                        super::check_func_use(
+                            self.ast,
+                            self.module,
+                            op.span,
+                            M(entity.module, func),
+                        );
+
+                        let fkey = match func {
+                            NFunc::Key(fkey) => fkey,
                             NFunc::Method(trait_, method) => {
-                                let fkey =
-                                    self.ast.entities.methods[trait_.inside(entity.module)][method];
-                                self.ast.entities.fattributes[fkey.inside(entity.module)].precedence
+                                self.ast.entities.methods[trait_.inside(entity.module)][method]
                             }
                             NFunc::SumVar(_, _) => todo!(),
                             NFunc::Val(_) => todo!(),
-                        }
-                        .expect("non-operator used as operator");
+                        };
+                        let attributes = &self.ast.entities.fattributes[fkey.inside(entity.module)];
+
+                        let fixity = Fixity {
+                            precedence: attributes
+                                .precedence
+                                .expect("non-operator used as operator"),
+                            associativity: attributes.associativity,
+                        };
 
                         let func = Either::Left(entity.map(|_| func));
 
-                        lhs.handle_right(precedence, func, rhs.as_ref(), *op)
+                        lhs.handle_right(fixity, func, rhs.as_ref(), *op, &mut mixed)
                     }
                     _ => {
                         self.ast.sources.emit_wrong_entity(
@@ -493,19 +553,40 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
                 },
                 Err(err) => {
                     self.ast
-                        .sources
                         .emit_lookup_err(op.span, self.module, "operator", err);
                     return Side::Tail((&parser::Expr::Poison).tr(op.span));
                 }
             }
         });
 
+        for (left, right) in mixed {
+            self.ast
+                .sources
+                .error("mixed operator associativity")
+                .m(self.module)
+                .eline(right.span, "")
+                .iline(left.span, "")
+                .text(format!(
+                    "`{}` and `{}` share a precedence but group in different directions, add parentheses",
+                    *left, *right
+                ))
+                .emit();
+        }
+
         self.fold_optree_side(op).value
     }
 
     fn fold_optree<'o>(&mut self, op: OpTree<'o, 's>) -> Expr<'s> {
         let [left, right] = *op.sides;
         let left = self.fold_optree_side(left);
+
+        if let Either::Right("|>") = op.func {
+            return match right {
+                Side::Tail(right) => self.pipe(left, right),
+                Side::Op(inner) => self.emit_invalid_pipe(inner.raw.span),
+            };
+        }
+
         let right = self.fold_optree_side(right);
         match op.func {
             Either::Left(func) => Expr::Call(func.into(), TypeAnnotation::new(), vec![left, right]),
@@ -619,7 +700,8 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
         let mut type_anot = TypeAnnotation::new();
 
         let int_size = self.target.int_size();
-        let mut tlower = TypeLower::new(self.module, self.ast, int_size, &mut self.type_info);
+        let ast = self.ast;
+        let mut tlower = TypeLower::new(self.module, ast, int_size, &mut self.type_info);
 
         for (at, anot) in &apath.for_segments {
             if *at == (len - 1) {
@@ -631,8 +713,13 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
             }
 
             if *at == (len - 2) {
-                if let Some(_) = type_ {
+                if let Some(type_) = type_ {
                     for (span, name, ty) in &anot.assignments {
+                        if let Some(iname) = Self::impl_name(ast, type_, name, ty) {
+                            type_anot.for_impl = Some(iname.tr(*span));
+                            continue;
+                        }
+
                         let ty = tlower.ty(ty.tr(*span));
                         type_anot.for_type.push(((*name).tr(*span), ty));
                     }
@@ -648,6 +735,32 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
         type_anot
     }
 
+    /// `Trait(name)` selects a named implementation instead of annotating `self`, as long as
+    /// there's an implementation with that name
+    fn impl_name(
+        ast: &ast::AST<'s>,
+        type_: M<key::TypeKind>,
+        name: &str,
+        ty: &parser::Type<'s>,
+    ) -> Option<&'s str> {
+        match (type_.1, ty) {
+            (key::TypeKind::Trait(_), parser::Type::Defined(apath, params))
+                if name == "self"
+                    && params.is_empty()
+                    && apath.for_segments.is_empty()
+                    && apath.path.is_name() =>
+            {
+                let iname = apath.path.as_name().unwrap();
+                let impls = &ast.entities.impls;
+                impls
+                    .iter()
+                    .any(|imp| impls[imp].attributes.name.is_some_and(|n| *n == iname))
+                    .then_some(iname)
+            }
+            _ => None,
+        }
+    }
+
     fn desugar_record(
         &mut self,
         span: Span,
@@ -826,46 +939,60 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
 #[derive(Clone, Debug)]
 struct OpTree<'a, 's> {
     func: Either<Mod<NFunc>, &'static str>,
-    precedence: u32,
+    fixity: Fixity,
     raw: Tr<&'s str>,
     sides: Box<[Side<'a, 's>; 2]>,
 }
 
+#[derive(Clone, Copy, Debug)]
+struct Fixity {
+    precedence: u32,
+    associativity: Associativity,
+}
+
+impl Fixity {
+    // Whether this operator takes the right-hand side of `left` as its left-hand side. The
+    // associativity of the operator to the right decides operators of the same precedence.
+    fn binds_tighter_than(self, left: Fixity) -> bool {
+        self.precedence > left.precedence
+            || (self.precedence == left.precedence && self.associativity == Associativity::Right)
+    }
+
+    // Operators of the same precedence but different associativity have no sensible grouping
+    fn mixes_with(self, left: Fixity) -> bool {
+        self.precedence == left.precedence && self.associativity != left.associativity
+    }
+}
+
 impl<'a, 's> Side<'a, 's> {
+    // Operators are folded from left to right, with each new operator descending the right-hand
+    // spine of the tree for as long as it binds tighter than the operators already in it.
     fn handle_right(
         self,
-        precedence: u32,
+        fixity: Fixity,
         op: Either<Mod<NFunc>, &'static str>,
         rhs: Tr<&'a parser::Expr<'s>>,
         raw: Tr<&'s str>,
+        mixed: &mut Vec<(Tr<&'s str>, Tr<&'s str>)>,
     ) -> Side<'a, 's> {
+        if let Side::Op(lop) = &self {
+            if fixity.mixes_with(lop.fixity) {
+                mixed.push((lop.raw, raw));
+            }
+        }
+
         match self {
-            Side::Op(mut lop) => {
-                if precedence > lop.precedence {
-                    let previous_rhs = lop.sides[1].clone();
-                    let this = OpTree {
-                        func: op,
-                        precedence,
-                        raw,
-                        sides: Box::new([previous_rhs, Side::Tail(rhs)]),
-                    };
-                    lop.sides[1] = Side::Op(this);
-                    Side::Op(lop)
-                } else {
-                    let this = OpTree {
-                        func: op,
-                        precedence,
-                        raw,
-                        sides: Box::new([Side::Op(lop), Side::Tail(rhs)]),
-                    };
-                    Side::Op(this)
-                }
+            Side::Op(mut lop) if fixity.binds_tighter_than(lop.fixity) => {
+                let [lhs, previous_rhs] = *lop.sides;
+                let rhs = previous_rhs.handle_right(fixity, op, rhs, raw, mixed);
+                lop.sides = Box::new([lhs, rhs]);
+                Side::Op(lop)
             }
-            Side::Tail(lhs) => Side::Op(OpTree {
+            lhs => Side::Op(OpTree {
                 func: op,
-                precedence,
+                fixity,
                 raw,
-                sides: Box::new([Side::Tail(lhs), Side::Tail(rhs)]),
+                sides: Box::new([lhs, Side::Tail(rhs)]),
             }),
         }
     }
@@ -958,12 +1085,16 @@ impl<'s> fmt::Display for Expr<'s> {
 
 impl<'s> fmt::Debug for TypeAnnotation<'s> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.for_type.is_empty() && self.for_entity.is_empty() {
+        if self.for_type.is_empty() && self.for_entity.is_empty() && self.for_impl.is_none() {
             return Ok(());
         }
 
         write!(f, "(")?;
 
+        if let Some(name) = &self.for_impl {
+            write!(f, "{} {name}, ", "impl".keyword())?;
+        }
+
         for (name, ty) in self.for_type.iter() {
             write!(f, "·{name} {} {ty}, ", "as".keyword())?;
         }
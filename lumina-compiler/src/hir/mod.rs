@@ -19,20 +19,21 @@ use ast::{Mod, AST};
 use derive_new::new;
 use lumina_parser as parser;
 use lumina_typesystem::{
-    Constraint, Downgrade, Forall, Generic, GenericKind, IType, ImplIndex, Inference, Static, TEnv,
-    Transformer, Ty, Type,
+    Constraint, Container, Downgrade, Forall, Generic, GenericKind, IType, ImplIndex, Inference,
+    Static, TEnv, Transformer, Ty, Type,
 };
 use lumina_util::{Highlighting, Identifier};
 use std::cell::RefCell;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use tracing::info_span;
 
+mod calls;
 mod expr;
 pub use expr::{Callable, Expr, Literal};
 mod pat;
-pub use pat::{Pattern, StringPattern};
+pub use pat::{ByteLen, Pattern, Segment, StringPattern};
 mod scope;
 use scope::Bindings;
 mod ty;
@@ -55,6 +56,8 @@ pub struct HIR<'s> {
     pub impltors: MMap<key::Impl, Tr<Type>>,
     pub iassoc: MMap<key::Impl, Vec<(Tr<&'s str>, Tr<Type>)>>,
     pub itraits: MMap<key::Impl, (M<key::Trait>, Vec<Type>)>,
+    /// Implementations given a name with `@[name ...]`, which are left out of the `ImplIndex`
+    pub named_impls: HashMap<(M<key::Trait>, &'s str), Vec<M<key::Impl>>>,
 
     pub type_repr: TypeRepr,
 
@@ -65,11 +68,17 @@ pub struct HIR<'s> {
     pub vnames: MMap<key::Sum, Map<key::Variant, Tr<&'s str>>>,
     pub func_names: MMap<key::Func, Tr<&'s str>>,
     pub val_initializers: MMap<key::Val, M<key::Func>>,
+    pub const_vals: HashSet<M<key::Val>>,
+    pub static_asserts: HashMap<M<key::Val>, String>,
+    pub thread_local_vals: HashSet<M<key::Val>>,
+    pub tests: Vec<(M<key::Func>, ast::TestKind, Option<ast::ExpectOutput>)>,
     pub sources: ast::Sources,
     pub lookups: ast::Lookups<'s>, // Still needed because of type-dependent lookups
     pub methods: MMap<key::Trait, Map<key::Method, key::Func>>,
     pub imethods: MMap<key::Impl, Map<key::Method, key::Func>>,
     pub assoc_names: MMap<key::Trait, Map<key::AssociatedType, Tr<&'s str>>>,
+    /// Declarations and resolved uses of functions and types
+    pub references: Vec<ast::Reference>,
 }
 
 type LangItems<'s> = HashMap<&'s str, M<key::TypeKind>>;
@@ -81,6 +90,11 @@ impl TypeRepr {
         self.0.insert(key.map(K::into), repr);
     }
 
+    /// Whether the representation was given with an attribute rather than being the default
+    pub fn is_specified<K: Into<key::TypeKind>>(&self, key: M<K>) -> bool {
+        self.0.contains_key(&key.map(K::into))
+    }
+
     pub fn get<K: Into<key::TypeKind>>(&self, key: M<K>) -> ast::attr::Repr {
         let key = key.map(K::into);
         self.0.get(&key).copied().unwrap_or_else(|| match key.1 {
@@ -185,17 +199,99 @@ pub fn run<'a, 's>(
     }
 
     // Build an index of the implementations
+    //
+    // Named implementations are only ever selected explicitly, so they're kept separately
     let mut iquery = ImplIndex::new();
+    let mut named_impls: HashMap<_, Vec<_>> = HashMap::new();
     for module in ast.sources.modules() {
         for ikey in impls.iter_module(module) {
             let impltor = &impltors[ikey];
             let trait_ = itraits[ikey].0;
-            iquery.insert(trait_, &impltor, ikey);
+            match ast.entities.impls[ikey].attributes.name {
+                Some(name) => named_impls.entry((trait_, *name)).or_default().push(ikey),
+                None => iquery.insert(trait_, &impltor, ikey),
+            }
+        }
+    }
+
+    // Overlapping implementations are only allowed if one is more specific than the other
+    let get_impl_data = &|ikey: M<key::Impl>| {
+        let (trait_, trait_params) = &itraits[ikey];
+        (
+            *trait_,
+            &impls[ikey],
+            &*impltors[ikey],
+            trait_params.as_slice(),
+        )
+    };
+    for (a, b) in iquery.conflicts(get_impl_data) {
+        let trait_ = *traits[itraits[a].0].0;
+        ast.sources
+            .error("conflicting implementations")
+            .m(b.0)
+            .eline(
+                impltors[b].span,
+                format!("this implementation of `{trait_}` overlaps with another"),
+            )
+            .m(a.0)
+            .iline(impltors[a].span, "previously implemented here")
+            .text("overlapping implementations are only allowed if one is more specific than the other")
+            .text("alternatively, give one of them a name with `@[name ...]` and select it explicitly with `Trait(name):method`")
+            .emit();
+    }
+
+    for module in ast.sources.modules() {
+        for ikey in impls.iter_module(module) {
+            check_impl_location(&ast, &traits, ikey, itraits[ikey].0, &impltors[ikey]);
         }
     }
 
+    ast.record_declarations(|impl_| itraits[impl_].0);
+
     let func_names = ast.entities.fheaders.map(|_, header| header.name);
 
+    let static_asserts: HashMap<_, _> = ast
+        .entities
+        .vals
+        .iter()
+        .filter_map(|val| {
+            let attributes = &ast.entities.fattributes[ast.entities.vals[val]];
+            attributes.static_assert.clone().map(|msg| (val, msg))
+        })
+        .collect();
+
+    // Static assertions are evaluated at compile time like any other const val, as are vals placed
+    // in custom sections since their contents have to be in the object file
+    let const_vals = ast
+        .entities
+        .vals
+        .iter()
+        .filter(|val| {
+            let attributes = &ast.entities.fattributes[ast.entities.vals[*val]];
+            attributes.const_
+                || attributes.linkage.section.is_some()
+                || static_asserts.contains_key(val)
+        })
+        .collect();
+
+    let thread_local_vals = ast
+        .entities
+        .vals
+        .iter()
+        .filter(|val| ast.entities.fattributes[ast.entities.vals[*val]].thread_local)
+        .collect();
+
+    let tests = ast
+        .entities
+        .fattributes
+        .iter()
+        .filter_map(|func| {
+            let attributes = &ast.entities.fattributes[func];
+            let kind = attributes.test?;
+            Some((func, kind, attributes.expect_output.clone()))
+        })
+        .collect();
+
     let assoc_names = ast
         .entities
         .associated_types
@@ -206,8 +302,13 @@ pub fn run<'a, 's>(
         HIR {
             fnames: ast.entities.field_names,
             val_initializers: ast.entities.vals,
+            const_vals,
+            static_asserts,
+            thread_local_vals,
+            tests,
             func_names,
             assoc_names,
+            references: ast.references.take(),
             sources: ast.sources,
             lookups: ast.lookups,
             methods: ast.entities.methods,
@@ -225,6 +326,7 @@ pub fn run<'a, 's>(
             impltors,
             iassoc,
             itraits,
+            named_impls,
             langitems,
         },
         tenvs,
@@ -304,6 +406,8 @@ fn lower_func<'a, 's>(
     let header = &ast.entities.fheaders[func];
     let attributes = &ast.entities.fattributes[func];
     let no_mangle = attributes.no_mangle;
+    let export = attributes.export.clone();
+    let linkage = attributes.linkage.clone();
 
     let _span = info_span!(
         "lowering func",
@@ -313,24 +417,59 @@ fn lower_func<'a, 's>(
     );
     let _handle = _span.enter();
 
+    if let Some(span) = header.typing.as_ref().and_then(|typing| typing.variadic) {
+        if !matches!(&ast.entities.fbodies[func], ast::FuncBody::Extern { .. }) {
+            err_invalid_variadic(module, ast, span);
+        }
+    }
+
     let flangitems = lower_langitems(ast, module, &attributes.shared.lang_items);
 
     let list = list_from_langs(&flangitems, &langitems, &pinfo);
     let string = pinfo.string.map(key::TypeKind::Record);
+    let closure = pinfo.closure.map(key::TypeKind::Trait);
 
-    let mut tinfo = TypeEnvInfo::new(true, string, list);
+    let mut tinfo = TypeEnvInfo::new(true, string, list, closure);
 
     match &ast.entities.fbodies[func] {
-        ast::FuncBody::Extern { link_name } => {
+        ast::FuncBody::Extern { link_name, data, library } => {
             let typing = ty::TypeLower::new(module, ast, target.int_size(), &mut tinfo)
                 .typing_or_emit_and_poison(header, "extern functions");
-            let kind = FuncDefKind::Extern { link_name: link_name.clone(), typing };
+
+            let is_pointer = matches!(
+                &*typing.returns,
+                Ty::Container(lumina_typesystem::Container::Pointer, _)
+            );
+            let variadic = header.typing.as_ref().is_some_and(|t| t.variadic.is_some());
+            if *data && (!typing.params.is_empty() || !is_pointer || variadic) {
+                ast.sources
+                    .error("invalid extern data")
+                    .m(module)
+                    .eline(
+                        header.name.span,
+                        "extern data must be declared as a pointer to its type",
+                    )
+                    .emit();
+            }
+
+            let (link_name, library) = (link_name.clone(), library.clone());
+            let kind = FuncDefKind::Extern { link_name, data: *data, variadic, library, typing };
             (kind, TEnv::new())
         }
         ast::FuncBody::Val(body, _) | ast::FuncBody::Func(body) => {
             let mut tinfo = tinfo.inference(TEnv::new());
             let (fdef, env) = FuncLower::new(module, ast, &mut tinfo, &body.where_binds, target)
-                .lower_func(&header, &body, no_mangle);
+                .lower_func(&header, &body, no_mangle, export, linkage);
+
+            // Foreign code can only call a single instantiation of the function
+            if attributes.export.is_some() && !fdef.forall.borrow().generics.is_empty() {
+                ast.sources
+                    .error("invalid export")
+                    .m(module)
+                    .eline(header.name.span, "exported functions can not be generic")
+                    .emit();
+            }
+
             (FuncDefKind::Defined(fdef), env)
         }
         ast::FuncBody::TraitMethod(Some(body), tr) => {
@@ -338,7 +477,7 @@ fn lower_func<'a, 's>(
             tinfo.enter_type_or_impl_or_method(tforalls[*tr].1.clone(), GenericKind::Parent);
             tinfo.self_handler = SelfHandler::Direct;
             let (fdef, env) = FuncLower::new(module, ast, &mut tinfo, &body.where_binds, target)
-                .lower_func(&header, &body, no_mangle);
+                .lower_func(&header, &body, no_mangle, export, linkage);
 
             let kind = disallow_inference_in_trait_default(module, ast, *tr, fdef);
             (kind, env)
@@ -351,7 +490,7 @@ fn lower_func<'a, 's>(
             tinfo.enter_type_or_impl_or_method(iforalls[*imp].clone(), GenericKind::Parent);
             tinfo.self_handler = SelfHandler::Direct;
             let (fdef, env) = FuncLower::new(module, ast, &mut tinfo, &body.where_binds, target)
-                .lower_func(&header, &body, no_mangle);
+                .lower_func(&header, &body, no_mangle, export, linkage);
             (FuncDefKind::ImplMethod(*imp, fdef), env)
         }
         ast::FuncBody::TraitMethod(None, trait_) => {
@@ -375,8 +514,9 @@ fn tydef_type_env<'s, K: Into<key::TypeKind>>(
     generics: &Map<key::Generic, &'s str>,
     list: M<key::TypeKind>,
     string: M<key::TypeKind>,
+    closure: M<key::TypeKind>,
 ) -> TypeEnvInfo<'s> {
-    let mut tinfo = TypeEnvInfo::new(false, string, list);
+    let mut tinfo = TypeEnvInfo::new(false, string, list, closure);
     tinfo.self_handler = SelfHandler::Substituted(kind.map(Into::into));
     let forall = Forall::from_names(generics.values().copied());
 
@@ -414,8 +554,9 @@ fn lower_sum<'a, 's>(
 
     let list = list_from_langs(&tlangs, lang, pinfo);
     let string = pinfo.string.map(key::TypeKind::Record);
+    let closure = pinfo.closure.map(key::TypeKind::Trait);
 
-    let mut tinfo = tydef_type_env(sum, &ty.header.type_params, list, string);
+    let mut tinfo = tydef_type_env(sum, &ty.header.type_params, list, string, closure);
 
     let mut tlower = ty::TypeLower::new(sum.0, ast, target.int_size(), &mut tinfo);
 
@@ -443,8 +584,9 @@ fn lower_record<'a, 's>(
 
     let list = list_from_langs(&tlangs, lang, pinfo);
     let string = pinfo.string.map(key::TypeKind::Record);
+    let closure = pinfo.closure.map(key::TypeKind::Trait);
 
-    let mut tinfo = tydef_type_env(rec, &ty.header.type_params, list, string);
+    let mut tinfo = tydef_type_env(rec, &ty.header.type_params, list, string, closure);
 
     let mut tlower = ty::TypeLower::new(rec.0, ast, target.int_size(), &mut tinfo);
 
@@ -479,7 +621,8 @@ fn lower_trait<'a, 's>(
 
     let list = list_from_langs(&tlangs, langitems, pinfo);
     let string = pinfo.string.map(key::TypeKind::Record);
-    let mut tinfo = TypeEnvInfo::new(false, string, list);
+    let closure = pinfo.closure.map(key::TypeKind::Trait);
+    let mut tinfo = TypeEnvInfo::new(false, string, list, closure);
     let forall = Forall::from_names(ty.header.type_params.values().copied());
     tinfo.enter_type_or_impl_or_method(forall, GenericKind::Parent);
     tinfo.self_handler = SelfHandler::Direct;
@@ -506,6 +649,11 @@ fn lower_trait<'a, 's>(
 pub enum FuncDefKind<'s> {
     Extern {
         link_name: String,
+        data: bool,
+        /// Takes additional parameters of any type after those in its typing
+        variadic: bool,
+        /// Native library to link against when the function is used
+        library: Option<String>,
         typing: Typing<Type>,
     },
     Defined(FuncDef<'s>),
@@ -536,6 +684,8 @@ pub struct FuncDef<'s> {
     pub expr: Tr<Expr<'s>>,
 
     pub no_mangle: bool,
+    pub export: Option<String>,
+    pub linkage: ast::Linkage,
 
     #[new(default)]
     pub lambdas: Lambdas<'s>,
@@ -663,6 +813,8 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
         header: &parser::func::Header<'s>,
         body: &parser::func::Body<'s>,
         no_mangle: bool,
+        export: Option<String>,
+        linkage: ast::Linkage,
     ) -> (FuncDef<'s>, TEnv<'s>) {
         let forall = generics_from_con(&header.when);
         self.type_info.enter_function(forall);
@@ -679,7 +831,16 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
 
         self.type_info.declare_generics = false;
 
-        let (mut params, expr) = self.lower_func_body(header, body);
+        let (mut params, mut expr) = self.lower_func_body(header, body);
+
+        if let Some(some) = header.typing.as_ref().and_then(|typing| typing.opaque) {
+            match self.type_info.self_handler {
+                // Every implementation returns a type of its own, so methods box them into an object
+                // of the trait to keep the signatures of the trait and its implementations the same
+                SelfHandler::Direct => expr = self.boxed_return(some, &typing.returns, expr),
+                _ => self.opaque_return(some, &mut typing.returns),
+            }
+        }
 
         check_param_len(
             self.module,
@@ -694,7 +855,16 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
 
         let list = self.type_info.list;
 
-        let mut func = FuncDef::new(RefCell::new(forall), typing, list, params, expr, no_mangle);
+        let mut func = FuncDef::new(
+            RefCell::new(forall),
+            typing,
+            list,
+            params,
+            expr,
+            no_mangle,
+            export,
+            linkage,
+        );
         func.lambdas = self.lambdas;
 
         // Copy the captures of where-bindings to lambdas which use them
@@ -725,6 +895,62 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
         (func, env)
     }
 
+    // The type of the returned value is inferred from the body and only constrained to implement
+    // the trait, which lets callers statically dispatch on it without it being named.
+    //
+    // Closures already are objects of their trait, so those are kept as they are.
+    fn opaque_return(&mut self, some: Span, returns: &mut Tr<IType>) {
+        match &returns.value {
+            Ty::Container(
+                Container::Defined(M(module, key::TypeKind::Trait(trait_)), _),
+                params,
+            ) => {
+                let con = Constraint {
+                    span: returns.span,
+                    trait_: trait_.inside(*module),
+                    params: params.clone(),
+                };
+
+                let vars = self.type_info.inference_mut().unwrap();
+                let var = vars.var(returns.span);
+                vars.add_trait_constraint(var, con);
+
+                returns.value = IType::infer(var);
+            }
+            Ty::Container(Container::Closure, _) => {}
+            _ => self.err_invalid_opaque(some, returns.span),
+        }
+    }
+
+    // The returned value is cast into an object of the trait
+    fn boxed_return(
+        &mut self,
+        some: Span,
+        returns: &Tr<IType>,
+        expr: Tr<Expr<'s>>,
+    ) -> Tr<Expr<'s>> {
+        match &returns.value {
+            Ty::Container(Container::Defined(M(_, key::TypeKind::Trait(_)), _), _)
+            | Ty::Container(Container::Closure, _) => {
+                let span = expr.span;
+                Expr::Cast(Box::new(expr), returns.clone()).tr(span)
+            }
+            _ => {
+                self.err_invalid_opaque(some, returns.span);
+                expr
+            }
+        }
+    }
+
+    fn err_invalid_opaque(&self, some: Span, ty: Span) {
+        self.ast
+            .sources
+            .error("invalid opaque type")
+            .m(self.module)
+            .eline(some.extend(ty), "`some` must be followed by a trait")
+            .emit();
+    }
+
     fn lower_func_body(
         &mut self,
         header: &parser::func::Header<'s>,
@@ -736,6 +962,10 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
 
             self.type_info.enter_lambda(lkey, Forall::new(0));
 
+            if let Some(span) = fdecl.header.typing.as_ref().and_then(|t| t.variadic) {
+                err_invalid_variadic(self.module, self.ast, span);
+            }
+
             let typing = self
                 .to_type_lower()
                 .typing_or_inferred(&fdecl.header, false);
@@ -842,6 +1072,7 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
         match self.ast.lookups.resolve_func(self.module, path) {
             Ok(entity) => match entity.key {
                 ast::Entity::Func(nfunc) => {
+                    check_func_use(self.ast, self.module, span, M(entity.module, nfunc));
                     let type_ = match nfunc {
                         ast::NFunc::SumVar(key, _) => {
                             Some(M(entity.module, key::TypeKind::Sum(key)))
@@ -897,6 +1128,10 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
                         }
                     };
 
+                    check_func_use(self.ast, self.module, span, M(module, nfunc));
+                    let referent = ast::Referent::Type(M(module, type_));
+                    self.ast.record_use(self.module, span, 1, referent);
+
                     let to_anot = ToAnnotate::Some(Some(M(entity.module, type_)));
                     Some((Callable::Func(entity.map(|_| nfunc)), to_anot))
                 }
@@ -915,7 +1150,6 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
             },
             Err(err) => {
                 self.ast
-                    .sources
                     .emit_lookup_err(span, self.module, "function or binding", err);
 
                 None
@@ -945,8 +1179,9 @@ fn lower_impl<'a, 's>(
 
     let list = list_from_langs(langitems, &HashMap::new(), pinfo);
     let string = pinfo.string.map(key::TypeKind::Record);
+    let closure = pinfo.closure.map(key::TypeKind::Trait);
 
-    let mut tinfo = TypeEnvInfo::new(true, string, list);
+    let mut tinfo = TypeEnvInfo::new(true, string, list, closure);
 
     let impl_forall = generics_from_con(&imp.header.when);
     tinfo.enter_type_or_impl_or_method(impl_forall, GenericKind::Parent);
@@ -985,12 +1220,189 @@ fn lower_impl<'a, 's>(
     (trait_, forall, impltor, associations)
 }
 
+/// Reject implementations which aren't in the module of either the trait or the implementor, and
+/// implementations of sealed traits from outside of the library which declares the trait
+///
+/// The standard library provides the implementations for built-in types, so it's exempt from the
+/// orphan rule. So are named implementations, since they're never selected implicitly.
+fn check_impl_location<'s>(
+    ast: &AST<'s>,
+    traits: &MMap<key::Trait, (Tr<&'s str>, Forall<'s, Static>)>,
+    ikey: M<key::Impl>,
+    trait_: M<key::Trait>,
+    impltor: &Tr<Type>,
+) {
+    let module = ikey.0;
+    if module == trait_.0 {
+        return;
+    }
+
+    let name = traits[trait_].0;
+
+    if ast.entities.traits[trait_].attributes.sealed
+        && !ast.lookups.are_members_of_same_project(&[module, trait_.0])
+    {
+        ast.sources
+            .error("sealed trait")
+            .m(module)
+            .eline(
+                impltor.span,
+                format!(
+                    "`{}` can only be implemented by the library which declares it",
+                    *name
+                ),
+            )
+            .m(trait_.0)
+            .iline(name.span, "declared as `@[sealed]` here")
+            .emit();
+        return;
+    }
+
+    let type_module = match &impltor.value {
+        Ty::Container(Container::Defined(M(m, _), _), _) => Some(*m),
+        _ => None,
+    };
+
+    if type_module == Some(module)
+        || ast.lookups.is_stdlib(module)
+        || ast.entities.impls[ikey].attributes.name.is_some()
+    {
+        return;
+    }
+
+    ast.sources
+        .error("orphan implementation")
+        .m(module)
+        .eline(
+            impltor.span,
+            format!(
+                "`{}` is implemented outside of the module of both the trait and the type",
+                *name
+            ),
+        )
+        .m(trait_.0)
+        .iline(name.span, "trait declared here")
+        .text("implementations must be in the same module as either the trait or the type they're for")
+        .emit();
+}
+
 enum ToAnnotate {
     Some(Option<M<key::TypeKind>>),
     None,
 }
 
+/// Record a use of a function or val, warn about it if it's marked with `@[deprecated]`, and reject
+/// it if it's marked with `@[std_only]`
+fn check_func_use(ast: &AST, module: key::Module, span: Span, M(m, nfunc): M<ast::NFunc>) {
+    ast.record_use(module, span, 0, ast::Referent::Func(M(m, nfunc)));
+
+    let func = match nfunc {
+        ast::NFunc::Key(func) => func.inside(m),
+        ast::NFunc::Method(trait_, method) => {
+            ast.entities.methods[trait_.inside(m)][method].inside(m)
+        }
+        ast::NFunc::Val(val) => ast.entities.vals[val.inside(m)],
+        ast::NFunc::SumVar(sum, _) => {
+            return check_type_attributes(ast, module, span, M(m, key::TypeKind::Sum(sum)))
+        }
+    };
+
+    let attributes = &ast.entities.fattributes[func].shared;
+    let name = ast.entities.fheaders[func].name;
+
+    if let Some(message) = attributes.deprecated {
+        ast.sources
+            .emit_deprecated_use(module, span, "function", (func.0, name), message);
+    }
+
+    // stubs are allowed to refer to each other
+    if attributes.std_only && module != func.0 {
+        let what = format!("the function `{name}` is only available with the standard library");
+        ast.sources
+            .emit_std_only_use(module, span, what, (func.0, name));
+    }
+}
+
+/// Record a use of a type, warn about it if it's marked with `@[deprecated]`, and reject it if it's
+/// marked with `@[std_only]`
+fn check_type_use(ast: &AST, module: key::Module, span: Span, kind: M<key::TypeKind>) {
+    ast.record_use(module, span, 0, ast::Referent::Type(kind));
+    check_type_attributes(ast, module, span, kind);
+}
+
+fn check_type_attributes(ast: &AST, module: key::Module, span: Span, kind: M<key::TypeKind>) {
+    let ty = ast.entities.header_of_ty(kind);
+    let name = ty.header.name.tr(ty.header.span);
+
+    if let Some(message) = ty.attributes.shared.deprecated {
+        ast.sources
+            .emit_deprecated_use(module, span, "type", (kind.0, name), message);
+    }
+
+    if ty.attributes.shared.std_only && module != kind.0 {
+        let what = format!("the type `{name}` is only available with the standard library");
+        ast.sources
+            .emit_std_only_use(module, span, what, (kind.0, name));
+    }
+}
+
+/// Reject language features which are built on top of a lang item marked with `@[std_only]`
+fn check_std_feature(
+    ast: &AST,
+    module: key::Module,
+    span: Span,
+    feature: &str,
+    kind: M<key::TypeKind>,
+) {
+    let ty = ast.entities.header_of_ty(kind);
+    if ty.attributes.shared.std_only && module != kind.0 {
+        let name = ty.header.name.tr(ty.header.span);
+        let what = format!("{feature} require `{name}` from the standard library");
+        ast.sources
+            .emit_std_only_use(module, span, what, (kind.0, name));
+    }
+}
+
 impl ast::Sources {
+    fn emit_deprecated_use(
+        &self,
+        module: key::Module,
+        span: Span,
+        kind: &str,
+        (def_module, name): (key::Module, Tr<&str>),
+        message: &str,
+    ) {
+        let mut warning = self
+            .warning("use of deprecated item")
+            .m(module)
+            .eline(span, format!("the {kind} `{name}` is deprecated"));
+
+        if !message.is_empty() {
+            warning = warning.text(message);
+        }
+
+        warning
+            .m(def_module)
+            .iline(name.span, "marked as deprecated here")
+            .emit()
+    }
+
+    fn emit_std_only_use(
+        &self,
+        module: key::Module,
+        span: Span,
+        what: String,
+        (def_module, name): (key::Module, Tr<&str>),
+    ) {
+        self.error("standard library not available")
+            .m(module)
+            .eline(span, what)
+            .m(def_module)
+            .iline(name.span, "declared as a stub here")
+            .text("the project is built with `no_std`, remove it from the project config to use the full standard library")
+            .emit()
+    }
+
     fn emit_identifier_not_found<'s>(
         &self,
         module: key::Module,
@@ -1121,6 +1533,17 @@ fn check_param_len<'s>(
     }
 }
 
+fn err_invalid_variadic(module: key::Module, ast: &AST, span: Span) {
+    ast.sources
+        .error("invalid variadic parameters")
+        .m(module)
+        .eline(
+            span,
+            "only extern functions can take a variable amount of parameters",
+        )
+        .emit();
+}
+
 fn disallow_inference_in_trait_default<'s>(
     module: key::Module,
     ast: &AST<'s>,
@@ -2,7 +2,7 @@ use super::{Callable, Expr, FuncLower, IType, TypeAnnotation};
 use crate::prelude::*;
 use ast::{Entity, Mod, NFunc};
 use lumina_parser as parser;
-use lumina_typesystem::{Generic, Var};
+use lumina_typesystem::{Generic, IntSize, Var};
 use lumina_util::{Highlighting, Identifier, ParamFmt};
 use parser::ListLength;
 use std::fmt;
@@ -27,16 +27,36 @@ pub enum Pattern<'s> {
     GenericArray(Vec<Tr<Self>>, Tr<Generic>),
     Bool(bool),
     String(Vec<StringPattern<'s>>),
+    Binary(Vec<Tr<Segment>>),
     Poison,
 }
 
 #[derive(Clone, Debug)]
 pub enum StringPattern<'s> {
     Literal(&'s str),
+    Suffix(&'s str),
     Extractor(Extractor<'s>),
     Wildcard(Tr<key::Bind>),
 }
 
+/// A segment of a binary pattern, read from the buffer in order
+#[derive(Clone, Debug)]
+pub enum Segment {
+    // Big-endian integer
+    Int(IntSize, Option<Tr<key::Bind>>),
+    Literal(IntSize, i128),
+    Bytes(ByteLen, Option<Tr<key::Bind>>),
+    // The remaining bytes, only allowed as the last segment
+    Rest(Option<Tr<key::Bind>>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ByteLen {
+    Const(u64),
+    // Bound by an earlier integer segment of the same pattern
+    Bind(key::Bind),
+}
+
 #[derive(Clone, Debug)]
 pub struct Extractor<'s> {
     pub call: Callable<'s>,
@@ -67,13 +87,13 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
                 if is_str {
                     let bind = self.bindings.declare(identifier.as_name().unwrap());
                     let init = StringPattern::Wildcard(bind.tr(pat.span));
-                    self.pat_strings(init, params)
+                    self.pat_strings(pat.span, init, params)
                 } else {
                     self.pat_name(pat.span, identifier, params)
                 }
             }
             parser::Pattern::String(str, params) => {
-                self.pat_strings(StringPattern::Literal(*str), params)
+                self.pat_strings(pat.span, StringPattern::Literal(*str), params)
             }
             parser::Pattern::Char(str, params) => {
                 assert!(params.is_empty(), "TODO: char literal in string patterns");
@@ -82,7 +102,7 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
             parser::Pattern::Extractor(expr, bind, params) => {
                 match self.extractor((**expr).as_ref(), *bind) {
                     None => Pattern::Poison,
-                    Some(spat) => self.pat_strings(spat, params),
+                    Some(spat) => self.pat_strings(pat.span, spat, params),
                 }
             }
             parser::Pattern::Fields(init, fields) => self.pat_record(pat.span, init, fields),
@@ -97,6 +117,16 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
                     .unwrap_or(Pattern::Poison)
             },
             parser::Pattern::List(elems, ListLength::None) => self.pat_list(pat.span, elems),
+            parser::Pattern::List(_, ListLength::Unknown) => {
+                self.ast
+                    .sources
+                    .error("invalid slice")
+                    .m(self.module)
+                    .eline(pat.span, "slices can not be matched on")
+                    .emit();
+
+                Pattern::Poison
+            }
             parser::Pattern::Tuple(elems) => {
                 let params = self.pats(elems);
                 Pattern::Tuple(params)
@@ -114,7 +144,12 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
                 Pattern::Int(*bound, var)
             }
             parser::Pattern::Float(_) => todo!(),
+            parser::Pattern::Operators { init, ops } if ops.iter().all(|(op, _)| *op == "++") => {
+                let operands = std::iter::once(&**init).chain(ops.iter().map(|(_, pat)| pat));
+                self.pat_concat(pat.span, operands)
+            }
             parser::Pattern::Operators { .. } => panic!("ET: unexpected operator"),
+            parser::Pattern::Binary(segments) => self.pat_binary(segments),
             parser::Pattern::Poison => dbg!(Pattern::Poison),
         }
         .tr(pat.span)
@@ -156,9 +191,13 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
 
     fn pat_strings(
         &mut self,
+        span: Span,
         init: StringPattern<'s>,
         params: &[Tr<parser::Pattern<'s>>],
     ) -> Pattern<'s> {
+        let string = self.type_info.string;
+        super::check_std_feature(self.ast, self.module, span, "string patterns", string);
+
         let mut pats = vec![init];
 
         for pat in params {
@@ -199,6 +238,174 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
         Pattern::String(pats)
     }
 
+    // "GET " ++ path ++ " HTTP/1.1"
+    // -----------------------------
+    // "GET " (suffix " HTTP/1.1") path
+    //
+    // Suffixes are checked against the end of the string, so that the binder takes what remains
+    fn pat_concat<'p>(
+        &mut self,
+        span: Span,
+        operands: impl Iterator<Item = &'p Tr<parser::Pattern<'s>>>,
+    ) -> Pattern<'s>
+    where
+        's: 'p,
+    {
+        let string = self.type_info.string;
+        super::check_std_feature(self.ast, self.module, span, "string patterns", string);
+
+        let mut pats = vec![];
+        let mut suffixes = vec![];
+        let mut rest = None;
+
+        for pat in operands {
+            match &pat.value {
+                parser::Pattern::String(str, params) if params.is_empty() => match rest {
+                    None => pats.push(StringPattern::Literal(*str)),
+                    Some(_) => suffixes.push(StringPattern::Suffix(*str)),
+                },
+                parser::Pattern::Extractor(expr, bind, params)
+                    if params.is_empty() && rest.is_none() =>
+                {
+                    if let Some(extractor) = self.extractor((**expr).as_ref(), *bind) {
+                        pats.push(extractor);
+                    }
+                }
+                parser::Pattern::Name(name, params)
+                    if name.is_name() && params.is_empty() && rest.is_none() =>
+                {
+                    let bind = self.bindings.declare(name.as_name().unwrap());
+                    rest = Some(StringPattern::Wildcard(bind.tr(pat.span)));
+                }
+                parser::Pattern::Name(..) | parser::Pattern::Extractor(..) if rest.is_some() => {
+                    self.ast
+                        .sources
+                        .error("invalid string pattern")
+                        .m(self.module)
+                        .eline(pat.span, "")
+                        .text("note: only string literals may follow the binder of the remainder")
+                        .emit();
+
+                    return Pattern::Poison;
+                }
+                _ => {
+                    self.ast
+                        .sources
+                        .error("invalid string pattern")
+                        .m(self.module)
+                        .eline(pat.span, "")
+                        .emit();
+
+                    return Pattern::Poison;
+                }
+            }
+        }
+
+        pats.extend(suffixes.into_iter().rev());
+        pats.extend(rest);
+
+        Pattern::String(pats)
+    }
+
+    // <<len:u16, body:bytes[len], rest:bytes>>
+    // ----------------------------------------
+    // Int(u16) len, Bytes(len) body, Rest rest
+    fn pat_binary(&mut self, segments: &[Tr<parser::pat::Segment<'s>>]) -> Pattern<'s> {
+        use parser::pat::{Bound, SegmentSize, SegmentValue};
+
+        let mut binds: Vec<(&'s str, key::Bind)> = vec![];
+        let mut lowered = vec![];
+
+        for (i, segment) in segments.iter().enumerate() {
+            let is_last = i == segments.len() - 1;
+            let kind = segment.kind.map(|kind| *kind).unwrap_or("u8");
+
+            let lsegment = match (kind, &segment.value, &segment.size) {
+                ("bytes", SegmentValue::Name(name), Some(size)) => {
+                    let len = match size.value {
+                        SegmentSize::Int(n) => Some(ByteLen::Const(n)),
+                        SegmentSize::Name(sname) => binds
+                            .iter()
+                            .rev()
+                            .find(|(n, _)| *n == sname)
+                            .map(|(_, bind)| ByteLen::Bind(*bind)),
+                    };
+
+                    let Some(len) = len else {
+                        let msg = "the size must be an integer bound by an earlier segment";
+                        return self.emit_invalid_segment(size.span, msg);
+                    };
+
+                    Segment::Bytes(len, self.segment_bind(name, segment.span))
+                }
+                ("bytes", SegmentValue::Name(name), None) if is_last => {
+                    Segment::Rest(self.segment_bind(name, segment.span))
+                }
+                ("bytes", SegmentValue::Name(_), None) => {
+                    return self.emit_invalid_segment(
+                        segment.span,
+                        "only the last segment may leave out the size of `bytes`",
+                    )
+                }
+                ("bytes", SegmentValue::Int(_), _) => {
+                    return self.emit_invalid_segment(
+                        segment.span,
+                        "integer literals can't be matched as `bytes`",
+                    )
+                }
+                (_, _, Some(size)) => {
+                    return self.emit_invalid_segment(
+                        size.span,
+                        "only `bytes` segments may be given a size",
+                    )
+                }
+                (kind, value, None) => {
+                    let Some(intsize) = segment_intsize(kind) else {
+                        let span = segment.kind.map(|kind| kind.span).unwrap_or(segment.span);
+                        return self
+                            .emit_invalid_segment(span, format!("unknown segment type `{kind}`"));
+                    };
+
+                    match value {
+                        SegmentValue::Name(name) => {
+                            let bind = self.segment_bind(name, segment.span);
+                            // Integer segments may be used as the size of later segments
+                            binds.extend(bind.map(|bind| (*name, bind.value)));
+                            Segment::Int(intsize, bind)
+                        }
+                        SegmentValue::Int(Bound::Pos(n)) => Segment::Literal(intsize, *n as i128),
+                        SegmentValue::Int(Bound::Neg(n)) => {
+                            Segment::Literal(intsize, -(*n as i128))
+                        }
+                        SegmentValue::Int(Bound::Excess) => unreachable!(),
+                    }
+                }
+            };
+
+            lowered.push(lsegment.tr(segment.span));
+        }
+
+        Pattern::Binary(lowered)
+    }
+
+    fn segment_bind(&mut self, name: &'s str, span: Span) -> Option<Tr<key::Bind>> {
+        (name != "_").then(|| self.bindings.declare(name).tr(span))
+    }
+
+    fn emit_invalid_segment(&self, span: Span, msg: impl Into<String>) -> Pattern<'s> {
+        self.ast
+            .sources
+            .error("invalid binary pattern")
+            .m(self.module)
+            .eline(span, msg)
+            .text(
+                "note: segments are either `bytes` or one of u8, u16, u32, u64, i8, i16, i32, i64",
+            )
+            .emit();
+
+        Pattern::Poison
+    }
+
     fn pat_record(
         &mut self,
         span: Span,
@@ -224,6 +431,9 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
     }
 
     fn pat_list(&mut self, span: Span, elems: &[Tr<parser::Pattern<'s>>]) -> Pattern<'s> {
+        let list = self.type_info.list;
+        super::check_std_feature(self.ast, self.module, span, "list patterns", list);
+
         let inf = self.type_info.inference_mut().unwrap();
         let ivar = inf.var(elems.get(0).map(|p| p.span).unwrap_or(span));
 
@@ -297,18 +507,27 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
             ["_"] => self.pat_forbid_params(params, Pattern::Any),
             path => match self.ast.lookups.resolve_func(self.module, path) {
                 Ok(Mod { key: Entity::Func(NFunc::SumVar(type_, var)), module, .. }) => {
+                    let nfunc = M(module, NFunc::SumVar(type_, var));
+                    super::check_func_use(self.ast, self.module, span, nfunc);
                     let params = self.pats(params);
                     Pattern::Constructor(type_.inside(module), var, params)
                 }
                 Ok(Mod {
                     key: Entity::Member(key::TypeKind::Sum(sum), name), module, ..
-                }) => match self.resolve_variant(sum.inside(module), span, name) {
-                    None => Pattern::Poison,
-                    Some(var) => {
-                        let params = self.pats(params);
-                        Pattern::Constructor(sum.inside(module), var, params)
+                }) => {
+                    let kind = M(module, key::TypeKind::Sum(sum));
+                    self.ast
+                        .record_use(self.module, span, 1, ast::Referent::Type(kind));
+                    match self.resolve_variant(sum.inside(module), span, name) {
+                        None => Pattern::Poison,
+                        Some(var) => {
+                            let nfunc = M(module, NFunc::SumVar(sum, var));
+                            super::check_func_use(self.ast, self.module, span, nfunc);
+                            let params = self.pats(params);
+                            Pattern::Constructor(sum.inside(module), var, params)
+                        }
                     }
-                },
+                }
 
                 _ if is_valid_wildcard() => {
                     let bind = self.bindings.declare(path[0]);
@@ -328,9 +547,7 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
                     Pattern::Poison
                 }
                 Err(err) => {
-                    self.ast
-                        .sources
-                        .emit_lookup_err(span, self.module, "variant", err);
+                    self.ast.emit_lookup_err(span, self.module, "variant", err);
 
                     Pattern::Poison
                 }
@@ -423,6 +640,19 @@ impl<'t, 'a, 's> FuncLower<'t, 'a, 's> {
     }
 }
 
+fn segment_intsize(kind: &str) -> Option<IntSize> {
+    let signed = match kind.as_bytes().first() {
+        Some(b'u') => false,
+        Some(b'i') => true,
+        _ => return None,
+    };
+
+    match kind[1..].parse() {
+        Ok(bits @ (8 | 16 | 32 | 64)) => Some(IntSize::new(signed, bits)),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 impl<'s> From<u128> for Pattern<'s> {
     fn from(value: u128) -> Self {
@@ -487,11 +717,31 @@ impl<'s> fmt::Display for Pattern<'s> {
             Pattern::Nil(_) => "Nil".fmt(f),
             Pattern::Bool(b) => b.fmt(f),
             Pattern::String(str) => write!(f, "{str:?}"),
+            Pattern::Binary(segments) => write!(f, "<<{}>>", segments.iter().format(", ")),
             Pattern::Poison => write!(f, "<poison>"),
         }
     }
 }
 
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = |bind: &Option<Tr<key::Bind>>| match bind {
+            Some(bind) => bind.value.to_string(),
+            None => "_".into(),
+        };
+
+        match self {
+            Segment::Int(intsize, bind) => write!(f, "{}:{intsize}", name(bind)),
+            Segment::Literal(intsize, n) => write!(f, "{n}:{intsize}"),
+            Segment::Bytes(ByteLen::Const(n), bind) => write!(f, "{}:bytes[{n}]", name(bind)),
+            Segment::Bytes(ByteLen::Bind(len), bind) => {
+                write!(f, "{}:bytes[{len}]", name(bind))
+            }
+            Segment::Rest(bind) => write!(f, "{}:bytes", name(bind)),
+        }
+    }
+}
+
 fn fmt_fields<'s>(fields: &[(Tr<&'s str>, key::Bind, Tr<Pattern<'s>>)]) -> String {
     fields
         .iter()
@@ -24,6 +24,7 @@ pub struct TypeEnvInfo<'s> {
     pub declare_generics: bool,
     pub list: M<key::TypeKind>,
     pub string: M<key::TypeKind>,
+    pub closure: M<key::TypeKind>,
     pub self_handler: SelfHandler,
     inference: Option<TEnv<'s>>,
 }
@@ -51,10 +52,16 @@ impl FromVar for Static {
 }
 
 impl<'s> TypeEnvInfo<'s> {
-    pub fn new(declare_generics: bool, string: M<key::TypeKind>, list: M<key::TypeKind>) -> Self {
+    pub fn new(
+        declare_generics: bool,
+        string: M<key::TypeKind>,
+        list: M<key::TypeKind>,
+        closure: M<key::TypeKind>,
+    ) -> Self {
         Self {
             list,
             string,
+            closure,
             declare_generics,
             self_handler: SelfHandler::Disallowed,
             iforalls: SmallVec::new(),
@@ -190,6 +197,8 @@ impl<'t, 'a, 's> TypeLower<'t, 'a, 's> {
 
         match *ty {
             parser::Type::Closure(ptypes, returns) => {
+                let closure = self.type_info.closure;
+                super::check_std_feature(self.ast, self.module, ty.span, "closures", closure);
                 let ptypes = self.tys(ptypes);
                 let returns = self.ty((**returns).as_ref());
                 Ty::closure(ptypes, returns)
@@ -225,7 +234,12 @@ impl<'t, 'a, 's> TypeLower<'t, 'a, 's> {
                             .map(|generic| Ty::const_array(generic, inner))
                             .unwrap_or_else(Ty::poison),
                         parser::ListLength::Exact(len) => Ty::array(**len, inner),
-                        parser::ListLength::None => Ty::list(self.type_info.list, vec![inner]),
+                        parser::ListLength::Unknown => Ty::slice(inner),
+                        parser::ListLength::None => {
+                            let list = self.type_info.list;
+                            super::check_std_feature(self.ast, self.module, ty.span, "lists", list);
+                            Ty::list(list, vec![inner])
+                        }
                     }
                 }
             }
@@ -311,9 +325,12 @@ impl<'t, 'a, 's> TypeLower<'t, 'a, 's> {
                 let int = Ty::Int(IntSize::new(false, self.default_int_size));
                 return self.forbid_params(span, int, params);
             }
-            ["float"] => {
+            ["float" | "f64"] => {
                 return self.forbid_params(span, Ty::f64(), params);
             }
+            ["f32"] => {
+                return self.forbid_params(span, Ty::f32(), params);
+            }
             ["self"] => match self.type_info.self_handler {
                 SelfHandler::Substituted(kind) => {
                     let (forall, gkind) = &self.type_info.cforalls.last().unwrap();
@@ -338,6 +355,7 @@ impl<'t, 'a, 's> TypeLower<'t, 'a, 's> {
             },
             ["string"] => {
                 let string = self.type_info.string;
+                super::check_std_feature(self.ast, self.module, span, "strings", string);
                 return self.forbid_params(span, Ty::string(string, vec![]), params);
             }
             [name] if name.starts_with('u') => {
@@ -370,6 +388,7 @@ impl<'t, 'a, 's> TypeLower<'t, 'a, 's> {
                 Entity::Alias(ty) => return self.ty(ty.as_ref()),
                 Entity::Type(tkey) => {
                     let key = M(entity.module, tkey);
+                    super::check_type_use(self.ast, self.module, span, key);
                     let mut tparams = self.tys(params);
                     let expected_type_params =
                         &self.ast.entities.header_of_ty(key).header.type_params;
@@ -420,9 +439,7 @@ impl<'t, 'a, 's> TypeLower<'t, 'a, 's> {
             },
 
             Err(err) => {
-                self.ast
-                    .sources
-                    .emit_lookup_err(span, self.module, "type", err);
+                self.ast.emit_lookup_err(span, self.module, "type", err);
 
                 Ty::poison()
             }
@@ -507,10 +524,12 @@ pub enum GenericError {
 pub struct TypeAnnotation<'s> {
     pub for_entity: Vec<(Tr<&'s str>, IType)>,
     pub for_type: Vec<(Tr<&'s str>, IType)>,
+    /// Name of the implementation selected with `Trait(name):method`
+    pub for_impl: Option<Tr<&'s str>>,
 }
 
 impl<'s> TypeAnnotation<'s> {
     pub fn new() -> Self {
-        Self { for_type: vec![], for_entity: vec![] }
+        Self { for_type: vec![], for_entity: vec![], for_impl: None }
     }
 }
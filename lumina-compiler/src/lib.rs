@@ -1,6 +1,7 @@
 pub mod ast;
 pub mod backend;
 mod debuginfo;
+pub use debuginfo::BinDebugInfo;
 pub mod hir;
 pub mod lir;
 pub mod mir;
@@ -36,6 +37,39 @@ pub const SIZE_OF: key::Method = key::Method(0);
 pub const TRAIT_OBJECT_DATA_FIELD: key::Field = key::Field(0);
 pub const VTABLE_FIELD: key::Field = key::Field(1);
 
+/// How much effort is spent on optimising the generated code
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OptLevel {
+    /// No optimisations, for the fastest builds
+    None,
+    /// Fold constants and simplify control flow, but don't inline functions
+    Less,
+    /// All optimisations
+    #[default]
+    Speed,
+    /// All optimisations, but functions are only inlined if it doesn't grow the program
+    Size,
+}
+
+/// The kind of artifact the project is linked into
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CrateType {
+    /// An executable which calls `main`
+    #[default]
+    Bin,
+    /// A shared library of position-independent code, exposing the `@[export]` functions
+    Lib,
+    /// An archive of the objects, for linking the `@[export]` functions into a C program
+    Staticlib,
+}
+
+impl CrateType {
+    /// Libraries are compiled as position-independent code without an entrypoint
+    pub fn is_library(self) -> bool {
+        matches!(self, CrateType::Lib | CrateType::Staticlib)
+    }
+}
+
 #[derive(new, Clone, Copy)]
 pub struct ProjectInfo {
     main: M<key::Func>,
@@ -48,4 +82,6 @@ pub struct ProjectInfo {
     stringable: M<key::Trait>,
     string: M<key::Record>,
     maybe: M<key::Sum>,
+    /// `std:test:Generate`, which is only available if the project includes `std:test`
+    generate: Option<M<key::Trait>>,
 }
@@ -0,0 +1,1146 @@
+//! Compact binary serialisation of LIR
+//!
+//! Lets a program be compiled ahead of time and later executed by the reference interpreter
+//! without the rest of the compiler, which is how host applications embed Lumina scripts. The
+//! host decodes the bytecode with [`decode`] and runs it with [`super::interpret::run`] or drives
+//! an [`super::interpret::Interpreter`] directly. Scripts are sandboxed by the interpreter, they
+//! only have access to the emulated subset of libc and write their output to the host's writer.
+//!
+//! Decoding only reads from the given slice and rejects malformed input instead of panicking.
+//! Since it doesn't validate that the LIR itself is well-formed, only bytecode produced by
+//! [`encode`] should be executed.
+
+use super::{
+    AtomicOrdering, AtomicRmwOp, BinOp, Block, BlockJump, Entry, ExternFunction, Function,
+    MonoFunc, MonoType, MonoTypeData, MonoTypeKey, Output, Test, Types, Value, SSA, V,
+};
+use crate::debuginfo::Item;
+use crate::prelude::*;
+use ast::attr::Repr;
+use cranelift_codegen::ir::TrapCode;
+use lumina_collections::MapKey;
+use lumina_typesystem::{ConstValue, FloatSize, IntSize};
+use lumina_util::Symbol;
+use std::cmp::Ordering;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+use std::num::NonZeroU8;
+
+pub const MAGIC: [u8; 4] = *b"LMBC";
+
+/// Bumped whenever the encoding of LIR changes
+pub const VERSION: u32 = 17;
+
+/// Conventional file extension of serialised LIR
+pub const EXTENSION: &str = "lmb";
+
+#[derive(Debug)]
+pub enum Error {
+    NotBytecode,
+    Version(u32),
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotBytecode => write!(f, "not a lumina bytecode file"),
+            Error::Version(v) => write!(
+                f,
+                "bytecode was produced for version {v} while this interpreter expects version {VERSION}"
+            ),
+            Error::Truncated => write!(f, "unexpected end of bytecode"),
+            Error::Invalid(what) => write!(f, "malformed bytecode: invalid {what}"),
+        }
+    }
+}
+
+pub fn encode(lir: &Output) -> Vec<u8> {
+    let mut buf = MAGIC.to_vec();
+    VERSION.encode(&mut buf);
+    lir.encode(&mut buf);
+    buf
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Output, Error> {
+    let mut r = Reader::new(bytes);
+    if r.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err(Error::NotBytecode);
+    }
+    match u32::decode(&mut r)? {
+        VERSION => {}
+        other => return Err(Error::Version(other)),
+    }
+    let lir = Output::decode(&mut r)?;
+    if !r.is_empty() {
+        return Err(Error::Invalid("trailing data"));
+    }
+    Ok(lir)
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    /// Modules of the keys read so far, which the MIR cache uses to find what a module depends on
+    pub(crate) modules: HashSet<key::Module>,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, modules: HashSet::new() }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < n {
+            return Err(Error::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8, Error> {
+        self.take(1).map(|b| b[0])
+    }
+
+    /// Read a length, rejecting lengths which can't possibly fit in the remaining input so that
+    /// malformed input can't cause huge allocations
+    pub(crate) fn len(&mut self) -> Result<usize, Error> {
+        let len = usize::decode(self)?;
+        if len > self.bytes.len() {
+            return Err(Error::Truncated);
+        }
+        Ok(len)
+    }
+}
+
+pub(crate) trait Encode {
+    fn encode(&self, buf: &mut Vec<u8>);
+}
+
+pub(crate) trait Decode: Sized {
+    fn decode(r: &mut Reader) -> Result<Self, Error>;
+}
+
+// Integers are encoded as LEB128
+impl Encode for u64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let mut n = *self;
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                buf.push(byte);
+                break;
+            }
+            buf.push(byte | 0x80);
+        }
+    }
+}
+
+impl Decode for u64 {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = r.byte()?;
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(Error::Invalid("integer"))
+    }
+}
+
+macro_rules! via_u64 {
+    ($($ty:ty),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    (*self as u64).encode(buf)
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode(r: &mut Reader) -> Result<Self, Error> {
+                    <$ty>::try_from(u64::decode(r)?).map_err(|_| Error::Invalid("integer"))
+                }
+            }
+        )*
+    };
+}
+
+via_u64!(u16, u32, usize);
+
+impl Encode for u8 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self)
+    }
+}
+
+impl Decode for u8 {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        r.byte()
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8)
+    }
+}
+
+impl Decode for bool {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        match r.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Error::Invalid("boolean")),
+        }
+    }
+}
+
+// Zigzag encoded so that small negative integers stay small
+impl Encode for i128 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let n = ((self << 1) ^ (self >> 127)) as u128;
+        (n as u64).encode(buf);
+        ((n >> 64) as u64).encode(buf);
+    }
+}
+
+impl Decode for i128 {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let n = u64::decode(r)? as u128 | (u64::decode(r)? as u128) << 64;
+        Ok((n >> 1) as i128 ^ -((n & 1) as i128))
+    }
+}
+
+impl Encode for f64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes())
+    }
+}
+
+impl Decode for f64 {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let bytes = r.take(8)?.try_into().unwrap();
+        Ok(f64::from_le_bytes(bytes))
+    }
+}
+
+impl Encode for char {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u32).encode(buf)
+    }
+}
+
+impl Decode for char {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        char::from_u32(u32::decode(r)?).ok_or(Error::Invalid("char"))
+    }
+}
+
+impl Encode for [u8] {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.len().encode(buf);
+        buf.extend_from_slice(self);
+    }
+}
+
+impl Encode for mir::ReadOnlyBytes {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf)
+    }
+}
+
+impl Decode for mir::ReadOnlyBytes {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let len = r.len()?;
+        r.take(len).map(|bytes| mir::ReadOnlyBytes(bytes.into()))
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_bytes().encode(buf)
+    }
+}
+
+impl Decode for String {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let len = r.len()?;
+        let bytes = r.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| Error::Invalid("string"))
+    }
+}
+
+impl Encode for Symbol {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_bytes().encode(buf)
+    }
+}
+
+impl Decode for Symbol {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        String::decode(r).map(Symbol::from)
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.len().encode(buf);
+        self.iter().for_each(|v| v.encode(buf));
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let len = r.len()?;
+        (0..len).map(|_| T::decode(r)).collect()
+    }
+}
+
+impl<T: Encode> Encode for VecDeque<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.len().encode(buf);
+        self.iter().for_each(|v| v.encode(buf));
+    }
+}
+
+impl<T: Decode> Decode for VecDeque<T> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let len = r.len()?;
+        (0..len).map(|_| T::decode(r)).collect()
+    }
+}
+
+impl<T: Encode> Encode for Box<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (**self).encode(buf)
+    }
+}
+
+impl<T: Decode> Decode for Box<T> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        T::decode(r).map(Box::new)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            None => buf.push(0),
+            Some(v) => {
+                buf.push(1);
+                v.encode(buf);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        match r.byte()? {
+            0 => Ok(None),
+            1 => T::decode(r).map(Some),
+            _ => Err(Error::Invalid("option")),
+        }
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok((A::decode(r)?, B::decode(r)?))
+    }
+}
+
+/// Encode the elements of an unordered collection sorted by their encoding, so that the same
+/// program always results in the same bytecode
+fn encode_unordered<T: Encode>(elems: impl ExactSizeIterator<Item = T>, buf: &mut Vec<u8>) {
+    elems.len().encode(buf);
+    let mut encoded = elems
+        .map(|elem| {
+            let mut buf = vec![];
+            elem.encode(&mut buf);
+            buf
+        })
+        .collect::<Vec<_>>();
+    encoded.sort_unstable();
+    encoded.iter().for_each(|elem| buf.extend_from_slice(elem));
+}
+
+impl<K: Encode, V: Encode> Encode for HashMap<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_unordered(self.iter(), buf)
+    }
+}
+
+impl<K: Decode + Hash + Eq, V: Decode> Decode for HashMap<K, V> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let len = r.len()?;
+        (0..len).map(|_| <(K, V)>::decode(r)).collect()
+    }
+}
+
+impl<T: Encode> Encode for HashSet<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_unordered(self.iter(), buf)
+    }
+}
+
+impl<T: Decode + Hash + Eq> Decode for HashSet<T> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let len = r.len()?;
+        (0..len).map(|_| T::decode(r)).collect()
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (**self).encode(buf)
+    }
+}
+
+impl<K: MapKey, V: Encode> Encode for Map<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.len().encode(buf);
+        self.values().for_each(|v| v.encode(buf));
+    }
+}
+
+impl<K: MapKey, V: Decode> Decode for Map<K, V> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let len = r.len()?;
+        let mut map = Map::with_capacity(len);
+        for _ in 0..len {
+            map.push(V::decode(r)?);
+        }
+        Ok(map)
+    }
+}
+
+impl<K: MapKey, V: Encode> Encode for MMap<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let modules = self.modules().collect::<Vec<_>>();
+        modules.len().encode(buf);
+        modules
+            .into_iter()
+            .for_each(|module| self[module].encode(buf));
+    }
+}
+
+impl<K: MapKey, V: Decode> Decode for MMap<K, V> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let len = r.len()?;
+        let mut mmap = MMap::with_capacity(len);
+        for _ in 0..len {
+            let map = Map::<K, V>::decode(r)?;
+            let module = mmap.add_module(map.len());
+            mmap[module] = map;
+        }
+        Ok(mmap)
+    }
+}
+
+impl<K: Encode> Encode for M<K> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+    }
+}
+
+impl<K: Decode> Decode for M<K> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let module = key::Module::decode(r)?;
+        r.modules.insert(module);
+        Ok(M(module, K::decode(r)?))
+    }
+}
+
+macro_rules! keys {
+    ($($ty:ty),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    Into::<usize>::into(*self).encode(buf)
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode(r: &mut Reader) -> Result<Self, Error> {
+                    usize::decode(r).map(<$ty>::from)
+                }
+            }
+        )*
+    };
+}
+
+keys!(
+    key::Module,
+    key::Func,
+    key::Val,
+    key::ReadOnly,
+    key::Record,
+    key::Sum,
+    key::Trait,
+    key::Impl,
+    key::Method,
+    key::Lambda,
+    key::Field,
+    key::Variant,
+    key::Bind,
+    key::Param,
+    key::Generic,
+    key::DecisionTreeTail,
+    MonoFunc,
+    MonoTypeKey,
+    Block,
+    V
+);
+
+/// Implement encoding for a fieldless enum as its index in `variants`
+macro_rules! unit_enum {
+    ($ty:ty, $what:literal, [$($variant:expr),*]) => {
+        impl Encode for $ty {
+            fn encode(&self, buf: &mut Vec<u8>) {
+                let i = [$($variant),*].iter().position(|v| v == self).unwrap();
+                buf.push(i as u8);
+            }
+        }
+
+        impl Decode for $ty {
+            fn decode(r: &mut Reader) -> Result<Self, Error> {
+                [$($variant),*]
+                    .get(r.byte()? as usize)
+                    .copied()
+                    .ok_or(Error::Invalid($what))
+            }
+        }
+    };
+}
+
+unit_enum!(
+    BinOp,
+    "binary operator",
+    [
+        BinOp::Add,
+        BinOp::Sub,
+        BinOp::Mul,
+        BinOp::Div,
+        BinOp::And,
+        BinOp::Rem
+    ]
+);
+unit_enum!(
+    Ordering,
+    "ordering",
+    [Ordering::Less, Ordering::Equal, Ordering::Greater]
+);
+unit_enum!(FloatSize, "float size", [FloatSize::F32, FloatSize::F64]);
+unit_enum!(
+    AtomicOrdering,
+    "atomic ordering",
+    [
+        AtomicOrdering::Relaxed,
+        AtomicOrdering::Acquire,
+        AtomicOrdering::Release,
+        AtomicOrdering::AcqRel,
+        AtomicOrdering::SeqCst
+    ]
+);
+unit_enum!(
+    AtomicRmwOp,
+    "atomic operator",
+    [
+        AtomicRmwOp::Add,
+        AtomicRmwOp::Sub,
+        AtomicRmwOp::And,
+        AtomicRmwOp::Or,
+        AtomicRmwOp::Xor,
+        AtomicRmwOp::Xchg
+    ]
+);
+unit_enum!(
+    ast::SymbolVisibility,
+    "symbol visibility",
+    [
+        ast::SymbolVisibility::Hidden,
+        ast::SymbolVisibility::Default
+    ]
+);
+unit_enum!(
+    ast::TestKind,
+    "test kind",
+    [ast::TestKind::Unit, ast::TestKind::Property]
+);
+
+impl Encode for IntSize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.signed.encode(buf);
+        self.bits().encode(buf);
+    }
+}
+
+impl Decode for IntSize {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let signed = bool::decode(r)?;
+        match r.byte()? {
+            bits @ 1..=64 => Ok(IntSize::new(signed, bits)),
+            _ => Err(Error::Invalid("integer size")),
+        }
+    }
+}
+
+impl Encode for TrapCode {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(self.as_raw().get())
+    }
+}
+
+impl Decode for TrapCode {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        NonZeroU8::new(r.byte()?)
+            .map(TrapCode::from_raw)
+            .ok_or(Error::Invalid("trap code"))
+    }
+}
+
+impl Encode for ConstValue {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            ConstValue::Usize(n) => (0u8, n).encode(buf),
+            ConstValue::Bool(b) => (1u8, b).encode(buf),
+            ConstValue::Char(c) => (2u8, c).encode(buf),
+        }
+    }
+}
+
+impl Decode for ConstValue {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => ConstValue::Usize(u64::decode(r)?),
+            1 => ConstValue::Bool(bool::decode(r)?),
+            2 => ConstValue::Char(char::decode(r)?),
+            _ => return Err(Error::Invalid("constant")),
+        })
+    }
+}
+
+impl Encode for Repr {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Repr::Lumina => buf.push(0),
+            Repr::C => buf.push(1),
+            Repr::Packed => buf.push(2),
+            Repr::Align(n) => (3u8, n).encode(buf),
+            Repr::Enum(intsize) => (4u8, intsize).encode(buf),
+        }
+    }
+}
+
+impl Decode for Repr {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => Repr::Lumina,
+            1 => Repr::C,
+            2 => Repr::Packed,
+            3 => Repr::Align(u8::decode(r)?),
+            4 => Repr::Enum(IntSize::decode(r)?),
+            _ => return Err(Error::Invalid("representation")),
+        })
+    }
+}
+
+impl Encode for MonoType {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            MonoType::Int(intsize) => (0u8, intsize).encode(buf),
+            MonoType::Pointer(inner) => (1u8, inner).encode(buf),
+            MonoType::FnPointer(params, ret) => {
+                (2u8, params).encode(buf);
+                ret.encode(buf);
+            }
+            MonoType::Float(size) => (3u8, size).encode(buf),
+            MonoType::Unreachable => buf.push(4),
+            MonoType::Const(const_) => (5u8, const_).encode(buf),
+            MonoType::Array(len, inner) => {
+                (6u8, len).encode(buf);
+                inner.encode(buf);
+            }
+            MonoType::Monomorphised(key) => (7u8, key).encode(buf),
+        }
+    }
+}
+
+impl Decode for MonoType {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => MonoType::Int(IntSize::decode(r)?),
+            1 => MonoType::Pointer(Box::decode(r)?),
+            2 => MonoType::FnPointer(Vec::decode(r)?, Box::decode(r)?),
+            3 => MonoType::Float(FloatSize::decode(r)?),
+            4 => MonoType::Unreachable,
+            5 => MonoType::Const(ConstValue::decode(r)?),
+            6 => MonoType::Array(u64::decode(r)?, Box::decode(r)?),
+            7 => MonoType::Monomorphised(MonoTypeKey::decode(r)?),
+            _ => return Err(Error::Invalid("type")),
+        })
+    }
+}
+
+impl Encode for MonoTypeData {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            MonoTypeData::Record { repr, key, fields } => {
+                (0u8, repr).encode(buf);
+                (key, fields).encode(buf);
+            }
+            MonoTypeData::Sum { tag, key, variants } => {
+                (1u8, tag).encode(buf);
+                (key, variants).encode(buf);
+            }
+            MonoTypeData::DynTraitObject { trait_, vtable } => {
+                (2u8, trait_).encode(buf);
+                vtable.encode(buf);
+            }
+            MonoTypeData::Placeholder => buf.push(3),
+        }
+    }
+}
+
+impl Decode for MonoTypeData {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => MonoTypeData::Record {
+                repr: Repr::decode(r)?,
+                key: Option::decode(r)?,
+                fields: Map::decode(r)?,
+            },
+            1 => MonoTypeData::Sum {
+                tag: IntSize::decode(r)?,
+                key: M::decode(r)?,
+                variants: Map::decode(r)?,
+            },
+            2 => {
+                MonoTypeData::DynTraitObject { trait_: M::decode(r)?, vtable: MonoType::decode(r)? }
+            }
+            3 => MonoTypeData::Placeholder,
+            _ => return Err(Error::Invalid("type definition")),
+        })
+    }
+}
+
+impl Encode for Value {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Value::ReadOnly(ro) => (0u8, ro).encode(buf),
+            Value::FuncPtr(mfunc) => (1u8, mfunc).encode(buf),
+            Value::ExternFuncPtr(key) => (2u8, key).encode(buf),
+            Value::V(v) => (3u8, v).encode(buf),
+            Value::Int(n, intsize) => {
+                (4u8, n).encode(buf);
+                intsize.encode(buf);
+            }
+            Value::Float(n) => (5u8, n).encode(buf),
+        }
+    }
+}
+
+impl Decode for Value {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => Value::ReadOnly(M::decode(r)?),
+            1 => Value::FuncPtr(MonoFunc::decode(r)?),
+            2 => Value::ExternFuncPtr(M::decode(r)?),
+            3 => Value::V(V::decode(r)?),
+            4 => Value::Int(i128::decode(r)?, IntSize::decode(r)?),
+            5 => Value::Float(f64::decode(r)?),
+            _ => return Err(Error::Invalid("value")),
+        })
+    }
+}
+
+impl Encode for BlockJump {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.id, &self.params).encode(buf)
+    }
+}
+
+impl Decode for BlockJump {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(BlockJump { id: Block::decode(r)?, params: Vec::decode(r)? })
+    }
+}
+
+impl Encode for Entry {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Entry::CallStatic(mfunc, params) => (0u8, (mfunc, params)).encode(buf),
+            Entry::CallExtern(key, params) => (1u8, (key, params)).encode(buf),
+            Entry::CallValue(fptr, params) => (2u8, (fptr, params)).encode(buf),
+            Entry::JmpFunc(mfunc, params) => (3u8, (mfunc, params)).encode(buf),
+            Entry::JmpBlock(jump) => (4u8, jump).encode(buf),
+            Entry::Return(value) => (5u8, value).encode(buf),
+            Entry::Select { value, on_true, on_false } => {
+                (6u8, value).encode(buf);
+                (on_true, on_false).encode(buf);
+            }
+            Entry::JmpTable(value, blocks) => (7u8, (value, blocks)).encode(buf),
+            Entry::Trap(code) => (8u8, code).encode(buf),
+            Entry::Construct(values) => (9u8, values).encode(buf),
+            Entry::Replicate(value, times) => (10u8, (value, times)).encode(buf),
+            Entry::Variant(var, values) => (11u8, (var, values)).encode(buf),
+            Entry::RefStaticVal(val) => (12u8, val).encode(buf),
+            Entry::RefExternData(key) => (13u8, key).encode(buf),
+            Entry::BlockParam(block, i) => (14u8, (block, i)).encode(buf),
+            Entry::Field { of, key, field } => {
+                (15u8, of).encode(buf);
+                (key, field).encode(buf);
+            }
+            Entry::CastFromSum { of } => (16u8, of).encode(buf),
+            Entry::TagFromSum { of } => (17u8, of).encode(buf),
+            Entry::Indice { of, indice } => (18u8, (of, indice)).encode(buf),
+            Entry::BinOp(op, [lhs, rhs]) => {
+                (19u8, op).encode(buf);
+                (lhs, rhs).encode(buf);
+            }
+            Entry::IntCmpInclusive([lhs, rhs], ord, intsize) => {
+                (20u8, (lhs, rhs)).encode(buf);
+                (ord, intsize).encode(buf);
+            }
+            Entry::IntAbs(value) => (21u8, value).encode(buf),
+            Entry::Transmute(value) => (22u8, value).encode(buf),
+            Entry::SizeOf(ty) => (23u8, ty).encode(buf),
+            Entry::AlignOf(ty) => (24u8, ty).encode(buf),
+            Entry::Reduce(value) => (25u8, value).encode(buf),
+            Entry::ExtendSigned(value) => (26u8, value).encode(buf),
+            Entry::ExtendUnsigned(value) => (27u8, value).encode(buf),
+            Entry::IntToFloat(value, intsize) => (28u8, (value, intsize)).encode(buf),
+            Entry::FloatToInt(value, intsize) => (29u8, (value, intsize)).encode(buf),
+            Entry::BitNot(value) => (30u8, value).encode(buf),
+            Entry::Alloc => buf.push(31),
+            Entry::Alloca => buf.push(32),
+            Entry::Dealloc { ptr } => (33u8, ptr).encode(buf),
+            Entry::WritePtr { ptr, value } => (34u8, (ptr, value)).encode(buf),
+            Entry::MemCpy { dst, src, count } => {
+                (35u8, dst).encode(buf);
+                (src, count).encode(buf);
+            }
+            Entry::Deref(ptr) => (36u8, ptr).encode(buf),
+            Entry::IntAddCarry([lhs, rhs, carry]) => (37u8, (lhs, (rhs, carry))).encode(buf),
+            Entry::IntSubBorrow([lhs, rhs, carry]) => (38u8, (lhs, (rhs, carry))).encode(buf),
+            Entry::IntMin([lhs, rhs], intsize) => (39u8, (lhs, (rhs, intsize))).encode(buf),
+            Entry::IntMax([lhs, rhs], intsize) => (40u8, (lhs, (rhs, intsize))).encode(buf),
+            Entry::SelectValue([cond, on_true, on_false]) => {
+                (41u8, (cond, (on_true, on_false))).encode(buf)
+            }
+            Entry::FloatAdd([lhs, rhs]) => (42u8, (lhs, rhs)).encode(buf),
+            Entry::FloatSub([lhs, rhs]) => (43u8, (lhs, rhs)).encode(buf),
+            Entry::FloatMul([lhs, rhs]) => (44u8, (lhs, rhs)).encode(buf),
+            Entry::FloatDiv([lhs, rhs]) => (45u8, (lhs, rhs)).encode(buf),
+            Entry::FloatNeg(value) => (46u8, value).encode(buf),
+            Entry::FloatCmp([lhs, rhs], ord) => (47u8, (lhs, (rhs, ord))).encode(buf),
+            Entry::FloatCast(value) => (48u8, value).encode(buf),
+            Entry::AtomicLoad(ptr, ordering) => (49u8, (ptr, ordering)).encode(buf),
+            Entry::AtomicStore { ptr, value, ordering } => {
+                (50u8, (ptr, (value, ordering))).encode(buf)
+            }
+            Entry::AtomicCas([ptr, expected, replacement], ordering) => {
+                (51u8, (ptr, expected)).encode(buf);
+                (replacement, ordering).encode(buf);
+            }
+            Entry::AtomicRmw(op, [ptr, value], ordering) => {
+                (52u8, op).encode(buf);
+                (ptr, (value, ordering)).encode(buf);
+            }
+            Entry::Breakpoint => buf.push(53),
+            Entry::MemSet { dst, byte, count } => (54u8, (dst, (byte, count))).encode(buf),
+        }
+    }
+}
+
+impl Decode for Entry {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => Entry::CallStatic(MonoFunc::decode(r)?, Vec::decode(r)?),
+            1 => Entry::CallExtern(M::decode(r)?, Vec::decode(r)?),
+            2 => Entry::CallValue(Value::decode(r)?, Vec::decode(r)?),
+            3 => Entry::JmpFunc(MonoFunc::decode(r)?, Vec::decode(r)?),
+            4 => Entry::JmpBlock(BlockJump::decode(r)?),
+            5 => Entry::Return(Value::decode(r)?),
+            6 => Entry::Select {
+                value: Value::decode(r)?,
+                on_true: BlockJump::decode(r)?,
+                on_false: BlockJump::decode(r)?,
+            },
+            7 => Entry::JmpTable(Value::decode(r)?, Vec::decode(r)?),
+            8 => Entry::Trap(TrapCode::decode(r)?),
+            9 => Entry::Construct(Vec::decode(r)?),
+            10 => Entry::Replicate(Value::decode(r)?, u64::decode(r)?),
+            11 => Entry::Variant(key::Variant::decode(r)?, Vec::decode(r)?),
+            12 => Entry::RefStaticVal(M::decode(r)?),
+            13 => Entry::RefExternData(M::decode(r)?),
+            14 => Entry::BlockParam(Block::decode(r)?, u32::decode(r)?),
+            15 => Entry::Field {
+                of: Value::decode(r)?,
+                key: MonoTypeKey::decode(r)?,
+                field: key::Field::decode(r)?,
+            },
+            16 => Entry::CastFromSum { of: Value::decode(r)? },
+            17 => Entry::TagFromSum { of: Value::decode(r)? },
+            18 => Entry::Indice { of: Value::decode(r)?, indice: Value::decode(r)? },
+            19 => {
+                let op = BinOp::decode(r)?;
+                Entry::BinOp(op, [Value::decode(r)?, Value::decode(r)?])
+            }
+            20 => {
+                let values = [Value::decode(r)?, Value::decode(r)?];
+                Entry::IntCmpInclusive(values, Ordering::decode(r)?, IntSize::decode(r)?)
+            }
+            21 => Entry::IntAbs(Value::decode(r)?),
+            22 => Entry::Transmute(Value::decode(r)?),
+            23 => Entry::SizeOf(MonoType::decode(r)?),
+            24 => Entry::AlignOf(MonoType::decode(r)?),
+            25 => Entry::Reduce(Value::decode(r)?),
+            26 => Entry::ExtendSigned(Value::decode(r)?),
+            27 => Entry::ExtendUnsigned(Value::decode(r)?),
+            28 => Entry::IntToFloat(Value::decode(r)?, IntSize::decode(r)?),
+            29 => Entry::FloatToInt(Value::decode(r)?, IntSize::decode(r)?),
+            30 => Entry::BitNot(Value::decode(r)?),
+            31 => Entry::Alloc,
+            32 => Entry::Alloca,
+            33 => Entry::Dealloc { ptr: Value::decode(r)? },
+            34 => Entry::WritePtr { ptr: Value::decode(r)?, value: Value::decode(r)? },
+            35 => Entry::MemCpy {
+                dst: Value::decode(r)?,
+                src: Value::decode(r)?,
+                count: Value::decode(r)?,
+            },
+            36 => Entry::Deref(Value::decode(r)?),
+            37 => Entry::IntAddCarry([Value::decode(r)?, Value::decode(r)?, Value::decode(r)?]),
+            38 => Entry::IntSubBorrow([Value::decode(r)?, Value::decode(r)?, Value::decode(r)?]),
+            39 => Entry::IntMin([Value::decode(r)?, Value::decode(r)?], IntSize::decode(r)?),
+            40 => Entry::IntMax([Value::decode(r)?, Value::decode(r)?], IntSize::decode(r)?),
+            41 => Entry::SelectValue([Value::decode(r)?, Value::decode(r)?, Value::decode(r)?]),
+            42 => Entry::FloatAdd([Value::decode(r)?, Value::decode(r)?]),
+            43 => Entry::FloatSub([Value::decode(r)?, Value::decode(r)?]),
+            44 => Entry::FloatMul([Value::decode(r)?, Value::decode(r)?]),
+            45 => Entry::FloatDiv([Value::decode(r)?, Value::decode(r)?]),
+            46 => Entry::FloatNeg(Value::decode(r)?),
+            47 => Entry::FloatCmp([Value::decode(r)?, Value::decode(r)?], Ordering::decode(r)?),
+            48 => Entry::FloatCast(Value::decode(r)?),
+            49 => Entry::AtomicLoad(Value::decode(r)?, AtomicOrdering::decode(r)?),
+            50 => Entry::AtomicStore {
+                ptr: Value::decode(r)?,
+                value: Value::decode(r)?,
+                ordering: AtomicOrdering::decode(r)?,
+            },
+            51 => Entry::AtomicCas(
+                [Value::decode(r)?, Value::decode(r)?, Value::decode(r)?],
+                AtomicOrdering::decode(r)?,
+            ),
+            52 => {
+                let op = AtomicRmwOp::decode(r)?;
+                let values = [Value::decode(r)?, Value::decode(r)?];
+                Entry::AtomicRmw(op, values, AtomicOrdering::decode(r)?)
+            }
+            53 => Entry::Breakpoint,
+            54 => Entry::MemSet {
+                dst: Value::decode(r)?,
+                byte: Value::decode(r)?,
+                count: Value::decode(r)?,
+            },
+            _ => return Err(Error::Invalid("entry")),
+        })
+    }
+}
+
+impl Encode for Item {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Item::SumConstructorWrapper(sum, var) => (0u8, (sum, var)).encode(buf),
+            Item::Defined(func) => (1u8, func).encode(buf),
+            Item::Method(impl_, method) => (2u8, (impl_, method)).encode(buf),
+            Item::Autogenerated(module, mfunc) => (3u8, (module, mfunc)).encode(buf),
+            Item::Lambda(parent, lambda) => (4u8, (parent, lambda)).encode(buf),
+        }
+    }
+}
+
+impl Decode for Item {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => Item::SumConstructorWrapper(M::decode(r)?, key::Variant::decode(r)?),
+            1 => Item::Defined(M::decode(r)?),
+            2 => Item::Method(M::decode(r)?, key::Method::decode(r)?),
+            3 => Item::Autogenerated(key::Module::decode(r)?, MonoFunc::decode(r)?),
+            4 => Item::Lambda(Box::decode(r)?, key::Lambda::decode(r)?),
+            _ => return Err(Error::Invalid("item")),
+        })
+    }
+}
+
+impl Encode for ast::Linkage {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.weak, self.visibility).encode(buf);
+        self.section.encode(buf);
+    }
+}
+
+impl Decode for ast::Linkage {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(ast::Linkage {
+            weak: bool::decode(r)?,
+            visibility: ast::SymbolVisibility::decode(r)?,
+            section: Option::decode(r)?,
+        })
+    }
+}
+
+impl Encode for Function {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (&self.symbol, &self.kind).encode(buf);
+        (&self.ssa, &self.returns).encode(buf);
+        (self.invocations, &self.linkage).encode(buf);
+        (self.directly_recursive, self.pointed_to_by_func_pointer).encode(buf);
+        (&self.export, self.calls).encode(buf);
+    }
+}
+
+impl Decode for Function {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(Function {
+            symbol: Symbol::decode(r)?,
+            kind: Item::decode(r)?,
+            ssa: SSA::decode(r)?,
+            returns: MonoType::decode(r)?,
+            invocations: u32::decode(r)?,
+            linkage: ast::Linkage::decode(r)?,
+            directly_recursive: bool::decode(r)?,
+            pointed_to_by_func_pointer: bool::decode(r)?,
+            export: Option::decode(r)?,
+            calls: Option::decode(r)?,
+        })
+    }
+}
+
+impl Encode for ExternFunction {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (&self.symbol, &self.params).encode(buf);
+        (&self.returns, (self.data, self.variadic)).encode(buf);
+        self.library.encode(buf);
+    }
+}
+
+impl Decode for ExternFunction {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(ExternFunction {
+            symbol: Symbol::decode(r)?,
+            params: Vec::decode(r)?,
+            returns: MonoType::decode(r)?,
+            data: bool::decode(r)?,
+            variadic: bool::decode(r)?,
+            library: Option::decode(r)?,
+        })
+    }
+}
+
+impl Encode for mir::ExpectedOutput {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            mir::ExpectedOutput::Inline(bytes) => (0u8, bytes).encode(buf),
+            mir::ExpectedOutput::File(path) => {
+                (1u8, path.to_string_lossy().into_owned()).encode(buf)
+            }
+        }
+    }
+}
+
+impl Decode for mir::ExpectedOutput {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => mir::ExpectedOutput::Inline(Vec::decode(r)?),
+            1 => mir::ExpectedOutput::File(String::decode(r)?.into()),
+            _ => return Err(Error::Invalid("expected output")),
+        })
+    }
+}
+
+impl Encode for Test {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (&self.name, self.module).encode(buf);
+        (self.kind, &self.expected_output).encode(buf);
+        (self.func, &self.params).encode(buf);
+        self.generators.encode(buf);
+    }
+}
+
+impl Decode for Test {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(Test {
+            name: String::decode(r)?,
+            module: key::Module::decode(r)?,
+            kind: ast::TestKind::decode(r)?,
+            expected_output: Option::decode(r)?,
+            func: MonoFunc::decode(r)?,
+            params: Vec::decode(r)?,
+            generators: Vec::decode(r)?,
+        })
+    }
+}
+
+impl Encode for Output {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.types.pointer_bits, &self.types.records).encode(buf);
+        (&self.functions, &self.extern_funcs).encode(buf);
+        (&self.val_initializers, &self.val_types).encode(buf);
+        (&self.const_vals, &self.val_constants).encode(buf);
+        self.static_asserts.encode(buf);
+        self.thread_local_vals.encode(buf);
+        self.read_only_table.encode(buf);
+        (&self.func_names, &self.module_names).encode(buf);
+        (&self.record_names, &self.field_names).encode(buf);
+        (self.main, self.sys_init).encode(buf);
+        (self.alloc, self.dealloc).encode(buf);
+        self.tests.encode(buf);
+    }
+}
+
+impl Decode for Output {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(Output {
+            types: Types { pointer_bits: u32::decode(r)?, records: Map::decode(r)? },
+            functions: Map::decode(r)?,
+            extern_funcs: HashMap::decode(r)?,
+            val_initializers: HashMap::decode(r)?,
+            val_types: MMap::decode(r)?,
+            const_vals: HashSet::decode(r)?,
+            val_constants: HashMap::decode(r)?,
+            static_asserts: HashMap::decode(r)?,
+            thread_local_vals: HashSet::decode(r)?,
+            read_only_table: MMap::decode(r)?,
+            func_names: MMap::decode(r)?,
+            module_names: Map::decode(r)?,
+            record_names: MMap::decode(r)?,
+            field_names: MMap::decode(r)?,
+            main: MonoFunc::decode(r)?,
+            sys_init: MonoFunc::decode(r)?,
+            alloc: MonoFunc::decode(r)?,
+            dealloc: MonoFunc::decode(r)?,
+            tests: Vec::decode(r)?,
+        })
+    }
+}
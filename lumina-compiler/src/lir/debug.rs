@@ -16,7 +16,7 @@ impl<'a> Debugger<'a> {
         for (mfunc, func) in self.lir.functions.iter() {
             self.mfunc = mfunc;
 
-            let _span = info_span!("running LIR debugger", entity = func.symbol);
+            let _span = info_span!("running LIR debugger", entity = func.symbol.as_str());
             let _handle = _span.enter();
 
             for v in func.ssa.iterv() {
@@ -105,6 +105,10 @@ impl<'a> Debugger<'a> {
             Entry::CallExtern(fkey, params) => {
                 self.check_declaredn(at, params);
                 let extern_ = &self.lir.extern_funcs[fkey];
+                let params = match extern_.variadic {
+                    true => &params[..extern_.params.len().min(params.len())],
+                    false => params.as_slice(),
+                };
                 self.params(params, extern_.params.iter());
                 assert_eq!(exp, &extern_.returns);
             }
@@ -186,6 +190,11 @@ impl<'a> Debugger<'a> {
                 let ty = &self.lir.vals[*val];
                 assert_eq!(exp, &MonoType::pointer(ty.clone()));
             }
+            Entry::RefExternData(fkey) => {
+                let extern_ = &self.lir.extern_funcs[fkey];
+                assert!(extern_.data, "referencing extern function as data");
+                assert_eq!(exp, &extern_.returns);
+            }
             Entry::Field { of, key, field } => {
                 self.check_declared(at, *of);
                 assert_eq!(self.lir.type_of_value(self.mfunc, *of).as_key(), *key);
@@ -248,6 +257,27 @@ impl<'a> Debugger<'a> {
                 let ty = self.lir.type_of_value(self.mfunc, *v);
                 self.as_int(&ty, "iabs");
             }
+            Entry::IntAddCarry(values) | Entry::IntSubBorrow(values) => {
+                self.check_declaredn(at, values);
+                let [lhs, rhs, carry] = values.map(|v| self.lir.type_of_value(self.mfunc, v));
+                assert_eq!(lhs, rhs, "{} != {}", self.tfmt(&lhs), self.tfmt(&rhs));
+                self.as_int(&lhs, "carry arithmetic");
+                assert_eq!(carry, MonoType::bool());
+            }
+            Entry::IntMin(values, intsize) | Entry::IntMax(values, intsize) => {
+                self.check_declaredn(at, values);
+                for ty in values.map(|v| self.lir.type_of_value(self.mfunc, v)) {
+                    self.check(&ty, &MonoType::Int(*intsize));
+                }
+            }
+            Entry::SelectValue(values) => {
+                self.check_declaredn(at, values);
+                let [cond, on_true, on_false] =
+                    values.map(|v| self.lir.type_of_value(self.mfunc, v));
+                self.check(&cond, &MonoType::bool());
+                self.check(&on_true, exp);
+                self.check(&on_false, exp);
+            }
             Entry::Reduce(v) => {
                 self.check_declared(at, *v);
                 let ty = self.lir.type_of_value(self.mfunc, *v);
@@ -275,6 +305,35 @@ impl<'a> Debugger<'a> {
                 self.as_float(&ty, "cast");
                 assert_eq!(*size, self.as_int(exp, "cast"));
             }
+            Entry::FloatCast(v) => {
+                self.check_declared(at, *v);
+                let ty = self.lir.type_of_value(self.mfunc, *v);
+                self.as_float(&ty, "cast");
+                self.as_float(exp, "cast");
+            }
+            Entry::FloatAdd(values)
+            | Entry::FloatSub(values)
+            | Entry::FloatMul(values)
+            | Entry::FloatDiv(values) => {
+                self.check_declaredn(at, values);
+                let size = self.as_float(exp, "float arithmetic");
+                for ty in values.map(|v| self.lir.type_of_value(self.mfunc, v)) {
+                    self.check(&ty, &MonoType::Float(size));
+                }
+            }
+            Entry::FloatNeg(v) => {
+                self.check_declared(at, *v);
+                let ty = self.lir.type_of_value(self.mfunc, *v);
+                self.check(&ty, exp);
+                self.as_float(&ty, "fneg");
+            }
+            Entry::FloatCmp(values, _) => {
+                self.check_declaredn(at, values);
+                let [lhs, rhs] = values.map(|v| self.lir.type_of_value(self.mfunc, v));
+                assert_eq!(lhs, rhs, "{} != {}", self.tfmt(&lhs), self.tfmt(&rhs));
+                self.as_float(&lhs, "float comparison");
+                assert_eq!(*exp, MonoType::bool());
+            }
 
             Entry::BitNot(v) => {
                 self.check_declared(at, *v);
@@ -289,6 +348,7 @@ impl<'a> Debugger<'a> {
             }
             Entry::Alloc { .. } => {}
             Entry::Alloca => {}
+            Entry::Breakpoint => self.as_unit(exp),
             Entry::Dealloc { ptr } => {
                 self.check_declared(at, *ptr);
                 let ty = self.lir.type_of_value(self.mfunc, *ptr);
@@ -314,6 +374,58 @@ impl<'a> Debugger<'a> {
                 assert!(matches!(dstt, MonoType::Pointer(..)));
                 self.as_unit(exp);
             }
+            Entry::MemSet { dst, byte, count } => {
+                self.check_declaredn(at, &[*dst, *byte, *count]);
+                let dstt = self.lir.type_of_value(self.mfunc, *dst);
+                self.as_ptr(&dstt);
+                let bytet = self.lir.type_of_value(self.mfunc, *byte);
+                assert_eq!(bytet, MonoType::byte(), "memset with non-u8 byte");
+                self.as_unit(exp);
+            }
+            Entry::AtomicLoad(ptr, ordering) => {
+                self.check_declared(at, *ptr);
+                let ty = self.lir.type_of_value(self.mfunc, *ptr);
+                self.check(self.as_ptr(&ty), exp);
+                self.as_atomic(exp);
+                assert!(
+                    !matches!(ordering, AtomicOrdering::Release | AtomicOrdering::AcqRel),
+                    "atomic load with {ordering} ordering"
+                );
+            }
+            Entry::AtomicStore { ptr, value, ordering } => {
+                self.check_declared(at, *ptr);
+                self.check_declared(at, *value);
+                let ty = self.lir.type_of_value(self.mfunc, *ptr);
+                let inner = self.as_ptr(&ty);
+                let ty = self.lir.type_of_value(self.mfunc, *value);
+                self.check(&ty, inner);
+                self.as_atomic(&ty);
+                assert!(
+                    !matches!(ordering, AtomicOrdering::Acquire | AtomicOrdering::AcqRel),
+                    "atomic store with {ordering} ordering"
+                );
+                self.as_unit(exp);
+            }
+            Entry::AtomicCas([ptr, expected, replacement], _) => {
+                self.check_declaredn(at, &[*ptr, *expected, *replacement]);
+                let ty = self.lir.type_of_value(self.mfunc, *ptr);
+                self.check(self.as_ptr(&ty), exp);
+                for v in [expected, replacement] {
+                    self.check(&self.lir.type_of_value(self.mfunc, *v), exp);
+                }
+                self.as_atomic(exp);
+            }
+            Entry::AtomicRmw(op, [ptr, value], _) => {
+                self.check_declared(at, *ptr);
+                self.check_declared(at, *value);
+                let ty = self.lir.type_of_value(self.mfunc, *ptr);
+                self.check(self.as_ptr(&ty), exp);
+                self.check(&self.lir.type_of_value(self.mfunc, *value), exp);
+                if *op != AtomicRmwOp::Xchg {
+                    self.as_int(exp, "atomic arithmetic");
+                }
+                self.as_atomic(exp);
+            }
             Entry::Deref(ptr) => {
                 self.check_declared(at, *ptr);
                 // TODO: I think our casts are currently implicit for pointers. Wwe should probably change that?
@@ -365,13 +477,21 @@ impl<'a> Debugger<'a> {
     }
 
     #[track_caller]
-    fn as_float(&self, ty: &MonoType, ctx: &str) {
+    fn as_float(&self, ty: &MonoType, ctx: &str) -> FloatSize {
         match ty {
-            MonoType::Float => {}
+            MonoType::Float(size) => *size,
             _ => panic!("non-float {ctx}: {}", self.tfmt(ty)),
         }
     }
 
+    #[track_caller]
+    fn as_atomic(&self, ty: &MonoType) {
+        match ty {
+            MonoType::Int(_) | MonoType::Pointer(_) => {}
+            _ => panic!("non-scalar atomic: {}", self.tfmt(ty)),
+        }
+    }
+
     #[track_caller]
     fn as_ptr<'t>(&self, ty: &'t MonoType) -> &'t MonoType {
         match ty {
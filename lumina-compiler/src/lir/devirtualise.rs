@@ -0,0 +1,192 @@
+//! Whole-program devirtualisation
+//!
+//! If a trait only has a single implementation in the entire program, every object of that trait
+//! holds the same methods. Calls through the vtable of such objects are replaced with direct calls
+//! to the method wrappers, which lets them be inlined like any other call.
+//!
+//! The same applies to objects whose implementation is known statically, either because they're
+//! constructed in the calling function or because they're returned by a function which always
+//! returns the same implementation, as `some Trait` return types usually do.
+
+use super::{Entry, MonoFunc, MonoType, MonoTypeData, Value, LIR, SSA, V};
+use crate::prelude::*;
+use crate::VTABLE_FIELD;
+use either::Either;
+
+/// The vtable of an object, or the method wrapper stored in place of it
+type Vtable = Either<M<key::Val>, MonoFunc>;
+
+/// The method wrappers of the single implementation of a trait
+#[derive(Clone)]
+enum Methods {
+    // Objects of single-method traits store the function pointer directly
+    Single(MonoFunc),
+    Vtable(Vec<MonoFunc>),
+}
+
+impl LIR {
+    pub(super) fn devirtualise(&mut self, mir: &mir::MIR) {
+        let mut impls = HashMap::<M<key::Trait>, Vec<M<key::Impl>>>::new();
+        for ikey in mir.itraits.iter() {
+            impls.entry(mir.itraits[ikey].0).or_default().push(ikey);
+        }
+
+        let sole = impls
+            .into_iter()
+            .filter_map(|(trait_, impls)| match impls[..] {
+                // Generic implementations can still have several implementing types
+                [ikey] if mir.impls[ikey].generics.is_empty() => {
+                    let methods = self.methods(*self.memo_trait_objects.get(&ikey)?)?;
+                    Some((trait_, methods))
+                }
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+
+        let returned = self
+            .functions
+            .iter()
+            .filter_map(|(mfunc, func)| Some((mfunc, self.returned_vtable(&func.ssa)?)))
+            .collect::<HashMap<_, _>>();
+
+        for fkey in self.functions.keys() {
+            let ssa = &self.functions[fkey].ssa;
+            let calls = ssa
+                .iterv()
+                .filter_map(|v| match ssa.entry_of(v) {
+                    Entry::CallValue(Value::V(fnptr), _) => {
+                        let mfunc = self.method_of_fnptr(ssa, &sole, &returned, *fnptr)?;
+                        Some((v, mfunc))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            for (v, mfunc) in calls {
+                info!(
+                    "devirtualising call to {} in {}",
+                    &self.functions[mfunc].symbol, &self.functions[fkey].symbol
+                );
+
+                let ssa = &mut self.functions[fkey].ssa;
+                let entry = ssa.entry_of_mut(v);
+                let Entry::CallValue(_, params) = &mut *entry else {
+                    unreachable!();
+                };
+                *entry = Entry::CallStatic(mfunc, std::mem::take(params));
+
+                self.functions[mfunc].invocations += 1;
+            }
+        }
+    }
+
+    fn methods(&self, vtable: Vtable) -> Option<Methods> {
+        match vtable {
+            Either::Right(mfunc) => Some(Methods::Single(mfunc)),
+            Either::Left(val) => self.vtable_methods(val).map(Methods::Vtable),
+        }
+    }
+
+    // The vtable of every object returned by the function, if they're all the same
+    fn returned_vtable(&self, ssa: &SSA) -> Option<Vtable> {
+        let mut vtables = ssa.iterv().filter_map(|v| match ssa.entry_of(v) {
+            Entry::Return(object) => Some(self.constructed_vtable(ssa, object)),
+            _ => None,
+        });
+
+        let first = vtables.next()??;
+        vtables.all(|vtable| vtable == Some(first)).then_some(first)
+    }
+
+    // The vtable a trait object was constructed with in this function
+    fn constructed_vtable(&self, ssa: &SSA, object: &Value) -> Option<Vtable> {
+        let Value::V(object) = object else {
+            return None;
+        };
+
+        let MonoType::Monomorphised(key) = ssa.type_of(*object) else {
+            return None;
+        };
+        if !matches!(&self.mono.types[*key], MonoTypeData::DynTraitObject { .. }) {
+            return None;
+        }
+
+        let Entry::Construct(fields) = ssa.entry_of(*object) else {
+            return None;
+        };
+
+        match fields.get(VTABLE_FIELD.0 as usize)? {
+            Value::FuncPtr(mfunc) => Some(Either::Right(*mfunc)),
+            Value::V(vtableptr) => match ssa.entry_of(*vtableptr) {
+                Entry::RefStaticVal(val) => Some(Either::Left(*val)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // The method wrappers stored in the vtable constructed by the initialiser of a static value
+    fn vtable_methods(&self, val: M<key::Val>) -> Option<Vec<MonoFunc>> {
+        let ssa = &self.functions[self.val_initialisers[&val]].ssa;
+        ssa.iterv().find_map(|v| match ssa.entry_of(v) {
+            Entry::Construct(fptrs) => fptrs
+                .iter()
+                .map(|fptr| match fptr {
+                    Value::FuncPtr(mfunc) => Some(*mfunc),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        })
+    }
+
+    // The method a function pointer was loaded from, if the implementation of its object is known
+    //
+    // single method:  v0 = field obj 1
+    // multi method:   v0 = field obj 1; v1 = deref v0; v2 = field v1 method
+    fn method_of_fnptr(
+        &self,
+        ssa: &SSA,
+        sole: &HashMap<M<key::Trait>, Methods>,
+        returned: &HashMap<MonoFunc, Vtable>,
+        fnptr: V,
+    ) -> Option<MonoFunc> {
+        let methods_of_object = |v: V| match ssa.entry_of(v) {
+            Entry::Field { of, key, field } if *field == VTABLE_FIELD => {
+                let known = self.constructed_vtable(ssa, of).or_else(|| match of {
+                    Value::V(object) => match ssa.entry_of(*object) {
+                        Entry::CallStatic(mfunc, _) => returned.get(mfunc).copied(),
+                        _ => None,
+                    },
+                    _ => None,
+                });
+
+                if let Some(vtable) = known {
+                    return self.methods(vtable);
+                }
+
+                match &self.mono.types[*key] {
+                    MonoTypeData::DynTraitObject { trait_, .. } => sole.get(trait_).cloned(),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        if let Some(Methods::Single(mfunc)) = methods_of_object(fnptr) {
+            return Some(mfunc);
+        }
+
+        let Entry::Field { of: Value::V(vtable), field, .. } = ssa.entry_of(fnptr) else {
+            return None;
+        };
+        let Entry::Deref(Value::V(vtableptr)) = ssa.entry_of(*vtable) else {
+            return None;
+        };
+
+        match methods_of_object(*vtableptr)? {
+            Methods::Vtable(methods) => methods.get(field.0 as usize).copied(),
+            Methods::Single(_) => None,
+        }
+    }
+}
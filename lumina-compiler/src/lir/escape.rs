@@ -0,0 +1,200 @@
+//! Stack allocation of closures which don't escape
+//!
+//! The captures of a closure are heap allocated since the closure object may outlive the function
+//! which constructed it. Most closures are however only called, or passed down to functions which
+//! themselves only call them. The captures of those closures are allocated on the stack instead.
+
+use super::{Block, Entry, MonoFunc, MonoType, Value, LIR, SSA, V};
+use crate::prelude::*;
+use std::collections::HashSet;
+
+/// Whether each parameter of a function may escape it
+type Summaries = HashMap<MonoFunc, Vec<bool>>;
+
+impl LIR {
+    /// Must run after the tail-call substitution of the optimiser, since a tail call can't be
+    /// given a pointer into the stack frame it replaces.
+    pub(super) fn stack_allocate_closures(&mut self) {
+        let summaries = self.param_escape_summaries();
+
+        for fkey in self.functions.keys() {
+            let ssa = &self.functions[fkey].ssa;
+            let allocs = ssa
+                .iterv()
+                .filter(|&v| matches!(ssa.entry_of(v), Entry::Alloc))
+                .filter(|&v| {
+                    let derived = Derived::of(ssa, v);
+                    !derived.closures.is_empty()
+                        && derived
+                            .closures
+                            .iter()
+                            .all(|mfunc| !escapes_into(&summaries, *mfunc, 0))
+                        && !derived.escapes(ssa, &summaries)
+                })
+                .collect::<Vec<_>>();
+
+            for v in allocs {
+                info!(
+                    "stack allocating captures {v} in {}",
+                    &self.functions[fkey].symbol
+                );
+                *self.functions[fkey].ssa.entry_of_mut(v) = Entry::Alloca;
+            }
+        }
+    }
+
+    // Optimistically assumes that no parameter escapes and then iterates until that holds, which
+    // lets recursive functions keep their parameters from escaping.
+    fn param_escape_summaries(&self) -> Summaries {
+        let mut summaries: Summaries = self
+            .functions
+            .keys()
+            .map(|fkey| {
+                let ssa = &self.functions[fkey].ssa;
+                let count = entry_params(ssa).count();
+                (fkey, vec![false; count])
+            })
+            .collect();
+
+        loop {
+            let mut changed = false;
+
+            for fkey in self.functions.keys() {
+                let ssa = &self.functions[fkey].ssa;
+
+                for (i, param) in entry_params(ssa).enumerate() {
+                    if summaries[&fkey][i] || !may_hold_pointer(ssa.type_of(param)) {
+                        continue;
+                    }
+
+                    if Derived::of(ssa, param).escapes(ssa, &summaries) {
+                        summaries.get_mut(&fkey).unwrap()[i] = true;
+                        changed = true;
+                    }
+                }
+            }
+
+            if !changed {
+                return summaries;
+            }
+        }
+    }
+}
+
+fn entry_params(ssa: &SSA) -> impl Iterator<Item = V> + '_ {
+    ssa.block_start(Block::entry())
+        .into_iter()
+        .flat_map(|_| ssa.block_params(Block::entry()))
+}
+
+fn may_hold_pointer(ty: &MonoType) -> bool {
+    !matches!(
+        ty,
+        MonoType::Int(_) | MonoType::Float(_) | MonoType::FnPointer(..)
+    )
+}
+
+fn escapes_into(summaries: &Summaries, mfunc: MonoFunc, param: usize) -> bool {
+    summaries
+        .get(&mfunc)
+        .and_then(|params| params.get(param).copied())
+        .unwrap_or(true)
+}
+
+/// The values which may hold the pointer of an allocation or parameter
+struct Derived {
+    values: HashSet<V>,
+    /// Closure functions whose objects hold the pointer as their data
+    closures: Vec<MonoFunc>,
+}
+
+impl Derived {
+    fn of(ssa: &SSA, root: V) -> Self {
+        let mut values = HashSet::from([root]);
+
+        // Blocks aren't laid out in dominance order, so a single pass isn't enough
+        loop {
+            let mut changed = false;
+
+            for v in ssa.iterv() {
+                if values.contains(&v) {
+                    continue;
+                }
+
+                let propagates = matches!(
+                    ssa.entry_of(v),
+                    Entry::Transmute(_)
+                        | Entry::Construct(_)
+                        | Entry::Replicate(..)
+                        | Entry::Variant(..)
+                        | Entry::Field { .. }
+                        | Entry::CastFromSum { .. }
+                        | Entry::BinOp(..)
+                        | Entry::SelectValue(_)
+                );
+
+                if propagates && uses_any(ssa, v, &values) {
+                    values.insert(v);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        let closures = values
+            .iter()
+            .filter_map(|&v| match ssa.entry_of(v) {
+                Entry::Construct(fields) => Some(fields),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|value| match value {
+                Value::FuncPtr(mfunc) => Some(*mfunc),
+                _ => None,
+            })
+            .collect();
+
+        Derived { values, closures }
+    }
+
+    fn contains(&self, value: &Value) -> bool {
+        matches!(value, Value::V(v) if self.values.contains(v))
+    }
+
+    fn escapes(&self, ssa: &SSA, summaries: &Summaries) -> bool {
+        ssa.iterv().any(|v| match ssa.entry_of(v) {
+            Entry::CallStatic(mfunc, params) => params
+                .iter()
+                .enumerate()
+                .any(|(i, p)| self.contains(p) && escapes_into(summaries, *mfunc, i)),
+
+            // Calling the closure itself passes its data as the first parameter, which is checked
+            // against the closure function where the object is constructed.
+            Entry::CallValue(fnptr, params) if self.contains(fnptr) => {
+                params.iter().skip(1).any(|p| self.contains(p))
+            }
+
+            Entry::WritePtr { value, .. } | Entry::AtomicStore { value, .. } => {
+                self.contains(value)
+            }
+            Entry::Deref(_)
+            | Entry::AtomicLoad(..)
+            | Entry::MemCpy { .. }
+            | Entry::MemSet { .. } => false,
+
+            _ if self.values.contains(&v) => false,
+
+            // Returns, jumps with block parameters, extern calls, and everything else
+            _ => uses_any(ssa, v, &self.values),
+        })
+    }
+}
+
+fn uses_any(ssa: &SSA, v: V, values: &HashSet<V>) -> bool {
+    let mut found = false;
+    ssa.for_each_use(v, &mut |used| found |= values.contains(&used));
+    found
+}
@@ -79,6 +79,8 @@ impl<'a> FuncLower<'a> {
                 let mut mono = to_morphization!(self.lir, self.mir, &mut self.current.tmap);
                 let ty = MonoType::Monomorphised(mono.record(*record, types));
 
+                // Fields are evaluated in the order they're written and only then sorted into the
+                // order they're declared in
                 let values = fields
                     .iter()
                     .map(|(_, _, expr)| self.expr_to_value(expr))
@@ -188,14 +190,22 @@ impl<'a> FuncLower<'a> {
                 let inner = self.expr_to_value(&expr);
                 self.int_cast(inner, [*from, *to])
             }
-            mir::Expr::ToFloatCast(expr, fromint) => {
+            mir::Expr::ToFloatCast(expr, fromint, to) => {
                 let inner = self.expr_to_value(&expr);
-                self.ssa().int_to_float(inner, *fromint)
+                self.ssa().int_to_float(inner, *fromint, *to)
             }
-            mir::Expr::FromFloatCast(expr, toint) => {
+            mir::Expr::FromFloatCast(expr, _, toint) => {
                 let inner = self.expr_to_value(&expr);
                 self.ssa().float_to_int(inner, *toint)
             }
+            mir::Expr::FloatCast(expr, from, to) => {
+                let inner = self.expr_to_value(&expr);
+                if from == to {
+                    inner
+                } else {
+                    self.ssa().float_cast(inner, *to)
+                }
+            }
             mir::Expr::Deref(inner) => {
                 let inner = self.expr_to_value(&inner);
                 let ty = self.type_of_value(inner).deref();
@@ -209,6 +219,39 @@ impl<'a> FuncLower<'a> {
                 let [dst, src, count] = self.params_to_values(&**params).try_into().unwrap();
                 self.ssa().memcpy(dst, src, count)
             }
+            mir::Expr::MemSet(params) => {
+                let [dst, byte, count] = self.params_to_values(&**params).try_into().unwrap();
+                self.ssa().memset(dst, byte, count)
+            }
+            mir::Expr::Atomic(op, ordering, params) => {
+                let ordering = AtomicOrdering::from_name(ordering).unwrap();
+                match (*op, self.params_to_values(params).as_slice()) {
+                    ("load", &[ptr]) => {
+                        let ty = self.type_of_value(ptr).deref();
+                        self.ssa().atomic_load(ptr, ordering, ty)
+                    }
+                    ("store", &[ptr, value]) => self.ssa().atomic_store(ptr, value, ordering),
+                    ("cas", &[ptr, expected, replacement]) => {
+                        let ty = self.type_of_value(expected);
+                        self.ssa()
+                            .atomic_cas([ptr, expected, replacement], ordering, ty)
+                    }
+                    (op, &[ptr, value]) => {
+                        let op = match op {
+                            "add" => AtomicRmwOp::Add,
+                            "sub" => AtomicRmwOp::Sub,
+                            "and" => AtomicRmwOp::And,
+                            "or" => AtomicRmwOp::Or,
+                            "xor" => AtomicRmwOp::Xor,
+                            "swap" => AtomicRmwOp::Xchg,
+                            _ => panic!("unknown atomic operation: {op}"),
+                        };
+                        let ty = self.type_of_value(value);
+                        self.ssa().atomic_rmw(op, [ptr, value], ordering, ty)
+                    }
+                    (op, params) => panic!("invalid parameters for atomic {op}: {}", params.len()),
+                }
+            }
             mir::Expr::ObjectCast(expr, weak_impltor, trait_, trait_params) => {
                 let trait_ = *trait_;
                 let expr = self.expr_to_value(expr);
@@ -245,6 +288,14 @@ impl<'a> FuncLower<'a> {
 
                 self.dyn_object(impl_, trait_params, expr, methods)
             }
+            mir::Expr::Located(span, inner) => {
+                let module = self.lir.functions[self.current.mfkey].kind.module();
+                let location = ssa::Location { module, span: *span };
+                let previous = self.ssa().set_location(Some(location));
+                let value = self.expr_to_value(inner);
+                self.ssa().set_location(previous);
+                value
+            }
             mir::Expr::Match(on, tree, branches, pred) => {
                 let on = self.expr_to_value(on);
                 self.to_pat_lower(branches, pred).run(on, tree)
@@ -276,6 +327,14 @@ impl<'a> FuncLower<'a> {
 
                 let intsize = match self.type_of_value(params[0]) {
                     MonoType::Int(intsize) => intsize,
+                    MonoType::Float(_) => {
+                        return match *cmp {
+                            "eq" => self.ssa().fcmp(params, Ordering::Equal),
+                            "lt" => self.ssa().fcmp(params, Ordering::Less),
+                            "gt" => self.ssa().fcmp(params, Ordering::Greater),
+                            _ => panic!("unknown comparison operator: {cmp}"),
+                        };
+                    }
                     ty => panic!("not an int: {ty:?}"),
                 };
 
@@ -286,11 +345,28 @@ impl<'a> FuncLower<'a> {
                     _ => panic!("unknown comparison operator: {cmp}"),
                 }
             }
+            mir::Expr::Expect(cond, expected) => {
+                let cond = self.expr_to_value(cond);
+                if let Value::V(v) = cond {
+                    self.current.expectations.insert(v, *expected);
+                }
+                cond
+            }
             mir::Expr::IntAbs(n) => {
                 let n = self.expr_to_value(&*n);
                 let ty = self.type_of_value(n);
                 self.ssa().abs(n, ty)
             }
+            mir::Expr::Neg(n) => {
+                let n = self.expr_to_value(&*n);
+                match self.type_of_value(n) {
+                    MonoType::Float(size) => self.ssa().fneg(n, size),
+                    ty => {
+                        let zero = Value::Int(0, ty.as_int());
+                        self.ssa().sub(zero, n, ty)
+                    }
+                }
+            }
             mir::Expr::Num(name, params) => {
                 // Lazy operators
                 match *name {
@@ -304,10 +380,37 @@ impl<'a> FuncLower<'a> {
                     }
                 }
             }
+            mir::Expr::Slice(op, params) => {
+                let params = self.params_to_values(params);
+                self.slice_op(op, params)
+            }
+            mir::Expr::SelectValue(params) => {
+                let [cond, on_true, on_false] = [0, 1, 2].map(|i| self.expr_to_value(&params[i]));
+                self.select_value(cond, on_true, on_false)
+            }
+            mir::Expr::NumCarry(name, params) => {
+                let left = self.expr_to_value(&params[0]);
+                let right = self.expr_to_value(&params[1]);
+                let carry = self.expr_to_value(&params[2]);
+
+                let ty = self.type_of_value(left);
+                let cty = self
+                    .lir
+                    .mono
+                    .get_or_make_tuple(vec![ty, MonoType::bool()])
+                    .into();
+
+                match *name {
+                    "plus_carry" => self.ssa().add_carry(left, right, carry, cty),
+                    "minus_borrow" => self.ssa().sub_borrow(left, right, carry, cty),
+                    _ => panic!("unknown num builtin: {name}"),
+                }
+            }
             mir::Expr::Unreachable(ty) => {
                 let ty = to_morphization!(self.lir, self.mir, &mut self.current.tmap).apply(ty);
                 self.ssa().unreachable(ty)
             }
+            mir::Expr::Breakpoint => self.ssa().breakpoint(),
             mir::Expr::Poison => panic!("poisoned reached in LIR lower"),
         }
     }
@@ -342,6 +445,11 @@ impl<'a> FuncLower<'a> {
 
     fn eager_binop(&mut self, name: &'static str, [left, right]: [Value; 2]) -> Value {
         let ty = self.type_of_value(left);
+
+        if let MonoType::Float(size) = ty {
+            return self.float_binop(name, [left, right], size);
+        }
+
         let cty = self
             .lir
             .mono
@@ -353,15 +461,52 @@ impl<'a> FuncLower<'a> {
             "minus" => self.ssa().sub(left, right, ty),
             "mul" => self.ssa().mul(left, right, ty),
             "div" => self.ssa().div(left, right, ty),
+            "rem" => self.ssa().rem(left, right, ty),
             "plus_checked" => self.ssa().add(left, right, cty),
             "minus_checked" => self.ssa().sub(left, right, cty),
             "mul_checked" => self.ssa().mul(left, right, cty),
             "div_checked" => self.ssa().div(left, right, cty),
+            "min" => self.ssa().min([left, right], ty.as_int()),
+            "max" => self.ssa().max([left, right], ty.as_int()),
             _ => panic!("unknown num builtin: {name}"),
         }
     }
 
-    fn int_cast(&mut self, v: Value, [from, to]: [IntSize; 2]) -> Value {
+    fn float_binop(
+        &mut self,
+        name: &'static str,
+        [left, right]: [Value; 2],
+        size: FloatSize,
+    ) -> Value {
+        match name {
+            "plus" => self.ssa().fadd(left, right, size),
+            "minus" => self.ssa().fsub(left, right, size),
+            "mul" => self.ssa().fmul(left, right, size),
+            "div" => self.ssa().fdiv(left, right, size),
+            _ => panic!("unsupported float builtin: {name}"),
+        }
+    }
+
+    fn select_value(&mut self, cond: Value, on_true: Value, on_false: Value) -> Value {
+        let ty = self.type_of_value(on_true);
+
+        match ty {
+            MonoType::Int(_)
+            | MonoType::Float(_)
+            | MonoType::Pointer(_)
+            | MonoType::FnPointer(..) => self.ssa().select_value(cond, on_true, on_false, ty),
+            // Aggregates can't be selected between without branching
+            _ => {
+                let contb = self.ssa().new_block();
+                self.ssa()
+                    .select(cond, [(contb, vec![on_true]), (contb, vec![on_false])]);
+                self.ssa().switch_to_block(contb);
+                self.ssa().add_block_param(contb, ty).value()
+            }
+        }
+    }
+
+    pub fn int_cast(&mut self, v: Value, [from, to]: [IntSize; 2]) -> Value {
         let ty = MonoType::Int(to);
 
         match from.bits().cmp(&to.bits()) {
@@ -374,11 +519,43 @@ impl<'a> FuncLower<'a> {
         }
     }
 
+    // Integers smaller than `int` are passed as `int`, and `float` as `double`
+    fn promote_variadic(&mut self, v: Value) -> Value {
+        match self.type_of_value(v) {
+            MonoType::Int(intsize) if intsize.bits() < 32 => {
+                self.ssa()
+                    .extend(v, intsize.signed, MonoType::Int(IntSize::new(true, 32)))
+            }
+            MonoType::Float(FloatSize::F32) => self.ssa().float_cast(v, FloatSize::F64),
+            _ => v,
+        }
+    }
+
     fn call(&mut self, call: &mir::Callable, params: Vec<Value>) -> Value {
         match self.lower_callable(call) {
             Callable::Extern(fkey) => {
-                let ret = self.lir.extern_funcs[&fkey].returns.clone();
-                self.ssa().call_extern(fkey, params, ret)
+                let func = &self.lir.extern_funcs[&fkey];
+                let ret = func.returns.clone();
+                if func.data {
+                    assert!(params.is_empty(), "giving parameters to extern data");
+                    self.ssa().extern_data_ref(fkey, ret)
+                } else if func.variadic {
+                    let fixed = func.params.len();
+                    let params = params
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, v)| {
+                            if i < fixed {
+                                v
+                            } else {
+                                self.promote_variadic(v)
+                            }
+                        })
+                        .collect();
+                    self.ssa().call_extern(fkey, params, ret)
+                } else {
+                    self.ssa().call_extern(fkey, params, ret)
+                }
             }
             Callable::Static(mfunc) => {
                 let ret = self.lir.functions[mfunc].returns.clone();
@@ -395,7 +572,13 @@ impl<'a> FuncLower<'a> {
                 let v = self.ssa().val_to_ref(key, ty.clone());
                 self.ssa().deref(v, ty)
             }
-            Callable::Sum { var, ty, .. } => self.ssa().variant(var, params, ty),
+            Callable::Sum { var, ty, .. } => match self.lir.mono.niche_of(ty).cloned() {
+                Some(pointer) => {
+                    let size_t = self.types().size_t();
+                    self.ssa().niche_variant(params, pointer, size_t)
+                }
+                None => self.ssa().variant(var, params, ty),
+            },
             Callable::Local(to_call) => {
                 let ty = self.type_of_value(to_call);
                 match ty {
@@ -473,10 +656,18 @@ impl<'a> FuncLower<'a> {
         weak_impltor: Type,
         impltor: MonoType,
     ) -> (M<key::Impl>, TypeMap) {
-        warn!(
-            "conflicting implementations is not fully implemented. Weird auto-selections may occur"
-        );
+        self.try_find_implementation(trait_, trtp, weak_impltor, impltor)
+            .unwrap()
+    }
 
+    /// Like `find_implementation` but for types which aren't known to implement the trait
+    pub fn try_find_implementation(
+        &mut self,
+        trait_: M<key::Trait>,
+        trtp: &[Type],
+        weak_impltor: Type,
+        impltor: MonoType,
+    ) -> Option<(M<key::Impl>, TypeMap)> {
         let concrete_impltor = (&weak_impltor).try_into().ok();
 
         info!(
@@ -486,37 +677,105 @@ impl<'a> FuncLower<'a> {
             self.current.origin.name(self.mir)
         );
 
-        self.iquery
-            .for_each_relevant(trait_, concrete_impltor, |imp| {
-                let iforall = &self.mir.impls[imp];
-                let (_, trait_params) = &self.mir.itraits[imp];
-                let iimpltor = &self.mir.impltors[imp];
-
-                let mut comp = lumina_typesystem::Compatibility::new(
-                    &self.iquery,
-                    &|_| panic!("un-monomorphised generic in LHS"),
-                    &iforall,
-                    &|_| unreachable!(),
-                );
+        let get_impl_data = &|ikey: M<key::Impl>| {
+            let (trait_, trait_params) = &self.mir.itraits[ikey];
+            (
+                *trait_,
+                &self.mir.impls[ikey],
+                &*self.mir.impltors[ikey],
+                trait_params.as_slice(),
+            )
+        };
 
-                let valid = trtp
-                    .iter()
-                    .zip(trait_params)
-                    .all(|(ty, ttp)| comp.cmp(ty, ttp))
-                    && comp.cmp(&weak_impltor, iimpltor);
-
-                valid.then(|| {
-                    let mut tmap = TypeMap::new();
-                    tmap.set_self(weak_impltor.clone(), impltor.clone());
-                    for (generic, ty) in comp.into_assignments().generics.into_iter() {
-                        let mono =
-                            to_morphization!(self.lir, self.mir, &mut TypeMap::new()).apply(&ty);
-                        tmap.push(generic, ty, mono);
-                    }
-                    (imp, tmap)
-                })
+        // Coherence is checked in HIR, so out of all implementations whose types and constraints
+        // apply there's always a most specific one.
+        let (imp, assignments) = self
+            .iquery
+            .relevant(trait_, concrete_impltor)
+            .filter_map(|imp| {
+                self.impl_assignments(imp, trtp, &weak_impltor)
+                    .map(|assignments| (imp, assignments))
             })
-            .unwrap()
+            .reduce(|best, candidate| {
+                if self
+                    .iquery
+                    .is_subsumed_by(get_impl_data, candidate.0, best.0)
+                {
+                    candidate
+                } else {
+                    best
+                }
+            })?;
+
+        Some((imp, self.impl_tmap(assignments, weak_impltor, impltor)))
+    }
+
+    /// Instantiate an implementation which was selected explicitly rather than found through
+    /// the index
+    pub fn instantiate_implementation(
+        &mut self,
+        imp: M<key::Impl>,
+        trtp: &[Type],
+        weak_impltor: Type,
+        impltor: MonoType,
+    ) -> TypeMap {
+        let assignments = self
+            .impl_assignments(imp, trtp, &weak_impltor)
+            .expect("explicitly selected implementation does not apply");
+
+        self.impl_tmap(assignments, weak_impltor, impltor)
+    }
+
+    fn impl_assignments(
+        &self,
+        imp: M<key::Impl>,
+        trtp: &[Type],
+        weak_impltor: &Type,
+    ) -> Option<GenericMapper<Static>> {
+        let get_impl_data = &|ikey: M<key::Impl>| {
+            let (trait_, trait_params) = &self.mir.itraits[ikey];
+            (
+                *trait_,
+                &self.mir.impls[ikey],
+                &*self.mir.impltors[ikey],
+                trait_params.as_slice(),
+            )
+        };
+
+        let iforall = &self.mir.impls[imp];
+        let (_, trait_params) = &self.mir.itraits[imp];
+        let iimpltor = &self.mir.impltors[imp];
+
+        let mut comp = lumina_typesystem::Compatibility::new(
+            &self.iquery,
+            &|_| panic!("un-monomorphised generic in LHS"),
+            &iforall,
+            get_impl_data,
+        );
+
+        let valid = trtp
+            .iter()
+            .zip(trait_params)
+            .all(|(ty, ttp)| comp.cmp(ty, ttp))
+            && comp.cmp(weak_impltor, iimpltor)
+            && comp.check_all();
+
+        valid.then(|| comp.into_assignments())
+    }
+
+    fn impl_tmap(
+        &mut self,
+        assignments: GenericMapper<Static>,
+        weak_impltor: Type,
+        impltor: MonoType,
+    ) -> TypeMap {
+        let mut tmap = TypeMap::new();
+        tmap.set_self(weak_impltor, impltor);
+        for (generic, ty) in assignments.generics.into_iter() {
+            let mono = to_morphization!(self.lir, self.mir, &mut TypeMap::new()).apply(&ty);
+            tmap.push(generic, ty, mono);
+        }
+        tmap
     }
 
     pub fn call_to_mfunc(&mut self, func: Item, mut tmap: TypeMap) -> MonoFunc {
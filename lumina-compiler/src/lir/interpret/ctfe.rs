@@ -0,0 +1,117 @@
+use super::{Bytes, Error, Interpreter, INTERPRETER_STACK_SIZE};
+use crate::lir::{MonoType, MonoTypeData, MonoTypeKey, Output, Types};
+use crate::prelude::*;
+use std::collections::HashMap;
+
+/// Maximum amount of entries a single val initialiser may execute at compile time
+pub const CONST_EVAL_STEP_LIMIT: u64 = 10_000_000;
+
+pub struct ConstError {
+    pub val: String,
+    pub error: Error,
+    /// Symbols of the functions being evaluated when the error occurred, outermost first
+    pub trace: Vec<String>,
+}
+
+/// Evaluate the initialisers of all vals marked with `@[const]` or `@[static_assert]`
+///
+/// The results are stored in `val_constants` in the interpreter's memory layout. Const vals may
+/// depend on each other, so vals which fail are retried for as long as others still make progress.
+pub fn evaluate_consts(lir: &mut Output) -> Vec<ConstError> {
+    let (constants, errors) = std::thread::scope(|scope| {
+        let lir = &*lir;
+        std::thread::Builder::new()
+            .name(String::from("const-evaluator"))
+            .stack_size(INTERPRETER_STACK_SIZE)
+            .spawn_scoped(scope, move || evaluate(lir))
+            .expect("failed to spawn const evaluator thread")
+            .join()
+            .unwrap_or_else(|err| std::panic::resume_unwind(err))
+    });
+
+    lir.val_constants = constants;
+    errors
+}
+
+fn evaluate(lir: &Output) -> (HashMap<M<key::Val>, Bytes>, Vec<ConstError>) {
+    let mut constants = HashMap::new();
+    let mut errors = vec![];
+    let mut pending = vec![];
+
+    for val in lir.const_vals.iter().copied() {
+        if is_plain_data(&lir.types, &lir.val_types[val], &mut vec![]) {
+            pending.push(val);
+        } else {
+            let mut sink = std::io::sink();
+            let val = Interpreter::new(lir, &mut sink).val_name(val);
+            errors.push(ConstError { val, error: Error::UnrepresentableConst, trace: vec![] });
+        }
+    }
+
+    loop {
+        let mut failed = vec![];
+        let mut deferred = vec![];
+        let mut progress = false;
+
+        for val in pending {
+            let mut sink = std::io::sink();
+            let mut interpreter = Interpreter::new(lir, &mut sink).for_const_eval(&constants);
+            let mfunc = lir.val_initializers[&val];
+
+            match interpreter.call(mfunc, vec![]) {
+                Ok(bytes) => {
+                    constants.insert(val, bytes);
+                    progress = true;
+                }
+                Err(error) => {
+                    let trace = interpreter.trace().map(str::to_string).collect();
+                    let val_name = interpreter.val_name(val);
+                    failed.push(ConstError { val: val_name, error, trace });
+                    deferred.push(val);
+                }
+            }
+        }
+
+        if !progress || deferred.is_empty() {
+            errors.extend(failed);
+            break;
+        }
+
+        pending = deferred;
+    }
+
+    for (val, message) in lir.static_asserts.iter() {
+        if let Some(bytes) = constants.get(val) {
+            if bytes.iter().all(|b| *b == 0) {
+                let mut sink = std::io::sink();
+                let val = Interpreter::new(lir, &mut sink).val_name(*val);
+                let error = Error::StaticAssert(message.clone());
+                errors.push(ConstError { val, error, trace: vec![] });
+            }
+        }
+    }
+
+    (constants, errors)
+}
+
+/// Whether the type only consists of numbers, arrays and records
+///
+/// Pointers into the interpreter's memory and boxed sum payloads can't be stored in the binary.
+fn is_plain_data(types: &Types, ty: &MonoType, visiting: &mut Vec<MonoTypeKey>) -> bool {
+    match ty {
+        MonoType::Int(_) | MonoType::Float(_) => true,
+        MonoType::Array(_, inner) => is_plain_data(types, inner, visiting),
+        MonoType::Monomorphised(key) if !visiting.contains(key) => match &types[*key] {
+            MonoTypeData::Record { fields, .. } => {
+                visiting.push(*key);
+                let plain = fields
+                    .values()
+                    .all(|fty| is_plain_data(types, fty, visiting));
+                visiting.pop();
+                plain
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
@@ -0,0 +1,87 @@
+use super::{decode, decode_float, Error, Interpreter, INTERPRETER_STACK_SIZE};
+use crate::lir::{MonoFunc, MonoType, MonoTypeData, Output};
+use crate::prelude::*;
+use itertools::Itertools;
+use std::io::Write;
+
+/// Call a function without parameters and render the value it returns
+///
+/// The vals are initialised first, so the function may refer to them. Anything printed while
+/// evaluating is written to `stdout`.
+pub fn evaluate(
+    lir: &Output,
+    mfunc: MonoFunc,
+    stdout: &mut (dyn Write + Send),
+) -> Result<String, Error> {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .name(String::from("lir-evaluator"))
+            .stack_size(INTERPRETER_STACK_SIZE)
+            .spawn_scoped(scope, move || {
+                let mut interpreter = Interpreter::new(lir, stdout);
+                interpreter.init_vals()?;
+                let value = interpreter.call(mfunc, vec![])?;
+                interpreter.render(&value, &lir.functions[mfunc].returns)
+            })
+            .expect("failed to spawn evaluator thread")
+            .join()
+            .unwrap_or_else(|err| std::panic::resume_unwind(err))
+    })
+}
+
+impl<'a> Interpreter<'a> {
+    /// Render a value encoded in the interpreter's memory layout
+    ///
+    /// Since LIR doesn't retain the names of types, records are rendered as their fields and sums
+    /// as the index of their variant followed by its payload.
+    pub fn render(&self, bytes: &[u8], ty: &MonoType) -> Result<String, Error> {
+        Ok(match ty {
+            MonoType::Int(intsize) if intsize.bits() == 1 => {
+                (decode(bytes, false) != 0).to_string()
+            }
+            MonoType::Int(intsize) => decode(bytes, intsize.signed).to_string(),
+            MonoType::Float(_) => decode_float(bytes).to_string(),
+            MonoType::Pointer(_) | MonoType::FnPointer(..) => {
+                format!("{:#x}", decode(bytes, false))
+            }
+            MonoType::Unreachable => String::from("!"),
+            MonoType::Const(value) => format!("{value:?}"),
+            MonoType::Array(len, inner) => {
+                let stride = self.layouts.stride_of(inner);
+                let elems = (0..*len)
+                    .map(|i| self.render(&self.slice(bytes, i * stride, inner), inner))
+                    .collect::<Result<Vec<_>, _>>()?;
+                format!("[{}]", elems.iter().format(", "))
+            }
+            MonoType::Monomorphised(mk) => match &self.lir.types[*mk] {
+                MonoTypeData::Record { fields, .. } => {
+                    let offsets = &self.layouts.record(*mk).offsets;
+                    let fields = fields
+                        .iter()
+                        .map(|(field, ty)| {
+                            let offset = offsets[field.0 as usize];
+                            self.render(&self.slice(bytes, offset, ty), ty)
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    format!("{{{}}}", fields.iter().format(", "))
+                }
+                MonoTypeData::Sum { tag, variants, .. } => {
+                    let var = decode(&bytes[..tag.bytes().max(1) as usize], false);
+                    let payload_ty = MonoType::Monomorphised(variants[key::Variant(var as u32)]);
+                    let size = self.layouts.size_of(&payload_ty);
+                    if size == 0 {
+                        format!("#{var}")
+                    } else {
+                        let offset = self.layouts.payload_offset(*mk);
+                        let ptr =
+                            decode(&self.slice(bytes, offset, &MonoType::u8_pointer()), false);
+                        let payload = self.memory.read(ptr as u64, size)?;
+                        format!("#{var} {}", self.render(payload, &payload_ty)?)
+                    }
+                }
+                MonoTypeData::DynTraitObject { .. } => String::from("<dyn object>"),
+                MonoTypeData::Placeholder => unreachable!("placeholder type in evaluated value"),
+            },
+        })
+    }
+}
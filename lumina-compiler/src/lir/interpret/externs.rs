@@ -0,0 +1,232 @@
+use super::{decode, decode_float, float_bytes, int_bytes, Bytes, Error, Interpreter};
+use crate::prelude::*;
+use lumina_typesystem::FloatSize;
+use std::io::{Read, Write};
+use std::iter::Peekable;
+
+impl<'a> Interpreter<'a> {
+    /// Emulates the subset of libc used by the standard library, along with some of libm
+    pub(super) fn call_extern(
+        &mut self,
+        key: M<key::Func>,
+        params: Vec<Bytes>,
+    ) -> Result<Bytes, Error> {
+        let func = &self.lir.extern_funcs[&key];
+        if self.constant.is_some() {
+            return Err(Error::ExternAtCompileTime(func.symbol));
+        }
+
+        if let (Some(f), [x]) = (libm(&func.symbol), params.as_slice()) {
+            return Ok(float_bytes(f(decode_float(x)), FloatSize::F64));
+        }
+
+        let args: Vec<i128> = params.iter().map(|p| decode(p, true)).collect();
+        let ret = self.layouts.size_of(&func.returns) as usize;
+
+        let n = match func.symbol.as_str() {
+            "write" => {
+                let [fd, ptr, len] = [args[0], args[1], args[2]];
+                let bytes = self.memory.read(ptr as u64, len as u64)?;
+                let result = match fd {
+                    1 => self.stdout.write_all(bytes),
+                    2 => std::io::stderr().write_all(bytes),
+                    _ => return Err(Error::UnsupportedExtern(format!("write to fd {fd}"))),
+                };
+                result.map(|_| len).unwrap_or(-1)
+            }
+            "read" => {
+                let [fd, ptr, len] = [args[0], args[1], args[2]];
+                if fd != 0 {
+                    return Err(Error::UnsupportedExtern(format!("read from fd {fd}")));
+                }
+                let mut buf = vec![0; len as usize];
+                match std::io::stdin().read(&mut buf) {
+                    Ok(n) => {
+                        self.memory.write(ptr as u64, &buf[..n])?;
+                        n as i128
+                    }
+                    Err(_) => -1,
+                }
+            }
+            "exit" => return Err(Error::Exit(args[0] as i32)),
+            "raise" => return Err(Error::Signal(args[0] as i64)),
+            "malloc" => self.memory.alloc(args[0] as u64, 16) as i128,
+            "free" => 0,
+            "getenv" => {
+                let name = self.memory.read_cstr(args[0] as u64)?;
+                match std::env::var(String::from_utf8_lossy(&name).as_ref()) {
+                    Ok(value) => {
+                        let mut bytes = value.into_bytes();
+                        bytes.push(0);
+                        self.memory.alloc_bytes(&bytes) as i128
+                    }
+                    Err(_) => 0,
+                }
+            }
+            "__errno_location" => self.errno as i128,
+            "printf" => {
+                let formatted = self.printf(args[0] as u64, &params[1..])?;
+                match self.stdout.write_all(&formatted) {
+                    Ok(_) => formatted.len() as i128,
+                    Err(_) => -1,
+                }
+            }
+            "snprintf" => {
+                let [buf, size] = [args[0] as u64, args[1] as u64];
+                let mut formatted = self.printf(args[2] as u64, &params[3..])?;
+                let len = formatted.len() as i128;
+                if size != 0 {
+                    formatted.truncate(size as usize - 1);
+                    formatted.push(0);
+                    self.memory.write(buf, &formatted)?;
+                }
+                len
+            }
+            "qsort" => {
+                let [base, count, size, compare] = [0, 1, 2, 3].map(|i| args[i] as u64);
+                self.qsort(base, count, size, compare)?;
+                0
+            }
+            other => return Err(Error::UnsupportedExtern(other.to_string())),
+        };
+
+        Ok(int_bytes(n, ret))
+    }
+
+    // Formats the variadic parameters, supporting the flags, widths and conversions most often used
+    fn printf(&self, format: u64, params: &[Bytes]) -> Result<Vec<u8>, Error> {
+        let format = self.memory.read_cstr(format)?;
+        let mut params = params.iter();
+        let mut out = vec![];
+
+        let mut chars = format.into_iter().peekable();
+        while let Some(c) = chars.next() {
+            if c != b'%' {
+                out.push(c);
+                continue;
+            }
+
+            let mut flags = vec![];
+            while let Some(&flag @ (b'-' | b'0' | b'+' | b' ')) = chars.peek() {
+                flags.push(flag);
+                chars.next();
+            }
+
+            let width = number(&mut chars).unwrap_or(0);
+            let precision = match chars.peek() {
+                Some(b'.') => {
+                    chars.next();
+                    Some(number(&mut chars).unwrap_or(0))
+                }
+                _ => None,
+            };
+
+            // Variadic integers are promoted, so the length decides how much of them is read
+            let mut size = 4;
+            while let Some(&m @ (b'h' | b'l' | b'z' | b'j' | b't')) = chars.peek() {
+                size = match m {
+                    b'h' => size / 2,
+                    _ => 8,
+                };
+                chars.next();
+            }
+
+            let conversion = chars.next().unwrap_or(b'%');
+            if conversion == b'%' {
+                out.push(b'%');
+                continue;
+            }
+
+            let Some(param) = params.next() else {
+                return Err(Error::UnsupportedExtern(String::from(
+                    "printf with missing parameters",
+                )));
+            };
+            let int = |signed| decode(&param[..size.min(param.len())], signed);
+
+            let text = match conversion {
+                b'd' | b'i' if flags.contains(&b'+') => format!("{:+}", int(true)),
+                b'd' | b'i' => int(true).to_string(),
+                b'u' => int(false).to_string(),
+                b'x' => format!("{:x}", int(false)),
+                b'X' => format!("{:X}", int(false)),
+                b'p' => format!("{:#x}", decode(param, false)),
+                b'f' => format!("{:.*}", precision.unwrap_or(6), decode_float(param)),
+                b'c' => (param[0] as char).to_string(),
+                b's' => {
+                    let mut str = self.memory.read_cstr(decode(param, false) as u64)?;
+                    str.truncate(precision.unwrap_or(str.len()));
+                    String::from_utf8_lossy(&str).into_owned()
+                }
+                c => {
+                    let conversion = format!("printf conversion %{}", c as char);
+                    return Err(Error::UnsupportedExtern(conversion));
+                }
+            };
+
+            let padding = width.saturating_sub(text.len());
+            if flags.contains(&b'-') {
+                out.extend(text.bytes());
+                out.resize(out.len() + padding, b' ');
+            } else if flags.contains(&b'0') && conversion != b's' {
+                let (sign, digits) = match text.strip_prefix(['-', '+']) {
+                    Some(digits) => text.split_at(text.len() - digits.len()),
+                    None => ("", text.as_str()),
+                };
+                out.extend(sign.bytes());
+                out.resize(out.len() + padding, b'0');
+                out.extend(digits.bytes());
+            } else {
+                out.resize(out.len() + padding, b' ');
+                out.extend(text.bytes());
+            }
+        }
+
+        Ok(out)
+    }
+
+    // Insertion sort, calling back into the program to compare elements
+    fn qsort(&mut self, base: u64, count: u64, size: u64, compare: u64) -> Result<(), Error> {
+        for i in 1..count {
+            for j in (1..=i).rev() {
+                let [a, b] = [j - 1, j].map(|k| base + k * size);
+                let ordering = self.call_pointer(compare, vec![self.ptr(a), self.ptr(b)])?;
+                if decode(&ordering, true) <= 0 {
+                    break;
+                }
+
+                let left = self.memory.read(a, size)?.to_vec();
+                let right = self.memory.read(b, size)?.to_vec();
+                self.memory.write(a, &right)?;
+                self.memory.write(b, &left)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// libm functions from `double` to `double`
+fn libm(symbol: &str) -> Option<fn(f64) -> f64> {
+    Some(match symbol {
+        "sqrt" => f64::sqrt,
+        "cbrt" => f64::cbrt,
+        "exp" => f64::exp,
+        "log" => f64::ln,
+        "sin" => f64::sin,
+        "cos" => f64::cos,
+        "tan" => f64::tan,
+        "floor" => f64::floor,
+        "ceil" => f64::ceil,
+        _ => return None,
+    })
+}
+
+fn number(chars: &mut Peekable<impl Iterator<Item = u8>>) -> Option<usize> {
+    let mut n = None;
+    while let Some(&d @ b'0'..=b'9') = chars.peek() {
+        n = Some(n.unwrap_or(0) * 10 + (d - b'0') as usize);
+        chars.next();
+    }
+    n
+}
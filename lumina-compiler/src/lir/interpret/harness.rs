@@ -0,0 +1,294 @@
+use super::{decode, int_bytes, Bytes, Error, Interpreter, INTERPRETER_STACK_SIZE};
+use crate::lir::{ty_fmt, MonoFunc, MonoType, Output, Test};
+use crate::mir::ExpectedOutput;
+use lumina_typesystem::IntSize;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Amount of generated inputs each property is tested against
+pub const PROPERTY_CASES: u32 = 100;
+
+/// Upper bound of failing inputs tried while shrinking a counter-example
+const SHRINK_LIMIT: u32 = 1000;
+
+pub enum Outcome {
+    Passed,
+    /// The golden file of the test was overwritten with its output
+    Blessed,
+    Failed(Failure),
+    /// The test takes a parameter of a type which can't be generated
+    Unsupported(String),
+}
+
+pub struct Failure {
+    pub reason: Reason,
+    /// Symbols of the functions being executed when the error occurred, outermost first
+    pub trace: Vec<String>,
+    pub stdout: Vec<u8>,
+    /// The smallest failing input found for properties
+    pub input: Vec<Input>,
+    /// The input originally generated, before shrinking
+    pub original: Vec<Input>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Int(i128),
+    /// Passed to the `Generate` implementation of the parameter's type
+    Generated {
+        seed: u64,
+        size: u64,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Param {
+    Int(IntSize),
+    Generated(MonoFunc),
+}
+
+pub enum Reason {
+    ReturnedFalse,
+    Error(Error),
+    /// The test printed something other than what it was expected to
+    Output {
+        expected: Vec<u8>,
+    },
+    MissingGolden(PathBuf),
+    Bless(PathBuf, std::io::Error),
+}
+
+/// Run the `@[test]` and `@[property]` functions with the reference interpreter
+///
+/// Tests pass if they return `true` or `()` without trapping, and print their expected output if
+/// they have one. Every test runs in a fresh interpreter with the vals re-initialised.
+///
+/// With `bless`, golden files are overwritten instead of compared against.
+pub fn run_tests(
+    lir: &Output,
+    seed: u64,
+    bless: bool,
+    filter: &(dyn Fn(&Test) -> bool + Sync),
+    report: &mut (dyn FnMut(&Test, Outcome) + Send),
+) {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .name(String::from("test-harness"))
+            .stack_size(INTERPRETER_STACK_SIZE)
+            .spawn_scoped(scope, move || {
+                let mut rng = Rng(seed.max(1));
+                for test in lir.tests.iter().filter(|test| filter(test)) {
+                    let outcome = run_test(lir, test, bless, &mut rng);
+                    report(test, outcome);
+                }
+            })
+            .expect("failed to spawn test harness thread")
+            .join()
+            .unwrap_or_else(|err| std::panic::resume_unwind(err))
+    })
+}
+
+fn run_test(lir: &Output, test: &Test, bless: bool, rng: &mut Rng) -> Outcome {
+    let mut params = Vec::with_capacity(test.params.len());
+    for (ty, generator) in test.params.iter().zip(&test.generators) {
+        match (ty, generator) {
+            (MonoType::Int(intsize), _) => params.push(Param::Int(*intsize)),
+            (_, Some(generator)) => params.push(Param::Generated(*generator)),
+            (other, None) => return Outcome::Unsupported(ty_fmt(&lir.types, other).to_string()),
+        }
+    }
+
+    if params.is_empty() {
+        return match call(lir, test, &[], &[]) {
+            Ok(stdout) => check_output(test, stdout, bless),
+            Err(failure) => Outcome::Failed(failure),
+        };
+    }
+
+    // Generated values start out small and grow with every case
+    for size in 0..PROPERTY_CASES as u64 {
+        let input: Vec<Input> = params
+            .iter()
+            .map(|param| match param {
+                Param::Int(intsize) => Input::Int(rng.int(*intsize)),
+                Param::Generated(_) => Input::Generated { seed: rng.next(), size },
+            })
+            .collect();
+
+        if let Err(failure) = call(lir, test, &params, &input) {
+            return Outcome::Failed(shrink(lir, test, &params, failure));
+        }
+    }
+
+    Outcome::Passed
+}
+
+fn check_output(test: &Test, stdout: Vec<u8>, bless: bool) -> Outcome {
+    let fail = |reason, stdout| {
+        let failure = Failure {
+            reason,
+            trace: vec![],
+            stdout,
+            input: vec![],
+            original: vec![],
+        };
+        Outcome::Failed(failure)
+    };
+
+    let expected = match &test.expected_output {
+        None => return Outcome::Passed,
+        Some(ExpectedOutput::Inline(expected)) => expected.clone(),
+        Some(ExpectedOutput::File(path)) if bless => {
+            return match std::fs::write(path, &stdout) {
+                Ok(()) => Outcome::Blessed,
+                Err(err) => fail(Reason::Bless(path.clone(), err), stdout),
+            };
+        }
+        Some(ExpectedOutput::File(path)) => match std::fs::read(path) {
+            Ok(expected) => expected,
+            Err(_) => return fail(Reason::MissingGolden(path.clone()), stdout),
+        },
+    };
+
+    if stdout == expected {
+        Outcome::Passed
+    } else {
+        fail(Reason::Output { expected }, stdout)
+    }
+}
+
+/// Greedily move each parameter towards zero for as long as the test keeps failing
+///
+/// Generated parameters are shrunk by generating them again from the same seed with a smaller size.
+fn shrink(lir: &Output, test: &Test, params: &[Param], mut failure: Failure) -> Failure {
+    let mut attempts = 0;
+
+    'shrink: loop {
+        for i in 0..failure.input.len() {
+            for candidate in candidates(failure.input[i]) {
+                if attempts == SHRINK_LIMIT {
+                    break 'shrink;
+                }
+                attempts += 1;
+
+                let mut input = failure.input.clone();
+                input[i] = candidate;
+
+                if let Err(smaller) = call(lir, test, params, &input) {
+                    failure = Failure { original: failure.original, ..smaller };
+                    continue 'shrink;
+                }
+            }
+        }
+
+        break;
+    }
+
+    failure
+}
+
+fn candidates(input: Input) -> Vec<Input> {
+    match input {
+        Input::Int(n) => {
+            let mut candidates = vec![0, n / 2, n - n.signum()];
+            if n < 0 {
+                candidates.push(-n);
+            }
+            candidates.retain(|c| c.unsigned_abs() < n.unsigned_abs() || (*c > 0 && *c == -n));
+            candidates.dedup();
+            candidates.into_iter().map(Input::Int).collect()
+        }
+        Input::Generated { seed, size } => {
+            let mut sizes = vec![0, size / 2, size.saturating_sub(1)];
+            sizes.retain(|s| *s < size);
+            sizes.dedup();
+            sizes
+                .into_iter()
+                .map(|size| Input::Generated { seed, size })
+                .collect()
+        }
+    }
+}
+
+/// Call the test, returning what it printed if it passed
+fn call(lir: &Output, test: &Test, params: &[Param], input: &[Input]) -> Result<Vec<u8>, Failure> {
+    let mut stdout = Vec::new();
+    let mut interpreter = Interpreter::new(lir, &mut stdout);
+
+    let result = interpreter.init_vals().and_then(|_| {
+        let params = params
+            .iter()
+            .zip(input)
+            .map(|(param, input)| match (*param, *input) {
+                (Param::Int(intsize), Input::Int(n)) => {
+                    Ok(int_bytes(n, (intsize.bytes() as usize).max(1)))
+                }
+                (Param::Generated(generator), Input::Generated { seed, size }) => {
+                    let size_t = interpreter.layouts.ptr_size as usize;
+                    let params = vec![int_bytes(seed as i128, 8), int_bytes(size as i128, size_t)];
+                    interpreter.call(generator, params)
+                }
+                _ => unreachable!("input does not match the parameter"),
+            })
+            .collect::<Result<Vec<Bytes>, Error>>()?;
+
+        interpreter.call(test.func, params)
+    });
+
+    let (reason, trace) = match result {
+        Ok(bytes) if bytes.iter().any(|b| *b != 0) || bytes.is_empty() => return Ok(stdout),
+        Ok(_) => (Reason::ReturnedFalse, vec![]),
+        Err(err) => (
+            Reason::Error(err),
+            interpreter.trace().map(str::to_string).collect(),
+        ),
+    };
+
+    Err(Failure {
+        reason,
+        trace,
+        stdout,
+        input: input.to_vec(),
+        original: input.to_vec(),
+    })
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Input::Int(n) => n.fmt(f),
+            Input::Generated { seed, size } => {
+                write!(f, "<generated from seed {seed} at size {size}>")
+            }
+        }
+    }
+}
+
+/// Xorshift generator, we only need reproducible inputs rather than good randomness
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Biased towards small numbers and the edges of the integer's range since that's where
+    /// most bugs are found
+    fn int(&mut self, intsize: IntSize) -> i128 {
+        let bits = self.next();
+        let n = match self.next() % 8 {
+            0 => 0,
+            1 => intsize.max_value() as i128,
+            2 => intsize.min_value() as i128,
+            3 | 4 => (bits % 21) as i128 - 10,
+            _ => bits as i128,
+        };
+        decode(
+            &int_bytes(n, (intsize.bytes() as usize).max(1)),
+            intsize.signed,
+        )
+    }
+}
@@ -0,0 +1,145 @@
+use super::super::{MonoType, MonoTypeData, MonoTypeKey, Types};
+use crate::prelude::*;
+use ast::attr::Repr;
+
+/// Memory layout used by the interpreter
+///
+/// This is deliberately simpler than the layout used by the native backends. Fields are laid out
+/// in declaration order with natural alignment unless packed, and sum type payloads are always
+/// boxed so that recursive sums don't need special treatment.
+pub struct Layouts<'a> {
+    types: &'a Types,
+    records: Map<MonoTypeKey, Option<RecordLayout>>,
+    pub ptr_size: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordLayout {
+    pub size: u64,
+    pub align: u64,
+    pub offsets: Vec<u64>,
+}
+
+impl<'a> Layouts<'a> {
+    pub fn new(types: &'a Types) -> Self {
+        let mut this = Layouts {
+            types,
+            records: types.secondary_with(|_, _| None),
+            ptr_size: types.pointer_bits as u64 / 8,
+        };
+
+        for key in types.keys() {
+            this.layout_of_key(key);
+        }
+
+        this
+    }
+
+    pub fn size_of(&self, ty: &MonoType) -> u64 {
+        self.size_and_align_of(ty).0
+    }
+
+    pub fn align_of(&self, ty: &MonoType) -> u64 {
+        self.size_and_align_of(ty).1
+    }
+
+    pub fn record(&self, key: MonoTypeKey) -> &RecordLayout {
+        self.records[key]
+            .as_ref()
+            .expect("layout requested for placeholder type")
+    }
+
+    /// Offset of the boxed payload pointer inside of a sum
+    pub fn payload_offset(&self, key: MonoTypeKey) -> u64 {
+        self.record(key).offsets[1]
+    }
+
+    /// Element size of an array including the padding between elements
+    pub fn stride_of(&self, elem: &MonoType) -> u64 {
+        let (size, align) = self.size_and_align_of(elem);
+        align_to(size, align)
+    }
+
+    pub fn size_and_align_of(&self, ty: &MonoType) -> (u64, u64) {
+        match ty {
+            MonoType::Int(intsize) => {
+                let bytes = (intsize.bytes() as u64).max(1);
+                (bytes, bytes)
+            }
+            MonoType::Pointer(_) | MonoType::FnPointer(..) => (self.ptr_size, self.ptr_size),
+            MonoType::Float(size) => (size.bytes() as u64, size.bytes() as u64),
+            MonoType::Unreachable | MonoType::Const(_) => (0, 1),
+            MonoType::Array(len, inner) => {
+                let align = self.align_of(inner);
+                (self.stride_of(inner) * len, align)
+            }
+            MonoType::Monomorphised(key) => {
+                let layout = self.record(*key);
+                (layout.size, layout.align)
+            }
+        }
+    }
+
+    fn layout_of_key(&mut self, key: MonoTypeKey) -> (u64, u64) {
+        if let Some(layout) = &self.records[key] {
+            return (layout.size, layout.align);
+        }
+
+        let repr = match &self.types[key] {
+            MonoTypeData::Record { repr, .. } => *repr,
+            _ => Repr::Lumina,
+        };
+
+        let fields: Vec<MonoType> = match &self.types[key] {
+            MonoTypeData::Record { fields, .. } => fields.values().cloned().collect(),
+            MonoTypeData::Sum { tag, .. } => {
+                vec![MonoType::Int(*tag), MonoType::u8_pointer()]
+            }
+            MonoTypeData::DynTraitObject { vtable, .. } => {
+                vec![MonoType::u8_pointer(), vtable.clone()]
+            }
+            MonoTypeData::Placeholder => return (0, 1),
+        };
+
+        let mut offsets = Vec::with_capacity(fields.len());
+        let mut size = 0;
+        let mut align = 1;
+
+        for ty in fields.iter() {
+            let (fsize, falign) = self.compute(ty);
+
+            // Fields of packed records are placed directly after each other
+            let falign = if repr == Repr::Packed { 1 } else { falign };
+
+            size = align_to(size, falign);
+            offsets.push(size);
+            size += fsize;
+            align = align.max(falign);
+        }
+
+        if let Repr::Align(bytes) = repr {
+            align = align.max(bytes as u64);
+        }
+
+        let size = align_to(size, align);
+        self.records[key] = Some(RecordLayout { size, align, offsets });
+
+        (size, align)
+    }
+
+    // Like `size_and_align_of` but lays out records which haven't been visited yet
+    fn compute(&mut self, ty: &MonoType) -> (u64, u64) {
+        match ty {
+            MonoType::Monomorphised(key) => self.layout_of_key(*key),
+            MonoType::Array(len, elem) => {
+                let (size, align) = self.compute(elem);
+                (align_to(size, align) * len, align)
+            }
+            _ => self.size_and_align_of(ty),
+        }
+    }
+}
+
+pub fn align_to(n: u64, align: u64) -> u64 {
+    (n + align - 1) / align * align
+}
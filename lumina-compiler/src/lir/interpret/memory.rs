@@ -0,0 +1,103 @@
+use super::layout::align_to;
+use super::Error;
+
+/// Start of the heap region, which also holds read-only data and static vals
+pub const HEAP_BASE: u64 = 0x1_0000;
+
+/// Start of the stack region used by `alloca`
+pub const STACK_BASE: u64 = 1 << 40;
+
+/// Function pointers are encoded as addresses in regions that can't be dereferenced
+pub const FUNC_BASE: u64 = 1 << 48;
+pub const EXTERN_FUNC_BASE: u64 = 1 << 52;
+
+/// Flat little-endian memory
+///
+/// Heap memory is never reused. `free` is a no-op since the interpreter only runs short-lived
+/// programs. Stack memory is released when the function which allocated it returns.
+pub struct Memory {
+    heap: Vec<u8>,
+    stack: Vec<u8>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory { heap: Vec::new(), stack: Vec::new() }
+    }
+
+    pub fn alloc(&mut self, size: u64, align: u64) -> u64 {
+        let offset = align_to(self.heap.len() as u64, align.max(16));
+        self.heap.resize((offset + size.max(1)) as usize, 0);
+        HEAP_BASE + offset
+    }
+
+    pub fn alloc_bytes(&mut self, bytes: &[u8]) -> u64 {
+        let addr = self.alloc(bytes.len() as u64, 1);
+        self.write(addr, bytes).unwrap();
+        addr
+    }
+
+    pub fn alloca(&mut self, size: u64, align: u64) -> u64 {
+        let offset = align_to(self.stack.len() as u64, align.max(8));
+        self.stack.resize((offset + size) as usize, 0);
+        STACK_BASE + offset
+    }
+
+    pub fn stack_pointer(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn restore_stack_pointer(&mut self, sp: usize) {
+        self.stack.truncate(sp);
+    }
+
+    fn region(&self, addr: u64, len: u64) -> Result<(&[u8], usize), Error> {
+        let (region, base) = if addr >= STACK_BASE && addr < FUNC_BASE {
+            (&self.stack, STACK_BASE)
+        } else if addr >= HEAP_BASE && addr < STACK_BASE {
+            (&self.heap, HEAP_BASE)
+        } else {
+            return Err(Error::Fault(addr));
+        };
+
+        let start = (addr - base) as usize;
+        if start + len as usize > region.len() {
+            return Err(Error::Fault(addr));
+        }
+
+        Ok((region, start))
+    }
+
+    pub fn read(&self, addr: u64, len: u64) -> Result<&[u8], Error> {
+        let (region, start) = self.region(addr, len)?;
+        Ok(&region[start..start + len as usize])
+    }
+
+    pub fn write(&mut self, addr: u64, bytes: &[u8]) -> Result<(), Error> {
+        let (_, start) = self.region(addr, bytes.len() as u64)?;
+        let region = if addr >= STACK_BASE {
+            &mut self.stack
+        } else {
+            &mut self.heap
+        };
+        region[start..start + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    pub fn copy(&mut self, dst: u64, src: u64, count: u64) -> Result<(), Error> {
+        let bytes = self.read(src, count)?.to_vec();
+        self.write(dst, &bytes)
+    }
+
+    /// Read a null-terminated C string
+    pub fn read_cstr(&self, mut addr: u64) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![];
+        loop {
+            match self.read(addr, 1)?[0] {
+                0 => break Ok(buf),
+                c => buf.push(c),
+            }
+            addr += 1;
+        }
+    }
+}
@@ -0,0 +1,826 @@
+//! Reference interpreter for LIR
+//!
+//! Executes `lir::Output` entry by entry instead of lowering it through a native backend.
+//!
+//! Used by `lumina run --interpret` and to diff the behavior of the native backend against in
+//! tests. Since it can call any monomorphised function directly it also serves as the engine for
+//! compile-time evaluation.
+
+use super::{
+    AtomicRmwOp, BinOp, Block, BlockJump, Entry, MonoFunc, MonoType, Output, Value, SSA, V,
+};
+use crate::debuginfo::Item;
+use crate::prelude::*;
+use lumina_typesystem::{FloatSize, IntSize};
+use lumina_util::Symbol;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Write;
+
+mod ctfe;
+mod eval;
+mod externs;
+mod harness;
+mod layout;
+mod memory;
+mod reload;
+pub use ctfe::{evaluate_consts, ConstError, CONST_EVAL_STEP_LIMIT};
+pub use eval::evaluate;
+pub use harness::{run_tests, Failure, Input, Outcome, Reason, PROPERTY_CASES};
+pub use layout::Layouts;
+use memory::{Memory, EXTERN_FUNC_BASE, FUNC_BASE};
+
+/// Function calls are interpreted recursively, so we need more stack than the default
+const INTERPRETER_STACK_SIZE: usize = 1024 * 1024 * 1024;
+
+type Bytes = Vec<u8>;
+
+#[derive(Debug)]
+pub enum Error {
+    Exit(i32),
+    Signal(i64),
+    Trap(String),
+    Fault(u64),
+    DivisionByZero,
+    NotAFunction(u64),
+    UnsupportedExtern(String),
+    ExternAtCompileTime(Symbol),
+    RuntimeVal(String),
+    StepLimit,
+    UnrepresentableConst,
+    StaticAssert(String),
+}
+
+/// Interpret the program as if it was compiled natively and then executed with `args`
+///
+/// Returns the exit code of the program.
+pub fn run(lir: &Output, args: &[String], stdout: &mut (dyn Write + Send)) -> Result<i32, Error> {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .name(String::from("lir-interpreter"))
+            .stack_size(INTERPRETER_STACK_SIZE)
+            .spawn_scoped(scope, move || {
+                Interpreter::new(lir, stdout).run_program(args)
+            })
+            .expect("failed to spawn interpreter thread")
+            .join()
+            .unwrap_or_else(|err| std::panic::resume_unwind(err))
+    })
+}
+
+pub struct Interpreter<'a> {
+    lir: &'a Output,
+    layouts: Layouts<'a>,
+    memory: Memory,
+
+    readonly: MMap<key::ReadOnly, u64>,
+    vals: MMap<key::Val, u64>,
+    errno: u64,
+
+    /// Function pointers are indices into these tables rather than keys, so that pointers stored
+    /// in memory remain valid after the program is reloaded
+    functions: Vec<Option<MonoFunc>>,
+    function_slots: HashMap<MonoFunc, u64>,
+    externs: Vec<Option<M<key::Func>>>,
+
+    stdout: &'a mut dyn Write,
+
+    /// Functions currently being executed, outermost first
+    trace: Vec<MonoFunc>,
+    constant: Option<ConstEval>,
+}
+
+/// Restrictions applied when evaluating at compile time
+struct ConstEval {
+    fuel: u64,
+    known_vals: HashSet<M<key::Val>>,
+}
+
+struct Frame<'a> {
+    ssa: &'a SSA,
+    values: Vec<Bytes>,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(lir: &'a Output, stdout: &'a mut dyn Write) -> Self {
+        let layouts = Layouts::new(&lir.types);
+        let mut memory = Memory::new();
+
+        let readonly = lir
+            .read_only_table
+            .map(|_, (bytes, _)| memory.alloc_bytes(&bytes.0));
+
+        let vals = lir.val_types.map(|_, ty| {
+            let (size, align) = layouts.size_and_align_of(ty);
+            memory.alloc(size, align)
+        });
+
+        let errno = memory.alloc(4, 4);
+        let functions = lir.functions.keys().map(Some).collect();
+        let function_slots = lir
+            .functions
+            .keys()
+            .map(|mfunc| (mfunc, mfunc.0 as u64))
+            .collect();
+        let externs = lir.extern_funcs.keys().copied().map(Some).collect();
+
+        Interpreter {
+            lir,
+            layouts,
+            memory,
+            readonly,
+            vals,
+            errno,
+            functions,
+            function_slots,
+            externs,
+            stdout,
+            trace: vec![],
+            constant: None,
+        }
+    }
+
+    /// Restrict the interpreter to what's allowed during compile-time evaluation
+    ///
+    /// Extern calls are rejected, only the already evaluated `constants` may be read from other
+    /// vals, and execution is aborted after `CONST_EVAL_STEP_LIMIT` entries.
+    pub fn for_const_eval(mut self, constants: &HashMap<M<key::Val>, Bytes>) -> Self {
+        for (val, bytes) in constants {
+            self.memory.write(self.vals[*val], bytes).unwrap();
+        }
+
+        self.constant = Some(ConstEval {
+            fuel: CONST_EVAL_STEP_LIMIT,
+            known_vals: constants.keys().copied().collect(),
+        });
+
+        self
+    }
+
+    /// Symbols of the functions that were being executed when an error occurred
+    pub fn trace(&self) -> impl Iterator<Item = &str> + '_ {
+        self.trace
+            .iter()
+            .map(|mfunc| self.lir.functions[*mfunc].symbol.as_str())
+    }
+
+    /// Runs the val initialisers followed by the system initialiser and the main function
+    pub fn run_program(&mut self, args: &[String]) -> Result<i32, Error> {
+        let mut start = || {
+            self.init_vals()?;
+
+            let mut argv = vec![];
+            for arg in args {
+                let mut bytes = arg.as_bytes().to_vec();
+                bytes.push(0);
+                let addr = self.memory.alloc_bytes(&bytes);
+                argv.extend(self.ptr(addr));
+            }
+            let argv = self.memory.alloc_bytes(&argv);
+
+            let params = vec![int_bytes(args.len() as i128, 4), self.ptr(argv)];
+            self.call(self.lir.sys_init, params)?;
+            self.call(self.lir.main, vec![])?;
+
+            Ok(0)
+        };
+
+        match start() {
+            Err(Error::Exit(code)) => Ok(code),
+            result => result,
+        }
+    }
+
+    pub fn init_vals(&mut self) -> Result<(), Error> {
+        for val in self.lir.val_initializers.keys() {
+            self.init_val(*val)?;
+        }
+
+        Ok(())
+    }
+
+    fn init_val(&mut self, val: M<key::Val>) -> Result<(), Error> {
+        let value = match self.lir.val_constants.get(&val) {
+            Some(bytes) => bytes.clone(),
+            None => self.call(self.lir.val_initializers[&val], vec![])?,
+        };
+        self.memory.write(self.vals[val], &value)?;
+        Ok(())
+    }
+
+    /// Call a function with parameters encoded in the interpreter's memory layout
+    pub fn call(&mut self, mfunc: MonoFunc, params: Vec<Bytes>) -> Result<Bytes, Error> {
+        let sp = self.memory.stack_pointer();
+        let result = self.execute(mfunc, params);
+        self.memory.restore_stack_pointer(sp);
+        result
+    }
+
+    fn execute(&mut self, mut mfunc: MonoFunc, mut params: Vec<Bytes>) -> Result<Bytes, Error> {
+        let lir = self.lir;
+
+        'func: loop {
+            let ssa = &lir.functions[mfunc].ssa;
+            trace!("interpreting {}", lir.functions[mfunc].symbol);
+            self.trace.push(mfunc);
+
+            let mut frame = Frame { ssa, values: vec![vec![]; ssa.iterv().count()] };
+            let mut v = frame.enter(Block::entry(), params);
+
+            loop {
+                if let Some(constant) = &mut self.constant {
+                    if constant.fuel == 0 {
+                        return Err(Error::StepLimit);
+                    }
+                    constant.fuel -= 1;
+                }
+
+                let entry = ssa.entry_of(v);
+
+                match entry {
+                    Entry::BlockParam(..) => {}
+                    Entry::JmpFunc(target, values) => {
+                        params = self.values(&frame, values);
+                        mfunc = *target;
+                        self.trace.pop();
+                        continue 'func;
+                    }
+                    Entry::JmpBlock(jump) => {
+                        v = self.jump(&mut frame, jump);
+                        continue;
+                    }
+                    Entry::Select { value, on_true, on_false } => {
+                        let cond = self.value(&frame, *value);
+                        let jump = if cond.iter().any(|b| *b != 0) {
+                            on_true
+                        } else {
+                            on_false
+                        };
+                        v = self.jump(&mut frame, jump);
+                        continue;
+                    }
+                    Entry::JmpTable(of, blocks) => {
+                        let i = decode(&self.value(&frame, *of), false);
+                        match blocks.get(i as usize) {
+                            Some(block) => {
+                                v = frame.enter(*block, vec![]);
+                                continue;
+                            }
+                            None => return Err(Error::Trap(String::from("unreachable"))),
+                        }
+                    }
+                    Entry::Return(value) => {
+                        self.trace.pop();
+                        return Ok(self.value(&frame, *value));
+                    }
+                    Entry::Trap(code) => return Err(Error::Trap(code.to_string())),
+                    _ => {
+                        let ty = ssa.type_of(v);
+                        frame.values[v.0 as usize] = self.entry(&frame, entry, ty)?;
+                    }
+                }
+
+                v = V(v.0 + 1);
+            }
+        }
+    }
+
+    fn jump(&self, frame: &mut Frame, jump: &BlockJump) -> V {
+        let params = self.values(frame, &jump.params);
+        frame.enter(jump.id, params)
+    }
+
+    fn entry(&mut self, frame: &Frame, entry: &Entry, ty: &MonoType) -> Result<Bytes, Error> {
+        match entry {
+            Entry::CallStatic(mfunc, params) => {
+                let params = self.values(frame, params);
+                self.call(*mfunc, params)
+            }
+            Entry::CallExtern(key, params) => {
+                let params = self.values(frame, params);
+                self.call_extern(*key, params)
+            }
+            Entry::CallValue(ptr, params) => {
+                let addr = self.addr(frame, *ptr);
+                let params = self.values(frame, params);
+                self.call_pointer(addr, params)
+            }
+
+            Entry::Construct(values) => {
+                let values = self.values(frame, values);
+                Ok(self.construct(ty, values))
+            }
+            Entry::Replicate(value, times) => {
+                let elem = self.value(frame, *value);
+                let values = (0..*times).map(|_| elem.clone()).collect();
+                Ok(self.construct(ty, values))
+            }
+            Entry::Variant(var, values) => {
+                let values = self.values(frame, values);
+                self.variant(ty.as_key(), *var, values)
+            }
+            Entry::RefStaticVal(val) => match &self.constant {
+                Some(constant) if !constant.known_vals.contains(val) => {
+                    Err(Error::RuntimeVal(self.val_name(*val)))
+                }
+                _ => Ok(self.ptr(self.vals[*val])),
+            },
+            Entry::RefExternData(key) => {
+                let symbol = &self.lir.extern_funcs[key].symbol;
+                Err(Error::UnsupportedExtern(symbol.to_string()))
+            }
+
+            Entry::Field { of, key, field } => {
+                let of = self.value(frame, *of);
+                let offset = self.layouts.record(*key).offsets[field.0 as usize];
+                Ok(self.slice(&of, offset, ty))
+            }
+            Entry::CastFromSum { of } => {
+                let sum = self.type_of_value(frame, *of).as_key();
+                let of = self.value(frame, *of);
+                let offset = self.layouts.payload_offset(sum);
+                let size = self.layouts.size_of(ty);
+                if size == 0 {
+                    return Ok(vec![]);
+                }
+                let payload = decode(&self.slice(&of, offset, &MonoType::u8_pointer()), false);
+                Ok(self.memory.read(payload as u64, size)?.to_vec())
+            }
+            Entry::TagFromSum { of } => {
+                let of = self.value(frame, *of);
+                Ok(self.slice(&of, 0, ty))
+            }
+            Entry::Indice { of, indice } => {
+                let (_, inner) = self.type_of_value(frame, *of).as_array();
+                let [of, indice] = [*of, *indice].map(|v| self.value(frame, v));
+                let offset = decode(&indice, false) as u64 * self.layouts.stride_of(&inner);
+                Ok(self.slice(&of, offset, ty))
+            }
+
+            Entry::BinOp(op, [left, right]) => {
+                let [left, right] = [*left, *right].map(|v| self.value(frame, v));
+                self.binop(*op, [left, right], ty)
+            }
+            Entry::IntCmpInclusive([left, right], ordering, intsize) => {
+                let [left, right] = [*left, *right].map(|v| self.value(frame, v));
+                let [left, right] = [left, right].map(|v| decode(&v, intsize.signed));
+                Ok(vec![(left.cmp(&right) == *ordering) as u8])
+            }
+            Entry::IntAbs(v) => {
+                let n = self.value(frame, *v);
+                Ok(int_bytes(decode(&n, true).abs(), n.len()))
+            }
+            Entry::IntAddCarry(values) => {
+                let values = values.map(|v| self.value(frame, v));
+                Ok(self.carry(BinOp::Add, values, ty))
+            }
+            Entry::IntSubBorrow(values) => {
+                let values = values.map(|v| self.value(frame, v));
+                Ok(self.carry(BinOp::Sub, values, ty))
+            }
+            Entry::IntMin([left, right], intsize) => {
+                let [left, right] = [*left, *right].map(|v| self.value(frame, v));
+                Ok(std::cmp::min_by_key(left, right, |v| {
+                    decode(v, intsize.signed)
+                }))
+            }
+            Entry::IntMax([left, right], intsize) => {
+                let [left, right] = [*left, *right].map(|v| self.value(frame, v));
+                Ok(std::cmp::max_by_key(left, right, |v| {
+                    decode(v, intsize.signed)
+                }))
+            }
+            Entry::SelectValue([cond, on_true, on_false]) => {
+                let cond = self.value(frame, *cond);
+                let picked = if cond.iter().any(|b| *b != 0) {
+                    on_true
+                } else {
+                    on_false
+                };
+                Ok(self.value(frame, *picked))
+            }
+
+            Entry::Transmute(v) => {
+                let mut bytes = self.value(frame, *v);
+                bytes.resize(self.layouts.size_of(ty) as usize, 0);
+                Ok(bytes)
+            }
+            Entry::SizeOf(of) => {
+                let size = self.layouts.size_of(of);
+                Ok(int_bytes(size as i128, self.layouts.size_of(ty) as usize))
+            }
+            Entry::AlignOf(of) => {
+                let align = self.layouts.align_of(of);
+                Ok(int_bytes(align as i128, self.layouts.size_of(ty) as usize))
+            }
+            Entry::Reduce(v) | Entry::ExtendUnsigned(v) => {
+                let mut bytes = self.value(frame, *v);
+                bytes.resize(self.layouts.size_of(ty) as usize, 0);
+                Ok(bytes)
+            }
+            Entry::ExtendSigned(v) => {
+                let n = decode(&self.value(frame, *v), true);
+                Ok(int_bytes(n, self.layouts.size_of(ty) as usize))
+            }
+
+            Entry::IntToFloat(v, intsize) => {
+                let n = decode(&self.value(frame, *v), intsize.signed);
+                Ok(float_bytes(n as f64, ty.as_float()))
+            }
+            Entry::FloatToInt(v, intsize) => {
+                let f = decode_float(&self.value(frame, *v));
+                let n = if intsize.signed {
+                    f as i64 as i128
+                } else {
+                    f as u64 as i128
+                };
+                Ok(int_bytes(n, self.layouts.size_of(ty) as usize))
+            }
+            Entry::FloatCast(v) => {
+                let f = decode_float(&self.value(frame, *v));
+                Ok(float_bytes(f, ty.as_float()))
+            }
+            Entry::FloatAdd(values)
+            | Entry::FloatSub(values)
+            | Entry::FloatMul(values)
+            | Entry::FloatDiv(values) => {
+                let [l, r] = values.map(|v| decode_float(&self.value(frame, v)));
+                let f = match entry {
+                    Entry::FloatAdd(_) => l + r,
+                    Entry::FloatSub(_) => l - r,
+                    Entry::FloatMul(_) => l * r,
+                    _ => l / r,
+                };
+                Ok(float_bytes(f, ty.as_float()))
+            }
+            Entry::FloatNeg(v) => {
+                let f = decode_float(&self.value(frame, *v));
+                Ok(float_bytes(-f, ty.as_float()))
+            }
+            Entry::FloatCmp(values, ordering) => {
+                let [l, r] = values.map(|v| decode_float(&self.value(frame, v)));
+                Ok(vec![(l.partial_cmp(&r) == Some(*ordering)) as u8])
+            }
+
+            Entry::BitNot(v) => Ok(self.value(frame, *v).iter().map(|b| !b).collect()),
+
+            Entry::Alloc => {
+                let MonoType::Pointer(inner) = ty else {
+                    panic!("Alloc to non-pointer");
+                };
+                let (size, align) = self.layouts.size_and_align_of(inner);
+                Ok(self.ptr(self.memory.alloc(size, align)))
+            }
+            Entry::Alloca => {
+                let MonoType::Pointer(inner) = ty else {
+                    panic!("Alloca to non-pointer");
+                };
+                let (size, align) = self.layouts.size_and_align_of(inner);
+                Ok(self.ptr(self.memory.alloca(size, align)))
+            }
+            Entry::Dealloc { .. } => Ok(vec![]),
+            Entry::WritePtr { ptr, value } => {
+                let ptr = self.addr(frame, *ptr);
+                let value = self.value(frame, *value);
+                self.memory.write(ptr, &value)?;
+                Ok(vec![])
+            }
+            Entry::MemCpy { dst, src, count } => {
+                let [dst, src, count] = [*dst, *src, *count].map(|v| self.addr(frame, v));
+                self.memory.copy(dst, src, count)?;
+                Ok(vec![])
+            }
+            Entry::MemSet { dst, byte, count } => {
+                let [dst, count] = [*dst, *count].map(|v| self.addr(frame, v));
+                let byte = self.value(frame, *byte)[0];
+                self.memory.write(dst, &vec![byte; count as usize])?;
+                Ok(vec![])
+            }
+            Entry::Deref(ptr) => {
+                let ptr = self.addr(frame, *ptr);
+                let size = self.layouts.size_of(ty);
+                Ok(self.memory.read(ptr, size)?.to_vec())
+            }
+            Entry::Breakpoint => Ok(vec![]),
+            // The interpreter is single-threaded so atomics are plain reads and writes
+            Entry::AtomicLoad(ptr, _) => {
+                let ptr = self.addr(frame, *ptr);
+                let size = self.layouts.size_of(ty);
+                Ok(self.memory.read(ptr, size)?.to_vec())
+            }
+            Entry::AtomicStore { ptr, value, .. } => {
+                let ptr = self.addr(frame, *ptr);
+                let value = self.value(frame, *value);
+                self.memory.write(ptr, &value)?;
+                Ok(vec![])
+            }
+            Entry::AtomicCas([ptr, expected, replacement], _) => {
+                let ptr = self.addr(frame, *ptr);
+                let [expected, replacement] =
+                    [*expected, *replacement].map(|v| self.value(frame, v));
+                let old = self.memory.read(ptr, expected.len())?.to_vec();
+                if old == expected {
+                    self.memory.write(ptr, &replacement)?;
+                }
+                Ok(old)
+            }
+            Entry::AtomicRmw(op, [ptr, value], _) => {
+                let ptr = self.addr(frame, *ptr);
+                let value = self.value(frame, *value);
+                let old = self.memory.read(ptr, value.len())?.to_vec();
+                let bytewise = |f: fn(u8, u8) -> u8| old.iter().zip(&value).map(|(l, r)| f(*l, *r));
+                let new: Bytes = match op {
+                    AtomicRmwOp::Add => {
+                        int_bytes(decode(&old, false) + decode(&value, false), old.len())
+                    }
+                    AtomicRmwOp::Sub => {
+                        int_bytes(decode(&old, false) - decode(&value, false), old.len())
+                    }
+                    AtomicRmwOp::And => bytewise(|l, r| l & r).collect(),
+                    AtomicRmwOp::Or => bytewise(|l, r| l | r).collect(),
+                    AtomicRmwOp::Xor => bytewise(|l, r| l ^ r).collect(),
+                    AtomicRmwOp::Xchg => value.clone(),
+                };
+                self.memory.write(ptr, &new)?;
+                Ok(old)
+            }
+
+            Entry::BlockParam(..)
+            | Entry::JmpFunc(..)
+            | Entry::JmpBlock(..)
+            | Entry::Return(..)
+            | Entry::Select { .. }
+            | Entry::JmpTable(..)
+            | Entry::Trap(..) => unreachable!("control flow is handled by the caller"),
+        }
+    }
+
+    pub(super) fn val_name(&self, val: M<key::Val>) -> String {
+        val_name(self.lir, val)
+    }
+
+    fn call_pointer(&mut self, addr: u64, params: Vec<Bytes>) -> Result<Bytes, Error> {
+        if addr >= EXTERN_FUNC_BASE {
+            match self.externs.get((addr - EXTERN_FUNC_BASE) as usize) {
+                Some(Some(key)) => self.call_extern(*key, params),
+                _ => Err(Error::NotAFunction(addr)),
+            }
+        } else if addr >= FUNC_BASE {
+            match self.functions.get((addr - FUNC_BASE) as usize) {
+                Some(Some(mfunc)) => self.call(*mfunc, params),
+                _ => Err(Error::NotAFunction(addr)),
+            }
+        } else {
+            Err(Error::NotAFunction(addr))
+        }
+    }
+
+    fn binop(&self, op: BinOp, [left, right]: [Bytes; 2], ty: &MonoType) -> Result<Bytes, Error> {
+        if op == BinOp::And {
+            return Ok(left.iter().zip(&right).map(|(l, r)| l & r).collect());
+        }
+
+        // Record return types are used for checked arithmetic returning (n, overflowed)
+        let (intsize, checked) = match ty {
+            MonoType::Int(intsize) => (*intsize, None),
+            MonoType::Pointer(_) => (IntSize::new(false, self.layouts.ptr_size as u8 * 8), None),
+            MonoType::Monomorphised(mk) => match &self.lir.types[*mk].as_record()[key::Field(0)] {
+                MonoType::Int(intsize) => (*intsize, Some(*mk)),
+                other => panic!("invalid return signature for num binop: {other:?}"),
+            },
+            _ => panic!("invalid return signature for num binop: {ty:?}"),
+        };
+
+        let [l, r] = [&left, &right].map(|v| decode(v, intsize.signed));
+
+        let n = match op {
+            BinOp::Add => l + r,
+            BinOp::Sub => l - r,
+            BinOp::Mul => l * r,
+            BinOp::Div if r == 0 => return Err(Error::DivisionByZero),
+            BinOp::Div => l / r,
+            BinOp::Rem if r == 0 => return Err(Error::DivisionByZero),
+            BinOp::Rem => l % r,
+            BinOp::And => unreachable!(),
+        };
+
+        let bytes = int_bytes(n, left.len());
+
+        match checked {
+            None => Ok(bytes),
+            Some(mk) => {
+                let overflowed = decode(&bytes, intsize.signed) != n;
+                Ok(self.construct(&mk.into(), vec![bytes, vec![overflowed as u8]]))
+            }
+        }
+    }
+
+    // Same as the native backends, the carry is set if either of the two steps overflow
+    fn carry(&self, op: BinOp, [left, right, carry]: [Bytes; 3], ty: &MonoType) -> Bytes {
+        let MonoType::Int(intsize) = self.lir.types[ty.as_key()].as_record()[key::Field(0)] else {
+            panic!("invalid return signature for carry arithmetic: {ty:?}");
+        };
+
+        let step = |a: i128, b: i128| {
+            let n = match op {
+                BinOp::Add => a + b,
+                BinOp::Sub => a - b,
+                _ => unreachable!(),
+            };
+            let wrapped = decode(&int_bytes(n, left.len()), intsize.signed);
+            (wrapped, wrapped != n)
+        };
+
+        let [l, r] = [&left, &right].map(|v| decode(v, intsize.signed));
+        let (n, c0) = step(l, r);
+        let (n, c1) = step(n, decode(&carry, false));
+
+        self.construct(ty, vec![int_bytes(n, left.len()), vec![(c0 || c1) as u8]])
+    }
+
+    fn construct(&self, ty: &MonoType, values: Vec<Bytes>) -> Bytes {
+        let mut buf = vec![0; self.layouts.size_of(ty) as usize];
+
+        let offsets: Vec<u64> = match ty {
+            MonoType::Monomorphised(mk) => self.layouts.record(*mk).offsets.clone(),
+            MonoType::Array(len, inner) => {
+                assert_eq!(values.len() as u64, *len);
+                let stride = self.layouts.stride_of(inner);
+                (0..*len).map(|i| i * stride).collect()
+            }
+            _ => panic!("cannot construct: {ty:?}"),
+        };
+
+        for (offset, value) in offsets.into_iter().zip(values) {
+            let offset = offset as usize;
+            buf[offset..offset + value.len()].copy_from_slice(&value);
+        }
+
+        buf
+    }
+
+    fn variant(
+        &mut self,
+        sum: super::MonoTypeKey,
+        var: key::Variant,
+        values: Vec<Bytes>,
+    ) -> Result<Bytes, Error> {
+        let (tag, _, variants) = self.lir.types[sum].as_sum();
+        let payload_ty = MonoType::Monomorphised(variants[var]);
+
+        let payload = self.construct(&payload_ty, values);
+        let ptr = if payload.is_empty() {
+            0
+        } else {
+            let (size, align) = self.layouts.size_and_align_of(&payload_ty);
+            let ptr = self.memory.alloc(size, align);
+            self.memory.write(ptr, &payload)?;
+            ptr
+        };
+
+        let tag = int_bytes(var.0 as i128, (tag.bytes() as usize).max(1));
+        let ptr = self.ptr(ptr);
+        let offsets = &self.layouts.record(sum).offsets;
+
+        let mut buf = vec![0; self.layouts.record(sum).size as usize];
+        buf[..tag.len()].copy_from_slice(&tag);
+        buf[offsets[1] as usize..offsets[1] as usize + ptr.len()].copy_from_slice(&ptr);
+
+        Ok(buf)
+    }
+
+    fn slice(&self, bytes: &[u8], offset: u64, ty: &MonoType) -> Bytes {
+        let offset = offset as usize;
+        let size = self.layouts.size_of(ty) as usize;
+        bytes[offset..offset + size].to_vec()
+    }
+
+    fn ptr(&self, addr: u64) -> Bytes {
+        addr.to_le_bytes()[..self.layouts.ptr_size as usize].to_vec()
+    }
+
+    fn addr(&self, frame: &Frame, value: Value) -> u64 {
+        decode(&self.value(frame, value), false) as u64
+    }
+
+    fn values(&self, frame: &Frame, values: &[Value]) -> Vec<Bytes> {
+        values.iter().map(|v| self.value(frame, *v)).collect()
+    }
+
+    fn value(&self, frame: &Frame, value: Value) -> Bytes {
+        match value {
+            Value::V(v) => frame.values[v.0 as usize].clone(),
+            Value::ReadOnly(ro) => self.ptr(self.readonly[ro]),
+            Value::FuncPtr(mfunc) => self.ptr(FUNC_BASE + self.function_slots[&mfunc]),
+            Value::ExternFuncPtr(key) => {
+                let i = self.externs.iter().position(|k| *k == Some(key)).unwrap();
+                self.ptr(EXTERN_FUNC_BASE + i as u64)
+            }
+            Value::Int(n, intsize) => int_bytes(n, (intsize.bytes() as usize).max(1)),
+            Value::Float(f) => f.to_bits().to_le_bytes().to_vec(),
+        }
+    }
+
+    fn type_of_value(&self, frame: &Frame, value: Value) -> MonoType {
+        match value {
+            Value::V(v) => frame.ssa.type_of(v).clone(),
+            Value::ReadOnly(ro) => MonoType::pointer(self.lir.read_only_table[ro].1.clone()),
+            Value::Int(_, intsize) => MonoType::Int(intsize),
+            Value::Float(_) => MonoType::Float(FloatSize::F64),
+            Value::FuncPtr(_) | Value::ExternFuncPtr(_) => MonoType::u8_pointer(),
+        }
+    }
+}
+
+fn val_name(lir: &Output, val: M<key::Val>) -> String {
+    let mfunc = lir.val_initializers[&val];
+    match &lir.functions[mfunc].kind {
+        Item::Defined(func) => lir.func_names[*func].clone(),
+        _ => lir.functions[mfunc].symbol.to_string(),
+    }
+}
+
+impl<'a> Frame<'a> {
+    /// Assign the block parameters and get the first value of the block
+    fn enter(&mut self, block: Block, params: Vec<Bytes>) -> V {
+        let start = self.ssa.block_info(block).start;
+
+        let bparams = self.ssa.block_params(block);
+        for (v, param) in bparams.zip(params) {
+            self.values[v.0 as usize] = param;
+        }
+
+        start
+    }
+}
+
+/// Decode a little-endian integer of up to 64 bits
+pub fn decode(bytes: &[u8], signed: bool) -> i128 {
+    let mut buf = [0; 8];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    let n = u64::from_le_bytes(buf);
+
+    let bits = bytes.len() as u32 * 8;
+    if signed && bits > 0 && bits < 64 {
+        let shift = 64 - bits;
+        ((n << shift) as i64 >> shift) as i128
+    } else if signed {
+        n as i64 as i128
+    } else {
+        n as i128
+    }
+}
+
+/// Encode an integer as little-endian, truncating it to `size` bytes
+pub fn int_bytes(n: i128, size: usize) -> Bytes {
+    n.to_le_bytes()[..size].to_vec()
+}
+
+/// Decode a float of either size, widening `f32` to `f64`
+pub fn decode_float(bytes: &[u8]) -> f64 {
+    match bytes.len() {
+        4 => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        _ => f64::from_le_bytes(bytes.try_into().unwrap()),
+    }
+}
+
+pub fn float_bytes(f: f64, size: FloatSize) -> Bytes {
+    match size {
+        FloatSize::F32 => (f as f32).to_le_bytes().to_vec(),
+        FloatSize::F64 => f.to_le_bytes().to_vec(),
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Exit(code) => write!(f, "program exited with code {code}"),
+            Error::Signal(signal) => write!(f, "program raised signal {signal}"),
+            Error::Trap(code) => write!(f, "program trapped: {code}"),
+            Error::Fault(addr) => write!(f, "invalid memory access at {addr:#x}"),
+            Error::DivisionByZero => "integer division by zero".fmt(f),
+            Error::NotAFunction(addr) => write!(f, "call to non-function address {addr:#x}"),
+            Error::UnsupportedExtern(symbol) => {
+                write!(f, "extern `{symbol}` is not supported by the interpreter")
+            }
+            Error::ExternAtCompileTime(symbol) => {
+                write!(f, "extern `{symbol}` cannot be called at compile time")
+            }
+            Error::RuntimeVal(name) => {
+                write!(
+                    f,
+                    "depends on `{name}` which is not evaluated at compile time"
+                )
+            }
+            Error::StepLimit => write!(
+                f,
+                "evaluation did not finish within {CONST_EVAL_STEP_LIMIT} steps"
+            ),
+            Error::UnrepresentableConst => {
+                "only vals made of numbers, arrays and records can be evaluated at compile time"
+                    .fmt(f)
+            }
+            Error::StaticAssert(message) => write!(f, "static assertion failed: {message}"),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use super::{val_name, Error, Interpreter, Layouts};
+use crate::debuginfo::Item;
+use crate::lir::{Block, MonoFunc, Output};
+use crate::prelude::*;
+use std::collections::{HashMap, VecDeque};
+
+impl<'a> Interpreter<'a> {
+    /// Replace the program with a recompiled version of it while keeping the state of the running
+    /// program, for live-coding workflows where the host calls into the program repeatedly
+    ///
+    /// Functions are matched against their previous version by name and signature, so function
+    /// pointers stored in memory call the new implementation afterwards. Pointers to functions
+    /// which no longer exist are invalidated. Vals whose layout didn't change keep their current
+    /// value, while new or changed vals are initialised again.
+    ///
+    /// The layout of data on the heap isn't checked, changing the representation of types which
+    /// are kept alive across a reload is up to the program to handle.
+    ///
+    /// Since the previous version may still be referenced by the interpreter, `lir` has to live
+    /// as long as the interpreter does.
+    pub fn reload(&mut self, lir: &'a Output) -> Result<(), Error> {
+        let previous = std::mem::replace(&mut self.lir, lir);
+        let old_layouts = std::mem::replace(&mut self.layouts, Layouts::new(&lir.types));
+        self.trace.clear();
+
+        // Match functions with the same name and signature in the order they were monomorphised
+        let mut candidates: HashMap<_, VecDeque<MonoFunc>> = HashMap::new();
+        for mfunc in lir.functions.keys() {
+            let key = (
+                reload_name(lir, mfunc),
+                signature(lir, &self.layouts, mfunc),
+            );
+            candidates.entry(key).or_default().push_back(mfunc);
+        }
+
+        self.function_slots.clear();
+        for (slot, function) in self.functions.iter_mut().enumerate() {
+            *function = function.and_then(|old| {
+                let key = (
+                    reload_name(previous, old),
+                    signature(previous, &old_layouts, old),
+                );
+                candidates.get_mut(&key)?.pop_front()
+            });
+
+            if let Some(mfunc) = *function {
+                self.function_slots.insert(mfunc, slot as u64);
+            }
+        }
+        for mfunc in lir.functions.keys() {
+            if !self.function_slots.contains_key(&mfunc) {
+                self.function_slots
+                    .insert(mfunc, self.functions.len() as u64);
+                self.functions.push(Some(mfunc));
+            }
+        }
+
+        for extern_ in self.externs.iter_mut() {
+            *extern_ = extern_.and_then(|old| {
+                let symbol = &previous.extern_funcs[&old].symbol;
+                lir.extern_funcs
+                    .iter()
+                    .find_map(|(key, func)| (func.symbol == *symbol).then_some(*key))
+            });
+        }
+        for key in lir.extern_funcs.keys() {
+            if !self.externs.contains(&Some(*key)) {
+                self.externs.push(Some(*key));
+            }
+        }
+
+        // Read-only data is immutable, so the previous allocations can be left for any pointers into them
+        self.readonly = lir
+            .read_only_table
+            .map(|_, (bytes, _)| self.memory.alloc_bytes(&bytes.0));
+
+        let kept: HashMap<String, (u64, (u64, u64))> = previous
+            .val_types
+            .iter()
+            .map(|val| {
+                let layout = old_layouts.size_and_align_of(&previous.val_types[val]);
+                (val_name(previous, val), (self.vals[val], layout))
+            })
+            .collect();
+
+        let mut uninitialised = vec![];
+        self.vals = lir.val_types.map(|val, ty| {
+            let (size, align) = self.layouts.size_and_align_of(ty);
+            match kept.get(&val_name(lir, val)) {
+                Some((addr, layout)) if *layout == (size, align) => *addr,
+                _ => {
+                    uninitialised.push(val);
+                    self.memory.alloc(size, align)
+                }
+            }
+        });
+
+        for val in uninitialised {
+            self.init_val(val)?;
+        }
+
+        Ok(())
+    }
+
+    /// Look up a non-generic function by its name, such as `main:update`
+    ///
+    /// Keys change between reloads, so functions need to be looked up again after `reload`.
+    pub fn function(&self, name: &str) -> Option<MonoFunc> {
+        let (module, name) = name.split_once(':')?;
+        self.lir
+            .functions
+            .iter()
+            .find_map(|(mfunc, func)| match func.kind {
+                Item::Defined(key) => (self.lir.module_names[key.0] == module
+                    && self.lir.func_names[key] == name)
+                    .then_some(mfunc),
+                _ => None,
+            })
+    }
+}
+
+/// Name used to match a function against its previous version
+///
+/// Symbols of mangled functions end with the key they were monomorphised as, which isn't stable
+/// across compilations.
+fn reload_name(lir: &Output, mfunc: MonoFunc) -> String {
+    let func = &lir.functions[mfunc];
+    match func.kind {
+        Item::Defined(key) => format!("{}:{}", lir.module_names[key.0], lir.func_names[key]),
+        _ => func
+            .symbol
+            .rsplit_once("::")
+            .map_or(func.symbol.as_str(), |(name, _)| name)
+            .to_string(),
+    }
+}
+
+fn signature(lir: &Output, layouts: &Layouts, mfunc: MonoFunc) -> Vec<(u64, u64)> {
+    let func = &lir.functions[mfunc];
+    func.ssa
+        .param_types(Block::entry())
+        .chain(std::iter::once(&func.returns))
+        .map(|ty| layouts.size_and_align_of(ty))
+        .collect()
+}
@@ -0,0 +1,106 @@
+//! Merging of identical function instantiations
+//!
+//! Phantom type parameters are erased to unit when their type is monomorphised, so a function
+//! instantiated for both `Id UserTag` and `Id OrderTag` receives the same types in both instances
+//! and lowers to the same code. All but one of such instantiations are redirected to the first and
+//! then left to be removed as unreachable.
+//!
+//! Two instantiations calling each of their own duplicates only become identical once those are
+//! merged, so this repeats until nothing changes.
+
+use super::{MonoFunc, Output};
+use crate::prelude::*;
+use std::collections::hash_map::Entry;
+
+impl Output {
+    /// Must run before `remove_unreachable_functions`, which removes the redirected duplicates
+    pub(super) fn merge_identical_instances(&mut self) {
+        let mut merged = HashMap::<MonoFunc, MonoFunc>::new();
+
+        loop {
+            let mut instances = HashMap::<_, Vec<MonoFunc>>::new();
+            let mut found = HashMap::new();
+
+            for mfunc in self.functions.keys() {
+                if merged.contains_key(&mfunc) {
+                    continue;
+                }
+
+                let func = &self.functions[mfunc];
+                let candidates = match instances.entry(&func.kind) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        entry.insert(vec![mfunc]);
+                        continue;
+                    }
+                };
+
+                match candidates
+                    .iter()
+                    .find(|&&other| self.is_duplicate_of(mfunc, other))
+                {
+                    Some(&other) => {
+                        trace!(
+                            "merging {} into {}",
+                            func.symbol,
+                            self.functions[other].symbol
+                        );
+                        found.insert(mfunc, other);
+                    }
+                    None => candidates.push(mfunc),
+                }
+            }
+
+            if found.is_empty() {
+                break;
+            }
+
+            for func in self.functions.values_mut() {
+                func.ssa.for_each_func_mut(&mut |mfunc| {
+                    if let Some(&into) = found.get(mfunc) {
+                        *mfunc = into;
+                    }
+                });
+            }
+
+            for (&mfunc, &into) in &found {
+                let invocations = self.functions[mfunc].invocations;
+                let pointed_to = self.functions[mfunc].pointed_to_by_func_pointer;
+                self.functions[into].invocations += invocations;
+                self.functions[into].pointed_to_by_func_pointer |= pointed_to;
+            }
+
+            merged.extend(found);
+        }
+
+        if !merged.is_empty() {
+            info!("merged {} identical function instances", merged.len());
+        }
+    }
+
+    // Recursive instantiations call themselves, so references to `mfunc` within itself are
+    // considered to be the same as references to `other` within `other`.
+    fn is_duplicate_of(&self, mfunc: MonoFunc, other: MonoFunc) -> bool {
+        let [func, ofunc] = [&self.functions[mfunc], &self.functions[other]];
+
+        if func.returns != ofunc.returns
+            || func.linkage != ofunc.linkage
+            || func.export.is_some()
+            || ofunc.export.is_some()
+        {
+            return false;
+        }
+
+        if !func.directly_recursive {
+            return func.ssa.same_code(&ofunc.ssa);
+        }
+
+        let mut ssa = func.ssa.clone();
+        ssa.for_each_func_mut(&mut |called| {
+            if *called == mfunc {
+                *called = other;
+            }
+        });
+        ssa.same_code(&ofunc.ssa)
+    }
+}
@@ -1,17 +1,22 @@
 //! Monomorphization
 //! Closure lowering to vtables
 //! Dynamic Object lowering to vtables
+//! Devirtualisation of traits with a single implementation
+//! Removal of functions unreachable from the entry points
 //! Pattern decision trees lowering to expressions
 //! Flatten to SSA+CFG with Basic Blocks
 
 use crate::prelude::*;
-use crate::{debuginfo::Item, ProjectInfo, Target};
+use crate::{debuginfo::Item, OptLevel, ProjectInfo, Target};
 use ast::attr::Repr;
 use derive_new::new;
 use either::Either;
 use lumina_collections::map_key_impl;
-use lumina_typesystem::{Generic, GenericKind, GenericMapper, ImplIndex, IntSize, Static, Type};
-use lumina_util::Highlighting;
+use lumina_typesystem::{
+    FloatSize, Generic, GenericKind, GenericMapper, ImplIndex, IntSize, Static, Type,
+};
+use lumina_util::{Highlighting, Symbol};
+use std::collections::HashSet;
 use std::fmt;
 use tracing::info_span;
 mod debug;
@@ -35,44 +40,115 @@ macro_rules! to_morphization {
     };
 }
 
+pub mod bytecode;
 mod mono;
 mod reflect;
+mod slice;
 mod ssa;
 pub use mono::{
     fmt as ty_fmt, MonoFormatter, MonoType, MonoTypeData, MonoTypeKey, MonomorphisedTypes,
     Monomorphization, TypeMap, Types,
 };
-pub use ssa::{BinOp, Block, BlockJump, Entry, Value, SSA, V};
+pub use ssa::{
+    AtomicOrdering, AtomicRmwOp, BinOp, Block, BlockJump, Entry, Location, Value, SSA, V,
+};
+mod devirtualise;
 mod dyn_dispatch;
+mod escape;
 mod expr;
+pub mod interpret;
+mod merge;
 mod pat;
+pub mod profile;
+mod reachability;
+mod verify;
+pub use verify::{verify, VerifyError};
 
 pub const TRAP_UNREACHABLE: u8 = 1;
+pub const TRAP_OUT_OF_BOUNDS: u8 = 2;
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct MonoFunc(u32);
 map_key_impl!(MonoFunc(u32), "mfunc");
 
+pub struct Test {
+    pub name: String,
+    pub module: key::Module,
+    pub kind: ast::TestKind,
+    pub expected_output: Option<mir::ExpectedOutput>,
+    pub func: MonoFunc,
+    pub params: Vec<MonoType>,
+    /// The `Generate` implementation of each parameter, integers are generated by the harness
+    pub generators: Vec<Option<MonoFunc>>,
+}
+
 pub struct Output {
     pub functions: Map<MonoFunc, Function>,
     pub extern_funcs: HashMap<M<key::Func>, ExternFunction>,
     pub val_initializers: HashMap<M<key::Val>, MonoFunc>,
     pub val_types: MMap<key::Val, MonoType>,
 
+    /// Vals marked to be evaluated at compile time
+    pub const_vals: HashSet<M<key::Val>>,
+    /// Const vals which must evaluate to true, along with the message to report if they don't
+    pub static_asserts: HashMap<M<key::Val>, String>,
+    /// Vals which every thread has its own copy of
+    pub thread_local_vals: HashSet<M<key::Val>>,
+    /// Results of compile-time evaluated vals, these don't need their initialiser to be ran
+    pub val_constants: HashMap<M<key::Val>, Vec<u8>>,
+
     pub read_only_table: MMap<key::ReadOnly, (mir::ReadOnlyBytes, MonoType)>,
 
     pub types: Types,
 
     pub func_names: MMap<key::Func, String>,
     pub module_names: Map<key::Module, String>,
+    pub record_names: MMap<key::Record, String>,
+    pub field_names: MMap<key::Record, Map<key::Field, String>>,
 
     pub main: MonoFunc,
     pub sys_init: MonoFunc,
 
+    /// Functions marked with `@[test]` or `@[property]`
+    pub tests: Vec<Test>,
+
     pub alloc: MonoFunc,
     pub dealloc: MonoFunc,
 }
 
+impl Output {
+    /// Move the bodies of all functions out of the program, leaving only their signatures behind
+    ///
+    /// Backends lower the bodies one at a time and drop them afterwards, so that the bodies of
+    /// functions which already have code generated for them don't need to be kept in memory.
+    pub fn take_bodies(&mut self) -> Map<MonoFunc, Function> {
+        self.functions
+            .values_mut()
+            .map(|func| {
+                let signature = func.ssa.signature();
+                Function {
+                    symbol: func.symbol,
+                    kind: func.kind.clone(),
+                    ssa: std::mem::replace(&mut func.ssa, signature),
+                    returns: func.returns.clone(),
+                    invocations: func.invocations,
+                    directly_recursive: func.directly_recursive,
+                    pointed_to_by_func_pointer: func.pointed_to_by_func_pointer,
+                    linkage: func.linkage.clone(),
+                    export: func.export,
+                    calls: func.calls,
+                }
+            })
+            .collect()
+    }
+
+    /// Replace the body of a function with a trap, leaving its signature as is
+    pub fn stub(&mut self, func: MonoFunc) {
+        let ssa = &mut self.functions[func].ssa;
+        *ssa = ssa.stub();
+    }
+}
+
 #[derive(new)]
 struct LIR {
     #[new(default)]
@@ -91,6 +167,9 @@ struct LIR {
 
     target: Target,
 
+    /// Functions with fewer entries than this are inlined into their callers
+    inline_threshold: usize,
+
     vals: MMap<key::Val, MonoType>,
     #[new(default)]
     val_initialisers: HashMap<M<key::Val>, MonoFunc>,
@@ -144,11 +223,15 @@ struct Current {
     tmap: TypeMap,
     bindmap: HashMap<key::Bind, ssa::Value>,
     captures: Option<usize>,
+    /// Conditions hinted with `builtin:likely` or `builtin:unlikely`, and the value they're
+    /// expected to be
+    expectations: HashMap<V, bool>,
 }
 
 #[derive(new)]
 pub struct Function {
-    pub symbol: String,
+    #[new(into)]
+    pub symbol: Symbol,
     pub kind: Item,
     pub ssa: ssa::SSA,
     pub returns: MonoType,
@@ -158,6 +241,14 @@ pub struct Function {
     pub directly_recursive: bool,
     #[new(default)]
     pub pointed_to_by_func_pointer: bool,
+    #[new(default)]
+    pub linkage: ast::Linkage,
+    /// Symbol of the C-compatible entrypoint generated for an `@[export]` function
+    #[new(default)]
+    pub export: Option<Symbol>,
+    /// How often the function was called in the profile given with `--profile-use`
+    #[new(default)]
+    pub calls: Option<u64>,
 }
 
 impl Function {
@@ -169,9 +260,16 @@ impl Function {
 }
 
 pub struct ExternFunction {
-    pub symbol: String,
+    pub symbol: Symbol,
     pub params: Vec<MonoType>,
     pub returns: MonoType,
+    /// Whether the symbol refers to a data object rather than a function
+    pub data: bool,
+    /// Whether additional parameters may be given after `params`, which are then promoted as
+    /// C's default argument promotions require
+    pub variadic: bool,
+    /// Native library the program is linked against for this function
+    pub library: Option<String>,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, new)]
@@ -208,7 +306,15 @@ enum Callable {
     Local(Value),
 }
 
-pub fn run<'s>(info: ProjectInfo, target: Target, iquery: &ImplIndex, mut mir: mir::MIR) -> Output {
+pub fn run<'s>(
+    info: ProjectInfo,
+    target: Target,
+    iquery: &ImplIndex,
+    mut mir: mir::MIR,
+    profiling: &profile::Profiling,
+    opt_level: OptLevel,
+    inline_threshold: usize,
+) -> Output {
     info!("starting LIR lower");
 
     let mainfunc = &mir.funcs[info.main].as_done();
@@ -240,14 +346,21 @@ pub fn run<'s>(info: ProjectInfo, target: Target, iquery: &ImplIndex, mut mir: m
         .funcs
         .iter()
         .filter_map(|func| match &mir.funcs[func] {
-            mir::FunctionStatus::Extern { link_name, typing } => {
+            mir::FunctionStatus::Extern { link_name, data, variadic, library, typing } => {
                 let mut tmap = TypeMap::new();
                 let mut monomorphization = to_morphization(&mir, &mut mono, &mut tmap);
                 let params = monomorphization.applys(&typing.params);
                 let returns = monomorphization.apply(&typing.returns);
                 Some((
                     func,
-                    ExternFunction { symbol: link_name.clone(), params, returns },
+                    ExternFunction {
+                        symbol: link_name.as_str().into(),
+                        params,
+                        returns,
+                        data: *data,
+                        variadic: *variadic,
+                        library: library.clone(),
+                    },
                 ))
             }
             _ => None,
@@ -292,7 +405,19 @@ pub fn run<'s>(info: ProjectInfo, target: Target, iquery: &ImplIndex, mut mir: m
         "main function can not take parameters"
     );
 
-    let mut lir = LIR::new(extern_funcs, mono, read_only_table, target, vals);
+    let mut lir = LIR::new(
+        extern_funcs,
+        mono,
+        read_only_table,
+        target,
+        // Functions which are only called once are still inlined, since that can't grow the
+        // program
+        match opt_level {
+            OptLevel::Size => 0,
+            _ => inline_threshold,
+        },
+        vals,
+    );
 
     // fn alloc size as int -> *u8 =
     // fn dealloc ptr size as *u8, int -> () =
@@ -310,7 +435,7 @@ pub fn run<'s>(info: ProjectInfo, target: Target, iquery: &ImplIndex, mut mir: m
         let typing = MonoTyping::new(Item::Defined(info.main), Map::new(), returns);
         lir.func(&mir, iquery, info, tmap, typing, None)
     };
-    lir.functions[main].symbol = String::from("_lumina_main");
+    lir.functions[main].symbol = Symbol::intern("_lumina_main");
 
     for val in mir.val_initializers.iter() {
         let func = mir.val_initializers[val];
@@ -319,28 +444,93 @@ pub fn run<'s>(info: ProjectInfo, target: Target, iquery: &ImplIndex, mut mir: m
         assert_eq!(previous, None);
     }
 
+    // Exported functions are called by foreign code, so they are lowered even if nothing calls them
+    for func in mir.funcs.iter() {
+        if let mir::FunctionStatus::Done(fdef) = &mir.funcs[func] {
+            if fdef.export.is_some() {
+                lir.static_func(&mir, iquery, info, func);
+            }
+        }
+    }
+
+    // generics of test functions are substituted for unit the same way as for main
+    let tests = std::mem::take(&mut mir.tests)
+        .into_iter()
+        .map(|mir::Test { func, kind, expected_output }| {
+            let fdef = mir.funcs[func].as_done();
+            let mut tmap = TypeMap::new();
+            let mut morph = to_morphization!(lir, mir, &mut tmap);
+            morph.substitute_generics_for_unit_type(&fdef.typing.forall);
+            let weak_params: Vec<Type> = morph.applys_weak(fdef.typing.params.iter());
+            let typing = morph.apply_typing(Item::Defined(func), &fdef.typing);
+            let params: Vec<MonoType> = typing.params.values().cloned().collect();
+            let mfunc = lir.func(&mir, iquery, info, tmap.clone(), typing, None);
+            let generators = lir.generators(
+                &mir,
+                iquery,
+                info,
+                (func, mfunc, tmap),
+                weak_params,
+                &params,
+            );
+            Test {
+                name: mir.func_names[func].clone(),
+                module: func.0,
+                kind,
+                expected_output,
+                func: mfunc,
+                params,
+                generators,
+            }
+        })
+        .collect();
+
+    lir.devirtualise(&mir);
+
     #[cfg(debug_assertions)]
     Debugger::new(&lir, &mir).run();
 
-    lir.perform_optimizations();
+    match profiling {
+        // Counters of instrumented builds refer to the selects of the unoptimised functions
+        profile::Profiling::Generate => {}
+        profile::Profiling::Use(profile) => {
+            profile.apply(&mut lir.functions);
+            lir.perform_optimizations(opt_level);
+        }
+        profile::Profiling::None => lir.perform_optimizations(opt_level),
+    }
+
+    lir.stack_allocate_closures();
 
     #[cfg(debug_assertions)]
     Debugger::new(&lir, &mir).run();
 
-    Output {
+    let mut output = Output {
         functions: lir.functions,
         extern_funcs: lir.extern_funcs,
         val_initializers: lir.val_initialisers,
         val_types: lir.vals,
+        const_vals: mir.const_vals,
+        static_asserts: mir.static_asserts,
+        thread_local_vals: mir.thread_local_vals,
+        val_constants: HashMap::new(),
         read_only_table: lir.read_only_table,
         func_names: mir.func_names,
         module_names: mir.module_names,
+        record_names: mir.record_names,
+        field_names: mir.field_names,
         types: lir.mono.into_records(),
         alloc,
         dealloc,
         main,
         sys_init,
-    }
+        tests,
+    };
+
+    output.merge_identical_instances();
+    output.remove_unreachable_functions();
+
+    output
 }
 
 impl LIR {
@@ -425,6 +615,8 @@ impl LIR {
 
                 let symbol = func_symbol(mir, self.functions.next_key(), &origin);
                 let mfkey = self.push_function(symbol, typing.origin.clone(), ssa, returns);
+                self.functions[mfkey].linkage = func_linkage(mir, &origin);
+                self.functions[mfkey].export = func_export(mir, &origin);
 
                 let key = MonoTypesKey::new(
                     typing.origin.clone(),
@@ -438,7 +630,14 @@ impl LIR {
                     mir,
                     iquery,
                     info,
-                    current: Current { origin, mfkey, tmap, bindmap, captures: capture_count },
+                    current: Current {
+                        origin,
+                        mfkey,
+                        tmap,
+                        bindmap,
+                        captures: capture_count,
+                        expectations: HashMap::new(),
+                    },
                 };
 
                 lower.run();
@@ -448,12 +647,51 @@ impl LIR {
         }
     }
 
+    /// Find the `Generate` implementation for each parameter of a property test
+    fn generators(
+        &mut self,
+        mir: &mir::MIR,
+        iquery: &ImplIndex,
+        info: ProjectInfo,
+        (func, mfkey, tmap): (M<key::Func>, MonoFunc, TypeMap),
+        weak_params: Vec<Type>,
+        params: &[MonoType],
+    ) -> Vec<Option<MonoFunc>> {
+        let mut lower = FuncLower {
+            lir: self,
+            mir,
+            iquery,
+            info,
+            current: Current {
+                origin: Item::Defined(func),
+                mfkey,
+                tmap,
+                bindmap: HashMap::new(),
+                captures: None,
+                expectations: HashMap::new(),
+            },
+        };
+
+        weak_params
+            .into_iter()
+            .zip(params)
+            .map(|(weak, ty)| match (ty, info.generate) {
+                (MonoType::Int(_), _) | (_, None) => None,
+                (_, Some(generate)) => {
+                    let (ikey, tmap) =
+                        lower.try_find_implementation(generate, &[], weak, ty.clone())?;
+                    Some(lower.call_to_mfunc(Item::Method(ikey, key::Method(0)), tmap))
+                }
+            })
+            .collect()
+    }
+
     fn type_of_value(&self, mfkey: MonoFunc, value: ssa::Value) -> MonoType {
         match value {
             ssa::Value::ReadOnly(ro) => MonoType::pointer(self.read_only_table[ro].1.clone()),
             ssa::Value::V(v) => self.functions[mfkey].ssa.type_of(v).clone(),
             ssa::Value::Int(_, intsize) => MonoType::Int(intsize),
-            ssa::Value::Float(_) => MonoType::Float,
+            ssa::Value::Float(_) => MonoType::Float(FloatSize::F64),
             ssa::Value::FuncPtr(ptr) => {
                 let func = &self.functions[ptr];
                 func.as_fnpointer()
@@ -562,6 +800,8 @@ impl<'a> FuncLower<'a> {
         );
     }
 
+    // Parameters are guaranteed to be evaluated left-to-right, which programs may rely on for the
+    // order of their effects
     fn params_to_values(&mut self, params: &[mir::Expr]) -> Vec<ssa::Value> {
         params.iter().map(|p| self.expr_to_value(p)).collect()
     }
@@ -629,14 +869,21 @@ impl<'a> FuncLower<'a> {
                     .map(V::value)
                     .collect::<Vec<_>>();
 
-                let params_tuple = self.lir.mono.get_or_make_tuple(types).into();
-
-                ssa.construct(block_params, params_tuple);
+                let mk = ty.as_key();
+                let (v, returns) = match self.lir.mono.niche_of(mk).cloned() {
+                    Some(pointer) => {
+                        let size_t = self.types().size_t();
+                        let v = ssa.niche_variant(block_params, pointer.clone(), size_t);
+                        (v, pointer)
+                    }
+                    None => (ssa.variant(var, block_params, mk), ty.clone()),
+                };
+                ssa.return_(v);
 
                 let symbol = func_symbol(self.mir, mfunc, &origin);
-                let (_, sum, _) = self.types()[ty.as_key()].as_sum();
+                let (_, sum, _) = self.types()[mk].as_sum();
                 let kind = Item::SumConstructorWrapper(sum, var);
-                let mut function = Function::new(symbol, kind, ssa, ty, 1);
+                let mut function = Function::new(symbol, kind, ssa, returns, 1);
                 function.pointed_to_by_func_pointer = true;
 
                 self.lir.functions.push_as(mfunc, function);
@@ -657,10 +904,11 @@ impl<'a> FuncLower<'a> {
                 let (ikey, tmap) =
                     self.find_implementation(self.info.stringable, &[], weakstring, type_.into());
 
-                let [split_at, split_while, split_first, equals, from_raw_parts] = [0, 1, 2, 3, 4]
-                    .map(key::Method)
-                    .map(|method| Item::Method(ikey, method))
-                    .map(|origin| self.call_to_mfunc(origin, tmap.clone()));
+                let [split_at, split_while, split_first, equals, from_raw_parts, len] =
+                    [0, 1, 2, 3, 4, 5]
+                        .map(key::Method)
+                        .map(|method| Item::Method(ikey, method))
+                        .map(|origin| self.call_to_mfunc(origin, tmap.clone()));
 
                 let str = Stringable {
                     split_at,
@@ -668,6 +916,7 @@ impl<'a> FuncLower<'a> {
                     split_first,
                     equals,
                     from_raw_parts,
+                    len,
                     type_,
                 };
 
@@ -749,6 +998,14 @@ impl<'a> FuncLower<'a> {
             .call(stringable.equals, strs.into(), MonoType::bool())
     }
 
+    fn string_len(&mut self, str: Value) -> Value {
+        let stringable = self.stringable();
+        let (_, size) = self.uint(0);
+
+        self.ssa()
+            .call(stringable.len, vec![str], MonoType::Int(size))
+    }
+
     fn string_type(&mut self) -> MonoTypeKey {
         to_morphization!(self.lir, self.mir, &mut self.current.tmap).record(self.info.string, &[])
     }
@@ -889,6 +1146,27 @@ impl<'a> FuncLower<'a> {
                     Callable::Sum { var, ty, tmap }
                 }
             },
+            mir::Callable::Impl(ikey, method, mapper) => {
+                let mut morph = to_morphization!(self.lir, self.mir, &mut self.current.tmap);
+
+                let self_ = mapper.self_.as_ref().unwrap();
+
+                let weak_impltor = morph.apply_weak(self_);
+                let impltor = morph.apply(self_);
+
+                let trtp = mapper
+                    .types(GenericKind::Parent)
+                    .map(|ty| morph.apply_weak(ty))
+                    .collect::<Vec<_>>();
+
+                let itmap = self.instantiate_implementation(*ikey, &trtp, weak_impltor, impltor);
+
+                let forigin = Item::Method(*ikey, *method);
+
+                let mfunc = self.call_to_mfunc(forigin, itmap);
+
+                Callable::Static(mfunc)
+            }
             mir::Callable::Lambda(lambda, mapper) => {
                 let (mfunc, captures) = self.morphise_lambda(*lambda, mapper);
                 Callable::LiftedLambda(mfunc, captures)
@@ -927,6 +1205,24 @@ impl<'a> FuncLower<'a> {
     }
 }
 
+fn func_linkage(mir: &mir::MIR, origin: &Item) -> ast::Linkage {
+    match origin {
+        Item::Defined(key) => mir.funcs[*key].as_done().linkage.clone(),
+        _ => ast::Linkage::default(),
+    }
+}
+
+fn func_export(mir: &mir::MIR, origin: &Item) -> Option<Symbol> {
+    match origin {
+        Item::Defined(key) => mir.funcs[*key]
+            .as_done()
+            .export
+            .as_deref()
+            .map(Symbol::intern),
+        _ => None,
+    }
+}
+
 fn func_symbol(mir: &mir::MIR, key: MonoFunc, origin: &Item) -> String {
     if let Item::Defined(key) = origin {
         if mir.funcs[*key].as_done().no_mangle {
@@ -948,6 +1244,7 @@ struct Stringable {
     split_first: MonoFunc,    // self -> (u8, self)
     equals: MonoFunc,         // self, self -> bool
     from_raw_parts: MonoFunc, // *u8, uint -> self
+    len: MonoFunc,            // self -> uint
 
     type_: MonoTypeKey,
 }
@@ -5,7 +5,7 @@ use derive_more::{Deref, DerefMut};
 use lumina_collections::map_key_impl;
 use lumina_key as key;
 use lumina_typesystem::{
-    ConstValue, Container, Forall, Generic, GenericKind, GenericMapper, IntSize, Static,
+    ConstValue, Container, FloatSize, Forall, Generic, GenericKind, GenericMapper, IntSize, Static,
     Transformer, Ty, Type,
 };
 use lumina_util::Highlighting;
@@ -20,7 +20,7 @@ pub enum MonoType {
     Int(IntSize),
     Pointer(Box<Self>),
     FnPointer(Vec<Self>, Box<Self>),
-    Float,
+    Float(FloatSize),
     Unreachable,
     Const(ConstValue),
     Array(u64, Box<Self>),
@@ -42,9 +42,17 @@ pub struct Types {
     pub pointer_bits: u32,
 }
 
+impl Types {
+    /// The unsigned integer of the same size as a pointer
+    pub fn size_t(&self) -> IntSize {
+        IntSize::new(false, self.pointer_bits as u8)
+    }
+}
+
 pub struct MonomorphisedTypes {
     resolve: HashMap<(M<key::TypeKind>, Vec<MonoType>), MonoTypeKey>,
     tuples: HashMap<Vec<MonoType>, MonoTypeKey>,
+    niches: HashMap<MonoTypeKey, Option<MonoType>>,
 
     pub types: Types,
 
@@ -156,7 +164,7 @@ impl<'a, 't> fmt::Display for MonoFormatter<'a, &'t MonoType> {
                 )
             }
             MonoType::Const(const_) => const_.fmt(f),
-            MonoType::Float => "float".fmt(f),
+            MonoType::Float(size) => size.fmt(f),
             MonoType::Unreachable => "!".fmt(f),
             MonoType::Array(len, inner) => write!(f, "[{}; {len}]", self.fork(&**inner)),
             MonoType::Monomorphised(key) => self.fork(*key).fmt(f),
@@ -225,6 +233,7 @@ impl MonomorphisedTypes {
             closure,
             resolve: HashMap::new(),
             tuples: HashMap::new(),
+            niches: HashMap::new(),
             types: Types { records: Map::new(), pointer_bits: pointer_size },
             default_repr: default,
         };
@@ -240,6 +249,11 @@ impl MonomorphisedTypes {
         MonoFormatter { v, types: &self.types.records, funcs: None }
     }
 
+    /// The nullable pointer an option-like sum is represented as, see [`Monomorphization::sum`]
+    pub fn niche_of(&self, sum: MonoTypeKey) -> Option<&MonoType> {
+        self.niches.get(&sum).and_then(Option::as_ref)
+    }
+
     pub fn get_or_make_tuple(&mut self, elems: Vec<MonoType>) -> MonoTypeKey {
         if let Some(key) = self.tuples.get(&elems).copied() {
             return key;
@@ -326,6 +340,22 @@ impl MonoType {
         }
     }
 
+    #[track_caller]
+    pub fn as_int(&self) -> IntSize {
+        match self {
+            MonoType::Int(intsize) => *intsize,
+            ty => panic!("not an integer: {ty:?}"),
+        }
+    }
+
+    #[track_caller]
+    pub fn as_float(&self) -> FloatSize {
+        match self {
+            MonoType::Float(size) => *size,
+            ty => panic!("not a float: {ty:?}"),
+        }
+    }
+
     #[track_caller]
     pub fn as_array(&self) -> (u64, MonoType) {
         match self {
@@ -438,32 +468,112 @@ impl<'a> Monomorphization<'a> {
     }
 
     pub fn record(&mut self, key: M<key::Record>, params: &[Type]) -> MonoTypeKey {
-        self.get_or_monomorphise(key, params, GenericKind::Entity, |this, repr, mut tmap| {
+        let fields = self.field_types[key].values().map(|t| &t.value);
+        let params = erase_phantom_params(params, fields);
+
+        self.get_or_monomorphise(key, &params, GenericKind::Entity, |this, repr, mut tmap| {
             let fields = &this.field_types[key];
             let fields = fork!(this, &mut tmap).applys(fields.values().map(|t| &t.value));
             MonoTypeData::Record { key: Some(key), fields, repr }
         })
     }
 
+    /// Sums with one variant without parameters and another holding only a pointer are represented
+    /// as that pointer, with null standing in for the empty variant. Values of such sums have the
+    /// pointer as their type, the sum type itself is still created for its variants.
     pub fn sum(&mut self, key: M<key::Sum>, params: &[Type]) -> MonoTypeKey {
-        self.get_or_monomorphise(key, params, GenericKind::Entity, |this, repr, mut tmap| {
-            let tag = match repr {
-                Repr::Enum(size) => size,
-                Repr::Align(bytes) => IntSize::new(false, bytes * 8),
-                _ => IntSize::new(false, 16),
-            };
+        let variants = self.variant_types[key].values().flatten().map(|t| &t.value);
+        let params = erase_phantom_params(params, variants);
+
+        let mk =
+            self.get_or_monomorphise(key, &params, GenericKind::Entity, |this, repr, mut tmap| {
+                let tag = match repr {
+                    Repr::Enum(size) => size,
+                    Repr::Align(bytes) => IntSize::new(false, bytes.min(8) * 8),
+                    _ => IntSize::new(false, 16),
+                };
+
+                let variants = &this.variant_types[key];
+                let variants = variants
+                    .values()
+                    .map(|params| {
+                        let elems = fork!(this, &mut tmap).applys(params.iter().map(|t| &t.value));
+                        this.mono.get_or_make_tuple(elems)
+                    })
+                    .collect();
+
+                MonoTypeData::Sum { tag, variants, key }
+            });
+
+        if !self.mono.niches.contains_key(&mk) {
+            let niche = self.niche(key, &params);
+            self.mono.niches.insert(mk, niche);
+        }
 
-            let variants = &this.variant_types[key];
-            let variants = variants
-                .values()
-                .map(|params| {
-                    let elems = fork!(this, &mut tmap).applys(params.iter().map(|t| &t.value));
-                    this.mono.get_or_make_tuple(elems)
-                })
-                .collect();
+        mk
+    }
 
-            MonoTypeData::Sum { tag, variants, key }
-        })
+    fn niche(&mut self, key: M<key::Sum>, params: &[Type]) -> Option<MonoType> {
+        // An explicit representation is most likely relied upon by foreign code
+        if self.type_repr.is_specified(key) {
+            return None;
+        }
+
+        let params = self.applys_weak(params);
+        let pointer = self.niche_pointer(key, &params)?;
+
+        (!self.reaches(key, &pointer, &mut vec![])).then(|| self.apply(&pointer))
+    }
+
+    fn niche_pointer(&self, key: M<key::Sum>, params: &[Type]) -> Option<Type> {
+        let variants = &self.variant_types[key];
+        if variants.len() != 2 {
+            return None;
+        }
+
+        let pointer = match [
+            &variants[key::Variant(0)][..],
+            &variants[key::Variant(1)][..],
+        ] {
+            [[], [pointer]] | [[pointer], []] => &pointer.value,
+            _ => return None,
+        };
+
+        // Checked after substitution, so that `Maybe a` gets the niche when `a` is a pointer
+        let finst = GenericMapper::from_types(GenericKind::Entity, params.iter().cloned());
+        let pointer = (&finst).transform(pointer);
+        matches!(pointer, Ty::Container(Container::Pointer, _)).then_some(pointer)
+    }
+
+    // Whether monomorphising `ty` may require the sum `target` before reaching a named type.
+    //
+    // Niches are monomorphised in place of their sum, so such a sum would need its own niche to
+    // know its niche.
+    fn reaches(&self, target: M<key::Sum>, ty: &Type, visited: &mut Vec<M<key::Sum>>) -> bool {
+        let Ty::Container(con, params) = ty else {
+            return false;
+        };
+
+        if params.iter().any(|ty| self.reaches(target, ty, visited)) {
+            return true;
+        }
+
+        match con {
+            &Container::Defined(M(module, key::TypeKind::Sum(sum)), _) => {
+                let sum = sum.inside(module);
+                if sum == target {
+                    return true;
+                }
+                if visited.contains(&sum) {
+                    return false;
+                }
+                visited.push(sum);
+
+                self.niche_pointer(sum, params)
+                    .is_some_and(|pointer| self.reaches(target, &pointer, visited))
+            }
+            _ => false,
+        }
     }
 
     // For closures, the type parameter `p` actually expands from {a,b} to `a,b`
@@ -597,6 +707,14 @@ impl<'a> Monomorphization<'a> {
                     };
                     MonoType::Array(len, Box::new(inner))
                 }
+                Container::Slice => {
+                    let inner = self.apply(&params[0]);
+                    let len = MonoType::Int(self.mono.types.size_t());
+                    let fat = self
+                        .mono
+                        .get_or_make_tuple(vec![MonoType::pointer(inner), len]);
+                    MonoType::Monomorphised(fat)
+                }
                 &Container::Defined(M(module, key), _) => match key {
                     key::TypeKind::Record(rkey) => {
                         let mk = self.record(rkey.inside(module), params);
@@ -605,7 +723,10 @@ impl<'a> Monomorphization<'a> {
 
                     key::TypeKind::Sum(sum) => {
                         let mk = self.sum(sum.inside(module), params);
-                        MonoType::Monomorphised(mk)
+                        match self.mono.niche_of(mk) {
+                            Some(pointer) => pointer.clone(),
+                            None => MonoType::Monomorphised(mk),
+                        }
                     }
 
                     key::TypeKind::Trait(trait_) => {
@@ -618,7 +739,8 @@ impl<'a> Monomorphization<'a> {
             Ty::Const(const_) => MonoType::Const(const_.clone()),
             Ty::Generic(generic) => self.generic(*generic).clone(),
             Ty::Int(intsize) => MonoType::Int(*intsize),
-            Ty::Simple("f64") => MonoType::Float,
+            Ty::Simple("f64") => MonoType::Float(FloatSize::F64),
+            Ty::Simple("f32") => MonoType::Float(FloatSize::F32),
             Ty::Simple("bool") => MonoType::bool(),
             Ty::Simple("self") => self.tmap.self_.clone().unwrap(),
             _ => panic!("invalid type for LIR: {ty}"),
@@ -675,6 +797,37 @@ impl<'a> Monomorphization<'a> {
     }
 }
 
+/// Type parameters which none of the fields or variants mention only exist for type checking, so
+/// they're erased to unit to let every instantiation share the same monomorphised type.
+fn erase_phantom_params<'t>(params: &[Type], members: impl Iterator<Item = &'t Type>) -> Vec<Type> {
+    fn mark(ty: &Type, used: &mut [bool]) {
+        match ty {
+            Ty::Container(_, elems) => elems.iter().for_each(|ty| mark(ty, used)),
+            Ty::Generic(Generic { key, kind: GenericKind::Entity }) => {
+                if let Some(used) = used.get_mut(key.0 as usize) {
+                    *used = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut used = vec![false; params.len()];
+    members.for_each(|ty| mark(ty, &mut used));
+
+    params
+        .iter()
+        .zip(used)
+        .map(|(ty, used)| {
+            if used {
+                ty.clone()
+            } else {
+                Type::tuple(vec![])
+            }
+        })
+        .collect()
+}
+
 impl TypeMap {
     pub fn new() -> Self {
         Self {
@@ -722,7 +875,7 @@ impl fmt::Debug for MonoType {
                     params.iter().map(|t| format!("{t:?}")).format(", ")
                 )
             }
-            MonoType::Float => write!(f, "f64"),
+            MonoType::Float(size) => write!(f, "{size}"),
             MonoType::Unreachable => write!(f, "!"),
             MonoType::Array(len, inner) => write!(f, "[{:?}; {len}]", inner),
             MonoType::Monomorphised(key) => write!(f, "{key}"),
@@ -1,7 +1,7 @@
 use super::*;
 use crate::LISTABLE_SPLIT;
 use lumina_typesystem::{Container, GenericMapper, IntSize, Transformer};
-use mir::pat::{DecTree, Range, StrCheck, StrChecks, TreeTail};
+use mir::pat::{ByteCheck, ByteChecks, ByteLen, DecTree, Range, StrCheck, StrChecks, TreeTail};
 use ssa::{Block, Value};
 use std::collections::VecDeque;
 
@@ -100,6 +100,7 @@ impl<'f, 'v, 'a> PatLower<'f, 'v, 'a> {
             DecTree::Bools(next) => self.bools(on, next),
             DecTree::Sum { sum, params, next } => self.sum(on, *sum, params, next),
             DecTree::String { next, wildcard_next } => self.string(on, next, wildcard_next),
+            DecTree::Bytes { next, wildcard_next } => self.bytes(on, next, wildcard_next),
             DecTree::Wildcard { next, .. } | DecTree::Opaque { next, .. } => self.next(next),
             DecTree::End(tail) => self.tail(tail),
         }
@@ -270,6 +271,15 @@ impl<'f, 'v, 'a> PatLower<'f, 'v, 'a> {
         self.ssa()
             .select(on, [(on_true, vec![]), (on_false, vec![])]);
 
+        // Move the branch the condition isn't expected to take out of the way of the hot path
+        if let Value::V(v) = on {
+            match self.f.current.expectations.get(&v) {
+                Some(true) => self.ssa().set_cold(on_false),
+                Some(false) => self.ssa().set_cold(on_true),
+                None => {}
+            }
+        }
+
         self.ssa().switch_to_block(on_true);
         self.next(&truthy.1);
 
@@ -449,6 +459,39 @@ impl<'f, 'v, 'a> PatLower<'f, 'v, 'a> {
 
                     on
                 }
+                StrCheck::Suffix(key) => {
+                    assert!(
+                        !is_last,
+                        "suffixes are followed by the binder of the remainder"
+                    );
+
+                    let (str, slen_arg, _) = self.f.string_from_ro(*key);
+                    self.map.push(str);
+
+                    let (_, size) = self.f.uint(0);
+                    let len = self.f.string_len(on);
+                    let too_short = self.ssa().lti([len, slen_arg], size);
+
+                    let split_block = self.ssa().new_block();
+
+                    self.ssa()
+                        .select(too_short, [(falsely, vec![]), (split_block, vec![])]);
+
+                    self.ssa().switch_to_block(split_block);
+
+                    let at = self.ssa().sub(len, slen_arg, MonoType::Int(size));
+                    let [lhs, rhs] = self.f.string_split_at(on, at);
+                    let eq = self.f.string_equals([rhs, str]);
+
+                    let next_check_block = self.ssa().new_block();
+
+                    self.ssa()
+                        .select(eq, [(next_check_block, vec![]), (falsely, vec![])]);
+
+                    self.ssa().switch_to_block(next_check_block);
+
+                    lhs
+                }
                 StrCheck::TakeExcess => {
                     assert!(is_last);
                     self.map.push(on);
@@ -523,14 +566,160 @@ impl<'f, 'v, 'a> PatLower<'f, 'v, 'a> {
         self.next(next);
     }
 
+    fn bytes(
+        &mut self,
+        on: ssa::Value,
+        next: &mir::Branching<ByteChecks>,
+        wc_next: &DecTree<key::DecisionTreeTail>,
+    ) {
+        // Same as for strings, the checks map their own values
+        self.map.pop();
+
+        self.can_skip_continuation = false;
+
+        let reset = self.make_reset();
+
+        for (bytes, next) in &next.branches {
+            let falsely = self.ssa().new_block();
+            self.bytes_branch((on, falsely), (&bytes.checks, next));
+            self.reset(falsely, reset.clone());
+        }
+
+        self.map.push(on);
+        self.next(wc_next);
+    }
+
+    fn bytes_branch(
+        &mut self,
+        (on, falsely): (ssa::Value, Block),
+        (checks, next): (&[ByteCheck], &DecTree<key::DecisionTreeTail>),
+    ) {
+        let buffer = self.f.type_of_value(on).as_key();
+        let [ptr_ty, len_ty] = [0, 1]
+            .map(key::Field)
+            .map(|field| self.f.types()[buffer].as_record()[field].clone());
+
+        let mut cursor = self.ssa().field(on, buffer, key::Field(0), ptr_ty.clone());
+        let mut remaining = self.ssa().field(on, buffer, key::Field(1), len_ty.clone());
+
+        let (_, size_t) = self.f.uint(0);
+
+        let mut read = Vec::with_capacity(checks.len());
+
+        for check in checks {
+            let v = match check {
+                ByteCheck::Int(intsize) => {
+                    let at = cursor;
+                    let n = Value::Int(intsize.bytes() as i128, size_t);
+                    self.advance([&mut cursor, &mut remaining], n, falsely);
+                    self.read_int(at, *intsize)
+                }
+                ByteCheck::Literal(intsize, n) => {
+                    let at = cursor;
+                    let size = Value::Int(intsize.bytes() as i128, size_t);
+                    self.advance([&mut cursor, &mut remaining], size, falsely);
+                    let v = self.read_int(at, *intsize);
+
+                    let eq = self.ssa().eq([v, Value::Int(*n, *intsize)], *intsize);
+                    self.continue_if(eq, falsely);
+
+                    v
+                }
+                ByteCheck::Bytes(len) => {
+                    let len = match len {
+                        ByteLen::Const(n) => Value::Int(*n as i128, size_t),
+                        ByteLen::Check(i) => {
+                            let v = read[*i];
+                            let MonoType::Int(intsize) = self.f.type_of_value(v) else {
+                                panic!("size of segment is not an integer");
+                            };
+                            self.f.int_cast(v, [intsize, size_t])
+                        }
+                    };
+
+                    let at = cursor;
+                    self.advance([&mut cursor, &mut remaining], len, falsely);
+                    self.ssa().construct(vec![at, len], buffer.into())
+                }
+                ByteCheck::Rest => self.ssa().construct(vec![cursor, remaining], buffer.into()),
+            };
+
+            self.map.push(v);
+            read.push(v);
+        }
+
+        // Without a segment taking the remaining bytes, the buffer must've been fully consumed
+        if !matches!(checks.last(), Some(ByteCheck::Rest)) {
+            let consumed = self.ssa().eq([remaining, Value::Int(0, size_t)], size_t);
+            self.continue_if(consumed, falsely);
+        }
+
+        self.next(next);
+    }
+
+    // Bounds check the read of `n` bytes and then move the cursor past them
+    fn advance(&mut self, [cursor, remaining]: [&mut Value; 2], n: Value, falsely: Block) {
+        let (_, size_t) = self.f.uint(0);
+
+        let too_short = self.ssa().lti([*remaining, n], size_t);
+        let ok = self.ssa().not(too_short);
+        self.continue_if(ok, falsely);
+
+        let ptr_ty = self.f.type_of_value(*cursor);
+        *cursor = self.ssa().add(*cursor, n, ptr_ty);
+        *remaining = self.ssa().sub(*remaining, n, MonoType::Int(size_t));
+    }
+
+    // Reads a big-endian integer one byte at a time, so that the buffer doesn't need to be aligned
+    fn read_int(&mut self, at: Value, intsize: IntSize) -> Value {
+        let (_, size_t) = self.f.uint(0);
+        let u8 = IntSize::new(false, 8);
+        let unsigned = IntSize::new(false, intsize.bits());
+        let ty = MonoType::Int(unsigned);
+        let ptr_ty = self.f.type_of_value(at);
+
+        let mut v = self.ssa().deref(at, MonoType::Int(u8));
+
+        if intsize.bytes() > 1 {
+            v = self.ssa().extend(v, false, ty.clone());
+
+            for i in 1..intsize.bytes() {
+                let at = self
+                    .ssa()
+                    .add(at, Value::Int(i as i128, size_t), ptr_ty.clone());
+                let byte = self.ssa().deref(at, MonoType::Int(u8));
+                let byte = self.ssa().extend(byte, false, ty.clone());
+                let shifted = self.ssa().mul(v, Value::Int(256, unsigned), ty.clone());
+                v = self.ssa().add(shifted, byte, ty.clone());
+            }
+        }
+
+        self.f.int_cast(v, [unsigned, intsize])
+    }
+
+    fn continue_if(&mut self, cond: Value, falsely: Block) {
+        let next_check_block = self.ssa().new_block();
+
+        self.ssa()
+            .select(cond, [(next_check_block, vec![]), (falsely, vec![])]);
+
+        self.ssa().switch_to_block(next_check_block);
+    }
+
     fn sum(&mut self, on: Value, sum: M<key::Sum>, params: &[Type], v: &SumBranches) {
         self.can_skip_continuation &= v.branches.len() == 1;
 
         let oblock = self.block();
-        let on_mk = self.f.type_of_value(on).as_key();
+        let on_mk =
+            to_morphization!(self.f.lir, self.f.mir, &mut self.f.current.tmap).sum(sum, params);
+        let niche = self.f.lir.mono.niche_of(on_mk).is_some();
 
-        let (tag_size, _, _) = self.f.types()[on_mk].as_sum();
-        let tag = self.ssa().tag_of(on, tag_size);
+        let tag = if niche {
+            self.niche_tag(on, sum)
+        } else {
+            let (tag_size, _, _) = self.f.types()[on_mk].as_sum();
+            self.ssa().tag_of(on, tag_size)
+        };
 
         assert!(
             v.branches
@@ -562,14 +751,18 @@ impl<'f, 'v, 'a> PatLower<'f, 'v, 'a> {
                     to_morphization!(self.f.lir, self.f.mir, &mut self.f.current.tmap).apply(&ty)
                 })
                 .collect();
-            let param_tuple = self.f.lir.mono.get_or_make_tuple(param_types.clone());
-
-            let params = self.ssa().cast_payload(on, param_tuple.into());
-            let params = (0..param_types.len() as u32)
-                .map(key::Field)
-                .zip(param_types)
-                .map(|(field, ty)| self.ssa().field(params, param_tuple, field, ty))
-                .collect();
+            let params = if niche {
+                // The only parameter of the non-empty variant is the pointer itself
+                param_types.iter().map(|_| on).collect()
+            } else {
+                let param_tuple = self.f.lir.mono.get_or_make_tuple(param_types.clone());
+                let params = self.ssa().cast_payload(on, param_tuple.into());
+                (0..param_types.len() as u32)
+                    .map(key::Field)
+                    .zip(param_types)
+                    .map(|(field, ty)| self.ssa().field(params, param_tuple, field, ty))
+                    .collect()
+            };
 
             self.constructors.push(params);
 
@@ -578,6 +771,19 @@ impl<'f, 'v, 'a> PatLower<'f, 'v, 'a> {
         }
     }
 
+    // The variant of a sum represented as a nullable pointer, see [`mono::Monomorphization::sum`]
+    fn niche_tag(&mut self, on: Value, sum: M<key::Sum>) -> Value {
+        let size_t = self.f.types().size_t();
+        let ptr = self.ssa().transmute(on, MonoType::Int(size_t));
+        let null = Value::Int(0, size_t);
+
+        if self.f.mir.variant_types[sum][key::Variant(0)].is_empty() {
+            self.ssa().gti([ptr, null], size_t)
+        } else {
+            self.ssa().eq([ptr, null], size_t)
+        }
+    }
+
     pub fn get_continuation(&mut self, ty: MonoType) -> Block {
         match self.continuation_block {
             Some((block, _)) => block,
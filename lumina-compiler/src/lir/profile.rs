@@ -0,0 +1,185 @@
+//! Profile-guided optimisation
+//!
+//! Binaries built with `--profile-generate` count how often each function is called and which way
+//! each `Select` goes, and write the counters to a file when exiting. The file starts with a
+//! header naming every counter on its own line, followed by the counters themselves as
+//! little-endian 64-bit integers.
+//!
+//! Branches are identified by their order among the selects of a function before optimisations,
+//! so the LIR of instrumented builds is left unoptimised and profiles are applied before
+//! optimising.
+
+use super::{Entry, Function, MonoFunc, SSA, V};
+use lumina_collections::Map;
+use std::collections::HashMap;
+use std::fmt;
+
+/// File the instrumented binary writes its profile to, relative to its working directory
+pub const DEFAULT_PATH: &str = "lumina.profile";
+
+const MAGIC: &str = "lumina-profile 1";
+
+/// A branch taken this many times less often than the other is considered cold
+const COLD_RATIO: u64 = 16;
+
+/// Whether the program is instrumented to record a profile, or optimised with one
+#[derive(Default)]
+pub enum Profiling {
+    #[default]
+    None,
+    Generate,
+    Use(Profile),
+}
+
+/// Indices of the counters of an instrumented program
+pub struct Counters {
+    pub functions: Map<MonoFunc, u32>,
+    // The counter of the `on_true` branch, directly followed by the one for `on_false`
+    pub branches: HashMap<(MonoFunc, V), u32>,
+    names: Vec<String>,
+}
+
+impl Counters {
+    pub fn new(functions: &Map<MonoFunc, Function>) -> Self {
+        let mut names = vec![];
+        let mut branches = HashMap::new();
+
+        let functions = functions
+            .iter()
+            .map(|(mfunc, func)| {
+                let counter = names.len() as u32;
+                names.push(format!("func {}", func.symbol));
+
+                for (i, v) in selects(&func.ssa).enumerate() {
+                    branches.insert((mfunc, v), names.len() as u32);
+                    names.push(format!("branch {} {i} true", func.symbol));
+                    names.push(format!("branch {} {i} false", func.symbol));
+                }
+
+                counter
+            })
+            .collect();
+
+        Counters { functions, branches, names }
+    }
+
+    pub fn count(&self) -> usize {
+        self.names.len()
+    }
+
+    /// The header the instrumented binary writes before its counters
+    pub fn header(&self) -> Vec<u8> {
+        let mut header = format!("{MAGIC}\n{}\n", self.names.len());
+        for name in &self.names {
+            header.push_str(name);
+            header.push('\n');
+        }
+        header.into_bytes()
+    }
+}
+
+/// Counters read from a profile written by an instrumented binary
+#[derive(Default)]
+pub struct Profile {
+    functions: HashMap<String, u64>,
+    branches: HashMap<(String, usize), [u64; 2]>,
+}
+
+#[derive(Debug)]
+pub struct Error(&'static str);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "malformed profile: {}", self.0)
+    }
+}
+
+impl Profile {
+    pub fn parse(bytes: &[u8]) -> Result<Profile, Error> {
+        let mut rest = bytes;
+        let mut line = || {
+            let current = rest;
+            let end = current
+                .iter()
+                .position(|&b| b == b'\n')
+                .ok_or(Error("unexpected end of header"))?;
+            rest = &current[end + 1..];
+            std::str::from_utf8(&current[..end]).map_err(|_| Error("invalid header"))
+        };
+
+        if line()? != MAGIC {
+            return Err(Error("not a lumina profile"));
+        }
+
+        let len: usize = line()?
+            .parse()
+            .map_err(|_| Error("invalid counter count"))?;
+        let names = (0..len).map(|_| line()).collect::<Result<Vec<_>, _>>()?;
+
+        if rest.len() != len * 8 {
+            return Err(Error("counters do not match the header"));
+        }
+
+        let counts = rest
+            .chunks_exact(8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()));
+
+        let mut profile = Profile::default();
+
+        for (name, count) in names.into_iter().zip(counts) {
+            // Symbols are split from the right, in case they contain spaces
+            if let Some(symbol) = name.strip_prefix("func ") {
+                profile.functions.insert(symbol.to_string(), count);
+                continue;
+            }
+
+            let Some(branch) = name.strip_prefix("branch ") else {
+                return Err(Error("invalid counter name"));
+            };
+            let [taken, i, symbol] = branch.rsplitn(3, ' ').collect::<Vec<_>>()[..] else {
+                return Err(Error("invalid counter name"));
+            };
+
+            let i = i.parse().map_err(|_| Error("invalid branch index"))?;
+            let counts = profile.branches.entry((symbol.to_string(), i)).or_default();
+            match taken {
+                "true" => counts[0] = count,
+                "false" => counts[1] = count,
+                _ => return Err(Error("invalid branch direction")),
+            }
+        }
+
+        Ok(profile)
+    }
+
+    /// Record the call counts of functions and mark branches which are rarely taken as cold
+    pub(super) fn apply(&self, functions: &mut Map<MonoFunc, Function>) {
+        for func in functions.values_mut() {
+            let symbol = func.symbol.as_str();
+            func.calls = self.functions.get(symbol).copied();
+
+            let selects = selects(&func.ssa).collect::<Vec<_>>();
+            for (i, v) in selects.into_iter().enumerate() {
+                let Some(&[on_true, on_false]) = self.branches.get(&(symbol.to_string(), i)) else {
+                    continue;
+                };
+
+                let Entry::Select { on_true: t, on_false: f, .. } = func.ssa.entry_of(v) else {
+                    unreachable!();
+                };
+                let (t, f) = (t.id, f.id);
+
+                if on_true.saturating_mul(COLD_RATIO) < on_false {
+                    func.ssa.set_cold(t);
+                } else if on_false.saturating_mul(COLD_RATIO) < on_true {
+                    func.ssa.set_cold(f);
+                }
+            }
+        }
+    }
+}
+
+fn selects(ssa: &SSA) -> impl Iterator<Item = V> + '_ {
+    ssa.iterv()
+        .filter(|&v| matches!(ssa.entry_of(v), Entry::Select { .. }))
+}
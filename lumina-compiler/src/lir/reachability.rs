@@ -0,0 +1,89 @@
+//! Dead function elimination
+//!
+//! Monomorphisation lowers every function instantiation it comes across, and inlining leaves
+//! behind functions which no longer have any callers. Only functions reachable from the entry
+//! points of the program are kept, so that the backends don't declare and define the others.
+
+use super::{MonoFunc, Output};
+use crate::prelude::*;
+
+impl Output {
+    pub(super) fn remove_unreachable_functions(&mut self) {
+        let mut reachable: Map<MonoFunc, bool> = self.functions.secondary_with(|_, func| {
+            // Exported functions and functions placed in a section may be used by the linker or
+            // by foreign code
+            func.linkage.visibility == ast::SymbolVisibility::Default
+                || func.linkage.section.is_some()
+                || func.export.is_some()
+        });
+
+        let mut queue = [self.main, self.sys_init, self.alloc, self.dealloc]
+            .into_iter()
+            .chain(self.val_initializers.values().copied())
+            .chain(self.tests.iter().map(|test| test.func))
+            .chain(
+                self.tests
+                    .iter()
+                    .flat_map(|test| test.generators.iter().flatten().copied()),
+            )
+            .chain(self.functions.keys().filter(|&mfunc| reachable[mfunc]))
+            .collect::<Vec<_>>();
+
+        for &mfunc in &queue {
+            reachable[mfunc] = true;
+        }
+
+        while let Some(mfunc) = queue.pop() {
+            self.functions[mfunc]
+                .ssa
+                .for_each_func_mut(&mut |&mut called| {
+                    if !reachable[called] {
+                        reachable[called] = true;
+                        queue.push(called);
+                    }
+                });
+        }
+
+        let removed = reachable.values().filter(|&&kept| !kept).count();
+        if removed == 0 {
+            return;
+        }
+
+        info!("removing {removed} unreachable functions");
+
+        let mut new_keys = Map::<MonoFunc, Option<MonoFunc>>::new();
+        let mut functions = Map::with_capacity(self.functions.len() - removed);
+        for (mfunc, func) in std::mem::take(&mut self.functions) {
+            if reachable[mfunc] {
+                new_keys.push(Some(functions.push(func)));
+            } else {
+                trace!("removing unreachable function {}", func.symbol);
+                new_keys.push(None);
+            }
+        }
+
+        let remap = |mfunc: &mut MonoFunc| {
+            *mfunc = new_keys[*mfunc].expect("reference to unreachable function");
+        };
+
+        for func in functions.values_mut() {
+            func.ssa.for_each_func_mut(&mut |mfunc| remap(mfunc));
+        }
+
+        for mfunc in [
+            &mut self.main,
+            &mut self.sys_init,
+            &mut self.alloc,
+            &mut self.dealloc,
+        ] {
+            remap(mfunc);
+        }
+        self.val_initializers.values_mut().for_each(remap);
+        for test in self.tests.iter_mut() {
+            remap(&mut test.func);
+            test.generators.iter_mut().flatten().for_each(remap);
+        }
+
+        self.functions = functions;
+    }
+}
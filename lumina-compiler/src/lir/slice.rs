@@ -0,0 +1,95 @@
+//! Lowering of the slice builtins
+//!
+//! A slice `[a; ..]` is monomorphised as the tuple `(*a, uint)` of a pointer to its first element
+//! and its length, so the backends only ever see an ordinary record. Indexing and sub-slicing trap
+//! with `TRAP_OUT_OF_BOUNDS` instead of reading past the end.
+
+use super::{FuncLower, MonoType, Value, TRAP_OUT_OF_BOUNDS};
+use crate::prelude::*;
+
+impl<'a> FuncLower<'a> {
+    pub fn slice_op(&mut self, op: &str, params: Vec<Value>) -> Value {
+        let (_, size_t) = self.uint(0);
+
+        match (op, params.as_slice()) {
+            ("from_raw", &[ptr, len]) => {
+                let inner = self.type_of_value(ptr).deref();
+                self.construct_slice(inner, ptr, len)
+            }
+            ("from_array", &[ptr]) => {
+                let MonoType::Array(len, inner) = self.type_of_value(ptr).deref() else {
+                    panic!("not a pointer to an array: {ptr}");
+                };
+                let (len, _) = self.uint(len as i128);
+                let ptr = self
+                    .ssa()
+                    .transmute(ptr, MonoType::pointer((*inner).clone()));
+                self.construct_slice(*inner, ptr, len)
+            }
+            ("ptr", &[slice]) => self.slice_fields(slice).0,
+            ("len", &[slice]) => self.slice_fields(slice).1,
+            ("get", &[i, slice]) => {
+                let (ptr, len) = self.slice_fields(slice);
+                let in_bounds = self.ssa().lti([i, len], size_t);
+                self.bounds_check(in_bounds);
+
+                let inner = self.type_of_value(ptr).deref();
+                let at = self.element(ptr, i, inner.clone());
+                self.ssa().deref(at, inner)
+            }
+            ("sub", &[start, end, slice]) => {
+                let (ptr, len) = self.slice_fields(slice);
+                for bounds in [[start, end], [end, len]] {
+                    let out_of_bounds = self.ssa().gti(bounds, size_t);
+                    let in_bounds = self.ssa().not(out_of_bounds);
+                    self.bounds_check(in_bounds);
+                }
+
+                let inner = self.type_of_value(ptr).deref();
+                let ptr = self.element(ptr, start, inner.clone());
+                let len = self.ssa().sub(end, start, MonoType::Int(size_t));
+                self.construct_slice(inner, ptr, len)
+            }
+            _ => panic!("unknown slice builtin: {op}"),
+        }
+    }
+
+    fn construct_slice(&mut self, inner: MonoType, ptr: Value, len: Value) -> Value {
+        let (_, size_t) = self.uint(0);
+        let ty = self
+            .lir
+            .mono
+            .get_or_make_tuple(vec![MonoType::pointer(inner), MonoType::Int(size_t)]);
+        self.ssa().construct(vec![ptr, len], ty.into())
+    }
+
+    fn slice_fields(&mut self, slice: Value) -> (Value, Value) {
+        let key = self.type_of_value(slice).as_key();
+        let [ptr, len] = [0, 1].map(|i| {
+            let field = key::Field(i);
+            let fty = self.types()[key].as_record()[field].clone();
+            self.ssa().field(slice, key, field, fty)
+        });
+        (ptr, len)
+    }
+
+    // Pointer to the `i`th element
+    fn element(&mut self, ptr: Value, i: Value, inner: MonoType) -> Value {
+        let (_, size_t) = self.uint(0);
+        let size = self.ssa().size_of(inner, size_t);
+        let offset = self.ssa().mul(i, size, MonoType::Int(size_t));
+        let ty = self.type_of_value(ptr);
+        self.ssa().add(ptr, offset, ty)
+    }
+
+    fn bounds_check(&mut self, in_bounds: Value) {
+        let [ok, out_of_bounds] = [(); 2].map(|_| self.ssa().new_block());
+        self.ssa()
+            .select(in_bounds, [(ok, vec![]), (out_of_bounds, vec![])]);
+
+        self.ssa().switch_to_block(out_of_bounds);
+        self.ssa().trap(TRAP_OUT_OF_BOUNDS);
+
+        self.ssa().switch_to_block(ok);
+    }
+}
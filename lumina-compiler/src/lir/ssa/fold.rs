@@ -0,0 +1,142 @@
+//! Constant folding and copy propagation
+//!
+//! Arithmetic and comparisons on constant integers are computed at compile time, and transmutes to
+//! the type a value already has and selects on known conditions are replaced by the value itself.
+//! Uses of the folded values are substituted and the folded entries removed.
+
+use super::rewrite::for_entry_mut;
+use super::*;
+use std::collections::HashMap;
+
+impl SSA {
+    /// Fold constant arithmetic and propagate copies, returning whether anything changed
+    pub fn fold_constants(&mut self) -> bool {
+        let mut folded = HashMap::new();
+
+        for v in self.iterv() {
+            if let Some(value) = self.fold(v, &folded) {
+                trace!("folding {v} = {} into {value}", self.ventries[v]);
+                folded.insert(v, value);
+            }
+        }
+
+        if folded.is_empty() {
+            return false;
+        }
+
+        // Values are folded in the order they're placed in, which isn't always the order they're
+        // defined in, so a folded value may still refer to another folded value
+        let resolve = |mut value: Value| {
+            while let Value::V(v) = value {
+                match folded.get(&v) {
+                    Some(&to) => value = to,
+                    None => break,
+                }
+            }
+            value
+        };
+
+        for entry in self.ventries.values_mut() {
+            for_entry_mut(entry, &mut |v| resolve(Value::V(v)), &mut |b| b);
+        }
+
+        self.remove_unused_values(|v| folded.contains_key(&v));
+
+        true
+    }
+
+    fn fold(&self, v: V, folded: &HashMap<V, Value>) -> Option<Value> {
+        let resolve = |value: &Value| match value {
+            Value::V(v) => folded.get(v).copied().unwrap_or(*value),
+            _ => *value,
+        };
+
+        match &self.ventries[v] {
+            Entry::Transmute(of) => {
+                let of = resolve(of);
+                let ty = match of {
+                    Value::V(of) => self.vtypes[of].clone(),
+                    Value::Int(_, intsize) => MonoType::Int(intsize),
+                    _ => return None,
+                };
+                (ty == self.vtypes[v]).then_some(of)
+            }
+            Entry::BinOp(op, [lhs, rhs]) => {
+                // Checked arithmetic returns a record of the result and whether it overflowed
+                let MonoType::Int(intsize) = self.vtypes[v] else {
+                    return None;
+                };
+                let (Value::Int(l, _), Value::Int(r, _)) = (resolve(lhs), resolve(rhs)) else {
+                    return None;
+                };
+                // Division depends on the signedness, so reinterpreted constants are read the
+                // same way the backends read them
+                let (l, r) = (wrap(l, intsize), wrap(r, intsize));
+
+                let n = match op {
+                    BinOp::Add => l.wrapping_add(r),
+                    BinOp::Sub => l.wrapping_sub(r),
+                    BinOp::Mul => l.wrapping_mul(r),
+                    // Division by zero and overflowing division trap at runtime
+                    BinOp::Div => l.checked_div(r).filter(|&n| wrap(n, intsize) == n)?,
+                    BinOp::Rem => l.checked_rem(r)?,
+                    BinOp::And => l & r,
+                };
+
+                Some(Value::Int(wrap(n, intsize), intsize))
+            }
+            Entry::IntCmpInclusive([lhs, rhs], ord, intsize) => {
+                let (Value::Int(l, _), Value::Int(r, _)) = (resolve(lhs), resolve(rhs)) else {
+                    return None;
+                };
+                // Constants may have been reinterpreted from the other signedness, so they're
+                // compared the same way the backends compare them
+                let (l, r) = (wrap(l, *intsize), wrap(r, *intsize));
+                Some(Value::bool(l.cmp(&r) == *ord))
+            }
+            Entry::IntMin([lhs, rhs], intsize) | Entry::IntMax([lhs, rhs], intsize) => {
+                let (Value::Int(l, _), Value::Int(r, _)) = (resolve(lhs), resolve(rhs)) else {
+                    return None;
+                };
+                let intsize = *intsize;
+                let (l, r) = (wrap(l, intsize), wrap(r, intsize));
+                let n = match self.ventries[v] {
+                    Entry::IntMin(..) => l.min(r),
+                    _ => l.max(r),
+                };
+                Some(Value::Int(n, intsize))
+            }
+            Entry::SelectValue([cond, on_true, on_false]) => match resolve(cond) {
+                Value::Int(n, _) => Some(resolve(if n != 0 { on_true } else { on_false })),
+                _ if resolve(on_true) == resolve(on_false) => Some(resolve(on_true)),
+                _ => None,
+            },
+            Entry::Reduce(of) => {
+                let (Value::Int(n, _), MonoType::Int(to)) = (resolve(of), &self.vtypes[v]) else {
+                    return None;
+                };
+                Some(Value::Int(wrap(n, *to), *to))
+            }
+            Entry::ExtendSigned(of) | Entry::ExtendUnsigned(of) => {
+                let (Value::Int(n, from), MonoType::Int(to)) = (resolve(of), &self.vtypes[v])
+                else {
+                    return None;
+                };
+                let signed = matches!(self.ventries[v], Entry::ExtendSigned(_));
+                let n = wrap(n, IntSize::new(signed, from.bits()));
+                Some(Value::Int(wrap(n, *to), *to))
+            }
+            _ => None,
+        }
+    }
+}
+
+// Truncate to the size of the integer, sign extending signed integers
+fn wrap(n: i128, intsize: IntSize) -> i128 {
+    let shift = 128 - intsize.bits() as u32;
+    if intsize.signed {
+        (n << shift) >> shift
+    } else {
+        ((n as u128) << shift >> shift) as i128
+    }
+}
@@ -1,3 +1,4 @@
+use super::bytecode::{self, Decode, Encode, Reader};
 use super::{
     mono::MonoFormatter, Function, MonoFunc, MonoType, MonoTypeKey, TRAP_UNREACHABLE, UNIT,
 };
@@ -8,12 +9,13 @@ use itertools::Itertools;
 use key::{Map, M};
 use lumina_collections::{map_key_impl, KeysIter};
 use lumina_key as key;
-use lumina_typesystem::IntSize;
-use lumina_util::{Highlighting, ParamFmt};
+use lumina_typesystem::{FloatSize, IntSize};
+use lumina_util::{Highlighting, ParamFmt, Span};
 use owo_colors::OwoColorize;
 use std::fmt;
 use tracing::{info, trace};
 
+mod fold;
 mod opts;
 mod rewrite;
 
@@ -25,26 +27,39 @@ map_key_impl!(Block(u32), "block");
 pub struct V(pub u32);
 map_key_impl!(V(u32), "v");
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct BasicBlock {
     start: V,
     predecessors: u16,
+    // Rarely executed, so that backends can move it away from the hot path
+    cold: bool,
     // parameters: u32,
     // flow: ControlFlow,
 }
 
 impl BasicBlock {
     pub fn new() -> BasicBlock {
-        Self { start: V(u32::MAX), predecessors: 0 }
+        Self { start: V(u32::MAX), predecessors: 0, cold: false }
     }
 }
 
 #[derive(Clone)]
 pub struct SSA {
     current: Block,
+    location: Option<Location>,
     blocks: Map<Block, BasicBlock>,
     ventries: Map<V, Entry>,
     vtypes: Map<V, MonoType>,
+    vlocs: Map<V, Option<Location>>,
+}
+
+/// The source code a value was lowered from, used for debug info
+///
+/// The module is kept since values may be inlined into functions of other modules.
+#[derive(Clone, Copy, Debug)]
+pub struct Location {
+    pub module: key::Module,
+    pub span: Span,
 }
 
 /// Information retrieved with the `SSA::block_info` method.
@@ -64,6 +79,55 @@ impl<'a> BlockInfo<'a> {
     }
 }
 
+impl Encode for SSA {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let blocks = self
+            .blocks
+            .values()
+            .map(|block| (block.start, (block.predecessors, block.cold)));
+        blocks.collect::<Vec<_>>().encode(buf);
+        self.ventries.encode(buf);
+        self.vtypes.encode(buf);
+        self.vlocs.encode(buf);
+    }
+}
+
+impl Decode for SSA {
+    fn decode(r: &mut Reader) -> Result<Self, bytecode::Error> {
+        let blocks = Vec::<(V, (u16, bool))>::decode(r)?
+            .into_iter()
+            .map(|(start, (predecessors, cold))| BasicBlock { start, predecessors, cold })
+            .collect();
+        let ventries = Map::<V, Entry>::decode(r)?;
+        let vtypes = Map::<V, MonoType>::decode(r)?;
+        let vlocs = Map::<V, Option<Location>>::decode(r)?;
+        if ventries.len() != vtypes.len() || ventries.len() != vlocs.len() {
+            return Err(bytecode::Error::Invalid("function body"));
+        }
+        Ok(SSA {
+            current: Block::entry(),
+            location: None,
+            blocks,
+            ventries,
+            vtypes,
+            vlocs,
+        })
+    }
+}
+
+impl Encode for Location {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.module, (self.span.indice, self.span.length)).encode(buf)
+    }
+}
+
+impl Decode for Location {
+    fn decode(r: &mut Reader) -> Result<Self, bytecode::Error> {
+        let (module, (indice, length)) = Decode::decode(r)?;
+        Ok(Location { module, span: Span::new(indice, length) })
+    }
+}
+
 impl SSA {
     pub fn new() -> Self {
         let mut entry = BasicBlock::new();
@@ -71,9 +135,11 @@ impl SSA {
 
         SSA {
             current: Block::entry(),
+            location: None,
             blocks: [entry].into(),
             ventries: Map::new(),
             vtypes: Map::new(),
+            vlocs: Map::new(),
         }
     }
 
@@ -102,6 +168,18 @@ impl SSA {
         self.blocks.keys()
     }
 
+    /// The first value of the block, or `None` if nothing was ever added to it
+    pub fn block_start(&self, block: Block) -> Option<V> {
+        self.blocks
+            .has(block)
+            .then(|| self.blocks[block].start)
+            .filter(|start| *start != V(u32::MAX))
+    }
+
+    pub fn has_value(&self, v: V) -> bool {
+        self.ventries.has(v)
+    }
+
     pub fn block_info(&self, block: Block) -> BlockInfo<'_> {
         let start = self.blocks[block].start;
 
@@ -150,6 +228,11 @@ impl SSA {
             .sum()
     }
 
+    /// Calls `f` for every value used by the entry of `v`
+    pub fn for_each_use(&self, v: V, f: &mut dyn FnMut(V)) {
+        rewrite::for_entry(&self.ventries[v], f)
+    }
+
     pub fn get_block_param(&self, block: Block, i: u32) -> V {
         let start = self.blocks[block].start;
         assert_ne!(start, V(u32::MAX));
@@ -189,14 +272,47 @@ impl SSA {
         self.param_types(Block::entry())
     }
 
+    /// A body consisting of only the parameters of the function
+    pub fn signature(&self) -> SSA {
+        let mut ssa = SSA::new();
+        ssa.blocks[Block::entry()].start = V(0);
+        for ty in self.func_param_types() {
+            ssa.add_block_param(Block::entry(), ty.clone());
+        }
+        ssa
+    }
+
+    /// A body which traps as soon as the function is called
+    pub fn stub(&self) -> SSA {
+        let mut ssa = self.signature();
+        let code = cranelift_codegen::ir::TrapCode::user(TRAP_UNREACHABLE).unwrap();
+        ssa.assign(Entry::Trap(code), MonoType::unit());
+        ssa
+    }
+
     pub fn iterv(&self) -> impl Iterator<Item = V> + 'static {
         self.ventries.keys()
     }
 
+    /// Whether both functions would generate the same code, ignoring debug info
+    pub fn same_code(&self, other: &SSA) -> bool {
+        self.blocks == other.blocks
+            && self.vtypes == other.vtypes
+            && self.ventries == other.ventries
+    }
+
     pub fn predecessors(&self, block: Block) -> u16 {
         self.blocks[block].predecessors
     }
 
+    pub fn is_cold(&self, block: Block) -> bool {
+        self.blocks[block].cold
+    }
+
+    pub fn set_cold(&mut self, block: Block) {
+        self.blocks[block].cold = true;
+    }
+
     pub fn new_block(&mut self) -> Block {
         debug_assert_ne!(self.blocks.len(), 0);
         self.blocks.push(BasicBlock::new())
@@ -255,6 +371,7 @@ impl SSA {
 
         let v = self.ventries.push(entry);
         assert_eq!(self.vtypes.push(ty), v);
+        assert_eq!(self.vlocs.push(self.location), v);
 
         v.value()
     }
@@ -265,6 +382,22 @@ impl SSA {
     pub fn entry_of(&self, v: V) -> &Entry {
         &self.ventries[v]
     }
+    pub fn entry_of_mut(&mut self, v: V) -> &mut Entry {
+        &mut self.ventries[v]
+    }
+    pub fn location_of(&self, v: V) -> Option<Location> {
+        self.vlocs[v]
+    }
+
+    /// Set the source location of added values, returning the previous one
+    pub fn set_location(&mut self, location: Option<Location>) -> Option<Location> {
+        std::mem::replace(&mut self.location, location)
+    }
+
+    /// The first source location of the function, used as the location of the function itself
+    pub fn first_location(&self) -> Option<Location> {
+        self.vlocs.values().find_map(|loc| *loc)
+    }
 
     /// Perform a change to a block without switching to it
     pub fn in_block<T>(&mut self, block: Block, perform: impl FnOnce(&mut Self) -> T) -> T {
@@ -285,12 +418,52 @@ impl SSA {
         self.assign(entry, ty)
     }
 
+    pub fn atomic_load(&mut self, ptr: Value, ordering: AtomicOrdering, ty: MonoType) -> Value {
+        let entry = Entry::AtomicLoad(ptr, ordering);
+        self.assign(entry, ty)
+    }
+
+    pub fn atomic_store(&mut self, ptr: Value, value: Value, ordering: AtomicOrdering) -> Value {
+        let entry = Entry::AtomicStore { ptr, value, ordering };
+        let ty = MonoType::Monomorphised(UNIT);
+        self.assign(entry, ty)
+    }
+
+    /// Returns the previous value, which equals `expected` if `replacement` was written
+    pub fn atomic_cas(
+        &mut self,
+        [ptr, expected, replacement]: [Value; 3],
+        ordering: AtomicOrdering,
+        ty: MonoType,
+    ) -> Value {
+        let entry = Entry::AtomicCas([ptr, expected, replacement], ordering);
+        self.assign(entry, ty)
+    }
+
+    /// Returns the previous value
+    pub fn atomic_rmw(
+        &mut self,
+        op: AtomicRmwOp,
+        [ptr, value]: [Value; 2],
+        ordering: AtomicOrdering,
+        ty: MonoType,
+    ) -> Value {
+        let entry = Entry::AtomicRmw(op, [ptr, value], ordering);
+        self.assign(entry, ty)
+    }
+
     pub fn memcpy(&mut self, dst: Value, src: Value, count: Value) -> Value {
         let entry = Entry::MemCpy { dst, src, count };
         let ty = MonoType::Monomorphised(UNIT);
         self.assign(entry, ty)
     }
 
+    pub fn memset(&mut self, dst: Value, byte: Value, count: Value) -> Value {
+        let entry = Entry::MemSet { dst, byte, count };
+        let ty = MonoType::Monomorphised(UNIT);
+        self.assign(entry, ty)
+    }
+
     pub fn transmute(&mut self, v: Value, to: MonoType) -> Value {
         let entry = Entry::Transmute(v);
         self.assign(entry, to)
@@ -331,6 +504,19 @@ impl SSA {
         self.assign(entry, MonoType::Monomorphised(ty))
     }
 
+    /// Construct a variant of a sum represented as a nullable pointer
+    pub fn niche_variant(
+        &mut self,
+        params: Vec<Value>,
+        pointer: MonoType,
+        size_t: IntSize,
+    ) -> Value {
+        match params[..] {
+            [payload] => payload,
+            _ => self.transmute(Value::Int(0, size_t), pointer),
+        }
+    }
+
     pub fn tag_of(&mut self, sum: Value, tagsize: IntSize) -> Value {
         let entry = Entry::TagFromSum { of: sum };
         self.assign(entry, MonoType::Int(tagsize))
@@ -349,17 +535,21 @@ impl SSA {
         self.assign(entry, ty)
     }
 
-    pub fn int_to_float(&mut self, value: Value, intsize: IntSize) -> Value {
+    pub fn int_to_float(&mut self, value: Value, intsize: IntSize, to: FloatSize) -> Value {
         let entry = Entry::IntToFloat(value, intsize);
-        self.assign(entry, MonoType::Float)
+        self.assign(entry, MonoType::Float(to))
     }
     pub fn float_to_int(&mut self, value: Value, intsize: IntSize) -> Value {
         let entry = Entry::FloatToInt(value, intsize);
         self.assign(entry, MonoType::Int(intsize))
     }
+    pub fn float_cast(&mut self, value: Value, to: FloatSize) -> Value {
+        let entry = Entry::FloatCast(value);
+        self.assign(entry, MonoType::Float(to))
+    }
 
-    pub fn cmp(&mut self, v: [Value; 2], ord: std::cmp::Ordering, bitsize: IntSize) -> Value {
-        let entry = Entry::IntCmpInclusive(v, ord, bitsize);
+    pub fn cmp(&mut self, v: [Value; 2], ord: std::cmp::Ordering, intsize: IntSize) -> Value {
+        let entry = Entry::IntCmpInclusive(v, ord, intsize);
         let ty = MonoType::bool();
         self.assign(entry, ty)
     }
@@ -368,14 +558,14 @@ impl SSA {
         let ty = MonoType::bool();
         self.assign(entry, ty)
     }
-    pub fn eq(&mut self, v: [Value; 2], bitsize: IntSize) -> Value {
-        self.cmp(v, std::cmp::Ordering::Equal, bitsize)
+    pub fn eq(&mut self, v: [Value; 2], intsize: IntSize) -> Value {
+        self.cmp(v, std::cmp::Ordering::Equal, intsize)
     }
-    pub fn lti(&mut self, v: [Value; 2], bitsize: IntSize) -> Value {
-        self.cmp(v, std::cmp::Ordering::Less, bitsize)
+    pub fn lti(&mut self, v: [Value; 2], intsize: IntSize) -> Value {
+        self.cmp(v, std::cmp::Ordering::Less, intsize)
     }
-    pub fn gti(&mut self, v: [Value; 2], bitsize: IntSize) -> Value {
-        self.cmp(v, std::cmp::Ordering::Greater, bitsize)
+    pub fn gti(&mut self, v: [Value; 2], intsize: IntSize) -> Value {
+        self.cmp(v, std::cmp::Ordering::Greater, intsize)
     }
 
     // return type overloaded numeric operations
@@ -395,10 +585,64 @@ impl SSA {
         let entry = Entry::BinOp(BinOp::Div, [v, by]);
         self.assign(entry, ty)
     }
+    pub fn rem(&mut self, v: Value, by: Value, ty: MonoType) -> Value {
+        let entry = Entry::BinOp(BinOp::Rem, [v, by]);
+        self.assign(entry, ty)
+    }
     pub fn abs(&mut self, v: Value, ty: MonoType) -> Value {
         let entry = Entry::IntAbs(v);
         self.assign(entry, ty)
     }
+    pub fn fadd(&mut self, v: Value, by: Value, size: FloatSize) -> Value {
+        let entry = Entry::FloatAdd([v, by]);
+        self.assign(entry, MonoType::Float(size))
+    }
+    pub fn fsub(&mut self, v: Value, by: Value, size: FloatSize) -> Value {
+        let entry = Entry::FloatSub([v, by]);
+        self.assign(entry, MonoType::Float(size))
+    }
+    pub fn fmul(&mut self, v: Value, by: Value, size: FloatSize) -> Value {
+        let entry = Entry::FloatMul([v, by]);
+        self.assign(entry, MonoType::Float(size))
+    }
+    pub fn fdiv(&mut self, v: Value, by: Value, size: FloatSize) -> Value {
+        let entry = Entry::FloatDiv([v, by]);
+        self.assign(entry, MonoType::Float(size))
+    }
+    pub fn fneg(&mut self, v: Value, size: FloatSize) -> Value {
+        let entry = Entry::FloatNeg(v);
+        self.assign(entry, MonoType::Float(size))
+    }
+    pub fn fcmp(&mut self, v: [Value; 2], ord: std::cmp::Ordering) -> Value {
+        let entry = Entry::FloatCmp(v, ord);
+        self.assign(entry, MonoType::bool())
+    }
+    pub fn add_carry(&mut self, v: Value, by: Value, carry: Value, ty: MonoType) -> Value {
+        let entry = Entry::IntAddCarry([v, by, carry]);
+        self.assign(entry, ty)
+    }
+    pub fn sub_borrow(&mut self, v: Value, by: Value, borrow: Value, ty: MonoType) -> Value {
+        let entry = Entry::IntSubBorrow([v, by, borrow]);
+        self.assign(entry, ty)
+    }
+    pub fn min(&mut self, v: [Value; 2], intsize: IntSize) -> Value {
+        let entry = Entry::IntMin(v, intsize);
+        self.assign(entry, MonoType::Int(intsize))
+    }
+    pub fn max(&mut self, v: [Value; 2], intsize: IntSize) -> Value {
+        let entry = Entry::IntMax(v, intsize);
+        self.assign(entry, MonoType::Int(intsize))
+    }
+    pub fn select_value(
+        &mut self,
+        cond: Value,
+        on_true: Value,
+        on_false: Value,
+        ty: MonoType,
+    ) -> Value {
+        let entry = Entry::SelectValue([cond, on_true, on_false]);
+        self.assign(entry, ty)
+    }
 
     pub fn field(&mut self, of: Value, key: MonoTypeKey, field: key::Field, ty: MonoType) -> Value {
         let entry = Entry::Field { of, key, field };
@@ -455,6 +699,11 @@ impl SSA {
         self.assign(entry, ty)
     }
 
+    pub fn extern_data_ref(&mut self, key: M<key::Func>, ty: MonoType) -> Value {
+        let entry = Entry::RefExternData(key);
+        self.assign(entry, ty)
+    }
+
     pub fn val_to_ref(&mut self, val: M<key::Val>, ty: MonoType) -> Value {
         let entry = Entry::RefStaticVal(val);
         let ty = MonoType::Pointer(Box::new(ty));
@@ -472,6 +721,10 @@ impl SSA {
         self.assign(entry, MonoType::unit())
     }
 
+    pub fn breakpoint(&mut self) -> Value {
+        self.assign(Entry::Breakpoint, MonoType::unit())
+    }
+
     pub fn unreachable(&mut self, ty: MonoType) -> Value {
         let and_then = self.new_block();
         let entry = Entry::Trap(cranelift_codegen::ir::TrapCode::user(TRAP_UNREACHABLE).unwrap());
@@ -481,6 +734,11 @@ impl SSA {
         v.value()
     }
 
+    pub fn trap(&mut self, code: u8) -> Value {
+        let entry = Entry::Trap(cranelift_codegen::ir::TrapCode::user(code).unwrap());
+        self.assign(entry, MonoType::unit())
+    }
+
     pub fn return_(&mut self, value: Value) -> Value {
         let entry = Entry::Return(value);
         self.assign(entry, MonoType::unit())
@@ -586,9 +844,42 @@ pub enum BinOp {
     Sub,
     Mul,
     Div,
+    Rem,
     And,
 }
 
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum AtomicOrdering {
+    Relaxed,
+    Acquire,
+    Release,
+    AcqRel,
+    SeqCst,
+}
+
+impl AtomicOrdering {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "relaxed" => Some(AtomicOrdering::Relaxed),
+            "acquire" => Some(AtomicOrdering::Acquire),
+            "release" => Some(AtomicOrdering::Release),
+            "acqrel" => Some(AtomicOrdering::AcqRel),
+            "seqcst" => Some(AtomicOrdering::SeqCst),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum AtomicRmwOp {
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+    Xchg,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Entry {
     // Function Calls
@@ -607,12 +898,16 @@ pub enum Entry {
     },
     JmpTable(Value, Vec<Block>),
     Trap(cranelift_codegen::ir::TrapCode),
+    // Stops in an attached debugger when built with `--trap-on-panic` and is otherwise ignored.
+    // Unlike a trap, execution may continue afterwards.
+    Breakpoint,
 
     // Value Construction
     Construct(Vec<Value>),
     Replicate(Value, u64),
     Variant(key::Variant, Vec<Value>),
     RefStaticVal(M<key::Val>),
+    RefExternData(M<key::Func>),
     BlockParam(Block, u32),
 
     // Value Destruction
@@ -636,6 +931,21 @@ pub enum Entry {
     BinOp(BinOp, [Value; 2]),
     IntCmpInclusive([Value; 2], std::cmp::Ordering, IntSize),
     IntAbs(Value),
+    // Returns `(n, carry)` of `a + b + carry`
+    IntAddCarry([Value; 3]),
+    // Returns `(n, borrow)` of `a - b - borrow`
+    IntSubBorrow([Value; 3]),
+    IntMin([Value; 2], IntSize),
+    IntMax([Value; 2], IntSize),
+    FloatAdd([Value; 2]),
+    FloatSub([Value; 2]),
+    FloatMul([Value; 2]),
+    FloatDiv([Value; 2]),
+    FloatNeg(Value),
+    // Ordered comparison, always false if either side is NaN
+    FloatCmp([Value; 2], std::cmp::Ordering),
+    // Picks between two values without branching: `[cond, on_true, on_false]`
+    SelectValue([Value; 3]),
 
     Transmute(Value), // Transmute two values of equal size
     SizeOf(MonoType),
@@ -646,6 +956,8 @@ pub enum Entry {
 
     IntToFloat(Value, IntSize),
     FloatToInt(Value, IntSize),
+    // Promote or demote between float sizes
+    FloatCast(Value),
 
     BitNot(Value),
 
@@ -664,6 +976,24 @@ pub enum Entry {
         src: Value,
         count: Value,
     },
+    // Fill `count` bytes at `dst` with the `u8` `byte`
+    MemSet {
+        dst: Value,
+        byte: Value,
+        count: Value,
+    },
+
+    // Atomic Memory Access
+    AtomicLoad(Value, AtomicOrdering),
+    AtomicStore {
+        ptr: Value,
+        value: Value,
+        ordering: AtomicOrdering,
+    },
+    // `[ptr, expected, replacement]`, returning the previous value
+    AtomicCas([Value; 3], AtomicOrdering),
+    // `[ptr, value]`, returning the previous value
+    AtomicRmw(AtomicRmwOp, [Value; 2], AtomicOrdering),
     Deref(Value),
 }
 
@@ -776,7 +1106,7 @@ impl<'a, 'e> fmt::Display for EntryFmt<'a, 'e> {
             Entry::CallStatic(mfunc, params) => {
                 let symbol = self
                     .0
-                    .map(|funcs| funcs[*mfunc].symbol.clone())
+                    .map(|funcs| funcs[*mfunc].symbol.to_string())
                     .unwrap_or(mfunc.to_string());
                 write!(f, "{} {}", "call".keyword(), CStyle(&symbol, params))
             }
@@ -790,6 +1120,7 @@ impl<'a, 'e> fmt::Display for EntryFmt<'a, 'e> {
             Entry::SizeOf(v) => write!(f, "{} {v:#?}", "size-of".keyword()),
             Entry::AlignOf(v) => write!(f, "{} {v:#?}", "align-of".keyword()),
             Entry::RefStaticVal(val) => write!(f, "&{val}"),
+            Entry::RefExternData(key) => write!(f, "&{} {key}", "extern".keyword()),
             Entry::BlockParam(block, i) => write!(f, "{} {block}[{i}]", "bparam".keyword()),
             Entry::Deref(v) => write!(f, "{} {v}", "deref".keyword()),
             Entry::Construct(elems) => ParamFmt::new(&"construct".keyword(), elems).fmt(f),
@@ -813,6 +1144,14 @@ impl<'a, 'e> fmt::Display for EntryFmt<'a, 'e> {
                 let header = format!("{kind}.{size}");
                 write!(f, "{} {} {}", header.keyword(), left, right)
             }
+            Entry::FloatCmp([left, right], cmp) => {
+                let kind = match cmp {
+                    std::cmp::Ordering::Less => "flt",
+                    std::cmp::Ordering::Equal => "feq",
+                    std::cmp::Ordering::Greater => "fgt",
+                };
+                write!(f, "{} {} {}", kind.keyword(), left, right)
+            }
             Entry::BitNot(v) => write!(f, "{} {v}", "bit-not".keyword()),
             Entry::Alloc => write!(f, "{}", "alloc".keyword(),),
             Entry::Alloca => write!(f, "{}", "alloca".keyword()),
@@ -827,6 +1166,16 @@ impl<'a, 'e> fmt::Display for EntryFmt<'a, 'e> {
             }
             Entry::BinOp(kind, [a, b]) => write!(f, "{} {a} {b}", kind.keyword()),
             Entry::IntAbs(v) => write!(f, "{} {v}", "abs".keyword()),
+            Entry::IntAddCarry([a, b, c]) => write!(f, "{} {a} {b} {c}", "add-carry".keyword()),
+            Entry::IntSubBorrow([a, b, c]) => write!(f, "{} {a} {b} {c}", "sub-borrow".keyword()),
+            Entry::IntMin([a, b], size) => write!(f, "{} {a} {b}", format!("min.{size}").keyword()),
+            Entry::IntMax([a, b], size) => write!(f, "{} {a} {b}", format!("max.{size}").keyword()),
+            Entry::SelectValue([c, a, b]) => write!(f, "{} {c} {a} {b}", "select-value".keyword()),
+            Entry::FloatAdd([a, b]) => write!(f, "{} {a} {b}", "fadd".keyword()),
+            Entry::FloatSub([a, b]) => write!(f, "{} {a} {b}", "fsub".keyword()),
+            Entry::FloatMul([a, b]) => write!(f, "{} {a} {b}", "fmul".keyword()),
+            Entry::FloatDiv([a, b]) => write!(f, "{} {a} {b}", "fdiv".keyword()),
+            Entry::FloatNeg(v) => write!(f, "{} {v}", "fneg".keyword()),
             Entry::Reduce(v) => write!(f, "{} {v}", "reduce".keyword()),
             Entry::ExtendUnsigned(v) => write!(f, "{} {v}", "uextend".keyword()),
             Entry::ExtendSigned(v) => write!(f, "{} {v}", "sextend".keyword()),
@@ -836,12 +1185,32 @@ impl<'a, 'e> fmt::Display for EntryFmt<'a, 'e> {
             Entry::MemCpy { dst, src, count } => {
                 write!(f, "{} {dst} {src} {count}", "memcpy".keyword())
             }
+            Entry::MemSet { dst, byte, count } => {
+                write!(f, "{} {dst} {byte} {count}", "memset".keyword())
+            }
+            Entry::AtomicLoad(ptr, ordering) => {
+                let header = format!("atomic-load.{ordering}");
+                write!(f, "{} {ptr}", header.keyword())
+            }
+            Entry::AtomicStore { ptr, value, ordering } => {
+                let header = format!("atomic-store.{ordering}");
+                write!(f, "{} {ptr} {} {value}", header.keyword(), "<-".symbol())
+            }
+            Entry::AtomicCas([ptr, expected, replacement], ordering) => {
+                let header = format!("atomic-cas.{ordering}");
+                write!(f, "{} {ptr} {expected} {replacement}", header.keyword())
+            }
+            Entry::AtomicRmw(op, [ptr, value], ordering) => {
+                let header = format!("atomic-{op}.{ordering}");
+                write!(f, "{} {ptr} {value}", header.keyword())
+            }
             Entry::IntToFloat(v, _) => {
                 write!(f, "{} {v}", "int_to_float".keyword())
             }
             Entry::FloatToInt(v, intsize) => {
                 write!(f, "{} {intsize} {v}", "float_to_int".keyword())
             }
+            Entry::FloatCast(v) => write!(f, "{} {v}", "float_cast".keyword()),
             Entry::JmpFunc(mfunc, params) => {
                 write!(f, "{} {}", "jump".keyword(), CStyle(mfunc, params))
             }
@@ -850,6 +1219,7 @@ impl<'a, 'e> fmt::Display for EntryFmt<'a, 'e> {
             }
             Entry::Return(value) => write!(f, "{} {value}", "return".keyword()),
             Entry::Trap(code) => write!(f, "{} {code}", "trap".keyword()),
+            Entry::Breakpoint => write!(f, "{}", "breakpoint".keyword()),
             Entry::Select { value, on_true, on_false, .. } => {
                 writeln!(f, "{} {value}", "select".keyword())?;
                 let mut f = |str: &str, b: &BlockJump| {
@@ -875,6 +1245,33 @@ impl<'a, 'e> fmt::Display for EntryFmt<'a, 'e> {
     }
 }
 
+impl fmt::Display for AtomicOrdering {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtomicOrdering::Relaxed => "relaxed",
+            AtomicOrdering::Acquire => "acquire",
+            AtomicOrdering::Release => "release",
+            AtomicOrdering::AcqRel => "acqrel",
+            AtomicOrdering::SeqCst => "seqcst",
+        }
+        .fmt(f)
+    }
+}
+
+impl fmt::Display for AtomicRmwOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtomicRmwOp::Add => "add",
+            AtomicRmwOp::Sub => "sub",
+            AtomicRmwOp::And => "and",
+            AtomicRmwOp::Or => "or",
+            AtomicRmwOp::Xor => "xor",
+            AtomicRmwOp::Xchg => "xchg",
+        }
+        .fmt(f)
+    }
+}
+
 impl fmt::Display for BinOp {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -882,6 +1279,7 @@ impl fmt::Display for BinOp {
             BinOp::Sub => "sub",
             BinOp::Mul => "mul",
             BinOp::Div => "div",
+            BinOp::Rem => "rem",
             BinOp::And => "and",
         }
         .fmt(f)
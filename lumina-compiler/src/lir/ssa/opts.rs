@@ -5,8 +5,10 @@ use crate::lir::{
     ssa::rewrite::{
         for_entry_mut, for_value_mut, for_values_mut, insert_buf, offset_predecessors, Rewrite,
     },
-    Block, BlockJump, Entry, Function, MonoFunc, MonoType, Value, LIR, SSA, V,
+    Block, BlockJump, Entry, Function, Location, MonoFunc, MonoType, Value, LIR, SSA, V,
 };
+use crate::OptLevel;
+use lumina_key as key;
 use smallvec::SmallVec;
 use std::mem::take;
 use tracing::{info, info_span, trace};
@@ -14,14 +16,15 @@ use tracing::{info, info_span, trace};
 pub const ENABLE_OPTS: bool = true;
 
 impl LIR {
-    pub fn perform_optimizations(&mut self) {
-        let mut opt = Optimizer { lir: self };
+    pub fn perform_optimizations(&mut self, level: OptLevel) {
+        let mut opt = Optimizer { lir: self, level };
         opt.optimizations();
     }
 }
 
 struct Optimizer<'a> {
     lir: &'a mut LIR,
+    level: OptLevel,
 }
 
 type Changed = bool;
@@ -32,16 +35,35 @@ impl<'a> Optimizer<'a> {
             return;
         }
 
-        self.block_opts(1000);
-        self.func_opts(1000);
-        self.block_opts(5);
+        match self.level {
+            OptLevel::None => {}
+            OptLevel::Less => {
+                self.fold_opts();
+                self.block_opts(1000);
+            }
+            OptLevel::Speed | OptLevel::Size => {
+                self.fold_opts();
+                self.block_opts(1000);
+                self.func_opts(1000);
+                self.fold_opts();
+                self.block_opts(5);
+            }
+        }
+    }
+
+    fn fold_opts(&mut self) {
+        for func in self.lir.functions.values_mut() {
+            if func.ssa.fold_constants() {
+                info!("folded constants in {}", func.symbol);
+            }
+        }
     }
 
     fn block_opts(&mut self, fuel: usize) {
         for fkey in self.lir.functions.keys() {
             let _span = info_span!(
                 "running block optimizations",
-                entity = self.lir.functions[fkey].symbol.clone(),
+                entity = self.lir.functions[fkey].symbol.as_str(),
             );
             let _handle = _span.enter();
 
@@ -78,7 +100,7 @@ impl<'a> Optimizer<'a> {
         for fkey in self.lir.functions.keys() {
             let _span = info_span!(
                 "running func optimizations",
-                entity = self.lir.functions[fkey].symbol.clone(),
+                entity = self.lir.functions[fkey].symbol.as_str(),
             );
             let _handle = _span.enter();
 
@@ -156,7 +178,23 @@ fn block_opt_iter(func: &mut Function, _: MonoFunc, block: Block) -> Changed {
             //     func.ssa.ventries[v] = Entry::JmpBlock(jump);
             //     return true;
             // }
-            Entry::Select { on_true, on_false, .. } => {
+            Entry::Select { value, on_true, on_false } => {
+                if let Some(cond) = known_condition(&func.ssa, *value) {
+                    let (taken, dropped) = if cond {
+                        (on_true, on_false)
+                    } else {
+                        (on_false, on_true)
+                    };
+                    info!(
+                        "folding select on known condition into a jump to {}",
+                        taken.id
+                    );
+                    let (taken, dropped) = (taken.clone(), dropped.id);
+                    func.ssa.blocks[dropped].predecessors -= 1;
+                    func.ssa.ventries[v] = Entry::JmpBlock(taken);
+                    return true;
+                }
+
                 let new_on_true = try_inline_blockjump(&func.ssa, on_true);
                 let new_on_false = try_inline_blockjump(&func.ssa, on_false);
 
@@ -207,6 +245,22 @@ fn block_opt_iter(func: &mut Function, _: MonoFunc, block: Block) -> Changed {
 
                 return true;
             }
+            Entry::JmpTable(Value::V(tag), blocks)
+                if known_tag(&func.ssa, *tag)
+                    .is_some_and(|var| (var.0 as usize) < blocks.len()) =>
+            {
+                let taken = blocks[known_tag(&func.ssa, *tag).unwrap().0 as usize];
+                info!("folding jump table on the known tag of {tag} into a jump to {taken}");
+
+                let blocks = blocks.clone();
+                for block in blocks {
+                    func.ssa.blocks[block].predecessors -= 1;
+                }
+                func.ssa.blocks[taken].predecessors += 1;
+                func.ssa.ventries[v] = Entry::JmpBlock(BlockJump::new(taken, vec![]));
+
+                return true;
+            }
             Entry::JmpTable(_, blocks) => {
                 let rejumps = blocks
                     .iter()
@@ -240,6 +294,41 @@ fn block_opt_iter(func: &mut Function, _: MonoFunc, block: Block) -> Changed {
     false
 }
 
+// The variant of the sum whose tag is read by `v`, if the sum is constructed in this function
+fn known_tag(ssa: &SSA, v: V) -> Option<key::Variant> {
+    let Entry::TagFromSum { of: Value::V(sum) } = &ssa.ventries[v] else {
+        return None;
+    };
+
+    match &ssa.ventries[*sum] {
+        Entry::Variant(var, _) => Some(*var),
+        _ => None,
+    }
+}
+
+// Whether a select will always take the same branch, such as when matching on `Maybe` right after
+// its construction
+fn known_condition(ssa: &SSA, value: Value) -> Option<bool> {
+    let known = |value: Value| match value {
+        Value::Int(n, _) => Some(n),
+        Value::V(v) => known_tag(ssa, v).map(|var| var.0 as i128),
+        _ => None,
+    };
+
+    let v = match value {
+        Value::Int(n, _) => return Some(n != 0),
+        Value::V(v) => v,
+        _ => return None,
+    };
+
+    match &ssa.ventries[v] {
+        Entry::IntCmpInclusive([lhs, rhs], std::cmp::Ordering::Equal, _) => {
+            Some(known(*lhs)? == known(*rhs)?)
+        }
+        _ => None,
+    }
+}
+
 // When blocks contain binds, only single-use or scope-pure blocks are inlineable.
 //
 // TODO: avoid inlining things like jump tables, instead of just going by bind count
@@ -260,7 +349,7 @@ fn full_block_inline(ssa: &mut SSA, atv: V, jump: &BlockJump) {
     let mut r = Rewrite::new(V(atv.0 + 1), Block(0));
     r.new_block_params = Some((binfo.start, binfo.end, &jump.params));
 
-    let (mut inlinedv, mut inlinedt) =
+    let (mut inlinedv, mut inlinedt, mut inlinedl) =
         get_inlined_entries(ssa, r.clone(), V(binfo.start.0 + binfo.params), binfo.end);
 
     offset_predecessors(ssa, binfo.end, 1);
@@ -275,6 +364,7 @@ fn full_block_inline(ssa: &mut SSA, atv: V, jump: &BlockJump) {
     // Actually perform the inline
     insert_buf(atv, &mut ssa.ventries, inlinedv.drain(..), true);
     insert_buf(atv, &mut ssa.vtypes, inlinedt.drain(..), true);
+    insert_buf(atv, &mut ssa.vlocs, inlinedl.drain(..), true);
 }
 
 fn full_func_inline(func: &mut SSA, ofunc: &SSA, atv: V, params: Vec<Value>) -> Vec<MonoFunc> {
@@ -301,45 +391,53 @@ fn full_func_inline(func: &mut SSA, ofunc: &SSA, atv: V, params: Vec<Value>) ->
         .ventries
         .iter()
         .zip(ofunc.vtypes.values().cloned())
-        .flat_map(|((v, entry), ty)| match entry.clone() {
-            // Substitute return by jump to post-inline continuation
-            Entry::Return(mut v) => {
-                for_value_mut(&mut v, &mut |v| ioffset(&injected, atv, v).value());
-                func.blocks[conblock].predecessors += 1;
-                vec![(Entry::JmpBlock(BlockJump::new(conblock, vec![v])), ty)]
-            }
-            // Substitute JmpFunc by call+jump
-            Entry::JmpFunc(mfunc, mut params) => {
-                info!("injecting additional call instruction");
-                to_bump.push(mfunc);
+        .flat_map(|((v, entry), ty)| {
+            let loc = ofunc.vlocs[v];
+            let inlined = match entry.clone() {
+                // Substitute return by jump to post-inline continuation
+                Entry::Return(mut v) => {
+                    for_value_mut(&mut v, &mut |v| ioffset(&injected, atv, v).value());
+                    func.blocks[conblock].predecessors += 1;
+                    vec![(Entry::JmpBlock(BlockJump::new(conblock, vec![v])), ty)]
+                }
+                // Substitute JmpFunc by call+jump
+                Entry::JmpFunc(mfunc, mut params) => {
+                    info!("injecting additional call instruction");
+                    to_bump.push(mfunc);
 
-                for_values_mut(&mut params, &mut |v| ioffset(&injected, atv, v).value());
-                func.blocks[conblock].predecessors += 1;
+                    for_values_mut(&mut params, &mut |v| ioffset(&injected, atv, v).value());
+                    func.blocks[conblock].predecessors += 1;
 
-                let call = Entry::CallStatic(mfunc, params);
-                let con_params = vec![ioffset(&injected, atv, v).value()];
-                let con_jump = Entry::JmpBlock(BlockJump::new(conblock, con_params));
+                    let call = Entry::CallStatic(mfunc, params);
+                    let con_params = vec![ioffset(&injected, atv, v).value()];
+                    let con_jump = Entry::JmpBlock(BlockJump::new(conblock, con_params));
 
-                injected.push(v);
+                    injected.push(v);
 
-                vec![(call, ty.clone()), (con_jump, ty)]
-            }
-            mut entry => {
-                match entry {
-                    Entry::CallStatic(mfunc, _) => to_bump.push(mfunc),
-                    _ => {}
+                    vec![(call, ty.clone()), (con_jump, ty)]
                 }
-                for_entry_mut(
-                    &mut entry,
-                    &mut |v| ioffset(&injected, atv, v).value(),
-                    &mut |b| Block(b.0 + iblock.0),
-                );
-                vec![(entry, ty)]
-            }
+                mut entry => {
+                    match entry {
+                        Entry::CallStatic(mfunc, _) => to_bump.push(mfunc),
+                        _ => {}
+                    }
+                    for_entry_mut(
+                        &mut entry,
+                        &mut |v| ioffset(&injected, atv, v).value(),
+                        &mut |b| Block(b.0 + iblock.0),
+                    );
+                    vec![(entry, ty)]
+                }
+            };
+            inlined
+                .into_iter()
+                .map(move |(entry, ty)| (entry, (ty, loc)))
         })
         .unzip();
+    let (types, locs): (Vec<_>, Vec<_>) = types.into_iter().unzip();
 
     let ty = func.vtypes[atv].clone();
+    let loc = func.vlocs[atv];
 
     fn offset_by_inlined(v: V, atv: V, len: usize) -> V {
         if v.0 >= atv.0 {
@@ -386,6 +484,7 @@ fn full_func_inline(func: &mut SSA, ofunc: &SSA, atv: V, params: Vec<Value>) ->
     ];
     insertion(func.ventries.as_mut_vec(), entries, atv, injection);
     insertion(func.vtypes.as_mut_vec(), types, atv, [ty.clone(), ty]);
+    insertion(func.vlocs.as_mut_vec(), locs, atv, [loc, loc]);
 
     func.blocks[conblock].start = V(constart);
 
@@ -404,11 +503,12 @@ fn get_inlined_entries<'p>(
     mut r: Rewrite<'p>,
     start: V,
     end: V,
-) -> (Vec<Entry>, Vec<MonoType>) {
+) -> (Vec<Entry>, Vec<MonoType>, Vec<Option<Location>>) {
     let range = start.0 as usize..=end.0 as usize;
 
     let mut entries = ssa.ventries.as_slice()[range.clone()].to_vec();
-    let types = ssa.vtypes.as_slice()[range].to_vec();
+    let types = ssa.vtypes.as_slice()[range.clone()].to_vec();
+    let locs = ssa.vlocs.as_slice()[range].to_vec();
 
     // Offset the new inlined to start at the current V.
     //
@@ -418,7 +518,7 @@ fn get_inlined_entries<'p>(
 
     entries.iter_mut().for_each(|entry| r.entry(false, entry));
 
-    (entries, types)
+    (entries, types, locs)
 }
 
 fn try_inline_blockjump(ssa: &SSA, ijump: &BlockJump) -> Option<BlockJump> {
@@ -473,14 +573,30 @@ fn func_opt_iter(lir: &mut LIR, func: MonoFunc, _: Block) -> Changed {
     false
 }
 
+// Functions called at least this many times in the profile given with `--profile-use` are
+// inlined even if they're somewhat larger
+const HOT_CALLS: u64 = 1000;
+
+// Functions called at least `HOT_CALLS` times may be this many times larger than the inline
+// threshold and still be inlined
+const HOT_SIZE_FACTOR: usize = 8;
+
 // inline small functions or those who are only invoked once
 //
 // TODO: make sure directly recursive functions still work to inline
 // (I think they can since it can re-jump to entry)
 fn should_inline(lir: &LIR, func: MonoFunc) -> bool {
     let func = &lir.functions[func];
-    func.invocations == 1
-        || (func.ssa.ventries.len() - func.ssa.block_params(Block::entry()).count()) < 3
+    let size = func.ssa.ventries.len() - func.ssa.block_params(Block::entry()).count();
+
+    match func.calls {
+        // Never called while profiling, so inlining it would only grow its callers
+        Some(0) => func.invocations == 1,
+        Some(calls) if calls >= HOT_CALLS => {
+            func.invocations == 1 || size < lir.inline_threshold * HOT_SIZE_FACTOR
+        }
+        _ => func.invocations == 1 || size < lir.inline_threshold,
+    }
 }
 
 impl Entry {
@@ -650,4 +766,120 @@ mod tests {
 
         insta::assert_snapshot!(format!("{before}\n{after}"));
     }
+
+    #[test]
+    fn known_tag() {
+        lumina_util::test_logger();
+        use crate::lir::MonoTypeKey;
+        use lumina_typesystem::IntSize;
+
+        let mut ssa = SSA::new();
+
+        let block = [Block::entry(), ssa.new_block(), ssa.new_block()];
+
+        let tagsize = IntSize::new(false, 16);
+        let v0 = ssa.variant(key::Variant(1), vec![], MonoTypeKey(0));
+        let v1 = ssa.tag_of(v0, tagsize);
+        ssa.jump_table(v1, vec![block[1], block[2]]);
+
+        ssa.switch_to_block(block[1]);
+        ssa.return_(Value::u(1, 8));
+
+        ssa.switch_to_block(block[2]);
+        let v2 = ssa.tag_of(v0, tagsize);
+        let v3 = ssa.eq([v2, Value::u(1, 16)], tagsize);
+        let block = [ssa.new_block(), ssa.new_block()];
+        ssa.select(v3, [(block[0], vec![]), (block[1], vec![])]);
+
+        ssa.switch_to_block(block[0]);
+        ssa.return_(Value::u(2, 8));
+
+        ssa.switch_to_block(block[1]);
+        ssa.return_(Value::u(3, 8));
+
+        let item = Item::Defined(M(key::Module::from(0), key::Func::from(0)));
+        let mut func = Function::new("known_tag".into(), item, ssa, MonoType::u(8), 1);
+
+        while func
+            .ssa
+            .blocks
+            .keys()
+            .any(|block| block_opt_iter(&mut func, MonoFunc(0), block))
+        {}
+
+        let entries = func.ssa.ventries.values().collect::<Vec<_>>();
+        assert!(entries.contains(&&Entry::Return(Value::u(2, 8))));
+        assert!(!entries.contains(&&Entry::Return(Value::u(1, 8))));
+        assert!(!entries.contains(&&Entry::Return(Value::u(3, 8))));
+        assert_eq!(func.ssa.blocks.len(), 1);
+    }
+
+    #[test]
+    fn fold_constants() {
+        lumina_util::test_logger();
+        use lumina_typesystem::IntSize;
+
+        let mut ssa = SSA::new();
+
+        let block = [Block::entry(), ssa.new_block(), ssa.new_block()];
+
+        let v0 = ssa.add(Value::u(200, 8), Value::u(100, 8), MonoType::u(8));
+        let v1 = ssa.transmute(v0, MonoType::u(8));
+        let v2 = ssa.mul(v1, Value::u(2, 8), MonoType::u(8));
+        let v3 = ssa.lti([v2, Value::u(100, 8)], IntSize::new(false, 8));
+        ssa.select(v3, [(block[1], vec![]), (block[2], vec![])]);
+
+        ssa.switch_to_block(block[1]);
+        ssa.return_(v1);
+
+        ssa.switch_to_block(block[2]);
+        ssa.return_(v2);
+
+        let item = Item::Defined(M(key::Module::from(0), key::Func::from(0)));
+        let mut func = Function::new("fold_constants".into(), item, ssa, MonoType::u(8), 1);
+
+        assert!(func.ssa.fold_constants());
+        while func
+            .ssa
+            .blocks
+            .keys()
+            .any(|block| block_opt_iter(&mut func, MonoFunc(0), block))
+        {}
+
+        let entries = func.ssa.ventries.values().collect::<Vec<_>>();
+        assert_eq!(entries, [&Entry::Return(Value::u(44, 8))]);
+    }
+
+    #[test]
+    fn fold_comparison_signedness() {
+        lumina_util::test_logger();
+        use lumina_typesystem::IntSize;
+
+        let fold = |cmp: fn(&mut SSA, [Value; 2], IntSize) -> Value, params, intsize| {
+            let mut ssa = SSA::new();
+            let v = cmp(&mut ssa, params, intsize);
+            ssa.return_(v);
+
+            assert!(ssa.fold_constants());
+            match ssa.ventries.values().collect::<Vec<_>>()[..] {
+                [Entry::Return(value)] => *value,
+                ref entries => panic!("comparison was not folded: {entries:?}"),
+            }
+        };
+
+        // -1 reinterpreted as unsigned is the largest u8
+        let unsigned = IntSize::new(false, 8);
+        let params = [Value::Int(-1, unsigned), Value::u(1, 8)];
+        assert_eq!(fold(SSA::gti, params, unsigned), Value::bool(true));
+
+        // A u64 with its high bit set, stored as the i64 it was transmuted from
+        let unsigned = IntSize::new(false, 64);
+        let params = [Value::Int(i64::MIN as i128, unsigned), Value::u(1, 64)];
+        assert_eq!(fold(SSA::lti, params, unsigned), Value::bool(false));
+
+        // 255 reinterpreted as signed is -1
+        let signed = IntSize::new(true, 8);
+        let params = [Value::Int(255, signed), Value::i(0, 8)];
+        assert_eq!(fold(SSA::lti, params, signed), Value::bool(true));
+    }
 }
@@ -112,6 +112,11 @@ impl SSA {
             .drain(start.0 as usize..start.0 as usize + count)
             .count();
 
+        self.vlocs
+            .as_mut_vec()
+            .drain(start.0 as usize..start.0 as usize + count)
+            .count();
+
         assert_eq!(count, removed);
     }
 
@@ -133,6 +138,61 @@ impl SSA {
         r.boff = -1;
         self.apply(V(0), &r);
     }
+
+    /// Remove values no longer referenced by any entry, shifting down the values placed after them
+    pub fn remove_unused_values(&mut self, is_removed: impl Fn(V) -> bool) {
+        // The amount of removed values placed before each value
+        let mut shift = Map::<V, u32>::with_capacity(self.ventries.len());
+        let mut removed = 0;
+        for v in self.ventries.keys() {
+            shift.push(removed);
+            removed += is_removed(v) as u32;
+        }
+
+        if removed == 0 {
+            return;
+        }
+
+        fn retain<T>(map: &mut Map<V, T>, is_removed: &impl Fn(V) -> bool) {
+            let mut v = V(0);
+            map.as_mut_vec().retain(|_| {
+                let keep = !is_removed(v);
+                v.0 += 1;
+                keep
+            });
+        }
+
+        retain(&mut self.ventries, &is_removed);
+        retain(&mut self.vtypes, &is_removed);
+        retain(&mut self.vlocs, &is_removed);
+
+        for entry in self.ventries.values_mut() {
+            for_entry_mut(entry, &mut |v| Value::V(V(v.0 - shift[v])), &mut |b| b);
+        }
+
+        // A block starting with a removed value now starts with the value following it
+        for block in self.blocks.values_mut() {
+            // Blocks which were never started, or which have been removed, don't point to a value
+            if block.start != V(u32::MAX) {
+                block.start.0 -= shift[block.start];
+            }
+        }
+    }
+
+    /// Visit every function called, jumped to or referenced as a function pointer
+    pub fn for_each_func_mut(&mut self, f: &mut dyn FnMut(&mut MonoFunc)) {
+        for entry in self.ventries.values_mut() {
+            if let Entry::CallStatic(mfunc, _) | Entry::JmpFunc(mfunc, _) = entry {
+                f(mfunc);
+            }
+
+            for_operand_mut(entry, &mut |value| {
+                if let Value::FuncPtr(mfunc) = value {
+                    f(mfunc)
+                }
+            });
+        }
+    }
 }
 
 // Offset predecessor for any blocks referenced by the entry
@@ -190,11 +250,28 @@ where
         }
         Entry::BinOp(_, [lhs, rhs])
         | Entry::WritePtr { ptr: lhs, value: rhs }
-        | Entry::IntCmpInclusive([lhs, rhs], _, _) => {
+        | Entry::AtomicStore { ptr: lhs, value: rhs, .. }
+        | Entry::AtomicRmw(_, [lhs, rhs], _)
+        | Entry::IntCmpInclusive([lhs, rhs], _, _)
+        | Entry::IntMin([lhs, rhs], _)
+        | Entry::IntMax([lhs, rhs], _)
+        | Entry::FloatAdd([lhs, rhs])
+        | Entry::FloatSub([lhs, rhs])
+        | Entry::FloatMul([lhs, rhs])
+        | Entry::FloatDiv([lhs, rhs])
+        | Entry::FloatCmp([lhs, rhs], _) => {
             for_value_mut(lhs, on_v);
             for_value_mut(rhs, on_v);
         }
-        Entry::MemCpy { dst, src, count } => {
+        Entry::IntAddCarry([lhs, rhs, carry])
+        | Entry::IntSubBorrow([lhs, rhs, carry])
+        | Entry::AtomicCas([carry, lhs, rhs], _)
+        | Entry::SelectValue([carry, lhs, rhs]) => {
+            for_value_mut(lhs, on_v);
+            for_value_mut(rhs, on_v);
+            for_value_mut(carry, on_v);
+        }
+        Entry::MemCpy { dst, src, count } | Entry::MemSet { dst, byte: src, count } => {
             for_value_mut(dst, on_v);
             for_value_mut(src, on_v);
             for_value_mut(count, on_v);
@@ -216,8 +293,16 @@ where
         | Entry::ExtendSigned(v)
         | Entry::ExtendUnsigned(v)
         | Entry::IntToFloat(v, _)
-        | Entry::FloatToInt(v, _) => for_value_mut(v, on_v),
-        Entry::Alloc | Entry::Alloca | Entry::Trap(_) | Entry::RefStaticVal(_) => {}
+        | Entry::FloatToInt(v, _)
+        | Entry::AtomicLoad(v, _)
+        | Entry::FloatNeg(v)
+        | Entry::FloatCast(v) => for_value_mut(v, on_v),
+        Entry::Alloc
+        | Entry::Alloca
+        | Entry::Trap(_)
+        | Entry::Breakpoint
+        | Entry::RefStaticVal(_)
+        | Entry::RefExternData(_) => {}
         Entry::BlockParam(block, _) => *block = on_b(*block),
     }
 }
@@ -252,11 +337,28 @@ pub(super) fn for_entry(entry: &Entry, f: &mut dyn FnMut(V)) {
         }
         Entry::BinOp(_, [lhs, rhs])
         | Entry::WritePtr { ptr: lhs, value: rhs }
-        | Entry::IntCmpInclusive([lhs, rhs], _, _) => {
+        | Entry::AtomicStore { ptr: lhs, value: rhs, .. }
+        | Entry::AtomicRmw(_, [lhs, rhs], _)
+        | Entry::IntCmpInclusive([lhs, rhs], _, _)
+        | Entry::IntMin([lhs, rhs], _)
+        | Entry::IntMax([lhs, rhs], _)
+        | Entry::FloatAdd([lhs, rhs])
+        | Entry::FloatSub([lhs, rhs])
+        | Entry::FloatMul([lhs, rhs])
+        | Entry::FloatDiv([lhs, rhs])
+        | Entry::FloatCmp([lhs, rhs], _) => {
             for_value(lhs, f);
             for_value(rhs, f);
         }
-        Entry::MemCpy { dst, src, count } => {
+        Entry::IntAddCarry([lhs, rhs, carry])
+        | Entry::IntSubBorrow([lhs, rhs, carry])
+        | Entry::AtomicCas([carry, lhs, rhs], _)
+        | Entry::SelectValue([carry, lhs, rhs]) => {
+            for_value(lhs, f);
+            for_value(rhs, f);
+            for_value(carry, f);
+        }
+        Entry::MemCpy { dst, src, count } | Entry::MemSet { dst, byte: src, count } => {
             for_value(dst, f);
             for_value(src, f);
             for_value(count, f);
@@ -279,11 +381,93 @@ pub(super) fn for_entry(entry: &Entry, f: &mut dyn FnMut(V)) {
         | Entry::ExtendSigned(v)
         | Entry::ExtendUnsigned(v)
         | Entry::IntToFloat(v, _)
-        | Entry::FloatToInt(v, _) => for_value(v, f),
+        | Entry::FloatToInt(v, _)
+        | Entry::AtomicLoad(v, _)
+        | Entry::FloatNeg(v)
+        | Entry::FloatCast(v) => for_value(v, f),
         Entry::Alloc
         | Entry::Alloca
         | Entry::Trap(_)
+        | Entry::Breakpoint
+        | Entry::RefStaticVal(_)
+        | Entry::RefExternData(_)
+        | Entry::BlockParam(_, _) => {}
+    }
+}
+
+fn for_operand_mut(entry: &mut Entry, f: &mut dyn FnMut(&mut Value)) {
+    match entry {
+        Entry::CallStatic(_, params)
+        | Entry::Variant(_, params)
+        | Entry::Construct(params)
+        | Entry::CallExtern(_, params)
+        | Entry::JmpFunc(_, params)
+        | Entry::JmpBlock(BlockJump { params, .. }) => params.iter_mut().for_each(f),
+        Entry::CallValue(value, params) => {
+            f(value);
+            params.iter_mut().for_each(f);
+        }
+        Entry::Select { value, on_true, on_false } => {
+            f(value);
+            on_true.params.iter_mut().for_each(&mut *f);
+            on_false.params.iter_mut().for_each(f);
+        }
+        Entry::BinOp(_, [lhs, rhs])
+        | Entry::WritePtr { ptr: lhs, value: rhs }
+        | Entry::AtomicStore { ptr: lhs, value: rhs, .. }
+        | Entry::AtomicRmw(_, [lhs, rhs], _)
+        | Entry::IntCmpInclusive([lhs, rhs], _, _)
+        | Entry::IntMin([lhs, rhs], _)
+        | Entry::IntMax([lhs, rhs], _)
+        | Entry::FloatAdd([lhs, rhs])
+        | Entry::FloatSub([lhs, rhs])
+        | Entry::FloatMul([lhs, rhs])
+        | Entry::FloatDiv([lhs, rhs])
+        | Entry::FloatCmp([lhs, rhs], _) => {
+            f(lhs);
+            f(rhs);
+        }
+        Entry::IntAddCarry([lhs, rhs, carry])
+        | Entry::IntSubBorrow([lhs, rhs, carry])
+        | Entry::AtomicCas([carry, lhs, rhs], _)
+        | Entry::SelectValue([carry, lhs, rhs]) => {
+            f(lhs);
+            f(rhs);
+            f(carry);
+        }
+        Entry::MemCpy { dst, src, count } | Entry::MemSet { dst, byte: src, count } => {
+            f(dst);
+            f(src);
+            f(count);
+        }
+        Entry::Transmute(v)
+        | Entry::IntAbs(v)
+        | Entry::Field { of: v, .. }
+        | Entry::JmpTable(v, _)
+        | Entry::Replicate(v, _)
+        | Entry::BitNot(v)
+        | Entry::CastFromSum { of: v }
+        | Entry::TagFromSum { of: v }
+        | Entry::Indice { of: v, .. }
+        | Entry::Return(v)
+        | Entry::Reduce(v)
+        | Entry::Deref(v)
+        | Entry::Dealloc { ptr: v }
+        | Entry::ExtendSigned(v)
+        | Entry::ExtendUnsigned(v)
+        | Entry::IntToFloat(v, _)
+        | Entry::FloatToInt(v, _)
+        | Entry::AtomicLoad(v, _)
+        | Entry::FloatNeg(v)
+        | Entry::FloatCast(v) => f(v),
+        Entry::SizeOf(_)
+        | Entry::AlignOf(_)
+        | Entry::Alloc
+        | Entry::Alloca
+        | Entry::Trap(_)
+        | Entry::Breakpoint
         | Entry::RefStaticVal(_)
+        | Entry::RefExternData(_)
         | Entry::BlockParam(_, _) => {}
     }
 }
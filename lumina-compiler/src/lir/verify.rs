@@ -0,0 +1,310 @@
+//! Structural verification of LIR before it's handed to a backend
+//!
+//! Backends assume that the LIR they're given is well-formed, and malformed LIR otherwise only
+//! shows up as an opaque panic deep inside of codegen. The verifier checks the invariants which
+//! the backends rely on and reports the offending function in its entirety instead.
+
+use super::{Block, Entry, MonoFormatter, MonoFunc, MonoType, Output, Value, V};
+use crate::prelude::*;
+use lumina_typesystem::FloatSize;
+use lumina_util::Symbol;
+use std::collections::HashSet;
+use std::fmt;
+
+/// A function which failed verification
+pub struct VerifyError {
+    pub symbol: Symbol,
+    pub problems: Vec<String>,
+    /// The function as formatted by `MonoFormatter`
+    pub listing: String,
+}
+
+/// Check that every block ends in a terminator, that jumps give their target the parameters it
+/// expects, and that the operands of entries have the types they're used as.
+pub fn verify(output: &Output) -> Result<(), Vec<VerifyError>> {
+    let errors: Vec<VerifyError> = output
+        .functions
+        .keys()
+        .filter_map(|mfunc| {
+            let mut verifier = Verifier { output, mfunc, problems: vec![] };
+            verifier.run();
+
+            if verifier.problems.is_empty() {
+                return None;
+            }
+
+            let func = &output.functions[mfunc];
+            let listing = MonoFormatter {
+                types: &output.types,
+                funcs: Some(&output.functions),
+                v: func,
+            };
+
+            Some(VerifyError {
+                symbol: func.symbol,
+                problems: verifier.problems,
+                listing: listing.to_string(),
+            })
+        })
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+struct Verifier<'a> {
+    output: &'a Output,
+    mfunc: MonoFunc,
+    problems: Vec<String>,
+}
+
+impl<'a> Verifier<'a> {
+    fn run(&mut self) {
+        let output = self.output;
+        let ssa = &output.functions[self.mfunc].ssa;
+
+        if ssa.block_start(Block::entry()).is_none() {
+            self.problems.push("function has no entry block".into());
+            return;
+        }
+
+        let starts: HashSet<V> = ssa.blocks().filter_map(|b| ssa.block_start(b)).collect();
+
+        for block in ssa.blocks() {
+            let Some(start) = ssa.block_start(block) else {
+                continue;
+            };
+
+            // The values of a block are contiguous, so the block ends with the first terminator
+            // unless the next block starts before one is found.
+            let terminator = ssa
+                .iterv()
+                .skip(start.0 as usize)
+                .take_while(|&v| v == start || !starts.contains(&v))
+                .find(|&v| ssa.entry_of(v).is_terminator());
+
+            if terminator.is_none() {
+                self.problems.push(format!("{block} has no terminator"));
+            }
+        }
+
+        for v in ssa.iterv() {
+            let mut undefined = vec![];
+            ssa.for_each_use(v, &mut |used| {
+                if !ssa.has_value(used) {
+                    undefined.push(used);
+                }
+            });
+
+            if !undefined.is_empty() {
+                for used in undefined {
+                    self.problem(v, format!("use of undefined value {used}"));
+                }
+                continue;
+            }
+
+            self.entry(v, ssa.type_of(v), ssa.entry_of(v));
+        }
+    }
+
+    fn problem(&mut self, at: V, problem: impl fmt::Display) {
+        let entry = self.output.functions[self.mfunc].ssa.entry_of(at);
+        self.problems.push(format!("{at} = {entry}: {problem}"));
+    }
+
+    fn tfmt<T>(&self, v: T) -> MonoFormatter<'a, T> {
+        MonoFormatter {
+            types: &self.output.types,
+            funcs: Some(&self.output.functions),
+            v,
+        }
+    }
+
+    fn type_of_value(&self, value: Value) -> Option<MonoType> {
+        match value {
+            Value::ReadOnly(ro) => {
+                Some(MonoType::pointer(self.output.read_only_table[ro].1.clone()))
+            }
+            Value::V(v) => Some(self.output.functions[self.mfunc].ssa.type_of(v).clone()),
+            Value::Int(_, intsize) => Some(MonoType::Int(intsize)),
+            Value::Float(_) => Some(MonoType::Float(FloatSize::F64)),
+            Value::FuncPtr(mfunc) => {
+                let params = self.params_of(mfunc, Block::entry())?;
+                let ret = self.output.functions[mfunc].returns.clone();
+                Some(MonoType::FnPointer(params, Box::new(ret)))
+            }
+            Value::ExternFuncPtr(key) => {
+                let func = self.output.extern_funcs.get(&key)?;
+                Some(MonoType::FnPointer(
+                    func.params.clone(),
+                    Box::new(func.returns.clone()),
+                ))
+            }
+        }
+    }
+
+    fn params_of(&self, mfunc: MonoFunc, block: Block) -> Option<Vec<MonoType>> {
+        let func = self
+            .output
+            .functions
+            .has(mfunc)
+            .then(|| &self.output.functions[mfunc])?;
+        func.ssa.block_start(block)?;
+        Some(func.ssa.param_types(block).cloned().collect())
+    }
+
+    fn expect(&mut self, at: V, what: &str, got: &MonoType, exp: &MonoType) {
+        if got != exp {
+            let problem = format!(
+                "{what} is {} but {} was expected",
+                self.tfmt(got),
+                self.tfmt(exp)
+            );
+            self.problem(at, problem);
+        }
+    }
+
+    fn expect_params(&mut self, at: V, what: &str, given: &[Value], expected: &[MonoType]) {
+        if given.len() != expected.len() {
+            let problem = format!(
+                "{what} takes {} parameters but was given {}",
+                expected.len(),
+                given.len()
+            );
+            self.problem(at, problem);
+            return;
+        }
+
+        for (i, (value, exp)) in given.iter().zip(expected).enumerate() {
+            match self.type_of_value(*value) {
+                Some(got) => self.expect(at, &format!("parameter {i} of {what}"), &got, exp),
+                None => self.problem(at, format!("parameter {i} of {what} does not exist")),
+            }
+        }
+    }
+
+    fn jump(&mut self, at: V, target: Block, params: &[Value]) {
+        match self.params_of(self.mfunc, target) {
+            Some(expected) => self.expect_params(at, &target.to_string(), params, &expected),
+            None => self.problem(at, format!("jump to {target} which doesn't exist")),
+        }
+    }
+
+    fn value(&mut self, at: V, what: &str, value: Value) -> Option<MonoType> {
+        let ty = self.type_of_value(value);
+        if ty.is_none() {
+            self.problem(at, format!("{what} does not exist"));
+        }
+        ty
+    }
+
+    fn entry(&mut self, at: V, exp: &MonoType, entry: &Entry) {
+        let output = self.output;
+
+        match entry {
+            Entry::CallStatic(mfunc, params) | Entry::JmpFunc(mfunc, params) => {
+                let Some(expected) = self.params_of(*mfunc, Block::entry()) else {
+                    return self.problem(at, format!("call to {mfunc} which doesn't exist"));
+                };
+                let what = output.functions[*mfunc].symbol;
+                self.expect_params(at, &what, params, &expected);
+
+                if let Entry::CallStatic(..) = entry {
+                    let returns = &output.functions[*mfunc].returns;
+                    self.expect(at, "return value", exp, returns);
+                }
+            }
+            Entry::CallExtern(key, params) => {
+                let Some(extern_) = output.extern_funcs.get(key) else {
+                    return self.problem(at, format!("call to {key} which isn't declared"));
+                };
+                let what = extern_.symbol;
+                let params = match extern_.variadic {
+                    true if params.len() >= extern_.params.len() => &params[..extern_.params.len()],
+                    _ => params.as_slice(),
+                };
+                self.expect_params(at, &what, params, &extern_.params);
+                self.expect(at, "return value", exp, &extern_.returns);
+            }
+            Entry::CallValue(to_call, params) => {
+                if let Some(MonoType::FnPointer(ptypes, ret)) =
+                    self.value(at, "called value", *to_call)
+                {
+                    self.expect_params(at, "function pointer", params, &ptypes);
+                    self.expect(at, "return value", exp, &ret);
+                }
+            }
+            Entry::JmpBlock(jump) => self.jump(at, jump.id, &jump.params),
+            Entry::Select { value, on_true, on_false } => {
+                if let Some(ty) = self.value(at, "condition", *value) {
+                    self.expect(at, "condition", &ty, &MonoType::bool());
+                }
+                self.jump(at, on_true.id, &on_true.params);
+                self.jump(at, on_false.id, &on_false.params);
+            }
+            Entry::JmpTable(value, blocks) => {
+                if let Some(ty) = self.value(at, "jump table index", *value) {
+                    if !matches!(ty, MonoType::Int(_)) {
+                        let problem = format!("jump table index of type {}", self.tfmt(&ty));
+                        self.problem(at, problem);
+                    }
+                }
+                for block in blocks {
+                    self.jump(at, *block, &[]);
+                }
+            }
+            Entry::Return(value) => {
+                if let Some(ty) = self.value(at, "returned value", *value) {
+                    let returns = &output.functions[self.mfunc].returns;
+                    self.expect(at, "returned value", &ty, returns);
+                }
+            }
+            Entry::WritePtr { ptr, value } => {
+                let ptr = self.value(at, "pointer", *ptr);
+                let value = self.value(at, "written value", *value);
+                if let (Some(ptr), Some(value)) = (ptr, value) {
+                    match ptr {
+                        MonoType::Pointer(inner) => {
+                            self.expect(at, "written value", &value, &inner)
+                        }
+                        _ => self.problem(at, format!("write to {}", self.tfmt(&ptr))),
+                    }
+                }
+            }
+            Entry::Deref(ptr) => {
+                if let Some(ptr) = self.value(at, "pointer", *ptr) {
+                    match ptr {
+                        MonoType::Pointer(inner) => {
+                            self.expect(at, "dereferenced value", exp, &inner)
+                        }
+                        _ => self.problem(at, format!("dereference of {}", self.tfmt(&ptr))),
+                    }
+                }
+            }
+            Entry::BinOp(_, [lhs, rhs]) => {
+                let lhs = self.value(at, "left-hand side", *lhs);
+                let rhs = self.value(at, "right-hand side", *rhs);
+                if let (Some(lhs), Some(rhs)) = (lhs, rhs) {
+                    // Pointer arithmetic is allowed to offset by an integer
+                    if !matches!(lhs, MonoType::Pointer(_)) {
+                        self.expect(at, "right-hand side", &rhs, &lhs);
+                    }
+                }
+            }
+            Entry::SelectValue([cond, on_true, on_false]) => {
+                if let Some(ty) = self.value(at, "condition", *cond) {
+                    self.expect(at, "condition", &ty, &MonoType::bool());
+                }
+                for v in [on_true, on_false] {
+                    if let Some(ty) = self.value(at, "selected value", *v) {
+                        self.expect(at, "selected value", &ty, exp);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
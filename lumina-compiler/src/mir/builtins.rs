@@ -1,5 +1,5 @@
 use crate::{hir, mir, mir::Expr};
-use lumina_typesystem::{Container, Transformer, Ty, Var};
+use lumina_typesystem::{Container, IntSize, Transformer, Ty, Var};
 use lumina_util::{Span, Spanned, Tr};
 use mir::func::InstCall;
 use std::collections::HashMap;
@@ -26,7 +26,9 @@ pub fn signature<'t, 's>(lower: &mut mir::Verify<'t, 's>, span: Span, name: &str
     macro_rules! ty {
         (bool) => { Ty::bool() };
         (uint) => { Ty::Int(lower.target.uint()) };
+        (u8) => { Ty::Int(IntSize::new(false, 8)) };
         ((pointer $inner:tt)) => { Ty::pointer(ty!($inner)) };
+        ((slice $inner:tt)) => { Ty::slice(ty!($inner)) };
         (($($param:tt),*)) => { Ty::tuple(vec![$(ty!($param)),*]) };
         ($generic:literal) => {
             Ty::Special(
@@ -38,19 +40,33 @@ pub fn signature<'t, 's>(lower: &mut mir::Verify<'t, 's>, span: Span, name: &str
     }
 
     match name {
-        "plus" | "minus" | "mul" | "div" => {
+        "plus" | "minus" | "mul" | "div" | "rem" => {
             sig! { 'a', 'a' => 'a' }
         }
         "plus_checked" | "minus_checked" | "mul_checked" | "div_checked" => {
             sig! { 'a', 'a' => ('a', bool) }
         }
+        "plus_carry" | "minus_borrow" => {
+            sig! { 'a', 'a', bool => ('a', bool) }
+        }
+        "min" | "max" => sig! { 'a', 'a' => 'a' },
+        "select" => sig! { bool, 'a', 'a' => 'a' },
 
         "array_len" => sig! { 'a' => uint },
         "array_get" => sig! { uint, 'a' => 'b' },
+        "array_slice" => sig! { (pointer 'a') => (slice 'b') },
+        "slice_from_raw" => sig! { (pointer 'a'), uint => (slice 'a') },
+        "slice_ptr" => sig! { (slice 'a') => (pointer 'a') },
+        "slice_len" => sig! { (slice 'a') => uint },
+        "slice_get" => sig! { uint, (slice 'a') => 'a' },
+        "slice_sub" => sig! { uint, uint, (slice 'a') => (slice 'a') },
         "iabs" => sig! { 'n' => 'n' },
+        "neg" => sig! { 'n' => 'n' },
         "eq" | "lt" | "gt" => sig! { 'a', 'a' => bool },
+        "likely" | "unlikely" => sig! { bool => bool },
         "deref" => sig! { (pointer 'a') => 'a' },
         "memcpy" => sig! { (pointer 'a'), (pointer 'a'), uint => () },
+        "memset" => sig! { (pointer 'a'), u8, uint => () },
 
         "write" => sig! { (pointer 'a'), 'a' => () },
         "offset" => sig! { (pointer 'a'), uint => (pointer 'a') },
@@ -61,15 +77,52 @@ pub fn signature<'t, 's>(lower: &mut mir::Verify<'t, 's>, span: Span, name: &str
         "align_of" => sig! { direct uint },
         "alloca" => sig! { direct (pointer 'a') },
         "unreachable" => sig! { direct 'a' },
+        "breakpoint" => sig! { direct () },
         "transmute" => sig! { 'a' => 'b' },
         "val_to_ref" => sig! { 'a' => (pointer 'a') },
-        _ => {
-            lower.error("unrecognised builtin").eline(span, "").emit();
-            InstCall::Local(Ty::poison().tr(span))
-        }
+        _ => match atomic(name) {
+            Some((op, ordering)) => {
+                let invalid = match op {
+                    "load" => matches!(ordering, "release" | "acqrel"),
+                    "store" => matches!(ordering, "acquire" | "acqrel"),
+                    _ => false,
+                };
+                if invalid {
+                    lower
+                        .error("invalid memory ordering")
+                        .eline(span, format!("an atomic {op} can not be {ordering}"))
+                        .emit();
+                }
+
+                match op {
+                    "load" => sig! { (pointer 'a') => 'a' },
+                    "store" => sig! { (pointer 'a'), 'a' => () },
+                    "cas" => sig! { (pointer 'a'), 'a', 'a' => 'a' },
+                    _ => sig! { (pointer 'a'), 'a' => 'a' },
+                }
+            }
+            None => {
+                lower.error("unrecognised builtin").eline(span, "").emit();
+                InstCall::Local(Ty::poison().tr(span))
+            }
+        },
     }
 }
 
+/// Atomic builtins are named `atomic_{op}_{ordering}`
+fn atomic(name: &str) -> Option<(&'static str, &'static str)> {
+    let (op, ordering) = name.strip_prefix("atomic_")?.rsplit_once('_')?;
+    let op = [
+        "load", "store", "cas", "add", "sub", "and", "or", "xor", "swap",
+    ]
+    .into_iter()
+    .find(|o| *o == op)?;
+    let ordering = ["relaxed", "acquire", "release", "acqrel", "seqcst"]
+        .into_iter()
+        .find(|o| *o == ordering)?;
+    Some((op, ordering))
+}
+
 pub fn lower<'t, 's>(
     lower: &mut mir::Lower<'t, 's>,
     name: &str,
@@ -81,18 +134,36 @@ pub fn lower<'t, 's>(
         "minus" => lower.lower_builtin(params, |p| Expr::Num("minus", Box::new(p))),
         "mul" => lower.lower_builtin(params, |p| Expr::Num("mul", Box::new(p))),
         "div" => lower.lower_builtin(params, |p| Expr::Num("div", Box::new(p))),
+        "rem" => lower.lower_builtin(params, |p| Expr::Num("rem", Box::new(p))),
         "plus_checked" => lower.lower_builtin(params, |p| Expr::Num("plus_checked", Box::new(p))),
         "minus_checked" => lower.lower_builtin(params, |p| Expr::Num("minus_checked", Box::new(p))),
         "mul_checked" => lower.lower_builtin(params, |p| Expr::Num("mul_checked", Box::new(p))),
         "div_checked" => lower.lower_builtin(params, |p| Expr::Num("div_checked", Box::new(p))),
+        "plus_carry" => lower.lower_builtin(params, |p| Expr::NumCarry("plus_carry", Box::new(p))),
+        "minus_borrow" => {
+            lower.lower_builtin(params, |p| Expr::NumCarry("minus_borrow", Box::new(p)))
+        }
+        "min" => lower.lower_builtin(params, |p| Expr::Num("min", Box::new(p))),
+        "max" => lower.lower_builtin(params, |p| Expr::Num("max", Box::new(p))),
+        "select" => lower.lower_builtin(params, |p| Expr::SelectValue(Box::new(p))),
         "array_len" => lower.lower_builtin(params, |[p]| Expr::ArrayLen(Box::new(p))),
         "array_get" => lower.lower_builtin(params, |p| Expr::ArrayAccess(Box::new(p))),
+        "array_slice" => Expr::Slice("from_array", lower.lower_exprs(params)),
+        "slice_from_raw" => Expr::Slice("from_raw", lower.lower_exprs(params)),
+        "slice_ptr" => Expr::Slice("ptr", lower.lower_exprs(params)),
+        "slice_len" => Expr::Slice("len", lower.lower_exprs(params)),
+        "slice_get" => Expr::Slice("get", lower.lower_exprs(params)),
+        "slice_sub" => Expr::Slice("sub", lower.lower_exprs(params)),
         "iabs" => lower.lower_builtin(params, |[p]| Expr::IntAbs(Box::new(p))),
+        "neg" => lower.lower_builtin(params, |[p]| Expr::Neg(Box::new(p))),
         "eq" => lower.lower_builtin(params, |p| Expr::Cmp("eq", Box::new(p))),
         "lt" => lower.lower_builtin(params, |p| Expr::Cmp("lt", Box::new(p))),
         "gt" => lower.lower_builtin(params, |p| Expr::Cmp("gt", Box::new(p))),
+        "likely" => lower.lower_builtin(params, |[p]| Expr::Expect(Box::new(p), true)),
+        "unlikely" => lower.lower_builtin(params, |[p]| Expr::Expect(Box::new(p), false)),
         "deref" => lower.lower_builtin(params, |[inner]| Expr::Deref(Box::new(inner))),
         "memcpy" => lower.lower_builtin(params, |params| Expr::MemCpy(Box::new(params))),
+        "memset" => lower.lower_builtin(params, |params| Expr::MemSet(Box::new(params))),
         "write" => lower.lower_builtin(params, |p| Expr::Write(Box::new(p))),
         "offset" => lower.lower_builtin(params, |p| Expr::Num("plus", Box::new(p))),
         "unreachable" => {
@@ -101,6 +172,7 @@ pub fn lower<'t, 's>(
             let ty = lower.finalizer().transform(&ty);
             lower.lower_builtin::<0>(params, |_| Expr::Unreachable(ty))
         }
+        "breakpoint" => lower.lower_builtin::<0>(params, |_| Expr::Breakpoint),
         "transmute" => lower.lower_builtin(params, |[inner]| inner),
         "val_to_ref" => lower.lower_builtin(params, |[inner]| Expr::ValToRef(Box::new(inner))),
         "reflect_type" => {
@@ -127,7 +199,10 @@ pub fn lower<'t, 's>(
             let ty = lower.finalizer().transform(&ty);
             Expr::Alloca(ty)
         }
-        _ => panic!("unknown builtin: {name}"),
+        _ => match atomic(name) {
+            Some((op, ordering)) => Expr::Atomic(op, ordering, lower.lower_exprs(params)),
+            None => panic!("unknown builtin: {name}"),
+        },
     }
 }
 
@@ -0,0 +1,885 @@
+//! Binary serialisation of lowered functions for the [`super::cache`]
+//!
+//! Reuses the encoding of LIR bytecode. Names of builtins are `&'static str` in MIR, so they're
+//! decoded by looking them up in `BUILTINS` and anything not found there is rejected.
+
+use super::lower::{pat, Callable, ConcreteTyping, Expr, Function, Lambda};
+use crate::lir::bytecode::{Decode, Encode, Error, Reader};
+use crate::prelude::*;
+use ast::NFunc;
+use lumina_typesystem::{
+    ConstGeneric, ConstValue, Constraint, Container, FloatSize, Forall, Generic, GenericData,
+    GenericKind, GenericMapper, IntSize, Lang, Static, Ty, Type,
+};
+use lumina_util::Ignored;
+
+const SIMPLE_TYPES: &[&str] = &["bool", "f32", "f64", "poison", "self"];
+
+const BUILTINS: &[&str] = &[
+    // Arithmetic and comparisons
+    "plus",
+    "minus",
+    "mul",
+    "div",
+    "rem",
+    "plus_checked",
+    "minus_checked",
+    "mul_checked",
+    "div_checked",
+    "plus_carry",
+    "minus_borrow",
+    "min",
+    "max",
+    "eq",
+    "lt",
+    "gt",
+    "&&",
+    "||",
+    "|>",
+    // Slices
+    "from_array",
+    "from_raw",
+    "ptr",
+    "len",
+    "get",
+    "sub",
+    // Atomics and their memory orderings
+    "load",
+    "store",
+    "cas",
+    "add",
+    "and",
+    "or",
+    "xor",
+    "swap",
+    "relaxed",
+    "acquire",
+    "release",
+    "acqrel",
+    "seqcst",
+];
+
+fn decode_name(
+    r: &mut Reader,
+    names: &[&'static str],
+    what: &'static str,
+) -> Result<&'static str, Error> {
+    let name = String::decode(r)?;
+    names
+        .iter()
+        .find(|n| **n == name)
+        .copied()
+        .ok_or(Error::Invalid(what))
+}
+
+fn encode_exprs<const N: usize>(exprs: &[Expr; N], buf: &mut Vec<u8>) {
+    exprs.iter().for_each(|expr| expr.encode(buf));
+}
+
+fn decode_exprs<const N: usize>(r: &mut Reader) -> Result<Box<[Expr; N]>, Error> {
+    let exprs = (0..N)
+        .map(|_| Expr::decode(r))
+        .collect::<Result<Vec<_>, _>>()?;
+    let exprs = <[Expr; N]>::try_from(exprs).map_err(|_| Error::Invalid("expression"))?;
+    Ok(Box::new(exprs))
+}
+
+impl Encode for Span {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.indice, self.length).encode(buf)
+    }
+}
+
+impl Decode for Span {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let (indice, length) = Decode::decode(r)?;
+        Ok(Span::new(indice, length))
+    }
+}
+
+impl Encode for key::TypeKind {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            key::TypeKind::Record(record) => (0u8, record).encode(buf),
+            key::TypeKind::Sum(sum) => (1u8, sum).encode(buf),
+            key::TypeKind::Trait(trait_) => (2u8, trait_).encode(buf),
+        }
+    }
+}
+
+impl Decode for key::TypeKind {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => key::TypeKind::Record(key::Record::decode(r)?),
+            1 => key::TypeKind::Sum(key::Sum::decode(r)?),
+            2 => key::TypeKind::Trait(key::Trait::decode(r)?),
+            _ => return Err(Error::Invalid("type kind")),
+        })
+    }
+}
+
+impl Encode for NFunc {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            NFunc::Key(func) => (0u8, func).encode(buf),
+            NFunc::Method(trait_, method) => {
+                (1u8, trait_).encode(buf);
+                method.encode(buf);
+            }
+            NFunc::SumVar(sum, var) => {
+                (2u8, sum).encode(buf);
+                var.encode(buf);
+            }
+            NFunc::Val(val) => (3u8, val).encode(buf),
+        }
+    }
+}
+
+impl Decode for NFunc {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => NFunc::Key(key::Func::decode(r)?),
+            1 => NFunc::Method(key::Trait::decode(r)?, key::Method::decode(r)?),
+            2 => NFunc::SumVar(key::Sum::decode(r)?, key::Variant::decode(r)?),
+            3 => NFunc::Val(key::Val::decode(r)?),
+            _ => return Err(Error::Invalid("function")),
+        })
+    }
+}
+
+impl Encode for Container {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Container::FnPointer => buf.push(0),
+            Container::Closure => buf.push(1),
+            Container::Tuple => buf.push(2),
+            Container::Pointer => buf.push(3),
+            Container::Array => buf.push(4),
+            Container::Slice => buf.push(5),
+            Container::Defined(kind, lang) => {
+                let lang = match lang.inner {
+                    Lang::String => 0u8,
+                    Lang::List => 1,
+                    Lang::None => 2,
+                };
+                (6u8, kind).encode(buf);
+                lang.encode(buf);
+            }
+        }
+    }
+}
+
+impl Decode for Container {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => Container::FnPointer,
+            1 => Container::Closure,
+            2 => Container::Tuple,
+            3 => Container::Pointer,
+            4 => Container::Array,
+            5 => Container::Slice,
+            6 => {
+                let kind = M::decode(r)?;
+                let lang = match r.byte()? {
+                    0 => Lang::String,
+                    1 => Lang::List,
+                    2 => Lang::None,
+                    _ => return Err(Error::Invalid("language item")),
+                };
+                Container::Defined(kind, Ignored::new(lang))
+            }
+            _ => return Err(Error::Invalid("container")),
+        })
+    }
+}
+
+impl Encode for Generic {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.key.encode(buf);
+        match self.kind {
+            GenericKind::Lambda(lambda) => (0u8, lambda).encode(buf),
+            GenericKind::Entity => buf.push(1),
+            GenericKind::Parent => buf.push(2),
+        }
+    }
+}
+
+impl Decode for Generic {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let key = key::Generic::decode(r)?;
+        let kind = match r.byte()? {
+            0 => GenericKind::Lambda(key::Lambda::decode(r)?),
+            1 => GenericKind::Entity,
+            2 => GenericKind::Parent,
+            _ => return Err(Error::Invalid("generic")),
+        };
+        Ok(Generic { key, kind })
+    }
+}
+
+impl Encode for Type {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Ty::Container(container, params) => {
+                (0u8, container).encode(buf);
+                params.encode(buf);
+            }
+            Ty::Generic(generic) => (1u8, generic).encode(buf),
+            Ty::Int(intsize) => (2u8, intsize).encode(buf),
+            Ty::Const(const_) => (3u8, const_).encode(buf),
+            Ty::Simple(name) => (4u8, name.as_bytes()).encode(buf),
+            Ty::Special(Static) => buf.push(5),
+        }
+    }
+}
+
+impl Decode for Type {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => Ty::Container(Container::decode(r)?, Vec::decode(r)?),
+            1 => Ty::Generic(Generic::decode(r)?),
+            2 => Ty::Int(IntSize::decode(r)?),
+            3 => Ty::Const(ConstValue::decode(r)?),
+            4 => Ty::Simple(decode_name(r, SIMPLE_TYPES, "type")?),
+            5 => Ty::Special(Static),
+            _ => return Err(Error::Invalid("type")),
+        })
+    }
+}
+
+impl Encode for Constraint<Static> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.span, self.trait_).encode(buf);
+        self.params.encode(buf);
+    }
+}
+
+impl Decode for Constraint<Static> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(Constraint {
+            span: Span::decode(r)?,
+            trait_: M::decode(r)?,
+            params: Vec::decode(r)?,
+        })
+    }
+}
+
+// Generics are renamed after their keys before they're lowered, so the names aren't kept
+impl Encode for Forall<'static, Static> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.generics.len().encode(buf);
+        for data in self.generics.values() {
+            data.trait_constraints.encode(buf);
+            match data.const_ {
+                None => buf.push(0),
+                Some(ConstGeneric::Int(intsize)) => (1u8, intsize).encode(buf),
+                Some(ConstGeneric::Bool) => buf.push(2),
+                Some(ConstGeneric::Char) => buf.push(3),
+            }
+        }
+    }
+}
+
+impl Decode for Forall<'static, Static> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let len = r.len()?;
+        let mut forall = Forall::new(len);
+        for i in 0..len {
+            let name = Forall::<()>::name_by_key(key::Generic(i as u32), "");
+            let trait_constraints = Vec::decode(r)?;
+            let const_ = match r.byte()? {
+                0 => None,
+                1 => Some(ConstGeneric::Int(IntSize::decode(r)?)),
+                2 => Some(ConstGeneric::Bool),
+                3 => Some(ConstGeneric::Char),
+                _ => return Err(Error::Invalid("generic")),
+            };
+            forall
+                .generics
+                .push(GenericData { name, trait_constraints, const_ });
+        }
+        Ok(forall)
+    }
+}
+
+impl Encode for GenericMapper<Static> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (&self.generics, &self.self_).encode(buf)
+    }
+}
+
+impl Decode for GenericMapper<Static> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(GenericMapper { generics: Vec::decode(r)?, self_: Option::decode(r)? })
+    }
+}
+
+impl Encode for ConcreteTyping {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (&self.forall, &self.params).encode(buf);
+        self.returns.encode(buf);
+    }
+}
+
+impl Decode for ConcreteTyping {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(ConcreteTyping {
+            forall: Forall::decode(r)?,
+            params: Vec::decode(r)?,
+            returns: Type::decode(r)?,
+        })
+    }
+}
+
+impl Encode for Function {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (&self.typing, &self.lambdas).encode(buf);
+        (&self.lcaptures, self.no_mangle).encode(buf);
+        (&self.export, &self.linkage).encode(buf);
+        self.expr.encode(buf);
+    }
+}
+
+impl Decode for Function {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(Function {
+            typing: ConcreteTyping::decode(r)?,
+            lambdas: Map::decode(r)?,
+            lcaptures: Map::decode(r)?,
+            no_mangle: bool::decode(r)?,
+            export: Option::decode(r)?,
+            linkage: ast::Linkage::decode(r)?,
+            expr: Expr::decode(r)?,
+        })
+    }
+}
+
+impl Encode for Lambda {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (&self.typing, &self.expr).encode(buf)
+    }
+}
+
+impl Decode for Lambda {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(Lambda { typing: ConcreteTyping::decode(r)?, expr: Expr::decode(r)? })
+    }
+}
+
+impl Encode for Callable {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Callable::Func(func, mapper) => {
+                (0u8, func).encode(buf);
+                mapper.encode(buf);
+            }
+            Callable::Impl(impl_, method, mapper) => {
+                (1u8, impl_).encode(buf);
+                (method, mapper).encode(buf);
+            }
+            Callable::Lambda(lambda, mapper) => {
+                (2u8, lambda).encode(buf);
+                mapper.encode(buf);
+            }
+            Callable::Binding(bind) => (3u8, bind).encode(buf),
+            Callable::Param(param) => (4u8, param).encode(buf),
+        }
+    }
+}
+
+impl Decode for Callable {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => Callable::Func(M::decode(r)?, GenericMapper::decode(r)?),
+            1 => Callable::Impl(
+                M::decode(r)?,
+                key::Method::decode(r)?,
+                GenericMapper::decode(r)?,
+            ),
+            2 => Callable::Lambda(key::Lambda::decode(r)?, GenericMapper::decode(r)?),
+            3 => Callable::Binding(key::Bind::decode(r)?),
+            4 => Callable::Param(key::Param::decode(r)?),
+            _ => return Err(Error::Invalid("callable")),
+        })
+    }
+}
+
+impl Encode for Expr {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Expr::Call(call, params) => {
+                (0u8, call).encode(buf);
+                params.encode(buf);
+            }
+            Expr::PartiallyApplicate(call, params) => {
+                (1u8, call).encode(buf);
+                params.encode(buf);
+            }
+            Expr::Yield(call) => (2u8, call).encode(buf),
+            Expr::TupleAccess(inner, i) => {
+                (3u8, inner).encode(buf);
+                i.encode(buf);
+            }
+            Expr::Access(inner, record, params, field) => {
+                (4u8, inner).encode(buf);
+                (record, params).encode(buf);
+                field.encode(buf);
+            }
+            Expr::Record(record, params, fields) => {
+                (5u8, record).encode(buf);
+                params.encode(buf);
+                fields.len().encode(buf);
+                for (field, span, value) in fields {
+                    (field, span).encode(buf);
+                    value.encode(buf);
+                }
+            }
+            Expr::Array(elems, len, ty) => {
+                (6u8, elems).encode(buf);
+                (len, ty).encode(buf);
+            }
+            Expr::GenericArray(elem, generic, ty) => {
+                (7u8, elem).encode(buf);
+                (generic, ty).encode(buf);
+            }
+            Expr::ArrayAccess(p) => {
+                buf.push(8);
+                encode_exprs(p, buf);
+            }
+            Expr::Int(intsize, n) => {
+                (9u8, intsize).encode(buf);
+                n.encode(buf);
+            }
+            Expr::Bool(b) => (10u8, b).encode(buf),
+            Expr::Float(n) => (11u8, n).encode(buf),
+            Expr::ReadOnly(ro) => (12u8, ro).encode(buf),
+            Expr::PointerToPointerCast(inner, ty) => {
+                (13u8, inner).encode(buf);
+                ty.encode(buf);
+            }
+            Expr::PointerToArrayCast(inner, len, ty) => {
+                (14u8, inner).encode(buf);
+                (len, ty).encode(buf);
+            }
+            Expr::PointerToGenericArrayCast(inner, generic, ty) => {
+                (15u8, inner).encode(buf);
+                (generic, ty).encode(buf);
+            }
+            Expr::ToPointerCast(inner, intsize, ty) => {
+                (16u8, inner).encode(buf);
+                (intsize, ty).encode(buf);
+            }
+            Expr::FromPointerCast(inner, intsize) => {
+                (17u8, inner).encode(buf);
+                intsize.encode(buf);
+            }
+            Expr::IntCast(inner, from, to) => {
+                (18u8, inner).encode(buf);
+                (from, to).encode(buf);
+            }
+            Expr::ToFloatCast(inner, from, to) => {
+                (19u8, inner).encode(buf);
+                (from, to).encode(buf);
+            }
+            Expr::FromFloatCast(inner, from, to) => {
+                (20u8, inner).encode(buf);
+                (from, to).encode(buf);
+            }
+            Expr::FloatCast(inner, from, to) => {
+                (21u8, inner).encode(buf);
+                (from, to).encode(buf);
+            }
+            Expr::ArrayLen(inner) => (22u8, inner).encode(buf),
+            Expr::ObjectCast(inner, ty, trait_, params) => {
+                (23u8, inner).encode(buf);
+                (ty, trait_).encode(buf);
+                params.encode(buf);
+            }
+            Expr::MemCpy(p) => {
+                buf.push(24);
+                encode_exprs(p, buf);
+            }
+            Expr::MemSet(p) => {
+                buf.push(25);
+                encode_exprs(p, buf);
+            }
+            Expr::Deref(inner) => (26u8, inner).encode(buf),
+            Expr::Write(p) => {
+                buf.push(27);
+                encode_exprs(p, buf);
+            }
+            Expr::Atomic(op, ordering, params) => {
+                (28u8, op.as_bytes()).encode(buf);
+                (ordering.as_bytes(), params).encode(buf);
+            }
+            Expr::Slice(op, params) => {
+                (29u8, op.as_bytes()).encode(buf);
+                params.encode(buf);
+            }
+            Expr::ReflectTypeOf(ty) => (30u8, ty).encode(buf),
+            Expr::SizeOf(ty) => (31u8, ty).encode(buf),
+            Expr::AlignOf(ty) => (32u8, ty).encode(buf),
+            Expr::Alloca(ty) => (33u8, ty).encode(buf),
+            Expr::Unreachable(ty) => (34u8, ty).encode(buf),
+            Expr::Breakpoint => buf.push(35),
+            Expr::Cmp(op, p) => {
+                (36u8, op.as_bytes()).encode(buf);
+                encode_exprs(p, buf);
+            }
+            Expr::Num(op, p) => {
+                (37u8, op.as_bytes()).encode(buf);
+                encode_exprs(p, buf);
+            }
+            Expr::NumCarry(op, p) => {
+                (38u8, op.as_bytes()).encode(buf);
+                encode_exprs(p, buf);
+            }
+            Expr::SelectValue(p) => {
+                buf.push(39);
+                encode_exprs(p, buf);
+            }
+            Expr::IntAbs(inner) => (40u8, inner).encode(buf),
+            Expr::Neg(inner) => (41u8, inner).encode(buf),
+            Expr::ValToRef(inner) => (42u8, inner).encode(buf),
+            Expr::Expect(inner, expected) => {
+                (43u8, inner).encode(buf);
+                expected.encode(buf);
+            }
+            Expr::Tuple(elems) => (44u8, elems).encode(buf),
+            Expr::Located(span, inner) => {
+                (45u8, span).encode(buf);
+                inner.encode(buf);
+            }
+            Expr::Match(on, tree, tails, reached) => {
+                (46u8, on).encode(buf);
+                (tree, tails).encode(buf);
+                reached.encode(buf);
+            }
+            Expr::Poison => buf.push(47),
+        }
+    }
+}
+
+impl Decode for Expr {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => Expr::Call(Callable::decode(r)?, Vec::decode(r)?),
+            1 => Expr::PartiallyApplicate(Callable::decode(r)?, Vec::decode(r)?),
+            2 => Expr::Yield(Callable::decode(r)?),
+            3 => Expr::TupleAccess(Box::decode(r)?, usize::decode(r)?),
+            4 => Expr::Access(
+                Box::decode(r)?,
+                M::decode(r)?,
+                Vec::decode(r)?,
+                key::Field::decode(r)?,
+            ),
+            5 => {
+                let record = M::decode(r)?;
+                let params = Vec::decode(r)?;
+                let len = r.len()?;
+                let fields = (0..len)
+                    .map(|_| {
+                        let (field, span) = Decode::decode(r)?;
+                        Ok((field, span, Expr::decode(r)?))
+                    })
+                    .collect::<Result<_, Error>>()?;
+                Expr::Record(record, params, fields)
+            }
+            6 => Expr::Array(Vec::decode(r)?, u64::decode(r)?, Type::decode(r)?),
+            7 => Expr::GenericArray(Box::decode(r)?, Generic::decode(r)?, Type::decode(r)?),
+            8 => Expr::ArrayAccess(decode_exprs(r)?),
+            9 => Expr::Int(IntSize::decode(r)?, i128::decode(r)?),
+            10 => Expr::Bool(bool::decode(r)?),
+            11 => Expr::Float(f64::decode(r)?),
+            12 => Expr::ReadOnly(M::decode(r)?),
+            13 => Expr::PointerToPointerCast(Box::decode(r)?, Type::decode(r)?),
+            14 => Expr::PointerToArrayCast(Box::decode(r)?, u64::decode(r)?, Type::decode(r)?),
+            15 => Expr::PointerToGenericArrayCast(
+                Box::decode(r)?,
+                Generic::decode(r)?,
+                Type::decode(r)?,
+            ),
+            16 => Expr::ToPointerCast(Box::decode(r)?, IntSize::decode(r)?, Type::decode(r)?),
+            17 => Expr::FromPointerCast(Box::decode(r)?, IntSize::decode(r)?),
+            18 => Expr::IntCast(Box::decode(r)?, IntSize::decode(r)?, IntSize::decode(r)?),
+            19 => Expr::ToFloatCast(Box::decode(r)?, IntSize::decode(r)?, FloatSize::decode(r)?),
+            20 => Expr::FromFloatCast(Box::decode(r)?, FloatSize::decode(r)?, IntSize::decode(r)?),
+            21 => Expr::FloatCast(
+                Box::decode(r)?,
+                FloatSize::decode(r)?,
+                FloatSize::decode(r)?,
+            ),
+            22 => Expr::ArrayLen(Box::decode(r)?),
+            23 => Expr::ObjectCast(
+                Box::decode(r)?,
+                Type::decode(r)?,
+                M::decode(r)?,
+                Vec::decode(r)?,
+            ),
+            24 => Expr::MemCpy(decode_exprs(r)?),
+            25 => Expr::MemSet(decode_exprs(r)?),
+            26 => Expr::Deref(Box::decode(r)?),
+            27 => Expr::Write(decode_exprs(r)?),
+            28 => Expr::Atomic(
+                decode_name(r, BUILTINS, "builtin")?,
+                decode_name(r, BUILTINS, "builtin")?,
+                Vec::decode(r)?,
+            ),
+            29 => Expr::Slice(decode_name(r, BUILTINS, "builtin")?, Vec::decode(r)?),
+            30 => Expr::ReflectTypeOf(Type::decode(r)?),
+            31 => Expr::SizeOf(Type::decode(r)?),
+            32 => Expr::AlignOf(Type::decode(r)?),
+            33 => Expr::Alloca(Type::decode(r)?),
+            34 => Expr::Unreachable(Type::decode(r)?),
+            35 => Expr::Breakpoint,
+            36 => Expr::Cmp(decode_name(r, BUILTINS, "builtin")?, decode_exprs(r)?),
+            37 => Expr::Num(decode_name(r, BUILTINS, "builtin")?, decode_exprs(r)?),
+            38 => Expr::NumCarry(decode_name(r, BUILTINS, "builtin")?, decode_exprs(r)?),
+            39 => Expr::SelectValue(decode_exprs(r)?),
+            40 => Expr::IntAbs(Box::decode(r)?),
+            41 => Expr::Neg(Box::decode(r)?),
+            42 => Expr::ValToRef(Box::decode(r)?),
+            43 => Expr::Expect(Box::decode(r)?, bool::decode(r)?),
+            44 => Expr::Tuple(Vec::decode(r)?),
+            45 => Expr::Located(Span::decode(r)?, Box::decode(r)?),
+            46 => Expr::Match(
+                Box::decode(r)?,
+                pat::DecTree::decode(r)?,
+                Map::decode(r)?,
+                Map::decode(r)?,
+            ),
+            47 => Expr::Poison,
+            _ => return Err(Error::Invalid("expression")),
+        })
+    }
+}
+
+impl<Tail: Encode> Encode for pat::DecTree<Tail> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            pat::DecTree::Record { record, params, fields, next } => {
+                (0u8, record).encode(buf);
+                (params, fields).encode(buf);
+                next.encode(buf);
+            }
+            pat::DecTree::Tuple { elems, next } => {
+                (1u8, elems).encode(buf);
+                next.encode(buf);
+            }
+            pat::DecTree::Array { elems, next } => {
+                (2u8, elems).encode(buf);
+                next.encode(buf);
+            }
+            pat::DecTree::Sum { sum, params, next } => {
+                (3u8, sum).encode(buf);
+                (params, next).encode(buf);
+            }
+            pat::DecTree::List { next, ty } => {
+                (4u8, next).encode(buf);
+                ty.encode(buf);
+            }
+            pat::DecTree::String { next, wildcard_next } => {
+                (5u8, next).encode(buf);
+                wildcard_next.encode(buf);
+            }
+            pat::DecTree::Bytes { next, wildcard_next } => {
+                (6u8, next).encode(buf);
+                wildcard_next.encode(buf);
+            }
+            pat::DecTree::Ints { intsize, next } => {
+                (7u8, intsize).encode(buf);
+                next.encode(buf);
+            }
+            pat::DecTree::Bools(next) => (8u8, next).encode(buf),
+            pat::DecTree::Wildcard { ty, next } => {
+                (9u8, ty).encode(buf);
+                next.encode(buf);
+            }
+            pat::DecTree::Opaque { ty, next } => {
+                (10u8, ty).encode(buf);
+                next.encode(buf);
+            }
+            pat::DecTree::End(tail) => (11u8, tail).encode(buf),
+        }
+    }
+}
+
+impl<Tail: Decode> Decode for pat::DecTree<Tail> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => pat::DecTree::Record {
+                record: M::decode(r)?,
+                params: Vec::decode(r)?,
+                fields: usize::decode(r)?,
+                next: Box::decode(r)?,
+            },
+            1 => pat::DecTree::Tuple { elems: usize::decode(r)?, next: Box::decode(r)? },
+            2 => pat::DecTree::Array { elems: u64::decode(r)?, next: Box::decode(r)? },
+            3 => pat::DecTree::Sum {
+                sum: M::decode(r)?,
+                params: Vec::decode(r)?,
+                next: pat::Branching::decode(r)?,
+            },
+            4 => pat::DecTree::List { next: pat::Branching::decode(r)?, ty: Type::decode(r)? },
+            5 => pat::DecTree::String {
+                next: pat::Branching::decode(r)?,
+                wildcard_next: Box::decode(r)?,
+            },
+            6 => pat::DecTree::Bytes {
+                next: pat::Branching::decode(r)?,
+                wildcard_next: Box::decode(r)?,
+            },
+            7 => pat::DecTree::Ints {
+                intsize: IntSize::decode(r)?,
+                next: pat::Branching::decode(r)?,
+            },
+            8 => pat::DecTree::Bools(pat::Branching::decode(r)?),
+            9 => pat::DecTree::Wildcard { ty: Type::decode(r)?, next: Box::decode(r)? },
+            10 => pat::DecTree::Opaque { ty: Type::decode(r)?, next: Box::decode(r)? },
+            11 => pat::DecTree::End(pat::TreeTail::decode(r)?),
+            _ => return Err(Error::Invalid("decision tree")),
+        })
+    }
+}
+
+impl<K: pat::BranchKey + Encode, Tail: Encode> Encode for pat::Branching<K, Tail> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.branches.encode(buf)
+    }
+}
+
+impl<K: pat::BranchKey + Decode, Tail: Decode> Decode for pat::Branching<K, Tail> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(pat::Branching { branches: Vec::decode(r)? })
+    }
+}
+
+impl<Tail: Encode> Encode for pat::TreeTail<Tail> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            pat::TreeTail::Poison => buf.push(0),
+            pat::TreeTail::Unreached(excess) => (1u8, excess).encode(buf),
+            pat::TreeTail::Reached(table, excess, tail) => {
+                (2u8, &table.binds).encode(buf);
+                (excess, tail).encode(buf);
+            }
+        }
+    }
+}
+
+impl<Tail: Decode> Decode for pat::TreeTail<Tail> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => pat::TreeTail::Poison,
+            1 => pat::TreeTail::Unreached(Decode::decode(r)?),
+            2 => pat::TreeTail::Reached(
+                pat::PointTable::new(Vec::decode(r)?),
+                Decode::decode(r)?,
+                Tail::decode(r)?,
+            ),
+            _ => return Err(Error::Invalid("decision tree tail")),
+        })
+    }
+}
+
+impl Encode for pat::Range {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.con.min, self.con.max).encode(buf);
+        (self.start, self.end).encode(buf);
+    }
+}
+
+impl Decode for pat::Range {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let (min, max) = Decode::decode(r)?;
+        let (start, end) = Decode::decode(r)?;
+        Ok(pat::Range { con: pat::Constraints { min, max }, start, end })
+    }
+}
+
+impl Encode for pat::StrChecks {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.checks.encode(buf)
+    }
+}
+
+impl Decode for pat::StrChecks {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(pat::StrChecks { checks: Vec::decode(r)? })
+    }
+}
+
+impl Encode for pat::StrCheck {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            pat::StrCheck::Literal(ro) => (0u8, ro).encode(buf),
+            pat::StrCheck::Suffix(ro) => (1u8, ro).encode(buf),
+            pat::StrCheck::TakeExcess => buf.push(2),
+            pat::StrCheck::TakeByte => buf.push(3),
+            pat::StrCheck::TakeWhile(call, params) => {
+                (4u8, call).encode(buf);
+                params.encode(buf);
+            }
+            pat::StrCheck::TakeBySplit(call, ty, params) => {
+                (5u8, call).encode(buf);
+                (ty, params).encode(buf);
+            }
+        }
+    }
+}
+
+impl Decode for pat::StrCheck {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => pat::StrCheck::Literal(M::decode(r)?),
+            1 => pat::StrCheck::Suffix(M::decode(r)?),
+            2 => pat::StrCheck::TakeExcess,
+            3 => pat::StrCheck::TakeByte,
+            4 => pat::StrCheck::TakeWhile(Callable::decode(r)?, Vec::decode(r)?),
+            5 => {
+                pat::StrCheck::TakeBySplit(Callable::decode(r)?, Type::decode(r)?, Vec::decode(r)?)
+            }
+            _ => return Err(Error::Invalid("string pattern")),
+        })
+    }
+}
+
+impl Encode for pat::ByteChecks {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.checks.encode(buf)
+    }
+}
+
+impl Decode for pat::ByteChecks {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(pat::ByteChecks { checks: Vec::decode(r)? })
+    }
+}
+
+impl Encode for pat::ByteCheck {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            pat::ByteCheck::Int(intsize) => (0u8, intsize).encode(buf),
+            pat::ByteCheck::Literal(intsize, n) => {
+                (1u8, intsize).encode(buf);
+                n.encode(buf);
+            }
+            pat::ByteCheck::Bytes(pat::ByteLen::Const(n)) => (2u8, n).encode(buf),
+            pat::ByteCheck::Bytes(pat::ByteLen::Check(i)) => (3u8, i).encode(buf),
+            pat::ByteCheck::Rest => buf.push(4),
+        }
+    }
+}
+
+impl Decode for pat::ByteCheck {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(match r.byte()? {
+            0 => pat::ByteCheck::Int(IntSize::decode(r)?),
+            1 => pat::ByteCheck::Literal(IntSize::decode(r)?, i128::decode(r)?),
+            2 => pat::ByteCheck::Bytes(pat::ByteLen::Const(u64::decode(r)?)),
+            3 => pat::ByteCheck::Bytes(pat::ByteLen::Check(usize::decode(r)?)),
+            4 => pat::ByteCheck::Rest,
+            _ => return Err(Error::Invalid("binary pattern")),
+        })
+    }
+}
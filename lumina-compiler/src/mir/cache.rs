@@ -0,0 +1,267 @@
+//! Lowered functions of each module kept from previous builds
+//!
+//! Lowering a module only depends on the module itself and the modules it refers to, so a module
+//! is taken from the cache as long as none of those have changed since it was lowered. Those are
+//! the modules it imports and the modules of every item its MIR refers to. A module which has to
+//! be lowered again in turn invalidates every module which depends on it.
+//!
+//! Implementations are visible from every module, so changing which types implement which traits
+//! invalidates the entire cache.
+
+use super::lower::Function;
+use super::{FunctionStatus, ReadOnlyBytes};
+use crate::lir::bytecode::{Decode, Encode, Error, Reader};
+use crate::prelude::*;
+use lumina_typesystem::Type;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+const MAGIC: [u8; 4] = *b"LMIR";
+
+/// Bumped whenever the encoding of MIR changes
+const VERSION: u32 = 1;
+
+/// Conventional file extension of the cached MIR of a module
+const EXTENSION: &str = "lmm";
+
+pub struct ModuleCache {
+    dir: PathBuf,
+    settings: u64,
+    /// Names of the modules which the last build lowered instead of taking from the cache
+    pub lowered: Vec<String>,
+}
+
+/// What the modules of a build are lowered against
+struct Header {
+    salt: u64,
+    /// Hash of the path and source of every module, indexed by module
+    hashes: Vec<u64>,
+}
+
+impl Header {
+    fn unchanged(&self, since: &[u64], module: key::Module) -> bool {
+        let i: usize = module.into();
+        since.get(i).is_some() && since.get(i) == self.hashes.get(i)
+    }
+}
+
+struct Entry {
+    funcs: Vec<Option<Function>>,
+    read_only_table: Map<key::ReadOnly, (ReadOnlyBytes, Type)>,
+    dependencies: HashSet<key::Module>,
+}
+
+impl ModuleCache {
+    /// `settings` is a hash of everything other than the sources which lowering depends on
+    pub fn new(dir: PathBuf, settings: u64) -> Self {
+        ModuleCache { dir, settings, lowered: vec![] }
+    }
+
+    fn header(&self, hir: &hir::HIR) -> Header {
+        let mut hasher = DefaultHasher::new();
+        self.settings.hash(&mut hasher);
+        for impl_ in hir.impls.iter() {
+            let (trait_, params) = &hir.itraits[impl_];
+            (impl_, trait_).hash(&mut hasher);
+            params
+                .iter()
+                .for_each(|ty| ty.to_string().hash(&mut hasher));
+            hir.impltors[impl_].to_string().hash(&mut hasher);
+            for generic in hir.impls[impl_].generics.values() {
+                for constraint in &generic.trait_constraints {
+                    constraint.to_string().hash(&mut hasher);
+                }
+            }
+        }
+
+        let hashes = hir
+            .sources
+            .modules()
+            .map(|module| {
+                let mut hasher = DefaultHasher::new();
+                hir.sources.get_path(module).hash(&mut hasher);
+                hir.sources.get(module).hash(&mut hasher);
+                hasher.finish()
+            })
+            .collect();
+
+        Header { salt: hasher.finish(), hashes }
+    }
+
+    fn path(&self, hir: &hir::HIR, module: key::Module) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        hir.sources.get_path(module).hash(&mut hasher);
+        let name = hir.sources.name_of_module(module);
+        self.dir
+            .join(format!("{name}-{:016x}.{EXTENSION}", hasher.finish()))
+    }
+
+    /// Set the functions of every module which doesn't have to be lowered again, returning those
+    /// modules
+    pub(super) fn load(
+        &mut self,
+        hir: &hir::HIR,
+        funcs: &mut MMap<key::Func, FunctionStatus>,
+        read_only_table: &mut MMap<key::ReadOnly, (ReadOnlyBytes, Type)>,
+    ) -> HashSet<key::Module> {
+        let header = self.header(hir);
+
+        let mut entries = hir
+            .sources
+            .modules()
+            .filter_map(|module| {
+                let bytes = std::fs::read(self.path(hir, module)).ok()?;
+                // Entries written by other versions of the compiler are lowered again
+                let entry = decode(hir, &header, module, &bytes).ok().flatten()?;
+                Some((module, entry))
+            })
+            .collect::<HashMap<_, _>>();
+
+        loop {
+            let invalidated = entries
+                .iter()
+                .filter(|(_, entry)| {
+                    entry
+                        .dependencies
+                        .iter()
+                        .any(|dep| !entries.contains_key(dep))
+                })
+                .map(|(module, _)| *module)
+                .collect::<Vec<_>>();
+
+            if invalidated.is_empty() {
+                break;
+            }
+
+            for module in invalidated {
+                entries.remove(&module);
+            }
+        }
+
+        let cached = entries.keys().copied().collect::<HashSet<_>>();
+
+        for (module, entry) in entries {
+            for (status, func) in funcs[module].values_mut().zip(entry.funcs) {
+                if let Some(func) = func {
+                    *status = FunctionStatus::Done(func);
+                }
+            }
+            read_only_table[module] = entry.read_only_table;
+        }
+
+        self.lowered = hir
+            .sources
+            .modules()
+            .filter(|module| !cached.contains(module))
+            .map(|module| hir.sources.name_of_module(module))
+            .collect();
+
+        info!("{} modules taken from the MIR cache", cached.len());
+
+        cached
+    }
+
+    /// Write the modules which were lowered, replacing their previous entries
+    ///
+    /// Failing to write the cache isn't fatal, the next build will just lower the modules again.
+    pub(super) fn store(
+        &self,
+        hir: &hir::HIR,
+        funcs: &MMap<key::Func, FunctionStatus>,
+        read_only_table: &MMap<key::ReadOnly, (ReadOnlyBytes, Type)>,
+        cached: &HashSet<key::Module>,
+    ) {
+        let header = self.header(hir);
+
+        if let Err(err) = std::fs::create_dir_all(&self.dir) {
+            warn!("could not write MIR cache {}: {err}", self.dir.display());
+            return;
+        }
+
+        for module in hir.sources.modules().filter(|m| !cached.contains(m)) {
+            // Extern functions are quick to set up again
+            let lowered = funcs[module]
+                .values()
+                .map(|status| match status {
+                    FunctionStatus::Done(func) => Some(func),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            let mut buf = MAGIC.to_vec();
+            VERSION.encode(&mut buf);
+            (header.salt, &header.hashes).encode(&mut buf);
+            (lowered, &read_only_table[module]).encode(&mut buf);
+
+            if let Err(err) = std::fs::write(self.path(hir, module), buf) {
+                warn!("could not write MIR cache {}: {err}", self.dir.display());
+                return;
+            }
+        }
+
+        // Remove the entries of modules which no longer exist
+        let current = hir
+            .sources
+            .modules()
+            .map(|module| self.path(hir, module))
+            .collect::<HashSet<_>>();
+
+        for entry in std::fs::read_dir(&self.dir).into_iter().flatten().flatten() {
+            if !current.contains(&entry.path()) {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+/// Decode the entry of `module`, or `None` if it or anything it depends on has changed since it
+/// was lowered
+fn decode(
+    hir: &hir::HIR,
+    header: &Header,
+    module: key::Module,
+    bytes: &[u8],
+) -> Result<Option<Entry>, Error> {
+    let mut r = Reader::new(bytes);
+    if r.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err(Error::NotBytecode);
+    }
+    match u32::decode(&mut r)? {
+        VERSION => {}
+        other => return Err(Error::Version(other)),
+    }
+
+    let salt = u64::decode(&mut r)?;
+    let hashes = Vec::<u64>::decode(&mut r)?;
+    if salt != header.salt || !header.unchanged(&hashes, module) {
+        return Ok(None);
+    }
+
+    let funcs = Vec::<Option<Function>>::decode(&mut r)?;
+    let read_only_table = Map::decode(&mut r)?;
+    if !r.is_empty() {
+        return Err(Error::Invalid("trailing data"));
+    }
+    if funcs.len() != hir.funcs[module].len() {
+        return Ok(None);
+    }
+
+    let dependencies = r
+        .modules
+        .iter()
+        .copied()
+        .chain(hir.lookups.imported_modules(module))
+        .chain([key::PRELUDE])
+        .collect::<HashSet<_>>();
+
+    if !dependencies
+        .iter()
+        .all(|dep| header.unchanged(&hashes, *dep))
+    {
+        return Ok(None);
+    }
+
+    Ok(Some(Entry { funcs, read_only_table, dependencies }))
+}
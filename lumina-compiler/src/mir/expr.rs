@@ -2,7 +2,7 @@ use super::func::InstCall;
 use super::tcheck::SameAsCheck;
 use super::Verify;
 use crate::prelude::*;
-use lumina_typesystem::IType;
+use lumina_typesystem::{Container, IType, Ty};
 
 impl<'a, 's> Verify<'a, 's> {
     pub fn type_check_expr(&mut self, expr: Tr<&hir::Expr<'s>>) -> Tr<IType> {
@@ -12,6 +12,7 @@ impl<'a, 's> Verify<'a, 's> {
 
         match expr.value {
             hir::Expr::Call(call, tanot, params) => {
+                self.check_callbacks(call, params);
                 let ptypes = self.type_check_params(params);
                 let lhs = ptypes.last();
                 let instcall = self.type_of_callable(expr.span, lhs, call, params.len(), tanot);
@@ -191,6 +192,33 @@ impl<'a, 's> Verify<'a, 's> {
         }
     }
 
+    // C calls Lumina functions through a forwarder generated for each function given directly to
+    // an extern function, so function pointers coming from anywhere else can't be forwarded
+    fn check_callbacks(&mut self, call: &hir::Callable<'s>, params: &[Tr<hir::Expr<'s>>]) {
+        let hir::Callable::Func(nfunc) = call else {
+            return;
+        };
+        let ast::NFunc::Key(key) = nfunc.key else {
+            return;
+        };
+        let hir::FuncDefKind::Extern { typing, .. } = &self.hir.funcs[key.inside(nfunc.module)]
+        else {
+            return;
+        };
+
+        for (param, ty) in params.iter().zip(typing.params.values()) {
+            let is_callback = matches!(&ty.value, Ty::Container(Container::FnPointer, _));
+            if is_callback && !matches!(&param.value, hir::Expr::PassFnptr(..)) {
+                self.error("unsupported callback")
+                    .eline(param.span, "function pointer not known at compile time")
+                    .text(
+                        "only functions named directly with `#!` can be given to extern functions",
+                    )
+                    .emit();
+            }
+        }
+    }
+
     pub fn type_check_call(
         &mut self,
         span: Span,
@@ -200,12 +228,14 @@ impl<'a, 's> Verify<'a, 's> {
         match instcall {
             InstCall::LocalCall(espan, ptypes, ret, _) => {
                 if params.len() != ptypes.len() {
-                    self.error("invalid parameter amount")
-                        .eline(
-                            span,
-                            format!("expected {} got {}", ptypes.len(), params.len()),
-                        )
-                        .emit();
+                    let mut err = self.error("invalid parameter amount").eline(
+                        span,
+                        format!("expected {} got {}", ptypes.len(), params.len()),
+                    );
+                    if params.len() < ptypes.len() {
+                        err = err.fix(self.partial_application_fix(span));
+                    }
+                    err.emit();
                 }
 
                 warn!("TODO: merge errors of call");
@@ -232,6 +262,21 @@ impl<'a, 's> Verify<'a, 's> {
 
                 IType::poison()
             }
+            InstCall::Instantiated(instinfo)
+                if instinfo.variadic && params.len() >= instinfo.ptypes.len() =>
+            {
+                let (fixed, extra) = params.split_at(instinfo.ptypes.len());
+                self.type_check_and_emit_application(span, fixed, &instinfo.ptypes);
+
+                // Checked once their types are known, while lowering the call
+                let mut instinfo = instinfo;
+                instinfo.variadic_params = extra.to_vec();
+
+                let ret = instinfo.ret.value.clone();
+                self.current.push_inst(span, Some(instinfo));
+
+                ret
+            }
             InstCall::Instantiated(instinfo) => {
                 self.type_check_and_emit_application(span, &params, &instinfo.ptypes);
 
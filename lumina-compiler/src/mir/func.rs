@@ -6,25 +6,39 @@ use either::Either;
 use hir::HIR;
 use lumina_typesystem::{
     Compatibility, Constraint, ConstraintError, Container, DirectRecursion, Finalizer, Forall,
-    Generic, GenericKind, GenericMapper, IType, ImplIndex, Inference, Static, TEnv, Transformer,
-    Ty, Type, TypeSystem,
+    Generic, GenericKind, GenericMapper, IType, ImplIndex, Inference, Rejection, Static, TEnv,
+    Transformer, Ty, Type, TypeSystem,
 };
 use lumina_util::Highlighting;
 use std::fmt;
 use tracing::info_span;
 
+// Implementations listed when a trait constraint isn't met
+const MAX_NEAR_MISSES: usize = 5;
+
 #[derive(Clone, new, Debug)]
 pub struct InstInfo {
     pub module: key::Module,
     pub inst: GenericMapper<Inference>,
     pub ptypes: Vec<Tr<IType>>,
     pub ret: Tr<IType>,
+    /// Implementation selected explicitly with `Trait(name):method`
+    #[new(default)]
+    pub impl_: Option<M<key::Impl>>,
+    /// Extern functions ending in `...` accept more parameters than `ptypes`
+    #[new(default)]
+    pub variadic: bool,
+    #[new(default)]
+    pub variadic_params: Vec<Tr<IType>>,
 }
 
 pub enum FunctionStatus {
     Done(lower::Function),
     Extern {
         link_name: String,
+        data: bool,
+        variadic: bool,
+        library: Option<String>,
         typing: lower::ConcreteTyping,
     },
     InCallStack(Current),
@@ -103,10 +117,16 @@ impl<'a, 's> Verify<'a, 's> {
                         funcs[func] = FunctionStatus::Done(function);
                         return;
                     }
-                    hir::FuncDefKind::Extern { link_name, typing } => {
+                    hir::FuncDefKind::Extern { link_name, data, variadic, library, typing } => {
                         let typing = Self::lower_extern(typing);
-                        funcs[func] =
-                            FunctionStatus::Extern { link_name: link_name.clone(), typing };
+                        let link_name = link_name.clone();
+                        funcs[func] = FunctionStatus::Extern {
+                            link_name,
+                            data: *data,
+                            variadic: *variadic,
+                            library: library.clone(),
+                            typing,
+                        };
                         return;
                     }
                 };
@@ -136,11 +156,17 @@ impl<'a, 's> Verify<'a, 's> {
             FunctionStatus::InCallStack(_) => Err(false),
             FunctionStatus::Pending => {
                 match &self.hir.funcs[func] {
-                    hir::FuncDefKind::Extern { link_name, typing } => {
+                    hir::FuncDefKind::Extern { link_name, data, variadic, library, typing } => {
                         let typing = Self::lower_extern(typing);
 
-                        self.funcs[func] =
-                            FunctionStatus::Extern { link_name: link_name.clone(), typing };
+                        let link_name = link_name.clone();
+                        self.funcs[func] = FunctionStatus::Extern {
+                            link_name,
+                            data: *data,
+                            variadic: *variadic,
+                            library: library.clone(),
+                            typing,
+                        };
 
                         return Ok(());
                     }
@@ -298,6 +324,8 @@ impl<'a, 's> Verify<'a, 's> {
 
         let mut function = lower::Function::new(typing, lambdas, lcaptures, expr);
         function.no_mangle = self.fdef.no_mangle;
+        function.export = self.fdef.export.clone();
+        function.linkage = self.fdef.linkage.clone();
 
         function
     }
@@ -358,22 +386,57 @@ impl<'a, 's> Verify<'a, 's> {
                 }
                 ConstraintError::IntConstantNegativeUnsigned(_, _) => todo!(),
                 ConstraintError::IntConstantTooLarge(_, _, _) => todo!(),
-                ConstraintError::Trait(ty, con) if con.trait_ == self.items.pinfo.listable => {
+                ConstraintError::Trait(ty, con, _) if con.trait_ == self.items.pinfo.listable => {
                     let tfmt = self.ty_formatter();
                     let got = tfmt.clone().fmt(&*ty);
                     let exp = format!("[{}]", tfmt.fmt(&con.params[0]));
                     self.emit_type_mismatch(ty.span, "", got, exp);
                 }
-                ConstraintError::Trait(ty, con) => {
+                ConstraintError::Trait(ty, con, misses) => {
+                    let mut err = self.error("constraint not met").eline(
+                        ty.span,
+                        format!(
+                            "`{}` does not implement `{}`",
+                            self.ty_formatter().fmt(&*ty),
+                            self.ty_formatter().fmt((con.trait_, con.params.as_slice()))
+                        ),
+                    );
+
+                    for miss in misses.iter().take(MAX_NEAR_MISSES) {
+                        let text = match &miss.reason {
+                            Rejection::Mismatch => "does not match the types".to_string(),
+                            Rejection::Unmet(ty, con) => format!(
+                                "requires `{}` to implement `{}`",
+                                self.ty_formatter().fmt(ty),
+                                self.ty_formatter().fmt((con.trait_, con.params.as_slice()))
+                            ),
+                        };
+                        let span = self.hir.impltors[miss.ikey].span;
+                        err = err
+                            .m(miss.ikey.0)
+                            .iline(span, format!("`{}` {text}", self.impl_header(miss.ikey)));
+                    }
+
+                    if misses.len() > MAX_NEAR_MISSES {
+                        err = err.text(format!(
+                            "and {} other implementations",
+                            misses.len() - MAX_NEAR_MISSES
+                        ));
+                    }
+
+                    err.emit();
+                }
+                ConstraintError::TraitCycle(ty, con) => {
                     self.error("constraint not met")
                         .eline(
                             ty.span,
                             format!(
-                                "`{}` does not implement `{}`",
+                                "`{}` implementing `{}` depends on itself",
                                 self.ty_formatter().fmt(&*ty),
                                 self.ty_formatter().fmt((con.trait_, con.params.as_slice()))
                             ),
                         )
+                        .text("the constraints of the implementations which apply form a cycle")
                         .emit();
                 }
                 ConstraintError::FieldType { exp, got, at } => {
@@ -461,6 +524,23 @@ impl<'a, 's> Verify<'a, 's> {
         self.hir.sources.warning(name).m(self.current.fkey.0)
     }
 
+    // `impl Trait for Type` with the generics named as in the implementation
+    fn impl_header(&self, ikey: M<key::Impl>) -> String {
+        let iforall = self.hir.impls[ikey].names().collect();
+        let isize = self.target.int_size();
+        let env = &self.tenvs[self.current.fkey];
+        let tfmt = TyFmtState::new(self.hir, env, isize, Map::new(), iforall);
+        let (trait_, params) = &self.hir.itraits[ikey];
+
+        format!(
+            "{} {} {} {}",
+            "impl".keyword(),
+            tfmt.clone().fmt((*trait_, params.as_slice())),
+            "for".keyword(),
+            tfmt.fmt(&*self.hir.impltors[ikey])
+        )
+    }
+
     pub fn ty_formatter(&'a self) -> TyFmtState<'a, 's> {
         let forall = self.fdef.forall.borrow().names().collect();
         let pforall = self.pforall.names().collect();
@@ -536,6 +616,7 @@ impl<'a, 's> Verify<'a, 's> {
                 .and_then(|ty| self.module_of_type(ty.as_ref())),
             IType::Container(Container::Pointer, _) => self.hir.lookups.find_lib("std", "ptr"),
             IType::Container(Container::Array, _) => self.hir.lookups.find_lib("std", "array"),
+            IType::Container(Container::Slice, _) => self.hir.lookups.find_lib("std", "slice"),
             IType::Int(size) => self.hir.lookups.find_lib("std", "math").and_then(|math| {
                 match self
                     .hir
@@ -595,6 +676,10 @@ impl<'a, 's> Verify<'a, 's> {
     ) -> (IType, IType) {
         let lhs = params.last();
         match self.type_of_callable(span, lhs, call, params.len(), tanot) {
+            InstCall::LocalCall(_, ptypes, _, _) if params.len() > ptypes.len() => {
+                self.err_too_many_partial_arguments(span, ptypes.len(), params.len());
+                (IType::poison(), IType::poison())
+            }
             InstCall::LocalCall(_, ptypes, returns, cont) => {
                 let (applicated, xs) = ptypes.split_at(params.len());
                 params.iter().zip(applicated).for_each(|(g, e)| {
@@ -605,7 +690,17 @@ impl<'a, 's> Verify<'a, 's> {
                 remaining.push(returns.clone());
                 (IType::Container(cont, remaining), returns)
             }
-            InstCall::Local(_) if !params.is_empty() => todo!("ET: can not take parameters"),
+            InstCall::Local(ty) if !params.is_empty() => {
+                if !matches!(ty.value, IType::Simple("poison")) {
+                    let got = self.ty_formatter().fmt(&*ty);
+                    self.error("type mismatch")
+                        .eline(params[0].span, "parameter given to non-closure")
+                        .iline(span, format!("attempted to partially apply this {got}"))
+                        .emit();
+                }
+
+                (IType::poison(), IType::poison())
+            }
             // Turns into a closure yielding the inner value
             //
             // I'm unsure whether I want to allow this in the first place, since it could harm errors
@@ -620,8 +715,9 @@ impl<'a, 's> Verify<'a, 's> {
                 let instinfo = self.inst_direct_recursion(span);
                 self.partially_applicate_inst(span, instinfo, params)
             }
-            InstCall::CircularRecursion { .. } => {
-                todo!()
+            InstCall::CircularRecursion(key) => {
+                let instinfo = self.inst_indirect_recursion(span, key);
+                self.partially_applicate_inst(span, instinfo, params)
             }
             InstCall::TypeDependentFailure => (IType::poison(), IType::poison()),
         }
@@ -633,6 +729,12 @@ impl<'a, 's> Verify<'a, 's> {
         instinfo: InstInfo,
         params: Vec<Tr<IType>>,
     ) -> (IType, IType) {
+        if params.len() > instinfo.ptypes.len() {
+            self.err_too_many_partial_arguments(span, instinfo.ptypes.len(), params.len());
+            self.current.push_inst(span, None);
+            return (IType::poison(), IType::poison());
+        }
+
         self.type_check_and_emit_application(span, &params, &instinfo.ptypes[..params.len()]);
 
         let ret = instinfo.ret.value.clone();
@@ -646,6 +748,15 @@ impl<'a, 's> Verify<'a, 's> {
         (IType::closure(ptypes, ret.clone()), ret)
     }
 
+    fn err_too_many_partial_arguments(&self, span: Span, exp: usize, got: usize) {
+        self.error("too many function arguments")
+            .eline(
+                span,
+                format!("partially applied with {got} arguments, but it only takes {exp}"),
+            )
+            .emit();
+    }
+
     pub fn type_of_callable(
         &mut self,
         span: Span,
@@ -803,11 +914,20 @@ impl<'a, 's> Verify<'a, 's> {
                     panic!("non-var assignment to `self` from instantiation");
                 };
 
-                let params = finst.to_types(GenericKind::Parent);
-                self.vars()
-                    .add_trait_constraint(self_var, Constraint { span, trait_: *trait_, params });
+                // Named implementations aren't part of the index, so instead of constraining
+                // `self` the selected implementation is unified with the instantiation directly.
+                let impl_ = match tanot.for_impl {
+                    Some(name) => self.select_named_impl(name, *trait_, &finst),
+                    None => {
+                        let params = finst.to_types(GenericKind::Parent);
+                        let con = Constraint { span, trait_: *trait_, params };
+                        self.vars().add_trait_constraint(self_var, con);
+                        None
+                    }
+                };
 
-                let linfo = InstInfo::new(func.0, finst, ptypes, returns);
+                let mut linfo = InstInfo::new(func.0, finst, ptypes, returns);
+                linfo.impl_ = impl_;
 
                 InstCall::Instantiated(linfo)
             }
@@ -905,7 +1025,11 @@ impl<'a, 's> Verify<'a, 's> {
                     let returns = (&finst).transform_spanned(ret);
 
                     self.apply_tanot(tanot, &finst, Either::Left(func.map(NFunc::Key)));
-                    let linfo = InstInfo::new(func.0, finst, ptypes, returns);
+                    let mut linfo = InstInfo::new(func.0, finst, ptypes, returns);
+                    linfo.variadic = matches!(
+                        &self.hir.funcs[func],
+                        hir::FuncDefKind::Extern { variadic: true, .. }
+                    );
 
                     InstCall::Instantiated(linfo)
                 }
@@ -915,6 +1039,66 @@ impl<'a, 's> Verify<'a, 's> {
         }
     }
 
+    fn select_named_impl(
+        &mut self,
+        name: Tr<&'s str>,
+        trait_: M<key::Trait>,
+        finst: &GenericMapper<Inference>,
+    ) -> Option<M<key::Impl>> {
+        let tname = *self.hir.traits[trait_].0;
+        let candidates = self
+            .hir
+            .named_impls
+            .get(&(trait_, *name))
+            .map(Vec::as_slice)
+            .unwrap_or(&[]);
+
+        let ikey = match candidates {
+            [ikey] => *ikey,
+            [] => {
+                self.error("implementation not found")
+                    .eline(
+                        name.span,
+                        format!("no implementation of `{tname}` is named `{name}`"),
+                    )
+                    .emit();
+                return None;
+            }
+            _ => {
+                let mut err = self.error("ambiguous implementation").eline(
+                    name.span,
+                    format!("multiple implementations of `{tname}` are named `{name}`"),
+                );
+                for ikey in candidates {
+                    err = err
+                        .m(ikey.0)
+                        .iline(self.hir.impltors[*ikey].span, "candidate");
+                }
+                err.emit();
+                return None;
+            }
+        };
+
+        let span = name.span;
+        let iforall = &self.hir.impls[ikey];
+        let vars = &mut self.tenvs[self.current.fkey];
+        let iinst = GenericMapper::inst(vars)
+            .forall(GenericKind::Parent, span, iforall)
+            .cons(GenericKind::Parent, iforall)
+            .into_mapper();
+
+        let impltor = (&iinst).transform(&self.hir.impltors[ikey]);
+        let itrait_params = (&iinst).transforms(&self.hir.itraits[ikey].1);
+
+        let self_ = finst.self_.as_ref().unwrap();
+        let mut ok = self.type_check_and_emit((&impltor).tr(span), self_.tr(span));
+        for (ty, exp) in itrait_params.iter().zip(finst.types(GenericKind::Parent)) {
+            ok &= self.type_check_and_emit(ty.tr(span), exp.tr(span));
+        }
+
+        ok.then_some(ikey)
+    }
+
     pub fn apply_tanot(
         &mut self,
         tanot: &hir::TypeAnnotation<'s>,
@@ -1057,12 +1241,13 @@ impl fmt::Display for FunctionStatus {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             FunctionStatus::Done(func) => func.fmt(f),
-            FunctionStatus::Extern { link_name, typing } => {
+            FunctionStatus::Extern { link_name, data, variadic, typing, .. } => {
                 write!(
                     f,
-                    "{} {typing} {} {link_name}",
+                    "{} {typing}{} {} {link_name}",
                     "as".keyword(),
-                    "extern".keyword()
+                    if *variadic { ", ..." } else { "" },
+                    if *data { "extern_data" } else { "extern" }.keyword()
                 )
             }
             FunctionStatus::InCallStack(_) => "<current>".fmt(f),
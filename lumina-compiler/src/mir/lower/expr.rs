@@ -4,8 +4,8 @@ use crate::prelude::*;
 use crate::{LISTABLE_CONS, LISTABLE_NEW, LISTABLE_WITH_CAPACITY, STRINGABLE_FROM_RAW_PARTS};
 use ast::NFunc;
 use lumina_typesystem::{
-    ConstValue, Constraint, Container, Generic, GenericKind, GenericMapper, IntSize, Static,
-    Transformer, Ty, Type, Var,
+    ConstValue, Constraint, Container, FloatSize, Generic, GenericKind, GenericMapper, IType,
+    IntSize, Static, Transformer, Ty, Type, Var,
 };
 use lumina_util::Highlighting;
 use std::fmt;
@@ -34,26 +34,43 @@ pub enum Expr {
     ToPointerCast(Box<Self>, IntSize, Type),
     FromPointerCast(Box<Self>, IntSize),
     IntCast(Box<Self>, IntSize, IntSize),
-    ToFloatCast(Box<Self>, IntSize),
-    FromFloatCast(Box<Self>, IntSize),
+    ToFloatCast(Box<Self>, IntSize, FloatSize),
+    FromFloatCast(Box<Self>, FloatSize, IntSize),
+    FloatCast(Box<Self>, FloatSize, FloatSize),
     ArrayLen(Box<Self>),
 
     ObjectCast(Box<Self>, Type, M<key::Trait>, Vec<Type>),
     MemCpy(Box<[Self; 3]>),
+    MemSet(Box<[Self; 3]>),
     Deref(Box<Self>),
     Write(Box<[Self; 2]>),
+    // Atomic operation on a pointer with the given memory ordering
+    Atomic(&'static str, &'static str, Vec<Self>),
+    // Operation on a slice, trapping if an index is out of bounds
+    Slice(&'static str, Vec<Self>),
     ReflectTypeOf(Type),
     SizeOf(Type),
     AlignOf(Type),
     Alloca(Type),
     Unreachable(Type),
+    // Stops in an attached debugger when built with `--trap-on-panic`
+    Breakpoint,
 
     Cmp(&'static str, Box<[Expr; 2]>),
     Num(&'static str, Box<[Expr; 2]>),
+    // Arithmetic with a carry in, returning the result and the carry out
+    NumCarry(&'static str, Box<[Expr; 3]>),
+    // Picks between two eagerly evaluated values without branching
+    SelectValue(Box<[Expr; 3]>),
     IntAbs(Box<Expr>),
+    Neg(Box<Expr>),
     ValToRef(Box<Self>),
+    // A condition expected to almost always be the given value
+    Expect(Box<Self>, bool),
 
     Tuple(Vec<Self>),
+    // Source location of a call, for debug info
+    Located(Span, Box<Self>),
     Match(
         Box<Self>,
         pat::DecTree<key::DecisionTreeTail>,
@@ -67,6 +84,10 @@ impl Expr {
     pub fn bind(bind: key::Bind) -> Expr {
         Expr::Yield(Callable::Binding(bind))
     }
+
+    pub fn located(span: Span, expr: Expr) -> Expr {
+        Expr::Located(span, Box::new(expr))
+    }
 }
 
 impl<'a, 's> Lower<'a, 's> {
@@ -84,22 +105,66 @@ impl<'a, 's> Lower<'a, 's> {
         }
     }
 
+    // C receives the parameters given in place of `...` after default argument promotion, which
+    // is only defined for integers, floats and pointers
+    fn check_variadic_params(&mut self, params: &[Tr<IType>]) {
+        for ty in params {
+            let fty = self.finalizer().transform(ty);
+            match &fty {
+                Type::Int(_)
+                | Type::Simple("f32" | "f64" | "poison")
+                | Type::Container(Container::Pointer | Container::FnPointer, _) => {}
+                _ => self.errors.push(FinError::InvalidVariadic(fty.tr(ty.span))),
+            }
+        }
+    }
+
+    /// Finalise the instantiation of a call to `nfunc`, which for methods may have been given an
+    /// explicit implementation with `Trait(name):method`
+    fn fin_func_or_poison(
+        &mut self,
+        span: Span,
+        nfunc: M<ast::NFunc>,
+        f: impl FnOnce(&mut Self, Callable) -> Expr,
+    ) -> Expr {
+        match self.current.pop_inst(span) {
+            Some(instinfo) => {
+                let (inst, _) = (&mut *self).fin_typing(&instinfo);
+                self.check_variadic_params(&instinfo.variadic_params);
+                let call = match (instinfo.impl_, nfunc.1) {
+                    (Some(ikey), ast::NFunc::Method(_, method)) => {
+                        Callable::Impl(ikey, method, inst)
+                    }
+                    _ => Callable::Func(nfunc, inst),
+                };
+                f(self, call)
+            }
+            None => Expr::Poison,
+        }
+    }
+
     pub fn lower_expr(&mut self, expr: Tr<&hir::Expr<'s>>) -> Expr {
         match &expr.value {
             hir::Expr::Call(call, tanot, params) => match call {
                 hir::Callable::Func(nfunc) => {
                     let nfunc = M(nfunc.module, nfunc.key);
                     let params = self.lower_exprs(params);
-                    self.fin_inst_or_poison(expr.span, |_, mapper| {
-                        let call = Callable::Func(nfunc, mapper);
-                        Expr::Call(call, params)
+                    self.fin_func_or_poison(expr.span, nfunc, |_, call| {
+                        // Reading a val is matched on by `builtin:val_to_ref`
+                        let is_val = matches!(nfunc.1, ast::NFunc::Val(_));
+                        let call = Expr::Call(call, params);
+                        if is_val {
+                            call
+                        } else {
+                            Expr::located(expr.span, call)
+                        }
                     })
                 }
                 hir::Callable::Lambda(lambda) => {
                     let params = self.lower_exprs(params);
                     self.fin_inst_or_poison(expr.span, |_, mapper| {
                         let call = Callable::Lambda(*lambda, mapper);
-                        Expr::Call(call, params)
+                        Expr::located(expr.span, Expr::Call(call, params))
                     })
                 }
                 hir::Callable::Binding(bind) => {
@@ -109,7 +174,7 @@ impl<'a, 's> Lower<'a, 's> {
                         Type::Container(Container::FnPointer | Container::Closure, _) => {
                             let params = self.lower_exprs(params);
                             let call = Callable::Binding(*bind);
-                            Expr::Call(call, params)
+                            Expr::located(expr.span, Expr::Call(call, params))
                         }
                         _ if params.is_empty() => Expr::Yield(Callable::Binding(*bind)),
                         _ => Expr::Poison,
@@ -120,7 +185,7 @@ impl<'a, 's> Lower<'a, 's> {
                     self.fin_inst_or_poison(expr.span, |this, mapper| {
                         let fkey = this.current.type_dependent_lookup.pop_front().unwrap();
                         let call = Callable::Func(fkey, mapper);
-                        Expr::Call(call, params)
+                        Expr::located(expr.span, Expr::Call(call, params))
                     })
                 }
                 hir::Callable::Builtin(name) => builtins::lower(self, *name, params, tanot),
@@ -133,9 +198,9 @@ impl<'a, 's> Lower<'a, 's> {
             }
             hir::Expr::Pass(call, _, params) => match call {
                 hir::Callable::Func(nfunc) => {
+                    let nfunc = M(nfunc.module, nfunc.key);
                     let params = self.lower_exprs(params);
-                    self.fin_inst_or_poison(expr.span, |_, mapper| {
-                        let call = Callable::Func(M(nfunc.module, nfunc.key), mapper);
+                    self.fin_func_or_poison(expr.span, nfunc, |_, call| {
                         Expr::PartiallyApplicate(call, params)
                     })
                 }
@@ -333,6 +398,7 @@ impl<'a, 's> Lower<'a, 's> {
 
     fn lower_cast(&mut self, expr: mir::Expr, ty_of_expr: Tr<Type>, to: Type) -> Expr {
         let expr = Box::new(expr);
+        let float = |name: &str| FloatSize::from_name(name);
         match (&ty_of_expr.value, to) {
             (Ty::Simple("bool"), Ty::Int(tosize)) => {
                 Expr::IntCast(expr, IntSize::new(false, 8), tosize)
@@ -341,8 +407,18 @@ impl<'a, 's> Lower<'a, 's> {
                 Expr::IntCast(expr, *fromsize, IntSize::new(false, 8))
             }
             (Ty::Int(fromsize), Ty::Int(tosize)) => Expr::IntCast(expr, *fromsize, tosize),
-            (Ty::Int(intsize), Ty::Simple("f64")) => Expr::ToFloatCast(expr, *intsize),
-            (Type::Simple("f64"), Type::Int(intsize)) => Expr::FromFloatCast(expr, intsize),
+            (Ty::Int(intsize), Ty::Simple(to)) if float(to).is_some() => {
+                Expr::ToFloatCast(expr, *intsize, float(to).unwrap())
+            }
+            (Ty::Simple(from), Ty::Int(intsize)) if float(from).is_some() => {
+                Expr::FromFloatCast(expr, float(from).unwrap(), intsize)
+            }
+            (Ty::Simple(from), Ty::Simple(to)) if float(from).is_some() && float(to).is_some() => {
+                Expr::FloatCast(expr, float(from).unwrap(), float(to).unwrap())
+            }
+
+            // An opaque return of a value which already is an object of the trait
+            (from, to) if *from == to => *expr,
 
             // T as DynTrait
             (
@@ -571,6 +647,23 @@ impl fmt::Display for Expr {
             }
             Expr::Num(instr, p) => write!(f, "{op}{} {} {}{cp}", instr.keyword(), &p[0], &p[1]),
             Expr::IntAbs(n) => write!(f, "{op} {} {n}{cp}", "abs".keyword()),
+            Expr::Neg(n) => write!(f, "{op} {} {n}{cp}", "neg".keyword()),
+            Expr::NumCarry(instr, p) => write!(
+                f,
+                "{op}{} {} {} {}{cp}",
+                instr.keyword(),
+                &p[0],
+                &p[1],
+                &p[2]
+            ),
+            Expr::SelectValue(p) => write!(
+                f,
+                "{op}{} {} {} {}{cp}",
+                "select".keyword(),
+                &p[0],
+                &p[1],
+                &p[2]
+            ),
             Expr::Cmp(instr, p) => write!(f, "{op}{} {} {}{cp}", instr.keyword(), &p[0], &p[1]),
             Expr::Access(object, key, _, field) => write!(f, "({object} {as_} {key}).{field}"),
             Expr::Record(record, ptypes, fields) => write!(
@@ -603,6 +696,7 @@ impl fmt::Display for Expr {
             Expr::Float(n) => n.fmt(f),
             Expr::ReadOnly(k) => k.fmt(f),
             Expr::Tuple(elems) => write!(f, "{op}{}{cp}", elems.iter().format(", ")),
+            Expr::Located(_, expr) => expr.fmt(f),
             Expr::Match(on, tree, tails, _) => match tree {
                 // edge-case for formatting `let x = y in` prettily
                 pat::DecTree::End(pat::TreeTail::Reached(table, excess, key))
@@ -646,10 +740,10 @@ impl fmt::Display for Expr {
                 write!(f, "{op}{expr} {} [{inner}; {generic}]{cp}", "as".keyword())
             }
             Expr::IntCast(expr, _, to) => write!(f, "{op}{expr} {} {to}{cp}", "as".keyword()),
-            Expr::ToFloatCast(expr, _) => {
-                write!(f, "{op}{expr} {} float{cp}", "as".keyword())
+            Expr::ToFloatCast(expr, _, to) | Expr::FloatCast(expr, _, to) => {
+                write!(f, "{op}{expr} {} {to}{cp}", "as".keyword())
             }
-            Expr::FromFloatCast(expr, intsize) => {
+            Expr::FromFloatCast(expr, _, intsize) => {
                 write!(f, "{op}{expr} {} {intsize}{cp}", "as".keyword())
             }
             Expr::ObjectCast(expr, _, tr, params) => {
@@ -668,8 +762,30 @@ impl fmt::Display for Expr {
                 write!(f, "{op}{expr} {} {toint}", "as".keyword())
             }
             Expr::ValToRef(val) => write!(f, "{op}{} {val}{cp}", "ref_val".keyword()),
+            Expr::Expect(cond, true) => write!(f, "{op}{} {cond}{cp}", "likely".keyword()),
+            Expr::Expect(cond, false) => write!(f, "{op}{} {cond}{cp}", "unlikely".keyword()),
             Expr::Deref(inner) => write!(f, "{op}{} {inner}{cp}", "deref".keyword()),
             Expr::Write(p) => write!(f, "{op}{} {} {}{cp}", "write".keyword(), &p[0], &p[1]),
+            Expr::MemSet(p) => write!(
+                f,
+                "{op}{} dst{eq}{} byte{eq}{} count{eq}{}",
+                "memset".keyword(),
+                p[0],
+                p[1],
+                p[2]
+            ),
+            Expr::Atomic(kind, ordering, p) => write!(
+                f,
+                "{op}{}-{kind}.{ordering} {}{cp}",
+                "atomic".keyword(),
+                p.iter().format(" ")
+            ),
+            Expr::Slice(kind, p) => write!(
+                f,
+                "{op}{}-{kind} {}{cp}",
+                "slice".keyword(),
+                p.iter().format(" ")
+            ),
             Expr::MemCpy(p) => write!(
                 f,
                 "{op}{} dst{eq}{} src{eq}{} count{eq}{}",
@@ -684,6 +800,7 @@ impl fmt::Display for Expr {
             Expr::Alloca(ty) => write!(f, "{op}{} {ty}{cp}", "alloca".keyword()),
             Expr::Poison => "<poison>".fmt(f),
             Expr::Unreachable(_) => write!(f, "{}", "unreachable".keyword()),
+            Expr::Breakpoint => write!(f, "{}", "breakpoint".keyword()),
         }
     }
 }
@@ -692,6 +809,9 @@ impl fmt::Display for Callable {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Callable::Func(nfunc, mapper) => write!(f, "{nfunc}{mapper}"),
+            Callable::Impl(ikey, method, mapper) => {
+                write!(f, "{ikey}{}{method}{mapper}", ':'.symbol())
+            }
             Callable::Lambda(lambda, mapper) => write!(f, "{lambda}{mapper}"),
             Callable::Binding(binding) => binding.fmt(f),
             Callable::Param(param) => param.fmt(f),
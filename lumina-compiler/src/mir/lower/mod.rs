@@ -25,6 +25,10 @@ pub struct Function {
     pub lcaptures: Map<key::Lambda, Vec<key::Bind>>,
     #[new(default)]
     pub no_mangle: bool,
+    #[new(default)]
+    pub export: Option<String>,
+    #[new(default)]
+    pub linkage: ast::Linkage,
     pub expr: Expr,
 }
 
@@ -64,6 +68,8 @@ pub struct Lambda {
 #[derive(Clone, Debug)]
 pub enum Callable {
     Func(M<NFunc>, GenericMapper<Static>),
+    /// A method of an implementation which was selected explicitly
+    Impl(M<key::Impl>, key::Method, GenericMapper<Static>),
     Lambda(key::Lambda, GenericMapper<Static>),
     Binding(key::Bind),
     Param(key::Param),
@@ -96,11 +102,13 @@ pub enum FinError {
     UnreachablePattern(Span),
     MissingPatterns(Span, Vec<pat::MissingPattern>),
     InvalidCast(Tr<Type>, Type),
+    InvalidVariadic(Tr<Type>),
     LargeCharLiteral(Span),
     BadArrayCount { got: Tr<usize>, exp: Tr<u64> },
     BadGenericArrayCount { got: Tr<usize> },
     DuplicateField(Tr<String>, Span),
     MissingField(String, Span),
+    MixedBinaryPattern(Span),
 }
 
 impl<'a, 's> Lower<'a, 's> {
@@ -173,7 +181,7 @@ impl<'a, 's> Lower<'a, 's> {
     }
 }
 
-fn escape(str: &str) -> Vec<u8> {
+pub(super) fn escape(str: &str) -> Vec<u8> {
     let mut buffer = Vec::with_capacity(str.len());
     let mut bytes = str.bytes();
 
@@ -276,6 +284,10 @@ impl<'l, 'a, 's> pat::Merge<'s, key::DecisionTreeTail> for ParamsLower<'l, 'a, '
         ));
     }
 
+    fn err_mixed_binary_pattern(&mut self, span: Span) {
+        self.lower.errors.push(FinError::MixedBinaryPattern(span));
+    }
+
     fn str_to_ro(&mut self, str: &'s str) -> M<lumina_key::ReadOnly> {
         self.lower.str_to_ro(str)
     }
@@ -341,6 +353,10 @@ impl<'l, 'a, 's> pat::Merge<'s, key::DecisionTreeTail> for MatchBranchLower<'l,
         ));
     }
 
+    fn err_mixed_binary_pattern(&mut self, span: Span) {
+        self.lower.errors.push(FinError::MixedBinaryPattern(span));
+    }
+
     fn str_to_ro(&mut self, str: &'s str) -> M<key::ReadOnly> {
         self.lower.str_to_ro(str)
     }
@@ -442,12 +458,30 @@ pub fn emit_fin_error<'s>(
                 )
                 .emit();
         }
+        FinError::InvalidVariadic(ty) => sources
+            .error("invalid variadic parameter")
+            .m(module)
+            .eline(
+                ty.span,
+                format!(
+                    "a value of type `{}` can not be passed in place of `...`",
+                    tfmt.fmt(&*ty)
+                ),
+            )
+            .text("only integers, floats and pointers can be given to variadic extern functions")
+            .emit(),
         // TODO: merge multiple
         FinError::MissingField(field, rspan) => sources
             .error("missing record field")
             .m(module)
             .eline(rspan, format!("field `{field}` is missing"))
             .emit(),
+        FinError::MixedBinaryPattern(span) => sources
+            .error("mixed binary and tuple patterns")
+            .m(module)
+            .eline(span, "")
+            .text("a value matched with binary patterns can not also be matched as a tuple")
+            .emit(),
     }
 }
 
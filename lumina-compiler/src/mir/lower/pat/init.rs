@@ -71,7 +71,7 @@ impl<'a> Init<'a> {
                 let next = DecTree::End(TreeTail::Unreached(elems.to_vec().into()));
                 DecTree::Tuple { elems: elems.len(), next: Box::new(next) }
             }
-            Container::FnPointer | Container::Closure | Container::Pointer => {
+            Container::FnPointer | Container::Closure | Container::Pointer | Container::Slice => {
                 let ty = Type::Container(con.clone(), elems.to_vec());
                 self.unreached_opaque(ty)
             }
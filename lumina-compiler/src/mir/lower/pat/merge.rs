@@ -1,7 +1,7 @@
 use super::{
     super::{escape, CallTypes, Callable},
-    range, BranchKey, Branching, DecTree, Init, PointTable, StrCheck, StrChecks, TreeTail,
-    LIST_CONS, LIST_NIL,
+    range, BranchKey, Branching, ByteCheck, ByteChecks, ByteLen, DecTree, Init, PointTable,
+    StrCheck, StrChecks, TreeTail, LIST_CONS, LIST_NIL,
 };
 use crate::prelude::*;
 use hir::Pattern;
@@ -65,12 +65,21 @@ impl<'h, 's, Tail: Display + Clone + PartialEq, M: Merge<'s, Tail>> Merger<'h, '
         }
 
         match tree {
-            DecTree::Tuple { next, elems } => {
-                expected!(
-                    Pattern::Tuple(pelems) if *elems == pelems.len() =>
-                    self.merge_params(next, pelems)
-                )
-            }
+            DecTree::Tuple { next, elems } => match pat.value {
+                Pattern::Tuple(pelems) if *elems == pelems.len() => self.merge_params(next, pelems),
+                // Binary patterns match over `(*u8, uint)` which is initialised as a tuple
+                Pattern::Binary(_) if *elems == 2 => match tuple_into_bytes(next) {
+                    Some(bytes) => {
+                        *tree = bytes;
+                        self.merge(tree, pat)
+                    }
+                    None => {
+                        self.merge.err_mixed_binary_pattern(pat.span);
+                        true
+                    }
+                },
+                _ => reachable_as_poison!(pat),
+            },
             DecTree::Array { next, elems } => {
                 expected!(Pattern::Array(pelems, len) if **len == *elems =>
                     {
@@ -156,6 +165,14 @@ impl<'h, 's, Tail: Display + Clone + PartialEq, M: Merge<'s, Tail>> Merger<'h, '
                     _ => reachable_as_poison!(pat),
                 }
             }
+            DecTree::Bytes { next, wildcard_next } => match pat.value {
+                Pattern::Binary(segments) => self.merge_bytes(segments, next, wildcard_next),
+                Pattern::Tuple(_) => {
+                    self.merge.err_mixed_binary_pattern(pat.span);
+                    true
+                }
+                _ => reachable_as_poison!(pat),
+            },
             DecTree::Wildcard { ty, next } => {
                 *tree = self.expand_wildcard_and_bump_tails(ty, &mut **next);
                 self.merge(tree, pat)
@@ -218,6 +235,10 @@ impl<'h, 's, Tail: Display + Clone + PartialEq, M: Merge<'s, Tail>> Merger<'h, '
                         let ro = self.merge.str_to_ro(name);
                         StrCheck::Literal(ro)
                     }
+                    hir::StringPattern::Suffix(name) => {
+                        let ro = self.merge.str_to_ro(name);
+                        StrCheck::Suffix(ro)
+                    }
                     // The last wildcard will bind to excess string instead of char
                     hir::StringPattern::Wildcard(bind) if spats.len() - 1 == i => {
                         self.table.binds.push((**bind, self.depth));
@@ -289,6 +310,59 @@ impl<'h, 's, Tail: Display + Clone + PartialEq, M: Merge<'s, Tail>> Merger<'h, '
         }
     }
 
+    fn merge_bytes(
+        mut self,
+        segments: &[Tr<hir::Segment>],
+        next: &mut Branching<ByteChecks, Tail>,
+        wildcard_next: &mut DecTree<Tail>,
+    ) -> IsReachable {
+        // Integers bound by this pattern, by the index of their check
+        let mut ints = vec![];
+        let mut checks = Vec::with_capacity(segments.len());
+
+        for (i, segment) in segments.iter().enumerate() {
+            let check = match &segment.value {
+                hir::Segment::Int(intsize, bind) => {
+                    if let Some(bind) = bind {
+                        self.table.binds.push((bind.value, self.depth));
+                        ints.push((bind.value, i));
+                    }
+                    ByteCheck::Int(*intsize)
+                }
+                hir::Segment::Literal(intsize, n) => ByteCheck::Literal(*intsize, *n),
+                hir::Segment::Bytes(len, bind) => {
+                    if let Some(bind) = bind {
+                        self.table.binds.push((bind.value, self.depth));
+                    }
+                    match len {
+                        hir::ByteLen::Const(n) => ByteCheck::Bytes(ByteLen::Const(*n)),
+                        hir::ByteLen::Bind(size) => {
+                            let (_, i) = ints.iter().find(|(bind, _)| bind == size).unwrap();
+                            ByteCheck::Bytes(ByteLen::Check(*i))
+                        }
+                    }
+                }
+                hir::Segment::Rest(bind) => {
+                    if let Some(bind) = bind {
+                        self.table.binds.push((bind.value, self.depth));
+                    }
+                    ByteCheck::Rest
+                }
+            };
+            self.depth += 1;
+            checks.push(check);
+        }
+
+        if matches!(checks.as_slice(), [ByteCheck::Rest]) {
+            self.next(wildcard_next)
+        } else {
+            let mut branch_next = wildcard_next.clone();
+            self.next(&mut branch_next);
+            next.branches.push((ByteChecks { checks }, branch_next));
+            true
+        }
+    }
+
     fn extractor_by_typing(
         &mut self,
         callable: Callable,
@@ -413,6 +487,7 @@ impl<'h, 's, Tail: Display + Clone + PartialEq, M: Merge<'s, Tail>> Merger<'h, '
                 self.merge_int(next, full.min, full.max)
             }
             DecTree::String { wildcard_next, .. } => self.next(wildcard_next),
+            DecTree::Bytes { wildcard_next, .. } => self.next(wildcard_next),
             DecTree::Bools(next) => self.merge_any_into_branches(span, next, |_, _| 0),
             DecTree::Opaque { next, .. } => self.next(next),
             DecTree::End(_) => self.merge_any_into_tail(span, tree),
@@ -568,6 +643,8 @@ pub trait Merge<'s, Tail: Display + Clone + PartialEq>: Sized {
 
     fn err_duplicate_field(&mut self, field: Tr<&'s str>, previous: Span);
 
+    fn err_mixed_binary_pattern(&mut self, span: Span);
+
     fn fin_popped_inst(&mut self, span: Span) -> Option<(GenericMapper<Static>, CallTypes)>;
     fn fin_record(&mut self, rvar: Var) -> Option<(M<key::Record>, Vec<Type>)>;
 
@@ -644,6 +721,21 @@ pub trait Merge<'s, Tail: Display + Clone + PartialEq>: Sized {
 
 pub type IsReachable = bool;
 
+// `None` if tuple patterns were already merged into the tree
+fn tuple_into_bytes<Tail>(next: &mut DecTree<Tail>) -> Option<DecTree<Tail>> {
+    match next {
+        DecTree::End(TreeTail::Unreached(types)) => {
+            let mut excess = std::mem::take(types);
+            excess.drain(..2);
+            Some(DecTree::Bytes {
+                next: Branching { branches: vec![] },
+                wildcard_next: Box::new(DecTree::End(TreeTail::Unreached(excess))),
+            })
+        }
+        _ => None,
+    }
+}
+
 fn types_into_wildcards<Tail>(
     types: impl DoubleEndedIterator<Item = Type>,
     next: DecTree<Tail>,
@@ -181,7 +181,7 @@ impl<'a> MissingGeneration<'a> {
                     _ => missing,
                 }
             }
-            DecTree::String { wildcard_next, .. } => {
+            DecTree::String { wildcard_next, .. } | DecTree::Bytes { wildcard_next, .. } => {
                 let mut missing = self.tree(wildcard_next);
                 missing
                     .iter_mut()
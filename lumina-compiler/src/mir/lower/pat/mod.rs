@@ -4,7 +4,7 @@ use lumina_typesystem::{IntSize, Type};
 use std::collections::VecDeque;
 
 mod range;
-pub use range::Range;
+pub use range::{Constraints, Range};
 
 mod init;
 pub use init::Init;
@@ -52,6 +52,10 @@ pub enum DecTree<Tail> {
         next: Branching<StrChecks, Tail>,
         wildcard_next: Box<Self>,
     },
+    Bytes {
+        next: Branching<ByteChecks, Tail>,
+        wildcard_next: Box<Self>,
+    },
     Ints {
         intsize: IntSize,
         next: Branching<Range, Tail>,
@@ -83,6 +87,8 @@ pub struct StrChecks {
 #[derive(Clone, Debug)]
 pub enum StrCheck {
     Literal(M<key::ReadOnly>),
+    // Checked against the end of the string, leaving the rest for the checks after it
+    Suffix(M<key::ReadOnly>),
     TakeExcess,
     TakeByte,
     // fn(u8 -> bool) -> Maybe string
@@ -91,6 +97,30 @@ pub enum StrCheck {
     TakeBySplit(Callable, Type, Vec<mir::Expr>),
 }
 
+#[derive(Clone, Debug)]
+pub struct ByteChecks {
+    pub checks: Vec<ByteCheck>,
+}
+
+/// Reads from a `(*u8, uint)` buffer, each advancing past what it read
+///
+/// Unless the last check is `Rest`, the buffer must be fully consumed by the checks
+#[derive(Clone, Debug)]
+pub enum ByteCheck {
+    // Big-endian integer
+    Int(IntSize),
+    Literal(IntSize, i128),
+    Bytes(ByteLen),
+    Rest,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ByteLen {
+    Const(u64),
+    // The integer read by an earlier check of the same branch
+    Check(usize),
+}
+
 #[derive(Clone, Debug)]
 pub enum TreeTail<Tail> {
     Poison,
@@ -112,6 +142,7 @@ pub trait BranchKey: std::fmt::Display {}
 impl BranchKey for key::Variant {}
 impl BranchKey for bool {}
 impl BranchKey for StrChecks {}
+impl BranchKey for ByteChecks {}
 impl BranchKey for Range {}
 
 #[derive(Debug, Clone)]
@@ -135,6 +166,10 @@ impl<Tail> DecTree<Tail> {
                 next.for_each_tail_mut(f);
                 wildcard_next.for_each_tail_mut(f);
             }
+            DecTree::Bytes { next, wildcard_next } => {
+                next.for_each_tail_mut(f);
+                wildcard_next.for_each_tail_mut(f);
+            }
             DecTree::End(_) => take_mut::take(self, |tree| match tree {
                 DecTree::End(tail) => f(tail),
                 _ => unreachable!(),
@@ -157,6 +192,10 @@ impl<Tail> DecTree<Tail> {
                 next.for_each_tail(f);
                 wildcard_next.for_each_tail(f);
             }
+            DecTree::Bytes { next, wildcard_next } => {
+                next.for_each_tail(f);
+                wildcard_next.for_each_tail(f);
+            }
             DecTree::End(tail) => f(tail),
         }
     }
@@ -253,6 +292,14 @@ impl<Tail: std::fmt::Display> std::fmt::Display for DecTree<Tail> {
                     wildcard_next.to_string().lines().format("\n  ")
                 )
             }
+            DecTree::Bytes { next, wildcard_next } => {
+                next.fmt("bytes", f)?;
+                write!(
+                    f,
+                    "bytes _\n  {}",
+                    wildcard_next.to_string().lines().format("\n  ")
+                )
+            }
             DecTree::Opaque { ty, next } => {
                 write!(f, "{ty}\n  {}", next.to_string().lines().format("\n  "))
             }
@@ -288,6 +335,7 @@ impl std::fmt::Display for StrChecks {
             .iter()
             .map(|check| match check {
                 StrCheck::Literal(name) => name.to_string(),
+                StrCheck::Suffix(name) => format!("_ ++ {name}"),
                 StrCheck::TakeExcess | StrCheck::TakeByte => format!("_"),
                 StrCheck::TakeWhile(call, params) => {
                     format!("#({call} {})", params.iter().format(" "))
@@ -300,3 +348,20 @@ impl std::fmt::Display for StrChecks {
             .fmt(f)
     }
 }
+
+impl std::fmt::Display for ByteChecks {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let checks = self
+            .checks
+            .iter()
+            .format_with(", ", |check, f| match check {
+                ByteCheck::Int(intsize) => f(&format_args!("_:{intsize}")),
+                ByteCheck::Literal(intsize, n) => f(&format_args!("{n}:{intsize}")),
+                ByteCheck::Bytes(ByteLen::Const(n)) => f(&format_args!("_:bytes[{n}]")),
+                ByteCheck::Bytes(ByteLen::Check(i)) => f(&format_args!("_:bytes[#{i}]")),
+                ByteCheck::Rest => f(&"_:bytes"),
+            });
+
+        write!(f, "<<{checks}>>")
+    }
+}
@@ -95,6 +95,10 @@ impl<'a, 's> Merge<'s, Tail> for Lower {
         panic!("duplicate field: {field}");
     }
 
+    fn err_mixed_binary_pattern(&mut self, _: Span) {
+        panic!("mixed binary and tuple patterns");
+    }
+
     fn name_of_field(&self, _: M<key::Record>, _: key::Field) -> &'s str {
         todo!();
     }
@@ -6,12 +6,14 @@
 use crate::prelude::*;
 use crate::{ProjectInfo, Target};
 use lumina_typesystem::{
-    Downgrade, Forall, GenericKind, GenericMapper, IType, Static, TEnv, Transformer, Ty, Var,
+    Container, Downgrade, Forall, GenericKind, GenericMapper, IType, Static, TEnv, Transformer, Ty,
+    Var,
 };
 use lumina_util::Highlighting;
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 use std::fmt;
 use std::ops::Not;
+use std::path::PathBuf;
 use tracing::info_span;
 
 mod expr;
@@ -20,6 +22,9 @@ pub use func::FunctionStatus;
 use func::InstInfo;
 use lumina_typesystem::{ImplIndex, Type};
 mod builtins;
+mod bytecode;
+mod cache;
+pub use cache::ModuleCache;
 mod patc;
 mod tcheck;
 
@@ -32,6 +37,19 @@ pub type Branching<K> = pat::Branching<K, key::DecisionTreeTail>;
 
 type SelfPositions = Map<key::Method, key::Param>;
 
+pub struct Test {
+    pub func: M<key::Func>,
+    pub kind: ast::TestKind,
+    pub expected_output: Option<ExpectedOutput>,
+}
+
+/// What a test is expected to print to stdout
+pub enum ExpectedOutput {
+    Inline(Vec<u8>),
+    /// Golden file which `lumina test --bless` overwrites with the actual output
+    File(PathBuf),
+}
+
 pub struct MIR {
     pub funcs: MMap<key::Func, FunctionStatus>,
     pub read_only_table: MMap<key::ReadOnly, (ReadOnlyBytes, Type)>,
@@ -45,6 +63,10 @@ pub struct MIR {
     pub impltors: MMap<key::Impl, Tr<Type>>,
     pub itraits: MMap<key::Impl, (M<key::Trait>, Vec<Type>)>,
     pub val_initializers: MMap<key::Val, M<key::Func>>,
+    pub const_vals: HashSet<M<key::Val>>,
+    pub static_asserts: HashMap<M<key::Val>, String>,
+    pub thread_local_vals: HashSet<M<key::Val>>,
+    pub tests: Vec<Test>,
     pub trait_objects: MMap<key::Trait, Option<SelfPositions>>,
     pub type_repr: hir::TypeRepr,
 
@@ -81,10 +103,17 @@ pub fn run<'a, 'h, 's>(
     hir: hir::HIR<'s>,
     mut tenvs: MMap<key::Func, TEnv<'s>>,
     iquery: &mut ImplIndex,
+    mut cache: Option<&mut ModuleCache>,
 ) -> (MIR, bool) {
     let mut funcs = hir.funcs.secondary_with(|_, _| FunctionStatus::Pending);
     let mut rotable = hir.sources.modules().collect();
 
+    // Modules which haven't changed since they were last lowered are taken from the cache
+    let cached = match cache.as_mut() {
+        Some(cache) => cache.load(&hir, &mut funcs, &mut rotable),
+        None => HashSet::new(),
+    };
+
     let fields = hir.lookups.to_field_lookup();
 
     // Verify members of implementations, as well as statically finishing the method typings
@@ -97,6 +126,13 @@ pub fn run<'a, 'h, 's>(
         imethods.push(impl_.0, methods);
     }
 
+    // Verify that foreign functions are only given types with a C representation
+    for func in hir.funcs.iter() {
+        if let hir::FuncDefKind::Extern { typing, .. } = &hir.funcs[func] {
+            verify_extern_signature(&hir, func, typing);
+        }
+    }
+
     // Check and lower the functions
     for func in hir.funcs.iter() {
         Verify::start_at(
@@ -134,8 +170,28 @@ pub fn run<'a, 'h, 's>(
         impls[imp.0].push(foralls);
     });
 
+    let tests = hir
+        .tests
+        .iter()
+        .map(|(func, kind, expect_output)| {
+            let expected_output = expect_output.as_ref().map(|expect| match expect {
+                ast::ExpectOutput::Inline(str) => ExpectedOutput::Inline(lower::escape(str)),
+                ast::ExpectOutput::File => {
+                    let name = *hir.func_names[*func];
+                    let path = hir.sources.get_path(func.0);
+                    ExpectedOutput::File(path.with_file_name(format!("{name}.expected")))
+                }
+            });
+            Test { func: *func, kind: *kind, expected_output }
+        })
+        .collect();
+
     let has_failed = hir.sources.has_failed();
 
+    if let Some(cache) = cache.filter(|_| !has_failed) {
+        cache.store(&hir, &funcs, &rotable, &cached);
+    }
+
     (
         MIR {
             funcs,
@@ -167,6 +223,10 @@ pub fn run<'a, 'h, 's>(
             field_types: hir.field_types,
             variant_types: hir.variant_types,
             val_initializers: hir.val_initializers,
+            const_vals: hir.const_vals,
+            static_asserts: hir.static_asserts,
+            thread_local_vals: hir.thread_local_vals,
+            tests,
         },
         has_failed,
     )
@@ -258,6 +318,80 @@ struct LangItems {
     pinfo: ProjectInfo,
 }
 
+fn verify_extern_signature(hir: &hir::HIR, func: M<key::Func>, typing: &hir::Typing<Type>) {
+    for ty in typing.params.values().chain([&typing.returns]) {
+        if let Some(reason) = non_c_type(hir, &ty.value, &mut vec![]) {
+            hir.sources
+                .error("unsupported type in extern signature")
+                .m(func.0)
+                .eline(ty.span, reason)
+                .emit();
+        }
+    }
+}
+
+// Why a type can't be passed to or returned from a foreign function, if it can't
+fn non_c_type(hir: &hir::HIR, ty: &Type, visited: &mut Vec<M<key::Record>>) -> Option<String> {
+    match ty {
+        Ty::Container(Container::Tuple, elems) if elems.is_empty() => None,
+        Ty::Container(Container::Tuple, _) => Some("tuples have no C representation".into()),
+        Ty::Container(Container::Closure, _) => {
+            Some("closures have no C representation, use a function pointer instead".into())
+        }
+        Ty::Container(Container::Slice, _) => {
+            Some("slices have no C representation, pass the pointer and length separately".into())
+        }
+        Ty::Container(Container::Pointer, _) => None,
+        // The forwarders C calls Lumina functions through pass the parameters along unchanged,
+        // which only holds for values C passes in registers of their own
+        Ty::Container(Container::FnPointer, params) => params.iter().find_map(|ty| match ty {
+            Ty::Container(Container::Defined(M(_, key::TypeKind::Record(_)), _), _)
+            | Ty::Container(Container::Array, _) => {
+                Some("callbacks can not take or return structs by value, use a pointer".into())
+            }
+            _ => non_c_type(hir, ty, visited),
+        }),
+        Ty::Container(Container::Array, params) => {
+            params.iter().find_map(|ty| non_c_type(hir, ty, visited))
+        }
+        Ty::Container(Container::Defined(M(module, kind), _), params) => match *kind {
+            key::TypeKind::Record(record) => {
+                let record = record.inside(*module);
+                let name = *hir.records[record].0;
+
+                if hir.type_repr.get(record) != ast::attr::Repr::C {
+                    return Some(format!(
+                        "{name} is not marked with the `@[repr \"C\"]` attribute"
+                    ));
+                }
+
+                if visited.contains(&record) {
+                    return Some(format!("{name} contains itself"));
+                }
+                visited.push(record);
+
+                let finst = GenericMapper::from_types(GenericKind::Entity, params.iter().cloned());
+                let reason = hir.field_types[record]
+                    .values()
+                    .find_map(|fty| non_c_type(hir, &(&finst).transform(&fty.value), visited));
+
+                visited.pop();
+                reason
+            }
+            key::TypeKind::Sum(sum) => {
+                let name = *hir.sums[sum.inside(*module)].0;
+                Some(format!("the sum type {name} has no C representation"))
+            }
+            key::TypeKind::Trait(trait_) => {
+                let name = *hir.traits[trait_.inside(*module)].0;
+                Some(format!("the trait object {name} has no C representation"))
+            }
+        },
+        // Integers, floats, booleans and the lengths of arrays
+        _ => None,
+    }
+}
+
 fn verify_impl_headers<'s>(
     hir: &hir::HIR<'s>,
     tenvs: &mut MMap<key::Func, TEnv<'s>>,
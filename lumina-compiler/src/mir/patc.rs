@@ -98,7 +98,7 @@ impl<'a, 's> Verify<'a, 's> {
                 for (i, spat) in spats.iter().enumerate() {
                     let is_last = || i == spats.len() - 1;
                     match spat {
-                        hir::StringPattern::Literal(_) => {}
+                        hir::StringPattern::Literal(_) | hir::StringPattern::Suffix(_) => {}
                         hir::StringPattern::Extractor(extractor) => {
                             let params = self.type_check_params(&extractor.params);
                             self.type_check_pass(
@@ -126,6 +126,26 @@ impl<'a, 's> Verify<'a, 's> {
                 let record = self.items.pinfo.string;
                 IType::defined(record, vec![])
             }
+            hir::Pattern::Binary(segments) => {
+                let buffer = IType::tuple(vec![
+                    IType::u8_pointer(),
+                    IType::int(false, self.target.int_size()),
+                ]);
+
+                for segment in segments {
+                    match &segment.value {
+                        hir::Segment::Int(intsize, Some(bind)) => {
+                            self.new_bind_as(bind.value, IType::Int(*intsize).tr(bind.span))
+                        }
+                        hir::Segment::Bytes(_, Some(bind)) | hir::Segment::Rest(Some(bind)) => {
+                            self.new_bind_as(bind.value, buffer.clone().tr(bind.span))
+                        }
+                        _ => {}
+                    }
+                }
+
+                buffer
+            }
             hir::Pattern::Poison => todo!(),
         }
         .tr(pat.span)
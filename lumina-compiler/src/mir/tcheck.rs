@@ -122,29 +122,28 @@ impl<'a, 's> Verify<'a, 's> {
 
         match (errors.as_slice(), len_ok) {
             ([], true) => true,
-            ([], false) => {
-                let error = self
-                    .hir
+            ([], false) if got.len() < exp.len() => {
+                self.hir
                     .sources
                     .error("missing function argument")
-                    .m(self.module());
-
-                // if got.len().abs_diff(exp.len()) == 1 {
-                //     error.eline(
-                //         span,
-                //         format!(
-                //             "missing argument of type `{}`",
-                //             tyfmt.fmt(&*exp[exp.len() - 1])
-                //         ),
-                //     )
-                // } else {
-                warn!("TODO: show full typings");
-                error
+                    .m(self.module())
+                    .eline(
+                        span,
+                        format!("expected {} arguments, was given {}", exp.len(), got.len()),
+                    )
+                    .fix(self.partial_application_fix(span))
+                    .emit();
+                false
+            }
+            ([], false) => {
+                self.hir
+                    .sources
+                    .error("too many function arguments")
+                    .m(self.module())
                     .eline(
                         span,
                         format!("expected {} arguments, was given {}", exp.len(), got.len()),
                     )
-                    // }
                     .emit();
                 false
             }
@@ -165,6 +164,18 @@ impl<'a, 's> Verify<'a, 's> {
         }
     }
 
+    /// Calls given too few arguments are most often meant to be passed as a closure
+    pub fn partial_application_fix(&self, span: Span) -> ast::Fix {
+        let module = self.module();
+        let replacement = format!("#({})", self.hir.sources.get_span(module, span));
+        ast::Fix {
+            module,
+            span,
+            title: format!("partially apply it as a closure with `{replacement}`"),
+            replacement,
+        }
+    }
+
     pub fn emit_type_mismatch(
         &self,
         span: Span,
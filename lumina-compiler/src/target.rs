@@ -1,4 +1,5 @@
 use std::fmt;
+use std::path::{Path, PathBuf};
 
 #[derive(Clone, Copy)]
 pub struct Target {
@@ -9,11 +10,19 @@ pub struct Target {
 #[derive(Clone, Copy)]
 pub enum Arch {
     X86_64,
+    Aarch64,
+    Wasm32,
 }
 
 #[derive(Clone, Copy)]
 pub enum Platform {
-    Linux { sub: LinuxPlatform },
+    Linux {
+        sub: LinuxPlatform,
+    },
+    Windows,
+    Darwin,
+    /// WebAssembly modules run by a WASI runtime, which is only paired with `Arch::Wasm32`
+    Wasi,
 }
 
 #[derive(Clone, Copy)]
@@ -24,9 +33,21 @@ pub enum LinuxPlatform {
 }
 
 impl Arch {
+    #[cfg(target_arch = "x86_64")]
+    fn native() -> Self {
+        Arch::X86_64
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    fn native() -> Self {
+        Arch::Aarch64
+    }
+
     fn name(&self) -> &'static str {
         match self {
             Arch::X86_64 => "x86_64",
+            Arch::Aarch64 => "aarch64",
+            Arch::Wasm32 => "wasm32",
         }
     }
 }
@@ -39,6 +60,8 @@ impl TryFrom<&str> for Target {
 
         let arch = match iter.next().ok_or("missing target")? {
             "x86_64" => Arch::X86_64,
+            "aarch64" | "arm64" => Arch::Aarch64,
+            "wasm32" => Arch::Wasm32,
             _ => return Err("unsupported CPU architecture"),
         };
         let platform = match iter.next().ok_or("missing platform")? {
@@ -50,9 +73,16 @@ impl TryFrom<&str> for Target {
                     Some(_) => return Err("unknown linux platform"),
                 },
             },
+            "windows" => Platform::Windows,
+            "macos" | "darwin" => Platform::Darwin,
+            "wasi" => Platform::Wasi,
             _ => return Err("unsupported platform"),
         };
 
+        if matches!(arch, Arch::Wasm32) != matches!(platform, Platform::Wasi) {
+            return Err("wasm32 is only supported by the wasi platform");
+        }
+
         Ok(Target { arch, platform })
     }
 }
@@ -60,12 +90,18 @@ impl TryFrom<&str> for Target {
 impl Target {
     #[cfg(target_os = "linux")]
     pub fn native() -> Self {
-        #[cfg(target_arch = "x86_64")]
-        let arch = Arch::X86_64;
-        Target { platform: Platform::Linux { sub: LinuxPlatform::Gnu }, arch }
+        Target {
+            platform: Platform::Linux { sub: LinuxPlatform::Gnu },
+            arch: Arch::native(),
+        }
     }
 
-    #[cfg(not(target_os = "linux"))]
+    #[cfg(target_os = "macos")]
+    pub fn native() -> Self {
+        Target { platform: Platform::Darwin, arch: Arch::native() }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
     pub fn native() -> Self {
         panic!("unknown platform");
     }
@@ -74,15 +110,22 @@ impl Target {
         self.to_string()
     }
 
+    /// Directory in LUMINAPATH for everything built for this target, so that builds for different
+    /// targets never overwrite each other's artifacts
+    pub fn artifact_dir(&self, luminapath: &Path) -> PathBuf {
+        luminapath.join("artifacts").join(self.name())
+    }
+
     pub fn int_size(&self) -> u8 {
         match self.arch {
-            Arch::X86_64 => 64,
+            Arch::X86_64 | Arch::Aarch64 => 64,
+            Arch::Wasm32 => 32,
         }
     }
 
     pub fn endian(&self) -> gimli::RunTimeEndian {
         match self.arch {
-            Arch::X86_64 => gimli::RunTimeEndian::Little,
+            Arch::X86_64 | Arch::Aarch64 | Arch::Wasm32 => gimli::RunTimeEndian::Little,
         }
     }
 
@@ -101,17 +144,26 @@ impl Target {
 
         let targetted = iter.all(|name| match name {
             "unix" => match self.platform {
-                Platform::Linux { .. } => true,
+                Platform::Linux { .. } | Platform::Darwin => true,
+                Platform::Windows | Platform::Wasi => false,
             },
             "linux" => match self.platform {
                 Platform::Linux { .. } => true,
+                Platform::Windows | Platform::Darwin | Platform::Wasi => false,
             },
+            "windows" => matches!(self.platform, Platform::Windows),
+            "macos" | "darwin" => matches!(self.platform, Platform::Darwin),
+            "wasi" => matches!(self.platform, Platform::Wasi),
             "gnu" => matches!(self.platform, Platform::Linux { sub: LinuxPlatform::Gnu }),
             "musl" => matches!(self.platform, Platform::Linux { sub: LinuxPlatform::Musl }),
             "syscall" => matches!(
                 self.platform,
                 Platform::Linux { sub: LinuxPlatform::Syscall }
             ),
+            "libc" => !matches!(
+                self.platform,
+                Platform::Linux { sub: LinuxPlatform::Syscall } | Platform::Wasi
+            ),
             _ => self.arch.name() == name,
         });
 
@@ -120,13 +172,16 @@ impl Target {
 
     pub fn object_extension(&self) -> &'static str {
         match self.platform {
-            Platform::Linux { .. } => "o",
+            Platform::Linux { .. } | Platform::Darwin | Platform::Wasi => "o",
+            Platform::Windows => "obj",
         }
     }
 
     pub fn executable_extension(&self) -> &'static str {
         match self.platform {
-            Platform::Linux { .. } => "out",
+            Platform::Linux { .. } | Platform::Darwin => "out",
+            Platform::Windows => "exe",
+            Platform::Wasi => "wasm",
         }
     }
 }
@@ -147,6 +202,9 @@ impl fmt::Display for Platform {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Platform::Linux { sub } => write!(f, "linux-{sub}"),
+            Platform::Windows => "windows".fmt(f),
+            Platform::Darwin => "macos".fmt(f),
+            Platform::Wasi => "wasi".fmt(f),
         }
     }
 }
@@ -2,7 +2,7 @@ use super::{Expr, Parser, Token, Type};
 use lumina_util::{Highlighting, Tr};
 use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Declaration<'a> {
     pub name: Tr<&'a str>,
     pub dst: Tr<Type<'a>>,
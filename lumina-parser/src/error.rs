@@ -18,6 +18,7 @@ pub enum Error {
     ConflictingBars(IndentConflict),
     MissingReturnType(Span),
     NestedWhere { previous: Span, kw: Span },
+    OpaqueParameter(Span),
 }
 
 impl<'a> Parser<'a> {
@@ -80,4 +81,10 @@ impl<'a> Parser<'a> {
     pub(crate) fn err_nested_where(&mut self, previous: Span, kw: Span) {
         self.errors.push(Error::NestedWhere { previous, kw });
     }
+
+    pub(crate) fn err_opaque_parameter(&mut self, some: Option<Span>) {
+        if let Some(span) = some {
+            self.errors.push(Error::OpaqueParameter(span));
+        }
+    }
 }
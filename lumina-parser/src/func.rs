@@ -23,6 +23,10 @@ pub struct Typing<'a> {
     pub span: Span,
     pub ptypes: Vec<Tr<Type<'a>>>,
     pub returns: Tr<Type<'a>>,
+    /// `some Trait` return types hide the returned type behind the trait
+    pub opaque: Option<Span>,
+    /// A trailing `...` lets extern functions take any amount of additional parameters
+    pub variadic: Option<Span>,
 }
 
 #[derive(Clone, Debug)]
@@ -110,8 +114,17 @@ impl<'a> Parser<'a> {
 
     fn func_typing(&mut self, start: Span) -> Option<(Typing<'a>, bool)> {
         let mut ptypes = vec![];
+        let mut variadic = None;
 
         loop {
+            if let Some(span) = self.next_is(|t| t == T::Ellipsis) {
+                variadic = Some(span);
+                self.expect(T::Arrow)?;
+                break;
+            }
+
+            let opaque = self.opaque_marker();
+
             match self.type_with_params() {
                 Some(t) => ptypes.push(t),
                 None => {
@@ -122,7 +135,16 @@ impl<'a> Parser<'a> {
 
             let shorthand_return = |mut ptypes: Vec<_>, eq, end| {
                 let returns = ptypes.remove(0);
-                Some((Typing { span: start.extend(end), ptypes, returns }, eq))
+                Some((
+                    Typing {
+                        span: start.extend(end),
+                        ptypes,
+                        returns,
+                        opaque,
+                        variadic: None,
+                    },
+                    eq,
+                ))
             };
 
             select! { self, "`->`, `=` or `,`", span peeked: true;
@@ -132,10 +154,12 @@ impl<'a> Parser<'a> {
                 },
                 T::Arrow => {
                     self.progress();
+                    self.err_opaque_parameter(opaque);
                     break;
                 },
                 T::Comma => {
                     self.progress();
+                    self.err_opaque_parameter(opaque);
                     continue;
                 },
                 _ if ptypes.len() == 1 => {
@@ -144,14 +168,41 @@ impl<'a> Parser<'a> {
             }
         }
 
+        let opaque = self.opaque_marker();
+
         self.type_with_params().map(|returns| {
             (
-                Typing { span: start.extend(returns.span), ptypes, returns },
+                Typing {
+                    span: start.extend(returns.span),
+                    ptypes,
+                    returns,
+                    opaque,
+                    variadic,
+                },
                 false,
             )
         })
     }
 
+    // `some` is only treated as a marker when a type follows it on the same line, so that a type
+    // which happens to be named `some` can still be returned.
+    fn opaque_marker(&mut self) -> Option<Span> {
+        let (t, span) = self.lexer.peek();
+        if t != T::Path || self.take(span) != "some" {
+            return None;
+        }
+
+        let mut ahead = self.lexer.clone();
+        ahead.next();
+        match ahead.peek_line_sensitive().0 {
+            T::Path | T::AnnotatedPath | T::FnOpenParen | T::OpenParen => {
+                self.progress();
+                Some(span)
+            }
+            _ => None,
+        }
+    }
+
     fn where_binds(&mut self, kw_span: Span) -> Vec<Declaration<'a>> {
         let base_indent = self.lexer.current_indent();
 
@@ -252,10 +303,21 @@ impl<'a> fmt::Display for Header<'a> {
 
 impl<'a> fmt::Display for Typing<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.ptypes.is_empty() {
-            self.returns.fmt(f)
+        let some = if self.opaque.is_some() { "some " } else { "" };
+        let dots = match (self.variadic.is_some(), self.ptypes.is_empty()) {
+            (false, _) => "",
+            (true, true) => "...",
+            (true, false) => ", ...",
+        };
+        if self.ptypes.is_empty() && dots.is_empty() {
+            write!(f, "{some}{}", self.returns)
         } else {
-            write!(f, "{} -> {}", self.ptypes.iter().format(", "), self.returns)
+            write!(
+                f,
+                "{}{dots} -> {some}{}",
+                self.ptypes.iter().format(", "),
+                self.returns
+            )
         }
     }
 }
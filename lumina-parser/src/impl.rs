@@ -5,7 +5,7 @@ use lumina_key as key;
 use lumina_util::Highlighting;
 use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Declaration<'a> {
     pub header: Header<'a>,
     pub attributes: Vec<Tr<Expr<'a>>>,
@@ -14,7 +14,7 @@ pub struct Declaration<'a> {
     pub associations: Map<key::AssociatedType, Association<'a>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Header<'a> {
     pub trait_: Tr<Type<'a>>,
     pub impltor: Tr<Type<'a>>,
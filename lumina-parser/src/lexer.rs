@@ -65,6 +65,8 @@ pub enum Token {
     For,
     #[token("..")]
     DotDot,
+    #[token("...")]
+    Ellipsis,
     #[token("@")]
     At,
     #[token("then")]
@@ -190,6 +192,7 @@ impl Token {
             T::Match => "start of match expression",
             T::For => "`for` keyword",
             T::DotDot => "`..`",
+            T::Ellipsis => "`...`",
             T::Dot => "dot pipe",
             T::At => "`@`",
             T::Then => "`then` keyword",
@@ -228,6 +231,13 @@ impl Token {
     }
 }
 
+/// Every token of `src` including comments, for editor highlighting
+pub fn tokenize(src: &str) -> impl Iterator<Item = (Token, Span)> + '_ {
+    Token::lexer(src)
+        .spanned()
+        .map(|(token, range)| (token, Span::from(range)))
+}
+
 pub struct Lexer<'src> {
     logos: SpannedIter<'src, Token>,
     indentation: u16,
@@ -307,7 +317,7 @@ impl<'src> Lexer<'src> {
         } else {
             let t = match self.logos.next() {
                 None => self.eof(),
-                Some((Token::LineComment, _)) => self.peek_line_sensitive(),
+                Some((Token::LineComment | Token::LineDocComment, _)) => self.peek_line_sensitive(),
                 Some((t, mut range)) => {
                     if t == Token::NewLines {
                         self.register_newline(range.clone());
@@ -330,7 +340,7 @@ impl<'src> Lexer<'src> {
                 self.register_newline(span);
                 self.generate()
             }
-            Some((Token::LineComment, _)) => self.generate(),
+            Some((Token::LineComment | Token::LineDocComment, _)) => self.generate(),
             Some((t, mut range)) => {
                 range.start += self.span_offset;
                 range.end += self.span_offset;
@@ -3,7 +3,7 @@ use lumina_util::{Identifier, Span, Spanned, Tr};
 use std::fmt;
 
 mod lexer;
-pub use lexer::Token;
+pub use lexer::{tokenize, Token};
 use lexer::{Lexer, Token as T};
 
 mod expr;
@@ -136,6 +136,7 @@ impl<'a> Parser<'a> {
             | Declaration::Impl(r#impl::Declaration { attributes, .. })
             | Declaration::Type(ty::Declaration { attributes, .. })
             | Declaration::Alias(alias::Declaration { attributes, .. })
+            | Declaration::Val(val::Declaration { attributes, .. })
             | Declaration::Function(func::Declaration { attributes, .. }) => {
                 attributes.extend(attribute);
                 decl
@@ -318,7 +319,7 @@ impl<'a> Parser<'a> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Declaration<'a> {
     ModuleAttribute(Span, Vec<Tr<expr::Expr<'a>>>),
     Function(func::Declaration<'a>),
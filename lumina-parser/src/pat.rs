@@ -19,9 +19,30 @@ pub enum Pattern<'a> {
         init: Box<Tr<Self>>,
         ops: Vec<(&'a str, Tr<Self>)>,
     },
+    Binary(Vec<Tr<Segment<'a>>>),
     Poison,
 }
 
+/// A segment of a binary pattern such as `len:u16` or `body:bytes[len]`
+#[derive(Clone, Debug)]
+pub struct Segment<'a> {
+    pub value: SegmentValue<'a>,
+    pub kind: Option<Tr<&'a str>>,
+    pub size: Option<Tr<SegmentSize<'a>>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum SegmentValue<'a> {
+    Name(&'a str),
+    Int(Bound),
+}
+
+#[derive(Clone, Debug)]
+pub enum SegmentSize<'a> {
+    Int(u64),
+    Name(&'a str),
+}
+
 #[derive(Clone, PartialEq, Eq, Copy)]
 pub enum Bound {
     Excess,
@@ -62,10 +83,84 @@ impl<'a> Parser<'a> {
             T::CharLiteral => self.pat_char(span, params),
             T::OpenParen => self.pat_paren(span),
             T::OpenCurly => self.pat_record(span),
-            T::OpenList => self.pat_list(span)
+            T::OpenList => self.pat_list(span),
+            T::Operator => self.pat_binary(span)
+        }
+    }
+
+    // <<len:u16, body:bytes[len], rest:bytes>>
+    fn pat_binary(&mut self, span: Span) -> Option<Tr<Pattern<'a>>> {
+        match self.take(span) {
+            "<<>>" => return Some(Pattern::Binary(vec![]).tr(span)),
+            "<<" => {}
+            _ => {
+                self.err_unexpected_token((T::Operator, span), "pattern");
+                return None;
+            }
+        }
+
+        let mut segments = vec![self.pat_segment()?];
+
+        loop {
+            match self.lexer.peek() {
+                (T::Comma, _) => {
+                    self.progress();
+                    segments.push(self.pat_segment()?);
+                }
+                (T::Operator, end) if self.take(end) == ">>" => {
+                    self.progress();
+                    return Some(Pattern::Binary(segments).tr(span.extend(end)));
+                }
+                (t, span) => {
+                    self.err_unexpected_token((t, span), "`,` or `>>`");
+                    return None;
+                }
+            }
         }
     }
 
+    fn pat_segment(&mut self) -> Option<Tr<Segment<'a>>> {
+        let (value, kind, mut span) = select! { self, "name or integer", span;
+            T::Path => {
+                // `len:u16` is lexed as a single path
+                let str = self.take(span);
+                match str.rfind(':') {
+                    Some(at) => {
+                        let kind = str[at + 1..].tr(span.move_indice(at as i32 + 1));
+                        (SegmentValue::Name(&str[..at]), Some(kind), span)
+                    }
+                    None => (SegmentValue::Name(str), None, span),
+                }
+            },
+            T::Int => {
+                let value = SegmentValue::Int(self.int_to_bound(span));
+                match self.lexer.peek() {
+                    (T::Operator, colon) if self.take(colon) == ":" => {
+                        self.progress();
+                        let kind = self.expect_name("segment type")?;
+                        (value, Some(kind), span.extend(kind.span))
+                    }
+                    _ => (value, None, span),
+                }
+            }
+        };
+
+        let size = match self.next_is(|t| t == T::OpenList) {
+            None => None,
+            Some(open) => {
+                let size = select! { self, "size of segment", sspan;
+                    T::Int => SegmentSize::Int(self.take(sspan).parse().unwrap()).tr(sspan),
+                    T::Path => SegmentSize::Name(self.take(sspan)).tr(sspan)
+                };
+                let end = self.expect(T::CloseList).unwrap_or(open);
+                span = span.extend(end);
+                Some(size)
+            }
+        };
+
+        Some(Segment { value, kind, size }.tr(span))
+    }
+
     fn pat_extractor(&mut self, span: Span, params: bool) -> Option<Tr<Pattern<'a>>> {
         let expr = ExprParser::new(self).expr_pass_expr(span)?;
 
@@ -325,11 +420,31 @@ impl<'a> fmt::Display for Pattern<'a> {
                 ops.iter()
                     .format_with(" ", |(op, right), f| f(&format_args!("{} {}", op, right)))
             ),
+            Pattern::Binary(segments) => write!(f, "<<{}>>", segments.iter().format(", ")),
             Pattern::Poison => "???".fmt(f),
         }
     }
 }
 
+impl<'a> fmt::Display for Segment<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.value {
+            SegmentValue::Name(name) => name.fmt(f)?,
+            SegmentValue::Int(n) => n.numeric().fmt(f)?,
+        }
+
+        if let Some(kind) = &self.kind {
+            write!(f, ":{kind}")?;
+        }
+
+        match self.size.as_ref().map(|size| &size.value) {
+            Some(SegmentSize::Int(n)) => write!(f, "[{n}]"),
+            Some(SegmentSize::Name(name)) => write!(f, "[{name}]"),
+            None => Ok(()),
+        }
+    }
+}
+
 impl fmt::Display for Bound {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
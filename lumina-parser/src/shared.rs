@@ -73,6 +73,8 @@ impl<'a> CurlyValue<'a> for Pattern<'a> {
 pub enum ListLength<'a> {
     Name(Tr<&'a str>),
     Exact(Tr<u64>),
+    /// `; ..` for slices, whose length is only known at runtime
+    Unknown,
     None,
 }
 
@@ -345,6 +347,7 @@ impl<'a> Parser<'a> {
                             let name = self.taken(nspan);
                             ListLength::Name(name)
                         },
+                        (Token::DotDot, _) => ListLength::Unknown,
                         (Token::CloseList, _) => break Some((fields, ListLength::None, span)),
                         got => {
                             self.err_unexpected_token(got, "array size");
@@ -483,6 +486,7 @@ impl<'a> fmt::Display for ListLength<'a> {
         match self {
             ListLength::Name(name) => write!(f, "; {name}"),
             ListLength::Exact(n) => write!(f, "; {n}"),
+            ListLength::Unknown => write!(f, "; .."),
             ListLength::None => Ok(()),
         }
     }
@@ -232,6 +232,7 @@ fn map f list as fn(a -> b), [a] -> [b] =
   | []     -> []
 "
 );
+declaration!(decl_extern_variadic, "fn printf format as *u8, ... -> i32");
 declaration!(
     decl_impl_into,
     "
@@ -5,21 +5,21 @@ use lumina_key::Map;
 use lumina_util::{Highlighting, Identifier, Span, Spanned, Tr};
 use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Declaration<'a> {
     pub header: Header<'a>,
     pub body: DeclarationBody<'a>,
     pub attributes: Vec<Tr<Expr<'a>>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Header<'a> {
     pub span: Span,
     pub name: &'a str,
     pub type_params: Map<key::Generic, &'a str>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum DeclarationBody<'a> {
     Record(RecordBody<'a>),
     Sum(SumBody<'a>),
@@ -33,18 +33,18 @@ impl<'a> DeclarationBody<'a> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SumBody<'a> {
     pub variants: Map<key::Variant, (Span, &'a str, Vec<Tr<Type<'a>>>)>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TraitBody<'a> {
     pub associations: Map<key::AssociatedType, r#impl::Association<'a>>,
     pub methods: Map<key::Method, func::Declaration<'a>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RecordBody<'a> {
     pub fields: Map<key::Field, (Span, &'a str, Type<'a>)>,
 }
@@ -2,13 +2,14 @@ use super::{select, Expr, Parser, Type, T};
 use lumina_util::{Highlighting, Span, Tr};
 use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Declaration<'a> {
     pub span: Span,
     pub name: &'a str,
     pub type_: Option<Tr<Type<'a>>>,
     pub value: Tr<Expr<'a>>,
     pub public: bool,
+    pub attributes: Vec<Tr<Expr<'a>>>,
 }
 
 impl<'a> Parser<'a> {
@@ -36,6 +37,7 @@ impl<'a> Parser<'a> {
             span: name.span.extend(value.span),
             value,
             public: false,
+            attributes: vec![],
         })
     }
 }
@@ -1,7 +1,7 @@
 use super::{
     tenv::FieldMismatch, Compatibility as C, ConstGeneric, ConstValue, Constraint, Container,
-    Forall, GetForall, GetImplData, IType, ImplIndex, IntConstraint, IntSize, Static, Transformer,
-    Ty, Type, TypeSystem, Upgrade, Var,
+    Forall, GetForall, GetImplData, IType, ImplIndex, IntConstraint, IntSize, NearMiss, Static,
+    Transformer, Ty, Type, TypeSystem, Unresolved, Upgrade, Var,
 };
 use key::M;
 use lumina_key as key;
@@ -253,7 +253,8 @@ pub enum ConstraintError {
     },
     IntConstantNegativeUnsigned(Span, IntSize),
     IntConstantTooLarge(Span, i128, IntSize),
-    Trait(Tr<Type>, Constraint<Static>),
+    Trait(Tr<Type>, Constraint<Static>, Vec<NearMiss>),
+    TraitCycle(Tr<Type>, Constraint<Static>),
     FieldType {
         exp: Type,
         got: Type,
@@ -349,10 +350,16 @@ impl<'e, 's> TypeSystem<'e, 's> {
                 // Check trait constraints
                 for con in &vdata.trait_constraints {
                     let con = Upgrade(self.env).transform_constraint(con);
-                    let ok = C::constraint(index, in_trait, get_impl_data, lhs_forall, &ty, &con);
-
-                    if !ok {
-                        errors.push(ConstraintError::Trait(ty.clone().tr(tyspan), con));
+                    match C::resolve(index, in_trait, get_impl_data, lhs_forall, &ty, &con) {
+                        Ok(()) => {}
+                        Err(Unresolved::NotImplemented) => {
+                            let misses =
+                                C::near_misses(index, get_impl_data, lhs_forall, &ty, &con);
+                            errors.push(ConstraintError::Trait(ty.clone().tr(tyspan), con, misses))
+                        }
+                        Err(Unresolved::Cycle) => {
+                            errors.push(ConstraintError::TraitCycle(ty.clone().tr(tyspan), con))
+                        }
                     }
                 }
 
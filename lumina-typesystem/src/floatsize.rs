@@ -0,0 +1,35 @@
+use std::fmt;
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, PartialOrd, Ord, Hash)]
+pub enum FloatSize {
+    F32,
+    F64,
+}
+
+impl FloatSize {
+    /// The size of the float type with this name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "f32" => Some(FloatSize::F32),
+            "f64" => Some(FloatSize::F64),
+            _ => None,
+        }
+    }
+
+    pub fn bytes(self) -> u8 {
+        match self {
+            FloatSize::F32 => 4,
+            FloatSize::F64 => 8,
+        }
+    }
+
+    pub fn bits(self) -> u8 {
+        self.bytes() * 8
+    }
+}
+
+impl fmt::Display for FloatSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "f{}", self.bits())
+    }
+}
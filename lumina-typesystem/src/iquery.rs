@@ -7,8 +7,6 @@ use lumina_key as key;
 use std::collections::HashMap;
 use tracing::{info, warn};
 
-// TODO: Specialisation via explicit default notation
-
 /// The way we check constraints and select implementations is by making type check against the
 /// implementation's types. However; checking every single implementation to find a valid one would
 /// of course be an absurdly slow operation.
@@ -81,24 +79,141 @@ impl ImplIndex {
             .unwrap_or(&[])
     }
 
-    pub fn for_each_relevant<T>(
+    /// All implementations of the trait which might apply to the implementor, with implementations
+    /// for its concrete type before the blanket implementations.
+    pub fn relevant(
         &self,
         trait_: M<key::Trait>,
         impltor: Option<ConcreteType>,
-        mut for_each: impl FnMut(M<key::Impl>) -> Option<T>,
-    ) -> Option<T> {
-        let (blanked, concrete) = self.traits.get(&trait_)?;
-
-        match impltor {
-            Some(c) => concrete
-                .get(&c)
-                .and_then(|these| these.iter().copied().find_map(&mut for_each)),
-            None => None,
+    ) -> impl Iterator<Item = M<key::Impl>> + '_ {
+        let (blanked, concrete): (&[_], &[_]) = match self.traits.get(&trait_) {
+            Some((blanked, concrete)) => (
+                blanked.as_slice(),
+                impltor
+                    .and_then(|c| concrete.get(&c))
+                    .map(Vec::as_slice)
+                    .unwrap_or(&[]),
+            ),
+            None => (&[], &[]),
+        };
+
+        concrete.iter().chain(blanked).copied()
+    }
+
+    /// Whether every type accepted by the implementation `a` is also accepted by `b`.
+    ///
+    /// When both implementations apply to a type, the one which is subsumed by the other is the
+    /// more specific one and takes precedence.
+    pub fn is_subsumed_by(
+        &self,
+        get_impl_data: GetImplData,
+        a: M<key::Impl>,
+        b: M<key::Impl>,
+    ) -> bool {
+        let (_, _, aimpltor, atrtp) = get_impl_data(a);
+        let (_, bforall, bimpltor, btrtp) = get_impl_data(b);
+
+        let mut comp = Compatibility::new(
+            self,
+            &|_| panic!("constraint checked during specificity comparison"),
+            bforall,
+            get_impl_data,
+        );
+
+        comp.cmp(aimpltor, bimpltor) && comp.cmps(atrtp, btrtp)
+    }
+
+    /// Find all pairs of implementations for the same trait which overlap without either of them
+    /// being more specific than the other.
+    ///
+    /// Since trait constraints are not considered, implementations which are only disjoint
+    /// because of their constraints are also reported.
+    pub fn conflicts(&self, get_impl_data: GetImplData) -> Vec<(M<key::Impl>, M<key::Impl>)> {
+        let mut conflicts = vec![];
+        let mut check = |a, b| {
+            if self.is_conflicting(get_impl_data, a, b) {
+                conflicts.push((a, b));
+            }
+        };
+
+        // Implementations for different concrete types can never overlap, while blanket
+        // implementations can overlap with anything.
+        for (blanked, concrete) in self.traits.values() {
+            for these in concrete.values() {
+                for (i, &a) in these.iter().enumerate() {
+                    these[i + 1..]
+                        .iter()
+                        .chain(blanked)
+                        .for_each(|&b| check(a, b));
+                }
+            }
+
+            for (i, &a) in blanked.iter().enumerate() {
+                blanked[i + 1..].iter().for_each(|&b| check(a, b));
+            }
+        }
+
+        conflicts
+    }
+
+    fn is_conflicting(&self, get_impl_data: GetImplData, a: M<key::Impl>, b: M<key::Impl>) -> bool {
+        let (_, _, aimpltor, atrtp) = get_impl_data(a);
+        let (_, _, bimpltor, btrtp) = get_impl_data(b);
+
+        let overlaps = unifiable(aimpltor, bimpltor)
+            && atrtp.len() == btrtp.len()
+            && atrtp.iter().zip(btrtp).all(|(a, b)| unifiable(a, b));
+
+        overlaps
+            && self.is_subsumed_by(get_impl_data, a, b) == self.is_subsumed_by(get_impl_data, b, a)
+    }
+}
+
+/// Whether some type could be accepted by both `a` and `b`, assuming that generics are independent
+/// of each other.
+fn unifiable(a: &Type, b: &Type) -> bool {
+    match (a, b) {
+        (Ty::Generic(_), _) | (_, Ty::Generic(_)) => true,
+        (Ty::Int(a), Ty::Int(b)) => a == b,
+        (Ty::Simple(a), Ty::Simple(b)) => a == b,
+        (Ty::Const(a), Ty::Const(b)) => a == b,
+        (Ty::Container(a, aparams), Ty::Container(b, bparams)) => {
+            a == b
+                && aparams.len() == bparams.len()
+                && aparams.iter().zip(bparams).all(|(a, b)| unifiable(a, b))
         }
-        .or_else(|| blanked.iter().copied().find_map(for_each))
+        _ => false,
     }
 }
 
+/// The reason a trait constraint could not be resolved
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Unresolved {
+    NotImplemented,
+    Cycle,
+}
+
+/// An implementation of the trait which was considered for a constraint but rejected
+#[derive(Clone)]
+pub struct NearMiss {
+    pub ikey: M<key::Impl>,
+    pub reason: Rejection,
+}
+
+#[derive(Clone)]
+pub enum Rejection {
+    /// The implementor or trait parameters are incompatible with those of the constraint
+    Mismatch,
+    /// The types are compatible, but the instantiated type doesn't satisfy a constraint of the
+    /// implementation
+    Unmet(Type, Constraint<Static>),
+}
+
+const MAX_RESOLVE_DEPTH: usize = 64;
+
+// Implementor and trait parameters of a constraint which is currently being resolved
+type Resolving = (Type, M<key::Trait>, Vec<Type>);
+
 // Getter for the forall of a kind
 pub(crate) type GetForall<'a, 't, 's> = &'a dyn Fn(GenericKind) -> &'t Forall<'s, Static>;
 
@@ -136,27 +251,31 @@ impl<'a, 't, 's> Compatibility<'a, 't, 's> {
         self.mapping
     }
 
-    pub fn check_all(self) -> bool {
-        self.mapping
-            .assignments_by_kind(GenericKind::Parent)
-            .all(|(key, ty)| {
-                self.rhs_forall[key].trait_constraints.iter().all(|con| {
-                    // Since all types have already been visited and mapped, we
-                    // can instantiate the types in the constraint ahead of time so we don't need
-                    // to keep around multiple `mapping`.
-                    // let con = map_con(con, |ty| apply_mapping(&self.mapping, ty));
-                    let con = (&self.mapping).transform_constraint(con);
-
-                    Compatibility::constraint(
-                        self.impls,
-                        None,
-                        self.get_impl_data,
-                        self.lhs_forall,
-                        ty,
-                        &con,
-                    )
-                })
-            })
+    pub fn check_all(&self) -> bool {
+        self.resolve_all(&mut vec![]).is_ok()
+    }
+
+    fn resolve_all(&self, stack: &mut Vec<Resolving>) -> Result<(), Unresolved> {
+        for (key, ty) in self.mapping.assignments_by_kind(GenericKind::Parent) {
+            for con in &self.rhs_forall[key].trait_constraints {
+                // Since all types have already been visited and mapped, we
+                // can instantiate the types in the constraint ahead of time so we don't need
+                // to keep around multiple `mapping`.
+                let con = (&self.mapping).transform_constraint(con);
+
+                Compatibility::resolve_in(
+                    self.impls,
+                    None,
+                    self.get_impl_data,
+                    self.lhs_forall,
+                    ty,
+                    &con,
+                    stack,
+                )?;
+            }
+        }
+
+        Ok(())
     }
 
     /// Check whether `got` satisfies the constraint `con`.
@@ -170,6 +289,94 @@ impl<'a, 't, 's> Compatibility<'a, 't, 's> {
         got: &Type,
         con: &Constraint<Static>,
     ) -> bool {
+        Compatibility::resolve(impls, in_trait, get_impl_data, lhs_forall, got, con).is_ok()
+    }
+
+    /// Resolve whether `got` satisfies the constraint `con`, recursively resolving the constraints
+    /// of any blanket implementation which applies.
+    pub fn resolve(
+        impls: &'a ImplIndex,
+        in_trait: Option<(M<key::Trait>, &'t Forall<'s, Static>)>,
+        get_impl_data: GetImplData<'a, 't, 's>,
+        lhs_forall: GetForall<'a, 't, 's>,
+        got: &Type,
+        con: &Constraint<Static>,
+    ) -> Result<(), Unresolved> {
+        let mut stack = vec![];
+        Compatibility::resolve_in(
+            impls,
+            in_trait,
+            get_impl_data,
+            lhs_forall,
+            got,
+            con,
+            &mut stack,
+        )
+    }
+
+    /// The implementations which were considered for `con` but rejected, and why each of them
+    /// was rejected.
+    ///
+    /// Only meant for explaining a constraint which failed to resolve.
+    pub fn near_misses(
+        impls: &'a ImplIndex,
+        get_impl_data: GetImplData<'a, 't, 's>,
+        lhs_forall: GetForall<'a, 't, 's>,
+        got: &Type,
+        con: &Constraint<Static>,
+    ) -> Vec<NearMiss> {
+        impls
+            .relevant(con.trait_, got.try_into().ok())
+            .filter_map(|ikey| {
+                let (_, iforall, iimpltor, itrtp) = get_impl_data(ikey);
+                let mut comp = Compatibility::new(impls, lhs_forall, iforall, get_impl_data);
+
+                let reason = if !comp.cmp(got, iimpltor) || !comp.cmps(&con.params, itrtp) {
+                    Rejection::Mismatch
+                } else {
+                    let (ty, con) = comp.first_unmet()?;
+                    Rejection::Unmet(ty, con)
+                };
+
+                Some(NearMiss { ikey, reason })
+            })
+            .collect()
+    }
+
+    // The first constraint of the implementation which isn't satisfied by the types its generics
+    // were mapped to
+    fn first_unmet(&self) -> Option<(Type, Constraint<Static>)> {
+        self.mapping
+            .assignments_by_kind(GenericKind::Parent)
+            .find_map(|(key, ty)| {
+                self.rhs_forall[key]
+                    .trait_constraints
+                    .iter()
+                    .map(|con| (&self.mapping).transform_constraint(con))
+                    .find(|con| {
+                        Compatibility::resolve(
+                            self.impls,
+                            None,
+                            self.get_impl_data,
+                            self.lhs_forall,
+                            ty,
+                            con,
+                        )
+                        .is_err()
+                    })
+                    .map(|con| (ty.clone(), con))
+            })
+    }
+
+    fn resolve_in(
+        impls: &'a ImplIndex,
+        in_trait: Option<(M<key::Trait>, &'t Forall<'s, Static>)>,
+        get_impl_data: GetImplData<'a, 't, 's>,
+        lhs_forall: GetForall<'a, 't, 's>,
+        got: &Type,
+        con: &Constraint<Static>,
+        stack: &mut Vec<Resolving>,
+    ) -> Result<(), Unresolved> {
         info!("checking if {got} implements {con}");
 
         // First check whether the constraint is directly satisfied by the given type being a
@@ -182,7 +389,7 @@ impl<'a, 't, 's> Compatibility<'a, 't, 's> {
                         debug_assert_eq!(gcon.params.len(), con.params.len());
 
                         if gcon.params.iter().eq(&con.params) {
-                            return true;
+                            return Ok(());
                         }
                     }
                 }
@@ -201,26 +408,46 @@ impl<'a, 't, 's> Compatibility<'a, 't, 's> {
                             _ => false,
                         })
                 {
-                    return true;
+                    return Ok(());
                 }
             }
             _ => {}
         }
 
+        // A constraint which depends on itself can never be satisfied. Constraints which keep
+        // growing their types are caught by the depth limit instead.
+        let resolving = (got.clone(), con.trait_, con.params.clone());
+        if stack.len() >= MAX_RESOLVE_DEPTH || stack.contains(&resolving) {
+            warn!("cycle while checking if {got} implements {con}");
+            return Err(Unresolved::Cycle);
+        }
+
         // If not then fall back to querying the implementations.
-        impls
-            .for_each_relevant::<()>(con.trait_, got.try_into().ok(), |ikey: _| {
-                let (_, iforall, iimpltor, itrtp) = get_impl_data(ikey);
-                let mut comp = Compatibility::new(impls, lhs_forall, iforall, get_impl_data);
+        stack.push(resolving);
 
-                // Return None if the types aren't compatible
-                comp.cmp(got, iimpltor).then(|| ())?;
-                comp.cmps(&con.params, itrtp).then(|| ())?;
+        let mut result = Err(Unresolved::NotImplemented);
+        for ikey in impls.relevant(con.trait_, got.try_into().ok()) {
+            let (_, iforall, iimpltor, itrtp) = get_impl_data(ikey);
+            let mut comp = Compatibility::new(impls, lhs_forall, iforall, get_impl_data);
 
-                // If they were compatible; then check any constraints that arose.
-                comp.check_all().then(|| ())
-            })
-            .is_some()
+            // Skip if the types aren't compatible
+            if !comp.cmp(got, iimpltor) || !comp.cmps(&con.params, itrtp) {
+                continue;
+            }
+
+            // If they were compatible; then check any constraints that arose.
+            match comp.resolve_all(stack) {
+                Ok(()) => {
+                    result = Ok(());
+                    break;
+                }
+                Err(Unresolved::Cycle) => result = Err(Unresolved::Cycle),
+                Err(Unresolved::NotImplemented) => {}
+            }
+        }
+
+        stack.pop();
+        result
     }
 
     pub fn cmp(&mut self, got: &Type, exp: &Type) -> bool {
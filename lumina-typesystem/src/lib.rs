@@ -19,6 +19,9 @@ mod tests;
 mod fin;
 pub use fin::Finalizer;
 
+mod floatsize;
+pub use floatsize::FloatSize;
+
 mod intsize;
 pub use intsize::IntSize;
 
@@ -31,7 +34,7 @@ mod tenv;
 pub use tenv::{IntConstraint, TEnv, Var};
 
 mod iquery;
-pub use iquery::{Compatibility, ConcreteType, ImplIndex};
+pub use iquery::{Compatibility, ConcreteType, ImplIndex, NearMiss, Rejection, Unresolved};
 pub(crate) use iquery::{GetForall, GetImplData};
 
 mod check;
@@ -233,6 +236,7 @@ pub enum Container {
     Tuple,
     Pointer,
     Array,
+    Slice,
     Defined(M<key::TypeKind>, Ignored<Lang>),
 }
 
@@ -273,6 +277,9 @@ impl<T> Ty<T> {
         let size = Ty::Generic(generic);
         Self::Container(Container::Array, vec![inner, size])
     }
+    pub fn slice(inner: Self) -> Self {
+        Self::Container(Container::Slice, vec![inner])
+    }
     pub fn string<K: Into<key::TypeKind>>(key: M<K>, params: Vec<Self>) -> Self {
         let key = key.map(K::into);
         Self::Container(Container::Defined(key, Ignored::new(Lang::String)), params)
@@ -357,6 +364,7 @@ impl Container {
             Container::Tuple => Self::fmt_tuple(elems, format, f),
             Container::Pointer => write!(f, "*{}", format(&elems[0])),
             Container::Array => write!(f, "[{}; {}]", format(&elems[0]), format(&elems[1])),
+            Container::Slice => write!(f, "[{}; ..]", format(&elems[0])),
             Container::Defined(_, Ignored { inner: Lang::String }) => write!(f, "string"),
             Container::Defined(_, Ignored { inner: Lang::List }) => {
                 write!(f, "[{}]", elems.iter().map(format).format(", "))
@@ -99,6 +99,10 @@ impl Error {
         false
     }
 
+    pub fn is_warning(&self) -> bool {
+        self.is_warning
+    }
+
     #[must_use]
     pub fn with_text<S: Into<String>>(mut self, message: S) -> Self {
         self.contexts.push(Context::Text(message.into()));
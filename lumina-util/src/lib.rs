@@ -13,8 +13,13 @@ pub use highlight::*;
 mod span;
 pub use span::*;
 
+mod symbol;
+pub use symbol::{Symbol, SymbolSession};
+
 mod helpers;
 pub use helpers::ParamFmt;
 
 mod test_logger;
 pub use test_logger::test_logger;
+
+pub mod log_buffer;
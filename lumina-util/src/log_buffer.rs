@@ -0,0 +1,65 @@
+//! Tracing output of work done in parallel
+//!
+//! The tree logger writes through [`LogWriter`], which goes to stdout unless the current thread
+//! is capturing its output with [`capture`]. Workers capture the output of each item they work on,
+//! which is then written with [`flush`] in the order the items were given. That way the output
+//! reads the same as it would if everything ran on a single thread.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+}
+
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let captured = CAPTURED.with_borrow_mut(|captured| match captured {
+            Some(captured) => {
+                captured.extend_from_slice(buf);
+                true
+            }
+            None => false,
+        });
+
+        if captured {
+            Ok(buf.len())
+        } else {
+            io::stdout().write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+/// Run `f` with the tracing output of this thread written to a buffer instead of stdout
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Vec<u8>) {
+    // Output leading up to a panic is written right away, as it's likely what's being looked for
+    struct Restore(Option<Vec<u8>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let captured = CAPTURED.replace(self.0.take());
+            if std::thread::panicking() {
+                flush(&captured.unwrap_or_default());
+            }
+        }
+    }
+
+    let _restore = Restore(CAPTURED.replace(Some(vec![])));
+    let v = f();
+    let output = CAPTURED.with_borrow_mut(|captured| captured.take().unwrap_or_default());
+
+    (v, output)
+}
+
+/// Write output previously captured with [`capture`]
+pub fn flush(output: &[u8]) {
+    if !output.is_empty() {
+        let _ = io::stdout().lock().write_all(output);
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Mutex, OnceLock};
+
+/// An interned string
+///
+/// The strings are stored in an arena which is freed once every [`SymbolSession`] has ended, so
+/// symbols may only be used during the compilation they were interned in.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    sessions: usize,
+    indices: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+    arena: Arena,
+}
+
+/// Strings are copied into chunks which are never grown past their capacity, so the strings
+/// never move for as long as the arena is alive.
+#[derive(Default)]
+struct Arena {
+    chunks: Vec<String>,
+}
+
+impl Arena {
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    fn alloc(&mut self, str: &str) -> &'static str {
+        let fits = self
+            .chunks
+            .last()
+            .is_some_and(|chunk| chunk.capacity() - chunk.len() >= str.len());
+
+        if !fits {
+            let size = Self::CHUNK_SIZE.max(str.len());
+            self.chunks.push(String::with_capacity(size));
+        }
+
+        let chunk = self.chunks.last_mut().unwrap();
+        let start = chunk.len();
+        chunk.push_str(str);
+
+        // SAFETY: The chunk had room for the string, so it wasn't reallocated. The reference is
+        // only handed out while the interner owning the arena is alive.
+        unsafe { &*(&chunk[start..] as *const str) }
+    }
+}
+
+fn interner() -> &'static Mutex<Interner> {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(Mutex::default)
+}
+
+/// A compilation in which symbols may be interned
+///
+/// Sessions running at the same time, such as the builds of tests running in parallel, share the
+/// interner. Its strings are freed when the last of them ends.
+pub struct SymbolSession(());
+
+impl SymbolSession {
+    pub fn enter() -> SymbolSession {
+        interner().lock().unwrap().sessions += 1;
+        SymbolSession(())
+    }
+}
+
+impl Drop for SymbolSession {
+    fn drop(&mut self) {
+        let mut interner = interner().lock().unwrap();
+        interner.sessions -= 1;
+        if interner.sessions == 0 {
+            *interner = Interner::default();
+        }
+    }
+}
+
+impl Symbol {
+    pub fn intern(str: &str) -> Symbol {
+        let mut interner = interner().lock().unwrap();
+        assert!(
+            interner.sessions != 0,
+            "symbol `{str}` interned outside of a session"
+        );
+
+        if let Some(&symbol) = interner.indices.get(str) {
+            return symbol;
+        }
+
+        let str = interner.arena.alloc(str);
+        let symbol = Symbol(interner.strings.len() as u32);
+        interner.strings.push(str);
+        interner.indices.insert(str, symbol);
+        symbol
+    }
+
+    pub fn as_str(&self) -> &str {
+        interner().lock().unwrap().strings[self.0 as usize]
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(str: &str) -> Symbol {
+        Symbol::intern(str)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(str: String) -> Symbol {
+        Symbol::intern(&str)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
@@ -8,7 +8,7 @@ pub fn test_logger() {
         let filter = EnvFilter::from_default_env();
 
         let layer = tracing_tree::HierarchicalLayer::default()
-            .with_writer(std::io::stdout)
+            .with_writer(|| crate::log_buffer::LogWriter)
             .with_indent_lines(true)
             .with_indent_amount(2)
             .with_verbose_entry(false)
@@ -0,0 +1,398 @@
+use super::cli;
+use itertools::Itertools;
+use lang_c::ast::{
+    DeclarationSpecifier, Declarator, DeclaratorKind, DerivedDeclarator, Ellipsis,
+    ExternalDeclaration, SpecifierQualifier, StorageClassSpecifier, StructDeclaration, StructType,
+    TypeSpecifier,
+};
+use lang_c::driver;
+use lang_c::span::Node;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::process::ExitCode;
+
+pub fn generate_bindings(settings: cli::BindgenFlags) -> ExitCode {
+    fn err_and_failure(and: impl FnOnce(lumina_util::Error) -> lumina_util::Error) -> ExitCode {
+        let err = lumina_util::Error::error("bindgen error");
+        eprintln!("{}", and(err));
+        ExitCode::FAILURE
+    }
+
+    let config = driver::Config::default();
+    let parse = match driver::parse(&config, &settings.header) {
+        Ok(parse) => parse,
+        Err(err) => return err_and_failure(|e| e.with_text(err.to_string())),
+    };
+
+    let mut gen = Generator {
+        platforms: &settings.platform,
+        out: String::new(),
+        skipped: vec![],
+        typedefs: HashMap::new(),
+        records: HashSet::new(),
+    };
+
+    let header = settings.header.to_string_lossy();
+    let markers = LineMarkers::new(&parse.source);
+
+    for node in parse.unit.0.iter() {
+        if let ExternalDeclaration::Declaration(decl) = &node.node {
+            // Only the header's own declarations are generated, but the typedefs of the headers it
+            // includes are still needed to resolve the types it uses
+            let emit = match markers.file_at(node.span.start) {
+                Some(file) => file == header,
+                None => true,
+            };
+            gen.declaration(&decl.node.specifiers, &decl.node.declarators, emit);
+        }
+    }
+
+    for (name, reason) in gen.skipped.iter() {
+        let warning = lumina_util::Error::warning("skipped declaration")
+            .with_text(format!("`{name}` {reason}"));
+        eprintln!("{warning}");
+    }
+
+    match settings.output {
+        Some(path) => {
+            if let Err(err) = fs::write(&path, gen.out) {
+                return err_and_failure(|e| e.with_text(err.to_string()));
+            }
+        }
+        None => print!("{}", gen.out),
+    }
+
+    ExitCode::SUCCESS
+}
+
+struct Generator<'a> {
+    platforms: &'a [String],
+    out: String,
+    skipped: Vec<(String, &'static str)>,
+    typedefs: HashMap<String, Base>,
+    // Structs which have been generated as records
+    records: HashSet<String>,
+}
+
+#[derive(Clone)]
+enum Base {
+    Known(String),
+    // Types which aren't generated, such as structs from other headers, can only be pointed to
+    Opaque,
+}
+
+const UNREPRESENTABLE: &str = "uses types which cannot be represented in Lumina";
+
+impl<'a> Generator<'a> {
+    fn declaration(
+        &mut self,
+        specifiers: &[Node<DeclarationSpecifier>],
+        declarators: &[Node<lang_c::ast::InitDeclarator>],
+        emit: bool,
+    ) {
+        let is_typedef = specifiers.iter().any(|spec| {
+            matches!(
+                &spec.node,
+                DeclarationSpecifier::StorageClass(class)
+                    if class.node == StorageClassSpecifier::Typedef
+            )
+        });
+
+        let tspecs = specifiers
+            .iter()
+            .filter_map(|spec| match &spec.node {
+                DeclarationSpecifier::TypeSpecifier(ty) => Some(&ty.node),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // Emit record types for any struct definitions in the specifiers
+        for spec in tspecs.iter().filter(|_| emit) {
+            if let TypeSpecifier::Struct(sty) = spec {
+                let typedef_name = is_typedef
+                    .then(|| declarators.first())
+                    .flatten()
+                    .and_then(|decl| identifier(&decl.node.declarator.node));
+                self.record(&sty.node, typedef_name);
+            }
+        }
+
+        if is_typedef {
+            for decl in declarators.iter() {
+                let declarator = &decl.node.declarator.node;
+                let Some(name) = identifier(declarator) else {
+                    continue;
+                };
+
+                // Anonymous structs are generated under the name of their typedef
+                let ty = if self.records.contains(name) {
+                    Some(Base::Known(name.to_string()))
+                } else {
+                    self.ty(&tspecs, &declarator.derived)
+                };
+
+                if let Some(ty) = ty {
+                    self.typedefs.insert(name.to_string(), ty);
+                }
+            }
+            return;
+        }
+
+        if !emit {
+            return;
+        }
+
+        for decl in declarators.iter() {
+            let declarator = &decl.node.declarator.node;
+            let Some(name) = identifier(declarator) else {
+                continue;
+            };
+
+            match declarator.derived.last().map(|d| &d.node) {
+                Some(DerivedDeclarator::Function(fdecl)) => {
+                    let derived = &declarator.derived[..declarator.derived.len() - 1];
+                    let ret = self.lumina_ty(&tspecs, derived);
+
+                    let params = fdecl
+                        .node
+                        .parameters
+                        .iter()
+                        .map(|param| {
+                            let ptspecs = param
+                                .node
+                                .specifiers
+                                .iter()
+                                .filter_map(|spec| match &spec.node {
+                                    DeclarationSpecifier::TypeSpecifier(ty) => Some(&ty.node),
+                                    _ => None,
+                                })
+                                .collect::<Vec<_>>();
+                            let derived = param
+                                .node
+                                .declarator
+                                .as_ref()
+                                .map(|decl| decl.node.derived.as_slice())
+                                .unwrap_or(&[]);
+                            self.lumina_ty(&ptspecs, derived)
+                        })
+                        .collect::<Option<Vec<_>>>();
+
+                    match (ret, params) {
+                        (Some(ret), Some(mut params)) => {
+                            // `f(void)` has no parameters
+                            if params.as_slice() == ["()"] {
+                                params.clear();
+                            }
+                            if fdecl.node.ellipsis == Ellipsis::Some {
+                                params.push("...".to_string());
+                            }
+                            self.function(name, &params, &ret);
+                        }
+                        _ => self.skipped.push((name.to_string(), UNREPRESENTABLE)),
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn function(&mut self, name: &str, params: &[String], ret: &str) {
+        writeln!(self.out, "@[extern \"{name}\"{}]", self.platform_attr()).unwrap();
+        if params.is_empty() {
+            writeln!(self.out, "pub fn {name} as {ret}\n").unwrap();
+        } else {
+            writeln!(
+                self.out,
+                "pub fn {name} as {} -> {ret}\n",
+                params.iter().format(", ")
+            )
+            .unwrap();
+        }
+    }
+
+    fn record(&mut self, sty: &StructType, typedef_name: Option<&str>) {
+        let Some(fields) = &sty.declarations else {
+            return;
+        };
+        let Some(name) = sty
+            .identifier
+            .as_ref()
+            .map(|ident| ident.node.name.as_str())
+            .or(typedef_name)
+        else {
+            return;
+        };
+
+        // Inserted up front so that fields can point to the record itself
+        self.records.insert(name.to_string());
+
+        let mut buf = String::new();
+
+        for field in fields.iter() {
+            let StructDeclaration::Field(field) = &field.node else {
+                continue;
+            };
+
+            let tspecs = field
+                .node
+                .specifiers
+                .iter()
+                .filter_map(|spec| match &spec.node {
+                    SpecifierQualifier::TypeSpecifier(ty) => Some(&ty.node),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            for decl in field.node.declarators.iter() {
+                let Some(decl) = &decl.node.declarator else {
+                    continue;
+                };
+                let Some(fname) = identifier(&decl.node) else {
+                    continue;
+                };
+
+                match self.lumina_ty(&tspecs, &decl.node.derived) {
+                    Some(ty) => writeln!(buf, "  {fname} {ty}").unwrap(),
+                    None => {
+                        self.records.remove(name);
+                        self.skipped.push((name.to_string(), UNREPRESENTABLE));
+                        return;
+                    }
+                }
+            }
+        }
+
+        writeln!(self.out, "@[repr \"C\"{}]", self.platform_attr()).unwrap();
+        writeln!(self.out, "type {name} {{\n{buf}}}\n").unwrap();
+    }
+
+    fn platform_attr(&self) -> String {
+        if self.platforms.is_empty() {
+            String::new()
+        } else {
+            format!(
+                ", platform [{}]",
+                self.platforms
+                    .iter()
+                    .map(|p| format!("\"{p}\""))
+                    .format(", ")
+            )
+        }
+    }
+
+    fn lumina_ty(
+        &self,
+        specs: &[&TypeSpecifier],
+        derived: &[Node<DerivedDeclarator>],
+    ) -> Option<String> {
+        match self.ty(specs, derived)? {
+            Base::Known(ty) => Some(ty),
+            Base::Opaque => None,
+        }
+    }
+
+    fn ty(&self, specs: &[&TypeSpecifier], derived: &[Node<DerivedDeclarator>]) -> Option<Base> {
+        let mut ty = self.base_ty(specs)?;
+
+        for d in derived.iter() {
+            let (DerivedDeclarator::Pointer(_) | DerivedDeclarator::Array(_)) = &d.node else {
+                return None;
+            };
+
+            ty = match ty {
+                Base::Known(ty) if ty == "()" => Base::Known(String::from("*u8")),
+                Base::Known(ty) => Base::Known(format!("*{ty}")),
+                Base::Opaque => Base::Known(String::from("*u8")),
+            };
+        }
+
+        Some(ty)
+    }
+
+    fn base_ty(&self, specs: &[&TypeSpecifier]) -> Option<Base> {
+        let mut signed = None;
+        let mut longs = 0;
+        let mut base = None;
+
+        for spec in specs {
+            match spec {
+                TypeSpecifier::Void => return Some(Base::Known("()".into())),
+                TypeSpecifier::Bool => return Some(Base::Known("bool".into())),
+                TypeSpecifier::Double => return Some(Base::Known("f64".into())),
+                TypeSpecifier::Float => return Some(Base::Known("f32".into())),
+                TypeSpecifier::Struct(sty) => {
+                    let name = &sty.node.identifier.as_ref()?.node.name;
+                    return Some(if self.records.contains(name) {
+                        Base::Known(name.clone())
+                    } else {
+                        Base::Opaque
+                    });
+                }
+                TypeSpecifier::TypedefName(ident) => {
+                    return self.typedefs.get(&ident.node.name).cloned()
+                }
+                TypeSpecifier::Unsigned => signed = Some(false),
+                TypeSpecifier::Signed => signed = Some(true),
+                TypeSpecifier::Char => base = Some(8),
+                TypeSpecifier::Short => base = Some(16),
+                TypeSpecifier::Int => base = base.or(Some(32)),
+                TypeSpecifier::Long => longs += 1,
+                _ => return None,
+            }
+        }
+
+        // plain `char` is treated as unsigned to match how Lumina represents C strings
+        let bits = match (base, longs) {
+            (Some(8), _) => {
+                let ty = if signed == Some(true) { "i8" } else { "u8" };
+                return Some(Base::Known(ty.into()));
+            }
+            (_, 0) => base.unwrap_or(32),
+            (_, _) => 64,
+        };
+
+        let sign = if signed.unwrap_or(true) { 'i' } else { 'u' };
+        Some(Base::Known(format!("{sign}{bits}")))
+    }
+}
+
+/// The files the preprocessed source came from, according to its line markers
+struct LineMarkers<'s> {
+    markers: Vec<(usize, &'s str)>,
+}
+
+impl<'s> LineMarkers<'s> {
+    fn new(source: &'s str) -> Self {
+        let mut markers = vec![];
+        let mut offset = 0;
+
+        for line in source.split_inclusive('\n') {
+            if let Some(file) = line.strip_prefix("# ").and_then(marker_file) {
+                markers.push((offset, file));
+            }
+            offset += line.len();
+        }
+
+        LineMarkers { markers }
+    }
+
+    fn file_at(&self, offset: usize) -> Option<&'s str> {
+        let i = self.markers.partition_point(|(start, _)| *start <= offset);
+        i.checked_sub(1).map(|i| self.markers[i].1)
+    }
+}
+
+// `# 12 "/usr/include/stdio.h" 1 3`
+fn marker_file(marker: &str) -> Option<&str> {
+    let (line, file) = marker.split_once(' ')?;
+    line.parse::<u32>().ok()?;
+    file.strip_prefix('"')?.split('"').next()
+}
+
+fn identifier(decl: &Declarator) -> Option<&str> {
+    match &decl.kind.node {
+        DeclaratorKind::Identifier(ident) => Some(ident.node.name.as_str()),
+        _ => None,
+    }
+}
@@ -0,0 +1,344 @@
+//! Reduce a project which crashes the code generator or is miscompiled to a minimal reproducer
+//!
+//! Functions are excluded from the program by replacing their bodies with a trap. Whole modules
+//! are excluded first and then single functions, for as long as the failure still reproduces.
+
+use super::build::{lower_project, Lowered};
+use super::cli;
+use super::refs;
+use itertools::Itertools;
+use lumina_compiler as compiler;
+use lumina_compiler::ast;
+use lumina_compiler::backend::{link_native_binary, Backend};
+use lumina_compiler::lir::{bytecode, interpret, MonoFunc};
+use lumina_compiler::Target;
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf as FilePathBuf;
+use std::process::{Command, ExitCode};
+
+struct Bisect {
+    /// The lowered program, decoded again for every attempt
+    bytecode: Vec<u8>,
+    mode: Mode,
+    backend: cli::Backend,
+    target: Target,
+    project_path: FilePathBuf,
+    lumina_dir: FilePathBuf,
+    config: ast::ProjectConfig,
+    super_debug: bool,
+    features: Vec<String>,
+    attempts: Cell<usize>,
+}
+
+enum Mode {
+    /// The code generator panics or fails with this message
+    Crash(String),
+    /// The native binary behaves differently than the reference interpreter
+    Miscompile,
+}
+
+#[derive(PartialEq, Debug)]
+enum Outcome {
+    Exited(i32, Vec<u8>),
+    Crashed,
+}
+
+pub fn bisect_project(env: cli::Environment, mut settings: cli::BisectFlags) -> ExitCode {
+    let Lowered { project_path, lumina_dir, target, pconfig, dinfo, lir, .. } =
+        match lower_project(&env, &mut settings.build) {
+            Ok(lowered) => lowered,
+            Err(code) => return code,
+        };
+
+    let lumina_dir = if pconfig.vendored {
+        project_path.join("vendor")
+    } else {
+        lumina_dir
+    };
+
+    let mut bisect = Bisect {
+        bytecode: bytecode::encode(&lir),
+        mode: Mode::Miscompile,
+        backend: settings.build.backend,
+        target,
+        project_path,
+        lumina_dir,
+        config: pconfig,
+        super_debug: settings.build.super_debug,
+        features: settings.build.features.clone(),
+        attempts: Cell::new(0),
+    };
+
+    // Panics are expected while bisecting, and would otherwise be printed for every attempt
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+    let reproduced = bisect.reproduce(settings.miscompile, dinfo, lir);
+    let kept = reproduced.map(|_| bisect.minimise());
+    std::panic::set_hook(hook);
+
+    let kept = match kept {
+        Ok(kept) => kept,
+        Err(err) => return refs::err_and_failure("bisect error", err),
+    };
+
+    let mut lir = bisect.program();
+    let stubbed = lir
+        .functions
+        .keys()
+        .filter(|func| !kept.contains(func))
+        .collect::<Vec<_>>();
+    for &func in &stubbed {
+        lir.stub(func);
+    }
+
+    println!(
+        "reproduced after stubbing {} of {} functions in {} attempts, remaining functions:",
+        stubbed.len(),
+        lir.functions.len(),
+        bisect.attempts.get()
+    );
+    for func in lir.functions.keys().filter(|func| kept.contains(func)) {
+        let func = &lir.functions[func];
+        println!("  {}:{}", lir.module_names[func.kind.module()], func.symbol);
+    }
+
+    let output = match settings.build.output.as_deref() {
+        Some(path) => env.current_directory.join(path),
+        None => env
+            .current_directory
+            .join(format!("{}-bisect", bisect.config.name)),
+    }
+    .with_extension(bytecode::EXTENSION);
+
+    if let Err(err) = std::fs::write(&output, bytecode::encode(&lir)) {
+        return refs::err_and_failure("bisect error", format!("{}: {err}", output.display()));
+    }
+    println!("reduced program written to {}", output.display());
+
+    ExitCode::SUCCESS
+}
+
+impl Bisect {
+    /// Check that the unmodified program fails, and remember how
+    fn reproduce(
+        &mut self,
+        miscompile: bool,
+        dinfo: compiler::BinDebugInfo,
+        lir: compiler::lir::Output,
+    ) -> Result<(), String> {
+        if miscompile {
+            let expected =
+                run_interpreted(&lir).ok_or("the interpreter could not run the program")?;
+            let objects = self.codegen(dinfo, lir)?;
+            let found = self.run_native(objects)?;
+            if found == expected {
+                return Err("the native binary behaves the same as the interpreter".into());
+            }
+            return Ok(());
+        }
+
+        match self.codegen(dinfo, lir) {
+            Ok(_) => Err(String::from(
+                "code generation succeeded, use --miscompile to bisect wrong output instead",
+            )),
+            Err(err) => {
+                println!("bisecting code generation failure: {err}");
+                self.mode = Mode::Crash(err);
+                Ok(())
+            }
+        }
+    }
+
+    /// Exclude modules and then functions while the failure still reproduces
+    fn minimise(&self) -> HashSet<MonoFunc> {
+        let lir = self.program();
+
+        let modules = lir
+            .functions
+            .iter()
+            .into_group_map_by(|(_, func)| func.kind.module())
+            .into_values()
+            .map(|funcs| funcs.into_iter().map(|(func, _)| func).collect())
+            .collect();
+
+        let functions = self
+            .reduce(modules)
+            .into_iter()
+            .flatten()
+            .map(|func| vec![func])
+            .collect();
+
+        self.reduce(functions).into_iter().flatten().collect()
+    }
+
+    /// Delta debugging, stubbing ever smaller chunks of the groups until no single group can be
+    /// stubbed without the failure going away
+    fn reduce(&self, mut groups: Vec<Vec<MonoFunc>>) -> Vec<Vec<MonoFunc>> {
+        let mut n = 2;
+
+        while groups.len() >= 2 {
+            let size = groups.len().div_ceil(n);
+
+            let reduced = (0..groups.len()).step_by(size).find_map(|start| {
+                let end = (start + size).min(groups.len());
+                let complement = groups[..start]
+                    .iter()
+                    .chain(&groups[end..])
+                    .cloned()
+                    .collect::<Vec<_>>();
+                self.reproduces(&complement).then_some(complement)
+            });
+
+            match reduced {
+                Some(complement) => {
+                    groups = complement;
+                    n = (n - 1).max(2);
+                    println!("{} functions left", groups.iter().flatten().count());
+                }
+                None if n >= groups.len() => break,
+                None => n = (n * 2).min(groups.len()),
+            }
+        }
+
+        groups
+    }
+
+    fn reproduces(&self, kept: &[Vec<MonoFunc>]) -> bool {
+        self.attempts.set(self.attempts.get() + 1);
+
+        let kept = kept.iter().flatten().copied().collect::<HashSet<_>>();
+        let mut lir = self.program();
+        let stubbed = lir
+            .functions
+            .keys()
+            .filter(|func| !kept.contains(func))
+            .collect::<Vec<_>>();
+        for func in stubbed {
+            lir.stub(func);
+        }
+
+        let Some(dinfo) = self.debug_info() else {
+            return false;
+        };
+
+        match &self.mode {
+            Mode::Crash(expected) => self.codegen(dinfo, lir).is_err_and(|err| err == *expected),
+            Mode::Miscompile => {
+                let Some(expected) = run_interpreted(&lir) else {
+                    return false;
+                };
+                // Crashes of the code generator are a different bug than the one being bisected
+                let found = self
+                    .codegen(dinfo, lir)
+                    .and_then(|objects| self.run_native(objects));
+                found.is_ok_and(|found| found != expected)
+            }
+        }
+    }
+
+    fn program(&self) -> compiler::lir::Output {
+        bytecode::decode(&self.bytecode).expect("bytecode encoded by the same compiler")
+    }
+
+    /// Debug info can't be reused after it's been given to a backend, so the project is parsed
+    /// again to get a new one
+    fn debug_info(&self) -> Option<compiler::BinDebugInfo> {
+        ast::parse(
+            self.project_path.clone(),
+            Some(self.lumina_dir.clone()),
+            false,
+            self.super_debug,
+            ast::Generate::Nothing,
+            &self.features,
+            self.target,
+        )
+        .ok()
+        .map(|(_, dinfo)| dinfo)
+    }
+
+    fn codegen(
+        &self,
+        dinfo: compiler::BinDebugInfo,
+        lir: compiler::lir::Output,
+    ) -> Result<Vec<Vec<u8>>, String> {
+        let mut backend: Box<dyn Backend> = match self.backend {
+            cli::Backend::Cranelift => Box::new(compiler::backend::cranelift::Cranelift::default()),
+            cli::Backend::C => Box::new(compiler::backend::c::C { source: None }),
+            #[cfg(feature = "llvm")]
+            cli::Backend::Llvm => Box::new(compiler::backend::llvm::Llvm { ir: None }),
+            #[cfg(not(feature = "llvm"))]
+            cli::Backend::Llvm => {
+                return Err("lumina was built without the `llvm` feature".into());
+            }
+        };
+
+        let target = self.target;
+        std::panic::catch_unwind(AssertUnwindSafe(|| backend.compile(target, dinfo, lir)))
+            .map_err(|payload| {
+                payload
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| payload.downcast_ref::<&str>().map(|str| str.to_string()))
+                    .unwrap_or_else(|| String::from("code generator panicked"))
+            })?
+            .map_err(|err| err.to_string())
+    }
+
+    fn run_native(&self, objects: Vec<Vec<u8>>) -> Result<Outcome, String> {
+        let output = std::env::temp_dir()
+            .join(format!("{}-bisect", self.config.name))
+            .with_extension(self.target.executable_extension());
+
+        // Only the linker settings of the config are needed to link the binary
+        let config = ast::ProjectConfig {
+            name: self.config.name.clone(),
+            linker_args: self.config.linker_args.clone(),
+            linker_libs: self.config.linker_libs.clone(),
+            libraries: self.config.libraries.clone(),
+            library_paths: self.config.library_paths.clone(),
+            linker: self.config.linker.clone(),
+            linker_script: self.config.linker_script.clone(),
+            ..Default::default()
+        };
+
+        link_native_binary(
+            config,
+            self.target,
+            compiler::CrateType::Bin,
+            &output,
+            self.project_path.clone(),
+            self.lumina_dir.clone(),
+            objects,
+        )
+        .map_err(|_| String::from("linking failed"))?;
+
+        let result = Command::new(&output)
+            .output()
+            .map_err(|err| format!("{}: {err}", output.display()))?;
+
+        Ok(match result.status.code() {
+            Some(code) => Outcome::Exited(code, result.stdout),
+            None => Outcome::Crashed,
+        })
+    }
+}
+
+/// Run the program with the reference interpreter, or `None` if it uses something the interpreter
+/// can't run
+fn run_interpreted(lir: &compiler::lir::Output) -> Option<Outcome> {
+    let mut stdout = Vec::new();
+
+    match interpret::run(lir, &[String::from("bisect")], &mut stdout) {
+        Ok(code) => Some(Outcome::Exited(code, stdout)),
+        Err(
+            interpret::Error::Signal(_)
+            | interpret::Error::Trap(_)
+            | interpret::Error::Fault(_)
+            | interpret::Error::DivisionByZero
+            | interpret::Error::NotAFunction(_),
+        ) => Some(Outcome::Crashed),
+        Err(_) => None,
+    }
+}
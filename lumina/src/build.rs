@@ -1,9 +1,13 @@
+use super::cache::BuildCache;
 use super::cli;
+use super::emit;
 use itertools::Itertools;
 use lumina_compiler as compiler;
 use lumina_compiler::ast;
 use lumina_compiler::ast::{CollectError, ConfigError};
-use lumina_compiler::backend::link_native_binary;
+use lumina_compiler::backend::{link_native_binary, Backend};
+use lumina_compiler::lir::profile;
+use lumina_compiler::target::{LinuxPlatform, Platform};
 use lumina_compiler::Target;
 use lumina_key as key;
 use lumina_key::M;
@@ -14,15 +18,28 @@ use std::path::PathBuf as FilePathBuf;
 use std::process::Command;
 use std::process::ExitCode;
 
-pub fn build_project(
-    env: cli::Environment,
-    run: bool,
-    settings: cli::BuildFlags,
-) -> Result<FilePathBuf, ExitCode> {
+/// A project lowered all the way to LIR, ready to be handed to a backend or the interpreter
+pub struct Lowered {
+    pub project_path: FilePathBuf,
+    pub lumina_dir: FilePathBuf,
+    pub target: Target,
+    pub pconfig: ast::ProjectConfig,
+    pub dinfo: compiler::BinDebugInfo,
+    pub lir: compiler::lir::Output,
+    pub cache: Option<BuildCache>,
+    /// Dumps of the stages up to LIR requested by `--emit`, as `(extension, contents)`
+    pub dumps: Vec<(&'static str, Vec<u8>)>,
+    /// Names of the modules lowered to MIR rather than taken from the cache
+    pub relowered: Vec<String>,
+}
+
+fn project_and_target(
+    env: &cli::Environment,
+    settings: &mut cli::BuildFlags,
+) -> (FilePathBuf, Target) {
     let mut project_path = env.current_directory.clone();
-    let lumina_dir = env.lumina_directory.clone();
 
-    if let Some(path) = settings.project {
+    if let Some(path) = settings.project.take() {
         if path.is_absolute() {
             project_path = path;
         } else {
@@ -32,14 +49,112 @@ pub fn build_project(
 
     let target = settings
         .target
+        .take()
         .map(|name| Target::try_from(name.as_str()).unwrap())
         .unwrap_or_else(Target::native);
 
+    (project_path, target)
+}
+
+pub fn lower_project(
+    env: &cli::Environment,
+    settings: &mut cli::BuildFlags,
+) -> Result<Lowered, ExitCode> {
+    let (project_path, target) = project_and_target(env, settings);
+    // Parsing fails for projects which aren't vendored when there's no LUMINAPATH
+    let lumina_dir = env
+        .lumina_directory
+        .clone()
+        .unwrap_or_else(|| project_path.join("vendor"));
+
+    if settings.profile_generate {
+        if settings.backend != cli::Backend::Cranelift {
+            eprintln!("--profile-generate is only supported by the cranelift backend");
+            return Err(ExitCode::FAILURE);
+        }
+        // Instrumented builds aren't optimised, so a profile would go unused
+        if settings.profile_use.is_some() {
+            eprintln!("--profile-generate can not be combined with --profile-use");
+            return Err(ExitCode::FAILURE);
+        }
+    }
+
+    if settings.codegen_units > 1 && settings.backend != cli::Backend::Cranelift {
+        eprintln!("--codegen-units is only supported by the cranelift backend");
+        return Err(ExitCode::FAILURE);
+    }
+
+    if settings.sanitize.is_some() {
+        if settings.backend != cli::Backend::Cranelift {
+            eprintln!("--sanitize is only supported by the cranelift backend");
+            return Err(ExitCode::FAILURE);
+        }
+        // The runtime is linked by the system's C compiler
+        if !matches!(target.platform, Platform::Linux { sub: LinuxPlatform::Gnu }) {
+            eprintln!("--sanitize is only supported on the linux-gnu target");
+            return Err(ExitCode::FAILURE);
+        }
+    }
+
+    if settings.debug_memory {
+        if settings.backend != cli::Backend::Cranelift {
+            eprintln!("--debug-memory is only supported by the cranelift backend");
+            return Err(ExitCode::FAILURE);
+        }
+        // Valgrind can't run binaries which are instrumented for a sanitizer
+        if settings.sanitize.is_some() {
+            eprintln!("--debug-memory can not be combined with --sanitize");
+            return Err(ExitCode::FAILURE);
+        }
+    }
+
+    if settings.trap_on_panic && settings.backend != cli::Backend::Cranelift {
+        eprintln!("--trap-on-panic is only supported by the cranelift backend");
+        return Err(ExitCode::FAILURE);
+    }
+
+    if settings.crate_type != cli::CrateType::Bin && settings.backend != cli::Backend::Cranelift {
+        eprintln!("libraries are only supported by the cranelift backend");
+        return Err(ExitCode::FAILURE);
+    }
+
+    let profiling = match &settings.profile_use {
+        Some(path) => {
+            let profile = std::fs::read(path)
+                .map_err(|err| err.to_string())
+                .and_then(|bytes| profile::Profile::parse(&bytes).map_err(|err| err.to_string()));
+            match profile {
+                Ok(profile) => profile::Profiling::Use(profile),
+                Err(err) => {
+                    eprintln!("could not read profile {}: {err}", path.display());
+                    return Err(ExitCode::FAILURE);
+                }
+            }
+        }
+        None if settings.profile_generate => profile::Profiling::Generate,
+        None => profile::Profiling::None,
+    };
+
+    let generate = match settings.eval.take() {
+        Some((file, expr)) => ast::Generate::Eval(ast::Eval { file, expr }),
+        None if settings.doctests => ast::Generate::Doctests,
+        None => ast::Generate::Nothing,
+    };
+    let emits = settings.emit.clone();
+    let emitted = |kind| emits.contains(&kind);
+    let mut dumps = vec![];
+
+    // Programs generated for `lumina eval` are only run once, and cached builds skip lowering to
+    // MIR so it can't be dumped
+    let cacheable = !matches!(generate, ast::Generate::Eval(_)) && !emitted(cli::Emit::Mir);
+
     let (ast, dinfo) = match compiler::ast::parse(
         project_path.clone(),
-        lumina_dir.clone(),
+        env.lumina_directory.clone(),
         settings.epanic,
         settings.super_debug,
+        generate,
+        &settings.features,
         target.clone(),
     ) {
         Err(fatal_err) => {
@@ -49,6 +164,10 @@ pub fn build_project(
         Ok(ast) => ast,
     };
 
+    if emitted(cli::Emit::Ast) {
+        dumps.push(("ast", emit::ast(&ast)));
+    }
+
     let pinfo = match project_info(ast.main_module, &ast.lookups) {
         Err(err) => {
             eprintln!("{err}");
@@ -57,20 +176,218 @@ pub fn build_project(
         Ok(pinfo) => pinfo,
     };
 
-    let (pconfig, hir, tenvs, mut iquery) = compiler::hir::run(pinfo, target, ast);
+    let cache = env
+        .cache_directory
+        .as_deref()
+        .filter(|_| cacheable)
+        .map(|dir| BuildCache::new(dir, &project_path, &ast.sources, settings, target));
+
+    let (mut pconfig, hir, tenvs, mut iquery) = compiler::hir::run(pinfo, target, ast);
+
+    if emitted(cli::Emit::Hir) {
+        dumps.push(("hir", emit::hir(&hir)));
+    }
+
+    pconfig
+        .linker_args
+        .extend(std::mem::take(&mut settings.link_args));
+    if settings.sanitize == Some(cli::Sanitizer::Address) {
+        pconfig.linker_args.push("-fsanitize=address".into());
+    }
+    if let Some(linker) = settings.linker.take() {
+        // bare names such as `clang` are looked up in PATH
+        let is_path = linker.components().count() > 1;
+        pconfig.linker = Some(if is_path {
+            env.current_directory.join(linker)
+        } else {
+            linker
+        });
+    }
+    if let Some(script) = settings.linker_script.take() {
+        pconfig.linker_script = Some(env.current_directory.join(script));
+    }
+
+    // Nothing which lowering depends on has changed since the cached build
+    if let Some(lir) = cache.as_ref().and_then(BuildCache::lir) {
+        if emitted(cli::Emit::Lir) {
+            dumps.push(("lir", emit::lir(&lir)));
+        }
+        return Ok(Lowered {
+            project_path,
+            lumina_dir,
+            target,
+            pconfig,
+            dinfo,
+            lir,
+            cache,
+            dumps,
+            relowered: vec![],
+        });
+    }
 
     let mut src_dir = FilePathBuf::new();
     src_dir.push(project_path.file_name().unwrap());
     src_dir.push("src/");
-    let (mir, has_failed) = compiler::mir::run(pinfo, target, src_dir, hir, tenvs, &mut iquery);
+    let mut mir_cache = cache.as_ref().map(BuildCache::mir);
+    let (mir, has_failed) = compiler::mir::run(
+        pinfo,
+        target,
+        src_dir,
+        hir,
+        tenvs,
+        &mut iquery,
+        mir_cache.as_mut(),
+    );
     if has_failed {
         eprintln!("aborting compilation due to previous errors");
         return Err(ExitCode::FAILURE);
     }
 
-    let lir = compiler::lir::run(pinfo, target, &iquery, mir);
+    if emitted(cli::Emit::Mir) {
+        dumps.push(("mir", emit::mir(&mir)));
+    }
+
+    let mut lir = compiler::lir::run(
+        pinfo,
+        target,
+        &iquery,
+        mir,
+        &profiling,
+        opt_level(settings.opt_level),
+        settings.inline_threshold,
+    );
 
-    let object = compiler::backend::cranelift::run(target, dinfo, lir);
+    let errors = compiler::lir::interpret::evaluate_consts(&mut lir);
+    if !errors.is_empty() {
+        for err in errors {
+            let mut error = lumina_util::Error::error("compile-time evaluation failed")
+                .with_text(format!("could not evaluate `{}`: {}", err.val, err.error));
+            for symbol in err.trace.iter().rev() {
+                error = error.with_text(format!("  in {symbol}"));
+            }
+            eprintln!("{error}");
+        }
+        return Err(ExitCode::FAILURE);
+    }
+
+    if let Some(cache) = &cache {
+        cache.store_lir(&lir);
+    }
+
+    if emitted(cli::Emit::Lir) {
+        dumps.push(("lir", emit::lir(&lir)));
+    }
+
+    // Bindings declare the libraries they need with `@[link]`, which are only linked if one of
+    // their functions is used. On WASI they instead name the module the function is imported from.
+    let mut libraries = lir
+        .extern_funcs
+        .values()
+        .filter_map(|func| func.library.clone())
+        .filter(|_| !matches!(target.platform, Platform::Wasi))
+        .collect::<Vec<_>>();
+    libraries.sort();
+    libraries.dedup();
+    for lib in libraries {
+        if !pconfig.libraries.contains(&lib) {
+            pconfig.libraries.push(lib);
+        }
+    }
+
+    Ok(Lowered {
+        project_path,
+        lumina_dir,
+        target,
+        pconfig,
+        dinfo,
+        lir,
+        cache,
+        dumps,
+        relowered: mir_cache.map(|cache| cache.lowered).unwrap_or_default(),
+    })
+}
+
+/// Type check the project and report its errors, stopping before monomorphisation and codegen
+pub fn check_project(env: cli::Environment, mut settings: cli::BuildFlags) -> ExitCode {
+    let (project_path, target) = project_and_target(&env, &mut settings);
+
+    let (ast, _) = match compiler::ast::parse(
+        project_path.clone(),
+        env.lumina_directory,
+        settings.epanic,
+        false,
+        ast::Generate::Nothing,
+        &settings.features,
+        target,
+    ) {
+        Err(fatal_err) => {
+            eprintln!("{}", project_error(fatal_err));
+            return ExitCode::FAILURE;
+        }
+        Ok(ast) => ast,
+    };
+
+    let pinfo = match project_info(ast.main_module, &ast.lookups) {
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+        Ok(pinfo) => pinfo,
+    };
+
+    let (_, hir, tenvs, mut iquery) = compiler::hir::run(pinfo, target, ast);
+
+    let mut src_dir = FilePathBuf::new();
+    src_dir.push(project_path.file_name().unwrap());
+    src_dir.push("src/");
+    let (_, has_failed) = compiler::mir::run(pinfo, target, src_dir, hir, tenvs, &mut iquery, None);
+
+    if has_failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+pub fn build_project(
+    env: cli::Environment,
+    run: bool,
+    mut settings: cli::BuildFlags,
+) -> Result<FilePathBuf, ExitCode> {
+    if run && settings.crate_type != cli::CrateType::Bin {
+        eprintln!("a library can not be ran, use `lumina build` instead");
+        return Err(ExitCode::FAILURE);
+    }
+
+    let Lowered {
+        project_path,
+        lumina_dir,
+        target,
+        pconfig,
+        dinfo,
+        lir,
+        cache,
+        mut dumps,
+        ..
+    } = lower_project(&env, &mut settings)?;
+
+    // C programs linking a static library declare its functions with the generated header
+    if settings.crate_type == cli::CrateType::Staticlib
+        || settings.emit.contains(&cli::Emit::CHeader)
+    {
+        match compiler::backend::c_header(&lir, &pconfig.name) {
+            Ok(header) => dumps.push(("h", header.into_bytes())),
+            Err(err) => {
+                let error = lumina_util::Error::error("could not generate C header")
+                    .with_text(err.to_string());
+                eprintln!("{error}");
+                return Err(ExitCode::FAILURE);
+            }
+        }
+    }
+
+    let (objects, ir) = generate_objects(&settings, target, dinfo, lir, cache.as_ref())?;
+    dumps.extend(ir);
 
     let output = match settings.output.as_deref() {
         Some(name) => {
@@ -92,20 +409,473 @@ pub fn build_project(
         }
     };
 
-    link_native_binary(pconfig, target, &output, project_path, lumina_dir, object)?;
+    let lumina_dir = if pconfig.vendored {
+        project_path.join("vendor")
+    } else {
+        lumina_dir
+    };
+
+    let mut dumps = dumps
+        .into_iter()
+        .map(|(extension, dump)| (extension.to_string(), dump))
+        .collect::<Vec<_>>();
+
+    // Objects are numbered if the program was split across several of them
+    if settings.emit.contains(&cli::Emit::Obj) {
+        for (i, object) in objects.iter().enumerate() {
+            let extension = match objects.len() {
+                1 => String::from("o"),
+                _ => format!("{i}.o"),
+            };
+            dumps.push((extension, object.clone()));
+        }
+    }
+
+    for (extension, dump) in dumps {
+        let path = output.with_extension(extension);
+        if let Err(err) = std::fs::write(&path, dump) {
+            eprintln!("could not write {}: {err}", path.display());
+            return Err(ExitCode::FAILURE);
+        }
+    }
+
+    link_native_binary(
+        pconfig,
+        target,
+        crate_type(settings.crate_type),
+        &output,
+        project_path,
+        lumina_dir,
+        objects,
+    )?;
 
     Ok(output)
 }
 
+/// Generate the object files with the selected backend, along with the IR dumps requested by
+/// `--emit`
+fn generate_objects(
+    settings: &cli::BuildFlags,
+    target: Target,
+    dinfo: compiler::BinDebugInfo,
+    lir: compiler::lir::Output,
+    cache: Option<&BuildCache>,
+) -> Result<(Vec<Vec<u8>>, Vec<(&'static str, Vec<u8>)>), ExitCode> {
+    let emit = |kind| settings.emit.contains(&kind).then(String::new);
+
+    let bytecode = settings.emit.contains(&cli::Emit::Bytecode).then(|| {
+        (
+            compiler::lir::bytecode::EXTENSION,
+            compiler::lir::bytecode::encode(&lir),
+        )
+    });
+
+    // IR dumps are only produced by running the backend
+    let dumps_ir = settings
+        .emit
+        .iter()
+        .any(|emit| matches!(emit, cli::Emit::Clif | cli::Emit::LlvmIr | cli::Emit::C));
+    // Frame sizes and compile times are also only known after running the backend
+    let reports_stats = settings.frame_size_warning.is_some() || settings.timings.is_some();
+    if let Some(objects) = cache
+        .filter(|_| !dumps_ir && !reports_stats)
+        .and_then(|cache| cache.objects(settings.backend, settings.codegen_units))
+    {
+        return Ok((objects, bytecode.into_iter().collect()));
+    }
+
+    verify_lir(&lir)?;
+
+    let (objects, dumps) = match settings.backend {
+        cli::Backend::Cranelift => {
+            let mut cranelift = cranelift_backend(settings);
+            cranelift.clif = emit(cli::Emit::Clif);
+            let objects = cranelift.compile(target, dinfo, lir);
+            report_cranelift_stats(settings, &cranelift);
+            (objects, cranelift.clif.map(|clif| ("clif", clif)))
+        }
+        cli::Backend::C => {
+            let mut c = compiler::backend::c::C { source: emit(cli::Emit::C) };
+            let objects = c.compile(target, dinfo, lir);
+            (objects, c.source.map(|source| ("c", source)))
+        }
+        #[cfg(feature = "llvm")]
+        cli::Backend::Llvm => {
+            let mut llvm = compiler::backend::llvm::Llvm { ir: emit(cli::Emit::LlvmIr) };
+            let objects = llvm.compile(target, dinfo, lir);
+            (objects, llvm.ir.map(|ir| ("ll", ir)))
+        }
+        #[cfg(not(feature = "llvm"))]
+        cli::Backend::Llvm => {
+            let error = lumina_util::Error::error("backend not available")
+                .with_text("lumina was built without the `llvm` feature");
+            eprintln!("{error}");
+            return Err(ExitCode::FAILURE);
+        }
+    };
+
+    match objects {
+        Ok(objects) => {
+            if let Some(cache) = cache {
+                cache.store_objects(settings.backend, settings.codegen_units, &objects);
+            }
+            let dumps = dumps
+                .into_iter()
+                .map(|(extension, dump)| (extension, dump.into_bytes()))
+                .chain(bytecode)
+                .collect();
+            Ok((objects, dumps))
+        }
+        Err(err) => {
+            let error =
+                lumina_util::Error::error("code generation failed").with_text(err.to_string());
+            eprintln!("{error}");
+            Err(ExitCode::FAILURE)
+        }
+    }
+}
+
+// Malformed LIR otherwise only shows up as a panic from within the backend
+fn verify_lir(lir: &compiler::lir::Output) -> Result<(), ExitCode> {
+    if let Err(errors) = compiler::lir::verify(lir) {
+        for err in errors {
+            let mut error = lumina_util::Error::error("malformed LIR")
+                .with_text(format!("in function `{}`", err.symbol));
+            for problem in &err.problems {
+                error = error.with_text(format!("  {problem}"));
+            }
+            eprintln!("{error}\n{}", err.listing);
+        }
+        return Err(ExitCode::FAILURE);
+    }
+
+    Ok(())
+}
+
+fn cranelift_backend(settings: &cli::BuildFlags) -> compiler::backend::cranelift::Cranelift {
+    compiler::backend::cranelift::Cranelift {
+        clif: None,
+        debug_info: settings.debug_info || settings.debug_memory || settings.trap_on_panic,
+        profile_generate: settings.profile_generate,
+        frame_size_warning: settings.frame_size_warning,
+        large_frames: vec![],
+        timings: settings.timings,
+        slowest: vec![],
+        codegen_units: settings.codegen_units,
+        sanitize_address: settings.sanitize == Some(cli::Sanitizer::Address),
+        debug_memory: settings.debug_memory,
+        trap_on_panic: settings.trap_on_panic,
+        opt_level: opt_level(settings.opt_level),
+        crate_type: crate_type(settings.crate_type),
+    }
+}
+
+fn report_cranelift_stats(
+    settings: &cli::BuildFlags,
+    cranelift: &compiler::backend::cranelift::Cranelift,
+) {
+    for (symbol, size) in &cranelift.large_frames {
+        let warning = lumina_util::Error::warning("large stack frame").with_text(format!(
+            "{symbol} uses {size} bytes of stack, which exceeds the limit of {} bytes",
+            settings.frame_size_warning.unwrap(),
+        ));
+        eprintln!("{warning}");
+    }
+
+    if !cranelift.slowest.is_empty() {
+        eprintln!("slowest functions to compile:");
+        for (symbol, time) in &cranelift.slowest {
+            eprintln!("  {time:>12.2?}  {symbol}");
+        }
+    }
+}
+
+/// Compile the project into this process and run it there, skipping the linker
+pub fn jit_project(env: cli::Environment, mut settings: cli::BuildFlags) -> ExitCode {
+    if settings.backend != cli::Backend::Cranelift {
+        eprintln!("--jit is only supported by the cranelift backend");
+        return ExitCode::FAILURE;
+    }
+
+    if settings.crate_type != cli::CrateType::Bin {
+        eprintln!("a library can not be ran, use `lumina build` instead");
+        return ExitCode::FAILURE;
+    }
+
+    let excess_arguments: Vec<String> = std::env::args()
+        .skip_while(|arg| arg != "--")
+        .skip(1)
+        .collect();
+
+    let Lowered { target, pconfig, dinfo, lir, .. } = match lower_project(&env, &mut settings) {
+        Ok(lowered) => lowered,
+        Err(code) => return code,
+    };
+
+    if let Err(code) = verify_lir(&lir) {
+        return code;
+    }
+
+    let mut cranelift = cranelift_backend(&settings);
+    let program = cranelift.jit(target, dinfo, lir);
+    report_cranelift_stats(&settings, &cranelift);
+
+    match program {
+        Ok(program) => u8::try_from(program.run(&pconfig.name, &excess_arguments))
+            .map(ExitCode::from)
+            .unwrap_or(ExitCode::FAILURE),
+        Err(err) => {
+            let error =
+                lumina_util::Error::error("code generation failed").with_text(err.to_string());
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+pub fn interpret_project(env: cli::Environment, settings: cli::BuildFlags) -> ExitCode {
+    let excess_arguments: Vec<String> = std::env::args()
+        .skip_while(|arg| arg != "--")
+        .skip(1)
+        .collect();
+
+    match interpret_with(env, settings, &excess_arguments, &mut std::io::stdout()) {
+        Ok(Ok(code)) => u8::try_from(code)
+            .map(ExitCode::from)
+            .unwrap_or(ExitCode::FAILURE),
+        Ok(Err(compiler::lir::interpret::Error::Signal(_))) => ExitCode::FAILURE,
+        Ok(Err(err)) => {
+            let err = lumina_util::Error::error("interpreter error").with_text(err.to_string());
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+        Err(code) => code,
+    }
+}
+
+/// Lower the project and run its tests with the reference interpreter
+pub fn test_project(env: cli::Environment, mut settings: cli::TestFlags) -> ExitCode {
+    use compiler::lir::interpret::{Outcome, Reason};
+
+    settings.build.doctests = true;
+
+    let lowered = match lower_project(&env, &mut settings.build) {
+        Ok(lowered) => lowered,
+        Err(code) => return code,
+    };
+    let lir = &lowered.lir;
+
+    let seed = settings.seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(1)
+    });
+
+    let filter = |test: &compiler::lir::Test| match &settings.filter {
+        Some(filter) => test.name.contains(filter.as_str()),
+        None => true,
+    };
+
+    let (mut passed, mut failed) = (0, 0);
+
+    let bless = settings.bless;
+
+    compiler::lir::interpret::run_tests(lir, seed, bless, &filter, &mut |test, outcome| {
+        let name = format!("{}:{}", lir.module_names[test.module], test.name);
+
+        let err = match outcome {
+            Outcome::Passed => {
+                println!("test {name} ... ok");
+                passed += 1;
+                return;
+            }
+            Outcome::Blessed => {
+                println!("test {name} ... blessed");
+                passed += 1;
+                return;
+            }
+            Outcome::Unsupported(ty) => lumina_util::Error::error("unsupported property test")
+                .with_text(format!("values of type {ty} can not be generated"))
+                .with_text("implement `std:test:Generate` for the type to use it as a parameter"),
+            Outcome::Failed(failure) => {
+                let mut err = lumina_util::Error::error("test failed");
+
+                if !failure.input.is_empty() {
+                    err = err.with_text(format!(
+                        "failing input: {}",
+                        failure.input.iter().format(" ")
+                    ));
+                    if failure.original != failure.input {
+                        err = err.with_text(format!(
+                            "shrunk from: {}",
+                            failure.original.iter().format(" ")
+                        ));
+                    }
+                    err = err.with_text(format!("reproduce with --seed {seed}"));
+                }
+
+                match &failure.reason {
+                    Reason::ReturnedFalse => err = err.with_text("returned false"),
+                    Reason::Error(error) => {
+                        err = err.with_text(error.to_string());
+                        for symbol in failure.trace.iter().rev() {
+                            err = err.with_text(format!("  in {symbol}"));
+                        }
+                    }
+                    Reason::Output { expected } => {
+                        err = err.with_text(format!(
+                            "expected output:\n{}",
+                            String::from_utf8_lossy(expected)
+                        ));
+                    }
+                    Reason::MissingGolden(path) => {
+                        err = err
+                            .with_text(format!("could not read {}", path.display()))
+                            .with_text("run with --bless to create it");
+                    }
+                    Reason::Bless(path, io) => {
+                        err = err.with_text(format!("could not write {}: {io}", path.display()));
+                    }
+                }
+
+                if !failure.stdout.is_empty() {
+                    err = err.with_text(format!(
+                        "stdout:\n{}",
+                        String::from_utf8_lossy(&failure.stdout)
+                    ));
+                }
+
+                err
+            }
+        };
+
+        println!("test {name} ... FAILED");
+        eprintln!("{err}");
+        failed += 1;
+    });
+
+    println!("\n{passed} passed, {failed} failed");
+
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Evaluate an expression inside of a module of the project and print the resulting value
+pub fn eval_project(env: cli::Environment, mut flags: cli::EvalFlags) -> ExitCode {
+    let project = match &flags.build.project {
+        Some(path) => env.current_directory.join(path),
+        None => env.current_directory.clone(),
+    };
+
+    let file = match &flags.file {
+        Some(file) => env.current_directory.join(file),
+        None => project.join("src").join("main.lm"),
+    };
+
+    let file = match file.canonicalize() {
+        Ok(file) => file,
+        Err(err) => {
+            let error = lumina_util::Error::error("could not find module")
+                .with_text(format!("{}: {err}", file.display()));
+            eprintln!("{error}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    flags.build.eval = Some((file.clone(), flags.expr));
+
+    let lowered = match lower_project(&env, &mut flags.build) {
+        Ok(lowered) => lowered,
+        Err(code) => return code,
+    };
+
+    let lir = &lowered.lir;
+    let Some(test) = lir
+        .tests
+        .iter()
+        .find(|test| test.name == ast::EVAL_FUNCTION)
+    else {
+        let error = lumina_util::Error::error("could not find module")
+            .with_text(format!("{} is not a module of the project", file.display()));
+        eprintln!("{error}");
+        return ExitCode::FAILURE;
+    };
+
+    match compiler::lir::interpret::evaluate(lir, test.func, &mut std::io::stdout()) {
+        Ok(value) => {
+            println!("{value}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            let error = lumina_util::Error::error("evaluation failed").with_text(err.to_string());
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Lower the project, or load it from a bytecode file, and execute it with the reference interpreter
+///
+/// Returns the exit code of the program, or the error which stopped the interpreter.
+pub fn interpret_with(
+    env: cli::Environment,
+    mut settings: cli::BuildFlags,
+    args: &[String],
+    stdout: &mut (dyn std::io::Write + Send),
+) -> Result<Result<i32, compiler::lir::interpret::Error>, ExitCode> {
+    let bytecode = settings.project.as_ref().filter(|path| {
+        path.extension()
+            .is_some_and(|ext| ext == compiler::lir::bytecode::EXTENSION)
+    });
+
+    let (name, lir) = match bytecode {
+        Some(path) => {
+            let path = env.current_directory.join(path);
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            (name, read_bytecode(&path)?)
+        }
+        None => {
+            let lowered = lower_project(&env, &mut settings)?;
+            (lowered.pconfig.name, lowered.lir)
+        }
+    };
+
+    let argv = std::iter::once(name)
+        .chain(args.iter().cloned())
+        .collect::<Vec<_>>();
+
+    Ok(compiler::lir::interpret::run(&lir, &argv, stdout))
+}
+
+fn read_bytecode(path: &std::path::Path) -> Result<compiler::lir::Output, ExitCode> {
+    let decoded = std::fs::read(path)
+        .map_err(|err| err.to_string())
+        .and_then(|bytes| compiler::lir::bytecode::decode(&bytes).map_err(|err| err.to_string()));
+
+    decoded.map_err(|err| {
+        let error = lumina_util::Error::error("could not load bytecode")
+            .with_text(format!("{}: {err}", path.display()));
+        eprintln!("{error}");
+        ExitCode::FAILURE
+    })
+}
+
 pub fn run_built_binary(output: &FilePathBuf) -> ExitCode {
     let excess_arguments = std::env::args().skip_while(|arg| arg != "--").skip(1);
 
-    let result = Command::new(output.clone())
-        .args(excess_arguments)
-        .spawn()
-        .expect("could not run binary")
-        .wait()
-        .unwrap();
+    let result = match Command::new(output.clone()).args(excess_arguments).status() {
+        Ok(status) => status,
+        Err(err) => {
+            let error = lumina_util::Error::error("could not run binary")
+                .with_text(format!("{}: {err}", output.display()));
+            eprintln!("{error}");
+            return ExitCode::FAILURE;
+        }
+    };
 
     #[cfg(unix)]
     if let Some(signal) = result.signal() {
@@ -114,17 +884,22 @@ pub fn run_built_binary(output: &FilePathBuf) -> ExitCode {
             2 => "SIGINT".into(),
             3 => "SIGQUIT".into(),
             4 => "SIGILL".into(),
-            5 => "SITTRAP".into(),
+            5 => "SIGTRAP".into(),
             11 => "SIGSEGV".into(),
             12 => "SIGSYS".into(),
             15 => "SIGTERM".into(),
             _ => signal.to_string(),
         };
 
-        println!("{} exited with signal {text}", output.display())
+        println!("{} exited with signal {text}", output.display());
+
+        // Same convention as shells use for processes terminated by a signal
+        return u8::try_from(128 + signal)
+            .map(ExitCode::from)
+            .unwrap_or(ExitCode::FAILURE);
     }
 
-    u8::try_from(result.code().unwrap_or(0))
+    u8::try_from(result.code().unwrap_or(1))
         .map(ExitCode::from)
         .unwrap_or(ExitCode::FAILURE)
 }
@@ -180,6 +955,7 @@ pub fn project_info<'s>(
     let listable = trait_(&["std", "prelude", "Listable"])?;
     let stringable = trait_(&["std", "prelude", "Stringable"])?;
     let reflect_type = trait_(&["std", "prelude", "Type"])?;
+    let generate = trait_(&["std", "test", "Generate"]).ok();
 
     let maybe = resolve_or_error(from, lookups, &["std", "prelude", "Maybe"], |k| match k {
         ast::Entity::Type(key::TypeKind::Sum(key)) => Some(key),
@@ -207,6 +983,7 @@ pub fn project_info<'s>(
         stringable,
         string,
         maybe,
+        generate,
     ))
 }
 
@@ -222,6 +999,18 @@ pub fn project_error(err: compiler::ast::Error) -> lumina_util::Error {
             "LUMINAPATH does not point to a valid directory: {}",
             path.display()
         )),
+        ast::Error::NoLuminaPath => error
+            .with_text("LUMINAPATH is not set and --no-default-luminapath was given")
+            .with_text("set LUMINAPATH or vendor the project with `lumina vendor`"),
+        ast::Error::StdVersion { expected, found: Some(found) } => error.with_text(format!(
+            "project requires std version {expected} but LUMINAPATH provides {found}"
+        )),
+        ast::Error::StdVersion { expected, found: None } => error.with_text(format!(
+            "project requires std version {expected} but LUMINAPATH does not specify a std version"
+        )),
+        ast::Error::UnknownFeature { project, feature } => error.with_text(format!(
+            "project {project} does not declare a feature named {feature}"
+        )),
         ast::Error::Config(ioerr) => {
             error.with_text(format!("could not open project config: {ioerr}"))
         }
@@ -260,3 +1049,20 @@ pub fn project_error(err: compiler::ast::Error) -> lumina_util::Error {
         },
     }
 }
+
+fn opt_level(level: cli::OptLevel) -> compiler::OptLevel {
+    match level {
+        cli::OptLevel::O0 => compiler::OptLevel::None,
+        cli::OptLevel::O1 => compiler::OptLevel::Less,
+        cli::OptLevel::O2 => compiler::OptLevel::Speed,
+        cli::OptLevel::Os => compiler::OptLevel::Size,
+    }
+}
+
+fn crate_type(crate_type: cli::CrateType) -> compiler::CrateType {
+    match crate_type {
+        cli::CrateType::Bin => compiler::CrateType::Bin,
+        cli::CrateType::Lib => compiler::CrateType::Lib,
+        cli::CrateType::Staticlib => compiler::CrateType::Staticlib,
+    }
+}
@@ -0,0 +1,227 @@
+//! Lowered programs and object files of previous builds
+//!
+//! An entry is only reused if every module the build loaded, including the standard library,
+//! and all settings which affect lowering are identical. That lets rebuilds of unchanged projects
+//! skip lowering to MIR, monomorphisation and code generation. When only some modules changed, the
+//! MIR of the others is taken from the [`compiler::mir::ModuleCache`] kept alongside the entry.
+//!
+//! Entries are kept in the user's cache directory, or `LUMINA_CACHE_DIR` if set, with a directory
+//! for each target so that building the same project for several targets keeps a separate entry
+//! for each of them. Entries which haven't been used for `MAX_AGE` are removed whenever a new one
+//! is written.
+
+use super::cli;
+use lumina_compiler as compiler;
+use lumina_compiler::ast::Sources;
+use lumina_compiler::Target;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf as FilePathBuf};
+use std::time::{Duration, SystemTime};
+
+const MAX_AGE: Duration = Duration::from_secs(60 * 60 * 24 * 30);
+
+pub struct BuildCache {
+    root: FilePathBuf,
+    dir: FilePathBuf,
+    hash: String,
+    frontend: u64,
+}
+
+impl BuildCache {
+    pub fn new(
+        cache_dir: &Path,
+        project_path: &Path,
+        sources: &Sources,
+        settings: &cli::BuildFlags,
+        target: Target,
+    ) -> Self {
+        let mut hasher = DefaultHasher::new();
+
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        compiler::lir::bytecode::VERSION.hash(&mut hasher);
+
+        // Dependencies and features are declared in the project config
+        std::fs::read(project_path.join("config.lm"))
+            .ok()
+            .hash(&mut hasher);
+
+        (settings.epanic, settings.super_debug, settings.doctests).hash(&mut hasher);
+        let mut features = settings.features.clone();
+        features.sort();
+        features.hash(&mut hasher);
+
+        target.name().hash(&mut hasher);
+
+        // Everything above affects lowering to MIR, which is also cached for each module
+        let frontend = hasher.finish();
+
+        for module in sources.modules() {
+            sources.get_path(module).hash(&mut hasher);
+            sources.get(module).hash(&mut hasher);
+        }
+
+        settings.debug_info.hash(&mut hasher);
+        settings.opt_level.hash(&mut hasher);
+        settings.crate_type.hash(&mut hasher);
+        settings.inline_threshold.hash(&mut hasher);
+        settings.profile_generate.hash(&mut hasher);
+        settings.sanitize.hash(&mut hasher);
+        settings.debug_memory.hash(&mut hasher);
+        settings.trap_on_panic.hash(&mut hasher);
+        settings
+            .profile_use
+            .as_ref()
+            .map(|path| std::fs::read(path).ok())
+            .hash(&mut hasher);
+
+        BuildCache {
+            root: cache_dir.to_path_buf(),
+            dir: cache_dir
+                .join(target.name())
+                .join(project_key(project_path)),
+            hash: format!("{:016x}", hasher.finish()),
+            frontend,
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        std::fs::read_to_string(self.dir.join("hash")).is_ok_and(|hash| hash == self.hash)
+    }
+
+    /// The program lowered by a previous build with the same sources and settings
+    pub fn lir(&self) -> Option<compiler::lir::Output> {
+        if !self.is_valid() {
+            return None;
+        }
+
+        // The modification time of the hash marks when the entry was last used
+        let _ = std::fs::File::options()
+            .write(true)
+            .open(self.dir.join("hash"))
+            .and_then(|file| file.set_modified(SystemTime::now()));
+
+        let path = self.lir_path();
+        let bytes = std::fs::read(path).ok()?;
+
+        // Entries written by other versions of the compiler are rebuilt
+        compiler::lir::bytecode::decode(&bytes).ok()
+    }
+
+    /// The MIR of each module lowered by previous builds, which is kept when the entry is replaced
+    pub fn mir(&self) -> compiler::mir::ModuleCache {
+        compiler::mir::ModuleCache::new(self.dir.join("mir"), self.frontend)
+    }
+
+    /// The object files generated by a previous build with the same sources, settings, backend and
+    /// number of codegen units
+    pub fn objects(&self, backend: cli::Backend, units: u32) -> Option<Vec<Vec<u8>>> {
+        if !self.is_valid() {
+            return None;
+        }
+
+        (0..units)
+            .map(|i| std::fs::read(self.object_path(backend, units, i)).ok())
+            .collect()
+    }
+
+    /// Replace the entry with a newly lowered program
+    ///
+    /// Failing to write the cache isn't fatal, the next build will just lower the program again.
+    pub fn store_lir(&self, lir: &compiler::lir::Output) {
+        if self.is_valid() {
+            return;
+        }
+
+        // The MIR cache checks which of its modules are still valid by itself
+        for entry in std::fs::read_dir(&self.dir).into_iter().flatten().flatten() {
+            if entry.file_name() != "mir" {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+        let written = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(self.lir_path(), compiler::lir::bytecode::encode(lir)))
+            .and_then(|_| std::fs::write(self.dir.join("hash"), &self.hash));
+
+        if let Err(err) = written {
+            tracing::warn!("could not write build cache {}: {err}", self.dir.display());
+        }
+
+        self.evict();
+    }
+
+    /// Remove the entries of every project and target which haven't been used for `MAX_AGE`
+    fn evict(&self) {
+        let entries = std::fs::read_dir(&self.root)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .flat_map(|target| {
+                std::fs::read_dir(target.path())
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+            });
+
+        for entry in entries {
+            let path = entry.path();
+            // Entries without a hash may still be being written by another build
+            let unused = std::fs::metadata(path.join("hash"))
+                .and_then(|meta| meta.modified())
+                .is_ok_and(|used| used.elapsed().is_ok_and(|age| age > MAX_AGE));
+
+            if unused && path != self.dir {
+                let _ = std::fs::remove_dir_all(&path);
+            }
+        }
+    }
+
+    pub fn store_objects(&self, backend: cli::Backend, units: u32, objects: &[Vec<u8>]) {
+        if !self.is_valid() {
+            return;
+        }
+
+        for (i, object) in objects.iter().enumerate() {
+            let path = self.object_path(backend, units, i as u32);
+            if let Err(err) = std::fs::write(path, object) {
+                tracing::warn!("could not write build cache {}: {err}", self.dir.display());
+                return;
+            }
+        }
+    }
+
+    fn lir_path(&self) -> FilePathBuf {
+        self.dir
+            .join("program")
+            .with_extension(compiler::lir::bytecode::EXTENSION)
+    }
+
+    fn object_path(&self, backend: cli::Backend, units: u32, i: u32) -> FilePathBuf {
+        let name = match backend {
+            cli::Backend::Cranelift => "cranelift",
+            cli::Backend::Llvm => "llvm",
+            cli::Backend::C => "c",
+        };
+        match units {
+            1 => self.dir.join(name).with_extension("o"),
+            _ => self.dir.join(format!("{name}.{i}-of-{units}.o")),
+        }
+    }
+}
+
+/// Projects are told apart by their location, since several of them may share a name
+fn project_key(project_path: &Path) -> String {
+    let path = project_path
+        .canonicalize()
+        .unwrap_or_else(|_| project_path.to_path_buf());
+
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    format!("{name}-{:016x}", hasher.finish())
+}
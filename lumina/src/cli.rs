@@ -1,4 +1,4 @@
-use clap::{command, Args, Parser, Subcommand};
+use clap::{command, Args, Parser, Subcommand, ValueEnum};
 use directories::BaseDirs;
 use std::fs;
 use std::path::PathBuf as FilePathBuf;
@@ -14,6 +14,24 @@ use std::path::PathBuf as FilePathBuf;
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// Fail instead of falling back to a default directory when LUMINAPATH isn't set
+    ///
+    /// Useful for hermetic builds where the standard library and dependencies must be provided explicitly
+    #[arg(long, global = true)]
+    pub no_default_luminapath: bool,
+
+    /// Only log from within the given module, such as `std:list`
+    ///
+    /// Defaults the log level to `trace` unless `RUST_LOG` is set
+    #[arg(long, global = true)]
+    pub log_module: Option<String>,
+
+    /// Only log from within functions with the given name
+    ///
+    /// Defaults the log level to `trace` unless `RUST_LOG` is set
+    #[arg(long, global = true)]
+    pub log_func: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -26,6 +44,41 @@ pub enum Commands {
 
     /// Build a Lumina project to temporary directory and run it
     Run(BuildFlags),
+
+    /// Report the errors of a Lumina project without generating any code
+    Check(BuildFlags),
+
+    /// Run the `@[test]` and `@[property]` functions of a Lumina project
+    Test(TestFlags),
+
+    /// Evaluate an expression in the context of a module and print the resulting value
+    Eval(EvalFlags),
+
+    /// Print the symbol outline or semantic tokens of a source file, for editor integrations
+    Outline(OutlineFlags),
+
+    /// Rename a function, method, variant or type along with every reference to it
+    Rename(RenameFlags),
+
+    /// List the references to a function, method, variant or type, or the callers and callees of
+    /// a function
+    Refs(RefsFlags),
+
+    /// Apply the fixes suggested by diagnostics, such as adding missing imports
+    Fix(FixFlags),
+
+    /// Copy the standard library and all dependencies into the project for offline builds
+    Vendor(VendorFlags),
+
+    /// Generate Lumina extern declarations from a C header
+    Bindgen(BindgenFlags),
+
+    /// Reduce a project which crashes the code generator or is miscompiled to the functions
+    /// needed to reproduce it, by replacing the bodies of all other functions with traps
+    Bisect(BisectFlags),
+
+    /// Map instruction addresses of a crashed binary back to the Lumina functions they're in
+    Symbolize(SymbolizeFlags),
 }
 
 #[derive(Args, Debug)]
@@ -79,36 +132,386 @@ pub struct BuildFlags {
     #[arg(long)]
     pub super_debug: bool,
 
+    /// Emit line and function information for debuggers such as gdb and lldb
+    ///
+    /// Only supported by the Cranelift backend
+    #[arg(long)]
+    pub debug_info: bool,
+
+    /// Instrument the binary to write `lumina.profile` to its working directory when exiting
+    ///
+    /// Only supported by the Cranelift backend
+    #[arg(long)]
+    pub profile_generate: bool,
+
+    /// Optimise with a profile written by a binary built with `--profile-generate`
+    #[arg(long)]
+    pub profile_use: Option<FilePathBuf>,
+
+    /// How much to optimise the program: `-O0` skips optimisations for the fastest builds, and
+    /// `-Os` avoids growing the program
+    #[arg(short = 'O', long, value_enum, default_value_t = OptLevel::O2)]
+    pub opt_level: OptLevel,
+
+    /// Whether to link an executable, or a library exposing the `@[export]` functions
+    ///
+    /// Libraries are only supported by the Cranelift backend on linux, and shared libraries only on
+    /// the linux-gnu target
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = CrateType::Bin,
+        default_value_if("shared", "true", "lib")
+    )]
+    pub crate_type: CrateType,
+
+    /// Shorthand for `--crate-type=lib`
+    #[arg(long, conflicts_with = "crate_type")]
+    pub shared: bool,
+
+    /// Inline functions with fewer LIR instructions than this into their callers
+    ///
+    /// Functions which are only called from a single place are always inlined
+    #[arg(long, default_value_t = 3)]
+    pub inline_threshold: usize,
+
+    /// Warn about functions whose stack frame is larger than this many bytes
+    ///
+    /// Only supported by the Cranelift backend
+    #[arg(long)]
+    pub frame_size_warning: Option<u32>,
+
+    /// List this many of the functions which took the longest to compile, 10 if not given
+    ///
+    /// Only supported by the Cranelift backend
+    #[arg(long, num_args = 0..=1, default_missing_value = "10")]
+    pub timings: Option<usize>,
+
+    /// Split the generated code across this many object files, which are linked together
+    ///
+    /// Only supported by the Cranelift backend
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub codegen_units: u32,
+
+    /// Instrument the binary with a sanitizer and link its runtime
+    ///
+    /// Only supported by the Cranelift backend on the linux-gnu target
+    #[arg(long, value_enum)]
+    pub sanitize: Option<Sanitizer>,
+
+    /// Build for running under Valgrind, with debug info and stack memory zeroed before use
+    ///
+    /// Only supported by the Cranelift backend
+    #[arg(long)]
+    pub debug_memory: bool,
+
+    /// Stop at a breakpoint when the program panics, so that an attached debugger lands at the
+    /// failure instead of inside of `exit`
+    ///
+    /// Only supported by the Cranelift backend
+    #[arg(long)]
+    pub trap_on_panic: bool,
+
     /// Path of output binary
     #[arg(short = 'o', long)]
     pub output: Option<String>,
 
+    /// Pass an additional argument to the linker
+    #[arg(long = "link-arg")]
+    pub link_args: Vec<String>,
+
+    /// Path to the linker to invoke instead of the platform default
+    #[arg(long)]
+    pub linker: Option<FilePathBuf>,
+
+    /// Linker script to control the memory layout of the binary
+    #[arg(short = 'T', long)]
+    pub linker_script: Option<FilePathBuf>,
+
+    /// Code generator used to produce the object file
+    #[arg(long, value_enum, default_value_t = Backend::Cranelift)]
+    pub backend: Backend,
+
+    /// Emit additional compiler artifacts next to the output binary
+    #[arg(long, value_delimiter = ',')]
+    pub emit: Vec<Emit>,
+
+    /// Enable features declared in the project config in addition to its default features
+    #[arg(long, value_delimiter = ',')]
+    pub features: Vec<String>,
+
+    /// Execute the program with the reference interpreter instead of compiling it natively
+    ///
+    /// Only applies to `lumina run`
+    #[arg(long)]
+    pub interpret: bool,
+
+    /// Compile the program into the compiler's own process and run it from there, without linking
+    ///
+    /// Only applies to `lumina run` with the cranelift backend, and only for the native target
+    #[arg(long, conflicts_with = "interpret")]
+    pub jit: bool,
+
+    /// Compile code examples in doc comments as tests, set by `lumina test`
+    #[arg(skip)]
+    pub doctests: bool,
+
+    /// Source file and expression to evaluate, set by `lumina eval`
+    #[arg(skip)]
+    pub eval: Option<(FilePathBuf, String)>,
+
     /// Path to lumina project, defaults to current directory
+    ///
+    /// `lumina run --interpret` also accepts a bytecode file produced by `--emit bytecode`
     pub project: Option<FilePathBuf>,
 }
 
+#[derive(Args, Debug)]
+pub struct TestFlags {
+    /// Only run tests whose name contains this string
+    #[arg(long)]
+    pub filter: Option<String>,
+
+    /// Seed for the inputs generated for property tests
+    ///
+    /// Failing properties print the seed they were generated with so that they can be reproduced
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Overwrite the `.expected` files of tests with their actual output
+    #[arg(long)]
+    pub bless: bool,
+
+    #[command(flatten)]
+    pub build: BuildFlags,
+}
+
+#[derive(Args, Debug)]
+pub struct EvalFlags {
+    /// Source file of the module to evaluate the expression in, defaults to `src/main.lm`
+    ///
+    /// The expression may refer to everything in scope of the module, including private items
+    #[arg(long)]
+    pub file: Option<FilePathBuf>,
+
+    /// Expression to evaluate
+    pub expr: String,
+
+    #[command(flatten)]
+    pub build: BuildFlags,
+}
+
+#[derive(Args, Debug)]
+pub struct BisectFlags {
+    /// Look for a difference between the output of the native binary and the reference interpreter
+    /// instead of a crash of the code generator
+    #[arg(long)]
+    pub miscompile: bool,
+
+    #[command(flatten)]
+    pub build: BuildFlags,
+}
+
+#[derive(Args, Debug)]
+pub struct SymbolizeFlags {
+    /// Address the binary was loaded at, subtracted from every address
+    ///
+    /// Needed for position-independent executables, whose addresses depend on where they were
+    /// loaded at runtime
+    #[arg(long, value_parser = parse_address)]
+    pub base: Option<u64>,
+
+    /// Path to the binary which crashed
+    pub binary: FilePathBuf,
+
+    /// Instruction addresses to look up, in hexadecimal
+    #[arg(required = true, value_parser = parse_address)]
+    pub addresses: Vec<u64>,
+}
+
+fn parse_address(str: &str) -> Result<u64, String> {
+    let digits = str
+        .strip_prefix("0x")
+        .or_else(|| str.strip_prefix("0X"))
+        .unwrap_or(str);
+    u64::from_str_radix(digits, 16).map_err(|err| format!("invalid address `{str}`: {err}"))
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Emit {
+    /// Declarations of every function as parsed
+    Ast,
+    /// Functions after name resolution, before type checking
+    Hir,
+    /// Type checked functions, before monomorphisation
+    Mir,
+    /// Monomorphised and optimised functions
+    Lir,
+    /// Cranelift IR of every function
+    Clif,
+    /// LLVM IR of the optimised module, only with `--backend llvm`
+    LlvmIr,
+    /// Generated C source, only with `--backend c`
+    C,
+    /// A C header declaring the `@[export]` functions and the `@[repr "C"]` records they point to,
+    /// always produced for `--crate-type staticlib`
+    CHeader,
+    /// The object file handed to the linker
+    Obj,
+    /// Serialised LIR which `lumina run --interpret` and applications embedding the interpreter can
+    /// execute without recompiling the project
+    Bytecode,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Sanitizer {
+    /// Out-of-bounds accesses and use after free of heap memory, and overflows of stack
+    /// allocations by extern functions
+    Address,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OptLevel {
+    /// No optimisations
+    #[value(name = "0")]
+    O0,
+    /// Simplify control flow without inlining functions
+    #[value(name = "1")]
+    O1,
+    /// All optimisations
+    #[value(name = "2")]
+    O2,
+    /// All optimisations, but only inline functions if it doesn't grow the program
+    #[value(name = "s")]
+    Os,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CrateType {
+    /// An executable
+    Bin,
+    /// A shared library
+    Lib,
+    /// A static library, along with a C header declaring the exported functions
+    Staticlib,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Cranelift,
+    /// Experimental, requires lumina to be built with the `llvm` feature
+    Llvm,
+    /// Portable C compiled with the C compiler in `$CC`
+    C,
+}
+
+#[derive(Args, Debug)]
+pub struct VendorFlags {
+    /// Path to lumina project, defaults to current directory
+    pub project: Option<FilePathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct OutlineFlags {
+    /// Print the classification of every token instead of the outline
+    ///
+    /// Identifiers are resolved, so the project the file belongs to is parsed as well
+    #[arg(long)]
+    pub tokens: bool,
+
+    /// Source file to inspect
+    pub file: FilePathBuf,
+
+    /// Path to lumina project, defaults to current directory
+    pub project: Option<FilePathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct FixFlags {
+    /// Path to lumina project, defaults to current directory
+    pub project: Option<FilePathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct RenameFlags {
+    /// Print the locations which would be changed without changing them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Path of the item to rename as seen from the main module, such as `list:map` or `Maybe:Just`
+    pub item: String,
+
+    /// New name for the item
+    pub name: String,
+
+    /// Path to lumina project, defaults to current directory
+    pub project: Option<FilePathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct RefsFlags {
+    /// List the functions which call the item instead
+    #[arg(long, conflicts_with = "callees")]
+    pub callers: bool,
+
+    /// List the functions called by the item instead
+    #[arg(long)]
+    pub callees: bool,
+
+    /// Path of the item as seen from the main module, such as `list:map` or `Maybe:Just`
+    pub item: String,
+
+    /// Path to lumina project, defaults to current directory
+    pub project: Option<FilePathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct BindgenFlags {
+    /// Restrict the generated declarations to the given platforms
+    #[arg(long)]
+    pub platform: Vec<String>,
+
+    /// Path of output file, defaults to stdout
+    #[arg(short = 'o', long)]
+    pub output: Option<FilePathBuf>,
+
+    /// Path to C header
+    pub header: FilePathBuf,
+}
+
 #[derive(Debug)]
 pub struct Environment {
     pub current_directory: FilePathBuf,
-    pub lumina_directory: FilePathBuf,
+    /// Missing if LUMINAPATH isn't set and the default was disallowed, in which case only vendored
+    /// projects can be built
+    pub lumina_directory: Option<FilePathBuf>,
+    /// Where previous builds are cached, missing if there's no per-user cache directory
+    pub cache_directory: Option<FilePathBuf>,
 }
 
 impl Environment {
-    pub fn parse() -> Self {
-        let dirs = BaseDirs::new().expect("Could not access home directory");
-
-        let lumina_directory = std::env::var("LUMINAPATH")
-            .map(|str| FilePathBuf::from(str))
-            .unwrap_or_else(|_| {
+    pub fn parse(allow_default_luminapath: bool) -> Self {
+        let lumina_directory = match std::env::var("LUMINAPATH") {
+            Ok(str) => Some(FilePathBuf::from(str)),
+            Err(_) if !allow_default_luminapath => None,
+            Err(_) => {
+                let dirs = BaseDirs::new().expect("Could not access home directory");
                 let mut local = dirs.data_local_dir().to_owned();
                 local.push("lumina");
                 fs::create_dir_all(&local).expect("could not create default LUMINAPATH directory");
-                local
-            });
+                Some(local)
+            }
+        };
+
+        let cache_directory = match std::env::var("LUMINA_CACHE_DIR") {
+            Ok(str) => Some(FilePathBuf::from(str)),
+            Err(_) => BaseDirs::new().map(|dirs| dirs.cache_dir().join("lumina")),
+        };
 
         Environment {
             current_directory: std::env::current_dir().unwrap(),
             lumina_directory,
+            cache_directory,
         }
     }
 }
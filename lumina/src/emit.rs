@@ -0,0 +1,101 @@
+//! Textual dumps of the intermediate representations requested with `--emit`
+
+use itertools::Itertools;
+use lumina_compiler as compiler;
+use lumina_compiler::ast::{FuncBody, AST};
+use lumina_compiler::hir::{FuncDefKind, HIR};
+use lumina_compiler::mir::MIR;
+use lumina_util::Highlighting;
+use std::fmt::{self, Write};
+use std::sync::atomic::Ordering;
+
+/// Format a representation for writing to a file, with highlighting disabled
+fn dump(write: impl FnOnce(&mut String) -> fmt::Result) -> Vec<u8> {
+    let highlighting = lumina_util::IS_ENABLED.load(Ordering::Relaxed);
+    lumina_util::enable_highlighting(false);
+
+    let mut out = String::new();
+    write(&mut out).expect("formatting into a string does not fail");
+
+    lumina_util::enable_highlighting(highlighting);
+    out.into_bytes()
+}
+
+fn indent(str: impl ToString) -> String {
+    str.to_string().lines().format("\n  ").to_string()
+}
+
+pub fn ast(ast: &AST) -> Vec<u8> {
+    dump(|out| {
+        for func in ast.entities.fheaders.iter() {
+            let module = ast.sources.name_of_module(func.0);
+            let header = &ast.entities.fheaders[func];
+            writeln!(out, "// {module}\n{header}")?;
+
+            match &ast.entities.fbodies[func] {
+                FuncBody::Extern { link_name, .. } => {
+                    writeln!(out, "  {} {link_name:?}", "extern".keyword())?
+                }
+                FuncBody::Val(body, _)
+                | FuncBody::Func(body)
+                | FuncBody::ImplMethod(body, _)
+                | FuncBody::TraitMethod(Some(body), _) => {
+                    writeln!(out, "  {}\n  {}", "=".symbol(), indent(&body.expr))?
+                }
+                FuncBody::TraitMethod(None, _) => {}
+            }
+
+            writeln!(out)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Dumped before type checking, so the types of expressions are still unresolved inference variables
+pub fn hir(hir: &HIR) -> Vec<u8> {
+    dump(|out| {
+        for func in hir.funcs.iter() {
+            let def = match &hir.funcs[func] {
+                FuncDefKind::Defined(def)
+                | FuncDefKind::ImplMethod(_, def)
+                | FuncDefKind::TraitDefaultMethod(_, _, _, def) => def,
+                _ => continue,
+            };
+
+            let module = hir.sources.name_of_module(func.0);
+            let name = hir.func_names[func].value;
+            writeln!(out, "{} {module}:{name} {def}\n", "fn".keyword())?;
+        }
+
+        Ok(())
+    })
+}
+
+pub fn mir(mir: &MIR) -> Vec<u8> {
+    dump(|out| {
+        for func in mir.funcs.iter() {
+            let module = &mir.module_names[func.0];
+            let name = &mir.func_names[func];
+            writeln!(
+                out,
+                "{} {module}:{name} {}\n",
+                "fn".keyword(),
+                mir.funcs[func]
+            )?;
+        }
+
+        Ok(())
+    })
+}
+
+pub fn lir(lir: &compiler::lir::Output) -> Vec<u8> {
+    dump(|out| {
+        for func in lir.functions.values() {
+            let func = compiler::lir::ty_fmt(&lir.types, func).fns(&lir.functions);
+            writeln!(out, "{func}\n")?;
+        }
+
+        Ok(())
+    })
+}
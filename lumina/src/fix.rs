@@ -0,0 +1,82 @@
+use super::build::{project_error, project_info};
+use super::cli;
+use super::refs;
+use itertools::Itertools;
+use lumina_compiler::ast::{self, Fix};
+use lumina_compiler::{hir, Target};
+use lumina_key as key;
+use std::collections::BTreeMap;
+use std::process::ExitCode;
+
+pub fn fix_project(env: cli::Environment, settings: cli::FixFlags) -> ExitCode {
+    let project_path = refs::project_path(&env, settings.project);
+
+    let (ast, _) = match ast::parse(
+        project_path,
+        env.lumina_directory.clone(),
+        false,
+        false,
+        ast::Generate::Nothing,
+        &[],
+        Target::native(),
+    ) {
+        Ok(ast) => ast,
+        Err(err) => {
+            eprintln!("{}", project_error(err));
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let pinfo = match project_info(ast.main_module, &ast.lookups) {
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+        Ok(pinfo) => pinfo,
+    };
+
+    let (_, hir, _, _) = hir::run(pinfo, Target::native(), ast);
+
+    // Diagnostics with several alternative fixes are left for the user to decide on
+    let mut edits: BTreeMap<key::Module, Vec<Fix>> = BTreeMap::new();
+    let mut ambiguous = 0;
+    for fixes in hir.sources.take_fixes() {
+        match <[Fix; 1]>::try_from(fixes) {
+            Ok([fix]) => edits.entry(fix.module).or_default().push(fix),
+            Err(_) => ambiguous += 1,
+        }
+    }
+
+    let mut applied = 0;
+    for (module, fixes) in edits {
+        // The same fix is suggested for every use of a missing name
+        let fixes = fixes
+            .into_iter()
+            .unique_by(|fix| (fix.span.indice, fix.span.length, fix.replacement.clone()))
+            .sorted_by_key(|fix| fix.span.indice)
+            .collect::<Vec<_>>();
+
+        let path = hir.sources.get_path(module);
+        let mut src = hir.sources.get(module).to_string();
+
+        for fix in fixes.iter().rev() {
+            let start = fix.span.indice as usize;
+            src.replace_range(start..start + fix.span.length as usize, &fix.replacement);
+        }
+        for fix in &fixes {
+            println!("{}: {}", path.display(), fix.title);
+        }
+        applied += fixes.len();
+
+        if let Err(err) = std::fs::write(path, src) {
+            return refs::err_and_failure("fix error", format!("{}: {err}", path.display()));
+        }
+    }
+
+    println!("applied {applied} fixes");
+    if ambiguous != 0 {
+        println!("{ambiguous} diagnostics have several possible fixes and were left as is");
+    }
+
+    ExitCode::SUCCESS
+}
@@ -1,29 +1,62 @@
 // Exports for integration tests
 
 mod build;
-pub use build::{build_project, run_built_binary};
+mod cache;
+mod emit;
+pub use build::{build_project, interpret_with, run_built_binary};
 pub mod cli;
-use lumina_util::test_logger;
+use lumina_util::{test_logger, SymbolSession};
 use std::path::PathBuf;
 
-pub fn run(path: &str) -> std::process::Output {
-    test_logger();
-
+fn test_environment(path: &str) -> (crate::cli::Environment, crate::cli::BuildFlags) {
     let manifest = env!("CARGO_MANIFEST_DIR");
 
     let environment = crate::cli::Environment {
         current_directory: PathBuf::from(format!("{manifest}/../{path}")),
-        lumina_directory: PathBuf::from(format!("{manifest}/../luminapath")),
+        lumina_directory: Some(PathBuf::from(format!("{manifest}/../luminapath"))),
+        // Kept apart from the user's own cache, which tests shouldn't fill up or read from
+        cache_directory: Some(std::env::temp_dir().join("lumina-test-cache")),
     };
 
     let buildflags = crate::cli::BuildFlags {
         target: None,
         epanic: true,
         output: None,
+        link_args: vec![],
+        linker: None,
+        linker_script: None,
+        backend: crate::cli::Backend::Cranelift,
+        emit: vec![],
+        features: vec![],
+        interpret: false,
+        doctests: false,
+        eval: None,
         super_debug: false,
+        debug_info: false,
+        profile_generate: false,
+        profile_use: None,
+        opt_level: crate::cli::OptLevel::O2,
+        crate_type: crate::cli::CrateType::Bin,
+        shared: false,
+        inline_threshold: 3,
+        frame_size_warning: None,
+        timings: None,
+        codegen_units: 1,
+        sanitize: None,
+        debug_memory: false,
+        trap_on_panic: false,
         project: Some(environment.current_directory.clone()),
     };
 
+    (environment, buildflags)
+}
+
+pub fn run(path: &str) -> std::process::Output {
+    test_logger();
+    let _symbols = SymbolSession::enter();
+
+    let (environment, buildflags) = test_environment(path);
+
     match build_project(environment, true, buildflags) {
         Ok(binary) => {
             let output = std::process::Command::new(binary)
@@ -39,3 +72,52 @@ pub fn run(path: &str) -> std::process::Output {
         Err(code) => panic!("running project {path} failed with status code {code:#?}"),
     }
 }
+
+/// Whether the project is rejected by the compiler
+pub fn fails_to_build(path: &str) -> bool {
+    test_logger();
+    let _symbols = SymbolSession::enter();
+
+    let (environment, buildflags) = test_environment(path);
+    build_project(environment, true, buildflags).is_err()
+}
+
+/// Run the project with the reference interpreter, returning its exit code and stdout
+///
+/// The exit code is `None` if the program raised a signal. Returns `None` if the program calls an
+/// extern function which the interpreter doesn't emulate, as there's then nothing to compare
+/// against native execution.
+pub fn interpret(path: &str) -> Option<(Option<i32>, Vec<u8>)> {
+    use lumina_compiler::lir::interpret::Error;
+
+    test_logger();
+    let _symbols = SymbolSession::enter();
+
+    let (environment, buildflags) = test_environment(path);
+    let mut stdout = Vec::new();
+
+    match interpret_with(environment, buildflags, &[], &mut stdout) {
+        Ok(Ok(code)) => Some((Some(code), stdout)),
+        Ok(Err(Error::Signal(_))) => Some((None, stdout)),
+        Ok(Err(Error::UnsupportedExtern(_))) => None,
+        Ok(Err(err)) => panic!("interpreting project {path} failed: {err}"),
+        Err(code) => panic!("interpreting project {path} failed with status code {code:#?}"),
+    }
+}
+
+/// Build the project at `project` with its cache kept in `cache`, returning the names of the
+/// modules which weren't taken from the MIR cache
+pub fn relowered_modules(project: &std::path::Path, cache: &std::path::Path) -> Vec<String> {
+    test_logger();
+    let _symbols = SymbolSession::enter();
+
+    let (mut environment, mut buildflags) = test_environment("");
+    environment.current_directory = project.to_path_buf();
+    environment.cache_directory = Some(cache.to_path_buf());
+    buildflags.project = Some(project.to_path_buf());
+
+    match build::lower_project(&environment, &mut buildflags) {
+        Ok(lowered) => lowered.relowered,
+        Err(code) => panic!("lowering project failed with status code {code:#?}"),
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::subscriber::Interest;
+use tracing::{Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Filter};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Only logs events from within the spans of a module or function, such as `lowering function`
+///
+/// Spans are matched on their `module` and `entity` fields. Mangled symbols recorded as the
+/// entity by the later stages of the compiler are matched on the module and function name they
+/// contain.
+pub struct SpanFilter {
+    pub module: Option<String>,
+    pub func: Option<String>,
+}
+
+/// Marks spans which matched the filter, or are nested within one which did
+struct Selected;
+
+#[derive(Default)]
+struct Fields {
+    module: Option<String>,
+    entity: Option<String>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "module" => self.module = Some(value.to_string()),
+            "entity" => self.entity = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record_str(field, &format!("{value:?}"))
+    }
+}
+
+impl SpanFilter {
+    fn matches(&self, attrs: &Attributes<'_>) -> bool {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+
+        // Mangled symbols are formatted as `m{index}::{module}::{function}::{key}`
+        let symbol = fields.entity.as_deref().and_then(|entity| {
+            let mut parts = entity.split("::");
+            parts.next().filter(|index| index.starts_with('m'))?;
+            Some((parts.next()?, parts.next()?))
+        });
+
+        let module = fields
+            .module
+            .as_deref()
+            .or(symbol.map(|(module, _)| module));
+        let func = symbol.map(|(_, func)| func).or(fields.entity.as_deref());
+
+        let is = |filter: &Option<String>, value: Option<&str>| {
+            filter
+                .as_deref()
+                .map_or(true, |filter| value == Some(filter))
+        };
+
+        is(&self.module, module) && is(&self.func, func)
+    }
+}
+
+impl<S> Filter<S> for SpanFilter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn enabled(&self, meta: &Metadata<'_>, cx: &Context<'_, S>) -> bool {
+        // Spans are let through so that they can be matched on their fields, they're then only
+        // printed if an event is logged within them
+        meta.is_span()
+            || cx
+                .lookup_current()
+                .is_some_and(|span| span.extensions().get::<Selected>().is_some())
+    }
+
+    fn callsite_enabled(&self, _: &'static Metadata<'static>) -> Interest {
+        Interest::sometimes()
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, cx: Context<'_, S>) {
+        let Some(span) = cx.span(id) else {
+            return;
+        };
+
+        let nested = span
+            .parent()
+            .is_some_and(|parent| parent.extensions().get::<Selected>().is_some());
+
+        if nested || self.matches(attrs) {
+            span.extensions_mut().insert(Selected);
+        }
+    }
+}
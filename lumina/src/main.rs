@@ -1,43 +1,88 @@
 use clap::Parser;
+use lumina_util::{log_buffer, SymbolSession};
 use std::process::ExitCode;
 use tracing::info;
-use tracing_subscriber::{layer::SubscriberExt, registry::Registry, EnvFilter};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::{layer::SubscriberExt, registry::Registry, EnvFilter, Layer};
 use tracing_tree;
 
+mod bindgen;
+mod bisect;
 mod build;
-use build::{build_project, run_built_binary};
+use build::{
+    build_project, check_project, eval_project, interpret_project, jit_project, run_built_binary,
+    test_project,
+};
+mod cache;
 mod cli;
+mod emit;
+mod fix;
 mod init;
+mod log_filter;
+mod outline;
+mod refs;
+mod rename;
+mod symbolize;
+mod vendor;
 
-fn init_logger() {
-    let filter = EnvFilter::from_default_env();
+fn init_logger(cli: &cli::Cli) {
+    let span_filter = (cli.log_module.is_some() || cli.log_func.is_some()).then(|| {
+        log_filter::SpanFilter { module: cli.log_module.clone(), func: cli.log_func.clone() }
+    });
+
+    // Selecting what to log with the span filter implies wanting to see all of it
+    let default_level = match span_filter {
+        Some(_) => LevelFilter::TRACE,
+        None => LevelFilter::ERROR,
+    };
+    let filter = EnvFilter::builder()
+        .with_default_directive(default_level.into())
+        .from_env_lossy();
 
     let layer = tracing_tree::HierarchicalLayer::default()
-        .with_writer(std::io::stdout)
+        .with_writer(|| log_buffer::LogWriter)
         .with_indent_lines(true)
         .with_indent_amount(2)
         .with_verbose_entry(false)
         .with_verbose_exit(false)
-        .with_targets(true);
+        .with_targets(true)
+        .with_deferred_spans(span_filter.is_some())
+        .with_span_retrace(true);
 
-    let subscriber = Registry::default().with(layer).with(filter);
+    let subscriber = Registry::default()
+        .with(layer.with_filter(span_filter))
+        .with(filter);
 
     tracing::subscriber::set_global_default(subscriber).unwrap();
 }
 
 fn main() -> ExitCode {
-    init_logger();
-
-    info!("parsing command line arguments");
     let cli = cli::Cli::parse_from(std::env::args().take_while(|arg| arg != "--"));
 
+    init_logger(&cli);
+
+    let _symbols = SymbolSession::enter();
+
     info!("initialising lumina environment");
-    let env = cli::Environment::parse();
+    let env = cli::Environment::parse(!cli.no_default_luminapath);
 
     let run_output = matches!(&cli.command, cli::Commands::Run(..));
 
     match cli.command {
         cli::Commands::Init(settings) => init::create_new_lumina_project(settings),
+        cli::Commands::Vendor(settings) => vendor::vendor_project(env, settings),
+        cli::Commands::Outline(settings) => outline::outline_file(env, settings),
+        cli::Commands::Rename(settings) => rename::rename_item(env, settings),
+        cli::Commands::Refs(settings) => refs::find_references(env, settings),
+        cli::Commands::Fix(settings) => fix::fix_project(env, settings),
+        cli::Commands::Bindgen(settings) => bindgen::generate_bindings(settings),
+        cli::Commands::Bisect(settings) => bisect::bisect_project(env, settings),
+        cli::Commands::Symbolize(settings) => symbolize::symbolize_addresses(settings),
+        cli::Commands::Check(settings) => check_project(env, settings),
+        cli::Commands::Test(settings) => test_project(env, settings),
+        cli::Commands::Eval(settings) => eval_project(env, settings),
+        cli::Commands::Run(settings) if settings.interpret => interpret_project(env, settings),
+        cli::Commands::Run(settings) if settings.jit => jit_project(env, settings),
         cli::Commands::Run(settings) | cli::Commands::Build(settings) => {
             match build_project(env, run_output, settings) {
                 Ok(output) if run_output => run_built_binary(&output),
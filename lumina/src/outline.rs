@@ -0,0 +1,105 @@
+use super::build::project_error;
+use super::cli;
+use lumina_compiler::ast;
+use lumina_compiler::ast::outline::{Symbol, TokenKind};
+use lumina_compiler::Target;
+use lumina_util::Span;
+use std::process::ExitCode;
+
+pub fn outline_file(env: cli::Environment, settings: cli::OutlineFlags) -> ExitCode {
+    fn err_and_failure(text: String) -> ExitCode {
+        let err = lumina_util::Error::error("outline error").with_text(text);
+        eprintln!("{err}");
+        ExitCode::FAILURE
+    }
+
+    let file = env.current_directory.join(&settings.file);
+    let src = match std::fs::read_to_string(&file) {
+        Ok(src) => src,
+        Err(err) => return err_and_failure(format!("{}: {err}", file.display())),
+    };
+
+    if !settings.tokens {
+        print_symbols(&src, &ast::outline::outline(&src), 0);
+        return ExitCode::SUCCESS;
+    }
+
+    let project = match &settings.project {
+        Some(path) => env.current_directory.join(path),
+        None => env.current_directory.clone(),
+    };
+
+    let (ast, _) = match ast::parse(
+        project,
+        env.lumina_directory.clone(),
+        false,
+        false,
+        ast::Generate::Nothing,
+        &[],
+        Target::native(),
+    ) {
+        Ok(ast) => ast,
+        Err(err) => {
+            eprintln!("{}", project_error(err));
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let file = file.canonicalize().ok();
+    let module = ast
+        .sources
+        .modules()
+        .find(|module| ast.sources.get_path(*module).canonicalize().ok() == file);
+
+    let Some(module) = module else {
+        return err_and_failure(format!(
+            "{} is not a module of the project",
+            settings.file.display()
+        ));
+    };
+
+    let src = ast.sources.get(module);
+    for (span, kind) in ast::outline::semantic_tokens(&ast, module) {
+        let (line, column) = position(src, span);
+        println!("{line}:{column} {} {}", span.length, describe(kind));
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn print_symbols(src: &str, symbols: &[Symbol], depth: usize) {
+    for symbol in symbols {
+        let (line, column) = position(src, symbol.span);
+        let kind = format!("{:?}", symbol.kind).to_lowercase();
+        println!(
+            "{:depth$}{line}:{column} {kind} {}",
+            "",
+            symbol.name,
+            depth = depth * 2
+        );
+        print_symbols(src, &symbol.children, depth + 1);
+    }
+}
+
+fn describe(kind: TokenKind) -> &'static str {
+    match kind {
+        TokenKind::Keyword => "keyword",
+        TokenKind::Comment => "comment",
+        TokenKind::String => "string",
+        TokenKind::Number => "number",
+        TokenKind::Operator => "operator",
+        TokenKind::Function => "function",
+        TokenKind::Method => "method",
+        TokenKind::Type => "type",
+        TokenKind::TypeParameter => "typeParameter",
+        TokenKind::Namespace => "namespace",
+    }
+}
+
+/// One-based line and column of the start of the span
+pub fn position(src: &str, span: Span) -> (usize, usize) {
+    let before = &src[..span.indice as usize];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
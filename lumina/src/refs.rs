@@ -0,0 +1,133 @@
+use super::build::{project_error, project_info};
+use super::cli;
+use super::outline::position;
+use lumina_compiler::ast::{self, NFunc, Referent};
+use lumina_compiler::hir::HIR;
+use lumina_compiler::{hir, Target};
+use lumina_key as key;
+use lumina_key::M;
+use lumina_util::Span;
+use std::path::PathBuf as FilePathBuf;
+use std::process::ExitCode;
+
+pub fn find_references(env: cli::Environment, settings: cli::RefsFlags) -> ExitCode {
+    let project_path = project_path(&env, settings.project);
+    let (referent, hir) = match resolve_item(&env, project_path, &settings.item) {
+        Ok(resolved) => resolved,
+        Err(code) => return code,
+    };
+
+    let func = match referent {
+        Referent::Func(nfunc) => Some(nfunc),
+        Referent::Type(_) => None,
+    };
+
+    if settings.callers {
+        let Some(callee) = func else {
+            return err_and_failure("refs error", "types don't have callers".into());
+        };
+        for caller in hir.callers(callee) {
+            print_func(&hir, caller.map(NFunc::Key));
+        }
+    } else if settings.callees {
+        let Some(M(module, NFunc::Key(func))) = func else {
+            return err_and_failure("refs error", "only functions have callees".into());
+        };
+        for callee in hir.callees(M(module, func)) {
+            print_func(&hir, callee);
+        }
+    } else {
+        let mut references: Vec<_> = hir.references_to(referent).collect();
+        references.sort_by_key(|reference| (reference.module, reference.span.indice));
+        for reference in references {
+            let kind = if reference.declaration {
+                "declaration"
+            } else {
+                "use"
+            };
+            print_location(&hir, reference.module, reference.span);
+            println!(" {kind}");
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+pub fn project_path(env: &cli::Environment, project: Option<FilePathBuf>) -> FilePathBuf {
+    let mut project_path = env.current_directory.clone();
+    if let Some(path) = project {
+        if path.is_absolute() {
+            project_path = path;
+        } else {
+            project_path.push(path);
+        }
+    }
+    project_path
+}
+
+/// Parse and resolve the project, and look up `item` from its main module
+pub fn resolve_item<'s>(
+    env: &cli::Environment,
+    project_path: FilePathBuf,
+    item: &str,
+) -> Result<(Referent, HIR<'s>), ExitCode> {
+    let (ast, _) = match ast::parse(
+        project_path,
+        env.lumina_directory.clone(),
+        false,
+        false,
+        ast::Generate::Nothing,
+        &[],
+        Target::native(),
+    ) {
+        Ok(ast) => ast,
+        Err(err) => {
+            eprintln!("{}", project_error(err));
+            return Err(ExitCode::FAILURE);
+        }
+    };
+
+    let path: Vec<&str> = item.split(':').collect();
+    let Some(referent) = ast.referent(&path) else {
+        let text = format!("`{item}` does not name a function, method, variant or type");
+        return Err(err_and_failure("lookup error", text));
+    };
+
+    let pinfo = match project_info(ast.main_module, &ast.lookups) {
+        Err(err) => {
+            eprintln!("{err}");
+            return Err(ExitCode::FAILURE);
+        }
+        Ok(pinfo) => pinfo,
+    };
+
+    let (_, hir, _, _) = hir::run(pinfo, Target::native(), ast);
+
+    Ok((referent, hir))
+}
+
+pub fn err_and_failure(name: &'static str, text: String) -> ExitCode {
+    let err = lumina_util::Error::error(name).with_text(text);
+    eprintln!("{err}");
+    ExitCode::FAILURE
+}
+
+fn print_func(hir: &HIR, M(module, nfunc): M<NFunc>) {
+    let name = match nfunc {
+        NFunc::Key(func) => hir.func_names[func.inside(module)],
+        NFunc::Method(trait_, method) => {
+            let func = hir.methods[trait_.inside(module)][method];
+            hir.func_names[func.inside(module)]
+        }
+        NFunc::SumVar(sum, var) => hir.vnames[sum.inside(module)][var],
+        NFunc::Val(val) => hir.func_names[hir.val_initializers[val.inside(module)]],
+    };
+    print_location(hir, module, name.span);
+    println!(" {}:{}", hir.sources.name_of_module(module), *name);
+}
+
+fn print_location(hir: &HIR, module: key::Module, span: Span) {
+    let (line, column) = position(hir.sources.get(module), span);
+    let path = hir.sources.get_path(module);
+    print!("{}:{line}:{column}", path.display());
+}
@@ -0,0 +1,119 @@
+use super::cli;
+use super::outline::position;
+use super::refs;
+use lumina_compiler::ast::Referent;
+use lumina_key as key;
+use lumina_util::Span;
+use std::collections::BTreeMap;
+use std::process::ExitCode;
+
+pub fn rename_item(env: cli::Environment, settings: cli::RenameFlags) -> ExitCode {
+    fn err_and_failure(text: String) -> ExitCode {
+        refs::err_and_failure("rename error", text)
+    }
+
+    let project_path = refs::project_path(&env, settings.project);
+    let (referent, hir) = match refs::resolve_item(&env, project_path.clone(), &settings.item) {
+        Ok(resolved) => resolved,
+        Err(code) => return code,
+    };
+
+    // References can't be trusted to be complete if resolution failed somewhere
+    if hir.sources.has_failed() {
+        return err_and_failure("the project has errors, resolve them before renaming".into());
+    }
+
+    let old = settings.item.rsplit(':').next().unwrap();
+    let new = settings.name.as_str();
+    if !is_valid_name(old, new) {
+        return err_and_failure(format!("`{new}` is not a valid name for `{old}`"));
+    }
+
+    let module = match referent {
+        Referent::Func(func) => func.0,
+        Referent::Type(kind) => kind.0,
+    };
+    let taken = match referent {
+        Referent::Func(_) => hir.lookups.resolve_func(module, &[new]),
+        Referent::Type(_) => hir.lookups.resolve_type(module, &[new]),
+    };
+    if taken.is_ok_and(|entity| entity.module == module) {
+        return err_and_failure(format!(
+            "`{new}` is already declared in the module of `{old}`"
+        ));
+    }
+
+    let project_path = project_path.canonicalize().unwrap_or(project_path);
+    let in_project = |module: key::Module| {
+        hir.sources
+            .get_path(module)
+            .canonicalize()
+            .is_ok_and(|path| path.starts_with(&project_path))
+    };
+
+    if !in_project(module) {
+        return err_and_failure(format!(
+            "`{}` is declared outside of the project",
+            settings.item
+        ));
+    }
+
+    let mut edits: BTreeMap<key::Module, Vec<Span>> = BTreeMap::new();
+    for reference in hir.references_to(referent) {
+        let src = hir.sources.get(reference.module);
+
+        // Aliased imports refer to the item by another name, which is left as is
+        if reference.span.get_str(src) == old && in_project(reference.module) {
+            edits
+                .entry(reference.module)
+                .or_default()
+                .push(reference.span);
+        }
+    }
+
+    let mut occurrences = 0;
+    for (module, mut spans) in edits {
+        spans.sort_by_key(|span| span.indice);
+        spans.dedup_by_key(|span| span.indice);
+        occurrences += spans.len();
+
+        let path = hir.sources.get_path(module);
+        let mut src = hir.sources.get(module).to_string();
+
+        if settings.dry_run {
+            for span in &spans {
+                let (line, column) = position(&src, *span);
+                println!("{}:{line}:{column}", path.display());
+            }
+            continue;
+        }
+
+        for span in spans.iter().rev() {
+            let start = span.indice as usize;
+            src.replace_range(start..start + span.length as usize, new);
+        }
+
+        if let Err(err) = std::fs::write(path, src) {
+            return err_and_failure(format!("{}: {err}", path.display()));
+        }
+    }
+
+    if !settings.dry_run {
+        println!("renamed {occurrences} occurrences of `{old}` to `{new}`");
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Operators can only be renamed to other operators, and identifiers to other identifiers
+fn is_valid_name(old: &str, new: &str) -> bool {
+    let is_name_start = |c: char| c.is_ascii_alphabetic() || c == '_';
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    let is_operator = |c: char| ":\\!+/*&%@$?^~<>=|-".contains(c);
+
+    if old.starts_with(is_name_start) {
+        new.starts_with(is_name_start) && new.chars().all(is_name)
+    } else {
+        !new.is_empty() && new.chars().all(is_operator)
+    }
+}
@@ -0,0 +1,86 @@
+use super::cli;
+use object::{Object, ObjectSymbol, SymbolKind};
+use std::fs;
+use std::process::ExitCode;
+
+pub fn symbolize_addresses(settings: cli::SymbolizeFlags) -> ExitCode {
+    fn err_and_failure(and: impl FnOnce(lumina_util::Error) -> lumina_util::Error) -> ExitCode {
+        let err = lumina_util::Error::error("symbolize error");
+        eprintln!("{}", and(err));
+        ExitCode::FAILURE
+    }
+
+    let bytes = match fs::read(&settings.binary) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            return err_and_failure(|e| {
+                e.with_text(format!("{}: {err}", settings.binary.display()))
+            })
+        }
+    };
+
+    let file = match object::File::parse(&*bytes) {
+        Ok(file) => file,
+        Err(err) => return err_and_failure(|e| e.with_text(err.to_string())),
+    };
+
+    let mut symbols = file
+        .symbols()
+        .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.address() != 0)
+        .filter_map(|symbol| Some((symbol.address(), symbol.size(), symbol.name().ok()?)))
+        .collect::<Vec<_>>();
+
+    if symbols.is_empty() {
+        return err_and_failure(|e| {
+            e.with_text(format!("{} has no symbols", settings.binary.display()))
+                .with_text("binaries which have been stripped can't be symbolized")
+        });
+    }
+
+    symbols.sort_by_key(|(address, _, _)| *address);
+
+    for &address in &settings.addresses {
+        let relative = address.wrapping_sub(settings.base.unwrap_or(0));
+
+        // Symbols without a size are assumed to extend until the next symbol
+        let i = symbols.partition_point(|(start, _, _)| *start <= relative);
+        let found = i
+            .checked_sub(1)
+            .map(|i| symbols[i])
+            .filter(|(start, size, _)| *size == 0 || relative < start + size);
+
+        match found {
+            Some((start, _, name)) => {
+                println!(
+                    "{address:#018x}  {}+{:#x}",
+                    demangle(name),
+                    relative - start
+                )
+            }
+            None => println!("{address:#018x}  ??"),
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Mangled symbols are formatted as `m{index}::{module}::{function}::{key}`, which is shown as the
+/// `{module}:{function}` path it was declared as
+fn demangle(symbol: &str) -> String {
+    // Mach-O prefixes every symbol with an underscore
+    let mangled = symbol
+        .trim_start_matches('_')
+        .split_once("::")
+        .filter(|(index, _)| {
+            index
+                .strip_prefix('m')
+                .is_some_and(|n| n.parse::<u32>().is_ok())
+        })
+        .and_then(|(_, path)| path.rsplit_once("::"))
+        .and_then(|(path, _)| path.split_once("::"));
+
+    match mangled {
+        Some((module, func)) => format!("{module}:{func}"),
+        None => symbol.to_string(),
+    }
+}
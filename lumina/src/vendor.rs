@@ -0,0 +1,126 @@
+use super::build::project_error;
+use super::cli;
+use lumina_compiler::ast;
+use lumina_compiler::ast::ProjectConfig;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::process::ExitCode;
+
+pub fn vendor_project(env: cli::Environment, settings: cli::VendorFlags) -> ExitCode {
+    fn err_and_failure(and: impl FnOnce(lumina_util::Error) -> lumina_util::Error) -> ExitCode {
+        let err = lumina_util::Error::error("vendor error");
+        eprintln!("{}", and(err));
+        ExitCode::FAILURE
+    }
+
+    let mut project_path = env.current_directory.clone();
+    if let Some(path) = settings.project {
+        if path.is_absolute() {
+            project_path = path;
+        } else {
+            project_path.push(path);
+        }
+    }
+
+    let config_path = project_path.join("config.lm");
+    let config = match read_config(&config_path) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", project_error(err));
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if config.vendored {
+        return err_and_failure(|e| e.with_text("project is already vendored"));
+    }
+
+    let vendor = project_path.join("vendor");
+    if vendor.exists() {
+        return err_and_failure(|e| {
+            e.with_text(format!("{} already exists", vendor.display()))
+                .with_text("remove it to re-vendor the project")
+        });
+    }
+
+    let Some(lumina) = &env.lumina_directory else {
+        return err_and_failure(|e| {
+            e.with_text("LUMINAPATH is not set and --no-default-luminapath was given")
+        });
+    };
+
+    let result = copy_dir_all(&lumina.join("std"), &vendor.join("std"))
+        .and_then(|_| copy_dir_all(&lumina.join("targets"), &vendor.join("targets")));
+    if let Err(err) = result {
+        return err_and_failure(|e| e.with_text(err.to_string()));
+    }
+
+    let mut included = vec![];
+    if let Err(err) = vendor_dependencies(lumina, &vendor, &config, &mut included) {
+        eprintln!("{}", project_error(err));
+        return ExitCode::FAILURE;
+    }
+
+    let result = fs::OpenOptions::new()
+        .append(true)
+        .open(&config_path)
+        .and_then(|mut file| file.write_all(b"\nval vendored = true\n"));
+    if let Err(err) = result {
+        return err_and_failure(|e| e.with_text(err.to_string()));
+    }
+
+    println!(
+        " vendored std and {} dependencies into {}",
+        included.len(),
+        vendor.display()
+    );
+
+    ExitCode::SUCCESS
+}
+
+fn read_config(path: &Path) -> Result<ProjectConfig, ast::Error> {
+    let str = fs::read_to_string(path).map_err(ast::Error::Config)?;
+    ProjectConfig::parse(&str)
+        .map_err(|cerr| ast::Error::ConfigError(str, path.to_path_buf(), cerr))
+}
+
+fn vendor_dependencies(
+    lumina: &Path,
+    vendor: &Path,
+    config: &ProjectConfig,
+    included: &mut Vec<String>,
+) -> Result<(), ast::Error> {
+    for dep in config.dependencies.iter() {
+        if included.contains(&dep.name) {
+            continue;
+        }
+        included.push(dep.name.clone());
+
+        let src = lumina.join("ext").join(&dep.name);
+        let dst = vendor.join("ext").join(&dep.name);
+        copy_dir_all(&src, &dst).map_err(ast::Error::Config)?;
+
+        let config = read_config(&src.join("config.lm"))?;
+        vendor_dependencies(lumina, vendor, &config, included)?;
+    }
+
+    Ok(())
+}
+
+fn copy_dir_all(src: &Path, dst: &Path) -> io::Result<()> {
+    fs::create_dir_all(dst)?;
+
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let to = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &to)?;
+        } else {
+            fs::copy(entry.path(), to)?;
+        }
+    }
+
+    Ok(())
+}
@@ -11,6 +11,17 @@ fn run(path: &str, expected: &str) {
     if !output.status.success() {
         panic!("non-success error code: {}", output.status);
     }
+
+    let Some((code, stdout)) = lumina::interpret(path) else {
+        return;
+    };
+    if stdout != output.stdout || code != output.status.code() {
+        panic!(
+            "interpreter diverged from native execution:\n  native:\n{}\n  interpreted:\n{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&stdout)
+        );
+    }
 }
 
 #[test]
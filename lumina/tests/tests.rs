@@ -15,6 +15,15 @@ fn run(path: &str) {
         .unwrap();
 
     assert_eq!(output.status.code(), Some(expected));
+
+    // Programs calling externs the interpreter doesn't emulate can only be run natively
+    if let Some((interpreted, _)) = lumina::interpret(path) {
+        assert_eq!(
+            interpreted,
+            output.status.code(),
+            "interpreter diverged from native execution"
+        );
+    }
 }
 
 #[test]
@@ -56,3 +65,232 @@ fn tests_mem_nested_combination() {
 fn tests_mem_sum_in_struct() {
     run("tests/mem-sum-in-struct");
 }
+
+#[test]
+fn tests_mem_slot_reuse() {
+    run("tests/mem-slot-reuse");
+}
+
+#[test]
+fn tests_sum_niche_pointer() {
+    run("tests/sum-niche-pointer");
+}
+
+#[test]
+fn tests_sum_niche_null() {
+    run("tests/sum-niche-null");
+}
+
+#[test]
+fn tests_eval_order() {
+    run("tests/eval-order");
+}
+
+#[test]
+fn tests_int_compare() {
+    run("tests/int-compare");
+}
+
+#[test]
+fn tests_mem_packed_struct() {
+    run("tests/mem-packed-struct");
+}
+
+#[test]
+fn tests_int_remainder() {
+    run("tests/int-remainder");
+}
+
+#[test]
+fn tests_int_fold_division() {
+    run("tests/int-fold-division");
+}
+
+#[test]
+fn tests_float_arith() {
+    run("tests/float-arith");
+}
+
+#[test]
+fn tests_atomic_ops() {
+    run("tests/atomic-ops");
+}
+
+#[test]
+fn tests_thread_local_val() {
+    run("tests/thread-local-val");
+}
+
+#[test]
+fn tests_mem_replicate() {
+    run("tests/mem-replicate");
+}
+
+#[test]
+fn tests_impl_specialisation() {
+    run("tests/impl-specialisation");
+}
+
+#[test]
+fn tests_blanket_impls() {
+    run("tests/blanket-impls");
+}
+
+#[test]
+fn tests_named_impls() {
+    run("tests/named-impls");
+}
+
+#[test]
+fn tests_closure_captures() {
+    run("tests/closure-captures");
+}
+
+#[test]
+fn tests_platform_modules() {
+    run("tests/platform-modules");
+}
+
+#[test]
+fn tests_sealed_traits() {
+    run("tests/sealed-traits");
+}
+
+#[test]
+fn tests_exported_symbols() {
+    run("tests/exported-symbols");
+}
+
+#[test]
+fn tests_phantom_type_params() {
+    run("tests/phantom-type-params");
+}
+
+#[test]
+fn tests_opaque_returns() {
+    run("tests/opaque-returns");
+}
+
+#[test]
+fn tests_partial_application() {
+    run("tests/partial-application");
+}
+
+#[test]
+fn tests_variadic_extern() {
+    run("tests/variadic-extern");
+}
+
+#[test]
+fn tests_extern_callbacks() {
+    run("tests/extern-callbacks");
+}
+
+#[test]
+fn tests_extern_callback_indirect() {
+    assert!(lumina::fails_to_build("tests/extern-callback-indirect"));
+}
+
+#[test]
+fn tests_extern_callback_struct() {
+    assert!(lumina::fails_to_build("tests/extern-callback-struct"));
+}
+
+#[test]
+fn tests_operator_fixity() {
+    run("tests/operator-fixity");
+}
+
+#[test]
+fn tests_operator_mixed_associativity() {
+    assert!(lumina::fails_to_build("tests/operator-mixed-associativity"));
+}
+
+#[test]
+fn tests_link_library() {
+    run("tests/link-library");
+}
+
+#[test]
+fn tests_pipeline_operator() {
+    run("tests/pipeline-operator");
+}
+
+#[test]
+fn tests_string_affix_patterns() {
+    run("tests/string-affix-patterns");
+}
+
+#[test]
+fn tests_binary_patterns() {
+    run("tests/binary-patterns");
+}
+
+#[test]
+fn tests_binary_tuple_patterns() {
+    assert!(lumina::fails_to_build("tests/binary-tuple-patterns"));
+}
+
+#[test]
+fn tests_slices() {
+    run("tests/slices");
+}
+
+#[test]
+fn tests_weak_linkage() {
+    run("tests/weak-linkage");
+}
+
+#[test]
+fn tests_weak_hidden_visibility() {
+    assert!(lumina::fails_to_build("tests/weak-hidden-visibility"));
+}
+
+#[test]
+fn tests_const_vals() {
+    run("tests/const-vals");
+}
+
+#[test]
+fn tests_static_assert_failed() {
+    assert!(lumina::fails_to_build("tests/static-assert-failed"));
+}
+
+#[test]
+fn tests_val_sections() {
+    run("tests/val-sections");
+}
+
+#[test]
+fn tests_mir_cache() {
+    run("tests/mir-cache");
+}
+
+#[test]
+fn tests_mir_cache_invalidation() {
+    let manifest = env!("CARGO_MANIFEST_DIR");
+    let tmp = std::env::temp_dir().join(format!("lumina-mir-cache-{}", std::process::id()));
+    let project = tmp.join("mir-cache");
+    let cache = tmp.join("cache");
+
+    std::fs::create_dir_all(project.join("src")).unwrap();
+    let original = PathBuf::from(format!("{manifest}/../tests/mir-cache"));
+    std::fs::copy(original.join("config.lm"), project.join("config.lm")).unwrap();
+    for file in std::fs::read_dir(original.join("src")).unwrap() {
+        let file = file.unwrap();
+        std::fs::copy(file.path(), project.join("src").join(file.file_name())).unwrap();
+    }
+
+    lumina::relowered_modules(&project, &cache);
+
+    let mut c = std::fs::read_to_string(project.join("src/c.lm")).unwrap();
+    c.push_str("\npub fn unused as i32 = 0\n");
+    std::fs::write(project.join("src/c.lm"), c).unwrap();
+
+    // `a` doesn't depend on `c`, while `main` depends on `b` which imports `c`
+    let mut relowered = lumina::relowered_modules(&project, &cache);
+    relowered.sort();
+
+    let _ = std::fs::remove_dir_all(&tmp);
+    assert_eq!(relowered, ["b", "c", "main"]);
+}
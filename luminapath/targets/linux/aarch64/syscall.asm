@@ -0,0 +1,10 @@
+    .global aarch64_syscall
+    .text
+
+// aapcs64 user application: x0, x1, x2, x3, x4, x5
+// aapcs64 kern application: x0, x1, x2, x3, x4, x5, with the syscall number in x8
+
+aarch64_syscall:
+    mov x8, x5 // the last parameter is used as the syscall number instead of an actual parameter
+    svc #0
+    ret